@@ -0,0 +1,669 @@
+//! Clipboard soak test.
+//!
+//! Drives two clipboard endpoints joined by an in-process loopback bridge with
+//! randomized copy, paste and file operations, the way a long RDP session
+//! would, and fails as soon as process memory, open file descriptors or the
+//! state kept per endpoint grows past its threshold.
+//!
+//! ```text
+//! cargo run --release --all-features --example clipboard_soak -- --duration 4h
+//! ```
+//!
+//! Options:
+//!
+//! - `--duration <N[s|m|h]>` - how long to run (default: 60s)
+//! - `--seed <N>` - PRNG seed, printed at start so a failing run can be replayed
+//! - `--report-every <N[s|m|h]>` - interval between samples (default: 10s)
+//! - `--warmup <N[s|m|h]>` - time before the baseline sample (default: 5s)
+//! - `--max-rss-growth-mb <N>` - resident memory allowed over baseline (default: 64)
+//! - `--max-fd-growth <N>` - open file descriptors allowed over baseline (default: 8)
+//!
+//! Memory and descriptor counts come from `/proc/self`; where it is missing
+//! only the in-process checks run.
+
+use std::path::PathBuf;
+use std::process::ExitCode;
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+
+use lamco_clipboard_core::formats::{mime_to_rdp_formats, ClipboardFormat};
+use lamco_clipboard_core::registry::registered_name_to_mime;
+use lamco_clipboard_core::sanitize::{parse_file_uri, path_to_file_uri, sanitize_filename_for_linux};
+use lamco_clipboard_core::{
+    build_file_group_descriptor_w, ClipDataLocks, ClipboardError, ClipboardResult, ClipboardSource, FileDescriptor,
+    FlowControlConfig, FormatConverter, FormatNegotiator, LoopDetectionConfig, LoopDetector, MemoryBudget, Negotiation,
+    RequestWindow, TempFileGuard, TransferEngine,
+};
+
+/// Most files put on the clipboard by one copy
+const MAX_FILES_PER_COPY: usize = 3;
+
+/// Memory budget per endpoint for data being received
+const RECEIVE_BUDGET: usize = 32 * 1024 * 1024;
+
+/// Local applications pasting, by the MIME types they accept in order of preference
+const PASTE_TARGETS: &[&[&str]] = &[
+    &["text/html", "text/plain"],
+    &["text/plain"],
+    &["text/rtf", "text/html", "text/plain"],
+    &["text/csv", "text/plain"],
+    &["text/uri-list"],
+    &["image/png", "text/html"],
+];
+
+const WORDS: &[&str] = &[
+    "clipboard",
+    "naïve",
+    "Straße",
+    "日本語",
+    "emoji 🎉",
+    "tab\there",
+    "quote\"d",
+    "a,b",
+    "<tag>",
+    "&amp;",
+    "\\par",
+    "line\nbreak",
+    "€100",
+    "—",
+    "x",
+];
+
+struct Options {
+    duration: Duration,
+    seed: u64,
+    report_every: Duration,
+    warmup: Duration,
+    max_rss_growth_kb: u64,
+    max_fd_growth: usize,
+}
+
+impl Options {
+    fn parse() -> Result<Self, String> {
+        let mut options = Self {
+            duration: Duration::from_secs(60),
+            seed: SystemTime::now()
+                .duration_since(UNIX_EPOCH)
+                .map(|d| d.as_nanos() as u64)
+                .unwrap_or(1),
+            report_every: Duration::from_secs(10),
+            warmup: Duration::from_secs(5),
+            max_rss_growth_kb: 64 * 1024,
+            max_fd_growth: 8,
+        };
+
+        let mut args = std::env::args().skip(1);
+        while let Some(flag) = args.next() {
+            let value = args.next().ok_or_else(|| format!("missing value for {flag}"))?;
+            let number = || {
+                value
+                    .parse::<u64>()
+                    .map_err(|_| format!("invalid value for {flag}: {value}"))
+            };
+            match flag.as_str() {
+                "--duration" => options.duration = parse_duration(&value)?,
+                "--seed" => options.seed = number()?,
+                "--report-every" => options.report_every = parse_duration(&value)?,
+                "--warmup" => options.warmup = parse_duration(&value)?,
+                "--max-rss-growth-mb" => options.max_rss_growth_kb = number()? * 1024,
+                "--max-fd-growth" => options.max_fd_growth = number()? as usize,
+                _ => return Err(format!("unknown option {flag}")),
+            }
+        }
+        Ok(options)
+    }
+}
+
+/// Parse `90`, `90s`, `15m` or `4h`
+fn parse_duration(value: &str) -> Result<Duration, String> {
+    let (number, unit) = match value.find(|c: char| !c.is_ascii_digit()) {
+        Some(i) => value.split_at(i),
+        None => (value, "s"),
+    };
+    let number: u64 = number.parse().map_err(|_| format!("invalid duration: {value}"))?;
+    let secs = match unit {
+        "s" => number,
+        "m" => number * 60,
+        "h" => number * 3600,
+        _ => return Err(format!("invalid duration unit: {value}")),
+    };
+    Ok(Duration::from_secs(secs))
+}
+
+/// xorshift64*: reproducible from the seed without pulling in a dependency
+struct Rng(u64);
+
+impl Rng {
+    fn new(seed: u64) -> Self {
+        // Zero is a fixed point of xorshift
+        Self(seed.max(1))
+    }
+
+    fn next(&mut self) -> u64 {
+        let mut x = self.0;
+        x ^= x >> 12;
+        x ^= x << 25;
+        x ^= x >> 27;
+        self.0 = x;
+        x.wrapping_mul(0x2545_F491_4F6C_DD1D)
+    }
+
+    fn below(&mut self, n: usize) -> usize {
+        (self.next() % n as u64) as usize
+    }
+
+    fn pick<T: Copy>(&mut self, items: &[T]) -> T {
+        items[self.below(items.len())]
+    }
+
+    fn text(&mut self, max_words: usize) -> String {
+        let words = 1 + self.below(max_words);
+        let mut text = String::new();
+        for i in 0..words {
+            if i > 0 {
+                text.push(' ');
+            }
+            text.push_str(self.pick(WORDS));
+        }
+        text
+    }
+
+    fn bytes(&mut self, len: usize) -> Vec<u8> {
+        (0..len).map(|_| self.next() as u8).collect()
+    }
+}
+
+/// What one endpoint's local clipboard holds
+#[derive(Default)]
+struct Clip {
+    entries: Vec<(String, Vec<u8>)>,
+    files: Vec<TempFileGuard>,
+}
+
+impl Clip {
+    fn mime_types(&self) -> Vec<String> {
+        self.entries.iter().map(|(mime, _)| mime.clone()).collect()
+    }
+}
+
+/// One side of the bridge: a local clipboard plus the per-session state a server keeps
+struct Endpoint {
+    name: &'static str,
+    dir: PathBuf,
+    clip: Clip,
+    remote_formats: Vec<ClipboardFormat>,
+    detector: LoopDetector,
+    converter: FormatConverter,
+    negotiator: FormatNegotiator,
+    requests: RequestWindow<u32>,
+    locks: ClipDataLocks,
+    budget: MemoryBudget,
+    send: TransferEngine,
+    receive: TransferEngine,
+}
+
+impl Endpoint {
+    fn new(name: &'static str) -> std::io::Result<Self> {
+        let dir = std::env::temp_dir().join(format!("lamco-clipboard-soak-{}-{name}", std::process::id()));
+        std::fs::create_dir_all(&dir)?;
+
+        Ok(Self {
+            name,
+            dir,
+            clip: Clip::default(),
+            remote_formats: Vec::new(),
+            detector: LoopDetector::new(),
+            converter: FormatConverter::new(),
+            negotiator: FormatNegotiator::new(),
+            requests: FlowControlConfig::default().outgoing_window(),
+            locks: ClipDataLocks::new(|_| {}),
+            budget: MemoryBudget::new(RECEIVE_BUDGET),
+            send: TransferEngine::new(),
+            receive: TransferEngine::new(),
+        })
+    }
+
+    /// Produce `format` from the local clipboard, as a Format Data Response would
+    fn serve(&self, format: &str) -> ClipboardResult<Vec<u8>> {
+        if format == "FileGroupDescriptorW" {
+            let paths: Vec<PathBuf> = self.clip.files.iter().map(|f| f.path().to_path_buf()).collect();
+            return build_file_group_descriptor_w(&paths);
+        }
+
+        for (mime, data) in &self.clip.entries {
+            if self.converter.has_converter(mime, format) {
+                return self.converter.convert(mime, format, data);
+            }
+            if registered_name_to_mime(format) == Some(mime.as_str()) {
+                return Ok(data.clone());
+            }
+        }
+        Err(ClipboardError::UnsupportedFormat(format.to_string()))
+    }
+
+    fn file_count(&self) -> usize {
+        std::fs::read_dir(&self.dir).map(|entries| entries.count()).unwrap_or(0)
+    }
+}
+
+impl Drop for Endpoint {
+    fn drop(&mut self) {
+        self.clip = Clip::default();
+        let _ = std::fs::remove_dir_all(&self.dir);
+    }
+}
+
+#[derive(Debug, Default)]
+struct Stats {
+    copies: u64,
+    pastes: u64,
+    files: u64,
+    bytes: u64,
+    echoes_suppressed: u64,
+    no_common_format: u64,
+    failed_responses: u64,
+}
+
+struct Bridge {
+    sides: [Endpoint; 2],
+    rng: Rng,
+    stats: Stats,
+    next_id: u32,
+}
+
+impl Bridge {
+    fn step(&mut self) -> ClipboardResult<()> {
+        let side = self.rng.below(2);
+        match self.rng.below(10) {
+            0..=3 => self.copy_content(side),
+            4 => self.copy_files(side),
+            _ => self.paste(side),
+        }
+    }
+
+    fn copy_content(&mut self, side: usize) -> ClipboardResult<()> {
+        let rng = &mut self.rng;
+        // Now and then a payload big enough to need several chunks
+        let text = if rng.below(50) == 0 {
+            rng.text(1).repeat(64 * 1024)
+        } else {
+            rng.text(40)
+        };
+
+        let mut clip = Clip::default();
+        match rng.below(5) {
+            0 => clip.entries.push(("text/plain".to_string(), text.into_bytes())),
+            1 => {
+                clip.entries
+                    .push(("text/html".to_string(), format!("<p><b>{text}</b></p>").into_bytes()));
+                clip.entries.push(("text/plain".to_string(), text.into_bytes()));
+            }
+            2 => {
+                let rtf = self.sides[side].converter.text_to_rtf(&text)?;
+                clip.entries.push(("text/rtf".to_string(), rtf));
+                clip.entries.push(("text/plain".to_string(), text.into_bytes()));
+            }
+            3 => {
+                let rows = 1 + rng.below(20);
+                let csv: Vec<String> = (0..rows)
+                    .map(|_| {
+                        format!(
+                            "{},{},\"{}\"",
+                            rng.below(1000),
+                            rng.pick(WORDS).replace('"', "\"\""),
+                            rng.text(3)
+                        )
+                    })
+                    .collect();
+                clip.entries.push(("text/csv".to_string(), csv.join("\n").into_bytes()));
+                clip.entries.push(("text/plain".to_string(), text.into_bytes()));
+            }
+            _ => {
+                #[cfg(feature = "image")]
+                clip.entries.push(("image/png".to_string(), random_png(rng)?));
+                #[cfg(not(feature = "image"))]
+                clip.entries.push(("text/plain".to_string(), text.into_bytes()));
+            }
+        }
+
+        self.set_local(side, clip)
+    }
+
+    fn copy_files(&mut self, side: usize) -> ClipboardResult<()> {
+        let mut clip = Clip::default();
+        let mut uri_list = Vec::new();
+
+        for _ in 0..1 + self.rng.below(MAX_FILES_PER_COPY) {
+            self.next_id += 1;
+            let name = format!(
+                "{} {} #{}.txt",
+                self.next_id,
+                self.rng.pick(&["naïve", "report", "日本"]),
+                self.rng.below(9)
+            );
+            let path = self.sides[side].dir.join(name);
+            let guard = TempFileGuard::new(&path);
+            let len = self.rng.below(256 * 1024);
+            std::fs::write(&path, self.rng.bytes(len)).map_err(io_error)?;
+
+            uri_list.push(path_to_file_uri(&path));
+            clip.files.push(guard);
+        }
+
+        clip.entries
+            .push(("text/uri-list".to_string(), uri_list.join("\r\n").into_bytes()));
+        self.set_local(side, clip)
+    }
+
+    /// Replace the local clipboard of `side` and announce it to the peer unless it is an echo
+    fn set_local(&mut self, side: usize, clip: Clip) -> ClipboardResult<()> {
+        let mime_types = clip.mime_types();
+        let [local, remote] = pair(&mut self.sides, side);
+        local.clip = clip;
+
+        if local.detector.would_cause_loop_mime(&mime_types) {
+            self.stats.echoes_suppressed += 1;
+            return Ok(());
+        }
+        local.detector.record_mime_types(&mime_types, ClipboardSource::Local);
+        // A new local owner supersedes whatever the peer announced before
+        local.remote_formats.clear();
+        self.stats.copies += 1;
+
+        let mimes: Vec<&str> = mime_types.iter().map(String::as_str).collect();
+        let formats = mime_to_rdp_formats(&mimes);
+        if remote.detector.would_cause_loop(&formats) {
+            self.stats.echoes_suppressed += 1;
+            return Ok(());
+        }
+        remote.detector.record_formats(&formats, ClipboardSource::Rdp);
+        remote.remote_formats = formats;
+        Ok(())
+    }
+
+    /// A local application on `side` pastes what the peer announced
+    fn paste(&mut self, side: usize) -> ClipboardResult<()> {
+        let accepted = self.rng.pick(PASTE_TARGETS);
+        let [local, remote] = pair(&mut self.sides, side);
+
+        let Some(choice) = local
+            .negotiator
+            .negotiate(&local.remote_formats, accepted, &local.converter)
+        else {
+            self.stats.no_common_format += 1;
+            return Ok(());
+        };
+
+        if local.requests.submit(choice.format_id)?.is_none() {
+            return Err(ClipboardError::InvalidState(
+                "request queued on an idle window".to_string(),
+            ));
+        }
+        self.next_id += 1;
+        let received = fetch(local, remote, &choice, self.next_id);
+        if local.requests.complete().is_some() {
+            return Err(ClipboardError::InvalidState("request left queued".to_string()));
+        }
+
+        let clip = match received {
+            Ok(clip) => clip,
+            // The peer replaced its clipboard but its announcement was taken for an echo
+            Err(ClipboardError::UnsupportedFormat(_)) => {
+                self.stats.failed_responses += 1;
+                return Ok(());
+            }
+            Err(e) => return Err(e),
+        };
+        self.stats.pastes += 1;
+        self.stats.files += clip.files.len() as u64;
+        self.stats.bytes += clip.entries.iter().map(|(_, data)| data.len() as u64).sum::<u64>();
+
+        // Owning the local clipboard raises a change event that must not bounce back
+        for (_, data) in &clip.entries {
+            local.detector.record_content(data, ClipboardSource::Rdp);
+        }
+        local
+            .detector
+            .record_mime_types(&clip.mime_types(), ClipboardSource::Rdp);
+
+        self.set_local(side, clip)
+    }
+}
+
+/// The endpoint acting on `side` and its peer
+fn pair(sides: &mut [Endpoint; 2], side: usize) -> [&mut Endpoint; 2] {
+    let [a, b] = sides;
+    if side == 0 {
+        [a, b]
+    } else {
+        [b, a]
+    }
+}
+
+/// Request the negotiated format from the peer and convert it for the local clipboard
+fn fetch(local: &mut Endpoint, remote: &mut Endpoint, choice: &Negotiation, id: u32) -> ClipboardResult<Clip> {
+    let response = remote.serve(&choice.format)?;
+    let _reservation = local.budget.try_reserve(response.len())?;
+
+    let mut clip = Clip::default();
+    let data = if choice.format == "FileGroupDescriptorW" {
+        clip.files = receive_files(local, remote, &response, id)?;
+        let uris: Vec<String> = clip.files.iter().map(|f| path_to_file_uri(f.path())).collect();
+        uris.join("\r\n").into_bytes()
+    } else if local.converter.has_converter(&choice.format, &choice.mime_type) {
+        local.converter.convert(&choice.format, &choice.mime_type, &response)?
+    } else {
+        response
+    };
+    clip.entries.push((choice.mime_type.clone(), data));
+    Ok(clip)
+}
+
+/// Fetch the files listed in a FileGroupDescriptorW through chunked transfers
+fn receive_files(
+    local: &mut Endpoint,
+    remote: &mut Endpoint,
+    fgd: &[u8],
+    id: u32,
+) -> ClipboardResult<Vec<TempFileGuard>> {
+    let descriptors = FileDescriptor::parse_list(fgd)?;
+    let _lock = remote.locks.acquire(id)?;
+
+    let mut files = Vec::new();
+    for (descriptor, source) in descriptors.iter().zip(&remote.clip.files) {
+        let contents = std::fs::read(source.path()).map_err(io_error)?;
+        let hash = remote.send.compute_hash(&contents);
+        let chunks = remote.send.prepare_send(&contents)?;
+
+        local.receive.start_receive(contents.len() as u64, Some(hash))?;
+        for chunk in chunks {
+            local.receive.receive_chunk(chunk)?;
+        }
+        let received = local.receive.finalize_receive()?;
+
+        let name = format!("{id} {}", sanitize_filename_for_linux(&descriptor.name));
+        let path = local.dir.join(name);
+        let guard = TempFileGuard::new(&path);
+        std::fs::write(&path, received).map_err(io_error)?;
+        debug_assert_eq!(
+            parse_file_uri(&path_to_file_uri(&path)).as_deref(),
+            Some(path.as_path())
+        );
+        files.push(guard);
+    }
+    Ok(files)
+}
+
+#[cfg(feature = "image")]
+fn random_png(rng: &mut Rng) -> ClipboardResult<Vec<u8>> {
+    let (width, height) = (1 + rng.below(64), 1 + rng.below(64));
+    let stride = (width * 3 + 3) & !3;
+
+    // BITMAPINFOHEADER, 24 bpp, bottom-up
+    let mut dib = Vec::with_capacity(40 + stride * height);
+    dib.extend_from_slice(&40u32.to_le_bytes());
+    dib.extend_from_slice(&(width as i32).to_le_bytes());
+    dib.extend_from_slice(&(height as i32).to_le_bytes());
+    dib.extend_from_slice(&1u16.to_le_bytes());
+    dib.extend_from_slice(&24u16.to_le_bytes());
+    dib.extend_from_slice(&0u32.to_le_bytes());
+    dib.extend_from_slice(&((stride * height) as u32).to_le_bytes());
+    dib.extend_from_slice(&[0; 16]);
+    dib.extend_from_slice(&rng.bytes(stride * height));
+
+    lamco_clipboard_core::image::dib_to_png(&dib)
+}
+
+fn io_error(e: std::io::Error) -> ClipboardError {
+    ClipboardError::Backend(e.to_string())
+}
+
+/// Resource usage at one point in the run
+#[derive(Debug, Clone, Copy)]
+struct Sample {
+    rss_kb: Option<u64>,
+    fds: Option<usize>,
+    history: usize,
+    files: usize,
+}
+
+impl Sample {
+    fn take(bridge: &Bridge) -> Self {
+        Self {
+            rss_kb: rss_kb(),
+            fds: std::fs::read_dir("/proc/self/fd").ok().map(|entries| entries.count()),
+            history: bridge.sides.iter().map(|s| s.detector.history_len()).max().unwrap_or(0),
+            files: bridge.sides.iter().map(Endpoint::file_count).max().unwrap_or(0),
+        }
+    }
+}
+
+fn rss_kb() -> Option<u64> {
+    let status = std::fs::read_to_string("/proc/self/status").ok()?;
+    let line = status.lines().find(|line| line.starts_with("VmRSS:"))?;
+    line.split_whitespace().nth(1)?.parse().ok()
+}
+
+/// Every threshold the sample breaks, relative to the baseline
+fn violations(bridge: &Bridge, sample: Sample, baseline: Option<Sample>, options: &Options) -> Vec<String> {
+    let mut violations = Vec::new();
+
+    let max_history = 2 * LoopDetectionConfig::default().max_history;
+    if sample.history > max_history {
+        violations.push(format!("loop detector history {} > {max_history}", sample.history));
+    }
+    if sample.files > MAX_FILES_PER_COPY {
+        violations.push(format!(
+            "{} files left in a temp dir > {MAX_FILES_PER_COPY}",
+            sample.files
+        ));
+    }
+    for side in &bridge.sides {
+        let outstanding = side.requests.in_flight() + side.requests.queued();
+        if outstanding > 0 || !side.locks.is_empty() || side.budget.used() > 0 || side.receive.is_active() {
+            violations.push(format!(
+                "{}: {outstanding} requests, {} locks, {} budget bytes, receive active: {} between operations",
+                side.name,
+                side.locks.len(),
+                side.budget.used(),
+                side.receive.is_active()
+            ));
+        }
+    }
+
+    let Some(baseline) = baseline else {
+        return violations;
+    };
+    if let (Some(rss), Some(base)) = (sample.rss_kb, baseline.rss_kb) {
+        if rss > base + options.max_rss_growth_kb {
+            violations.push(format!("RSS grew from {base} kB to {rss} kB"));
+        }
+    }
+    if let (Some(fds), Some(base)) = (sample.fds, baseline.fds) {
+        if fds > base + options.max_fd_growth {
+            violations.push(format!("open descriptors grew from {base} to {fds}"));
+        }
+    }
+    violations
+}
+
+fn main() -> ExitCode {
+    let options = match Options::parse() {
+        Ok(options) => options,
+        Err(e) => {
+            eprintln!("clipboard_soak: {e}");
+            return ExitCode::FAILURE;
+        }
+    };
+
+    let sides = match (Endpoint::new("local"), Endpoint::new("remote")) {
+        (Ok(a), Ok(b)) => [a, b],
+        (Err(e), _) | (_, Err(e)) => {
+            eprintln!("clipboard_soak: cannot create temp dir: {e}");
+            return ExitCode::FAILURE;
+        }
+    };
+    let mut bridge = Bridge {
+        sides,
+        rng: Rng::new(options.seed),
+        stats: Stats::default(),
+        next_id: 0,
+    };
+
+    println!(
+        "clipboard_soak: seed {} for {:?} ({} <-> {})",
+        options.seed, options.duration, bridge.sides[0].name, bridge.sides[1].name
+    );
+
+    let start = Instant::now();
+    let mut baseline = None;
+    let mut next_report = start + options.warmup.min(options.report_every);
+    let mut ops: u64 = 0;
+
+    while start.elapsed() < options.duration {
+        if let Err(e) = bridge.step() {
+            eprintln!("clipboard_soak: operation {ops} failed: {e}");
+            eprintln!("clipboard_soak: replay with --seed {}", options.seed);
+            return ExitCode::FAILURE;
+        }
+        ops += 1;
+
+        if Instant::now() < next_report {
+            continue;
+        }
+        let sample = Sample::take(&bridge);
+        if baseline.is_none() && start.elapsed() >= options.warmup {
+            baseline = Some(sample);
+        }
+        println!(
+            "[{:>6}s] {ops} ops, rss {:?} kB, fds {:?}, history {}, files {}, {:?}",
+            start.elapsed().as_secs(),
+            sample.rss_kb,
+            sample.fds,
+            sample.history,
+            sample.files,
+            bridge.stats
+        );
+
+        let violations = violations(&bridge, sample, baseline, &options);
+        if !violations.is_empty() {
+            for violation in violations {
+                eprintln!("clipboard_soak: {violation}");
+            }
+            eprintln!("clipboard_soak: replay with --seed {}", options.seed);
+            return ExitCode::FAILURE;
+        }
+        next_report = Instant::now() + options.report_every;
+    }
+
+    let violations = violations(&bridge, Sample::take(&bridge), baseline, &options);
+    if !violations.is_empty() {
+        for violation in violations {
+            eprintln!("clipboard_soak: {violation}");
+        }
+        eprintln!("clipboard_soak: replay with --seed {}", options.seed);
+        return ExitCode::FAILURE;
+    }
+
+    println!("clipboard_soak: passed, {ops} ops in {:?}", start.elapsed());
+    ExitCode::SUCCESS
+}
@@ -0,0 +1,548 @@
+//! Audio clipboard conversion.
+//!
+//! Windows carries audio on the clipboard as CF_WAVE (a RIFF WAVE file) and
+//! occasionally CF_RIFF. Both are validated before being handed on: the RIFF
+//! and chunk sizes must fit the data, and a `fmt ` and a `data` chunk must be
+//! present. Trailing bytes after the RIFF chunk are dropped.
+//!
+//! Raw PCM is wrapped into a WAV container with [`pcm_to_wav`]; local offers
+//! of `audio/L16` (RFC 2586, big-endian PCM with its rate and channel count in
+//! MIME parameters) are converted with [`l16_to_wav`].
+//!
+//! # Feature Flag
+//!
+//! With the `flac` feature, `audio/flac` is offered as CF_WAVE and decoded
+//! with [`flac_to_wav`], and CF_WAVE can be sent out as FLAC with
+//! [`wav_to_flac`]. Opus is not supported, as every encoder needs libopus.
+
+use std::ops::Range;
+
+use crate::{ClipboardError, ClipboardResult};
+
+/// Integer PCM (`WAVE_FORMAT_PCM`)
+pub const WAVE_FORMAT_PCM: u16 = 0x0001;
+
+/// 32/64-bit float samples (`WAVE_FORMAT_IEEE_FLOAT`)
+pub const WAVE_FORMAT_IEEE_FLOAT: u16 = 0x0003;
+
+/// Format given by a sub-format GUID (`WAVE_FORMAT_EXTENSIBLE`)
+pub const WAVE_FORMAT_EXTENSIBLE: u16 = 0xFFFE;
+
+/// Sample layout of a WAV file (the `fmt ` chunk)
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct WaveFormat {
+    /// `WAVE_FORMAT_*` tag; for `WAVE_FORMAT_EXTENSIBLE` the tag from its sub-format
+    pub format_tag: u16,
+
+    /// Interleaved channels
+    pub channels: u16,
+
+    /// Frames per second
+    pub sample_rate: u32,
+
+    /// Bits per sample, rounded up to whole bytes when stored
+    pub bits_per_sample: u16,
+}
+
+impl WaveFormat {
+    /// Integer PCM layout
+    pub fn pcm(channels: u16, sample_rate: u32, bits_per_sample: u16) -> Self {
+        Self {
+            format_tag: WAVE_FORMAT_PCM,
+            channels,
+            sample_rate,
+            bits_per_sample,
+        }
+    }
+
+    /// Bytes per frame (one sample for every channel)
+    pub fn block_align(&self) -> usize {
+        usize::from(self.channels) * usize::from(self.bits_per_sample).div_ceil(8)
+    }
+
+    /// Bytes per second
+    pub fn byte_rate(&self) -> u64 {
+        self.sample_rate as u64 * self.block_align() as u64
+    }
+
+    fn validate(&self) -> ClipboardResult<()> {
+        if self.channels == 0 || self.sample_rate == 0 || self.bits_per_sample == 0 {
+            return Err(ClipboardError::FormatConversion(format!(
+                "invalid WAV format: {} channels, {} Hz, {} bits",
+                self.channels, self.sample_rate, self.bits_per_sample
+            )));
+        }
+        Ok(())
+    }
+}
+
+/// Layout of a parsed WAV file
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct WavInfo {
+    /// Sample layout
+    pub format: WaveFormat,
+
+    /// Byte range of the sample data
+    pub data: Range<usize>,
+
+    /// Length of the RIFF chunk including its header
+    pub len: usize,
+}
+
+/// Parse and validate a RIFF WAVE file
+///
+/// # Example
+///
+/// ```
+/// use lamco_clipboard_core::audio::{parse_wav, pcm_to_wav, WaveFormat};
+///
+/// let wav = pcm_to_wav(&WaveFormat::pcm(2, 44100, 16), &[0; 8]).unwrap();
+/// let info = parse_wav(&wav).unwrap();
+/// assert_eq!(info.format.sample_rate, 44100);
+/// assert_eq!(info.data, 44..52);
+/// ```
+pub fn parse_wav(data: &[u8]) -> ClipboardResult<WavInfo> {
+    if data.len() < 12 || &data[0..4] != b"RIFF" || &data[8..12] != b"WAVE" {
+        return Err(ClipboardError::FormatConversion("not a RIFF WAVE file".to_string()));
+    }
+
+    // Streaming writers leave the size at 0 or 0xFFFFFFFF
+    let riff_size = read_u32(data, 4);
+    let streamed = matches!(riff_size, 0 | u32::MAX);
+    let len = if streamed { data.len() } else { 8 + riff_size as usize };
+    if len > data.len() {
+        return Err(ClipboardError::FormatConversion(format!(
+            "truncated RIFF: header says {} bytes, got {}",
+            len,
+            data.len()
+        )));
+    }
+
+    let mut format = None;
+    let mut samples = None;
+    let mut offset = 12;
+    while offset + 8 <= len {
+        let id = &data[offset..offset + 4];
+        let size = read_u32(data, offset + 4) as usize;
+        let body = offset + 8;
+        let end = match body.checked_add(size).filter(|&end| end <= len) {
+            Some(end) => end,
+            // A streamed data chunk runs to the end of the file
+            None if streamed && id == b"data" => len,
+            None => {
+                return Err(ClipboardError::FormatConversion(format!(
+                    "RIFF chunk '{}' overruns the file",
+                    String::from_utf8_lossy(id)
+                )))
+            }
+        };
+
+        match id {
+            b"fmt " => format = Some(parse_fmt(&data[body..end])?),
+            b"data" => samples = Some(body..end),
+            _ => {}
+        }
+        // Chunks are padded to even sizes
+        offset = end + (end - body) % 2;
+    }
+
+    let format = format.ok_or_else(|| ClipboardError::FormatConversion("WAV without fmt chunk".to_string()))?;
+    let data = samples.ok_or_else(|| ClipboardError::FormatConversion("WAV without data chunk".to_string()))?;
+    format.validate()?;
+
+    Ok(WavInfo { format, data, len })
+}
+
+/// Validate a WAV file and cut it to the length its RIFF header gives
+pub fn validate_wav(data: &[u8]) -> ClipboardResult<Vec<u8>> {
+    let info = parse_wav(data)?;
+    Ok(data[..info.len].to_vec())
+}
+
+/// Wrap interleaved little-endian samples in a WAV container
+///
+/// Fails if `pcm` is not a whole number of frames.
+pub fn pcm_to_wav(format: &WaveFormat, pcm: &[u8]) -> ClipboardResult<Vec<u8>> {
+    format.validate()?;
+    if pcm.len() % format.block_align() != 0 {
+        return Err(ClipboardError::FormatConversion(format!(
+            "PCM length {} is not a multiple of the {}-byte frame",
+            pcm.len(),
+            format.block_align()
+        )));
+    }
+    let data_len = u32::try_from(pcm.len())
+        .ok()
+        .filter(|len| *len <= u32::MAX - 36)
+        .ok_or(ClipboardError::DataSizeExceeded {
+            actual: pcm.len(),
+            max: (u32::MAX - 36) as usize,
+        })?;
+
+    let mut wav = Vec::with_capacity(44 + pcm.len() + 1);
+    wav.extend_from_slice(b"RIFF");
+    wav.extend_from_slice(&(36 + data_len + data_len % 2).to_le_bytes());
+    wav.extend_from_slice(b"WAVE");
+
+    wav.extend_from_slice(b"fmt ");
+    wav.extend_from_slice(&16u32.to_le_bytes());
+    wav.extend_from_slice(&format.format_tag.to_le_bytes());
+    wav.extend_from_slice(&format.channels.to_le_bytes());
+    wav.extend_from_slice(&format.sample_rate.to_le_bytes());
+    wav.extend_from_slice(&(format.byte_rate() as u32).to_le_bytes());
+    wav.extend_from_slice(&(format.block_align() as u16).to_le_bytes());
+    wav.extend_from_slice(&format.bits_per_sample.to_le_bytes());
+
+    wav.extend_from_slice(b"data");
+    wav.extend_from_slice(&data_len.to_le_bytes());
+    wav.extend_from_slice(pcm);
+    if data_len % 2 == 1 {
+        wav.push(0);
+    }
+
+    Ok(wav)
+}
+
+/// Whether a MIME type is `audio/L16`, with or without parameters
+pub fn is_l16_mime(mime: &str) -> bool {
+    mime.split(';')
+        .next()
+        .is_some_and(|essence| essence.trim().eq_ignore_ascii_case("audio/L16"))
+}
+
+/// Convert `audio/L16` samples to a WAV file
+///
+/// `mime` is the full MIME type; its `rate` parameter is required and
+/// `channels` defaults to 1 (RFC 2586).
+///
+/// # Example
+///
+/// ```
+/// use lamco_clipboard_core::audio::{l16_to_wav, parse_wav};
+///
+/// let wav = l16_to_wav("audio/L16; rate=8000; channels=2", &[0x12, 0x34, 0x56, 0x78]).unwrap();
+/// let info = parse_wav(&wav).unwrap();
+/// assert_eq!(info.format.channels, 2);
+/// assert_eq!(&wav[info.data], &[0x34, 0x12, 0x78, 0x56]);
+/// ```
+pub fn l16_to_wav(mime: &str, data: &[u8]) -> ClipboardResult<Vec<u8>> {
+    if !is_l16_mime(mime) {
+        return Err(ClipboardError::UnsupportedFormat(mime.to_string()));
+    }
+
+    let param = |name: &str| {
+        mime.split(';').skip(1).find_map(|param| {
+            let (key, value) = param.split_once('=')?;
+            key.trim()
+                .eq_ignore_ascii_case(name)
+                .then(|| value.trim().trim_matches('"'))
+        })
+    };
+    let invalid = |name: &str| ClipboardError::FormatConversion(format!("invalid audio/L16 {name} parameter"));
+    let sample_rate = param("rate")
+        .ok_or_else(|| ClipboardError::FormatConversion("audio/L16 without rate parameter".to_string()))?
+        .parse()
+        .map_err(|_| invalid("rate"))?;
+    let channels = param("channels").map_or(Ok(1), |value| value.parse().map_err(|_| invalid("channels")))?;
+
+    let pcm: Vec<u8> = data.chunks_exact(2).flat_map(|pair| [pair[1], pair[0]]).collect();
+    if pcm.len() != data.len() {
+        return Err(ClipboardError::FormatConversion(
+            "audio/L16 data has an odd length".to_string(),
+        ));
+    }
+    pcm_to_wav(&WaveFormat::pcm(channels, sample_rate, 16), &pcm)
+}
+
+fn parse_fmt(chunk: &[u8]) -> ClipboardResult<WaveFormat> {
+    if chunk.len() < 16 {
+        return Err(ClipboardError::FormatConversion(format!(
+            "WAV fmt chunk too short: {} bytes",
+            chunk.len()
+        )));
+    }
+
+    let mut format_tag = read_u16(chunk, 0);
+    // WAVEFORMATEXTENSIBLE: the sub-format GUID starts with the real tag
+    if format_tag == WAVE_FORMAT_EXTENSIBLE && chunk.len() >= 40 {
+        format_tag = read_u16(chunk, 24);
+    }
+
+    Ok(WaveFormat {
+        format_tag,
+        channels: read_u16(chunk, 2),
+        sample_rate: read_u32(chunk, 4),
+        bits_per_sample: read_u16(chunk, 14),
+    })
+}
+
+fn read_u16(data: &[u8], offset: usize) -> u16 {
+    u16::from_le_bytes([data[offset], data[offset + 1]])
+}
+
+fn read_u32(data: &[u8], offset: usize) -> u32 {
+    u32::from_le_bytes([data[offset], data[offset + 1], data[offset + 2], data[offset + 3]])
+}
+
+// =============================================================================
+// FLAC
+// =============================================================================
+
+/// Samples per FLAC frame
+#[cfg(feature = "flac")]
+const FLAC_BLOCK_SIZE: usize = 4096;
+
+/// Decode FLAC into a WAV file
+///
+/// Fails with [`ClipboardError::DataSizeExceeded`] before decoding if the
+/// stream announces more than `max_size` bytes of samples. Sample sizes that
+/// are not whole bytes are widened to the next byte.
+#[cfg(feature = "flac")]
+pub fn flac_to_wav(flac: &[u8], max_size: usize) -> ClipboardResult<Vec<u8>> {
+    let invalid = |e: claxon::Error| ClipboardError::FormatConversion(format!("invalid FLAC: {e}"));
+    let mut reader = claxon::FlacReader::new(flac).map_err(invalid)?;
+    let info = reader.streaminfo();
+
+    let container_bits = info.bits_per_sample.div_ceil(8) * 8;
+    let shift = container_bits - info.bits_per_sample;
+    let format = WaveFormat::pcm(info.channels as u16, info.sample_rate, container_bits as u16);
+    let expected = info.samples.unwrap_or(0).saturating_mul(format.block_align() as u64);
+    if expected > max_size as u64 {
+        return Err(ClipboardError::DataSizeExceeded {
+            actual: expected.min(usize::MAX as u64) as usize,
+            max: max_size,
+        });
+    }
+
+    let mut pcm = Vec::with_capacity(expected as usize);
+    for sample in reader.samples() {
+        let sample = sample.map_err(invalid)? << shift;
+        match container_bits {
+            8 => pcm.push((sample + 128) as u8),
+            16 => pcm.extend_from_slice(&(sample as i16).to_le_bytes()),
+            24 => pcm.extend_from_slice(&sample.to_le_bytes()[..3]),
+            _ => pcm.extend_from_slice(&sample.to_le_bytes()),
+        }
+        if pcm.len() > max_size {
+            return Err(ClipboardError::DataSizeExceeded {
+                actual: pcm.len(),
+                max: max_size,
+            });
+        }
+    }
+
+    pcm_to_wav(&format, &pcm)
+}
+
+/// Encode a WAV file as FLAC
+///
+/// Supports 8, 16 and 24-bit integer PCM with up to 8 channels. Samples are
+/// stored verbatim, so the result is a valid FLAC stream no smaller than the
+/// input; it exists for receivers that only accept FLAC.
+#[cfg(feature = "flac")]
+pub fn wav_to_flac(wav: &[u8]) -> ClipboardResult<Vec<u8>> {
+    let info = parse_wav(wav)?;
+    let format = info.format;
+    if format.format_tag != WAVE_FORMAT_PCM
+        || !matches!(format.bits_per_sample, 8 | 16 | 24)
+        || !(1..=8).contains(&format.channels)
+    {
+        return Err(ClipboardError::UnsupportedFormat(format!(
+            "FLAC from WAV format {:#06x}, {} bits, {} channels",
+            format.format_tag, format.bits_per_sample, format.channels
+        )));
+    }
+
+    let bytes = usize::from(format.bits_per_sample / 8);
+    // Frame header sample size code; decoders need it spelled out
+    let size_code: u8 = match bytes {
+        1 => 0b001,
+        2 => 0b100,
+        _ => 0b110,
+    };
+    let channels = usize::from(format.channels);
+    let frame_len = format.block_align();
+    let pcm = &wav[info.data];
+    let total = pcm.len() / frame_len;
+
+    let mut flac = b"fLaC".to_vec();
+    // Last metadata block: STREAMINFO, 34 bytes
+    flac.extend_from_slice(&[0x80, 0, 0, 34]);
+    flac.extend_from_slice(&(FLAC_BLOCK_SIZE as u16).to_be_bytes());
+    flac.extend_from_slice(&(FLAC_BLOCK_SIZE as u16).to_be_bytes());
+    flac.extend_from_slice(&[0; 6]); // frame sizes unknown
+    let packed = (u64::from(format.sample_rate) << 44)
+        | ((channels as u64 - 1) << 41)
+        | (u64::from(format.bits_per_sample - 1) << 36)
+        | total as u64;
+    flac.extend_from_slice(&packed.to_be_bytes());
+    flac.extend_from_slice(&[0; 16]); // MD5 not computed
+
+    for (index, block) in pcm[..total * frame_len].chunks(FLAC_BLOCK_SIZE * frame_len).enumerate() {
+        let start = flac.len();
+        // Fixed block size, block size in 16 bits after the header,
+        // sample rate from STREAMINFO, independent channels
+        flac.extend_from_slice(&[0xFF, 0xF8, 0x70, ((channels as u8 - 1) << 4) | (size_code << 1)]);
+        push_utf8_number(&mut flac, index as u64);
+        flac.extend_from_slice(&((block.len() / frame_len - 1) as u16).to_be_bytes());
+        flac.push(crc8(&flac[start..]));
+
+        for channel in 0..channels {
+            flac.push(0x02); // verbatim subframe, no wasted bits
+            for frame in block.chunks_exact(frame_len) {
+                let sample = &frame[channel * bytes..(channel + 1) * bytes];
+                match bytes {
+                    // 8-bit WAV is unsigned, FLAC is signed
+                    1 => flac.push(sample[0].wrapping_sub(128)),
+                    2 => flac.extend_from_slice(&[sample[1], sample[0]]),
+                    _ => flac.extend_from_slice(&[sample[2], sample[1], sample[0]]),
+                }
+            }
+        }
+
+        let crc = crc16(&flac[start..]);
+        flac.extend_from_slice(&crc.to_be_bytes());
+    }
+
+    Ok(flac)
+}
+
+/// FLAC's UTF-8-like variable-length frame number
+#[cfg(feature = "flac")]
+fn push_utf8_number(out: &mut Vec<u8>, n: u64) {
+    if n < 0x80 {
+        out.push(n as u8);
+        return;
+    }
+    let mut len = 2;
+    while n >= 1 << (5 * len + 1) {
+        len += 1;
+    }
+    out.push((0xFF00u16 >> len) as u8 | (n >> (6 * (len - 1))) as u8);
+    for i in (0..len - 1).rev() {
+        out.push(0x80 | ((n >> (6 * i)) & 0x3F) as u8);
+    }
+}
+
+/// CRC-8, polynomial 0x07, over a FLAC frame header
+#[cfg(feature = "flac")]
+fn crc8(data: &[u8]) -> u8 {
+    data.iter().fold(0u8, |crc, &byte| {
+        (0..8).fold(
+            crc ^ byte,
+            |crc, _| {
+                if crc & 0x80 != 0 {
+                    (crc << 1) ^ 0x07
+                } else {
+                    crc << 1
+                }
+            },
+        )
+    })
+}
+
+/// CRC-16, polynomial 0x8005, over a whole FLAC frame
+#[cfg(feature = "flac")]
+fn crc16(data: &[u8]) -> u16 {
+    data.iter().fold(0u16, |crc, &byte| {
+        (0..8).fold(crc ^ (u16::from(byte) << 8), |crc, _| {
+            if crc & 0x8000 != 0 {
+                (crc << 1) ^ 0x8005
+            } else {
+                crc << 1
+            }
+        })
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::FormatConverter;
+
+    fn tone() -> Vec<u8> {
+        (0..1000i16)
+            .flat_map(|i| [i * 7, -i * 5])
+            .flat_map(i16::to_le_bytes)
+            .collect()
+    }
+
+    #[test]
+    fn test_parse_wav_chunks() {
+        let mut wav = pcm_to_wav(&WaveFormat::pcm(1, 8000, 8), &[1, 2, 3]).unwrap();
+        // Odd data is padded, then an unknown chunk and trailing garbage follow
+        assert_eq!(wav.len(), 44 + 4);
+        wav.extend_from_slice(b"LIST\x02\x00\x00\x00ab");
+        let riff_size = (wav.len() - 8) as u32;
+        wav[4..8].copy_from_slice(&riff_size.to_le_bytes());
+        wav.extend_from_slice(b"garbage");
+
+        let info = parse_wav(&wav).unwrap();
+        assert_eq!(info.data, 44..47);
+        assert_eq!(validate_wav(&wav).unwrap().len(), wav.len() - 7);
+
+        // Truncated file and missing chunks
+        assert!(parse_wav(&wav[..40]).is_err());
+        let mut no_data = pcm_to_wav(&WaveFormat::pcm(1, 8000, 8), &[]).unwrap();
+        no_data[36..40].copy_from_slice(b"junk");
+        assert!(parse_wav(&no_data).is_err());
+        assert!(parse_wav(b"RIFF\x04\x00\x00\x00AVI ").is_err());
+    }
+
+    #[test]
+    fn test_pcm_and_l16() {
+        let format = WaveFormat::pcm(2, 44100, 16);
+        assert_eq!(format.block_align(), 4);
+        assert_eq!(format.byte_rate(), 176_400);
+        assert!(pcm_to_wav(&format, &[0; 6]).is_err());
+        assert!(pcm_to_wav(&WaveFormat::pcm(0, 44100, 16), &[]).is_err());
+
+        assert!(is_l16_mime("Audio/L16;rate=48000"));
+        assert!(!is_l16_mime("audio/L24;rate=48000"));
+        let wav = l16_to_wav("audio/L16;rate=48000", &[0, 1, 0, 2]).unwrap();
+        let info = parse_wav(&wav).unwrap();
+        assert_eq!(info.format, WaveFormat::pcm(1, 48000, 16));
+        assert_eq!(&wav[info.data], &[1, 0, 2, 0]);
+        assert!(l16_to_wav("audio/L16", &[0, 1]).is_err());
+        assert!(l16_to_wav("audio/L16;rate=8000", &[0]).is_err());
+    }
+
+    #[test]
+    fn test_converter_cf_wave() {
+        let converter = FormatConverter::new();
+        let wav = pcm_to_wav(&WaveFormat::pcm(2, 44100, 16), &tone()).unwrap();
+        assert_eq!(converter.convert("audio/wav", "CF_WAVE", &wav).unwrap(), wav);
+        assert_eq!(converter.convert("CF_RIFF", "audio/wav", &wav).unwrap(), wav);
+        assert!(converter.convert("CF_WAVE", "audio/wav", &wav[..100]).is_err());
+
+        let formats = crate::formats::mime_to_rdp_formats(&["audio/L16; rate=44100; channels=2"]);
+        assert_eq!(formats[0].id, crate::formats::CF_WAVE);
+    }
+
+    #[cfg(feature = "flac")]
+    #[test]
+    fn test_flac_round_trip() {
+        let converter = FormatConverter::new();
+        let pcm: Vec<u8> = tone().repeat(5);
+        let wav = pcm_to_wav(&WaveFormat::pcm(2, 44100, 16), &pcm).unwrap();
+
+        let flac = converter.convert("CF_WAVE", "audio/flac", &wav).unwrap();
+        assert!(flac.starts_with(b"fLaC"));
+        assert_eq!(converter.convert("audio/flac", "CF_WAVE", &flac).unwrap(), wav);
+
+        for (bits, pcm) in [(8, vec![0u8, 128, 255, 7]), (24, vec![1, 2, 0x80, 4, 5, 6])] {
+            let wav = pcm_to_wav(&WaveFormat::pcm(1, 8000, bits), &pcm).unwrap();
+            assert_eq!(flac_to_wav(&wav_to_flac(&wav).unwrap(), 1 << 20).unwrap(), wav);
+        }
+        assert!(matches!(
+            flac_to_wav(&flac, 100),
+            Err(ClipboardError::DataSizeExceeded { .. })
+        ));
+
+        let mut numbers = Vec::new();
+        push_utf8_number(&mut numbers, 0x7F);
+        push_utf8_number(&mut numbers, 0x80);
+        push_utf8_number(&mut numbers, 0x800);
+        assert_eq!(numbers, [0x7F, 0xC2, 0x80, 0xE0, 0xA0, 0x80]);
+    }
+}
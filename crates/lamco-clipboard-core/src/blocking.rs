@@ -0,0 +1,139 @@
+//! Synchronous facade over [`ClipboardSink`].
+//!
+//! GTK main loops, C FFI callers and other non-async integrators cannot
+//! await futures directly. [`BlockingSink`] drives the async sink methods to
+//! completion on a Tokio runtime and exposes plain blocking calls instead.
+
+use crate::sink::{ClipboardChangeReceiver, ClipboardSink, FileInfo};
+use crate::ClipboardResult;
+use tokio::runtime::{Builder, Handle, Runtime};
+
+/// Blocking adapter for any [`ClipboardSink`].
+///
+/// The adapter either owns a dedicated single-worker runtime ([`new`](Self::new))
+/// or borrows a handle to an existing one ([`with_handle`](Self::with_handle)).
+/// The owned runtime keeps a worker thread alive, so background tasks spawned
+/// by the sink (for example a [`PollingChangeDriver`](crate::PollingChangeDriver)
+/// poll loop) keep running between calls.
+///
+/// # Panics
+///
+/// Every method blocks the calling thread and panics if called from within
+/// an async context. Dropping an adapter that owns its runtime from within
+/// an async context also panics.
+///
+/// # Example
+///
+/// ```rust,ignore
+/// use lamco_clipboard_core::BlockingSink;
+///
+/// let clipboard = BlockingSink::new(my_sink)?;
+/// let text = clipboard.read_clipboard("text/plain;charset=utf-8")?;
+/// ```
+#[derive(Debug)]
+pub struct BlockingSink<S> {
+    sink: S,
+    handle: Handle,
+    _runtime: Option<Runtime>,
+}
+
+impl<S: ClipboardSink> BlockingSink<S> {
+    /// Wrap a sink, creating a dedicated runtime to drive it
+    pub fn new(sink: S) -> ClipboardResult<Self> {
+        let runtime = Builder::new_multi_thread()
+            .worker_threads(1)
+            .thread_name("clipboard-blocking")
+            .enable_all()
+            .build()?;
+
+        Ok(Self {
+            sink,
+            handle: runtime.handle().clone(),
+            _runtime: Some(runtime),
+        })
+    }
+
+    /// Wrap a sink, driving it on an existing runtime
+    pub fn with_handle(sink: S, handle: Handle) -> Self {
+        Self {
+            sink,
+            handle,
+            _runtime: None,
+        }
+    }
+
+    /// Get a reference to the wrapped sink
+    pub fn inner(&self) -> &S {
+        &self.sink
+    }
+
+    /// Get the runtime handle used to drive the sink
+    pub fn handle(&self) -> &Handle {
+        &self.handle
+    }
+
+    /// Announce that new clipboard content is available
+    pub fn announce_formats(&self, mime_types: Vec<String>) -> ClipboardResult<()> {
+        self.handle.block_on(self.sink.announce_formats(mime_types))
+    }
+
+    /// Read clipboard data in the specified format
+    pub fn read_clipboard(&self, mime_type: &str) -> ClipboardResult<Vec<u8>> {
+        self.handle.block_on(self.sink.read_clipboard(mime_type))
+    }
+
+    /// Write data to the local clipboard
+    pub fn write_clipboard(&self, mime_type: &str, data: Vec<u8>) -> ClipboardResult<()> {
+        self.handle.block_on(self.sink.write_clipboard(mime_type, data))
+    }
+
+    /// Subscribe to local clipboard changes.
+    ///
+    /// Use [`ClipboardChangeReceiver::recv_blocking`] or
+    /// [`ClipboardChangeReceiver::try_recv`] to consume the changes.
+    pub fn subscribe_changes(&self) -> ClipboardResult<ClipboardChangeReceiver> {
+        self.handle.block_on(self.sink.subscribe_changes())
+    }
+
+    /// Get the list of files in the clipboard
+    pub fn get_file_list(&self) -> ClipboardResult<Vec<FileInfo>> {
+        self.handle.block_on(self.sink.get_file_list())
+    }
+
+    /// Read a chunk of file data
+    pub fn read_file_chunk(&self, index: u32, offset: u64, size: u32) -> ClipboardResult<Vec<u8>> {
+        self.handle.block_on(self.sink.read_file_chunk(index, offset, size))
+    }
+
+    /// Write a received file to the local filesystem
+    pub fn write_file(&self, path: &str, data: Vec<u8>) -> ClipboardResult<()> {
+        self.handle.block_on(self.sink.write_file(path, data))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::sink::MemorySink;
+
+    #[test]
+    fn test_owned_runtime_roundtrip() {
+        let sink =
+            BlockingSink::new(MemorySink::new().with_files(vec![("a.txt".to_string(), b"abc".to_vec())])).unwrap();
+
+        sink.write_clipboard("text/plain", b"hello".to_vec()).unwrap();
+        assert_eq!(sink.read_clipboard("text/plain").unwrap(), b"hello");
+        assert!(sink.read_clipboard("text/html").is_err());
+        assert_eq!(sink.get_file_list().unwrap().len(), 1);
+        assert_eq!(sink.read_file_chunk(0, 0, 4).unwrap(), b"abc");
+    }
+
+    #[test]
+    fn test_existing_handle() {
+        let runtime = Builder::new_current_thread().build().unwrap();
+        let sink = BlockingSink::with_handle(MemorySink::default(), runtime.handle().clone());
+
+        sink.announce_formats(vec!["text/plain".to_string()]).unwrap();
+        assert!(sink.subscribe_changes().is_err());
+    }
+}
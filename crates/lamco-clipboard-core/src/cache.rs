@@ -0,0 +1,275 @@
+//! Cache of recent image conversions.
+//!
+//! Windows sends several Format Data Requests for the same format during one
+//! paste, and without a cache each of them decodes and re-encodes the whole
+//! image. [`FormatConverter::convert`] keeps the results of recent built-in
+//! image conversions, keyed by the SHA-256 of the source data, the format
+//! pair and the converter's image settings, and serves repeats from memory.
+//!
+//! Text conversions are cheaper than hashing their input and are not cached,
+//! nor are registered converters, which need not return the same output
+//! twice.
+
+use crate::formats::FormatConverter;
+use crate::ClipboardResult;
+use sha2::{Digest, Sha256};
+use std::collections::VecDeque;
+use std::sync::Mutex;
+
+/// Default number of conversion results kept
+pub const DEFAULT_CACHE_ENTRIES: usize = 8;
+
+/// Default total size of the conversion results kept
+pub const DEFAULT_CACHE_BYTES: usize = 64 * 1024 * 1024;
+
+/// Size of the conversion cache
+///
+/// The least recently used result is dropped once either limit is exceeded.
+/// Results larger than `max_bytes` are never kept; `max_entries: 0` turns
+/// the cache off.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "serde", serde(default))]
+pub struct ConversionCacheConfig {
+    /// Maximum number of results (default: 8)
+    pub max_entries: usize,
+
+    /// Maximum total size of the results in bytes (default: 64 MiB)
+    pub max_bytes: usize,
+}
+
+impl ConversionCacheConfig {
+    /// No caching
+    pub const DISABLED: Self = Self {
+        max_entries: 0,
+        max_bytes: 0,
+    };
+}
+
+impl Default for ConversionCacheConfig {
+    fn default() -> Self {
+        Self {
+            max_entries: DEFAULT_CACHE_ENTRIES,
+            max_bytes: DEFAULT_CACHE_BYTES,
+        }
+    }
+}
+
+/// Counters describing the conversion cache
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct CacheMetrics {
+    /// Conversions served from the cache
+    pub hits: u64,
+
+    /// Cacheable conversions that had to run
+    pub misses: u64,
+
+    /// Results currently kept
+    pub entries: usize,
+
+    /// Total size of the results currently kept
+    pub bytes: usize,
+}
+
+type CacheKey = [u8; 32];
+
+/// Least recently used conversion results, oldest first
+#[derive(Debug, Default)]
+pub(crate) struct ConversionCache {
+    config: ConversionCacheConfig,
+    state: Mutex<CacheState>,
+}
+
+#[derive(Debug, Default)]
+struct CacheState {
+    entries: VecDeque<(CacheKey, Vec<u8>)>,
+    bytes: usize,
+    hits: u64,
+    misses: u64,
+}
+
+impl ConversionCache {
+    fn new(config: ConversionCacheConfig) -> Self {
+        Self {
+            config,
+            state: Mutex::default(),
+        }
+    }
+
+    fn state(&self) -> std::sync::MutexGuard<'_, CacheState> {
+        self.state.lock().unwrap_or_else(|e| e.into_inner())
+    }
+
+    fn get(&self, key: &CacheKey) -> Option<Vec<u8>> {
+        let mut state = self.state();
+        let Some(index) = state.entries.iter().position(|(k, _)| k == key) else {
+            state.misses += 1;
+            return None;
+        };
+        state.hits += 1;
+
+        let entry = state.entries.remove(index)?;
+        let value = entry.1.clone();
+        state.entries.push_back(entry);
+        Some(value)
+    }
+
+    fn insert(&self, key: CacheKey, value: Vec<u8>) {
+        if value.len() > self.config.max_bytes {
+            return;
+        }
+
+        let mut state = self.state();
+        if state.entries.iter().any(|(k, _)| *k == key) {
+            return;
+        }
+        state.bytes += value.len();
+        state.entries.push_back((key, value));
+
+        while state.entries.len() > self.config.max_entries || state.bytes > self.config.max_bytes {
+            let Some((_, evicted)) = state.entries.pop_front() else {
+                break;
+            };
+            state.bytes -= evicted.len();
+        }
+    }
+}
+
+/// Whether a format name refers to an image format
+pub(crate) fn is_image_format(name: &str) -> bool {
+    name.starts_with("image/")
+        || matches!(
+            name,
+            "CF_DIB"
+                | "CF_DIBV5"
+                | "CF_DSPBITMAP"
+                | "CF_TIFF"
+                | "CF_ENHMETAFILE"
+                | "CF_METAFILEPICT"
+                | "PNG"
+                | "JFIF"
+                | "GIF"
+                | "WebP"
+        )
+}
+
+impl FormatConverter {
+    /// Set the size of the cache for image conversions
+    pub fn with_conversion_cache(mut self, config: ConversionCacheConfig) -> Self {
+        self.cache = ConversionCache::new(config);
+        self
+    }
+
+    /// Hits, misses and current size of the conversion cache
+    pub fn cache_metrics(&self) -> CacheMetrics {
+        let state = self.cache.state();
+        CacheMetrics {
+            hits: state.hits,
+            misses: state.misses,
+            entries: state.entries.len(),
+            bytes: state.bytes,
+        }
+    }
+
+    /// Drop all cached conversion results
+    pub fn clear_conversion_cache(&self) {
+        let mut state = self.cache.state();
+        state.entries.clear();
+        state.bytes = 0;
+    }
+
+    /// Run a conversion, or return its result from the cache
+    pub(crate) fn cached(
+        &self,
+        from: &str,
+        to: &str,
+        data: &[u8],
+        convert: impl FnOnce() -> ClipboardResult<Vec<u8>>,
+    ) -> ClipboardResult<Vec<u8>> {
+        if self.cache.config.max_entries == 0 {
+            return convert();
+        }
+
+        let mut hasher = Sha256::new();
+        hasher.update(from.as_bytes());
+        hasher.update([0]);
+        hasher.update(to.as_bytes());
+        hasher.update([0]);
+        hasher.update(format!("{:?}{:?}", self.image_limits, self.image_encode).as_bytes());
+        hasher.update(data);
+        let key: CacheKey = hasher.finalize().into();
+
+        if let Some(hit) = self.cache.get(&key) {
+            return Ok(hit);
+        }
+        let result = convert()?;
+        self.cache.insert(key, result.clone());
+        Ok(result)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::fixtures::DibBuilder;
+
+    #[test]
+    fn test_repeated_conversion_served_from_cache() {
+        let converter = FormatConverter::new();
+        let dib = DibBuilder::new(32, 32).build();
+
+        let first = converter.convert("CF_DIB", "image/png", &dib).unwrap();
+        let second = converter.convert("CF_DIB", "image/png", &dib).unwrap();
+        assert_eq!(first, second);
+
+        let metrics = converter.cache_metrics();
+        assert_eq!((metrics.hits, metrics.misses, metrics.entries), (1, 1, 1));
+        assert_eq!(metrics.bytes, first.len());
+
+        // Text is never cached
+        converter.convert("text/plain", "CF_UNICODETEXT", b"hi").unwrap();
+        assert_eq!(converter.cache_metrics().misses, 1);
+
+        converter.clear_conversion_cache();
+        assert_eq!(converter.cache_metrics().entries, 0);
+    }
+
+    #[test]
+    fn test_least_recently_used_evicted() {
+        let converter = FormatConverter::new().with_conversion_cache(ConversionCacheConfig {
+            max_entries: 2,
+            ..ConversionCacheConfig::default()
+        });
+        let dibs: Vec<_> = (1..=3).map(|size| DibBuilder::new(size, size).build()).collect();
+
+        converter.convert("CF_DIB", "image/png", &dibs[0]).unwrap();
+        converter.convert("CF_DIB", "image/png", &dibs[1]).unwrap();
+        converter.convert("CF_DIB", "image/png", &dibs[0]).unwrap();
+        converter.convert("CF_DIB", "image/png", &dibs[2]).unwrap();
+        assert_eq!(converter.cache_metrics().entries, 2);
+
+        // dibs[1] was the least recently used
+        converter.convert("CF_DIB", "image/png", &dibs[0]).unwrap();
+        converter.convert("CF_DIB", "image/png", &dibs[1]).unwrap();
+        let metrics = converter.cache_metrics();
+        assert_eq!((metrics.hits, metrics.misses), (2, 4));
+    }
+
+    #[test]
+    fn test_disabled_and_settings_in_key() {
+        let dib = DibBuilder::new(16, 16).build();
+
+        let converter = FormatConverter::new().with_conversion_cache(ConversionCacheConfig::DISABLED);
+        converter.convert("CF_DIB", "image/png", &dib).unwrap();
+        converter.convert("CF_DIB", "image/png", &dib).unwrap();
+        assert_eq!(converter.cache_metrics(), CacheMetrics::default());
+
+        let mut converter = FormatConverter::new();
+        converter.convert("CF_DIB", "image/png", &dib).unwrap();
+        converter.image_limits.max_width = 8;
+        let png = converter.convert("CF_DIB", "image/png", &dib).unwrap();
+        assert_eq!(converter.cache_metrics().hits, 0);
+        let image = image::load_from_memory(&png).unwrap();
+        assert_eq!(image.width(), 8);
+    }
+}
@@ -0,0 +1,102 @@
+//! Time source for loop detection and transfers.
+//!
+//! [`LoopDetector`](crate::LoopDetector) windows and
+//! [`TransferEngine`](crate::TransferEngine) timeouts read monotonic time
+//! through the [`Clock`] trait. [`SystemClock`] wraps [`Instant`]; tests use
+//! [`ManualClock`] to step time forward without sleeping, and platforms
+//! where `Instant` is unavailable can supply their own implementation.
+
+use std::fmt;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, OnceLock};
+use std::time::{Duration, Instant};
+
+/// Source of monotonic time
+pub trait Clock: fmt::Debug + Send + Sync {
+    /// Time since an arbitrary fixed origin; must never decrease
+    fn now(&self) -> Duration;
+}
+
+impl<C: Clock + ?Sized> Clock for Arc<C> {
+    fn now(&self) -> Duration {
+        (**self).now()
+    }
+}
+
+/// [`Instant`]-based clock, the default
+#[derive(Debug, Clone, Copy, Default)]
+pub struct SystemClock;
+
+impl Clock for SystemClock {
+    fn now(&self) -> Duration {
+        static ORIGIN: OnceLock<Instant> = OnceLock::new();
+        ORIGIN.get_or_init(Instant::now).elapsed()
+    }
+}
+
+/// Clock that only moves when told to
+///
+/// Clones share the same time, so a test can keep one and hand another to
+/// the component under test.
+///
+/// # Example
+///
+/// ```rust
+/// use lamco_clipboard_core::{ClipboardFormat, LoopDetector, ManualClock};
+/// use lamco_clipboard_core::loop_detector::ClipboardSource;
+/// use std::time::Duration;
+///
+/// let clock = ManualClock::new();
+/// let detector = LoopDetector::new().with_clock(clock.clone());
+///
+/// let formats = vec![ClipboardFormat::unicode_text()];
+/// detector.record_formats(&formats, ClipboardSource::Rdp);
+/// assert!(detector.would_cause_loop(&formats));
+///
+/// clock.advance(Duration::from_secs(1));
+/// assert!(!detector.would_cause_loop(&formats));
+/// ```
+#[derive(Debug, Clone, Default)]
+pub struct ManualClock {
+    nanos: Arc<AtomicU64>,
+}
+
+impl ManualClock {
+    /// Create a clock reading zero
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Move the clock forward
+    pub fn advance(&self, by: Duration) {
+        let nanos = u64::try_from(by.as_nanos()).unwrap_or(u64::MAX);
+        let _ = self.nanos.fetch_update(Ordering::Relaxed, Ordering::Relaxed, |now| {
+            Some(now.saturating_add(nanos))
+        });
+    }
+}
+
+impl Clock for ManualClock {
+    fn now(&self) -> Duration {
+        Duration::from_nanos(self.nanos.load(Ordering::Relaxed))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_manual_clock_shared_between_clones() {
+        let clock = ManualClock::new();
+        let shared = clock.clone();
+        assert_eq!(shared.now(), Duration::ZERO);
+
+        clock.advance(Duration::from_millis(250));
+        assert_eq!(shared.now(), Duration::from_millis(250));
+
+        let system = SystemClock;
+        let before = system.now();
+        assert!(system.now() >= before);
+    }
+}
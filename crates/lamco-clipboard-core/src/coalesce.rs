@@ -0,0 +1,332 @@
+//! Coalescing bursts of local clipboard changes.
+//!
+//! Clipboard managers, and applications that set several formats one at a
+//! time, change the clipboard many times for a single copy. Announcing each
+//! change sends the peer a Format List PDU it will likely never request
+//! data for. [`ChangeCoalescer`] sits between a sink's change events and the
+//! session: it holds a change until the clipboard has been quiet for
+//! [`quiet_period_ms`](CoalesceConfig::quiet_period_ms), then releases the
+//! latest one. A steady stream of changes is still released every
+//! [`max_delay_ms`](CoalesceConfig::max_delay_ms).
+//!
+//! A released change with the same MIME types and content hash as the one
+//! released before it is dropped, since the peer already has that content
+//! announced. Changes without a content hash are always released.
+//!
+//! Like [`FileTransfer`](crate::file_transfer::FileTransfer) it does no I/O
+//! and reads time through a [`Clock`]: [`push`](ChangeCoalescer::push)
+//! changes as they arrive and [`poll`](ChangeCoalescer::poll) when
+//! [`next_poll`](ChangeCoalescer::next_poll) comes due.
+//!
+//! # Example
+//!
+//! ```rust
+//! use lamco_clipboard_core::{ChangeCoalescer, ClipboardChange, ManualClock};
+//! use std::time::Duration;
+//!
+//! let clock = ManualClock::new();
+//! let mut coalescer = ChangeCoalescer::new().with_clock(clock.clone());
+//!
+//! coalescer.push(ClipboardChange::new(vec!["text/plain".to_string()]));
+//! coalescer.push(ClipboardChange::new(vec!["text/plain".to_string(), "text/html".to_string()]));
+//! assert!(coalescer.poll().is_none());
+//!
+//! clock.advance(coalescer.next_poll().unwrap());
+//! let change = coalescer.poll().unwrap();
+//! assert_eq!(change.mime_types.len(), 2);
+//! assert!(coalescer.poll().is_none());
+//! ```
+
+use crate::clock::{Clock, SystemClock};
+use crate::sink::ClipboardChange;
+use std::sync::Arc;
+use std::time::Duration;
+
+/// Default time the clipboard must stay unchanged before a change is released, in milliseconds
+pub const DEFAULT_QUIET_PERIOD_MS: u64 = 100;
+
+/// Default longest time a change is held during a burst, in milliseconds
+pub const DEFAULT_MAX_COALESCE_DELAY_MS: u64 = 500;
+
+/// Configuration for [`ChangeCoalescer`]
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "serde", serde(default))]
+pub struct CoalesceConfig {
+    /// Time without further changes before the latest is released
+    /// (milliseconds, 0 releases every change on the next poll)
+    pub quiet_period_ms: u64,
+
+    /// Longest time the first change of a burst is held (milliseconds)
+    pub max_delay_ms: u64,
+
+    /// Drop changes whose MIME types and content hash match the last release
+    pub skip_unchanged: bool,
+}
+
+impl Default for CoalesceConfig {
+    fn default() -> Self {
+        Self {
+            quiet_period_ms: DEFAULT_QUIET_PERIOD_MS,
+            max_delay_ms: DEFAULT_MAX_COALESCE_DELAY_MS,
+            skip_unchanged: true,
+        }
+    }
+}
+
+impl CoalesceConfig {
+    /// Set the quiet period and the longest hold
+    pub fn with_delays(mut self, quiet_period_ms: u64, max_delay_ms: u64) -> Self {
+        self.quiet_period_ms = quiet_period_ms;
+        self.max_delay_ms = max_delay_ms;
+        self
+    }
+
+    /// Set whether unchanged content is announced again
+    pub fn with_skip_unchanged(mut self, skip_unchanged: bool) -> Self {
+        self.skip_unchanged = skip_unchanged;
+        self
+    }
+}
+
+/// A change waiting for its burst to end
+#[derive(Debug)]
+struct Pending {
+    change: ClipboardChange,
+    /// Clock time the burst began
+    first_at: Duration,
+    /// Clock time of the latest change
+    last_at: Duration,
+}
+
+/// What a released change announced
+#[derive(Debug, PartialEq, Eq)]
+struct Released {
+    mime_types: Vec<String>,
+    content_hash: Option<String>,
+}
+
+impl Released {
+    fn of(change: &ClipboardChange) -> Self {
+        let mut mime_types = change.mime_types.clone();
+        mime_types.sort_unstable();
+        mime_types.dedup();
+        Self {
+            mime_types,
+            content_hash: change.content_hash.clone(),
+        }
+    }
+}
+
+/// Debounces local clipboard changes into one announcement per burst.
+///
+/// The clipboard and the primary selection are coalesced separately, so a
+/// run of selection changes never swallows a copy.
+#[derive(Debug)]
+pub struct ChangeCoalescer {
+    config: CoalesceConfig,
+    clock: Arc<dyn Clock>,
+
+    /// Held change per selection, clipboard first
+    pending: [Option<Pending>; 2],
+
+    /// Last release per selection, clipboard first
+    released: [Option<Released>; 2],
+
+    /// Changes superseded within a burst or dropped as unchanged
+    suppressed: u64,
+}
+
+impl Default for ChangeCoalescer {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl ChangeCoalescer {
+    /// Create a coalescer with the default configuration
+    pub fn new() -> Self {
+        Self {
+            config: CoalesceConfig::default(),
+            clock: Arc::new(SystemClock),
+            pending: [None, None],
+            released: [None, None],
+            suppressed: 0,
+        }
+    }
+
+    /// Use a custom configuration
+    pub fn with_config(mut self, config: CoalesceConfig) -> Self {
+        self.config = config;
+        self
+    }
+
+    /// Read time from `clock` instead of the system clock
+    pub fn with_clock(mut self, clock: impl Clock + 'static) -> Self {
+        self.clock = Arc::new(clock);
+        self
+    }
+
+    /// Get the configuration
+    pub fn config(&self) -> &CoalesceConfig {
+        &self.config
+    }
+
+    /// Number of changes that were never released
+    pub fn suppressed(&self) -> u64 {
+        self.suppressed
+    }
+
+    /// Hold a change, replacing any held for the same selection
+    pub fn push(&mut self, change: ClipboardChange) {
+        let now = self.clock.now();
+        let slot = &mut self.pending[usize::from(change.is_primary)];
+        let first_at = match slot.take() {
+            Some(superseded) => {
+                self.suppressed += 1;
+                superseded.first_at
+            }
+            None => now,
+        };
+        *slot = Some(Pending {
+            change,
+            first_at,
+            last_at: now,
+        });
+    }
+
+    /// Take the next change to announce, if a burst has ended
+    ///
+    /// Call repeatedly until it returns `None`.
+    pub fn poll(&mut self) -> Option<ClipboardChange> {
+        let now = self.clock.now();
+        for index in 0..self.pending.len() {
+            let due = self.pending[index]
+                .as_ref()
+                .is_some_and(|pending| self.due_at(pending) <= now);
+            if !due {
+                continue;
+            }
+            let Some(pending) = self.pending[index].take() else {
+                continue;
+            };
+
+            let released = Released::of(&pending.change);
+            let unchanged = released.content_hash.is_some() && self.released[index].as_ref() == Some(&released);
+            if self.config.skip_unchanged && unchanged {
+                tracing::debug!("Skipping unchanged clipboard announcement {:?}", released.mime_types);
+                self.suppressed += 1;
+                continue;
+            }
+            self.released[index] = Some(released);
+            return Some(pending.change);
+        }
+        None
+    }
+
+    /// Time until [`poll()`](Self::poll) has a change to release
+    pub fn next_poll(&self) -> Option<Duration> {
+        let now = self.clock.now();
+        self.pending
+            .iter()
+            .flatten()
+            .map(|pending| self.due_at(pending).saturating_sub(now))
+            .min()
+    }
+
+    /// Forget the last releases, so the next change is announced even if unchanged
+    ///
+    /// Call it when the peer takes over the clipboard with a Format List of
+    /// its own; copying the same local content again must then be announced.
+    /// A clipboard change still held is dropped, since the peer's copy is
+    /// newer; a held primary selection change is kept.
+    pub fn forget(&mut self) {
+        self.released = [None, None];
+        if self.pending[0].take().is_some() {
+            self.suppressed += 1;
+        }
+    }
+
+    fn due_at(&self, pending: &Pending) -> Duration {
+        let quiet = pending.last_at + Duration::from_millis(self.config.quiet_period_ms);
+        let deadline = pending.first_at + Duration::from_millis(self.config.max_delay_ms);
+        quiet.min(deadline)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ManualClock;
+
+    fn change(mime_types: &[&str], hash: &str) -> ClipboardChange {
+        ClipboardChange::new(mime_types.iter().map(|mime| mime.to_string()).collect()).with_hash(hash)
+    }
+
+    #[test]
+    fn test_burst_released_once() {
+        let clock = ManualClock::new();
+        let mut coalescer = ChangeCoalescer::new()
+            .with_config(CoalesceConfig::default().with_delays(100, 300))
+            .with_clock(clock.clone());
+
+        // A change every 50 ms keeps the burst going until the longest hold
+        for _ in 0..6 {
+            coalescer.push(change(&["text/plain"], "a"));
+            assert!(coalescer.poll().is_none());
+            clock.advance(Duration::from_millis(50));
+        }
+        assert_eq!(coalescer.next_poll(), Some(Duration::ZERO));
+        assert!(coalescer.poll().is_some());
+        assert!(coalescer.poll().is_none());
+        assert_eq!(coalescer.suppressed(), 5);
+
+        // The primary selection is held on its own
+        coalescer.push(change(&["text/plain"], "b"));
+        coalescer.push(change(&["text/plain"], "c").with_primary(true));
+        clock.advance(Duration::from_millis(100));
+        assert!(coalescer.poll().is_some_and(|change| !change.is_primary));
+        assert!(coalescer.poll().is_some_and(|change| change.is_primary));
+    }
+
+    #[test]
+    fn test_unchanged_content_skipped() {
+        let clock = ManualClock::new();
+        let mut coalescer = ChangeCoalescer::new().with_clock(clock.clone());
+        let release = |coalescer: &mut ChangeCoalescer, change: ClipboardChange| {
+            coalescer.push(change);
+            clock.advance(Duration::from_secs(1));
+            coalescer.poll()
+        };
+
+        assert!(release(&mut coalescer, change(&["text/plain", "text/html"], "a")).is_some());
+        // Same formats in another order, same content
+        assert!(release(&mut coalescer, change(&["text/html", "text/plain"], "a")).is_none());
+        assert!(release(&mut coalescer, change(&["text/plain", "text/html"], "b")).is_some());
+        // Without a hash the content may differ
+        let unhashed = ClipboardChange::new(vec!["text/plain".to_string()]);
+        assert!(release(&mut coalescer, unhashed.clone()).is_some());
+        assert!(release(&mut coalescer, unhashed).is_some());
+
+        // After the peer owned the clipboard, the same copy is news again
+        assert!(release(&mut coalescer, change(&["text/plain"], "c")).is_some());
+        coalescer.forget();
+        assert!(release(&mut coalescer, change(&["text/plain"], "c")).is_some());
+    }
+
+    #[test]
+    fn test_forget_drops_held_change() {
+        let clock = ManualClock::new();
+        let mut coalescer = ChangeCoalescer::new().with_clock(clock.clone());
+
+        // A local copy still held when the peer copies must not override it
+        coalescer.push(change(&["text/plain"], "a"));
+        coalescer.push(change(&["text/plain"], "b").with_primary(true));
+        coalescer.forget();
+        clock.advance(Duration::from_secs(1));
+        assert!(coalescer.poll().is_some_and(|change| change.is_primary));
+        assert!(coalescer.poll().is_none());
+        assert_eq!(coalescer.next_poll(), None);
+        assert_eq!(coalescer.suppressed(), 1);
+    }
+}
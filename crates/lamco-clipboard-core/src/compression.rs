@@ -0,0 +1,220 @@
+//! Optional zstd compression of clipboard payloads between two peers running
+//! this library.
+//!
+//! Large text and HTML copies and file contents cross slow WAN links
+//! uncompressed in plain CLIPRDR. Compression is negotiated per payload so
+//! that peers without it are never sent anything they cannot read:
+//!
+//! - The copying side announces a wrapper format next to every format it
+//!   can compress, named `LamcoZstd:` followed by the wrapped format's name
+//!   (`LamcoZstd:HTML Format`, `LamcoZstd:CF_UNICODETEXT`, ...).
+//! - A pasting side that can decompress requests the wrapper instead of the
+//!   plain format. The response carries a frame: the payload compressed, or
+//!   stored as is when it is below the copying side's threshold.
+//! - File Contents Requests carry no format, so a pasting side opts in for
+//!   a whole copy by sending a Format Data Request for
+//!   [`FILE_CONTENTS_FORMAT_NAME`] before its first File Contents Request.
+//!   The copying side answers it with an empty response and frames range
+//!   responses for that copy from then on.
+//!
+//! [`ClipboardSession::with_compression`](crate::protocol::ClipboardSession::with_compression)
+//! handles the formats transparently. For file contents, see
+//! [`FileServer::with_compression`](crate::file_transfer::FileServer::with_compression)
+//! and [`FileTransfer::with_compression`](crate::file_transfer::FileTransfer::with_compression).
+//!
+//! Wrapper names are longer than the 16 characters of short format names,
+//! so both peers must use long format names.
+//!
+//! # Example
+//!
+//! ```
+//! use lamco_clipboard_core::compression::{compress, decompress, CompressionConfig};
+//!
+//! let html = "<p>quarterly report</p>".repeat(1000);
+//! let frame = compress(html.as_bytes(), &CompressionConfig::default())?;
+//! assert!(frame.len() < html.len() / 10);
+//! assert_eq!(decompress(&frame, 1 << 20)?, html.as_bytes());
+//! # Ok::<(), lamco_clipboard_core::ClipboardError>(())
+//! ```
+
+use crate::{ClipboardError, ClipboardResult};
+
+/// Prefix of the wrapper format names
+pub const WRAPPED_FORMAT_PREFIX: &str = "LamcoZstd:";
+
+/// Wrapper format requested to opt in to compressed File Contents Responses
+pub const FILE_CONTENTS_FORMAT_NAME: &str = "LamcoZstd:FileContents";
+
+/// Default size below which payloads are sent uncompressed: 4 KiB
+pub const DEFAULT_COMPRESSION_THRESHOLD: usize = 4 * 1024;
+
+/// Default zstd level, favoring speed
+pub const DEFAULT_COMPRESSION_LEVEL: i32 = 3;
+
+/// Formats worth compressing, by registered or `CF_*` name
+const COMPRESSIBLE_FORMATS: &[&str] = &[
+    "CF_UNICODETEXT",
+    "CF_TEXT",
+    "CF_OEMTEXT",
+    "HTML Format",
+    "Rich Text Format",
+    "FileContents",
+];
+
+/// Frame method byte: payload follows as is
+const METHOD_STORED: u8 = 0;
+
+/// Frame method byte: u32 LE original size, then a zstd frame
+const METHOD_ZSTD: u8 = 1;
+
+/// Compression settings of the copying side
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "serde", serde(default))]
+pub struct CompressionConfig {
+    /// Payloads smaller than this are stored uncompressed
+    pub threshold_bytes: usize,
+
+    /// zstd compression level (1-22)
+    pub level: i32,
+}
+
+impl Default for CompressionConfig {
+    fn default() -> Self {
+        Self {
+            threshold_bytes: DEFAULT_COMPRESSION_THRESHOLD,
+            level: DEFAULT_COMPRESSION_LEVEL,
+        }
+    }
+}
+
+/// Whether payloads of a format are compressed when the peer asks
+pub fn is_compressible(format: &str) -> bool {
+    COMPRESSIBLE_FORMATS.contains(&format)
+}
+
+/// Name of the wrapper format for `format`
+pub fn wrapped_name(format: &str) -> String {
+    format!("{}{}", WRAPPED_FORMAT_PREFIX, format)
+}
+
+/// Name of the format a wrapper format wraps
+///
+/// ```
+/// use lamco_clipboard_core::compression::unwrapped_name;
+///
+/// assert_eq!(unwrapped_name("LamcoZstd:HTML Format"), Some("HTML Format"));
+/// assert_eq!(unwrapped_name("HTML Format"), None);
+/// ```
+pub fn unwrapped_name(name: &str) -> Option<&str> {
+    name.strip_prefix(WRAPPED_FORMAT_PREFIX)
+        .filter(|inner| !inner.is_empty())
+}
+
+/// Frame a payload for a wrapper format response
+///
+/// Payloads below the threshold, or that zstd cannot shrink, are stored
+/// uncompressed.
+pub fn compress(data: &[u8], config: &CompressionConfig) -> ClipboardResult<Vec<u8>> {
+    if data.len() >= config.threshold_bytes {
+        let original = u32::try_from(data.len()).map_err(|_| ClipboardError::DataSizeExceeded {
+            actual: data.len(),
+            max: u32::MAX as usize,
+        })?;
+        let compressed = zstd::bulk::compress(data, config.level)
+            .map_err(|e| ClipboardError::FormatConversion(format!("zstd compression failed: {}", e)))?;
+        if compressed.len() + 5 < data.len() {
+            let mut frame = Vec::with_capacity(compressed.len() + 5);
+            frame.push(METHOD_ZSTD);
+            frame.extend_from_slice(&original.to_le_bytes());
+            frame.extend_from_slice(&compressed);
+            return Ok(frame);
+        }
+    }
+
+    let mut frame = Vec::with_capacity(data.len() + 1);
+    frame.push(METHOD_STORED);
+    frame.extend_from_slice(data);
+    Ok(frame)
+}
+
+/// Payload of a wrapper format response
+///
+/// Frames claiming more than `max_size` bytes are rejected before anything
+/// is decompressed.
+pub fn decompress(frame: &[u8], max_size: usize) -> ClipboardResult<Vec<u8>> {
+    let corrupt = |reason: &str| ClipboardError::FormatConversion(format!("compressed payload {}", reason));
+    match frame.split_first() {
+        Some((&METHOD_STORED, data)) if data.len() <= max_size => Ok(data.to_vec()),
+        Some((&METHOD_STORED, data)) => Err(ClipboardError::DataSizeExceeded {
+            actual: data.len(),
+            max: max_size,
+        }),
+        Some((&METHOD_ZSTD, rest)) => {
+            let original = rest
+                .get(..4)
+                .and_then(|bytes| bytes.try_into().ok())
+                .map(u32::from_le_bytes)
+                .ok_or_else(|| corrupt("is truncated"))? as usize;
+            if original > max_size {
+                return Err(ClipboardError::DataSizeExceeded {
+                    actual: original,
+                    max: max_size,
+                });
+            }
+            let data = zstd::bulk::decompress(&rest[4..], original)
+                .map_err(|e| ClipboardError::FormatConversion(format!("zstd decompression failed: {}", e)))?;
+            if data.len() != original {
+                return Err(corrupt("has the wrong size"));
+            }
+            Ok(data)
+        }
+        Some((method, _)) => Err(corrupt(&format!("uses unknown method {}", method))),
+        None => Err(corrupt("is empty")),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_frames() {
+        let config = CompressionConfig::default();
+
+        let small = b"short text";
+        let frame = compress(small, &config).unwrap();
+        assert_eq!(frame[0], METHOD_STORED);
+        assert_eq!(decompress(&frame, 100).unwrap(), small);
+
+        let large = "<tr><td>row</td></tr>".repeat(500).into_bytes();
+        let frame = compress(&large, &config).unwrap();
+        assert_eq!(frame[0], METHOD_ZSTD);
+        assert!(frame.len() < large.len() / 10);
+        assert_eq!(decompress(&frame, large.len()).unwrap(), large);
+
+        // Size limits hold before decompressing
+        assert!(matches!(
+            decompress(&frame, large.len() - 1),
+            Err(ClipboardError::DataSizeExceeded { .. })
+        ));
+        assert!(decompress(&frame[..3], large.len()).is_err());
+        assert!(decompress(&[7, 1, 2], 10).is_err());
+        assert!(decompress(&[], 10).is_err());
+
+        // Incompressible data is stored
+        let noise: Vec<u8> = (0..8192u32)
+            .map(|i| xxhash_rust::xxh3::xxh3_64(&i.to_le_bytes()) as u8)
+            .collect();
+        assert_eq!(compress(&noise, &config).unwrap()[0], METHOD_STORED);
+    }
+
+    #[test]
+    fn test_names() {
+        assert_eq!(wrapped_name("FileContents"), FILE_CONTENTS_FORMAT_NAME);
+        assert_eq!(unwrapped_name(FILE_CONTENTS_FORMAT_NAME), Some("FileContents"));
+        assert_eq!(unwrapped_name(WRAPPED_FORMAT_PREFIX), None);
+        assert!(is_compressible("HTML Format"));
+        assert!(!is_compressible("PNG"));
+    }
+}
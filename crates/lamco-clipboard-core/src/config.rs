@@ -0,0 +1,916 @@
+//! Combined clipboard configuration with validation and self-description.
+//!
+//! [`ClipboardConfig`] groups the settings integrators usually expose to
+//! operators. Every field is described once in a static table, which drives
+//! both [`ClipboardConfig::json_schema`] (for validating deployment files)
+//! and [`ClipboardConfig::example_toml`] (for templating them), so the two
+//! cannot drift apart.
+//!
+//! [`ClipboardConfig::validate`] reports every problem at once instead of
+//! stopping at the first.
+//!
+//! # Example
+//!
+//! ```rust
+//! use lamco_clipboard_core::config::ClipboardConfig;
+//!
+//! let mut config = ClipboardConfig::default();
+//! config.transfer.chunk_size = 0;
+//! config.loop_detection.max_history = 0;
+//!
+//! let errors = config.validate().unwrap_err();
+//! assert_eq!(errors.len(), 2);
+//! ```
+
+use crate::file_transfer::FileTransferPolicy;
+use crate::{
+    CoalesceConfig, FlowControlConfig, LoopDetectionConfig, PollingConfig, PollingMode, StagingConfig, TransferConfig,
+};
+use std::fmt;
+use std::fmt::Write;
+
+/// Clipboard settings exposed to operators
+#[derive(Debug, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "serde", serde(default))]
+pub struct ClipboardConfig {
+    /// Chunked transfer settings (`[transfer]`)
+    pub transfer: TransferConfig,
+
+    /// Loop detection settings (`[loop_detection]`)
+    pub loop_detection: LoopDetectionConfig,
+
+    /// Request flow control settings (`[flow_control]`)
+    pub flow_control: FlowControlConfig,
+
+    /// Change polling for sinks without change events (`[polling]`)
+    ///
+    /// Unlike [`PollingConfig::default`], polling is disabled here by
+    /// default, so deployments only poll when an operator asks for it.
+    pub polling: PollingConfig,
+
+    /// Debouncing of local clipboard change bursts (`[coalesce]`)
+    pub coalesce: CoalesceConfig,
+
+    /// Staging directory for received files (`[staging]`)
+    pub staging: StagingConfig,
+
+    /// Limits on copied and pasted files (`[file_policy]`)
+    pub file_policy: FileTransferPolicy,
+}
+
+impl Default for ClipboardConfig {
+    fn default() -> Self {
+        Self {
+            transfer: TransferConfig::default(),
+            loop_detection: LoopDetectionConfig::default(),
+            flow_control: FlowControlConfig::default(),
+            polling: PollingConfig::default().with_mode(PollingMode::Disabled),
+            coalesce: CoalesceConfig::default(),
+            staging: StagingConfig::default(),
+            file_policy: FileTransferPolicy::default(),
+        }
+    }
+}
+
+/// A single configuration problem
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ConfigError {
+    /// Dotted path of the offending field, e.g. `transfer.chunk_size`
+    pub field: &'static str,
+    /// What is wrong with it
+    pub message: String,
+}
+
+impl fmt::Display for ConfigError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}: {}", self.field, self.message)
+    }
+}
+
+impl std::error::Error for ConfigError {}
+
+/// JSON Schema type of a field
+#[derive(Debug, Clone, Copy)]
+enum FieldType {
+    Integer,
+    Boolean,
+    /// Integer that may be omitted
+    OptionalInteger,
+    /// String that may be omitted
+    OptionalString,
+    /// String restricted to the listed values
+    Choice(&'static [&'static str]),
+    /// List of strings
+    StringList,
+}
+
+/// Description of one configuration field
+struct FieldSpec {
+    section: &'static str,
+    name: &'static str,
+    ty: FieldType,
+    minimum: Option<u64>,
+    description: &'static str,
+    /// Default value rendered as TOML, `None` if unset by default
+    default: fn(&ClipboardConfig) -> Option<String>,
+}
+
+const FIELDS: &[FieldSpec] = &[
+    FieldSpec {
+        section: "transfer",
+        name: "chunk_size",
+        ty: FieldType::Integer,
+        minimum: Some(1),
+        description: "Chunk size in bytes for large transfers",
+        default: |c| Some(c.transfer.chunk_size.to_string()),
+    },
+    FieldSpec {
+        section: "transfer",
+        name: "max_size",
+        ty: FieldType::Integer,
+        minimum: Some(1),
+        description: "Maximum clipboard data size in bytes",
+        default: |c| Some(c.transfer.max_size.to_string()),
+    },
+    FieldSpec {
+        section: "transfer",
+        name: "timeout_ms",
+        ty: FieldType::Integer,
+        minimum: Some(1),
+        description: "Transfer timeout in milliseconds",
+        default: |c| Some(c.transfer.timeout_ms.to_string()),
+    },
+    FieldSpec {
+        section: "transfer",
+        name: "verify_integrity",
+        ty: FieldType::Boolean,
+        minimum: None,
+        description: "Verify transferred data with SHA-256",
+        default: |c| Some(c.transfer.verify_integrity.to_string()),
+    },
+    FieldSpec {
+        section: "loop_detection",
+        name: "window_ms",
+        ty: FieldType::Integer,
+        minimum: Some(1),
+        description: "Time window for detecting sync loops in milliseconds",
+        default: |c| Some(c.loop_detection.window_ms.to_string()),
+    },
+    FieldSpec {
+        section: "loop_detection",
+        name: "max_history",
+        ty: FieldType::Integer,
+        minimum: Some(1),
+        description: "Number of recent operations remembered for loop detection",
+        default: |c| Some(c.loop_detection.max_history.to_string()),
+    },
+    FieldSpec {
+        section: "loop_detection",
+        name: "enable_content_hashing",
+        ty: FieldType::Boolean,
+        minimum: None,
+        description: "Detect loops by content hash in addition to formats",
+        default: |c| Some(c.loop_detection.enable_content_hashing.to_string()),
+    },
+    FieldSpec {
+        section: "loop_detection",
+        name: "hash_algorithm",
+        ty: FieldType::Choice(&["xxh3", "sha256"]),
+        minimum: None,
+        description: "Hash function for loop detection: fast xxh3 or collision-resistant sha256",
+        default: |c| Some(format!("\"{}\"", c.loop_detection.hash_algorithm)),
+    },
+    FieldSpec {
+        section: "loop_detection",
+        name: "rate_limit_ms",
+        ty: FieldType::OptionalInteger,
+        minimum: Some(1),
+        description: "Minimum time between sync operations in milliseconds (unset: no limit)",
+        default: |c| c.loop_detection.rate_limit_ms.map(|ms| ms.to_string()),
+    },
+    FieldSpec {
+        section: "loop_detection",
+        name: "perceptual_image_hashing",
+        ty: FieldType::Boolean,
+        minimum: None,
+        description: "Also detect loops of re-encoded images by perceptual hash (needs the image feature)",
+        default: |c| Some(c.loop_detection.perceptual_image_hashing.to_string()),
+    },
+    FieldSpec {
+        section: "loop_detection",
+        name: "perceptual_hash_distance",
+        ty: FieldType::Integer,
+        minimum: Some(0),
+        description: "Differing bits of 64 up to which two image hashes match",
+        default: |c| Some(c.loop_detection.perceptual_hash_distance.to_string()),
+    },
+    FieldSpec {
+        section: "loop_detection",
+        name: "oscillation_hops",
+        ty: FieldType::Integer,
+        minimum: Some(0),
+        description: "Alternating format lists between the sides that pause sync (0: disabled)",
+        default: |c| Some(c.loop_detection.oscillation_hops.to_string()),
+    },
+    FieldSpec {
+        section: "loop_detection",
+        name: "oscillation_window_ms",
+        ty: FieldType::Integer,
+        minimum: Some(1),
+        description: "Time span the alternating format lists must fall within in milliseconds",
+        default: |c| Some(c.loop_detection.oscillation_window_ms.to_string()),
+    },
+    FieldSpec {
+        section: "loop_detection",
+        name: "cooldown_ms",
+        ty: FieldType::Integer,
+        minimum: Some(1),
+        description: "How long sync stays paused after an oscillation in milliseconds",
+        default: |c| Some(c.loop_detection.cooldown_ms.to_string()),
+    },
+    FieldSpec {
+        section: "loop_detection",
+        name: "text_window_ms",
+        ty: FieldType::OptionalInteger,
+        minimum: Some(1),
+        description: "Detection window for text in milliseconds (unset: window_ms)",
+        default: |c| c.loop_detection.text_window_ms.map(|value| value.to_string()),
+    },
+    FieldSpec {
+        section: "loop_detection",
+        name: "image_window_ms",
+        ty: FieldType::OptionalInteger,
+        minimum: Some(1),
+        description: "Detection window for images in milliseconds (unset: window_ms)",
+        default: |c| c.loop_detection.image_window_ms.map(|value| value.to_string()),
+    },
+    FieldSpec {
+        section: "loop_detection",
+        name: "files_window_ms",
+        ty: FieldType::OptionalInteger,
+        minimum: Some(1),
+        description: "Detection window for file lists in milliseconds (unset: window_ms)",
+        default: |c| c.loop_detection.files_window_ms.map(|value| value.to_string()),
+    },
+    FieldSpec {
+        section: "loop_detection",
+        name: "text_max_history",
+        ty: FieldType::OptionalInteger,
+        minimum: Some(1),
+        description: "Text operations to track (unset: max_history)",
+        default: |c| c.loop_detection.text_max_history.map(|value| value.to_string()),
+    },
+    FieldSpec {
+        section: "loop_detection",
+        name: "image_max_history",
+        ty: FieldType::OptionalInteger,
+        minimum: Some(1),
+        description: "Image operations to track (unset: max_history)",
+        default: |c| c.loop_detection.image_max_history.map(|value| value.to_string()),
+    },
+    FieldSpec {
+        section: "loop_detection",
+        name: "files_max_history",
+        ty: FieldType::OptionalInteger,
+        minimum: Some(1),
+        description: "File list operations to track (unset: max_history)",
+        default: |c| c.loop_detection.files_max_history.map(|value| value.to_string()),
+    },
+    FieldSpec {
+        section: "loop_detection",
+        name: "long_horizon_ms",
+        ty: FieldType::Integer,
+        minimum: Some(0),
+        description: "How long repeated content is suppressed by the long-horizon filter in milliseconds (0: disabled)",
+        default: |c| Some(c.loop_detection.long_horizon_ms.to_string()),
+    },
+    FieldSpec {
+        section: "loop_detection",
+        name: "long_horizon_slots",
+        ty: FieldType::Integer,
+        minimum: Some(1),
+        description: "Counters in the long-horizon filter; memory use in bytes",
+        default: |c| Some(c.loop_detection.long_horizon_slots.to_string()),
+    },
+    FieldSpec {
+        section: "loop_detection",
+        name: "adaptive_window",
+        ty: FieldType::Boolean,
+        minimum: None,
+        description: "Derive window_ms from measured sync latencies",
+        default: |c| Some(c.loop_detection.adaptive_window.to_string()),
+    },
+    FieldSpec {
+        section: "loop_detection",
+        name: "adaptive_window_min_ms",
+        ty: FieldType::Integer,
+        minimum: Some(1),
+        description: "Shortest adaptive window in milliseconds",
+        default: |c| Some(c.loop_detection.adaptive_window_min_ms.to_string()),
+    },
+    FieldSpec {
+        section: "loop_detection",
+        name: "adaptive_window_max_ms",
+        ty: FieldType::Integer,
+        minimum: Some(1),
+        description: "Longest adaptive window in milliseconds",
+        default: |c| Some(c.loop_detection.adaptive_window_max_ms.to_string()),
+    },
+    FieldSpec {
+        section: "flow_control",
+        name: "max_outstanding_requests",
+        ty: FieldType::Integer,
+        minimum: Some(1),
+        description: "Format data and file contents requests awaiting a response from the peer at once",
+        default: |c| Some(c.flow_control.max_outstanding_requests.to_string()),
+    },
+    FieldSpec {
+        section: "flow_control",
+        name: "max_concurrent_servicing",
+        ty: FieldType::Integer,
+        minimum: Some(1),
+        description: "Peer requests serviced at once",
+        default: |c| Some(c.flow_control.max_concurrent_servicing.to_string()),
+    },
+    FieldSpec {
+        section: "flow_control",
+        name: "max_queued_requests",
+        ty: FieldType::Integer,
+        minimum: Some(1),
+        description: "Requests waiting for a free slot, per direction, before new ones are refused",
+        default: |c| Some(c.flow_control.max_queued_requests.to_string()),
+    },
+    FieldSpec {
+        section: "flow_control",
+        name: "max_bytes_per_second",
+        ty: FieldType::OptionalInteger,
+        minimum: Some(1),
+        description: "Bytes per second of file contents sent to the peer (unset: no limit)",
+        default: |c| c.flow_control.max_bytes_per_second.map(|rate| rate.to_string()),
+    },
+    FieldSpec {
+        section: "flow_control",
+        name: "burst_bytes",
+        ty: FieldType::Integer,
+        minimum: Some(1),
+        description: "Bytes of file contents that may be sent at once after being idle",
+        default: |c| Some(c.flow_control.burst_bytes.to_string()),
+    },
+    FieldSpec {
+        section: "flow_control",
+        name: "interactive_max_bytes",
+        ty: FieldType::Integer,
+        minimum: Some(0),
+        description: "Largest transfer scheduled ahead of bulk transfers",
+        default: |c| Some(c.flow_control.interactive_max_bytes.to_string()),
+    },
+    FieldSpec {
+        section: "flow_control",
+        name: "reserved_interactive_slots",
+        ty: FieldType::Integer,
+        minimum: Some(0),
+        description: "Request slots bulk transfers leave free for interactive ones (0: no preemption)",
+        default: |c| Some(c.flow_control.reserved_interactive_slots.to_string()),
+    },
+    FieldSpec {
+        section: "polling",
+        name: "mode",
+        ty: FieldType::Choice(&["disabled", "fallback", "always"]),
+        minimum: None,
+        description: "When to poll for clipboard changes: never, only if the backend has no change events, or always",
+        default: |c| Some(format!("\"{}\"", c.polling.mode)),
+    },
+    FieldSpec {
+        section: "polling",
+        name: "min_interval_ms",
+        ty: FieldType::Integer,
+        minimum: Some(1),
+        description: "Poll interval right after a change in milliseconds",
+        default: |c| Some(c.polling.min_interval_ms.to_string()),
+    },
+    FieldSpec {
+        section: "polling",
+        name: "max_interval_ms",
+        ty: FieldType::Integer,
+        minimum: Some(1),
+        description: "Longest poll interval while the clipboard is idle in milliseconds",
+        default: |c| Some(c.polling.max_interval_ms.to_string()),
+    },
+    FieldSpec {
+        section: "coalesce",
+        name: "quiet_period_ms",
+        ty: FieldType::Integer,
+        minimum: Some(0),
+        description: "Time the clipboard must stay unchanged before a change is announced in milliseconds",
+        default: |c| Some(c.coalesce.quiet_period_ms.to_string()),
+    },
+    FieldSpec {
+        section: "coalesce",
+        name: "max_delay_ms",
+        ty: FieldType::Integer,
+        minimum: Some(0),
+        description: "Longest time a change is held back during a burst of changes in milliseconds",
+        default: |c| Some(c.coalesce.max_delay_ms.to_string()),
+    },
+    FieldSpec {
+        section: "coalesce",
+        name: "skip_unchanged",
+        ty: FieldType::Boolean,
+        minimum: None,
+        description: "Skip announcing a change whose formats and content hash match the last announcement",
+        default: |c| Some(c.coalesce.skip_unchanged.to_string()),
+    },
+    FieldSpec {
+        section: "staging",
+        name: "directory",
+        ty: FieldType::OptionalString,
+        minimum: None,
+        description: "Absolute directory for staging received files (unset: under the system temporary directory)",
+        default: |c| c.staging.directory.as_ref().map(|d| format!("\"{}\"", d.display())),
+    },
+    FieldSpec {
+        section: "staging",
+        name: "quota_bytes",
+        ty: FieldType::Integer,
+        minimum: Some(1),
+        description: "Bytes of received files a session may stage",
+        default: |c| Some(c.staging.quota_bytes.to_string()),
+    },
+    FieldSpec {
+        section: "staging",
+        name: "fsync",
+        ty: FieldType::Choice(&["never", "files", "directories"]),
+        minimum: None,
+        description: "Flush staged files to disk: never, each file, or each file and its directory",
+        default: |c| Some(format!("\"{}\"", c.staging.fsync)),
+    },
+    FieldSpec {
+        section: "staging",
+        name: "sparse_threshold_bytes",
+        ty: FieldType::Integer,
+        minimum: Some(0),
+        description: "Shortest run of zeros left as a hole in staged files (0: write every byte)",
+        default: |c| Some(c.staging.sparse_threshold_bytes.to_string()),
+    },
+    FieldSpec {
+        section: "file_policy",
+        name: "max_file_size",
+        ty: FieldType::OptionalInteger,
+        minimum: Some(1),
+        description: "Largest single file copied or pasted in bytes (unset: no limit)",
+        default: |c| c.file_policy.max_file_size.map(|v| v.to_string()),
+    },
+    FieldSpec {
+        section: "file_policy",
+        name: "max_total_bytes",
+        ty: FieldType::OptionalInteger,
+        minimum: Some(1),
+        description: "Largest total of the files of one copy or paste in bytes (unset: no limit)",
+        default: |c| c.file_policy.max_total_bytes.map(|v| v.to_string()),
+    },
+    FieldSpec {
+        section: "file_policy",
+        name: "max_file_count",
+        ty: FieldType::OptionalInteger,
+        minimum: Some(1),
+        description: "Most files in one copy or paste (unset: no limit)",
+        default: |c| c.file_policy.max_file_count.map(|v| v.to_string()),
+    },
+    FieldSpec {
+        section: "file_policy",
+        name: "denied_extensions",
+        ty: FieldType::StringList,
+        minimum: None,
+        description: "File extensions that may not be copied or pasted, such as exe or msi",
+        default: |c| {
+            let extensions: Vec<String> = c
+                .file_policy
+                .denied_extensions
+                .iter()
+                .map(|e| format!("\"{}\"", e))
+                .collect();
+            Some(format!("[{}]", extensions.join(", ")))
+        },
+    },
+];
+
+impl ClipboardConfig {
+    /// Check all settings, returning every problem found
+    pub fn validate(&self) -> Result<(), Vec<ConfigError>> {
+        let mut errors = Vec::new();
+        let mut check = |ok: bool, field: &'static str, message: &str| {
+            if !ok {
+                errors.push(ConfigError {
+                    field,
+                    message: message.to_string(),
+                });
+            }
+        };
+
+        let transfer = &self.transfer;
+        check(transfer.chunk_size > 0, "transfer.chunk_size", "must be greater than 0");
+        check(transfer.max_size > 0, "transfer.max_size", "must be greater than 0");
+        check(
+            transfer.chunk_size <= transfer.max_size,
+            "transfer.chunk_size",
+            "must not exceed transfer.max_size",
+        );
+        check(transfer.timeout_ms > 0, "transfer.timeout_ms", "must be greater than 0");
+
+        let loop_detection = &self.loop_detection;
+        check(
+            loop_detection.window_ms > 0,
+            "loop_detection.window_ms",
+            "must be greater than 0",
+        );
+        check(
+            loop_detection.max_history > 0,
+            "loop_detection.max_history",
+            "must be greater than 0",
+        );
+        check(
+            loop_detection.rate_limit_ms != Some(0),
+            "loop_detection.rate_limit_ms",
+            "must be greater than 0 when set",
+        );
+        check(
+            loop_detection.perceptual_hash_distance <= 64,
+            "loop_detection.perceptual_hash_distance",
+            "must not exceed 64",
+        );
+        check(
+            loop_detection.oscillation_hops == 0 || loop_detection.oscillation_hops >= 3,
+            "loop_detection.oscillation_hops",
+            "must be 0 (disabled) or at least 3",
+        );
+        check(
+            loop_detection.oscillation_window_ms > 0,
+            "loop_detection.oscillation_window_ms",
+            "must be greater than 0",
+        );
+        check(
+            loop_detection.cooldown_ms > 0,
+            "loop_detection.cooldown_ms",
+            "must be greater than 0",
+        );
+        check(
+            loop_detection.text_window_ms != Some(0),
+            "loop_detection.text_window_ms",
+            "must be greater than 0 when set",
+        );
+        check(
+            loop_detection.image_window_ms != Some(0),
+            "loop_detection.image_window_ms",
+            "must be greater than 0 when set",
+        );
+        check(
+            loop_detection.files_window_ms != Some(0),
+            "loop_detection.files_window_ms",
+            "must be greater than 0 when set",
+        );
+        check(
+            loop_detection.text_max_history != Some(0),
+            "loop_detection.text_max_history",
+            "must be greater than 0 when set",
+        );
+        check(
+            loop_detection.image_max_history != Some(0),
+            "loop_detection.image_max_history",
+            "must be greater than 0 when set",
+        );
+        check(
+            loop_detection.files_max_history != Some(0),
+            "loop_detection.files_max_history",
+            "must be greater than 0 when set",
+        );
+        check(
+            loop_detection.long_horizon_ms == 0 || loop_detection.long_horizon_ms > loop_detection.window_ms,
+            "loop_detection.long_horizon_ms",
+            "must be 0 (disabled) or longer than loop_detection.window_ms",
+        );
+        check(
+            loop_detection.long_horizon_slots > 0,
+            "loop_detection.long_horizon_slots",
+            "must be greater than 0",
+        );
+        check(
+            loop_detection.adaptive_window_min_ms > 0,
+            "loop_detection.adaptive_window_min_ms",
+            "must be greater than 0",
+        );
+        check(
+            loop_detection.adaptive_window_min_ms <= loop_detection.adaptive_window_max_ms,
+            "loop_detection.adaptive_window_max_ms",
+            "must not be less than loop_detection.adaptive_window_min_ms",
+        );
+
+        let flow_control = &self.flow_control;
+        check(
+            flow_control.max_outstanding_requests > 0,
+            "flow_control.max_outstanding_requests",
+            "must be greater than 0",
+        );
+        check(
+            flow_control.max_concurrent_servicing > 0,
+            "flow_control.max_concurrent_servicing",
+            "must be greater than 0",
+        );
+        check(
+            flow_control.max_queued_requests > 0,
+            "flow_control.max_queued_requests",
+            "must be greater than 0",
+        );
+        check(
+            flow_control.max_bytes_per_second != Some(0),
+            "flow_control.max_bytes_per_second",
+            "must be greater than 0",
+        );
+        check(
+            flow_control.burst_bytes > 0,
+            "flow_control.burst_bytes",
+            "must be greater than 0",
+        );
+
+        let polling = &self.polling;
+        check(
+            polling.min_interval_ms > 0,
+            "polling.min_interval_ms",
+            "must be greater than 0",
+        );
+        check(
+            polling.max_interval_ms >= polling.min_interval_ms,
+            "polling.max_interval_ms",
+            "must not be less than polling.min_interval_ms",
+        );
+
+        let coalesce = &self.coalesce;
+        check(
+            coalesce.max_delay_ms >= coalesce.quiet_period_ms,
+            "coalesce.max_delay_ms",
+            "must not be less than coalesce.quiet_period_ms",
+        );
+
+        let staging = &self.staging;
+        check(
+            staging
+                .directory
+                .as_ref()
+                .map_or(true, |directory| directory.is_absolute()),
+            "staging.directory",
+            "must be an absolute path",
+        );
+        check(staging.quota_bytes > 0, "staging.quota_bytes", "must be greater than 0");
+
+        let file_policy = &self.file_policy;
+        check(
+            file_policy.max_file_size != Some(0),
+            "file_policy.max_file_size",
+            "must be greater than 0 when set",
+        );
+        check(
+            file_policy.max_total_bytes != Some(0),
+            "file_policy.max_total_bytes",
+            "must be greater than 0 when set",
+        );
+        check(
+            file_policy.max_file_count != Some(0),
+            "file_policy.max_file_count",
+            "must be greater than 0 when set",
+        );
+        check(
+            file_policy
+                .denied_extensions
+                .iter()
+                .all(|extension| !extension.trim_start_matches('.').is_empty()),
+            "file_policy.denied_extensions",
+            "must not contain empty extensions",
+        );
+
+        if errors.is_empty() {
+            Ok(())
+        } else {
+            Err(errors)
+        }
+    }
+
+    /// JSON Schema (draft 2020-12) describing the TOML/JSON layout of this config
+    pub fn json_schema() -> String {
+        let defaults = Self::default();
+        let mut schema = String::from(
+            "{\n  \"$schema\": \"https://json-schema.org/draft/2020-12/schema\",\n  \
+             \"title\": \"ClipboardConfig\",\n  \"type\": \"object\",\n  \"additionalProperties\": false,\n  \
+             \"properties\": {",
+        );
+
+        for (section_index, section) in Self::sections().enumerate() {
+            if section_index > 0 {
+                schema.push(',');
+            }
+            let _ = write!(
+                schema,
+                "\n    \"{}\": {{\n      \"type\": \"object\",\n      \"additionalProperties\": false,\n      \
+                 \"properties\": {{",
+                section
+            );
+            for (field_index, field) in FIELDS.iter().filter(|f| f.section == section).enumerate() {
+                if field_index > 0 {
+                    schema.push(',');
+                }
+                let ty = match field.ty {
+                    FieldType::Integer | FieldType::OptionalInteger => "integer",
+                    FieldType::Boolean => "boolean",
+                    FieldType::Choice(_) | FieldType::OptionalString => "string",
+                    FieldType::StringList => "array",
+                };
+                let _ = write!(
+                    schema,
+                    "\n        \"{}\": {{ \"type\": \"{}\", \"description\": \"{}\"",
+                    field.name, ty, field.description
+                );
+                if let FieldType::StringList = field.ty {
+                    schema.push_str(", \"items\": { \"type\": \"string\" }");
+                }
+                if let FieldType::Choice(values) = field.ty {
+                    let values: Vec<String> = values.iter().map(|v| format!("\"{}\"", v)).collect();
+                    let _ = write!(schema, ", \"enum\": [{}]", values.join(", "));
+                }
+                if let Some(minimum) = field.minimum {
+                    let _ = write!(schema, ", \"minimum\": {}", minimum);
+                }
+                if let Some(default) = (field.default)(&defaults) {
+                    let _ = write!(schema, ", \"default\": {}", default);
+                }
+                schema.push_str(" }");
+            }
+            schema.push_str("\n      }\n    }");
+        }
+
+        schema.push_str("\n  }\n}\n");
+        schema
+    }
+
+    /// Example TOML file with every setting at its default, documented inline
+    pub fn example_toml() -> String {
+        let defaults = Self::default();
+        let mut toml = String::new();
+
+        for (section_index, section) in Self::sections().enumerate() {
+            if section_index > 0 {
+                toml.push('\n');
+            }
+            let _ = writeln!(toml, "[{}]", section);
+            for field in FIELDS.iter().filter(|f| f.section == section) {
+                let _ = writeln!(toml, "# {}", field.description);
+                match (field.default)(&defaults) {
+                    Some(default) => {
+                        let _ = writeln!(toml, "{} = {}", field.name, default);
+                    }
+                    None => {
+                        let _ = writeln!(toml, "# {} =", field.name);
+                    }
+                }
+            }
+        }
+
+        toml
+    }
+
+    /// Section names in declaration order
+    fn sections() -> impl Iterator<Item = &'static str> {
+        let mut seen: Vec<&'static str> = Vec::new();
+        FIELDS.iter().filter_map(move |field| {
+            if seen.contains(&field.section) {
+                None
+            } else {
+                seen.push(field.section);
+                Some(field.section)
+            }
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_default_is_valid() {
+        assert!(ClipboardConfig::default().validate().is_ok());
+    }
+
+    #[test]
+    fn test_validate_reports_all_errors() {
+        let mut config = ClipboardConfig::default();
+        config.transfer.chunk_size = config.transfer.max_size + 1;
+        config.transfer.timeout_ms = 0;
+        config.loop_detection.rate_limit_ms = Some(0);
+        config.flow_control.max_queued_requests = 0;
+        config.file_policy.denied_extensions = vec!["exe".to_string(), ".".to_string()];
+
+        let errors = config.validate().unwrap_err();
+        let fields: Vec<_> = errors.iter().map(|e| e.field).collect();
+        assert_eq!(
+            fields,
+            vec![
+                "transfer.chunk_size",
+                "transfer.timeout_ms",
+                "loop_detection.rate_limit_ms",
+                "flow_control.max_queued_requests",
+                "file_policy.denied_extensions"
+            ]
+        );
+        assert_eq!(
+            errors[0].to_string(),
+            "transfer.chunk_size: must not exceed transfer.max_size"
+        );
+    }
+
+    #[test]
+    fn test_example_toml_lists_every_field() {
+        let toml = ClipboardConfig::example_toml();
+        assert!(toml.starts_with("[transfer]\n"));
+        assert!(toml.contains("\n[loop_detection]\n"));
+        assert!(toml.contains("\n[flow_control]\n"));
+        assert!(toml.contains("\n[polling]\n"));
+        assert!(toml.contains("\n[coalesce]\n"));
+        assert!(toml.contains("skip_unchanged = true\n"));
+        assert!(toml.contains("\n[staging]\n"));
+        assert!(toml.contains("# directory =\n"));
+        assert!(toml.contains("fsync = \"never\"\n"));
+        assert!(toml.contains("\n[file_policy]\n# "));
+        assert!(toml.contains("# max_file_count =\n"));
+        assert!(toml.contains("denied_extensions = []\n"));
+        assert!(toml.contains("mode = \"disabled\"\n"));
+        assert!(toml.contains("max_queued_requests = 1024\n"));
+        assert!(toml.contains("chunk_size = 65536\n"));
+        assert!(toml.contains("# rate_limit_ms =\n"));
+        for field in FIELDS {
+            assert!(toml.contains(field.name), "missing {}", field.name);
+        }
+    }
+
+    #[test]
+    fn test_json_schema_shape() {
+        let schema = ClipboardConfig::json_schema();
+        assert!(schema.contains("\"transfer\": {"));
+        assert!(schema.contains("\"verify_integrity\": { \"type\": \"boolean\""));
+        assert!(schema.contains("\"minimum\": 1, \"default\": 65536"));
+        assert!(schema.contains("\"enum\": [\"disabled\", \"fallback\", \"always\"], \"default\": \"disabled\""));
+        assert!(schema.contains("\"items\": { \"type\": \"string\" }, \"default\": []"));
+        assert_eq!(schema.matches('{').count(), schema.matches('}').count());
+    }
+
+    #[test]
+    fn test_polling_section() {
+        let mut config = ClipboardConfig::default();
+        assert_eq!(config.polling.mode, PollingMode::Disabled);
+
+        config.polling.min_interval_ms = 500;
+        config.polling.max_interval_ms = 100;
+        let errors = config.validate().unwrap_err();
+        assert_eq!(errors[0].field, "polling.max_interval_ms");
+
+        // Every mode name in the schema parses back to a mode
+        let FieldType::Choice(values) = FIELDS.iter().find(|f| f.name == "mode").unwrap().ty else {
+            panic!("mode is not a choice");
+        };
+        for value in values {
+            assert_eq!(value.parse::<PollingMode>().unwrap().as_str(), *value);
+        }
+        assert!("sometimes".parse::<PollingMode>().is_err());
+    }
+
+    #[test]
+    fn test_coalesce_section() {
+        let mut config = ClipboardConfig::default();
+        config.coalesce.quiet_period_ms = 0;
+        config.coalesce.max_delay_ms = 0;
+        assert!(config.validate().is_ok());
+
+        config.coalesce.quiet_period_ms = 200;
+        config.coalesce.max_delay_ms = 100;
+        let errors = config.validate().unwrap_err();
+        assert_eq!(errors.len(), 1);
+        assert_eq!(errors[0].field, "coalesce.max_delay_ms");
+    }
+
+    #[cfg(feature = "serde")]
+    #[test]
+    fn test_serde_partial_document() {
+        let json = r#"{"transfer": {"chunk_size": 1024}, "polling": {"mode": "fallback"}}"#;
+        let config: ClipboardConfig = serde_json::from_str(json).unwrap();
+        assert_eq!(config.transfer.chunk_size, 1024);
+        assert_eq!(config.transfer.max_size, TransferConfig::default().max_size);
+        assert_eq!(config.polling.mode, PollingMode::Fallback);
+        assert_eq!(config.loop_detection.window_ms, 500);
+
+        // Missing sections keep this type's defaults, not the section's
+        let config: ClipboardConfig = serde_json::from_str("{}").unwrap();
+        assert_eq!(config.polling.mode, PollingMode::Disabled);
+
+        let json = serde_json::to_string(&config).unwrap();
+        let round_trip: ClipboardConfig = serde_json::from_str(&json).unwrap();
+        assert_eq!(serde_json::to_string(&round_trip).unwrap(), json);
+    }
+}
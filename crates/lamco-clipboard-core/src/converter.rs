@@ -0,0 +1,480 @@
+//! Pluggable conversions between clipboard formats.
+//!
+//! Every conversion [`FormatConverter`] knows is also reachable by name
+//! through [`FormatConverter::convert`], and integrators can add their own
+//! with [`FormatConverter::register_converter`] — for example a proprietary
+//! CAD snippet format — without patching this crate.
+//!
+//! Formats are identified by strings:
+//!
+//! - Linux side: MIME types (`text/plain`, `image/png`, ...)
+//! - Windows registered formats: their registered name (`HTML Format`, `Csv`, ...)
+//! - Windows standard formats: the name of their constant (`CF_UNICODETEXT`,
+//!   `CF_DIB`, ...), see [`standard_format_name`]
+//!
+//! Registered converters take precedence over built-in ones for the same pair.
+//! With the `image` feature, results of built-in image conversions are
+//! cached; see `FormatConverter::with_conversion_cache`.
+//!
+//! # Example
+//!
+//! ```
+//! use lamco_clipboard_core::{ClipboardResult, FormatConverter};
+//!
+//! let mut converter = FormatConverter::new();
+//! converter.register_converter(
+//!     "application/x-acme-cad",
+//!     "AcmeCAD Snippet",
+//!     |_: &FormatConverter, data: &[u8]| -> ClipboardResult<Vec<u8>> {
+//!         let mut snippet = b"ACAD".to_vec();
+//!         snippet.extend_from_slice(data);
+//!         Ok(snippet)
+//!     },
+//! );
+//!
+//! let snippet = converter.convert("application/x-acme-cad", "AcmeCAD Snippet", b"line 0 0 1 1").unwrap();
+//! assert!(snippet.starts_with(b"ACAD"));
+//!
+//! // Built-in conversions are available the same way
+//! let unicode = converter.convert("text/plain", "CF_UNICODETEXT", b"hi").unwrap();
+//! assert_eq!(unicode, b"h\0i\0\0\0");
+//! ```
+
+use crate::formats::{
+    FormatConverter, CF_DIB, CF_DIBV5, CF_DSPBITMAP, CF_ENHMETAFILE, CF_HDROP, CF_LOCALE, CF_METAFILEPICT, CF_OEMTEXT,
+    CF_RIFF, CF_TEXT, CF_TIFF, CF_UNICODETEXT, CF_WAVE,
+};
+use crate::{ClipboardError, ClipboardResult};
+#[cfg(feature = "image")]
+use image::ImageFormat;
+use std::fmt;
+use std::sync::Arc;
+
+/// A conversion from one clipboard format to another
+///
+/// Implemented for closures taking the [`FormatConverter`] (for its size
+/// limit and built-in conversions) and the source data.
+pub trait Converter: Send + Sync {
+    /// Convert `data` to the target format
+    fn convert(&self, converter: &FormatConverter, data: &[u8]) -> ClipboardResult<Vec<u8>>;
+}
+
+impl<F> Converter for F
+where
+    F: Fn(&FormatConverter, &[u8]) -> ClipboardResult<Vec<u8>> + Send + Sync,
+{
+    fn convert(&self, converter: &FormatConverter, data: &[u8]) -> ClipboardResult<Vec<u8>> {
+        self(converter, data)
+    }
+}
+
+/// Name used for a standard Windows format in conversion keys
+///
+/// # Example
+///
+/// ```
+/// use lamco_clipboard_core::converter::standard_format_name;
+/// use lamco_clipboard_core::formats::CF_DIB;
+///
+/// assert_eq!(standard_format_name(CF_DIB), Some("CF_DIB"));
+/// assert_eq!(standard_format_name(0xC123), None);
+/// ```
+pub fn standard_format_name(id: u32) -> Option<&'static str> {
+    Some(match id {
+        CF_TEXT => "CF_TEXT",
+        CF_METAFILEPICT => "CF_METAFILEPICT",
+        CF_DSPBITMAP => "CF_DSPBITMAP",
+        CF_TIFF => "CF_TIFF",
+        CF_OEMTEXT => "CF_OEMTEXT",
+        CF_DIB => "CF_DIB",
+        CF_RIFF => "CF_RIFF",
+        CF_WAVE => "CF_WAVE",
+        CF_UNICODETEXT => "CF_UNICODETEXT",
+        CF_ENHMETAFILE => "CF_ENHMETAFILE",
+        CF_HDROP => "CF_HDROP",
+        CF_LOCALE => "CF_LOCALE",
+        CF_DIBV5 => "CF_DIBV5",
+        _ => return None,
+    })
+}
+
+type BuiltinFn = fn(&FormatConverter, &[u8]) -> ClipboardResult<Vec<u8>>;
+
+/// Conversions available on every [`FormatConverter`]
+const BUILTIN_CONVERTERS: &[(&str, &str, BuiltinFn)] = &[
+    ("text/plain", "CF_UNICODETEXT", |c, d| c.text_to_unicode(utf8(d)?)),
+    ("CF_UNICODETEXT", "text/plain", |c, d| {
+        Ok(c.unicode_to_text(d)?.into_bytes())
+    }),
+    ("text/plain", "CF_TEXT", |c, d| c.text_to_ansi(utf8(d)?)),
+    ("CF_TEXT", "text/plain", |c, d| Ok(c.ansi_to_text(d)?.into_bytes())),
+    ("text/plain", "CF_OEMTEXT", |c, d| c.text_to_oem(utf8(d)?)),
+    ("CF_OEMTEXT", "text/plain", |c, d| Ok(c.oem_to_text(d)?.into_bytes())),
+    ("text/html", "HTML Format", |c, d| c.html_to_cf_html(utf8(d)?)),
+    ("HTML Format", "text/html", |c, d| {
+        Ok(c.cf_html_to_html(d)?.into_bytes())
+    }),
+    ("text/rtf", "Rich Text Format", |c, d| c.validate_rtf(d)),
+    ("Rich Text Format", "text/rtf", |c, d| c.validate_rtf(d)),
+    ("text/x-moz-url", "UniformResourceLocatorW", |c, d| {
+        c.moz_url_to_url_w(d)
+    }),
+    ("UniformResourceLocatorW", "text/x-moz-url", |c, d| {
+        c.url_w_to_moz_url(d)
+    }),
+    ("text/x-moz-url", "UniformResourceLocator", |c, d| c.moz_url_to_url(d)),
+    ("UniformResourceLocator", "text/x-moz-url", |c, d| c.url_to_moz_url(d)),
+    ("text/uri-list", "CF_HDROP", |c, d| c.uri_list_to_hdrop(utf8(d)?)),
+    ("CF_HDROP", "text/uri-list", |c, d| {
+        Ok(c.hdrop_to_uri_list(d)?.into_bytes())
+    }),
+    ("x-special/gnome-copied-files", "CF_HDROP", |c, d| {
+        let (_, uri_list) = c.parse_gnome_copied_files(d)?;
+        c.uri_list_to_hdrop(&uri_list)
+    }),
+    ("x-special/gnome-copied-files", "Preferred DropEffect", |c, d| {
+        c.gnome_copied_files_to_drop_effect(d)
+    }),
+    ("application/x-kde-cutselection", "Preferred DropEffect", |c, d| {
+        c.kde_cut_selection_to_drop_effect(d)
+    }),
+    ("Preferred DropEffect", "application/x-kde-cutselection", |c, d| {
+        c.drop_effect_to_kde_cut_selection(d)
+    }),
+    ("text/csv", "Csv", |c, d| c.csv_to_rdp_csv(utf8(d)?)),
+    ("Csv", "text/csv", |c, d| Ok(c.rdp_csv_to_csv(d)?.into_bytes())),
+    ("text/csv", "XML Spreadsheet", |c, d| c.csv_to_xml_spreadsheet(utf8(d)?)),
+    ("XML Spreadsheet", "text/csv", |c, d| {
+        Ok(c.xml_spreadsheet_to_csv(d)?.into_bytes())
+    }),
+    ("audio/wav", "CF_WAVE", |_, d| crate::audio::validate_wav(d)),
+    ("CF_WAVE", "audio/wav", |_, d| crate::audio::validate_wav(d)),
+    ("CF_RIFF", "audio/wav", |_, d| crate::audio::validate_wav(d)),
+    #[cfg(feature = "flac")]
+    ("audio/flac", "CF_WAVE", |c, d| crate::audio::flac_to_wav(d, c.max_size)),
+    #[cfg(feature = "flac")]
+    ("CF_WAVE", "audio/flac", |_, d| crate::audio::wav_to_flac(d)),
+    #[cfg(feature = "markdown")]
+    ("text/markdown", "HTML Format", |c, d| c.markdown_to_cf_html(utf8(d)?)),
+    #[cfg(feature = "markdown")]
+    ("HTML Format", "text/markdown", |c, d| {
+        Ok(c.cf_html_to_markdown(d)?.into_bytes())
+    }),
+    #[cfg(feature = "image")]
+    ("image/png", "CF_DIB", |c, d| {
+        crate::image::png_to_dib_with_limits(d, &c.image_limits)
+    }),
+    #[cfg(feature = "image")]
+    ("CF_DIB", "image/png", |c, d| {
+        crate::image::dib_to_png_with_options(d, &c.image_limits, &c.image_encode)
+    }),
+    #[cfg(feature = "image")]
+    ("image/png", "CF_DIBV5", |c, d| {
+        crate::image::png_to_dibv5_with_limits(d, &c.image_limits)
+    }),
+    #[cfg(feature = "image")]
+    ("CF_DIBV5", "image/png", |c, d| {
+        crate::image::dibv5_to_png_with_options(d, &c.image_limits, &c.image_encode)
+    }),
+    #[cfg(feature = "image")]
+    ("image/jpeg", "CF_DIB", |c, d| {
+        crate::image::to_dib(d, ImageFormat::Jpeg, &c.image_limits)
+    }),
+    #[cfg(feature = "image")]
+    ("CF_DIB", "image/jpeg", |c, d| {
+        crate::image::dib_to_jpeg_with_options(d, &c.image_encode)
+    }),
+    #[cfg(feature = "image")]
+    ("image/bmp", "CF_DIB", |_, d| crate::image::bmp_to_dib(d)),
+    #[cfg(feature = "image")]
+    ("CF_DIB", "image/bmp", |_, d| crate::image::dib_to_bmp(d)),
+    #[cfg(feature = "image")]
+    ("CF_TIFF", "image/png", |c, d| {
+        crate::image::to_png(d, ImageFormat::Tiff, &c.image_limits, &c.image_encode)
+    }),
+    #[cfg(feature = "image")]
+    ("CF_TIFF", "image/tiff", |_, d| crate::image::validate_tiff(d)),
+    #[cfg(feature = "image")]
+    ("image/png", "CF_TIFF", |_, d| crate::image::png_to_tiff(d)),
+    #[cfg(feature = "image")]
+    ("image/tiff", "CF_TIFF", |_, d| crate::image::validate_tiff(d)),
+    #[cfg(feature = "image")]
+    ("image/tiff", "CF_DIB", |c, d| {
+        crate::image::to_dib(d, ImageFormat::Tiff, &c.image_limits)
+    }),
+    #[cfg(feature = "image")]
+    ("image/tiff", "CF_DIBV5", |c, d| {
+        crate::image::to_dibv5(d, ImageFormat::Tiff, &c.image_limits)
+    }),
+    #[cfg(feature = "image")]
+    ("CF_DSPBITMAP", "image/png", |c, d| {
+        crate::image::dib_to_png_with_options(d, &c.image_limits, &c.image_encode)
+    }),
+    #[cfg(feature = "image")]
+    ("image/x-icon", "PNG", |c, d| {
+        crate::image::ico_to_png_with_options(d, &c.image_limits, &c.image_encode)
+    }),
+    #[cfg(feature = "image")]
+    ("image/x-icon", "image/png", |c, d| {
+        crate::image::ico_to_png_with_options(d, &c.image_limits, &c.image_encode)
+    }),
+    #[cfg(feature = "image")]
+    ("image/x-icon", "CF_DIBV5", |c, d| {
+        crate::image::ico_to_dibv5_with_limits(d, &c.image_limits)
+    }),
+    #[cfg(feature = "image")]
+    ("image/x-icon", "CF_DIB", |c, d| {
+        crate::image::ico_to_dib_with_limits(d, &c.image_limits)
+    }),
+    #[cfg(feature = "image")]
+    ("image/vnd.microsoft.icon", "PNG", |c, d| {
+        crate::image::ico_to_png_with_options(d, &c.image_limits, &c.image_encode)
+    }),
+    #[cfg(feature = "image")]
+    ("image/vnd.microsoft.icon", "CF_DIBV5", |c, d| {
+        crate::image::ico_to_dibv5_with_limits(d, &c.image_limits)
+    }),
+    #[cfg(feature = "image")]
+    ("image/vnd.microsoft.icon", "CF_DIB", |c, d| {
+        crate::image::ico_to_dib_with_limits(d, &c.image_limits)
+    }),
+    #[cfg(feature = "webp")]
+    ("image/webp", "CF_DIB", |c, d| {
+        crate::image::to_dib(d, ImageFormat::WebP, &c.image_limits)
+    }),
+    #[cfg(feature = "webp")]
+    ("image/webp", "CF_DIBV5", |c, d| {
+        crate::image::to_dibv5(d, ImageFormat::WebP, &c.image_limits)
+    }),
+    #[cfg(feature = "webp")]
+    ("image/webp", "image/png", |c, d| {
+        crate::image::to_png(d, ImageFormat::WebP, &c.image_limits, &c.image_encode)
+    }),
+    #[cfg(feature = "webp")]
+    ("WebP", "image/png", |c, d| {
+        crate::image::to_png(d, ImageFormat::WebP, &c.image_limits, &c.image_encode)
+    }),
+    #[cfg(feature = "avif")]
+    ("image/avif", "CF_DIB", |c, d| {
+        crate::image::to_dib(d, ImageFormat::Avif, &c.image_limits)
+    }),
+    #[cfg(feature = "avif")]
+    ("image/avif", "CF_DIBV5", |c, d| {
+        crate::image::to_dibv5(d, ImageFormat::Avif, &c.image_limits)
+    }),
+    #[cfg(feature = "avif")]
+    ("image/avif", "image/png", |c, d| {
+        crate::image::to_png(d, ImageFormat::Avif, &c.image_limits, &c.image_encode)
+    }),
+    #[cfg(feature = "svg")]
+    ("image/svg+xml", "PNG", |_, d| crate::svg::svg_to_png(d)),
+    #[cfg(feature = "svg")]
+    ("image/svg+xml", "CF_DIBV5", |c, d| {
+        crate::image::png_to_dibv5_with_limits(&crate::svg::svg_to_png(d)?, &c.image_limits)
+    }),
+    #[cfg(feature = "svg")]
+    ("image/svg+xml", "CF_DIB", |c, d| {
+        crate::image::png_to_dib_with_limits(&crate::svg::svg_to_png(d)?, &c.image_limits)
+    }),
+    #[cfg(feature = "svg")]
+    ("image/svg+xml", "image/png", |_, d| crate::svg::svg_to_png(d)),
+    #[cfg(feature = "metafile")]
+    ("CF_ENHMETAFILE", "image/png", |_, d| crate::metafile::emf_to_png(d)),
+    #[cfg(feature = "metafile")]
+    ("CF_METAFILEPICT", "image/png", |_, d| {
+        crate::metafile::metafilepict_to_png(d)
+    }),
+];
+
+fn utf8(data: &[u8]) -> ClipboardResult<&str> {
+    std::str::from_utf8(data).map_err(|_| ClipboardError::InvalidUtf8)
+}
+
+/// Converters registered on a [`FormatConverter`], keyed by (from, to)
+#[derive(Clone, Default)]
+pub(crate) struct ConverterMap {
+    entries: Vec<(String, String, Arc<dyn Converter>)>,
+}
+
+impl ConverterMap {
+    fn get(&self, from: &str, to: &str) -> Option<&Arc<dyn Converter>> {
+        self.entries
+            .iter()
+            .find(|(f, t, _)| f == from && t == to)
+            .map(|(_, _, converter)| converter)
+    }
+
+    fn insert(&mut self, from: String, to: String, converter: Arc<dyn Converter>) {
+        match self.entries.iter_mut().find(|(f, t, _)| *f == from && *t == to) {
+            Some(entry) => entry.2 = converter,
+            None => self.entries.push((from, to, converter)),
+        }
+    }
+}
+
+impl fmt::Debug for ConverterMap {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_list()
+            .entries(self.entries.iter().map(|(from, to, _)| format!("{from} -> {to}")))
+            .finish()
+    }
+}
+
+impl FormatConverter {
+    /// Register a conversion, replacing any previous one for the same pair
+    pub fn register_converter(
+        &mut self,
+        from: impl Into<String>,
+        to: impl Into<String>,
+        converter: impl Converter + 'static,
+    ) {
+        self.converters.insert(from.into(), to.into(), Arc::new(converter));
+    }
+
+    /// Whether a conversion from `from` to `to` is available
+    pub fn has_converter(&self, from: &str, to: &str) -> bool {
+        self.converters.get(from, to).is_some() || builtin(from, to).is_some()
+    }
+
+    /// Every available conversion as (from, to), registered ones first
+    pub fn conversions(&self) -> Vec<(String, String)> {
+        let mut pairs: Vec<(String, String)> = self
+            .converters
+            .entries
+            .iter()
+            .map(|(from, to, _)| (from.clone(), to.clone()))
+            .collect();
+        for (from, to, _) in BUILTIN_CONVERTERS {
+            if self.converters.get(from, to).is_none() {
+                pairs.push((from.to_string(), to.to_string()));
+            }
+        }
+        pairs
+    }
+
+    /// Convert data between two formats by name
+    ///
+    /// Fails with [`ClipboardError::UnsupportedFormat`] if no converter
+    /// handles the pair.
+    ///
+    /// Failures are counted in [`conversion_metrics`](Self::conversion_metrics).
+    pub fn convert(&self, from: &str, to: &str, data: &[u8]) -> ClipboardResult<Vec<u8>> {
+        self.convert_unrecorded(from, to, data).inspect_err(|error| {
+            let failure = self.record_failure(error);
+            tracing::debug!("Conversion {} -> {} failed ({}): {}", from, to, failure, error);
+        })
+    }
+
+    fn convert_unrecorded(&self, from: &str, to: &str, data: &[u8]) -> ClipboardResult<Vec<u8>> {
+        if data.len() > self.max_size {
+            return Err(ClipboardError::DataSizeExceeded {
+                actual: data.len(),
+                max: self.max_size,
+            });
+        }
+
+        if let Some(converter) = self.converters.get(from, to) {
+            return converter.convert(self, data);
+        }
+        match builtin(from, to) {
+            #[cfg(feature = "image")]
+            Some(convert) if crate::cache::is_image_format(from) || crate::cache::is_image_format(to) => {
+                self.cached(from, to, data, || convert(self, data))
+            }
+            Some(convert) => convert(self, data),
+            None => Err(ClipboardError::UnsupportedFormat(format!("{from} -> {to}"))),
+        }
+    }
+}
+
+fn builtin(from: &str, to: &str) -> Option<BuiltinFn> {
+    BUILTIN_CONVERTERS
+        .iter()
+        .find(|(f, t, _)| *f == from && *t == to)
+        .map(|(_, _, convert)| *convert)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[cfg(feature = "image")]
+    #[test]
+    fn test_image_limits_applied() {
+        use crate::image::{ImageLimits, ResizeStrategy};
+
+        let dib = crate::testing::fixtures::DibBuilder::new(64, 32).build();
+        let converter = FormatConverter::new().with_image_limits(ImageLimits {
+            max_width: 16,
+            ..ImageLimits::default()
+        });
+        let png = converter.convert("CF_DIB", "image/png", &dib).unwrap();
+        let dib = converter.convert("image/png", "CF_DIB", &png).unwrap();
+        assert_eq!(crate::image::dib_dimensions(&dib).unwrap(), (16, 8));
+
+        let converter = FormatConverter::new().with_image_limits(ImageLimits {
+            max_pixels: 100,
+            resize_strategy: ResizeStrategy::Reject,
+            ..ImageLimits::default()
+        });
+        assert!(converter.convert("CF_DIB", "image/png", &dib).is_err());
+        assert_eq!(converter.conversion_metrics().oversized_payload, 1);
+    }
+
+    #[test]
+    fn test_builtin_round_trip() {
+        let converter = FormatConverter::new();
+        let cf_html = converter.convert("text/html", "HTML Format", b"<b>x</b>").unwrap();
+        assert_eq!(
+            converter.convert("HTML Format", "text/html", &cf_html).unwrap(),
+            b"<b>x</b>"
+        );
+
+        let csv = converter.convert("text/csv", "Csv", b"a,b\n").unwrap();
+        assert_eq!(csv, b"a,b\r\n\0");
+    }
+
+    #[test]
+    fn test_registered_converter_overrides_builtin() {
+        let mut converter = FormatConverter::new();
+        assert!(converter.has_converter("text/plain", "CF_TEXT"));
+        let builtin_count = converter.conversions().len();
+
+        converter.register_converter("text/plain", "CF_TEXT", |_: &FormatConverter, _: &[u8]| {
+            Ok(b"custom".to_vec())
+        });
+        assert_eq!(converter.convert("text/plain", "CF_TEXT", b"x").unwrap(), b"custom");
+        assert_eq!(converter.conversions().len(), builtin_count);
+        assert_eq!(
+            converter.conversions()[0],
+            ("text/plain".to_string(), "CF_TEXT".to_string())
+        );
+    }
+
+    #[test]
+    fn test_unknown_pair_and_limits() {
+        let converter = FormatConverter::with_max_size(4);
+        assert!(matches!(
+            converter.convert("text/plain", "application/x-nothing", b"x"),
+            Err(ClipboardError::UnsupportedFormat(_))
+        ));
+        assert!(matches!(
+            converter.convert("text/plain", "CF_UNICODETEXT", b"too long"),
+            Err(ClipboardError::DataSizeExceeded { .. })
+        ));
+        assert!(matches!(
+            converter.convert("text/plain", "CF_UNICODETEXT", &[0xFF]),
+            Err(ClipboardError::InvalidUtf8)
+        ));
+    }
+
+    #[test]
+    fn test_builtin_pairs_unique() {
+        for (i, (from, to, _)) in BUILTIN_CONVERTERS.iter().enumerate() {
+            assert!(
+                !BUILTIN_CONVERTERS[i + 1..].iter().any(|(f, t, _)| f == from && t == to),
+                "duplicate {from} -> {to}"
+            );
+        }
+    }
+}
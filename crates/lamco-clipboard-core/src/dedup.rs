@@ -0,0 +1,268 @@
+//! Sink decorator with built-in loop detection.
+//!
+//! [`DedupSink`] wraps a [`ClipboardSink`] and a shared [`LoopDetector`], and
+//! drops the echoes a sync produces instead of leaving every call site to
+//! check the detector: the change event raised by writing pasted data, the
+//! peer writing back content it just read from us, and duplicate
+//! announcements of a format list that was just announced.
+
+use crate::loop_detector::{ClipboardSource, LoopDetector};
+use crate::sink::{ClipboardChange, ClipboardChangeReceiver, ClipboardChangeReceiverInner, ClipboardSink, FileInfo};
+use crate::ClipboardResult;
+use std::future::Future;
+use std::sync::Arc;
+
+/// Clipboard sink decorator that suppresses sync loops.
+///
+/// - **Remote → local**: announcements and writes are recorded so the change
+///   events they raise are dropped. A write of content read from the local
+///   clipboard moments ago, and an announcement repeating one made within
+///   the detection window, are dropped without reaching the inner sink.
+/// - **Local → remote**: content read for the peer is recorded, and change
+///   events the detector considers echoes (see
+///   [`LoopDetector::should_skip_change`]) never reach the receiver.
+///
+/// The sink only sees MIME types; Format Lists echoed by the peer are still
+/// checked with [`LoopDetector::would_cause_loop`] on the RDP side, ideally
+/// with the same detector (see [`detector()`](Self::detector)).
+///
+/// # Example
+///
+/// ```rust,ignore
+/// use lamco_clipboard_core::DedupSink;
+///
+/// let sink = DedupSink::new(portal_sink);
+/// let detector = sink.detector().clone();
+/// ```
+#[derive(Debug)]
+pub struct DedupSink<S> {
+    inner: S,
+    detector: Arc<LoopDetector>,
+}
+
+impl<S: ClipboardSink> DedupSink<S> {
+    /// Wrap a sink with a default loop detector
+    pub fn new(inner: S) -> Self {
+        Self {
+            inner,
+            detector: Arc::new(LoopDetector::new()),
+        }
+    }
+
+    /// Use an existing detector, e.g. one shared with the CLIPRDR backend
+    pub fn with_detector(mut self, detector: Arc<LoopDetector>) -> Self {
+        self.detector = detector;
+        self
+    }
+
+    /// Get the loop detector
+    pub fn detector(&self) -> &Arc<LoopDetector> {
+        &self.detector
+    }
+
+    /// Get a reference to the wrapped sink
+    pub fn inner(&self) -> &S {
+        &self.inner
+    }
+
+    /// Unwrap the inner sink
+    pub fn into_inner(self) -> S {
+        self.inner
+    }
+}
+
+impl<S: ClipboardSink> ClipboardSink for DedupSink<S> {
+    async fn announce_formats(&self, mime_types: Vec<String>) -> ClipboardResult<()> {
+        if self.detector.should_skip_sync_mime(&mime_types, ClipboardSource::Rdp) {
+            tracing::debug!("Dropping repeated announcement of {:?}", mime_types);
+            return Ok(());
+        }
+
+        // Taking ownership raises a local change event; see `EchoFilter`
+        self.detector.record_mime_types(&mime_types, ClipboardSource::Local);
+        self.inner.announce_formats(mime_types).await
+    }
+
+    async fn read_clipboard(&self, mime_type: &str) -> ClipboardResult<Vec<u8>> {
+        let data = self.inner.read_clipboard(mime_type).await?;
+        self.detector.record_content(&data, ClipboardSource::Local);
+        Ok(data)
+    }
+
+    async fn write_clipboard(&self, mime_type: &str, data: Vec<u8>) -> ClipboardResult<()> {
+        if self.detector.would_cause_content_loop(&data, ClipboardSource::Rdp) {
+            tracing::debug!("Dropping write of {} echoing local content", mime_type);
+            return Ok(());
+        }
+
+        self.detector.record_content(&data, ClipboardSource::Rdp);
+        self.detector
+            .record_mime_types(&[mime_type.to_string()], ClipboardSource::Local);
+        self.inner.write_clipboard(mime_type, data).await
+    }
+
+    async fn subscribe_changes(&self) -> ClipboardResult<ClipboardChangeReceiver> {
+        let inner = self.inner.subscribe_changes().await?;
+        Ok(ClipboardChangeReceiver::new(Box::new(EchoFilter {
+            inner,
+            detector: Arc::clone(&self.detector),
+        })))
+    }
+
+    fn get_file_list(&self) -> impl Future<Output = ClipboardResult<Vec<FileInfo>>> + Send {
+        self.inner.get_file_list()
+    }
+
+    fn read_file_chunk(
+        &self,
+        index: u32,
+        offset: u64,
+        size: u32,
+    ) -> impl Future<Output = ClipboardResult<Vec<u8>>> + Send {
+        self.inner.read_file_chunk(index, offset, size)
+    }
+
+    fn write_file(&self, path: &str, data: Vec<u8>) -> impl Future<Output = ClipboardResult<()>> + Send {
+        self.inner.write_file(path, data)
+    }
+}
+
+/// Change receiver that drops the events caused by our own syncs
+struct EchoFilter {
+    inner: ClipboardChangeReceiver,
+    detector: Arc<LoopDetector>,
+}
+
+impl EchoFilter {
+    fn is_echo(&self, change: &ClipboardChange) -> bool {
+        // A cleared clipboard is never an echo
+        !change.mime_types.is_empty() && self.detector.should_skip_change(change)
+    }
+}
+
+impl ClipboardChangeReceiverInner for EchoFilter {
+    fn recv_blocking(&mut self) -> Option<ClipboardChange> {
+        loop {
+            let change = self.inner.recv_blocking()?;
+            if !self.is_echo(&change) {
+                return Some(change);
+            }
+        }
+    }
+
+    fn try_recv(&mut self) -> Option<ClipboardChange> {
+        loop {
+            let change = self.inner.try_recv()?;
+            if !self.is_echo(&change) {
+                return Some(change);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::block_on;
+    use std::collections::VecDeque;
+    use std::sync::Mutex;
+
+    struct QueuedChanges(VecDeque<ClipboardChange>);
+
+    impl ClipboardChangeReceiverInner for QueuedChanges {
+        fn recv_blocking(&mut self) -> Option<ClipboardChange> {
+            self.0.pop_front()
+        }
+
+        fn try_recv(&mut self) -> Option<ClipboardChange> {
+            self.0.pop_front()
+        }
+    }
+
+    /// Sink that raises a change event for every announcement and write
+    #[derive(Default)]
+    struct EchoingSink {
+        announced: Mutex<Vec<Vec<String>>>,
+        writes: Mutex<Vec<(String, Vec<u8>)>>,
+        changes: Mutex<VecDeque<ClipboardChange>>,
+    }
+
+    impl ClipboardSink for EchoingSink {
+        async fn announce_formats(&self, mime_types: Vec<String>) -> ClipboardResult<()> {
+            self.changes
+                .lock()
+                .unwrap()
+                .push_back(ClipboardChange::new(mime_types.clone()));
+            self.announced.lock().unwrap().push(mime_types);
+            Ok(())
+        }
+
+        async fn read_clipboard(&self, _mime_type: &str) -> ClipboardResult<Vec<u8>> {
+            Ok(b"copied locally".to_vec())
+        }
+
+        async fn write_clipboard(&self, mime_type: &str, data: Vec<u8>) -> ClipboardResult<()> {
+            self.changes
+                .lock()
+                .unwrap()
+                .push_back(ClipboardChange::new(vec![mime_type.to_string()]));
+            self.writes.lock().unwrap().push((mime_type.to_string(), data));
+            Ok(())
+        }
+
+        async fn subscribe_changes(&self) -> ClipboardResult<ClipboardChangeReceiver> {
+            let changes = std::mem::take(&mut *self.changes.lock().unwrap());
+            Ok(ClipboardChangeReceiver::new(Box::new(QueuedChanges(changes))))
+        }
+
+        async fn get_file_list(&self) -> ClipboardResult<Vec<FileInfo>> {
+            Ok(Vec::new())
+        }
+
+        async fn read_file_chunk(&self, _index: u32, _offset: u64, _size: u32) -> ClipboardResult<Vec<u8>> {
+            Ok(Vec::new())
+        }
+
+        async fn write_file(&self, _path: &str, _data: Vec<u8>) -> ClipboardResult<()> {
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn test_own_changes_dropped() {
+        let sink = DedupSink::new(EchoingSink::default());
+        let html = vec!["text/html".to_string()];
+
+        block_on(sink.announce_formats(html.clone())).unwrap();
+        block_on(sink.write_clipboard("text/plain", b"pasted".to_vec())).unwrap();
+        sink.inner()
+            .changes
+            .lock()
+            .unwrap()
+            .push_back(ClipboardChange::new(vec!["image/png".to_string()]));
+
+        // Only the user's own copy gets through
+        let mut changes = block_on(sink.subscribe_changes()).unwrap();
+        assert_eq!(changes.try_recv().unwrap().mime_types, vec!["image/png"]);
+        assert!(changes.try_recv().is_none());
+        assert_eq!(sink.detector().stats().loops_prevented, 2);
+
+        // The same announcement again within the window is a duplicate
+        block_on(sink.announce_formats(html)).unwrap();
+        assert_eq!(sink.inner().announced.lock().unwrap().len(), 1);
+    }
+
+    #[test]
+    fn test_write_of_local_content_dropped() {
+        let detector = Arc::new(LoopDetector::new());
+        let sink = DedupSink::new(EchoingSink::default()).with_detector(Arc::clone(&detector));
+
+        let data = block_on(sink.read_clipboard("text/plain")).unwrap();
+        block_on(sink.write_clipboard("text/plain", data)).unwrap();
+        assert!(sink.inner().writes.lock().unwrap().is_empty());
+
+        block_on(sink.write_clipboard("text/plain", b"from the peer".to_vec())).unwrap();
+        assert_eq!(sink.inner().writes.lock().unwrap().len(), 1);
+        assert_eq!(detector.stats().loops_prevented, 1);
+    }
+}
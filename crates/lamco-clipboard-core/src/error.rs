@@ -1,5 +1,6 @@
 //! Error types for clipboard operations.
 
+use std::fmt;
 use thiserror::Error;
 
 /// Result type for clipboard operations
@@ -32,10 +33,31 @@ pub enum ClipboardError {
     #[error("image decode error: {0}")]
     ImageDecode(String),
 
+    /// DIB bit depth the image decoder does not handle
+    #[error("unsupported DIB bit depth: {0}")]
+    UnsupportedBitDepth(u16),
+
+    /// CF_HTML with missing headers or offsets that do not fit the data
+    #[error("invalid CF_HTML: {0}")]
+    InvalidCfHtml(String),
+
+    /// A URI list entry that is not a `file:` URI where only files can be sent
+    #[error("not a file URI: {0}")]
+    NonFileUri(String),
+
     /// Image encode error
     #[error("image encode error: {0}")]
     ImageEncode(String),
 
+    /// Image larger than the configured limits, with downscaling disabled
+    #[error("image {width}x{height} exceeds the configured limits")]
+    ImageTooLarge {
+        /// Width in pixels
+        width: u32,
+        /// Height in pixels
+        height: u32,
+    },
+
     /// Data size exceeded maximum
     #[error("data size {actual} exceeds maximum {max}")]
     DataSizeExceeded {
@@ -49,10 +71,21 @@ pub enum ClipboardError {
     #[error("transfer timeout after {0}ms")]
     TransferTimeout(u64),
 
+    /// A file transfer failed at a known file and offset
+    #[error(transparent)]
+    FileTransfer(#[from] TransferError),
+
     /// Transfer was cancelled
     #[error("transfer cancelled")]
     TransferCancelled,
 
+    /// Too many requests waiting for a flow control slot
+    #[error("request queue full ({limit} queued)")]
+    QueueFull {
+        /// Maximum number of queued requests
+        limit: usize,
+    },
+
     /// Loop detected - would cause clipboard sync loop
     #[error("clipboard loop detected")]
     LoopDetected,
@@ -65,6 +98,21 @@ pub enum ClipboardError {
     #[error("file not found: {0}")]
     FileNotFound(String),
 
+    /// A file of that name already exists
+    #[error("file already exists: {0}")]
+    FileExists(String),
+
+    /// File changed between descriptor generation and FileContents serving
+    #[error("file changed since it was announced: {path} (announced {announced} bytes, now {actual} bytes)")]
+    FileChanged {
+        /// Path of the file
+        path: String,
+        /// Size announced in the file descriptor
+        announced: u64,
+        /// Size currently available on disk
+        actual: u64,
+    },
+
     /// Permission denied
     #[error("permission denied: {0}")]
     PermissionDenied(String),
@@ -77,10 +125,13 @@ pub enum ClipboardError {
 impl ClipboardError {
     /// Returns true if this error is recoverable
     pub fn is_recoverable(&self) -> bool {
-        matches!(
-            self,
-            Self::TransferTimeout(_) | Self::LoopDetected | Self::InvalidState(_)
-        )
+        match self {
+            Self::FileTransfer(error) => error.is_transient(),
+            _ => matches!(
+                self,
+                Self::TransferTimeout(_) | Self::LoopDetected | Self::InvalidState(_) | Self::QueueFull { .. }
+            ),
+        }
     }
 
     /// Returns true if this error indicates a format issue
@@ -93,6 +144,76 @@ impl ClipboardError {
                 | Self::InvalidUtf16
                 | Self::ImageDecode(_)
                 | Self::ImageEncode(_)
+                | Self::UnsupportedBitDepth(_)
+                | Self::InvalidCfHtml(_)
+                | Self::NonFileUri(_)
+        )
+    }
+}
+
+/// Why a file transfer stopped
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TransferErrorKind {
+    /// The peer answered with a failed File Contents Response
+    PeerError,
+    /// A chunk went unanswered through every retry while the peer kept
+    /// answering other requests
+    ChunkTimeout,
+    /// Nothing was heard from the peer through every retry of a chunk
+    PeerUnresponsive,
+    /// The transfer ran past its overall deadline
+    TransferTimeout,
+    /// A complete file does not match the checksum the peer sent for it
+    ChecksumMismatch,
+    /// A file is larger than the file transfer policy allows
+    FileTooLarge,
+    /// The files add up to more than the file transfer policy allows
+    TotalSizeExceeded,
+    /// More files than the file transfer policy allows
+    TooManyFiles,
+    /// The file's extension is denied by the file transfer policy
+    ExtensionDenied,
+}
+
+impl fmt::Display for TransferErrorKind {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Self::PeerError => "peer failed to provide",
+            Self::ChunkTimeout => "chunk timed out",
+            Self::PeerUnresponsive => "peer stopped responding",
+            Self::TransferTimeout => "transfer timed out",
+            Self::ChecksumMismatch => "checksum mismatch",
+            Self::FileTooLarge => "file larger than allowed",
+            Self::TotalSizeExceeded => "files larger than allowed in total",
+            Self::TooManyFiles => "more files than allowed",
+            Self::ExtensionDenied => "file type not allowed",
+        })
+    }
+}
+
+/// A file transfer failure, with the file and offset it happened at
+#[derive(Error, Debug, Clone, PartialEq, Eq)]
+#[error("{kind}: {path} at offset {offset}")]
+pub struct TransferError {
+    /// What went wrong
+    pub kind: TransferErrorKind,
+    /// Index of the file in the FileGroupDescriptorW
+    pub index: u32,
+    /// Relative path of the file
+    pub path: String,
+    /// Offset of the request that failed or of the first missing byte; 0
+    /// for a checksum mismatch or policy rejection, which cover the whole
+    /// file
+    pub offset: u64,
+}
+
+impl TransferError {
+    /// Returns true if the peer was still answering, so trying the paste
+    /// again may succeed
+    pub fn is_transient(&self) -> bool {
+        matches!(
+            self.kind,
+            TransferErrorKind::ChunkTimeout | TransferErrorKind::TransferTimeout | TransferErrorKind::ChecksumMismatch
         )
     }
 }
@@ -111,7 +232,24 @@ mod tests {
     fn test_is_recoverable() {
         assert!(ClipboardError::LoopDetected.is_recoverable());
         assert!(ClipboardError::TransferTimeout(1000).is_recoverable());
+        assert!(ClipboardError::QueueFull { limit: 8 }.is_recoverable());
+        let mut error = TransferError {
+            kind: TransferErrorKind::ChunkTimeout,
+            index: 2,
+            path: "docs/a.txt".to_string(),
+            offset: 65536,
+        };
+        assert_eq!(error.to_string(), "chunk timed out: docs/a.txt at offset 65536");
+        assert!(ClipboardError::from(error.clone()).is_recoverable());
+        error.kind = TransferErrorKind::PeerUnresponsive;
+        assert!(!ClipboardError::from(error).is_recoverable());
         assert!(!ClipboardError::InvalidUtf8.is_recoverable());
+        assert!(!ClipboardError::FileChanged {
+            path: "a.txt".to_string(),
+            announced: 10,
+            actual: 5,
+        }
+        .is_recoverable());
     }
 
     #[test]
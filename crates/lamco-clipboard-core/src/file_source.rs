@@ -0,0 +1,252 @@
+//! Serving announced files for MS-RDPECLIP FileContents requests.
+//!
+//! A FILEDESCRIPTORW promises a file size at copy time, but the peer may
+//! request the contents much later. In between, the file can grow, shrink or
+//! disappear. [`AnnouncedFile`] records what was announced and re-validates
+//! it on every request so the behavior is defined:
+//!
+//! | Situation            | Behavior                                          |
+//! |----------------------|---------------------------------------------------|
+//! | Zero-byte file       | Size 0, every range request returns empty data    |
+//! | Directory            | Size 0, range requests return empty data          |
+//! | File grew            | Only the announced size is served                 |
+//! | File shrank          | [`ClipboardError::FileChanged`]                   |
+//! | File deleted         | [`ClipboardError::FileNotFound`]                  |
+
+use crate::{ClipboardError, ClipboardResult};
+use std::fs::File;
+use std::io::{ErrorKind, Read, Seek, SeekFrom};
+use std::path::{Path, PathBuf};
+use std::time::SystemTime;
+
+/// Snapshot of a local file as announced to the remote peer
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct AnnouncedFile {
+    path: PathBuf,
+    size: u64,
+    modified: Option<SystemTime>,
+    is_directory: bool,
+}
+
+impl AnnouncedFile {
+    /// Capture the current state of a file for announcement
+    pub fn snapshot(path: impl Into<PathBuf>) -> ClipboardResult<Self> {
+        let path = path.into();
+        let metadata = std::fs::metadata(&path).map_err(|e| map_io_error(&path, e))?;
+        let is_directory = metadata.is_dir();
+
+        Ok(Self {
+            size: if is_directory { 0 } else { metadata.len() },
+            modified: metadata.modified().ok(),
+            is_directory,
+            path,
+        })
+    }
+
+    /// Path of the announced file
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// Size announced to the peer in bytes
+    pub fn size(&self) -> u64 {
+        self.size
+    }
+
+    /// Whether the announced entry is a directory
+    pub fn is_directory(&self) -> bool {
+        self.is_directory
+    }
+
+    /// Check the file still satisfies the announcement.
+    ///
+    /// Returns the announced size, which is what a FILECONTENTS_SIZE request
+    /// should report. Growth is tolerated (the extra bytes are never served);
+    /// shrinking or deletion is an error.
+    pub fn validate(&self) -> ClipboardResult<u64> {
+        let metadata = std::fs::metadata(&self.path).map_err(|e| map_io_error(&self.path, e))?;
+
+        if self.is_directory {
+            return if metadata.is_dir() {
+                Ok(0)
+            } else {
+                Err(ClipboardError::InvalidState(format!(
+                    "{} is no longer a directory",
+                    self.path.display()
+                )))
+            };
+        }
+
+        let actual = metadata.len();
+        if actual < self.size {
+            return Err(self.changed(actual));
+        }
+
+        if actual > self.size {
+            tracing::debug!(
+                "{} grew from {} to {} bytes since announcement, serving announced size",
+                self.path.display(),
+                self.size,
+                actual
+            );
+        } else if metadata.modified().ok() != self.modified {
+            tracing::debug!("{} was modified since announcement", self.path.display());
+        }
+
+        Ok(self.size)
+    }
+
+    /// Read a range of the file for a FILECONTENTS_RANGE request.
+    ///
+    /// The range is clamped to the announced size, so reads at or past the
+    /// end (and all reads of zero-byte files) return empty data. If the file
+    /// shrinks while being read, [`ClipboardError::FileChanged`] is returned
+    /// rather than silently truncated data.
+    pub fn read_range(&self, offset: u64, size: u32) -> ClipboardResult<Vec<u8>> {
+        if self.is_directory || size == 0 || offset >= self.size {
+            return Ok(Vec::new());
+        }
+
+        let len = (self.size - offset).min(u64::from(size)) as usize;
+
+        let mut file = File::open(&self.path).map_err(|e| map_io_error(&self.path, e))?;
+        file.seek(SeekFrom::Start(offset))?;
+
+        let mut data = vec![0u8; len];
+        let mut filled = 0;
+        while filled < len {
+            match file.read(&mut data[filled..]) {
+                Ok(0) => return Err(self.changed(offset + filled as u64)),
+                Ok(n) => filled += n,
+                Err(e) if e.kind() == ErrorKind::Interrupted => {}
+                Err(e) => return Err(e.into()),
+            }
+        }
+
+        Ok(data)
+    }
+
+    fn changed(&self, actual: u64) -> ClipboardError {
+        ClipboardError::FileChanged {
+            path: self.path.display().to_string(),
+            announced: self.size,
+            actual,
+        }
+    }
+}
+
+fn map_io_error(path: &Path, e: std::io::Error) -> ClipboardError {
+    match e.kind() {
+        ErrorKind::NotFound => ClipboardError::FileNotFound(path.display().to_string()),
+        ErrorKind::PermissionDenied => ClipboardError::PermissionDenied(path.display().to_string()),
+        _ => ClipboardError::Io(e),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::FileDescriptor;
+
+    fn temp_path(name: &str) -> PathBuf {
+        std::env::temp_dir().join(format!("lamco-file-source-{}-{}", std::process::id(), name))
+    }
+
+    #[test]
+    fn test_zero_byte_file() {
+        let path = temp_path("empty");
+        std::fs::write(&path, b"").unwrap();
+
+        let file = AnnouncedFile::snapshot(&path).unwrap();
+        assert_eq!(file.size(), 0);
+        assert_eq!(file.validate().unwrap(), 0);
+        assert!(file.read_range(0, 4096).unwrap().is_empty());
+
+        let descriptor = FileDescriptor::parse(&FileDescriptor::build(&path).unwrap()).unwrap();
+        assert_eq!(descriptor.size, Some(0));
+
+        std::fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn test_directory_has_zero_size() {
+        let path = temp_path("dir");
+        std::fs::create_dir_all(&path).unwrap();
+
+        let file = AnnouncedFile::snapshot(&path).unwrap();
+        assert!(file.is_directory());
+        assert_eq!(file.size(), 0);
+        assert!(file.read_range(0, 10).unwrap().is_empty());
+
+        let descriptor = FileDescriptor::parse(&FileDescriptor::build(&path).unwrap()).unwrap();
+        assert_eq!(descriptor.size, Some(0));
+
+        std::fs::remove_dir(&path).unwrap();
+    }
+
+    #[test]
+    fn test_read_range_clamped() {
+        let path = temp_path("range");
+        std::fs::write(&path, b"hello world").unwrap();
+
+        let file = AnnouncedFile::snapshot(&path).unwrap();
+        assert_eq!(file.read_range(6, 100).unwrap(), b"world");
+        assert!(file.read_range(11, 10).unwrap().is_empty());
+        assert!(file.read_range(0, 0).unwrap().is_empty());
+
+        std::fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn test_grown_file_serves_announced_size() {
+        let path = temp_path("grow");
+        std::fs::write(&path, b"hello").unwrap();
+        let file = AnnouncedFile::snapshot(&path).unwrap();
+
+        std::fs::write(&path, b"hello world").unwrap();
+        assert_eq!(file.validate().unwrap(), 5);
+        assert_eq!(file.read_range(0, 100).unwrap(), b"hello");
+
+        std::fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn test_shrunk_file_is_error() {
+        let path = temp_path("shrink");
+        std::fs::write(&path, b"hello world").unwrap();
+        let file = AnnouncedFile::snapshot(&path).unwrap();
+
+        std::fs::write(&path, b"hello").unwrap();
+        assert!(matches!(
+            file.validate(),
+            Err(ClipboardError::FileChanged {
+                announced: 11,
+                actual: 5,
+                ..
+            })
+        ));
+        assert!(matches!(
+            file.read_range(0, 100),
+            Err(ClipboardError::FileChanged { .. })
+        ));
+        // Ranges still within the remaining data are served
+        assert_eq!(file.read_range(0, 5).unwrap(), b"hello");
+
+        std::fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn test_deleted_file_is_not_found() {
+        let path = temp_path("deleted");
+        std::fs::write(&path, b"data").unwrap();
+        let file = AnnouncedFile::snapshot(&path).unwrap();
+
+        std::fs::remove_file(&path).unwrap();
+        assert!(matches!(file.validate(), Err(ClipboardError::FileNotFound(_))));
+        assert!(matches!(file.read_range(0, 4), Err(ClipboardError::FileNotFound(_))));
+        assert!(matches!(
+            AnnouncedFile::snapshot(&path),
+            Err(ClipboardError::FileNotFound(_))
+        ));
+    }
+}
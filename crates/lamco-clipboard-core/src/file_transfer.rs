@@ -0,0 +1,2826 @@
+//! FileContents exchanges for file copy and paste.
+//!
+//! Pasting files from the peer starts with a FileGroupDescriptorW listing
+//! them; their contents are then fetched with File Contents Request PDUs,
+//! each asking for the size of one file or a byte range of it, and answered
+//! by a File Contents Response carrying the same stream ID.
+//!
+//! [`FileTransfer`] is the sans-io state machine for the receiving side:
+//! it splits every file into requests of `chunk_size` bytes, keeps at most
+//! `max_outstanding_requests` of them in flight, reassembles the responses
+//! (which may arrive in any order) and reports each file once all of its
+//! bytes are in. Like [`ClipboardSession`](crate::protocol::ClipboardSession)
+//! it performs no I/O: [`drive`] writes received files through a
+//! [`ClipboardSink`] and returns the requests to send. For the serving side,
+//! [`serve`] answers a peer's request from the sink's file list.
+//!
+//! Clipboard data locks keep a paste valid when the copying side's clipboard
+//! changes mid-transfer. The receiver takes a [`ClipDataLock`] before
+//! requesting and hands it to [`FileTransfer::with_lock`], which refers every
+//! request to it and releases it (sending Unlock) when the transfer ends. On
+//! the serving side, [`FileServer`] snapshots the announced files when the
+//! peer locks and serves requests carrying that `clipDataId` from the
+//! snapshot until it unlocks.
+//! [`FileServer::with_chunk_cache`] reads contents that appear several
+//! times in one copy, such as hundreds of files made from one template,
+//! only once.
+//!
+//! [`FileTransfer::progress`] reports bytes done per file and overall, with
+//! rate and ETA, for a copy-progress dialog. With the `tokio` feature,
+//! [`FileTransfer::subscribe_progress`] hands out a watch channel that is
+//! updated after every response.
+//!
+//! A [`CancellationToken`] stops a transfer from anywhere, e.g. the paste
+//! dialog's cancel button: outstanding requests are abandoned, buffered data
+//! and staging files are released, and the transfer ends up
+//! [`Cancelled`](TransferState::Cancelled).
+//!
+//! Requests are pipelined rather than sent in lockstep, so throughput is not
+//! capped at one chunk per round trip. The window slides along each file:
+//! nothing is requested more than `max_outstanding_requests` chunks past the
+//! first missing byte, which bounds what a slow response can hold up. With
+//! `max_unwritten_bytes` set, received files that the host has not yet
+//! confirmed with [`FileTransfer::file_written`] hold back new requests, so
+//! a sink slower than the network is not buried in buffered files.
+//!
+//! With `max_bytes_per_second` set, requests are paced so the paste does not
+//! starve graphics and input on the same connection: when the limit holds
+//! requests back, [`FileTransfer::next_poll`] says when to call
+//! [`FileTransfer::poll`]. Responses we serve can be paced with a
+//! [`RateLimiter`] from [`FlowControlConfig`](crate::FlowControlConfig).
+//!
+//! A request left unanswered for `chunk_timeout_ms` is sent again, with the
+//! same stream ID, after a backoff that doubles with every retry. A stalled
+//! channel usually recovers within a retry or two; once `max_retries` are
+//! used up the transfer fails with a [`TransferError`] naming the file and
+//! offset, telling a chunk that never came back while the peer answered
+//! others apart from a peer that went silent altogether.
+//!
+//! Files can be verified end to end: a peer running this library announces
+//! [`FileChecksums`] as the [`FILE_CHECKSUMS_FORMAT_NAME`] registered format
+//! next to the file list. Handed to [`FileTransfer::with_checksums`], they
+//! make a file whose bytes hash differently fail the transfer with a
+//! [`TransferErrorKind::ChecksumMismatch`] instead of being written.
+//!
+//! A [`FileTransferPolicy`] caps what may cross the clipboard: the size of
+//! a single file, the total, the number of files, and extensions that are
+//! refused outright. [`FileTransfer::with_policy`] rejects a paste before
+//! requesting anything, releasing its clipboard data lock;
+//! [`FileServer::with_policy`] fails every request for a copy that breaks
+//! it, so the peer gets failed File Contents Responses.
+//!
+//! With the `compression` feature, range responses can travel
+//! zstd-compressed between two peers running this library once the pasting
+//! side has opted in; see [`compression`](crate::compression).
+//!
+//! Handing the same [`TransferRecorder`] to every transfer and server with
+//! `with_metrics` accounts for bytes in and out, pastes in progress, chunk
+//! round trips and failures by cause across all of them.
+//!
+//! To survive an RDP auto-reconnect, save a [`TransferManifest`] with
+//! [`FileTransfer::manifest`] and stage the bytes from
+//! [`FileTransfer::partial_data`] next to it. After reconnecting,
+//! [`FileTransfer::resume`] skips the files that were complete and
+//! [`FileTransfer::restore_partial`] picks unfinished ones up at their
+//! verified offset once the staged bytes match the manifest's checksum.
+//!
+//! # Example
+//!
+//! ```
+//! use lamco_clipboard_core::file_transfer::{FileContentsKind, FileTransfer, FileTransferEvent};
+//! use lamco_clipboard_core::FileDescriptor;
+//!
+//! # fn descriptor(name: &str, size: u64) -> FileDescriptor {
+//! #     FileDescriptor {
+//! #         flags: lamco_clipboard_core::FileDescriptorFlags::from_raw(0x40),
+//! #         attributes: 0x80,
+//! #         creation_time: None,
+//! #         access_time: None,
+//! #         write_time: None,
+//! #         size: Some(size),
+//! #         name: name.to_string(),
+//! #     }
+//! # }
+//! # let descriptors = vec![descriptor("notes.txt", 5)];
+//! // descriptors = FileDescriptor::parse_list(&file_group_descriptor)?
+//! let mut transfer = FileTransfer::new(&descriptors);
+//!
+//! let events = transfer.start()?;
+//! let FileTransferEvent::Request(request) = &events[0] else { unreachable!() };
+//! assert_eq!(request.kind, FileContentsKind::Range { position: 0, size: 5 });
+//!
+//! // The peer answers with the bytes
+//! let events = transfer.on_response(request.stream_id, b"hello".to_vec(), false)?;
+//! assert!(matches!(&events[0], FileTransferEvent::FileReceived { path, .. } if path == "notes.txt"));
+//! assert_eq!(events[1], FileTransferEvent::Completed);
+//! # Ok::<(), lamco_clipboard_core::ClipboardError>(())
+//! ```
+
+use crate::clock::{Clock, SystemClock};
+#[cfg(feature = "compression")]
+use crate::compression::{self, CompressionConfig};
+use crate::file_source::AnnouncedFile;
+use crate::flow::{RateLimiter, DEFAULT_BURST_BYTES, DEFAULT_MAX_OUTSTANDING_REQUESTS};
+use crate::formats::FileDescriptor;
+use crate::guard::{ClipDataLock, TempFileGuard};
+use crate::metrics::TransferRecorder;
+use crate::sanitize::sanitize_filename_for_linux;
+use crate::sink::ClipboardSink;
+use crate::transfer::{TransferState, DEFAULT_CHUNK_SIZE};
+use crate::{ClipboardError, ClipboardResult, TransferError, TransferErrorKind};
+use std::collections::{BTreeMap, HashMap, HashSet, VecDeque};
+use std::fmt;
+use std::path::Path;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex, MutexGuard};
+use std::time::Duration;
+#[cfg(feature = "tokio")]
+use tokio::sync::watch;
+use xxhash_rust::xxh3::{xxh3_64, Xxh3};
+
+/// Default largest file kept in memory until it is complete: 256 MiB
+pub const DEFAULT_MAX_FILE_SIZE: u64 = 256 * 1024 * 1024;
+
+/// Default time to wait for a File Contents Response: 10 seconds
+pub const DEFAULT_CHUNK_TIMEOUT_MS: u64 = 10_000;
+
+/// Default number of times an unanswered request is sent again
+pub const DEFAULT_MAX_RETRIES: u32 = 3;
+
+/// Default wait before the first retry: 500 ms
+pub const DEFAULT_RETRY_BACKOFF_MS: u64 = 500;
+
+/// Registered format name carrying [`FileChecksums`]
+pub const FILE_CHECKSUMS_FORMAT_NAME: &str = "LamcoFileChecksums";
+
+/// Default memory for chunks shared between files with the same contents: 16 MiB
+pub const DEFAULT_CHUNK_CACHE_BYTES: usize = 16 * 1024 * 1024;
+
+/// Windows FILE_ATTRIBUTE_DIRECTORY
+pub(crate) const FILE_ATTRIBUTE_DIRECTORY: u32 = 0x10;
+
+/// What a File Contents Request asks for
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "serde", serde(rename_all = "snake_case"))]
+pub enum FileContentsKind {
+    /// The size of the file (FILECONTENTS_SIZE), answered with 8 bytes
+    Size,
+    /// A byte range of the file (FILECONTENTS_RANGE)
+    Range {
+        /// Offset of the first byte
+        position: u64,
+        /// Number of bytes
+        size: u32,
+    },
+}
+
+impl FileContentsKind {
+    /// Offset of the first byte asked for; 0 for a size request
+    pub fn position(&self) -> u64 {
+        match self {
+            Self::Size => 0,
+            Self::Range { position, .. } => *position,
+        }
+    }
+}
+
+/// A File Contents Request PDU
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct FileContentsRequest {
+    /// ID the response will carry
+    pub stream_id: u32,
+
+    /// Index of the file in the FileGroupDescriptorW
+    pub file_index: u32,
+
+    /// Size or range
+    pub kind: FileContentsKind,
+
+    /// Clipboard data lock the request refers to, if any
+    pub clip_data_id: Option<u32>,
+}
+
+/// Limits for a [`FileTransfer`]
+#[derive(Debug, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "serde", serde(default))]
+pub struct FileTransferConfig {
+    /// Bytes asked for per range request (default: 64 KiB)
+    pub chunk_size: u32,
+
+    /// Requests awaiting a response at once (default: 4)
+    pub max_outstanding_requests: usize,
+
+    /// Largest file accepted, since each is held in memory until complete
+    /// (default: 256 MiB)
+    pub max_file_size: u64,
+
+    /// Bytes per second requested from the peer (default: None, unlimited)
+    pub max_bytes_per_second: Option<u64>,
+
+    /// Bytes that may be requested at once after being idle (default: 1 MiB)
+    pub burst_bytes: u64,
+
+    /// Bytes of received files awaiting [`FileTransfer::file_written`]
+    /// before requests pause (default: None, no limit)
+    pub max_unwritten_bytes: Option<u64>,
+
+    /// Milliseconds to wait for a response before sending the request
+    /// again (default: 10 000; None waits forever)
+    pub chunk_timeout_ms: Option<u64>,
+
+    /// Times an unanswered request is sent again before the transfer fails
+    /// (default: 3)
+    pub max_retries: u32,
+
+    /// Milliseconds before the first retry, doubled for each further one
+    /// (default: 500)
+    pub retry_backoff_ms: u64,
+
+    /// Milliseconds the whole transfer may take (default: None, no limit)
+    pub transfer_timeout_ms: Option<u64>,
+}
+
+impl Default for FileTransferConfig {
+    fn default() -> Self {
+        Self {
+            chunk_size: DEFAULT_CHUNK_SIZE as u32,
+            max_outstanding_requests: DEFAULT_MAX_OUTSTANDING_REQUESTS,
+            max_file_size: DEFAULT_MAX_FILE_SIZE,
+            max_bytes_per_second: None,
+            burst_bytes: DEFAULT_BURST_BYTES,
+            max_unwritten_bytes: None,
+            chunk_timeout_ms: Some(DEFAULT_CHUNK_TIMEOUT_MS),
+            max_retries: DEFAULT_MAX_RETRIES,
+            retry_backoff_ms: DEFAULT_RETRY_BACKOFF_MS,
+            transfer_timeout_ms: None,
+        }
+    }
+}
+
+/// Something for the host to do
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "serde", serde(rename_all = "snake_case"))]
+pub enum FileTransferEvent {
+    /// Send this request to the peer
+    Request(FileContentsRequest),
+
+    /// All bytes of a file arrived
+    FileReceived {
+        /// Index of the file in the FileGroupDescriptorW
+        index: u32,
+        /// Relative path, with `/` separators and sanitized components
+        path: String,
+        /// File contents
+        data: Vec<u8>,
+    },
+
+    /// Every file arrived
+    Completed,
+}
+
+/// Progress of one file
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct FileProgress {
+    /// Index of the file in the FileGroupDescriptorW
+    pub index: u32,
+
+    /// Relative path, as in [`FileTransferEvent::FileReceived`]
+    pub path: String,
+
+    /// Bytes received so far
+    pub bytes_done: u64,
+
+    /// Size of the file; `None` until the peer has reported it
+    pub total_bytes: Option<u64>,
+}
+
+/// Progress of a whole [`FileTransfer`]
+#[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct FileTransferProgress {
+    /// Current state
+    pub state: TransferState,
+
+    /// File the latest response belonged to
+    pub file: Option<FileProgress>,
+
+    /// Files received so far, directories included
+    pub files_done: usize,
+
+    /// Number of entries in the FileGroupDescriptorW
+    pub file_count: usize,
+
+    /// Bytes received so far
+    pub bytes_done: u64,
+
+    /// Total bytes of the files whose size is known
+    pub total_bytes: u64,
+
+    /// Average rate since the transfer started
+    pub bytes_per_second: Option<f64>,
+
+    /// Estimated time remaining in milliseconds; `None` until the rate and
+    /// every file size are known
+    pub eta_ms: Option<u64>,
+}
+
+impl FileTransferProgress {
+    /// Get completion percentage (0.0 - 100.0)
+    pub fn percentage(&self) -> f64 {
+        if self.total_bytes == 0 {
+            return 100.0;
+        }
+        (self.bytes_done as f64 / self.total_bytes as f64) * 100.0
+    }
+}
+
+/// Cooperative cancellation for a [`FileTransfer`]
+///
+/// Clones share the same flag, so the UI can keep one while the transfer
+/// runs elsewhere. The transfer notices on its next call, or when
+/// [`FileTransfer::cancel`] is called directly.
+#[derive(Debug, Clone, Default)]
+pub struct CancellationToken {
+    cancelled: Arc<AtomicBool>,
+}
+
+impl CancellationToken {
+    /// Create a token that is not cancelled
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Request cancellation
+    pub fn cancel(&self) {
+        self.cancelled.store(true, Ordering::Release);
+    }
+
+    /// Whether cancellation was requested
+    pub fn is_cancelled(&self) -> bool {
+        self.cancelled.load(Ordering::Acquire)
+    }
+}
+
+/// State of a [`FileTransfer`] saved across a reconnect
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "serde", serde(default))]
+pub struct TransferManifest {
+    /// Clipboard data lock the requests referred to
+    pub clip_data_id: Option<u32>,
+
+    /// One entry per FileGroupDescriptorW entry, in order
+    pub files: Vec<ManifestEntry>,
+}
+
+/// One file in a [`TransferManifest`]
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct ManifestEntry {
+    /// Index of the file in the FileGroupDescriptorW
+    pub index: u32,
+
+    /// Relative path, as in [`FileTransferEvent::FileReceived`]
+    pub path: String,
+
+    /// Size of the file, if known
+    pub size: Option<u64>,
+
+    /// Length of the prefix received without gaps
+    pub verified_offset: u64,
+
+    /// xxh3 checksum of the first `verified_offset` bytes
+    pub checksum: u64,
+
+    /// The file was received and handed to the sink
+    pub complete: bool,
+}
+
+/// xxh3 state over the bytes of a file received so far
+#[derive(Clone, Default)]
+struct RollingChecksum(Xxh3);
+
+impl fmt::Debug for RollingChecksum {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "RollingChecksum({:#018x})", self.0.digest())
+    }
+}
+
+/// One entry of the FileGroupDescriptorW being received
+#[derive(Debug)]
+struct IncomingFile {
+    path: String,
+    is_directory: bool,
+    /// Announced or reported size; `None` until a size request is answered
+    size: Option<u64>,
+    size_requested: bool,
+    /// Next offset to request
+    requested: u64,
+    /// Bytes received in order, and ranges that arrived early
+    data: Vec<u8>,
+    early: BTreeMap<u64, Vec<u8>>,
+    /// Checksum of `data`
+    checksum: RollingChecksum,
+    /// Verified offset and checksum from a manifest, until the staged bytes
+    /// are restored
+    resume_point: Option<(u64, u64)>,
+    done: bool,
+}
+
+/// Administrative limits on the files of a copy or paste
+///
+/// Directories do not count towards the file count and are never denied by
+/// extension. Files whose size is not known yet count as empty until it is.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "serde", serde(default))]
+pub struct FileTransferPolicy {
+    /// Largest single file in bytes (default: None, no limit)
+    pub max_file_size: Option<u64>,
+
+    /// Largest total of all files in bytes (default: None, no limit)
+    pub max_total_bytes: Option<u64>,
+
+    /// Most files (default: None, no limit)
+    pub max_file_count: Option<usize>,
+
+    /// Extensions refused, compared case-insensitively with or without the
+    /// leading dot (default: none)
+    pub denied_extensions: Vec<String>,
+}
+
+impl FileTransferPolicy {
+    /// Whether no limit is set
+    pub fn is_unrestricted(&self) -> bool {
+        self.max_file_size.is_none()
+            && self.max_total_bytes.is_none()
+            && self.max_file_count.is_none()
+            && self.denied_extensions.is_empty()
+    }
+
+    /// Check the files of a FileGroupDescriptorW before pasting them
+    pub fn check_descriptors(&self, descriptors: &[FileDescriptor]) -> Result<(), TransferError> {
+        let paths: Vec<_> = descriptors
+            .iter()
+            .map(|descriptor| local_path(&descriptor.name))
+            .collect();
+        let entries: Vec<_> = descriptors
+            .iter()
+            .zip(&paths)
+            .map(|(descriptor, path)| {
+                let is_directory = descriptor.attributes & FILE_ATTRIBUTE_DIRECTORY != 0;
+                (path.as_str(), descriptor.size, is_directory)
+            })
+            .collect();
+        self.check(&entries)
+    }
+
+    /// Check local files before serving them
+    pub fn check_files(&self, files: &[AnnouncedFile]) -> Result<(), TransferError> {
+        let paths: Vec<_> = files.iter().map(|file| file.path().display().to_string()).collect();
+        let entries: Vec<_> = files
+            .iter()
+            .zip(&paths)
+            .map(|(file, path)| (path.as_str(), Some(file.size()), file.is_directory()))
+            .collect();
+        self.check(&entries)
+    }
+
+    /// Whether the extension of `path` is denied
+    pub fn is_denied(&self, path: &str) -> bool {
+        let Some(extension) = Path::new(path).extension().and_then(|extension| extension.to_str()) else {
+            return false;
+        };
+        self.denied_extensions
+            .iter()
+            .any(|denied| denied.trim_start_matches('.').eq_ignore_ascii_case(extension))
+    }
+
+    /// Check `(path, size, is_directory)` entries in file list order
+    fn check(&self, entries: &[(&str, Option<u64>, bool)]) -> Result<(), TransferError> {
+        let mut total = 0u64;
+        let mut count = 0usize;
+        for (index, &(path, size, is_directory)) in entries.iter().enumerate() {
+            if is_directory {
+                continue;
+            }
+            let reject = |kind| {
+                Err(TransferError {
+                    kind,
+                    index: index as u32,
+                    path: path.to_string(),
+                    offset: 0,
+                })
+            };
+            count += 1;
+            if self.max_file_count.is_some_and(|max| count > max) {
+                return reject(TransferErrorKind::TooManyFiles);
+            }
+            if self.is_denied(path) {
+                return reject(TransferErrorKind::ExtensionDenied);
+            }
+            let size = size.unwrap_or(0);
+            if self.max_file_size.is_some_and(|max| size > max) {
+                return reject(TransferErrorKind::FileTooLarge);
+            }
+            total = total.saturating_add(size);
+            if self.max_total_bytes.is_some_and(|max| total > max) {
+                return reject(TransferErrorKind::TotalSizeExceeded);
+            }
+        }
+        Ok(())
+    }
+}
+
+/// xxh3 checksums of the files of a copy, in FileGroupDescriptorW order
+///
+/// The [`FILE_CHECKSUMS_FORMAT_NAME`] payload is a u32 count followed by one
+/// u64 checksum per file, all little-endian. Directories carry the checksum
+/// of no bytes.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct FileChecksums {
+    checksums: Vec<u64>,
+}
+
+impl FileChecksums {
+    /// Checksums in file order
+    pub fn new(checksums: Vec<u64>) -> Self {
+        Self { checksums }
+    }
+
+    /// Hash announced files up to their announced size
+    ///
+    /// Reads every file in full, so compute this when the peer asks for the
+    /// format rather than on every copy.
+    pub fn from_files(files: &[AnnouncedFile]) -> ClipboardResult<Self> {
+        let checksums = files.iter().map(content_hash).collect::<ClipboardResult<_>>()?;
+        Ok(Self { checksums })
+    }
+
+    /// Parse a [`FILE_CHECKSUMS_FORMAT_NAME`] payload
+    pub fn parse(data: &[u8]) -> ClipboardResult<Self> {
+        let Some(count) = data
+            .get(..4)
+            .and_then(|bytes| bytes.try_into().ok())
+            .map(u32::from_le_bytes)
+        else {
+            return Err(ClipboardError::FormatConversion(
+                "file checksums shorter than 4 bytes".to_string(),
+            ));
+        };
+        let (count, body) = (count as usize, &data[4..]);
+        if count > body.len() / 8 {
+            return Err(ClipboardError::FormatConversion(format!(
+                "file checksums claim {} entries in {} bytes",
+                count,
+                body.len()
+            )));
+        }
+        let checksums = body
+            .chunks_exact(8)
+            .take(count)
+            .map(|bytes| u64::from_le_bytes(bytes.try_into().expect("chunks_exact yields 8 bytes")))
+            .collect();
+        Ok(Self { checksums })
+    }
+
+    /// Encode as a [`FILE_CHECKSUMS_FORMAT_NAME`] payload
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut data = Vec::with_capacity(4 + self.checksums.len() * 8);
+        data.extend_from_slice(&(self.checksums.len() as u32).to_le_bytes());
+        for checksum in &self.checksums {
+            data.extend_from_slice(&checksum.to_le_bytes());
+        }
+        data
+    }
+
+    /// Checksum of the file with `index`
+    pub fn get(&self, index: u32) -> Option<u64> {
+        self.checksums.get(index as usize).copied()
+    }
+
+    /// Number of files
+    pub fn len(&self) -> usize {
+        self.checksums.len()
+    }
+
+    /// Whether there are no checksums
+    pub fn is_empty(&self) -> bool {
+        self.checksums.is_empty()
+    }
+}
+
+/// A request awaiting its response
+#[derive(Debug, Clone, Copy)]
+struct PendingRequest {
+    request: FileContentsRequest,
+    /// Times it was sent
+    attempts: u32,
+    /// Clock time it was first sent
+    first_sent_at: Duration,
+    /// Clock time it was last sent
+    sent_at: Duration,
+    /// Clock time to send it again, after a timeout
+    retry_at: Option<Duration>,
+}
+
+impl IncomingFile {
+    fn bytes_done(&self) -> u64 {
+        if self.done {
+            self.size.unwrap_or(0)
+        } else {
+            self.data.len() as u64 + self.early.values().map(|chunk| chunk.len() as u64).sum::<u64>()
+        }
+    }
+}
+
+/// Receiving side of a file paste.
+///
+/// Files are requested in FileGroupDescriptorW order. Directories need no
+/// requests, and files whose descriptor carries no size are asked for it
+/// first.
+#[derive(Debug)]
+pub struct FileTransfer {
+    config: FileTransferConfig,
+    clip_data_id: Option<u32>,
+    files: Vec<IncomingFile>,
+    /// First file that may still need requests
+    cursor: usize,
+    /// Outstanding requests by stream ID
+    pending: HashMap<u32, PendingRequest>,
+    /// Late answers still expected to retried requests that were answered
+    late_responses: HashMap<u32, u32>,
+    /// Clock time of the latest response
+    last_response_at: Option<Duration>,
+    /// Requests sent again after a timeout
+    retried: u64,
+    /// Checksums from the peer to verify complete files against
+    checksums: Option<FileChecksums>,
+    policy: FileTransferPolicy,
+    /// Why the transfer failed, when the failure is tied to a file
+    failure: Option<TransferError>,
+    next_stream_id: u32,
+    state: TransferState,
+    /// File the latest response belonged to
+    last_file: Option<usize>,
+    clock: Arc<dyn Clock>,
+    /// Transfer start time, in clock time
+    started_at: Option<Duration>,
+    cancellation: CancellationToken,
+    /// Staging files deleted if the transfer does not complete
+    temp_files: Vec<TempFileGuard>,
+    /// Lock on the peer's clipboard data, held until the transfer ends
+    lock: Option<ClipDataLock>,
+    limiter: Option<RateLimiter>,
+    /// Clock time when pacing lets the next request go
+    paced_until: Option<Duration>,
+    /// Sizes of received files the host has not confirmed as written
+    unwritten: BTreeMap<u32, u64>,
+    metrics: Option<TransferRecorder>,
+    /// Range responses arrive as compression frames
+    #[cfg(feature = "compression")]
+    compressed: bool,
+    #[cfg(feature = "tokio")]
+    progress_tx: Option<watch::Sender<FileTransferProgress>>,
+}
+
+impl FileTransfer {
+    /// Prepare to receive the files of a FileGroupDescriptorW
+    pub fn new(descriptors: &[FileDescriptor]) -> Self {
+        let files = descriptors
+            .iter()
+            .map(|descriptor| {
+                let is_directory = descriptor.attributes & FILE_ATTRIBUTE_DIRECTORY != 0;
+                IncomingFile {
+                    path: local_path(&descriptor.name),
+                    is_directory,
+                    size: if is_directory { Some(0) } else { descriptor.size },
+                    size_requested: false,
+                    requested: 0,
+                    data: Vec::new(),
+                    early: BTreeMap::new(),
+                    checksum: RollingChecksum::default(),
+                    resume_point: None,
+                    done: false,
+                }
+            })
+            .collect();
+
+        Self {
+            config: FileTransferConfig::default(),
+            clip_data_id: None,
+            files,
+            cursor: 0,
+            pending: HashMap::new(),
+            late_responses: HashMap::new(),
+            last_response_at: None,
+            retried: 0,
+            checksums: None,
+            policy: FileTransferPolicy::default(),
+            failure: None,
+            next_stream_id: 1,
+            state: TransferState::Pending,
+            last_file: None,
+            clock: Arc::new(SystemClock),
+            started_at: None,
+            cancellation: CancellationToken::new(),
+            temp_files: Vec::new(),
+            lock: None,
+            limiter: None,
+            paced_until: None,
+            unwritten: BTreeMap::new(),
+            metrics: None,
+            #[cfg(feature = "compression")]
+            compressed: false,
+            #[cfg(feature = "tokio")]
+            progress_tx: None,
+        }
+    }
+
+    /// Continue a transfer saved with [`manifest()`](Self::manifest)
+    ///
+    /// `descriptors` is the FileGroupDescriptorW announced after the
+    /// reconnect; it must list the same files with the same sizes, or the
+    /// peer's clipboard changed meanwhile and the manifest is stale. Files
+    /// that were complete are not requested again. Unfinished files start
+    /// over unless their staged bytes are handed to
+    /// [`restore_partial()`](Self::restore_partial) before
+    /// [`start()`](Self::start). The clipboard data lock is usually new after
+    /// a reconnect; set it with [`with_clip_data_id()`](Self::with_clip_data_id).
+    pub fn resume(descriptors: &[FileDescriptor], manifest: &TransferManifest) -> ClipboardResult<Self> {
+        let mut transfer = Self::new(descriptors);
+        transfer.clip_data_id = manifest.clip_data_id;
+
+        if manifest.files.len() != transfer.files.len() {
+            return Err(ClipboardError::InvalidState(format!(
+                "manifest lists {} files, descriptor list {}",
+                manifest.files.len(),
+                transfer.files.len()
+            )));
+        }
+        for (file, entry) in transfer.files.iter_mut().zip(&manifest.files) {
+            if file.path != entry.path {
+                return Err(ClipboardError::InvalidState(format!(
+                    "manifest entry {} is {}, descriptor is {}",
+                    entry.index, entry.path, file.path
+                )));
+            }
+            if let (Some(announced), Some(saved)) = (file.size, entry.size) {
+                if announced != saved {
+                    return Err(ClipboardError::FileChanged {
+                        path: file.path.clone(),
+                        announced: saved,
+                        actual: announced,
+                    });
+                }
+            }
+            file.size = file.size.or(entry.size);
+            if entry.complete {
+                file.done = true;
+            } else if entry.verified_offset > 0 {
+                file.resume_point = Some((entry.verified_offset, entry.checksum));
+            }
+        }
+        Ok(transfer)
+    }
+
+    /// Restore the staged bytes of an unfinished file after
+    /// [`resume()`](Self::resume)
+    ///
+    /// `data` must start with the manifest's verified prefix; anything past
+    /// it is dropped. Requests for the file continue from the verified
+    /// offset.
+    pub fn restore_partial(&mut self, index: u32, mut data: Vec<u8>) -> ClipboardResult<()> {
+        if self.state != TransferState::Pending {
+            return Err(ClipboardError::InvalidState(format!(
+                "cannot restore data into a {:?} transfer",
+                self.state
+            )));
+        }
+        let file = self
+            .files
+            .get_mut(index as usize)
+            .ok_or_else(|| ClipboardError::InvalidState(format!("no file with index {}", index)))?;
+        let Some((offset, checksum)) = file.resume_point else {
+            return Err(ClipboardError::InvalidState(format!(
+                "{} has no verified data to restore",
+                file.path
+            )));
+        };
+
+        if (data.len() as u64) < offset || xxh3_64(&data[..offset as usize]) != checksum {
+            return Err(ClipboardError::FileChanged {
+                path: file.path.clone(),
+                announced: offset,
+                actual: data.len() as u64,
+            });
+        }
+        data.truncate(offset as usize);
+        file.checksum.0.update(&data);
+        file.data = data;
+        file.requested = offset;
+        file.resume_point = None;
+        Ok(())
+    }
+
+    /// Save the transfer state for [`resume()`](Self::resume)
+    pub fn manifest(&self) -> TransferManifest {
+        TransferManifest {
+            clip_data_id: self.clip_data_id,
+            files: self
+                .files
+                .iter()
+                .enumerate()
+                .map(|(index, file)| {
+                    let (verified_offset, checksum) = if file.done {
+                        (file.size.unwrap_or(0), 0)
+                    } else {
+                        (file.data.len() as u64, file.checksum.0.digest())
+                    };
+                    ManifestEntry {
+                        index: index as u32,
+                        path: file.path.clone(),
+                        size: file.size,
+                        verified_offset,
+                        checksum,
+                        complete: file.done,
+                    }
+                })
+                .collect(),
+        }
+    }
+
+    /// Bytes of an unfinished file received without gaps, to stage next to
+    /// the [`manifest()`](Self::manifest)
+    pub fn partial_data(&self, index: u32) -> Option<&[u8]> {
+        self.files
+            .get(index as usize)
+            .filter(|file| !file.done)
+            .map(|file| file.data.as_slice())
+    }
+
+    /// Read time from `clock` instead of the system clock
+    pub fn with_clock(mut self, clock: impl Clock + 'static) -> Self {
+        self.clock = Arc::new(clock);
+        self
+    }
+
+    /// Stop when `token` is cancelled
+    pub fn with_cancellation(mut self, token: CancellationToken) -> Self {
+        self.cancellation = token;
+        self
+    }
+
+    /// Token that cancels this transfer
+    pub fn cancellation_token(&self) -> &CancellationToken {
+        &self.cancellation
+    }
+
+    /// Delete a staging file if the transfer is cancelled or fails
+    ///
+    /// For hosts whose sink writes received files to a temporary location
+    /// first. Once the transfer completes, [`take_temp_files`](Self::take_temp_files)
+    /// hands the guards back.
+    pub fn hold_temp_file(&mut self, guard: TempFileGuard) {
+        self.temp_files.push(guard);
+    }
+
+    /// Take back the staging files held by the transfer
+    pub fn take_temp_files(&mut self) -> Vec<TempFileGuard> {
+        std::mem::take(&mut self.temp_files)
+    }
+
+    /// Cancel the transfer
+    ///
+    /// Returns the requests still awaiting a response; CLIPRDR cannot
+    /// withdraw them, so their responses are simply rejected when they
+    /// arrive. Does nothing once the transfer has finished.
+    pub fn cancel(&mut self) -> Vec<FileContentsRequest> {
+        self.cancellation.cancel();
+        if !self.state.is_active() {
+            return Vec::new();
+        }
+
+        tracing::debug!(
+            "File transfer cancelled with {} requests outstanding",
+            self.pending.len()
+        );
+        self.finish(TransferState::Cancelled, Some(&ClipboardError::TransferCancelled));
+        let mut aborted: Vec<_> = self.pending.drain().map(|(_, pending)| pending.request).collect();
+        aborted.sort_by_key(|request| request.stream_id);
+        self.release();
+        self.publish_progress();
+        aborted
+    }
+
+    /// Cancel if the token was cancelled since the last call
+    fn check_cancelled(&mut self) -> ClipboardResult<()> {
+        if self.state == TransferState::Cancelled {
+            return Err(ClipboardError::TransferCancelled);
+        }
+        if self.cancellation.is_cancelled() {
+            self.cancel();
+            return Err(ClipboardError::TransferCancelled);
+        }
+        Ok(())
+    }
+
+    /// Drop buffered data and staging files
+    fn release(&mut self) {
+        for file in &mut self.files {
+            file.data = Vec::new();
+            file.early.clear();
+            file.checksum = RollingChecksum::default();
+        }
+        self.temp_files.clear();
+        self.lock = None;
+    }
+
+    /// Set the chunk size and limits
+    pub fn with_config(mut self, config: FileTransferConfig) -> Self {
+        self.config = config;
+        self
+    }
+
+    /// Refuse the paste if its files break `policy`
+    ///
+    /// Checked when the transfer starts and again whenever a size request
+    /// is answered; a violation fails the transfer without requesting more.
+    pub fn with_policy(mut self, policy: FileTransferPolicy) -> Self {
+        self.policy = policy;
+        self
+    }
+
+    /// Report bytes, round trips and the outcome into `metrics`
+    pub fn with_metrics(mut self, metrics: TransferRecorder) -> Self {
+        self.metrics = Some(metrics);
+        self
+    }
+
+    /// Expect range responses as compression frames
+    ///
+    /// Only after opting in with
+    /// [`ClipboardSession::request_file_compression`](crate::protocol::ClipboardSession::request_file_compression);
+    /// a frame that does not decompress to the requested size fails the
+    /// transfer.
+    #[cfg(feature = "compression")]
+    pub fn with_compression(mut self) -> Self {
+        self.compressed = true;
+        self
+    }
+
+    /// Why the transfer failed, for failures tied to a file: error
+    /// responses, timeouts, checksum mismatches and policy rejections
+    pub fn failure(&self) -> Option<&TransferError> {
+        self.failure.as_ref()
+    }
+
+    /// Verify every complete file against the peer's checksums
+    ///
+    /// A file that does not match fails the transfer with
+    /// [`TransferErrorKind::ChecksumMismatch`] instead of being reported
+    /// received. Files past the end of the list are not verified.
+    pub fn with_checksums(mut self, checksums: FileChecksums) -> Self {
+        if checksums.len() != self.files.len() {
+            tracing::warn!(
+                "{} file checksums for {} files, verifying those present",
+                checksums.len(),
+                self.files.len()
+            );
+        }
+        self.checksums = Some(checksums);
+        self
+    }
+
+    /// Refer requests to a clipboard data lock held on the peer
+    pub fn with_clip_data_id(mut self, clip_data_id: u32) -> Self {
+        self.clip_data_id = Some(clip_data_id);
+        self
+    }
+
+    /// Refer requests to `lock` and hold it until the transfer completes,
+    /// fails or is cancelled
+    ///
+    /// Dropping the lock runs the [`ClipDataLocks`](crate::ClipDataLocks)
+    /// release callback, which sends the Unlock Clipboard Data PDU.
+    pub fn with_lock(mut self, lock: ClipDataLock) -> Self {
+        self.clip_data_id = Some(lock.clip_data_id());
+        self.lock = Some(lock);
+        self
+    }
+
+    /// Clipboard data lock the requests refer to
+    pub fn clip_data_id(&self) -> Option<u32> {
+        self.clip_data_id
+    }
+
+    /// Current state
+    pub fn state(&self) -> TransferState {
+        self.state
+    }
+
+    /// Requests awaiting a response
+    pub fn outstanding(&self) -> usize {
+        self.pending.len()
+    }
+
+    /// Requests sent again after going unanswered
+    pub fn retried_requests(&self) -> u64 {
+        self.retried
+    }
+
+    /// Total bytes of the files whose size is known
+    pub fn total_bytes(&self) -> u64 {
+        self.files.iter().filter_map(|file| file.size).sum()
+    }
+
+    /// Bytes received so far
+    pub fn received_bytes(&self) -> u64 {
+        self.files.iter().map(IncomingFile::bytes_done).sum()
+    }
+
+    /// Progress of the file with `index`
+    pub fn file_progress(&self, index: u32) -> Option<FileProgress> {
+        let file = self.files.get(index as usize)?;
+        Some(FileProgress {
+            index,
+            path: file.path.clone(),
+            bytes_done: file.bytes_done(),
+            total_bytes: file.size,
+        })
+    }
+
+    /// Snapshot of the overall progress
+    pub fn progress(&self) -> FileTransferProgress {
+        let bytes_done = self.received_bytes();
+        let total_bytes = self.total_bytes();
+        let elapsed = self
+            .started_at
+            .map(|started| self.clock.now().saturating_sub(started).as_secs_f64())
+            .filter(|elapsed| *elapsed > 0.0);
+        let bytes_per_second = elapsed.map(|elapsed| bytes_done as f64 / elapsed);
+        let sizes_known = self.files.iter().all(|file| file.size.is_some());
+        let eta_ms = bytes_per_second
+            .filter(|rate| *rate > 0.0 && sizes_known)
+            .map(|rate| ((total_bytes.saturating_sub(bytes_done)) as f64 / rate * 1000.0) as u64);
+
+        FileTransferProgress {
+            state: self.state,
+            file: self.last_file.and_then(|index| self.file_progress(index as u32)),
+            files_done: self.files.iter().filter(|file| file.done).count(),
+            file_count: self.files.len(),
+            bytes_done,
+            total_bytes,
+            bytes_per_second,
+            eta_ms,
+        }
+    }
+
+    /// Watch the progress, updated whenever the transfer starts, a response
+    /// is processed or the transfer fails
+    #[cfg(feature = "tokio")]
+    pub fn subscribe_progress(&mut self) -> watch::Receiver<FileTransferProgress> {
+        match &self.progress_tx {
+            Some(tx) => tx.subscribe(),
+            None => {
+                let (tx, rx) = watch::channel(self.progress());
+                self.progress_tx = Some(tx);
+                rx
+            }
+        }
+    }
+
+    fn publish_progress(&self) {
+        #[cfg(feature = "tokio")]
+        if let Some(tx) = &self.progress_tx {
+            tx.send_replace(self.progress());
+        }
+    }
+
+    /// Issue the first requests
+    ///
+    /// Empty files and directories are reported right away; a descriptor
+    /// list without any data completes immediately. Fails, like
+    /// [`on_response()`](Self::on_response), if the transfer was cancelled,
+    /// the files break the policy or an empty file fails its checksum.
+    pub fn start(&mut self) -> ClipboardResult<Vec<FileTransferEvent>> {
+        if self.state != TransferState::Pending {
+            return Ok(Vec::new());
+        }
+        self.check_cancelled()?;
+        self.state = TransferState::InProgress;
+        self.started_at = Some(self.clock.now());
+        if let Some(metrics) = &self.metrics {
+            metrics.transfer_started();
+        }
+        self.limiter = self
+            .config
+            .max_bytes_per_second
+            .map(|rate| RateLimiter::new(rate, self.config.burst_bytes).with_clock(Arc::clone(&self.clock)));
+        self.check_policy()?;
+        let events = self.advance()?;
+        self.publish_progress();
+        Ok(events)
+    }
+
+    /// Send timed-out requests again and issue the requests that pacing or
+    /// `max_unwritten_bytes` held back
+    ///
+    /// Call it when [`next_poll()`](Self::next_poll) comes due. Fails with a
+    /// [`TransferError`] once a request goes unanswered through
+    /// `max_retries` retries or the transfer runs past
+    /// `transfer_timeout_ms`.
+    pub fn poll(&mut self) -> ClipboardResult<Vec<FileTransferEvent>> {
+        self.check_cancelled()?;
+        if self.state != TransferState::InProgress {
+            return Ok(Vec::new());
+        }
+        let mut events = self.retry_timed_out()?;
+        events.extend(self.advance()?);
+        self.publish_progress();
+        Ok(events)
+    }
+
+    /// Confirm that the file from a [`FileTransferEvent::FileReceived`] was
+    /// written, releasing requests held back by `max_unwritten_bytes`
+    ///
+    /// [`drive`] calls this after each write. Fails if the transfer was
+    /// cancelled or a file completed by now fails its checksum.
+    pub fn file_written(&mut self, index: u32) -> ClipboardResult<Vec<FileTransferEvent>> {
+        if self.unwritten.remove(&index).is_none() || self.state != TransferState::InProgress {
+            return Ok(Vec::new());
+        }
+        self.check_cancelled()?;
+        let events = self.advance()?;
+        self.publish_progress();
+        Ok(events)
+    }
+
+    /// Bytes of received files awaiting [`file_written()`](Self::file_written)
+    pub fn unwritten_bytes(&self) -> u64 {
+        self.unwritten.values().sum()
+    }
+
+    /// Time until [`poll()`](Self::poll) is due: when pacing lets more
+    /// requests go, a request times out or a retry is to be sent
+    pub fn next_poll(&self) -> Option<Duration> {
+        if self.state != TransferState::InProgress {
+            return None;
+        }
+        let chunk_timeout = self.config.chunk_timeout_ms.map(Duration::from_millis);
+        let retries = self.pending.values().filter_map(|pending| match pending.retry_at {
+            Some(at) => Some(at),
+            None => chunk_timeout.map(|timeout| pending.sent_at + timeout),
+        });
+        self.paced_until
+            .into_iter()
+            .chain(retries)
+            .chain(self.transfer_deadline())
+            .min()
+            .map(|at| at.saturating_sub(self.clock.now()))
+    }
+
+    /// Clock time the transfer times out
+    fn transfer_deadline(&self) -> Option<Duration> {
+        Some(self.started_at? + Duration::from_millis(self.config.transfer_timeout_ms?))
+    }
+
+    /// Schedule retries for requests that timed out and send those that are
+    /// due
+    fn retry_timed_out(&mut self) -> ClipboardResult<Vec<FileTransferEvent>> {
+        let now = self.clock.now();
+        if self.transfer_deadline().is_some_and(|deadline| now >= deadline) {
+            let index = self.files.iter().position(|file| !file.done).unwrap_or(0);
+            let offset = self.files[index].data.len() as u64;
+            return Err(self.fail_at(TransferErrorKind::TransferTimeout, index, offset));
+        }
+        let Some(timeout) = self.config.chunk_timeout_ms.map(Duration::from_millis) else {
+            return Ok(Vec::new());
+        };
+
+        let mut stream_ids: Vec<_> = self.pending.keys().copied().collect();
+        stream_ids.sort_unstable();
+        let mut events = Vec::new();
+        for stream_id in stream_ids {
+            let pending = self.pending[&stream_id];
+            let retry_at = match pending.retry_at {
+                Some(at) => at,
+                None if now >= pending.sent_at + timeout => {
+                    let retries = pending.attempts - 1;
+                    let (index, offset) = (pending.request.file_index as usize, pending.request.kind.position());
+                    // The peer answered something since we first asked: the
+                    // channel is alive and only this chunk is stuck
+                    let peer_alive = self.last_response_at.is_some_and(|at| at >= pending.first_sent_at);
+                    if retries >= self.config.max_retries {
+                        let kind = if peer_alive {
+                            TransferErrorKind::ChunkTimeout
+                        } else {
+                            TransferErrorKind::PeerUnresponsive
+                        };
+                        return Err(self.fail_at(kind, index, offset));
+                    }
+                    let backoff =
+                        Duration::from_millis(self.config.retry_backoff_ms.saturating_mul(1 << retries.min(16)));
+                    tracing::debug!(
+                        "No response for {} at offset {} (stream {}{}), retrying in {:?}",
+                        self.files[index].path,
+                        offset,
+                        stream_id,
+                        if peer_alive { "" } else { ", peer silent" },
+                        backoff
+                    );
+                    now + backoff
+                }
+                None => continue,
+            };
+
+            let pending = self.pending.get_mut(&stream_id).expect("stream ID was just listed");
+            if retry_at > now {
+                pending.retry_at = Some(retry_at);
+                continue;
+            }
+            pending.retry_at = None;
+            pending.sent_at = now;
+            pending.attempts += 1;
+            self.retried += 1;
+            if let Some(metrics) = &self.metrics {
+                metrics.record_retry();
+            }
+            events.push(FileTransferEvent::Request(pending.request));
+        }
+        Ok(events)
+    }
+
+    /// Process the File Contents Response for `stream_id`
+    ///
+    /// A failed response or a short read fails the whole transfer. A late
+    /// answer to a request that was sent again and already answered is
+    /// ignored. Once cancelled, every response is rejected with
+    /// [`ClipboardError::TransferCancelled`].
+    pub fn on_response(
+        &mut self,
+        stream_id: u32,
+        data: Vec<u8>,
+        is_error: bool,
+    ) -> ClipboardResult<Vec<FileTransferEvent>> {
+        self.check_cancelled()?;
+        if let Some(metrics) = &self.metrics {
+            metrics.record_received(data.len());
+        }
+        if let Some(late) = self.late_responses.get_mut(&stream_id) {
+            *late -= 1;
+            if *late == 0 {
+                self.late_responses.remove(&stream_id);
+            }
+            tracing::trace!("Ignoring late response for stream {}", stream_id);
+            return Ok(Vec::new());
+        }
+        if !self.state.is_active() {
+            return Err(ClipboardError::InvalidState(format!(
+                "file transfer not active: {:?}",
+                self.state
+            )));
+        }
+        let Some(pending) = self.pending.remove(&stream_id) else {
+            return Err(ClipboardError::InvalidState(format!(
+                "File Contents Response for unknown stream {}",
+                stream_id
+            )));
+        };
+        let request = pending.request;
+        let now = self.clock.now();
+        if pending.attempts > 1 {
+            self.late_responses.insert(stream_id, pending.attempts - 1);
+        } else if let Some(metrics) = &self.metrics {
+            // A retried request's response may answer any of its attempts,
+            // so only first attempts give a round trip
+            metrics.record_rtt(now.saturating_sub(pending.sent_at));
+        }
+        let index = request.file_index as usize;
+        self.last_file = Some(index);
+        self.last_response_at = Some(now);
+
+        if is_error {
+            return Err(self.fail_at(TransferErrorKind::PeerError, index, request.kind.position()));
+        }
+
+        match request.kind {
+            FileContentsKind::Size => {
+                let Some(size) = data
+                    .get(..8)
+                    .and_then(|bytes| bytes.try_into().ok())
+                    .map(u64::from_le_bytes)
+                else {
+                    return Err(self.fail(ClipboardError::InvalidState(format!(
+                        "size response of {} bytes",
+                        data.len()
+                    ))));
+                };
+                self.files[index].size = Some(size);
+                self.check_policy()?;
+            }
+            FileContentsKind::Range { position, size } => {
+                #[cfg(feature = "compression")]
+                let data = match self.compressed {
+                    true => match compression::decompress(&data, size as usize) {
+                        Ok(data) => data,
+                        Err(error) => return Err(self.fail(error)),
+                    },
+                    false => data,
+                };
+                let file = &self.files[index];
+                let announced = file.size.unwrap_or(0);
+                if data.len() != size as usize {
+                    let error = ClipboardError::FileChanged {
+                        path: file.path.clone(),
+                        announced,
+                        actual: position + data.len() as u64,
+                    };
+                    return Err(self.fail(error));
+                }
+                self.files[index].early.insert(position, data);
+            }
+        }
+
+        let mut events = self.reassemble(index)?;
+        events.extend(self.advance()?);
+        self.publish_progress();
+        Ok(events)
+    }
+
+    /// Move ranges that are now in order into the file, reporting it once
+    /// complete
+    fn reassemble(&mut self, index: usize) -> ClipboardResult<Vec<FileTransferEvent>> {
+        let file = &mut self.files[index];
+        while let Some(chunk) = file.early.remove(&(file.data.len() as u64)) {
+            file.checksum.0.update(&chunk);
+            file.data.extend_from_slice(&chunk);
+        }
+        Ok(self.take_if_complete(index)?.into_iter().collect())
+    }
+
+    /// Report a file once all of its bytes are in, failing the transfer if
+    /// they do not match the peer's checksum
+    fn take_if_complete(&mut self, index: usize) -> ClipboardResult<Option<FileTransferEvent>> {
+        let file = &mut self.files[index];
+        if file.done || file.size != Some(file.data.len() as u64) {
+            return Ok(None);
+        }
+        file.done = true;
+        // Directories carry no data and are created with the files inside
+        if file.is_directory {
+            return Ok(None);
+        }
+        let actual = file.checksum.0.digest();
+        if let Some(expected) = self
+            .checksums
+            .as_ref()
+            .and_then(|checksums| checksums.get(index as u32))
+        {
+            if actual != expected {
+                tracing::warn!(
+                    "{} hashes to {:016x}, peer sent {:016x}",
+                    self.files[index].path,
+                    actual,
+                    expected
+                );
+                return Err(self.fail_at(TransferErrorKind::ChecksumMismatch, index, 0));
+            }
+        }
+        let file = &mut self.files[index];
+        let data = std::mem::take(&mut file.data);
+        self.unwritten.insert(index as u32, data.len() as u64);
+        Ok(Some(FileTransferEvent::FileReceived {
+            index: index as u32,
+            path: file.path.clone(),
+            data,
+        }))
+    }
+
+    /// Issue requests up to the outstanding limit
+    ///
+    /// Fails the transfer, and returns its error, if a file completed by an
+    /// earlier step fails its checksum or is too large.
+    fn advance(&mut self) -> ClipboardResult<Vec<FileTransferEvent>> {
+        let mut events = Vec::new();
+        self.paced_until = None;
+        let max_outstanding = self.config.max_outstanding_requests.max(1);
+        let chunk_size = u64::from(self.config.chunk_size.max(1));
+
+        while self.cursor < self.files.len() && self.pending.len() < max_outstanding {
+            if let Some(limit) = self.config.max_unwritten_bytes {
+                if self.unwritten_bytes() >= limit {
+                    tracing::trace!("Waiting for the sink to write {} bytes", self.unwritten_bytes());
+                    break;
+                }
+            }
+
+            let index = self.cursor;
+            events.extend(self.take_if_complete(index)?);
+
+            let file = &mut self.files[index];
+            let kind = match file.size {
+                _ if file.done => {
+                    self.cursor += 1;
+                    continue;
+                }
+                None if file.size_requested => break,
+                None => {
+                    file.size_requested = true;
+                    FileContentsKind::Size
+                }
+                Some(size) if size > self.config.max_file_size => {
+                    let error = ClipboardError::DataSizeExceeded {
+                        actual: usize::try_from(size).unwrap_or(usize::MAX),
+                        max: usize::try_from(self.config.max_file_size).unwrap_or(usize::MAX),
+                    };
+                    tracing::warn!("Not receiving {}: {}", file.path, error);
+                    return Err(self.fail(error));
+                }
+                // Everything requested; wait for the responses
+                Some(size) if file.requested >= size => {
+                    self.cursor += 1;
+                    continue;
+                }
+                // Window full: wait for the first missing chunk
+                Some(_) if file.requested >= file.data.len() as u64 + max_outstanding as u64 * chunk_size => break,
+                Some(size) => {
+                    let position = file.requested;
+                    let len = (size - position).min(chunk_size) as u32;
+                    if let Some(Err(wait)) = self.limiter.as_ref().map(|limiter| limiter.try_acquire(u64::from(len))) {
+                        self.paced_until = Some(self.clock.now() + wait);
+                        break;
+                    }
+                    file.requested += u64::from(len);
+                    FileContentsKind::Range { position, size: len }
+                }
+            };
+
+            let request = FileContentsRequest {
+                stream_id: self.next_stream_id,
+                file_index: index as u32,
+                kind,
+                clip_data_id: self.clip_data_id,
+            };
+            self.next_stream_id = self.next_stream_id.wrapping_add(1).max(1);
+            let now = self.clock.now();
+            self.pending.insert(
+                request.stream_id,
+                PendingRequest {
+                    request,
+                    attempts: 1,
+                    first_sent_at: now,
+                    sent_at: now,
+                    retry_at: None,
+                },
+            );
+            events.push(FileTransferEvent::Request(request));
+        }
+
+        if self.pending.is_empty() && self.files.iter().all(|file| file.done) && self.state.is_active() {
+            self.finish(TransferState::Completed, None);
+            self.lock = None;
+            events.push(FileTransferEvent::Completed);
+        }
+        Ok(events)
+    }
+
+    /// Enter a final state, counting the outcome if the transfer had started
+    fn finish(&mut self, state: TransferState, error: Option<&ClipboardError>) {
+        let started = self.state == TransferState::InProgress;
+        self.state = state;
+        if let Some(metrics) = self.metrics.as_ref().filter(|_| started) {
+            metrics.transfer_finished(error);
+        }
+    }
+
+    fn fail(&mut self, error: ClipboardError) -> ClipboardError {
+        self.finish(TransferState::Failed, Some(&error));
+        self.pending.clear();
+        self.late_responses.clear();
+        self.release();
+        self.publish_progress();
+        error
+    }
+
+    fn fail_at(&mut self, kind: TransferErrorKind, index: usize, offset: u64) -> ClipboardError {
+        let error = TransferError {
+            kind,
+            index: index as u32,
+            path: self.files[index].path.clone(),
+            offset,
+        };
+        self.fail_with(error)
+    }
+
+    fn fail_with(&mut self, error: TransferError) -> ClipboardError {
+        tracing::warn!("File transfer failed: {}", error);
+        self.failure = Some(error.clone());
+        self.fail(error.into())
+    }
+
+    /// Fail the transfer if the files break the policy
+    fn check_policy(&mut self) -> ClipboardResult<()> {
+        let entries: Vec<_> = self
+            .files
+            .iter()
+            .map(|file| (file.path.as_str(), file.size, file.is_directory))
+            .collect();
+        match self.policy.check(&entries) {
+            Ok(()) => Ok(()),
+            Err(error) => Err(self.fail_with(error)),
+        }
+    }
+}
+
+/// Relative local path for a FILEDESCRIPTORW name
+///
+/// Names use `\` between the folders of a copied directory tree. Empty, `.`
+/// and `..` components are dropped so a name cannot escape the destination.
+pub(crate) fn local_path(name: &str) -> String {
+    name.split(['\\', '/'])
+        .filter(|component| !matches!(*component, "" | "." | ".."))
+        .map(sanitize_filename_for_linux)
+        .collect::<Vec<_>>()
+        .join("/")
+}
+
+/// Carry out `events`: write received files through `sink` and return the
+/// requests to send to the peer
+///
+/// A file the sink fails to write fails the transfer. Nothing more is
+/// written once the transfer is cancelled.
+pub async fn drive<S: ClipboardSink>(
+    transfer: &mut FileTransfer,
+    sink: &S,
+    events: Vec<FileTransferEvent>,
+) -> ClipboardResult<Vec<FileContentsRequest>> {
+    let mut requests = Vec::new();
+    let mut events = VecDeque::from(events);
+    while let Some(event) = events.pop_front() {
+        match event {
+            FileTransferEvent::Request(request) => requests.push(request),
+            FileTransferEvent::FileReceived { index, path, data } => {
+                transfer.check_cancelled()?;
+                if let Err(e) = sink.write_file(&path, data).await {
+                    tracing::warn!("Writing received file {} failed: {}", path, e);
+                    return Err(transfer.fail(e));
+                }
+                events.extend(transfer.file_written(index)?);
+            }
+            FileTransferEvent::Completed => tracing::debug!("File transfer completed"),
+        }
+    }
+    Ok(requests)
+}
+
+/// Answer a peer's File Contents Request from the sink's file list
+///
+/// Size requests are answered with the 8-byte little-endian size from
+/// [`ClipboardSink::get_file_list`], range requests with
+/// [`ClipboardSink::read_file_chunk`].
+pub async fn serve<S: ClipboardSink>(sink: &S, request: &FileContentsRequest) -> ClipboardResult<Vec<u8>> {
+    match request.kind {
+        FileContentsKind::Size => {
+            let files = sink.get_file_list().await?;
+            let file = files
+                .get(request.file_index as usize)
+                .ok_or_else(|| ClipboardError::FileNotFound(format!("file index {}", request.file_index)))?;
+            Ok(file.size.to_le_bytes().to_vec())
+        }
+        FileContentsKind::Range { position, size } => sink.read_file_chunk(request.file_index, position, size).await,
+    }
+}
+
+/// xxh3 of a file's announced contents
+fn content_hash(file: &AnnouncedFile) -> ClipboardResult<u64> {
+    let mut hasher = Xxh3::new();
+    let mut offset = 0;
+    while offset < file.size() {
+        let chunk = file.read_range(offset, DEFAULT_CHUNK_SIZE as u32)?;
+        hasher.update(&chunk);
+        offset += chunk.len() as u64;
+    }
+    Ok(hasher.digest())
+}
+
+/// Counters describing a [`FileServer`]'s chunk cache
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct ChunkCacheMetrics {
+    /// Range requests answered from the cache
+    pub hits: u64,
+
+    /// Range requests for repeated contents that had to be read
+    pub misses: u64,
+
+    /// Chunks currently kept
+    pub chunks: usize,
+
+    /// Total size of the chunks currently kept
+    pub bytes: usize,
+}
+
+/// Content hash, position and size of a cached range
+type ChunkKey = (u64, u64, u32);
+
+/// Ranges of files whose contents appear more than once in a copy, least
+/// recently used first
+#[derive(Debug, Default)]
+struct ChunkCache {
+    max_bytes: usize,
+    state: Mutex<ChunkCacheState>,
+}
+
+#[derive(Debug, Default)]
+struct ChunkCacheState {
+    /// Content hashes of the files hashed so far
+    hashes: HashMap<AnnouncedFile, u64>,
+    chunks: VecDeque<(ChunkKey, Vec<u8>)>,
+    bytes: usize,
+    hits: u64,
+    misses: u64,
+}
+
+impl ChunkCache {
+    fn state(&self) -> MutexGuard<'_, ChunkCacheState> {
+        self.state.lock().unwrap_or_else(|e| e.into_inner())
+    }
+
+    /// Hash of a file's contents, computed once per announced file
+    ///
+    /// Files larger than the cache are only known by the hashes kept from
+    /// the checksums format: hashing one here would read all of it while
+    /// the peer waits for a single range.
+    fn hash(&self, file: &AnnouncedFile) -> ClipboardResult<Option<u64>> {
+        if let Some(&hash) = self.state().hashes.get(file) {
+            return Ok(Some(hash));
+        }
+        if file.size() > self.max_bytes as u64 {
+            return Ok(None);
+        }
+        // Hash without holding the lock; a concurrent request may hash the
+        // same file, which costs a read but gives the same answer
+        let hash = content_hash(file)?;
+        self.state().hashes.insert(file.clone(), hash);
+        Ok(Some(hash))
+    }
+
+    /// Keep the hashes computed for the checksums format
+    fn remember(&self, files: &[AnnouncedFile], checksums: &FileChecksums) {
+        if self.max_bytes == 0 {
+            return;
+        }
+        let mut state = self.state();
+        for (index, file) in files.iter().enumerate() {
+            if let Some(hash) = checksums.get(index as u32) {
+                state.hashes.insert(file.clone(), hash);
+            }
+        }
+    }
+
+    /// Content hash of `files[index]` if another file of the list has the
+    /// same contents
+    fn shared_hash(&self, files: &[AnnouncedFile], index: usize) -> ClipboardResult<Option<u64>> {
+        let file = &files[index];
+        if self.max_bytes == 0 || file.is_directory() || file.size() == 0 {
+            return Ok(None);
+        }
+        // Only files of the same size can match, so unique sizes are never
+        // hashed
+        let mut hash = None;
+        for (other_index, other) in files.iter().enumerate() {
+            if other_index == index || other.is_directory() || other.size() != file.size() {
+                continue;
+            }
+            let own = match hash {
+                Some(own) => own,
+                None => match self.hash(file)? {
+                    Some(own) => *hash.insert(own),
+                    None => return Ok(None),
+                },
+            };
+            if self.hash(other)? == Some(own) {
+                return Ok(Some(own));
+            }
+        }
+        Ok(None)
+    }
+
+    /// Read a range, sharing it between files with the same contents
+    fn read_range(&self, files: &[AnnouncedFile], index: usize, position: u64, size: u32) -> ClipboardResult<Vec<u8>> {
+        let Some(hash) = self.shared_hash(files, index)? else {
+            return files[index].read_range(position, size);
+        };
+        let key = (hash, position, size);
+        {
+            let mut state = self.state();
+            if let Some(at) = state.chunks.iter().position(|(k, _)| *k == key) {
+                state.hits += 1;
+                let entry = state.chunks.remove(at).expect("position was just found");
+                let data = entry.1.clone();
+                state.chunks.push_back(entry);
+                return Ok(data);
+            }
+            state.misses += 1;
+        }
+
+        let data = files[index].read_range(position, size)?;
+        if data.len() <= self.max_bytes {
+            let mut state = self.state();
+            if !state.chunks.iter().any(|(k, _)| *k == key) {
+                state.bytes += data.len();
+                state.chunks.push_back((key, data.clone()));
+                while state.bytes > self.max_bytes {
+                    let Some((_, evicted)) = state.chunks.pop_front() else {
+                        break;
+                    };
+                    state.bytes -= evicted.len();
+                }
+            }
+        }
+        Ok(data)
+    }
+
+    fn metrics(&self) -> ChunkCacheMetrics {
+        let state = self.state();
+        ChunkCacheMetrics {
+            hits: state.hits,
+            misses: state.misses,
+            chunks: state.chunks.len(),
+            bytes: state.bytes,
+        }
+    }
+}
+
+/// Serving side of file copies, with snapshots for clipboard data locks.
+///
+/// [`set_files`](Self::set_files) replaces the announced files on every
+/// local copy. When the peer sends Lock Clipboard Data, [`lock`](Self::lock)
+/// keeps the current list under its `clipDataId`; requests referring to it
+/// are served from that list, even after a newer copy, until
+/// [`unlock`](Self::unlock). Requests without a lock use the current list.
+///
+/// # Example
+///
+/// ```no_run
+/// use lamco_clipboard_core::file_transfer::{FileContentsKind, FileContentsRequest, FileServer};
+/// use lamco_clipboard_core::AnnouncedFile;
+///
+/// let mut server = FileServer::new();
+/// server.set_files(vec![AnnouncedFile::snapshot("/home/user/report.pdf")?]);
+/// server.lock(3);
+///
+/// // The user copies something else; the paste in progress is unaffected
+/// server.set_files(vec![AnnouncedFile::snapshot("/home/user/other.txt")?]);
+/// let request = FileContentsRequest {
+///     stream_id: 1,
+///     file_index: 0,
+///     kind: FileContentsKind::Range { position: 0, size: 65536 },
+///     clip_data_id: Some(3),
+/// };
+/// let chunk = server.serve(&request)?; // from report.pdf
+/// server.unlock(3);
+/// # Ok::<(), lamco_clipboard_core::ClipboardError>(())
+/// ```
+#[derive(Debug)]
+pub struct FileServer {
+    files: Arc<[AnnouncedFile]>,
+    locked: HashMap<u32, Arc<[AnnouncedFile]>>,
+    policy: FileTransferPolicy,
+    metrics: Option<TransferRecorder>,
+    chunk_cache: ChunkCache,
+    #[cfg(feature = "compression")]
+    compression: Option<CompressionConfig>,
+    /// Whether the peer accepted compressed responses for the latest copy
+    #[cfg(feature = "compression")]
+    compressed: bool,
+    /// Locks taken on copies with compressed responses
+    #[cfg(feature = "compression")]
+    compressed_locks: HashSet<u32>,
+}
+
+impl Default for FileServer {
+    fn default() -> Self {
+        Self {
+            files: Vec::new().into(),
+            locked: HashMap::new(),
+            policy: FileTransferPolicy::default(),
+            metrics: None,
+            chunk_cache: ChunkCache::default(),
+            #[cfg(feature = "compression")]
+            compression: None,
+            #[cfg(feature = "compression")]
+            compressed: false,
+            #[cfg(feature = "compression")]
+            compressed_locks: HashSet::new(),
+        }
+    }
+}
+
+impl FileServer {
+    /// Create a server with no files
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Refuse to serve copies whose files break `policy`
+    pub fn with_policy(mut self, policy: FileTransferPolicy) -> Self {
+        self.policy = policy;
+        self
+    }
+
+    /// Count the bytes served into `metrics`
+    pub fn with_metrics(mut self, metrics: TransferRecorder) -> Self {
+        self.metrics = Some(metrics);
+        self
+    }
+
+    /// Read contents that appear several times in a copy only once
+    ///
+    /// Files whose size matches another file of the same copy are hashed,
+    /// and ranges of files with the same hash are kept in up to `max_bytes`
+    /// of memory ([`DEFAULT_CHUNK_CACHE_BYTES`] is a reasonable start), so
+    /// a copy of many identical files, and repeated requests for the same
+    /// range of them, are served from one read. Files larger than
+    /// `max_bytes` are only shared once [`checksums()`](Self::checksums)
+    /// has hashed them. Ranges served from memory are not re-validated
+    /// against the file on disk.
+    pub fn with_chunk_cache(mut self, max_bytes: usize) -> Self {
+        self.chunk_cache = ChunkCache {
+            max_bytes,
+            ..ChunkCache::default()
+        };
+        self
+    }
+
+    /// Hits, misses and current size of the chunk cache
+    pub fn chunk_cache_metrics(&self) -> ChunkCacheMetrics {
+        self.chunk_cache.metrics()
+    }
+
+    /// Compress range responses per `config` for copies whose pasting
+    /// side accepted it
+    #[cfg(feature = "compression")]
+    pub fn with_compression(mut self, config: CompressionConfig) -> Self {
+        self.compression = Some(config);
+        self
+    }
+
+    /// Answer range requests for the latest copy with compression frames
+    ///
+    /// Call it once
+    /// [`ClipboardSession::peer_accepts_compressed_files`](crate::protocol::ClipboardSession::peer_accepts_compressed_files)
+    /// turns true. Does nothing without [`with_compression`](Self::with_compression).
+    #[cfg(feature = "compression")]
+    pub fn accept_compression(&mut self) {
+        self.compressed = self.compression.is_some();
+    }
+
+    /// Whether the copy a request refers to was accepted for compression
+    #[cfg(feature = "compression")]
+    fn is_compressed(&self, request: &FileContentsRequest) -> bool {
+        match request.clip_data_id {
+            Some(id) => self.compressed_locks.contains(&id),
+            None => self.compressed,
+        }
+    }
+
+    /// Replace the announced files after a local copy
+    pub fn set_files(&mut self, files: Vec<AnnouncedFile>) {
+        self.files = files.into();
+        self.forget_hashes();
+        #[cfg(feature = "compression")]
+        {
+            self.compressed = false;
+        }
+    }
+
+    /// Files of the latest copy
+    pub fn files(&self) -> &[AnnouncedFile] {
+        &self.files
+    }
+
+    /// Snapshot the current files under `clip_data_id` (Lock Clipboard Data)
+    ///
+    /// Locking an ID that is already locked keeps the existing snapshot.
+    pub fn lock(&mut self, clip_data_id: u32) {
+        if self.locked.contains_key(&clip_data_id) {
+            tracing::debug!("clipDataId {} locked twice, keeping the first snapshot", clip_data_id);
+            return;
+        }
+        self.locked.insert(clip_data_id, Arc::clone(&self.files));
+        #[cfg(feature = "compression")]
+        if self.compressed {
+            self.compressed_locks.insert(clip_data_id);
+        }
+    }
+
+    /// Release the snapshot for `clip_data_id` (Unlock Clipboard Data)
+    ///
+    /// Returns false if it was not locked.
+    pub fn unlock(&mut self, clip_data_id: u32) -> bool {
+        #[cfg(feature = "compression")]
+        self.compressed_locks.remove(&clip_data_id);
+        let unlocked = self.locked.remove(&clip_data_id).is_some();
+        self.forget_hashes();
+        unlocked
+    }
+
+    /// Drop content hashes of files no longer served
+    fn forget_hashes(&self) {
+        let live: HashSet<&AnnouncedFile> = self
+            .files
+            .iter()
+            .chain(self.locked.values().flat_map(|files| files.iter()))
+            .collect();
+        self.chunk_cache.state().hashes.retain(|file, _| live.contains(file));
+    }
+
+    /// Checksums of the latest copy's files, for the
+    /// [`FILE_CHECKSUMS_FORMAT_NAME`] format
+    pub fn checksums(&self) -> ClipboardResult<FileChecksums> {
+        let checksums = FileChecksums::from_files(&self.files)?;
+        self.chunk_cache.remember(&self.files, &checksums);
+        Ok(checksums)
+    }
+
+    /// Number of snapshots held
+    pub fn locked(&self) -> usize {
+        self.locked.len()
+    }
+
+    /// Answer a File Contents Request
+    ///
+    /// Requests referring to an unknown `clipDataId` fail with
+    /// [`ClipboardError::InvalidState`] rather than being served from the
+    /// current files, which may no longer be what the peer is pasting. Every
+    /// request for a copy that breaks the policy fails with
+    /// [`ClipboardError::FileTransfer`].
+    pub fn serve(&self, request: &FileContentsRequest) -> ClipboardResult<Vec<u8>> {
+        let files = match request.clip_data_id {
+            Some(id) => self
+                .locked
+                .get(&id)
+                .ok_or_else(|| ClipboardError::InvalidState(format!("clipDataId {} is not locked", id)))?,
+            None => &self.files,
+        };
+        self.policy.check_files(files)?;
+        let index = request.file_index as usize;
+        let file = files
+            .get(index)
+            .ok_or_else(|| ClipboardError::FileNotFound(format!("file index {}", request.file_index)))?;
+
+        let data = match request.kind {
+            FileContentsKind::Size => file.validate()?.to_le_bytes().to_vec(),
+            FileContentsKind::Range { position, size } => self.chunk_cache.read_range(files, index, position, size)?,
+        };
+        #[cfg(feature = "compression")]
+        let data = match &self.compression {
+            Some(config) if matches!(request.kind, FileContentsKind::Range { .. }) && self.is_compressed(request) => {
+                compression::compress(&data, config)?
+            }
+            _ => data,
+        };
+        if let Some(metrics) = &self.metrics {
+            metrics.record_sent(data.len());
+        }
+        Ok(data)
+    }
+}
+
+/// [`serve`] with responses paced by `limiter`
+///
+/// Waits until the requested range may be sent before reading it, so
+/// responses held back by the limit do not sit in memory.
+#[cfg(feature = "tokio")]
+pub async fn serve_throttled<S: ClipboardSink>(
+    sink: &S,
+    request: &FileContentsRequest,
+    limiter: &RateLimiter,
+) -> ClipboardResult<Vec<u8>> {
+    if let FileContentsKind::Range { size, .. } = request.kind {
+        limiter.acquire(u64::from(size)).await;
+    }
+    serve(sink, request).await
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::formats::FileDescriptorFlags;
+    use crate::testing::block_on;
+    use crate::testing::sink::MemorySink;
+    use std::sync::{Arc, Mutex};
+
+    fn descriptor(name: &str, size: Option<u64>, attributes: u32) -> FileDescriptor {
+        FileDescriptor {
+            flags: FileDescriptorFlags::from_raw(FileDescriptorFlags::FILESIZE),
+            attributes,
+            creation_time: None,
+            access_time: None,
+            write_time: None,
+            size,
+            name: name.to_string(),
+        }
+    }
+
+    fn requests(events: &[FileTransferEvent]) -> Vec<FileContentsRequest> {
+        events
+            .iter()
+            .filter_map(|event| match event {
+                FileTransferEvent::Request(request) => Some(*request),
+                _ => None,
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_chunked_paste_through_sinks() {
+        let source = MemorySink::new().with_files(vec![
+            ("report.pdf".to_string(), (0..=255).cycle().take(10_000).collect()),
+            ("empty.txt".to_string(), Vec::new()),
+            ("notes.txt".to_string(), b"notes".to_vec()),
+        ]);
+        let target = MemorySink::default();
+        let descriptors = vec![
+            descriptor("docs", Some(0), FILE_ATTRIBUTE_DIRECTORY),
+            descriptor("docs\\report.pdf", Some(10_000), 0x80),
+            descriptor("docs\\empty.txt", Some(0), 0x80),
+            // No size in the descriptor: asked for first
+            descriptor("..\\notes.txt", None, 0x80),
+        ];
+        let mut transfer = FileTransfer::new(&descriptors).with_config(FileTransferConfig {
+            chunk_size: 4096,
+            max_outstanding_requests: 2,
+            ..Default::default()
+        });
+
+        let events = transfer.start().unwrap();
+        let mut to_send = block_on(drive(&mut transfer, &target, events)).unwrap();
+        let mut max_outstanding = 0;
+        // Answering the newest request first delivers ranges out of order
+        while let Some(request) = to_send.pop() {
+            max_outstanding = max_outstanding.max(transfer.outstanding());
+            // The source has no entry for the directory
+            let served = FileContentsRequest {
+                file_index: request.file_index - 1,
+                ..request
+            };
+            let data = block_on(serve(&source, &served)).unwrap();
+            let events = transfer.on_response(request.stream_id, data, false).unwrap();
+            to_send.extend(block_on(drive(&mut transfer, &target, events)).unwrap());
+        }
+
+        assert_eq!(max_outstanding, 2);
+        assert_eq!(transfer.state(), TransferState::Completed);
+        assert_eq!(transfer.received_bytes(), transfer.total_bytes());
+        let mut written = target.written_files();
+        written.sort();
+        let paths: Vec<_> = written.iter().map(|(path, _)| path.as_str()).collect();
+        assert_eq!(paths, ["docs/empty.txt", "docs/report.pdf", "notes.txt"]);
+        assert_eq!(written[1].1, source.files()[0].1);
+        assert_eq!(written[2].1, b"notes");
+    }
+
+    #[test]
+    fn test_progress() {
+        let clock = crate::ManualClock::new();
+        let descriptors = vec![descriptor("a.bin", Some(1000), 0x80), descriptor("b.bin", None, 0x80)];
+        let mut transfer = FileTransfer::new(&descriptors)
+            .with_clock(clock.clone())
+            .with_config(FileTransferConfig {
+                chunk_size: 500,
+                max_outstanding_requests: 1,
+                ..Default::default()
+            });
+        #[cfg(feature = "tokio")]
+        let mut watch = transfer.subscribe_progress();
+
+        let request = requests(&transfer.start().unwrap())[0];
+        clock.advance(Duration::from_secs(1));
+        let request = requests(&transfer.on_response(request.stream_id, vec![0; 500], false).unwrap())[0];
+        let progress = transfer.progress();
+        assert_eq!(progress.bytes_done, 500);
+        assert_eq!(progress.bytes_per_second, Some(500.0));
+        // Size of b.bin still unknown
+        assert_eq!(progress.eta_ms, None);
+        assert_eq!(
+            progress.file,
+            Some(FileProgress {
+                index: 0,
+                path: "a.bin".to_string(),
+                bytes_done: 500,
+                total_bytes: Some(1000),
+            })
+        );
+
+        let request = requests(&transfer.on_response(request.stream_id, vec![0; 500], false).unwrap())[0];
+        assert_eq!(request.kind, FileContentsKind::Size);
+        transfer
+            .on_response(request.stream_id, 1000u64.to_le_bytes().to_vec(), false)
+            .unwrap();
+        let progress = transfer.progress();
+        assert_eq!((progress.files_done, progress.file_count), (1, 2));
+        assert_eq!(progress.percentage(), 50.0);
+        assert_eq!(progress.eta_ms, Some(1000));
+        assert_eq!(progress.file.as_ref().unwrap().total_bytes, Some(1000));
+
+        #[cfg(feature = "tokio")]
+        {
+            assert!(watch.has_changed().unwrap());
+            assert_eq!(*watch.borrow_and_update(), progress);
+        }
+    }
+
+    #[test]
+    fn test_cancellation() {
+        let descriptors = vec![descriptor("big.iso", Some(1 << 20), 0x80)];
+        let staging = std::env::temp_dir().join(format!("lamco-transfer-staging-{}", std::process::id()));
+        std::fs::write(&staging, b"partial").unwrap();
+
+        // Cancelled from the UI while responses are in flight
+        let token = CancellationToken::new();
+        let mut transfer = FileTransfer::new(&descriptors).with_cancellation(token.clone());
+        transfer.hold_temp_file(TempFileGuard::new(&staging));
+        let outstanding = requests(&transfer.start().unwrap());
+        assert_eq!(outstanding.len(), DEFAULT_MAX_OUTSTANDING_REQUESTS);
+        transfer
+            .on_response(outstanding[0].stream_id, vec![0; DEFAULT_CHUNK_SIZE], false)
+            .unwrap();
+
+        token.cancel();
+        let result = transfer.on_response(outstanding[1].stream_id, vec![0; DEFAULT_CHUNK_SIZE], false);
+        assert!(matches!(result, Err(ClipboardError::TransferCancelled)));
+        assert_eq!(transfer.state(), TransferState::Cancelled);
+        assert_eq!(transfer.progress().state, TransferState::Cancelled);
+        assert_eq!(transfer.outstanding(), 0);
+        assert_eq!(transfer.received_bytes(), 0);
+        assert!(!staging.exists());
+
+        // Cancelled directly: the abandoned requests are handed back
+        let mut transfer = FileTransfer::new(&descriptors);
+        let outstanding = requests(&transfer.start().unwrap());
+        assert_eq!(transfer.cancel(), outstanding);
+        assert!(transfer.cancellation_token().is_cancelled());
+        assert!(transfer.cancel().is_empty());
+        let written = block_on(drive(
+            &mut transfer,
+            &MemorySink::default(),
+            vec![FileTransferEvent::FileReceived {
+                index: 0,
+                path: "big.iso".to_string(),
+                data: Vec::new(),
+            }],
+        ));
+        assert!(matches!(written, Err(ClipboardError::TransferCancelled)));
+    }
+
+    #[test]
+    fn test_resume_after_reconnect() {
+        let contents: Vec<u8> = (0..=255).cycle().take(10_000).collect();
+        let descriptors = vec![
+            descriptor("small.txt", Some(3), 0x80),
+            descriptor("big.bin", Some(10_000), 0x80),
+        ];
+        let config = FileTransferConfig {
+            chunk_size: 4096,
+            max_outstanding_requests: 1,
+            ..Default::default()
+        };
+
+        // Connection drops after the small file and one chunk of the big one
+        let mut transfer = FileTransfer::new(&descriptors)
+            .with_config(config.clone())
+            .with_clip_data_id(1);
+        let request = requests(&transfer.start().unwrap())[0];
+        let events = transfer.on_response(request.stream_id, b"abc".to_vec(), false).unwrap();
+        let request = requests(&events)[0];
+        transfer
+            .on_response(request.stream_id, contents[..4096].to_vec(), false)
+            .unwrap();
+        let manifest = transfer.manifest();
+        let staged = transfer.partial_data(1).unwrap().to_vec();
+        assert!(manifest.files[0].complete);
+        assert_eq!(manifest.files[1].verified_offset, 4096);
+        assert_eq!(manifest.files[1].checksum, xxh3_64(&contents[..4096]));
+
+        // A different clipboard behind the same names is refused
+        let changed = vec![
+            descriptor("small.txt", Some(3), 0x80),
+            descriptor("big.bin", Some(9_999), 0x80),
+        ];
+        assert!(matches!(
+            FileTransfer::resume(&changed, &manifest),
+            Err(ClipboardError::FileChanged { .. })
+        ));
+
+        let mut transfer = FileTransfer::resume(&descriptors, &manifest)
+            .unwrap()
+            .with_config(config)
+            .with_clip_data_id(2);
+        let mut corrupted = staged.clone();
+        corrupted[100] ^= 0xFF;
+        assert!(transfer.restore_partial(1, corrupted).is_err());
+        assert!(transfer.restore_partial(0, b"abc".to_vec()).is_err());
+        transfer.restore_partial(1, staged).unwrap();
+
+        let request = requests(&transfer.start().unwrap())[0];
+        assert_eq!(request.file_index, 1);
+        assert_eq!(request.clip_data_id, Some(2));
+        assert_eq!(
+            request.kind,
+            FileContentsKind::Range {
+                position: 4096,
+                size: 4096
+            }
+        );
+        let events = transfer
+            .on_response(request.stream_id, contents[4096..8192].to_vec(), false)
+            .unwrap();
+        let request = requests(&events)[0];
+        let events = transfer
+            .on_response(request.stream_id, contents[8192..].to_vec(), false)
+            .unwrap();
+        assert!(matches!(&events[0], FileTransferEvent::FileReceived { index: 1, data, .. } if *data == contents));
+        assert_eq!(events[1], FileTransferEvent::Completed);
+        assert_eq!(transfer.received_bytes(), 10_003);
+    }
+
+    #[test]
+    fn test_request_pacing() {
+        let clock = crate::ManualClock::new();
+        let descriptors = vec![descriptor("video.mkv", Some(1 << 20), 0x80)];
+        let mut transfer = FileTransfer::new(&descriptors)
+            .with_clock(clock.clone())
+            .with_config(FileTransferConfig {
+                chunk_size: 1000,
+                max_bytes_per_second: Some(10_000),
+                burst_bytes: 2000,
+                ..Default::default()
+            });
+
+        // The burst covers two chunks; the window would allow four
+        let first = requests(&transfer.start().unwrap());
+        assert_eq!(first.len(), 2);
+        assert_eq!(transfer.next_poll(), Some(Duration::from_millis(100)));
+        assert!(transfer.poll().unwrap().is_empty());
+
+        // Responses do not speed things up
+        let events = transfer.on_response(first[0].stream_id, vec![0; 1000], false).unwrap();
+        assert!(requests(&events).is_empty());
+
+        clock.advance(Duration::from_millis(100));
+        assert_eq!(transfer.next_poll(), Some(Duration::ZERO));
+        assert_eq!(requests(&transfer.poll().unwrap()).len(), 1);
+
+        let unpaced = FileTransfer::new(&descriptors).start().unwrap();
+        assert_eq!(requests(&unpaced).len(), DEFAULT_MAX_OUTSTANDING_REQUESTS);
+    }
+
+    #[test]
+    fn test_timeouts_and_retries() {
+        let clock = crate::ManualClock::new();
+        let descriptors = vec![descriptor("a.bin", Some(300), 0x80)];
+        let config = FileTransferConfig {
+            chunk_size: 100,
+            max_outstanding_requests: 2,
+            chunk_timeout_ms: Some(1000),
+            max_retries: 2,
+            retry_backoff_ms: 200,
+            ..Default::default()
+        };
+
+        // A stalled chunk is sent again with the same stream ID after a
+        // growing backoff, and the late answer to the first attempt is ignored
+        let mut transfer = FileTransfer::new(&descriptors)
+            .with_clock(clock.clone())
+            .with_config(config.clone());
+        let first = requests(&transfer.start().unwrap());
+        assert_eq!(transfer.next_poll(), Some(Duration::from_millis(1000)));
+        transfer.on_response(first[1].stream_id, vec![1; 100], false).unwrap();
+        clock.advance(Duration::from_millis(1000));
+        assert!(transfer.poll().unwrap().is_empty());
+        assert_eq!(transfer.next_poll(), Some(Duration::from_millis(200)));
+        clock.advance(Duration::from_millis(200));
+        assert_eq!(requests(&transfer.poll().unwrap()), [first[0]]);
+        assert_eq!(transfer.retried_requests(), 1);
+        let events = transfer.on_response(first[0].stream_id, vec![0; 100], false).unwrap();
+        let last = requests(&events)[0];
+        assert!(transfer
+            .on_response(first[0].stream_id, vec![0; 100], false)
+            .unwrap()
+            .is_empty());
+        let events = transfer.on_response(last.stream_id, vec![2; 100], false).unwrap();
+        assert_eq!(events.last(), Some(&FileTransferEvent::Completed));
+
+        // A chunk that never comes back while the peer answers others
+        let mut transfer = FileTransfer::new(&descriptors)
+            .with_clock(clock.clone())
+            .with_config(config.clone());
+        let first = requests(&transfer.start().unwrap());
+        transfer.on_response(first[1].stream_id, vec![1; 100], false).unwrap();
+        let mut result = Ok(Vec::new());
+        for wait in [1000, 200, 1000, 400, 1000] {
+            clock.advance(Duration::from_millis(wait));
+            result = transfer.poll();
+        }
+        assert_eq!(transfer.retried_requests(), 2);
+        let Err(ClipboardError::FileTransfer(error)) = result else {
+            panic!("{:?}", result)
+        };
+        assert_eq!(error.kind, TransferErrorKind::ChunkTimeout);
+        assert_eq!((error.index, error.path.as_str(), error.offset), (0, "a.bin", 0));
+        assert!(error.is_transient());
+        assert_eq!(transfer.state(), TransferState::Failed);
+
+        // A peer that answers nothing at all
+        let mut transfer = FileTransfer::new(&descriptors)
+            .with_clock(clock.clone())
+            .with_config(FileTransferConfig {
+                max_retries: 0,
+                ..config.clone()
+            });
+        transfer.start().unwrap();
+        clock.advance(Duration::from_millis(1000));
+        let result = transfer.poll();
+        assert!(matches!(
+            result,
+            Err(ClipboardError::FileTransfer(TransferError {
+                kind: TransferErrorKind::PeerUnresponsive,
+                ..
+            }))
+        ));
+
+        // The whole transfer runs out of time at the first missing byte
+        let mut transfer = FileTransfer::new(&descriptors)
+            .with_clock(clock.clone())
+            .with_config(FileTransferConfig {
+                chunk_timeout_ms: None,
+                transfer_timeout_ms: Some(5000),
+                ..config
+            });
+        let first = requests(&transfer.start().unwrap());
+        transfer.on_response(first[0].stream_id, vec![0; 100], false).unwrap();
+        assert_eq!(transfer.next_poll(), Some(Duration::from_millis(5000)));
+        clock.advance(Duration::from_millis(5000));
+        let result = transfer.poll();
+        assert!(matches!(
+            result,
+            Err(ClipboardError::FileTransfer(TransferError {
+                kind: TransferErrorKind::TransferTimeout,
+                offset: 100,
+                ..
+            }))
+        ));
+    }
+
+    #[test]
+    fn test_sliding_window_and_backpressure() {
+        let config = FileTransferConfig {
+            chunk_size: 100,
+            max_outstanding_requests: 2,
+            ..Default::default()
+        };
+
+        // A slow first chunk stops the window from running ahead
+        let mut transfer = FileTransfer::new(&[descriptor("a.bin", Some(1000), 0x80)]).with_config(config.clone());
+        let first = requests(&transfer.start().unwrap());
+        assert_eq!(first.len(), 2);
+        let events = transfer.on_response(first[1].stream_id, vec![1; 100], false).unwrap();
+        assert!(events.is_empty());
+        let next = requests(&transfer.on_response(first[0].stream_id, vec![0; 100], false).unwrap());
+        let positions: Vec<_> = next
+            .iter()
+            .map(|request| match request.kind {
+                FileContentsKind::Range { position, .. } => position,
+                FileContentsKind::Size => unreachable!(),
+            })
+            .collect();
+        assert_eq!(positions, [200, 300]);
+
+        // Files the sink has not written yet hold back new requests
+        let descriptors: Vec<_> = ["a", "b", "c"]
+            .iter()
+            .map(|name| descriptor(name, Some(100), 0x80))
+            .collect();
+        let mut transfer = FileTransfer::new(&descriptors).with_config(FileTransferConfig {
+            max_unwritten_bytes: Some(100),
+            ..config
+        });
+        let first = requests(&transfer.start().unwrap());
+        let events = transfer.on_response(first[0].stream_id, vec![0; 100], false).unwrap();
+        assert!(matches!(events[..], [FileTransferEvent::FileReceived { index: 0, .. }]));
+        assert_eq!(transfer.unwritten_bytes(), 100);
+        let released = requests(&transfer.file_written(0).unwrap());
+        assert_eq!(released.len(), 1);
+        assert_eq!(released[0].file_index, 2);
+        assert!(transfer.file_written(0).unwrap().is_empty());
+    }
+
+    #[test]
+    fn test_checksums() {
+        // Serving: checksums of the announced files survive the wire format
+        let path = std::env::temp_dir().join(format!("lamco-checksums-{}", std::process::id()));
+        std::fs::write(&path, b"verified contents").unwrap();
+        let mut server = FileServer::new();
+        server.set_files(vec![AnnouncedFile::snapshot(&path).unwrap()]);
+        let checksums = server.checksums().unwrap();
+        assert_eq!(checksums.get(0), Some(xxh3_64(b"verified contents")));
+        assert_eq!(FileChecksums::parse(&checksums.to_bytes()).unwrap(), checksums);
+        assert!(FileChecksums::parse(&[1, 0, 0]).is_err());
+        assert!(FileChecksums::parse(&[2, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0]).is_err());
+        std::fs::remove_file(path).unwrap();
+
+        // Receiving: a file that does not match is never reported received
+        let descriptors = vec![
+            descriptor("good.txt", Some(4), 0x80),
+            descriptor("bad.txt", Some(4), 0x80),
+        ];
+        let mut transfer = FileTransfer::new(&descriptors)
+            .with_checksums(FileChecksums::new(vec![xxh3_64(b"good"), xxh3_64(b"sent")]));
+        let first = requests(&transfer.start().unwrap());
+        let events = transfer
+            .on_response(first[0].stream_id, b"good".to_vec(), false)
+            .unwrap();
+        assert!(matches!(&events[0], FileTransferEvent::FileReceived { index: 0, .. }));
+        let result = transfer.on_response(first[1].stream_id, b"bent".to_vec(), false);
+        let Err(ClipboardError::FileTransfer(error)) = result else {
+            panic!("{:?}", result)
+        };
+        assert_eq!(error.kind, TransferErrorKind::ChecksumMismatch);
+        assert_eq!((error.index, error.path.as_str()), (1, "bad.txt"));
+        assert_eq!(transfer.state(), TransferState::Failed);
+
+        // An empty file is checked as the transfer starts
+        let mut transfer = FileTransfer::new(&[descriptor("empty.txt", Some(0), 0x80)])
+            .with_checksums(FileChecksums::new(vec![xxh3_64(b"sent")]));
+        let result = transfer.start();
+        assert!(matches!(
+            result,
+            Err(ClipboardError::FileTransfer(TransferError {
+                kind: TransferErrorKind::ChecksumMismatch,
+                index: 0,
+                ..
+            }))
+        ));
+        assert_eq!(transfer.state(), TransferState::Failed);
+    }
+
+    #[test]
+    fn test_chunk_cache() {
+        let dir = std::env::temp_dir().join(format!("lamco-chunk-cache-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let template = b"Dear customer, ...".repeat(100);
+        let mut other = template.clone();
+        other[0] = b'd';
+        let contents = [&template[..], &template, &other, b"short"];
+        let files: Vec<_> = contents
+            .iter()
+            .enumerate()
+            .map(|(index, data)| {
+                let path = dir.join(format!("letter-{}.txt", index));
+                std::fs::write(&path, data).unwrap();
+                AnnouncedFile::snapshot(path).unwrap()
+            })
+            .collect();
+
+        let mut server = FileServer::new().with_chunk_cache(DEFAULT_CHUNK_CACHE_BYTES);
+        server.set_files(files.clone());
+        let range = |file_index| FileContentsRequest {
+            stream_id: 1,
+            file_index,
+            kind: FileContentsKind::Range {
+                position: 0,
+                size: 65536,
+            },
+            clip_data_id: None,
+        };
+
+        // The second copy of the template and a repeated request come from
+        // memory; same-size files with other contents are read as usual
+        for (index, expected) in [0, 1, 2, 3, 0]
+            .into_iter()
+            .map(|index| (index, contents[index as usize]))
+        {
+            assert_eq!(server.serve(&range(index)).unwrap(), expected);
+        }
+        let metrics = server.chunk_cache_metrics();
+        assert_eq!((metrics.hits, metrics.misses), (2, 1));
+        assert_eq!((metrics.chunks, metrics.bytes), (1, template.len()));
+
+        // Files larger than the cache are not hashed to serve a range...
+        let mut server = FileServer::new().with_chunk_cache(template.len() - 1);
+        server.set_files(files[..2].to_vec());
+        server.serve(&range(1)).unwrap();
+        assert_eq!(server.chunk_cache_metrics(), ChunkCacheMetrics::default());
+        // ...but share the hashes computed for the checksums format
+        server.checksums().unwrap();
+        server.serve(&range(1)).unwrap();
+        assert_eq!(server.chunk_cache_metrics().misses, 1);
+
+        // Without a cache every request reads the file
+        let mut server = FileServer::new();
+        server.set_files(vec![AnnouncedFile::snapshot(dir.join("letter-0.txt")).unwrap()]);
+        server.serve(&range(0)).unwrap();
+        assert_eq!(server.chunk_cache_metrics(), ChunkCacheMetrics::default());
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn test_policy() {
+        let policy = FileTransferPolicy {
+            max_file_size: Some(1000),
+            max_total_bytes: Some(1500),
+            max_file_count: Some(2),
+            denied_extensions: vec![".EXE".to_string(), "msi".to_string()],
+        };
+        assert!(FileTransferPolicy::default().is_unrestricted());
+        assert!(policy.is_denied("tools/setup.exe"));
+        assert!(!policy.is_denied("exe"));
+
+        let check = |files: &[(&str, u64)]| {
+            let descriptors: Vec<_> = files
+                .iter()
+                .map(|(name, size)| descriptor(name, Some(*size), 0x80))
+                .collect();
+            policy
+                .check_descriptors(&descriptors)
+                .map_err(|error| (error.kind, error.index))
+        };
+        assert_eq!(check(&[("a.txt", 1000), ("b.txt", 500)]), Ok(()));
+        assert_eq!(check(&[("a.txt", 1001)]), Err((TransferErrorKind::FileTooLarge, 0)));
+        assert_eq!(
+            check(&[("a.txt", 1000), ("b.txt", 501)]),
+            Err((TransferErrorKind::TotalSizeExceeded, 1))
+        );
+        assert_eq!(
+            check(&[("a", 1), ("b", 1), ("c", 1)]),
+            Err((TransferErrorKind::TooManyFiles, 2))
+        );
+        assert_eq!(check(&[("Setup.Exe", 1)]), Err((TransferErrorKind::ExtensionDenied, 0)));
+
+        // Receiving: nothing is requested and the lock is released
+        let (unlock_tx, unlock_rx) = std::sync::mpsc::channel();
+        let locks = crate::ClipDataLocks::new(move |id| {
+            let _ = unlock_tx.send(id);
+        });
+        let mut transfer = FileTransfer::new(&[descriptor("payload.msi", Some(10), 0x80)])
+            .with_lock(locks.acquire(5).unwrap())
+            .with_policy(policy.clone());
+        assert!(transfer.start().is_err());
+        assert_eq!(transfer.state(), TransferState::Failed);
+        assert_eq!(transfer.failure().unwrap().kind, TransferErrorKind::ExtensionDenied);
+        assert_eq!(unlock_rx.try_recv(), Ok(5));
+
+        // Sizes learned from the peer are checked as they arrive
+        let mut transfer = FileTransfer::new(&[descriptor("big.bin", None, 0x80)]).with_policy(policy.clone());
+        let request = requests(&transfer.start().unwrap())[0];
+        let result = transfer.on_response(request.stream_id, 5000u64.to_le_bytes().to_vec(), false);
+        assert!(matches!(
+            result,
+            Err(ClipboardError::FileTransfer(TransferError {
+                kind: TransferErrorKind::FileTooLarge,
+                ..
+            }))
+        ));
+
+        // Serving: the peer gets a failed response
+        let path = std::env::temp_dir().join(format!("lamco-policy-{}.exe", std::process::id()));
+        std::fs::write(&path, b"MZ").unwrap();
+        let mut server = FileServer::new().with_policy(policy);
+        server.set_files(vec![AnnouncedFile::snapshot(&path).unwrap()]);
+        let request = FileContentsRequest {
+            stream_id: 1,
+            file_index: 0,
+            kind: FileContentsKind::Size,
+            clip_data_id: None,
+        };
+        assert!(matches!(server.serve(&request), Err(ClipboardError::FileTransfer(_))));
+        std::fs::remove_file(path).unwrap();
+    }
+
+    #[test]
+    fn test_metrics() {
+        let clock = crate::ManualClock::new();
+        let finished = Arc::new(Mutex::new(Vec::new()));
+        let recorder = TransferRecorder::with_callback({
+            let finished = Arc::clone(&finished);
+            move |metrics| finished.lock().unwrap().push(metrics.clone())
+        });
+        let descriptors = vec![descriptor("a.bin", Some(200), 0x80)];
+        let config = FileTransferConfig {
+            chunk_size: 100,
+            ..Default::default()
+        };
+
+        // Round trips land in their buckets; the outcome reaches the callback
+        let mut transfer = FileTransfer::new(&descriptors)
+            .with_clock(clock.clone())
+            .with_config(config.clone())
+            .with_metrics(recorder.clone());
+        let sent = requests(&transfer.start().unwrap());
+        assert_eq!(recorder.metrics().active_transfers, 1);
+        clock.advance(Duration::from_millis(30));
+        transfer.on_response(sent[0].stream_id, vec![0; 100], false).unwrap();
+        clock.advance(Duration::from_millis(700));
+        transfer.on_response(sent[1].stream_id, vec![0; 100], false).unwrap();
+
+        let metrics = recorder.metrics();
+        assert_eq!((metrics.bytes_received, metrics.active_transfers), (200, 0));
+        assert_eq!(metrics.completed_transfers, 1);
+        assert_eq!(metrics.chunk_rtt.count(), 2);
+        assert_eq!(metrics.chunk_rtt.counts[5], 1); // <= 50 ms
+        assert_eq!(metrics.chunk_rtt.counts[9], 1); // <= 1 s
+        assert_eq!(metrics.chunk_rtt.mean(), Some(Duration::from_millis(380)));
+        assert_eq!(*finished.lock().unwrap(), [metrics]);
+
+        // Failures are counted by cause, cancellations only once started
+        let mut transfer = FileTransfer::new(&descriptors).with_metrics(recorder.clone());
+        let sent = requests(&transfer.start().unwrap());
+        assert!(transfer.on_response(sent[0].stream_id, Vec::new(), true).is_err());
+        FileTransfer::new(&descriptors).with_metrics(recorder.clone()).cancel();
+        let mut transfer = FileTransfer::new(&descriptors).with_metrics(recorder.clone());
+        transfer.start().unwrap();
+        transfer.cancel();
+
+        let metrics = recorder.metrics();
+        assert_eq!(metrics.failures.peer_error, 1);
+        assert_eq!(metrics.failures.total(), 1);
+        assert_eq!(metrics.cancelled_transfers, 1);
+        assert_eq!(metrics.active_transfers, 0);
+        assert_eq!(finished.lock().unwrap().len(), 3);
+
+        // Served bytes count as sent
+        let path = std::env::temp_dir().join(format!("lamco-metrics-{}", std::process::id()));
+        std::fs::write(&path, b"served").unwrap();
+        let mut server = FileServer::new().with_metrics(recorder.clone());
+        server.set_files(vec![AnnouncedFile::snapshot(&path).unwrap()]);
+        let request = FileContentsRequest {
+            stream_id: 1,
+            file_index: 0,
+            kind: FileContentsKind::Range { position: 0, size: 6 },
+            clip_data_id: None,
+        };
+        server.serve(&request).unwrap();
+        assert_eq!(recorder.metrics().bytes_sent, 6);
+        std::fs::remove_file(path).unwrap();
+
+        recorder.reset();
+        assert_eq!(recorder.metrics().chunk_rtt.count(), 0);
+        assert_eq!(recorder.metrics().bytes_sent, 0);
+    }
+
+    #[cfg(feature = "compression")]
+    #[test]
+    fn test_compression() {
+        let path = std::env::temp_dir().join(format!("lamco-compressed-{}.csv", std::process::id()));
+        let contents = "id,name,amount\n".repeat(4000).into_bytes();
+        std::fs::write(&path, &contents).unwrap();
+        let recorder = TransferRecorder::new();
+        let mut server = FileServer::new()
+            .with_compression(CompressionConfig::default())
+            .with_metrics(recorder.clone());
+        server.set_files(vec![AnnouncedFile::snapshot(&path).unwrap()]);
+        server.accept_compression();
+        server.lock(4);
+        // A newer copy is not compressed, the locked one still is
+        server.set_files(vec![AnnouncedFile::snapshot(&path).unwrap()]);
+
+        let descriptors = vec![descriptor("data.csv", Some(contents.len() as u64), 0x80)];
+        let mut transfer = FileTransfer::new(&descriptors)
+            .with_clip_data_id(4)
+            .with_compression()
+            .with_metrics(recorder.clone());
+        let mut events = transfer.start().unwrap();
+        let mut received = Vec::new();
+        while let Some(event) = events.pop() {
+            match event {
+                FileTransferEvent::Request(request) => {
+                    let frame = server.serve(&request).unwrap();
+                    events.extend(transfer.on_response(request.stream_id, frame, false).unwrap());
+                }
+                FileTransferEvent::FileReceived { data, .. } => received = data,
+                FileTransferEvent::Completed => {}
+            }
+        }
+        assert_eq!(transfer.state(), TransferState::Completed);
+        assert_eq!(received, contents);
+        let metrics = recorder.metrics();
+        assert_eq!(metrics.bytes_sent, metrics.bytes_received);
+        assert!(metrics.bytes_sent < contents.len() as u64 / 10);
+
+        let request = FileContentsRequest {
+            stream_id: 1,
+            file_index: 0,
+            kind: FileContentsKind::Range { position: 0, size: 100 },
+            clip_data_id: None,
+        };
+        assert_eq!(server.serve(&request).unwrap(), contents[..100]);
+
+        // A stored frame of the wrong size fails the transfer
+        let mut transfer = FileTransfer::new(&descriptors).with_compression();
+        let request = requests(&transfer.start().unwrap())[0];
+        assert!(transfer.on_response(request.stream_id, vec![0, 1, 2], false).is_err());
+        assert_eq!(transfer.state(), TransferState::Failed);
+        std::fs::remove_file(path).unwrap();
+    }
+
+    #[test]
+    fn test_locks() {
+        use crate::ClipDataLocks;
+
+        // Receiving: the lock is released once the transfer ends
+        let (unlock_tx, unlock_rx) = std::sync::mpsc::channel();
+        let locks = ClipDataLocks::new(move |id| {
+            let _ = unlock_tx.send(id);
+        });
+        let mut transfer =
+            FileTransfer::new(&[descriptor("a.txt", Some(2), 0x80)]).with_lock(locks.acquire(9).unwrap());
+        let request = requests(&transfer.start().unwrap())[0];
+        assert_eq!(request.clip_data_id, Some(9));
+        assert!(unlock_rx.try_recv().is_err());
+        transfer.on_response(request.stream_id, b"ok".to_vec(), false).unwrap();
+        assert_eq!(unlock_rx.try_recv(), Ok(9));
+        locks.debug_assert_released();
+
+        // Serving: a locked paste keeps reading the files it started with
+        let dir = std::env::temp_dir();
+        let first = dir.join(format!("lamco-server-first-{}", std::process::id()));
+        let second = dir.join(format!("lamco-server-second-{}", std::process::id()));
+        std::fs::write(&first, b"first copy").unwrap();
+        std::fs::write(&second, b"second").unwrap();
+
+        let mut server = FileServer::new();
+        server.set_files(vec![AnnouncedFile::snapshot(&first).unwrap()]);
+        server.lock(9);
+        server.set_files(vec![AnnouncedFile::snapshot(&second).unwrap()]);
+
+        let mut request = FileContentsRequest {
+            stream_id: 1,
+            file_index: 0,
+            kind: FileContentsKind::Range { position: 0, size: 100 },
+            clip_data_id: Some(9),
+        };
+        assert_eq!(server.serve(&request).unwrap(), b"first copy");
+        request.kind = FileContentsKind::Size;
+        assert_eq!(server.serve(&request).unwrap(), 10u64.to_le_bytes());
+        request.clip_data_id = None;
+        assert_eq!(server.serve(&request).unwrap(), 6u64.to_le_bytes());
+
+        assert!(server.unlock(9));
+        assert!(!server.unlock(9));
+        request.clip_data_id = Some(9);
+        assert!(matches!(server.serve(&request), Err(ClipboardError::InvalidState(_))));
+
+        std::fs::remove_file(first).unwrap();
+        std::fs::remove_file(second).unwrap();
+    }
+
+    #[test]
+    fn test_failures() {
+        let descriptors = vec![descriptor("a.bin", Some(100), 0x80)];
+
+        // Short read: the file shrank on the peer
+        let mut transfer = FileTransfer::new(&descriptors).with_clip_data_id(7);
+        let request = requests(&transfer.start().unwrap())[0];
+        assert_eq!(request.clip_data_id, Some(7));
+        let result = transfer.on_response(request.stream_id, vec![0; 40], false);
+        assert!(matches!(result, Err(ClipboardError::FileChanged { actual: 40, .. })));
+        assert_eq!(transfer.state(), TransferState::Failed);
+        assert!(transfer.on_response(request.stream_id, vec![0; 100], false).is_err());
+
+        // Error response and unknown streams
+        let mut transfer = FileTransfer::new(&descriptors);
+        let request = requests(&transfer.start().unwrap())[0];
+        assert!(transfer.on_response(request.stream_id + 1, Vec::new(), false).is_err());
+        let result = transfer.on_response(request.stream_id, Vec::new(), true);
+        assert!(matches!(
+            result,
+            Err(ClipboardError::FileTransfer(TransferError {
+                kind: TransferErrorKind::PeerError,
+                index: 0,
+                offset: 0,
+                ..
+            }))
+        ));
+
+        // Too large to hold in memory
+        let mut transfer = FileTransfer::new(&descriptors).with_config(FileTransferConfig {
+            max_file_size: 99,
+            ..Default::default()
+        });
+        assert!(transfer.start().is_err());
+        assert_eq!(transfer.state(), TransferState::Failed);
+
+        // Nothing to transfer
+        let mut transfer = FileTransfer::new(&[]);
+        assert_eq!(transfer.start().unwrap(), vec![FileTransferEvent::Completed]);
+    }
+}
@@ -0,0 +1,382 @@
+//! Atomic writes of received files with a name collision policy.
+//!
+//! [`FileWriterSink`] wraps a [`ClipboardSink`] and writes the files a paste
+//! delivers into a destination directory itself, instead of passing them to
+//! the inner sink:
+//!
+//! - Each file is written under a temporary `.partial` name and renamed into
+//!   place once complete, so a failed paste never leaves a truncated file
+//!   under the real name.
+//! - [`CollisionPolicy`] decides what happens when a file of that name
+//!   already exists. Except with [`CollisionPolicy::Overwrite`], the final
+//!   rename refuses to replace a file, even one created while the paste was
+//!   being written.
+//! - [`FsyncPolicy`] and the sparse threshold work as for a
+//!   [`StagingArea`](crate::StagingArea).
+
+use crate::sink::{ClipboardChangeReceiver, ClipboardSink, FileInfo};
+use crate::staging::{resolve_relative, sync_parent, write_partial};
+use crate::{ClipboardError, ClipboardResult, FsyncPolicy, DEFAULT_SPARSE_THRESHOLD_BYTES};
+use std::fmt;
+use std::fs;
+use std::future::Future;
+use std::io::{self, ErrorKind};
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+
+/// Highest number tried when renaming a file out of the way
+const MAX_RENAME_SUFFIX: u32 = 999;
+
+/// What to do when a received file's name is already taken
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "serde", serde(rename_all = "snake_case"))]
+pub enum CollisionPolicy {
+    /// Replace the existing file
+    Overwrite,
+
+    /// Write the new file as `name (1).ext`, `name (2).ext`, ...
+    #[default]
+    Rename,
+
+    /// Keep the existing file and drop the received one
+    Skip,
+
+    /// Fail with [`ClipboardError::FileExists`]
+    Error,
+}
+
+impl CollisionPolicy {
+    /// Every policy, in configuration order
+    pub const ALL: [Self; 4] = [Self::Overwrite, Self::Rename, Self::Skip, Self::Error];
+
+    /// Name used in configuration files
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Self::Overwrite => "overwrite",
+            Self::Rename => "rename",
+            Self::Skip => "skip",
+            Self::Error => "error",
+        }
+    }
+}
+
+impl fmt::Display for CollisionPolicy {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+impl FromStr for CollisionPolicy {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Self::ALL
+            .into_iter()
+            .find(|policy| policy.as_str().eq_ignore_ascii_case(s.trim()))
+            .ok_or_else(|| format!("unknown collision policy '{}'", s))
+    }
+}
+
+/// Clipboard sink decorator that writes received files atomically.
+///
+/// [`write_file`](ClipboardSink::write_file) resolves the relative path
+/// under the destination directory, refusing absolute paths and `..`, and
+/// writes there; everything else is passed to the inner sink. Writes are
+/// synchronous file system calls.
+///
+/// # Example
+///
+/// ```rust,ignore
+/// use lamco_clipboard_core::{CollisionPolicy, FileWriterSink};
+///
+/// let sink = FileWriterSink::new(portal_sink, downloads).with_collision_policy(CollisionPolicy::Skip);
+/// let requests = file_transfer::drive(&mut transfer, &sink, events).await?;
+/// ```
+#[derive(Debug)]
+pub struct FileWriterSink<S> {
+    inner: S,
+    destination: PathBuf,
+    collisions: CollisionPolicy,
+    fsync: FsyncPolicy,
+    sparse_threshold: u64,
+}
+
+impl<S: ClipboardSink> FileWriterSink<S> {
+    /// Wrap a sink, writing received files under `destination`
+    pub fn new(inner: S, destination: impl Into<PathBuf>) -> Self {
+        Self {
+            inner,
+            destination: destination.into(),
+            collisions: CollisionPolicy::default(),
+            fsync: FsyncPolicy::default(),
+            sparse_threshold: DEFAULT_SPARSE_THRESHOLD_BYTES,
+        }
+    }
+
+    /// Set what happens when a file of the same name exists
+    pub fn with_collision_policy(mut self, policy: CollisionPolicy) -> Self {
+        self.collisions = policy;
+        self
+    }
+
+    /// Set when written files are flushed to disk
+    pub fn with_fsync(mut self, fsync: FsyncPolicy) -> Self {
+        self.fsync = fsync;
+        self
+    }
+
+    /// Set the shortest run of zeros left as a hole (0 writes every byte)
+    pub fn with_sparse_threshold(mut self, bytes: u64) -> Self {
+        self.sparse_threshold = bytes;
+        self
+    }
+
+    /// Directory received files are written to
+    pub fn destination(&self) -> &Path {
+        &self.destination
+    }
+
+    /// Get the collision policy
+    pub fn collision_policy(&self) -> CollisionPolicy {
+        self.collisions
+    }
+
+    /// Get a reference to the wrapped sink
+    pub fn inner(&self) -> &S {
+        &self.inner
+    }
+
+    /// Unwrap the inner sink
+    pub fn into_inner(self) -> S {
+        self.inner
+    }
+
+    /// Write a received file, returning where it went, or None if skipped
+    pub fn write(&self, relative: &str, data: &[u8]) -> ClipboardResult<Option<PathBuf>> {
+        let target = resolve_relative(&self.destination, relative, "the destination")?;
+        // Spare writing the data when the outcome is already known
+        if fs::symlink_metadata(&target).is_ok() {
+            match self.collisions {
+                CollisionPolicy::Skip => {
+                    tracing::debug!("Skipping received file {:?}: already exists", target);
+                    return Ok(None);
+                }
+                CollisionPolicy::Error => return Err(ClipboardError::FileExists(target.display().to_string())),
+                CollisionPolicy::Overwrite | CollisionPolicy::Rename => {}
+            }
+        }
+
+        let partial = write_partial(&target, data, self.fsync, self.sparse_threshold)?;
+        let placed = self.place(&partial, &target);
+        if !matches!(placed, Ok(Some(_))) {
+            let _ = fs::remove_file(&partial);
+        }
+        if let Ok(Some(path)) = &placed {
+            if path != &target {
+                tracing::info!("Received file {:?} exists, wrote {:?}", target, path);
+            }
+            if self.fsync == FsyncPolicy::Directories {
+                sync_parent(path)?;
+            }
+        }
+        placed
+    }
+
+    /// Rename a complete partial file to its final name
+    fn place(&self, partial: &Path, target: &Path) -> ClipboardResult<Option<PathBuf>> {
+        if self.collisions == CollisionPolicy::Overwrite {
+            fs::rename(partial, target)?;
+            return Ok(Some(target.to_path_buf()));
+        }
+
+        for suffix in 0..=MAX_RENAME_SUFFIX {
+            let candidate = match suffix {
+                0 => target.to_path_buf(),
+                n => numbered(target, n),
+            };
+            match rename_new(partial, &candidate) {
+                Ok(()) => return Ok(Some(candidate)),
+                Err(e) if e.kind() == ErrorKind::AlreadyExists => match self.collisions {
+                    CollisionPolicy::Rename => continue,
+                    CollisionPolicy::Skip => return Ok(None),
+                    _ => return Err(ClipboardError::FileExists(candidate.display().to_string())),
+                },
+                Err(e) => return Err(e.into()),
+            }
+        }
+        Err(ClipboardError::FileExists(target.display().to_string()))
+    }
+}
+
+/// `name (n).ext` next to `target`
+fn numbered(target: &Path, n: u32) -> PathBuf {
+    let stem = target.file_stem().unwrap_or_default().to_string_lossy();
+    let name = match target.extension() {
+        Some(extension) => format!("{} ({}).{}", stem, n, extension.to_string_lossy()),
+        None => format!("{} ({})", stem, n),
+    };
+    target.with_file_name(name)
+}
+
+/// Move `from` to `to`, failing with `AlreadyExists` rather than replacing it
+fn rename_new(from: &Path, to: &Path) -> io::Result<()> {
+    match fs::hard_link(from, to) {
+        Ok(()) => {
+            let _ = fs::remove_file(from);
+            Ok(())
+        }
+        Err(e) if e.kind() == ErrorKind::AlreadyExists => Err(e),
+        // No hard links on this file system (FAT, some network shares)
+        Err(_) if fs::symlink_metadata(to).is_ok() => Err(ErrorKind::AlreadyExists.into()),
+        Err(_) => fs::rename(from, to),
+    }
+}
+
+impl<S: ClipboardSink> ClipboardSink for FileWriterSink<S> {
+    fn announce_formats(&self, mime_types: Vec<String>) -> impl Future<Output = ClipboardResult<()>> + Send {
+        self.inner.announce_formats(mime_types)
+    }
+
+    fn read_clipboard(&self, mime_type: &str) -> impl Future<Output = ClipboardResult<Vec<u8>>> + Send {
+        self.inner.read_clipboard(mime_type)
+    }
+
+    fn write_clipboard(&self, mime_type: &str, data: Vec<u8>) -> impl Future<Output = ClipboardResult<()>> + Send {
+        self.inner.write_clipboard(mime_type, data)
+    }
+
+    fn subscribe_changes(&self) -> impl Future<Output = ClipboardResult<ClipboardChangeReceiver>> + Send {
+        self.inner.subscribe_changes()
+    }
+
+    fn get_file_list(&self) -> impl Future<Output = ClipboardResult<Vec<FileInfo>>> + Send {
+        self.inner.get_file_list()
+    }
+
+    fn read_file_chunk(
+        &self,
+        index: u32,
+        offset: u64,
+        size: u32,
+    ) -> impl Future<Output = ClipboardResult<Vec<u8>>> + Send {
+        self.inner.read_file_chunk(index, offset, size)
+    }
+
+    async fn write_file(&self, path: &str, data: Vec<u8>) -> ClipboardResult<()> {
+        self.write(path, &data).map(|_| ())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    struct NullSink;
+
+    impl ClipboardSink for NullSink {
+        async fn announce_formats(&self, _mime_types: Vec<String>) -> ClipboardResult<()> {
+            Ok(())
+        }
+
+        async fn read_clipboard(&self, _mime_type: &str) -> ClipboardResult<Vec<u8>> {
+            Ok(Vec::new())
+        }
+
+        async fn write_clipboard(&self, _mime_type: &str, _data: Vec<u8>) -> ClipboardResult<()> {
+            Ok(())
+        }
+
+        async fn subscribe_changes(&self) -> ClipboardResult<ClipboardChangeReceiver> {
+            Err(ClipboardError::InvalidState("no changes".to_string()))
+        }
+
+        async fn get_file_list(&self) -> ClipboardResult<Vec<FileInfo>> {
+            Ok(Vec::new())
+        }
+
+        async fn read_file_chunk(&self, _index: u32, _offset: u64, _size: u32) -> ClipboardResult<Vec<u8>> {
+            Ok(Vec::new())
+        }
+
+        async fn write_file(&self, _path: &str, _data: Vec<u8>) -> ClipboardResult<()> {
+            Err(ClipboardError::InvalidState("inner sink written".to_string()))
+        }
+    }
+
+    fn destination(name: &str) -> PathBuf {
+        let path = std::env::temp_dir().join(format!("lamco-writer-{}-{}", name, std::process::id()));
+        let _ = fs::remove_dir_all(&path);
+        path
+    }
+
+    #[test]
+    fn test_collision_policies() {
+        let dir = destination("collisions");
+        let sink = FileWriterSink::new(NullSink, &dir);
+
+        let first = sink.write("docs/report.pdf", b"one").unwrap().unwrap();
+        assert_eq!(first, dir.join("docs/report.pdf"));
+        assert_eq!(
+            sink.write("docs/report.pdf", b"two").unwrap(),
+            Some(dir.join("docs/report (1).pdf"))
+        );
+        assert_eq!(
+            sink.write("docs/report.pdf", b"three").unwrap(),
+            Some(dir.join("docs/report (2).pdf"))
+        );
+        assert_eq!(sink.write("README", b"x").unwrap(), Some(dir.join("README")));
+        assert_eq!(sink.write("README", b"y").unwrap(), Some(dir.join("README (1)")));
+
+        let sink = sink.with_collision_policy(CollisionPolicy::Skip);
+        assert_eq!(sink.write("docs/report.pdf", b"four").unwrap(), None);
+        let sink = sink.with_collision_policy(CollisionPolicy::Error);
+        assert!(matches!(
+            sink.write("docs/report.pdf", b"five"),
+            Err(ClipboardError::FileExists(_))
+        ));
+        assert_eq!(fs::read(&first).unwrap(), b"one");
+
+        let sink = sink.with_collision_policy(CollisionPolicy::Overwrite);
+        assert_eq!(sink.write("docs/report.pdf", b"six").unwrap(), Some(first.clone()));
+        assert_eq!(fs::read(&first).unwrap(), b"six");
+
+        // Nothing partial is left behind, and nothing escapes the destination
+        assert!(sink.write("../escape.txt", b"").is_err());
+        let mut names: Vec<_> = fs::read_dir(dir.join("docs"))
+            .unwrap()
+            .map(|entry| entry.unwrap().file_name().into_string().unwrap())
+            .collect();
+        names.sort();
+        assert_eq!(names, ["report (1).pdf", "report (2).pdf", "report.pdf"]);
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_file_created_while_writing() {
+        let dir = destination("race");
+        let sink = FileWriterSink::new(NullSink, &dir).with_collision_policy(CollisionPolicy::Skip);
+        let target = dir.join("notes.txt");
+
+        // Another program takes the name between the check and the rename
+        let partial = write_partial(&target, b"received", FsyncPolicy::Never, 0).unwrap();
+        fs::write(&target, b"local").unwrap();
+        assert_eq!(sink.place(&partial, &target).unwrap(), None);
+        assert_eq!(fs::read(&target).unwrap(), b"local");
+
+        let sink = sink.with_collision_policy(CollisionPolicy::Rename);
+        assert_eq!(sink.place(&partial, &target).unwrap(), Some(dir.join("notes (1).txt")));
+        assert!(!partial.exists());
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_policy_names() {
+        for policy in CollisionPolicy::ALL {
+            assert_eq!(policy.as_str().parse::<CollisionPolicy>().unwrap(), policy);
+        }
+        assert!("merge".parse::<CollisionPolicy>().is_err());
+    }
+}
@@ -0,0 +1,631 @@
+//! Flow control for clipboard requests.
+//!
+//! Pasting a large folder makes the receiving side ask for the contents of
+//! every file, and a peer can do the same to us. Issuing or servicing all of
+//! those requests at once keeps a buffer alive per request and can exhaust
+//! memory long before the transfer finishes.
+//!
+//! A [`RequestWindow`] caps how many requests are in flight and queues the
+//! rest in FIFO order. Sessions use one window for requests issued to the
+//! peer (Format Data Requests and File Contents Requests) and another for
+//! peer requests being serviced; [`FlowControlConfig`] sizes both.
+//!
+//! Requests carry a [`TransferPriority`]. Interactive requests, such as the
+//! chunks of a 4 KB config file, leave the queue before bulk ones, and bulk
+//! requests may not take the last
+//! [`reserved_interactive_slots`](FlowControlConfig::reserved_interactive_slots)
+//! slots, so a quick copy preempts a multi-gigabyte paste running on the
+//! same session instead of waiting for it.
+//!
+//! Format Data Responses carry no request ID and are matched to requests in
+//! the order they were sent. Because the window releases queued requests of
+//! one priority in submission order, that matching still holds as long as
+//! every Format Data Request is submitted at the same priority.
+//!
+//! File contents share the RDP connection with graphics and input, so a
+//! multi-gigabyte copy at full speed makes the session stutter. A
+//! [`RateLimiter`] caps the bytes per second of File Contents Responses we
+//! send, and [`FileTransfer`](crate::file_transfer::FileTransfer) paces the
+//! requests it issues with one of its own.
+
+use crate::clock::{Clock, SystemClock};
+use crate::{ClipboardError, ClipboardResult};
+use std::collections::VecDeque;
+use std::fmt;
+use std::str::FromStr;
+use std::sync::{Arc, Mutex, PoisonError};
+use std::time::Duration;
+
+/// Default number of requests awaiting a response from the peer
+pub const DEFAULT_MAX_OUTSTANDING_REQUESTS: usize = 4;
+
+/// Default number of peer requests serviced at once
+pub const DEFAULT_MAX_CONCURRENT_SERVICING: usize = 4;
+
+/// Default number of requests waiting for a slot, per direction
+pub const DEFAULT_MAX_QUEUED_REQUESTS: usize = 1024;
+
+/// Default bytes a [`RateLimiter`] lets through at once after being idle: 1 MiB
+pub const DEFAULT_BURST_BYTES: u64 = 1024 * 1024;
+
+/// Default largest transfer still scheduled as interactive: 1 MiB
+pub const DEFAULT_INTERACTIVE_MAX_BYTES: u64 = 1024 * 1024;
+
+/// Default slots per window that bulk requests leave free
+pub const DEFAULT_RESERVED_INTERACTIVE_SLOTS: usize = 1;
+
+/// Scheduling class of a request in a [`RequestWindow`]
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, PartialOrd, Ord, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "serde", serde(rename_all = "snake_case"))]
+pub enum TransferPriority {
+    /// Small transfers a user is waiting on; served first
+    #[default]
+    Interactive,
+
+    /// Large transfers; yield to interactive requests
+    Bulk,
+}
+
+impl TransferPriority {
+    /// Every priority, highest first
+    pub const ALL: [Self; 2] = [Self::Interactive, Self::Bulk];
+
+    /// Name used in configuration files and logs
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Self::Interactive => "interactive",
+            Self::Bulk => "bulk",
+        }
+    }
+}
+
+impl fmt::Display for TransferPriority {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+impl FromStr for TransferPriority {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Self::ALL
+            .into_iter()
+            .find(|priority| priority.as_str().eq_ignore_ascii_case(s.trim()))
+            .ok_or_else(|| format!("unknown transfer priority '{}'", s))
+    }
+}
+
+/// Limits for [`RequestWindow`]s in both directions
+#[derive(Debug, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "serde", serde(default))]
+pub struct FlowControlConfig {
+    /// Requests issued to the peer that may await a response at once
+    pub max_outstanding_requests: usize,
+
+    /// Peer requests serviced at once
+    pub max_concurrent_servicing: usize,
+
+    /// Requests that may wait for a slot, per direction
+    pub max_queued_requests: usize,
+
+    /// Bytes per second of File Contents Responses sent to the peer
+    /// (default: None, unlimited)
+    pub max_bytes_per_second: Option<u64>,
+
+    /// Bytes that may be sent at once after being idle (default: 1 MiB)
+    pub burst_bytes: u64,
+
+    /// Largest transfer scheduled as [`TransferPriority::Interactive`]
+    /// (default: 1 MiB)
+    pub interactive_max_bytes: u64,
+
+    /// Slots per window that bulk requests leave free for interactive ones
+    /// (default: 1, 0 disables preemption)
+    pub reserved_interactive_slots: usize,
+}
+
+impl Default for FlowControlConfig {
+    fn default() -> Self {
+        Self {
+            max_outstanding_requests: DEFAULT_MAX_OUTSTANDING_REQUESTS,
+            max_concurrent_servicing: DEFAULT_MAX_CONCURRENT_SERVICING,
+            max_queued_requests: DEFAULT_MAX_QUEUED_REQUESTS,
+            max_bytes_per_second: None,
+            burst_bytes: DEFAULT_BURST_BYTES,
+            interactive_max_bytes: DEFAULT_INTERACTIVE_MAX_BYTES,
+            reserved_interactive_slots: DEFAULT_RESERVED_INTERACTIVE_SLOTS,
+        }
+    }
+}
+
+impl FlowControlConfig {
+    /// Window for requests issued to the peer
+    pub fn outgoing_window<R>(&self) -> RequestWindow<R> {
+        RequestWindow::new(self.max_outstanding_requests, self.max_queued_requests)
+            .with_reserved_interactive_slots(self.reserved_interactive_slots)
+    }
+
+    /// Window for peer requests being serviced
+    pub fn incoming_window<R>(&self) -> RequestWindow<R> {
+        RequestWindow::new(self.max_concurrent_servicing, self.max_queued_requests)
+            .with_reserved_interactive_slots(self.reserved_interactive_slots)
+    }
+
+    /// Priority of a transfer of `total_bytes`, such as a paste's
+    /// [`total_bytes`](crate::file_transfer::FileTransfer::total_bytes) or
+    /// the size of the file a peer request reads from
+    pub fn priority(&self, total_bytes: u64) -> TransferPriority {
+        if total_bytes <= self.interactive_max_bytes {
+            TransferPriority::Interactive
+        } else {
+            TransferPriority::Bulk
+        }
+    }
+
+    /// Limiter for File Contents Responses, if a rate is configured
+    pub fn response_limiter(&self) -> Option<RateLimiter> {
+        self.max_bytes_per_second
+            .map(|rate| RateLimiter::new(rate, self.burst_bytes))
+    }
+}
+
+/// Counters describing a [`RequestWindow`]
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct WindowMetrics {
+    /// Requests currently in flight
+    pub in_flight: usize,
+
+    /// Requests currently waiting for a slot
+    pub queued: usize,
+
+    /// Highest number of requests in flight at once
+    pub peak_in_flight: usize,
+
+    /// Highest number of requests waiting at once
+    pub peak_queued: usize,
+
+    /// Requests that were started, immediately or after waiting
+    pub started: u64,
+
+    /// Requests that had to wait for a slot
+    pub deferred: u64,
+
+    /// Requests refused because the queue was full
+    pub rejected: u64,
+
+    /// Queued requests discarded before they started
+    pub dropped: u64,
+}
+
+/// Bounded set of in-flight requests with a FIFO overflow queue per
+/// [`TransferPriority`].
+///
+/// The window does not send anything itself: [`submit`](Self::submit) and
+/// [`complete`](Self::complete) hand back the request that may start now,
+/// and the caller sends or services it. Queued interactive requests start
+/// before queued bulk ones.
+///
+/// # Example
+///
+/// ```
+/// use lamco_clipboard_core::RequestWindow;
+///
+/// // File Contents Requests for a large folder paste: (stream ID, file index)
+/// let mut window = RequestWindow::new(2, 100);
+/// assert_eq!(window.submit((1, 0)).unwrap(), Some((1, 0)));
+/// assert_eq!(window.submit((2, 1)).unwrap(), Some((2, 1)));
+/// assert_eq!(window.submit((3, 2)).unwrap(), None); // queued
+///
+/// // The response for stream 1 frees a slot for the queued request
+/// assert_eq!(window.complete(), Some((3, 2)));
+/// assert_eq!(window.metrics().deferred, 1);
+/// ```
+///
+/// A small copy overtaking a large paste:
+///
+/// ```
+/// use lamco_clipboard_core::{RequestWindow, TransferPriority};
+///
+/// let mut window = RequestWindow::new(3, 100).with_reserved_interactive_slots(1);
+/// for chunk in 0..4 {
+///     window.submit_with_priority(("paste", chunk), TransferPriority::Bulk).unwrap();
+/// }
+/// // Two bulk chunks are in flight; the third slot is held back
+/// assert_eq!(window.in_flight(), 2);
+/// assert_eq!(window.submit(("config", 0)).unwrap(), Some(("config", 0)));
+///
+/// window.submit(("config", 1)).unwrap();
+/// assert_eq!(window.complete(), Some(("config", 1)));
+/// ```
+#[derive(Debug)]
+pub struct RequestWindow<R> {
+    max_in_flight: usize,
+    max_queued: usize,
+    reserved: usize,
+    in_flight: usize,
+    interactive: VecDeque<R>,
+    bulk: VecDeque<R>,
+    metrics: WindowMetrics,
+}
+
+impl<R> RequestWindow<R> {
+    /// Create a window allowing `max_in_flight` requests and `max_queued` waiting ones
+    pub fn new(max_in_flight: usize, max_queued: usize) -> Self {
+        Self {
+            max_in_flight: max_in_flight.max(1),
+            max_queued,
+            reserved: 0,
+            in_flight: 0,
+            interactive: VecDeque::new(),
+            bulk: VecDeque::new(),
+            metrics: WindowMetrics::default(),
+        }
+    }
+
+    /// Keep `slots` slots free of bulk requests
+    ///
+    /// At least one slot stays usable by bulk requests, so a window of one
+    /// still makes progress on a large paste.
+    pub fn with_reserved_interactive_slots(mut self, slots: usize) -> Self {
+        self.reserved = slots;
+        self
+    }
+
+    /// Offer an interactive request to the window.
+    ///
+    /// Returns `Ok(Some(request))` if it may start now and `Ok(None)` if it
+    /// was queued. Fails with [`ClipboardError::QueueFull`] when the queue is
+    /// at its limit; the request is dropped and the caller should answer or
+    /// abandon it.
+    pub fn submit(&mut self, request: R) -> ClipboardResult<Option<R>> {
+        self.submit_with_priority(request, TransferPriority::Interactive)
+    }
+
+    /// Offer a request of the given priority to the window, as [`submit`](Self::submit)
+    pub fn submit_with_priority(&mut self, request: R, priority: TransferPriority) -> ClipboardResult<Option<R>> {
+        let may_start = match priority {
+            TransferPriority::Interactive => self.interactive.is_empty() && self.in_flight < self.max_in_flight,
+            TransferPriority::Bulk => self.queued() == 0 && self.in_flight < self.bulk_limit(),
+        };
+        if may_start {
+            self.start();
+            return Ok(Some(request));
+        }
+
+        if self.queued() >= self.max_queued {
+            self.metrics.rejected += 1;
+            return Err(ClipboardError::QueueFull { limit: self.max_queued });
+        }
+
+        match priority {
+            TransferPriority::Interactive => self.interactive.push_back(request),
+            TransferPriority::Bulk => self.bulk.push_back(request),
+        }
+        self.metrics.deferred += 1;
+        self.metrics.peak_queued = self.metrics.peak_queued.max(self.queued());
+        Ok(None)
+    }
+
+    /// Mark one in-flight request as finished.
+    ///
+    /// Returns the next queued request, which now occupies the freed slot.
+    pub fn complete(&mut self) -> Option<R> {
+        if self.in_flight == 0 {
+            tracing::debug!("Request window completion with nothing in flight");
+        }
+        self.in_flight = self.in_flight.saturating_sub(1);
+
+        let next = if !self.interactive.is_empty() && self.in_flight < self.max_in_flight {
+            self.interactive.pop_front()
+        } else if self.in_flight < self.bulk_limit() {
+            self.bulk.pop_front()
+        } else {
+            None
+        }?;
+        self.start();
+        Some(next)
+    }
+
+    /// Discard every queued request, returning how many were dropped.
+    ///
+    /// Used when queued requests become stale, e.g. after a new Format List
+    /// replaces the content they refer to. In-flight requests are unaffected.
+    pub fn clear_queued(&mut self) -> usize {
+        let dropped = self.queued();
+        self.interactive.clear();
+        self.bulk.clear();
+        self.metrics.dropped += dropped as u64;
+        dropped
+    }
+
+    /// Forget all requests, in flight or queued (e.g. when the channel resets)
+    pub fn reset(&mut self) {
+        self.clear_queued();
+        self.in_flight = 0;
+    }
+
+    /// Number of requests in flight
+    pub fn in_flight(&self) -> usize {
+        self.in_flight
+    }
+
+    /// Number of requests waiting for a slot
+    pub fn queued(&self) -> usize {
+        self.interactive.len() + self.bulk.len()
+    }
+
+    /// Number of requests of one priority waiting for a slot
+    pub fn queued_with_priority(&self, priority: TransferPriority) -> usize {
+        match priority {
+            TransferPriority::Interactive => self.interactive.len(),
+            TransferPriority::Bulk => self.bulk.len(),
+        }
+    }
+
+    /// Whether a submitted interactive request would start immediately
+    pub fn has_capacity(&self) -> bool {
+        self.in_flight < self.max_in_flight && self.interactive.is_empty()
+    }
+
+    /// Snapshot of the window's counters
+    pub fn metrics(&self) -> WindowMetrics {
+        WindowMetrics {
+            in_flight: self.in_flight,
+            queued: self.queued(),
+            ..self.metrics
+        }
+    }
+
+    /// Slots bulk requests may occupy
+    fn bulk_limit(&self) -> usize {
+        self.max_in_flight.saturating_sub(self.reserved).max(1)
+    }
+
+    fn start(&mut self) {
+        self.in_flight += 1;
+        self.metrics.started += 1;
+        self.metrics.peak_in_flight = self.metrics.peak_in_flight.max(self.in_flight);
+    }
+}
+
+/// Token bucket limiting bytes per second.
+///
+/// The bucket holds up to `burst_bytes` and refills at `bytes_per_second`.
+/// A chunk may go once the bucket holds its size (or is full, for chunks
+/// larger than the burst); the bucket may then go into debt, which delays
+/// the chunks after it. Shared by reference, so one limiter can pace every
+/// response of a session.
+///
+/// # Example
+///
+/// ```
+/// use lamco_clipboard_core::{ManualClock, RateLimiter};
+/// use std::time::Duration;
+///
+/// let clock = ManualClock::new();
+/// let limiter = RateLimiter::new(1000, 500).with_clock(clock.clone());
+/// assert!(limiter.try_acquire(500).is_ok());
+/// assert_eq!(limiter.try_acquire(100), Err(Duration::from_millis(100)));
+///
+/// clock.advance(Duration::from_millis(100));
+/// assert!(limiter.try_acquire(100).is_ok());
+/// ```
+#[derive(Debug)]
+pub struct RateLimiter {
+    bytes_per_second: u64,
+    burst_bytes: u64,
+    clock: Arc<dyn Clock>,
+    /// Bytes in the bucket (negative: debt) and when it was last refilled
+    bucket: Mutex<(f64, Duration)>,
+}
+
+impl RateLimiter {
+    /// Create a limiter that starts with a full bucket
+    pub fn new(bytes_per_second: u64, burst_bytes: u64) -> Self {
+        let clock: Arc<dyn Clock> = Arc::new(SystemClock);
+        let burst_bytes = burst_bytes.max(1);
+        Self {
+            bytes_per_second: bytes_per_second.max(1),
+            burst_bytes,
+            bucket: Mutex::new((burst_bytes as f64, clock.now())),
+            clock,
+        }
+    }
+
+    /// Read time from `clock` instead of the system clock
+    pub fn with_clock(mut self, clock: impl Clock + 'static) -> Self {
+        self.clock = Arc::new(clock);
+        self.bucket = Mutex::new((self.burst_bytes as f64, self.clock.now()));
+        self
+    }
+
+    /// Configured rate
+    pub fn bytes_per_second(&self) -> u64 {
+        self.bytes_per_second
+    }
+
+    /// Take `bytes` from the bucket, or return how long to wait before
+    /// trying again
+    pub fn try_acquire(&self, bytes: u64) -> Result<(), Duration> {
+        let now = self.clock.now();
+        let mut bucket = self.bucket.lock().unwrap_or_else(PoisonError::into_inner);
+        let (tokens, refilled) = &mut *bucket;
+
+        let elapsed = now.saturating_sub(*refilled).as_secs_f64();
+        *tokens = (*tokens + elapsed * self.bytes_per_second as f64).min(self.burst_bytes as f64);
+        *refilled = now;
+
+        let needed = bytes.min(self.burst_bytes) as f64;
+        if *tokens >= needed {
+            *tokens -= bytes as f64;
+            Ok(())
+        } else {
+            Err(Duration::from_secs_f64(
+                (needed - *tokens) / self.bytes_per_second as f64,
+            ))
+        }
+    }
+
+    /// Wait until `bytes` may be sent, then take them from the bucket
+    #[cfg(feature = "tokio")]
+    pub async fn acquire(&self, bytes: u64) {
+        while let Err(wait) = self.try_acquire(bytes) {
+            tokio::time::sleep(wait).await;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_window_queues_in_order() {
+        let mut window = RequestWindow::new(1, 10);
+        assert_eq!(window.submit(1).unwrap(), Some(1));
+        assert_eq!(window.submit(2).unwrap(), None);
+        assert_eq!(window.submit(3).unwrap(), None);
+        assert!(!window.has_capacity());
+
+        assert_eq!(window.complete(), Some(2));
+        assert_eq!(window.complete(), Some(3));
+        assert_eq!(window.complete(), None);
+        assert_eq!(window.in_flight(), 0);
+
+        let metrics = window.metrics();
+        assert_eq!(metrics.started, 3);
+        assert_eq!(metrics.deferred, 2);
+        assert_eq!(metrics.peak_in_flight, 1);
+        assert_eq!(metrics.peak_queued, 2);
+    }
+
+    #[test]
+    fn test_window_rejects_when_queue_full() {
+        let mut window = RequestWindow::new(1, 1);
+        window.submit("a").unwrap();
+        window.submit("b").unwrap();
+
+        let err = window.submit("c").unwrap_err();
+        assert!(matches!(err, ClipboardError::QueueFull { limit: 1 }));
+        assert_eq!(window.metrics().rejected, 1);
+        assert_eq!(window.queued(), 1);
+    }
+
+    #[test]
+    fn test_clear_and_reset() {
+        let mut window = RequestWindow::new(2, 10);
+        for i in 0..5 {
+            window.submit(i).unwrap();
+        }
+        assert_eq!(window.clear_queued(), 3);
+        assert_eq!(window.in_flight(), 2);
+        assert_eq!(window.metrics().dropped, 3);
+
+        window.reset();
+        assert!(window.has_capacity());
+        assert_eq!(window.metrics().in_flight, 0);
+    }
+
+    #[test]
+    fn test_config_windows() {
+        let config = FlowControlConfig {
+            max_outstanding_requests: 1,
+            max_concurrent_servicing: 3,
+            max_queued_requests: 8,
+            ..Default::default()
+        };
+
+        let mut outgoing = config.outgoing_window();
+        outgoing.submit(()).unwrap();
+        assert!(!outgoing.has_capacity());
+
+        let mut incoming = config.incoming_window();
+        for _ in 0..3 {
+            assert!(incoming.submit(()).unwrap().is_some());
+        }
+        assert!(incoming.submit(()).unwrap().is_none());
+    }
+
+    #[test]
+    fn test_priorities() {
+        let config = FlowControlConfig {
+            max_outstanding_requests: 4,
+            reserved_interactive_slots: 2,
+            ..Default::default()
+        };
+        assert_eq!(config.priority(4096), TransferPriority::Interactive);
+        assert_eq!(config.priority(4 << 30), TransferPriority::Bulk);
+
+        let mut window = config.outgoing_window();
+        for chunk in 0..5 {
+            window.submit_with_priority(chunk, TransferPriority::Bulk).unwrap();
+        }
+        assert_eq!(window.in_flight(), 2);
+        assert!(window.has_capacity());
+
+        // Interactive requests use the reserved slots, then queue ahead of
+        // bulk ones
+        assert_eq!(window.submit(100).unwrap(), Some(100));
+        assert_eq!(window.submit(101).unwrap(), Some(101));
+        assert_eq!(window.submit(102).unwrap(), None);
+        assert_eq!(window.queued_with_priority(TransferPriority::Bulk), 3);
+        assert_eq!(window.complete(), Some(102));
+
+        // Freed slots only go to bulk requests below the limit
+        assert_eq!(window.complete(), None);
+        assert_eq!(window.complete(), None);
+        assert_eq!(window.complete(), Some(2));
+        assert_eq!(window.in_flight(), 2);
+
+        // A new bulk request queues behind the waiting ones
+        assert_eq!(window.submit_with_priority(5, TransferPriority::Bulk).unwrap(), None);
+        assert_eq!(window.complete(), Some(3));
+
+        // Reserving every slot still leaves bulk requests one
+        let mut window = RequestWindow::new(1, 10).with_reserved_interactive_slots(3);
+        assert!(window
+            .submit_with_priority(0, TransferPriority::Bulk)
+            .unwrap()
+            .is_some());
+
+        for priority in TransferPriority::ALL {
+            assert_eq!(priority.as_str().parse::<TransferPriority>().unwrap(), priority);
+        }
+    }
+
+    #[test]
+    fn test_rate_limiter_paces_to_rate() {
+        let clock = crate::ManualClock::new();
+        let limiter = FlowControlConfig {
+            max_bytes_per_second: Some(1000),
+            burst_bytes: 200,
+            ..Default::default()
+        }
+        .response_limiter()
+        .unwrap()
+        .with_clock(clock.clone());
+
+        // A chunk larger than the burst goes once the bucket is full, then
+        // its debt holds back what follows
+        assert!(limiter.try_acquire(700).is_ok());
+        assert_eq!(limiter.try_acquire(100), Err(Duration::from_millis(600)));
+
+        // Idle time refills no further than the burst
+        clock.advance(Duration::from_secs(10));
+        assert!(limiter.try_acquire(200).is_ok());
+        assert!(limiter.try_acquire(1).is_err());
+
+        let mut sent = 0;
+        for _ in 0..100 {
+            clock.advance(Duration::from_millis(10));
+            while limiter.try_acquire(50).is_ok() {
+                sent += 50;
+            }
+        }
+        assert_eq!(sent, 1000);
+        assert!(FlowControlConfig::default().response_limiter().is_none());
+    }
+}
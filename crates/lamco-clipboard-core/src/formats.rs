@@ -3,7 +3,16 @@
 //! This module handles conversion between MIME types and Windows clipboard format IDs,
 //! as well as data conversion between formats.
 
+use crate::converter::ConverterMap;
+use crate::metrics::FailureCounters;
+use crate::sanitize::{
+    convert_line_endings_to_unix, convert_line_endings_to_windows, file_uri_path_to_hdrop, has_file_scheme,
+    hdrop_path_to_file_uri, is_local_host, percent_decode_bytes, split_file_uri, system_hostname, uri_list_entries,
+    PathMapper,
+};
 use crate::{ClipboardError, ClipboardResult};
+use std::borrow::Cow;
+use std::ops::Range;
 
 // =============================================================================
 // Windows Clipboard Format IDs
@@ -26,6 +35,21 @@ pub const CF_DIB: u32 = 8;
 /// Extended bitmap format with alpha channel and color space support (124-byte header)
 pub const CF_DIBV5: u32 = 17;
 
+/// Standard Windows clipboard format: Tagged Image File Format
+/// Scanning and imaging applications publish this as their primary image format
+pub const CF_TIFF: u32 = 6;
+
+/// Private display format for CF_BITMAP (CF_DSPBITMAP)
+/// Applications that render their own bitmaps publish it; over RDP it arrives as a packed DIB
+pub const CF_DSPBITMAP: u32 = 0x0082;
+
+/// Standard Windows clipboard format: Windows metafile picture (METAFILEPICT header + WMF)
+pub const CF_METAFILEPICT: u32 = 3;
+
+/// Standard Windows clipboard format: Enhanced metafile (EMF)
+/// Office applications often offer charts and shapes only in metafile formats
+pub const CF_ENHMETAFILE: u32 = 14;
+
 /// Standard Windows clipboard format: File drop list
 pub const CF_HDROP: u32 = 15;
 
@@ -35,6 +59,15 @@ pub const CF_WAVE: u32 = 12;
 /// Standard Windows clipboard format: RIFF audio
 pub const CF_RIFF: u32 = 11;
 
+/// Standard Windows clipboard format: Locale identifier (LCID) for CF_TEXT
+/// A 4-byte little-endian LCID selecting the ANSI codepage of accompanying CF_TEXT
+pub const CF_LOCALE: u32 = 16;
+
+// Registered formats have no fixed IDs: the values below are the local IDs used
+// when announcing. IDs received from a peer must be resolved by name through
+// `FormatRegistry` or `ClipboardFormat::mime_type` instead of being compared
+// against these constants; `rdp_format_to_mime` does not know them.
+
 /// Custom format: HTML (registered format name: "HTML Format")
 pub const CF_HTML: u32 = 0xD010;
 
@@ -50,6 +83,56 @@ pub const CF_GIF: u32 = 0xD013;
 /// Custom format: Rich Text Format
 pub const CF_RTF: u32 = 0xD014;
 
+/// Custom format: URL object (registered format name: "UniformResourceLocatorW")
+/// Null-terminated UTF-16LE URL
+pub const CF_URLW: u32 = 0xD015;
+
+/// Custom format: URL object (registered format name: "UniformResourceLocator")
+/// Null-terminated ANSI URL, for applications that predate the wide variant
+pub const CF_URL: u32 = 0xD016;
+
+/// Custom format: Chromium web custom data
+/// (registered format name: "Chromium Web Custom MIME Data Format")
+/// A pickled map of page-defined MIME types, see [`crate::web_custom_data`]
+pub const CF_WEB_CUSTOM_DATA: u32 = 0xD017;
+
+/// Custom format: comma-separated values (registered format name: "Csv")
+/// ANSI text, as written by Excel
+pub const CF_CSV: u32 = 0xD018;
+
+/// Custom format: SpreadsheetML 2003 workbook (registered format name: "XML Spreadsheet")
+/// Excel's cell-preserving clipboard format, see [`crate::spreadsheet`]
+pub const CF_XML_SPREADSHEET: u32 = 0xD019;
+
+/// Custom format: preferred drop effect (registered format name: "Preferred DropEffect")
+/// A DWORD of `DROPEFFECT_*` flags telling the paste target whether to copy or move files
+pub const CF_PREFERRED_DROP_EFFECT: u32 = 0xD01A;
+
+/// Custom format: WebP image (registered format name: "image/webp", also seen as "WebP")
+pub const CF_WEBP: u32 = 0xD01B;
+
+/// Custom format: AVIF image (registered format name: "image/avif")
+pub const CF_AVIF: u32 = 0xD01C;
+
+/// Custom format: SVG drawing (registered format name: "image/svg+xml")
+pub const CF_SVG: u32 = 0xD01D;
+
+/// Custom format: Windows icon or cursor file (registered format name: "image/x-icon")
+pub const CF_ICO: u32 = 0xD01E;
+
+/// Custom format: checksums of copied files (registered format name: "LamcoFileChecksums")
+/// xxh3 checksums of the files in the FileGroupDescriptorW, see [`crate::file_transfer::FileChecksums`]
+pub const CF_FILE_CHECKSUMS: u32 = 0xD01F;
+
+/// Drop effect flag: the target copies the files
+pub const DROPEFFECT_COPY: u32 = 1;
+
+/// Drop effect flag: the target moves the files (a cut)
+pub const DROPEFFECT_MOVE: u32 = 2;
+
+/// Drop effect flag: the target links to the files
+pub const DROPEFFECT_LINK: u32 = 4;
+
 /// File transfer format: FileGroupDescriptorW (registered format name)
 /// Used for clipboard file transfer with delayed rendering (copy/paste, not drag/drop)
 /// Contains metadata about files without actual data
@@ -65,6 +148,7 @@ pub const CF_FILECONTENTS: u32 = 49338;
 
 /// A clipboard format with ID and optional name
 #[derive(Debug, Clone, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct ClipboardFormat {
     /// Windows clipboard format ID
     pub id: u32,
@@ -106,6 +190,24 @@ impl ClipboardFormat {
     pub fn file_drop() -> Self {
         Self::new(CF_HDROP)
     }
+
+    /// Preferred MIME type, by name for registered formats and by ID otherwise
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use lamco_clipboard_core::formats::ClipboardFormat;
+    ///
+    /// // Registered IDs differ between peers; only the name counts
+    /// assert_eq!(ClipboardFormat::with_name(0xC0A3, "HTML Format").mime_type(), Some("text/html"));
+    /// assert_eq!(ClipboardFormat::new(0xC0A3).mime_type(), None);
+    /// ```
+    pub fn mime_type(&self) -> Option<&'static str> {
+        match self.name.as_deref() {
+            Some(name) => crate::registry::registered_name_to_mime(name),
+            None => rdp_format_to_mime(self.id),
+        }
+    }
 }
 
 // =============================================================================
@@ -141,13 +243,51 @@ pub fn mime_to_rdp_formats(mime_types: &[&str]) -> Vec<ClipboardFormat> {
             }
 
             "text/html" => {
-                formats.push(ClipboardFormat::html());
+                if !formats.iter().any(|f: &ClipboardFormat| f.id == CF_HTML) {
+                    formats.push(ClipboardFormat::html());
+                }
+            }
+
+            // Markdown has no Windows format; it travels as rendered HTML plus its source text
+            #[cfg(feature = "markdown")]
+            "text/markdown" => {
+                if !formats.iter().any(|f: &ClipboardFormat| f.id == CF_HTML) {
+                    formats.push(ClipboardFormat::html());
+                }
+                if !formats.iter().any(|f: &ClipboardFormat| f.id == CF_UNICODETEXT) {
+                    formats.push(ClipboardFormat::unicode_text());
+                    formats.push(ClipboardFormat::new(CF_TEXT));
+                    formats.push(ClipboardFormat::new(CF_OEMTEXT));
+                }
             }
 
             "text/rtf" | "application/rtf" => {
                 formats.push(ClipboardFormat::with_name(CF_RTF, "Rich Text Format"));
             }
 
+            // URL objects (Firefox/Chromium link copies)
+            "text/x-moz-url" | "_NETSCAPE_URL" => {
+                if !formats.iter().any(|f: &ClipboardFormat| f.id == CF_URLW) {
+                    formats.push(ClipboardFormat::with_name(CF_URLW, "UniformResourceLocatorW"));
+                    formats.push(ClipboardFormat::with_name(CF_URL, "UniformResourceLocator"));
+                }
+            }
+
+            "chromium/x-web-custom-data" => {
+                formats.push(ClipboardFormat::with_name(
+                    CF_WEB_CUSTOM_DATA,
+                    "Chromium Web Custom MIME Data Format",
+                ));
+            }
+
+            // Tabular data: the XML workbook keeps cell boundaries, Csv is the fallback
+            "text/csv" => {
+                if !formats.iter().any(|f: &ClipboardFormat| f.id == CF_XML_SPREADSHEET) {
+                    formats.push(ClipboardFormat::with_name(CF_XML_SPREADSHEET, "XML Spreadsheet"));
+                    formats.push(ClipboardFormat::with_name(CF_CSV, "Csv"));
+                }
+            }
+
             // Image formats
             "image/png" => {
                 formats.push(ClipboardFormat::png());
@@ -176,6 +316,51 @@ pub fn mime_to_rdp_formats(mime_types: &[&str]) -> Vec<ClipboardFormat> {
                 formats.push(ClipboardFormat::new(CF_DIB));
             }
 
+            "image/tiff" => {
+                formats.push(ClipboardFormat::new(CF_TIFF));
+                #[cfg(feature = "image")]
+                push_bitmaps(&mut formats);
+            }
+
+            // Passed through as-is; decoded to bitmaps for peers without a decoder
+            "image/webp" => {
+                formats.push(ClipboardFormat::with_name(CF_WEBP, "image/webp"));
+                #[cfg(feature = "webp")]
+                push_bitmaps(&mut formats);
+            }
+
+            "image/avif" => {
+                formats.push(ClipboardFormat::with_name(CF_AVIF, "image/avif"));
+                #[cfg(feature = "avif")]
+                push_bitmaps(&mut formats);
+            }
+
+            // Vector art for peers that read SVG, rasterized for everyone else
+            "image/svg+xml" => {
+                formats.push(ClipboardFormat::with_name(CF_SVG, "image/svg+xml"));
+                #[cfg(feature = "svg")]
+                {
+                    if !formats.iter().any(|f| f.id == CF_PNG) {
+                        formats.push(ClipboardFormat::png());
+                    }
+                    push_bitmaps(&mut formats);
+                }
+            }
+
+            // Icons and cursors, with their largest frame as a bitmap
+            "image/x-icon" | "image/vnd.microsoft.icon" => {
+                if !formats.iter().any(|f| f.id == CF_ICO) {
+                    formats.push(ClipboardFormat::with_name(CF_ICO, "image/x-icon"));
+                }
+                #[cfg(feature = "image")]
+                {
+                    if !formats.iter().any(|f| f.id == CF_PNG) {
+                        formats.push(ClipboardFormat::png());
+                    }
+                    push_bitmaps(&mut formats);
+                }
+            }
+
             // File formats - use RDP registered formats for clipboard file transfer
             "text/uri-list" | "x-special/gnome-copied-files" => {
                 // For RDP file transfer, we need FileGroupDescriptorW (file list metadata)
@@ -188,11 +373,30 @@ pub fn mime_to_rdp_formats(mime_types: &[&str]) -> Vec<ClipboardFormat> {
                     formats.push(ClipboardFormat::with_name(0, "FileGroupDescriptorW"));
                     formats.push(ClipboardFormat::with_name(0, "FileContents"));
                 }
+                // GNOME's list carries the cut/copy verb, which Explorer reads from the drop effect
+                if *mime == "x-special/gnome-copied-files" {
+                    push_drop_effect(&mut formats);
+                }
+            }
+
+            // KDE marks a cut with a separate flag next to text/uri-list
+            "application/x-kde-cutselection" => {
+                push_drop_effect(&mut formats);
             }
 
             // Audio formats
             "audio/wav" | "audio/x-wav" => {
-                formats.push(ClipboardFormat::new(CF_WAVE));
+                push_wave(&mut formats);
+            }
+
+            #[cfg(feature = "flac")]
+            "audio/flac" | "audio/x-flac" => {
+                push_wave(&mut formats);
+            }
+
+            // Raw PCM, wrapped into a WAV container by `audio::l16_to_wav`
+            mime if crate::audio::is_l16_mime(mime) => {
+                push_wave(&mut formats);
             }
 
             _ => {
@@ -205,7 +409,39 @@ pub fn mime_to_rdp_formats(mime_types: &[&str]) -> Vec<ClipboardFormat> {
     formats
 }
 
-/// Convert RDP format ID to preferred MIME type
+/// Announce CF_DIBV5 and CF_DIB once, for images decoded to bitmaps
+#[cfg(feature = "image")]
+fn push_bitmaps(formats: &mut Vec<ClipboardFormat>) {
+    for id in [CF_DIBV5, CF_DIB] {
+        if !formats.iter().any(|f| f.id == id) {
+            formats.push(ClipboardFormat::new(id));
+        }
+    }
+}
+
+/// Announce CF_WAVE once
+fn push_wave(formats: &mut Vec<ClipboardFormat>) {
+    if !formats.iter().any(|f| f.id == CF_WAVE) {
+        formats.push(ClipboardFormat::new(CF_WAVE));
+    }
+}
+
+/// Announce "Preferred DropEffect" once
+fn push_drop_effect(formats: &mut Vec<ClipboardFormat>) {
+    if !formats.iter().any(|f| f.id == CF_PREFERRED_DROP_EFFECT) {
+        formats.push(ClipboardFormat::with_name(
+            CF_PREFERRED_DROP_EFFECT,
+            "Preferred DropEffect",
+        ));
+    }
+}
+
+/// Convert a standard RDP format ID to its preferred MIME type
+///
+/// Only formats with fixed IDs (below `0xC000`) are known. Registered
+/// formats such as "HTML Format" get a different ID from every peer, so
+/// they are resolved by name with [`ClipboardFormat::mime_type`] or a
+/// [`FormatRegistry`](crate::FormatRegistry).
 ///
 /// # Example
 ///
@@ -219,13 +455,16 @@ pub fn rdp_format_to_mime(format_id: u32) -> Option<&'static str> {
     match format_id {
         // All text formats map to the same MIME type - we'll convert encoding as needed
         CF_UNICODETEXT | CF_TEXT | CF_OEMTEXT => Some("text/plain;charset=utf-8"),
-        CF_HTML => Some("text/html"),
-        CF_RTF => Some("text/rtf"),
         CF_DIB | CF_DIBV5 => Some("image/png"), // Prefer PNG output (preserves alpha from DIBV5)
-        CF_PNG => Some("image/png"),
-        CF_JPEG => Some("image/jpeg"),
-        CF_GIF => Some("image/gif"),
-        CF_HDROP | CF_FILEGROUPDESCRIPTORW => Some("text/uri-list"),
+        #[cfg(feature = "metafile")]
+        CF_ENHMETAFILE | CF_METAFILEPICT => Some("image/png"), // Rasterized, see `crate::metafile`
+        #[cfg(feature = "image")]
+        CF_TIFF => Some("image/png"), // Decoded, see `crate::image::tiff_to_png`
+        #[cfg(not(feature = "image"))]
+        CF_TIFF => Some("image/tiff"),
+        #[cfg(feature = "image")]
+        CF_DSPBITMAP => Some("image/png"), // Decoded as a DIB
+        CF_HDROP => Some("text/uri-list"),
         CF_WAVE | CF_RIFF => Some("audio/wav"),
         // CF_FILECONTENTS is not mapped to MIME - it's a data retrieval mechanism, not a format
         _ => None,
@@ -236,24 +475,302 @@ pub fn rdp_format_to_mime(format_id: u32) -> Option<&'static str> {
 // Format Converter
 // =============================================================================
 
+/// Whether pasted files are copied or moved
+///
+/// Each desktop carries this differently: GNOME as the first line of
+/// `x-special/gnome-copied-files`, KDE as `application/x-kde-cutselection`
+/// ("1" for a cut), and Windows as the "Preferred DropEffect" DWORD.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "serde", serde(rename_all = "snake_case"))]
+pub enum FileOperation {
+    /// Files stay at the source
+    #[default]
+    Copy,
+    /// Files are moved on paste
+    Cut,
+}
+
+impl FileOperation {
+    /// Verb used in `x-special/gnome-copied-files`
+    pub fn verb(self) -> &'static str {
+        match self {
+            Self::Copy => "copy",
+            Self::Cut => "cut",
+        }
+    }
+
+    /// Parse a `x-special/gnome-copied-files` verb
+    pub fn from_verb(verb: &str) -> Option<Self> {
+        match verb.trim() {
+            "copy" => Some(Self::Copy),
+            "cut" => Some(Self::Cut),
+            _ => None,
+        }
+    }
+
+    /// `DROPEFFECT_*` flags Explorer sets for this operation
+    pub fn drop_effect(self) -> u32 {
+        match self {
+            Self::Copy => DROPEFFECT_COPY | DROPEFFECT_LINK,
+            Self::Cut => DROPEFFECT_MOVE,
+        }
+    }
+
+    /// Operation requested by `DROPEFFECT_*` flags
+    ///
+    /// Only a move without copy is a cut; anything else pastes as a copy.
+    pub fn from_drop_effect(effect: u32) -> Self {
+        if effect & DROPEFFECT_MOVE != 0 && effect & DROPEFFECT_COPY == 0 {
+            Self::Cut
+        } else {
+            Self::Copy
+        }
+    }
+}
+
+/// What to do with a file URI whose decoded path is not valid UTF-8
+///
+/// Linux filenames are arbitrary bytes, but HDROP carries UTF-16 paths, so a
+/// name written in a legacy encoding has no exact representation on the peer.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "serde", serde(rename_all = "snake_case"))]
+pub enum NonUtf8PathPolicy {
+    /// Leave the file out of the list
+    Skip,
+    /// Substitute U+FFFD for the invalid bytes
+    #[default]
+    Replace,
+    /// Fail the whole conversion
+    Error,
+}
+
+/// Line ending convention applied to text
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "serde", serde(rename_all = "snake_case"))]
+pub enum LineEndings {
+    /// Leave line endings as they are
+    #[default]
+    Preserve,
+    /// Convert CRLF to LF
+    Lf,
+    /// Convert bare LF to CRLF
+    Crlf,
+}
+
+/// Cleanup applied to text travelling in one direction
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "serde", serde(default))]
+pub struct TextPolicy {
+    /// Line ending conversion
+    pub line_endings: LineEndings,
+
+    /// Remove every trailing NUL, not just the terminator
+    pub trim_trailing_nuls: bool,
+
+    /// Remove a leading byte order mark (U+FEFF)
+    pub strip_bom: bool,
+}
+
+impl TextPolicy {
+    /// Leave text untouched
+    pub const PRESERVE: Self = Self {
+        line_endings: LineEndings::Preserve,
+        trim_trailing_nuls: false,
+        strip_bom: false,
+    };
+
+    /// Apply the policy, borrowing when nothing has to be rewritten
+    pub fn apply<'a>(&self, text: &'a str) -> Cow<'a, str> {
+        let mut text = text;
+        if self.strip_bom {
+            text = text.strip_prefix('\u{feff}').unwrap_or(text);
+        }
+        if self.trim_trailing_nuls {
+            text = text.trim_end_matches('\0');
+        }
+
+        match self.line_endings {
+            LineEndings::Lf if text.contains("\r\n") => Cow::Owned(convert_line_endings_to_unix(text)),
+            LineEndings::Crlf if has_bare_lf(text) => Cow::Owned(convert_line_endings_to_windows(text)),
+            _ => Cow::Borrowed(text),
+        }
+    }
+
+    /// Apply the policy to owned text, keeping its buffer when unchanged
+    fn apply_owned(&self, text: String) -> String {
+        let normalized = match self.apply(&text) {
+            Cow::Owned(normalized) => Some(normalized),
+            Cow::Borrowed(normalized) if normalized.len() == text.len() => None,
+            Cow::Borrowed(normalized) => Some(normalized.to_string()),
+        };
+        normalized.unwrap_or(text)
+    }
+}
+
+/// Whether any LF is not already part of a CRLF
+fn has_bare_lf(text: &str) -> bool {
+    let bytes = text.as_bytes();
+    bytes
+        .iter()
+        .enumerate()
+        .any(|(i, &b)| b == b'\n' && (i == 0 || bytes[i - 1] != b'\r'))
+}
+
+/// Text cleanup for CF_UNICODETEXT, CF_TEXT and CF_OEMTEXT, per direction
+///
+/// Windows text uses CRLF line endings and often arrives with a BOM or a
+/// buffer padded with NULs; Linux editors show all three as stray
+/// characters. By default incoming text is converted to LF with BOM and
+/// padding removed, and outgoing text is sent as is, since Windows
+/// applications accept LF.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "serde", serde(default))]
+pub struct TextNormalization {
+    /// Applied to text received from the peer
+    pub incoming: TextPolicy,
+
+    /// Applied to text sent to the peer
+    pub outgoing: TextPolicy,
+}
+
+impl TextNormalization {
+    /// Leave text untouched in both directions
+    pub const NONE: Self = Self {
+        incoming: TextPolicy::PRESERVE,
+        outgoing: TextPolicy::PRESERVE,
+    };
+
+    /// LF locally and CRLF on the peer, with BOMs and padding removed both ways
+    pub const FULL: Self = Self {
+        incoming: TextPolicy {
+            line_endings: LineEndings::Lf,
+            trim_trailing_nuls: true,
+            strip_bom: true,
+        },
+        outgoing: TextPolicy {
+            line_endings: LineEndings::Crlf,
+            trim_trailing_nuls: true,
+            strip_bom: true,
+        },
+    };
+}
+
+impl Default for TextNormalization {
+    fn default() -> Self {
+        Self {
+            incoming: Self::FULL.incoming,
+            outgoing: TextPolicy::PRESERVE,
+        }
+    }
+}
+
 /// Handles clipboard data format conversion
+///
+/// Besides the typed methods below, conversions can be looked up by format
+/// name and extended at runtime; see [`crate::converter`].
 #[derive(Debug, Default)]
 pub struct FormatConverter {
     /// Maximum data size for conversion (default: 16MB)
     pub max_size: usize,
+
+    /// How [`uri_list_to_hdrop`](Self::uri_list_to_hdrop) treats paths that are not valid UTF-8
+    pub non_utf8_paths: NonUtf8PathPolicy,
+
+    /// Translation of HDROP paths between Windows and local namespaces
+    pub path_mapper: PathMapper,
+
+    /// This machine's hostname, so file URIs naming it are treated as local
+    /// (default: the kernel hostname)
+    pub local_hostname: Option<String>,
+
+    /// Line ending, BOM and NUL cleanup for plain text formats
+    pub text_normalization: TextNormalization,
+
+    /// Largest image produced by image conversions; larger ones are downscaled
+    #[cfg(feature = "image")]
+    pub image_limits: crate::image::ImageLimits,
+
+    /// JPEG quality, chroma subsampling and PNG compression of encoded images
+    #[cfg(feature = "image")]
+    pub image_encode: crate::image::ImageEncodeOptions,
+
+    /// Conversions registered with [`register_converter`](Self::register_converter)
+    pub(crate) converters: ConverterMap,
+
+    /// Failed conversions by category, see [`conversion_metrics`](Self::conversion_metrics)
+    pub(crate) failures: FailureCounters,
+
+    /// Recent image conversion results, see [`with_conversion_cache`](Self::with_conversion_cache)
+    #[cfg(feature = "image")]
+    pub(crate) cache: crate::cache::ConversionCache,
 }
 
 impl FormatConverter {
     /// Create a new format converter with default settings
     pub fn new() -> Self {
-        Self {
-            max_size: 16 * 1024 * 1024, // 16MB
-        }
+        Self::with_max_size(16 * 1024 * 1024) // 16MB
     }
 
     /// Create a format converter with custom max size
     pub fn with_max_size(max_size: usize) -> Self {
-        Self { max_size }
+        Self {
+            max_size,
+            non_utf8_paths: NonUtf8PathPolicy::default(),
+            path_mapper: PathMapper::default(),
+            local_hostname: system_hostname(),
+            text_normalization: TextNormalization::default(),
+            #[cfg(feature = "image")]
+            image_limits: crate::image::ImageLimits::default(),
+            #[cfg(feature = "image")]
+            image_encode: crate::image::ImageEncodeOptions::default(),
+            converters: ConverterMap::default(),
+            failures: FailureCounters::default(),
+            #[cfg(feature = "image")]
+            cache: crate::cache::ConversionCache::default(),
+        }
+    }
+
+    /// Set the policy for file URIs whose paths are not valid UTF-8
+    pub fn with_non_utf8_path_policy(mut self, policy: NonUtf8PathPolicy) -> Self {
+        self.non_utf8_paths = policy;
+        self
+    }
+
+    /// Set how HDROP paths are translated between Windows and local namespaces
+    pub fn with_path_mapper(mut self, mapper: PathMapper) -> Self {
+        self.path_mapper = mapper;
+        self
+    }
+
+    /// Set the hostname under which file URIs refer to local files
+    pub fn with_local_hostname(mut self, hostname: impl Into<String>) -> Self {
+        self.local_hostname = Some(hostname.into());
+        self
+    }
+
+    /// Set the line ending, BOM and NUL cleanup for plain text formats
+    pub fn with_text_normalization(mut self, normalization: TextNormalization) -> Self {
+        self.text_normalization = normalization;
+        self
+    }
+
+    /// Set the size limits for images converted to DIB, DIBV5 or PNG
+    #[cfg(feature = "image")]
+    pub fn with_image_limits(mut self, limits: crate::image::ImageLimits) -> Self {
+        self.image_limits = limits;
+        self
+    }
+
+    /// Set the encoder settings for images converted to JPEG or PNG
+    #[cfg(feature = "image")]
+    pub fn with_image_encode_options(mut self, options: crate::image::ImageEncodeOptions) -> Self {
+        self.image_encode = options;
+        self
     }
 
     /// Convert UTF-8 text to UTF-16LE (for CF_UNICODETEXT)
@@ -267,6 +784,7 @@ impl FormatConverter {
             });
         }
 
+        let text = self.text_normalization.outgoing.apply(text);
         let mut result: Vec<u8> = text.encode_utf16().flat_map(|c| c.to_le_bytes()).collect();
 
         // Add null terminator (2 bytes for UTF-16)
@@ -300,7 +818,8 @@ impl FormatConverter {
             &utf16[..]
         };
 
-        String::from_utf16(utf16).map_err(|_| ClipboardError::InvalidUtf16)
+        let text = String::from_utf16(utf16).map_err(|_| ClipboardError::InvalidUtf16)?;
+        Ok(self.text_normalization.incoming.apply_owned(text))
     }
 
     /// Convert UTF-8 text to ANSI (Windows-1252) for CF_TEXT
@@ -308,6 +827,11 @@ impl FormatConverter {
     /// Characters not representable in Windows-1252 are replaced with '?'.
     /// Adds null terminator as required by Windows.
     pub fn text_to_ansi(&self, text: &str) -> ClipboardResult<Vec<u8>> {
+        self.encode_ansi(&self.text_normalization.outgoing.apply(text))
+    }
+
+    /// Windows-1252 encoding without text normalization
+    fn encode_ansi(&self, text: &str) -> ClipboardResult<Vec<u8>> {
         if text.len() > self.max_size {
             return Err(ClipboardError::DataSizeExceeded {
                 actual: text.len(),
@@ -329,6 +853,12 @@ impl FormatConverter {
 
     /// Convert ANSI (Windows-1252) to UTF-8 (from CF_TEXT)
     pub fn ansi_to_text(&self, data: &[u8]) -> ClipboardResult<String> {
+        let text = self.decode_ansi(data)?;
+        Ok(self.text_normalization.incoming.apply_owned(text))
+    }
+
+    /// Windows-1252 decoding without text normalization
+    fn decode_ansi(&self, data: &[u8]) -> ClipboardResult<String> {
         if data.len() > self.max_size {
             return Err(ClipboardError::DataSizeExceeded {
                 actual: data.len(),
@@ -347,6 +877,84 @@ impl FormatConverter {
         Ok(result)
     }
 
+    /// Parse CF_LOCALE data into a Windows locale identifier (LCID)
+    pub fn parse_locale(&self, data: &[u8]) -> ClipboardResult<u32> {
+        let bytes: [u8; 4] = data
+            .get(..4)
+            .and_then(|b| b.try_into().ok())
+            .ok_or_else(|| ClipboardError::FormatConversion(format!("CF_LOCALE too short: {} bytes", data.len())))?;
+        Ok(u32::from_le_bytes(bytes))
+    }
+
+    /// Convert UTF-8 text to CF_TEXT using the ANSI codepage of a locale
+    ///
+    /// Characters not representable in the codepage are replaced with '?'.
+    /// Adds null terminator as required by Windows.
+    pub fn text_to_ansi_with_locale(&self, text: &str, lcid: u32) -> ClipboardResult<Vec<u8>> {
+        let codepage = lcid_to_ansi_codepage(lcid);
+        let Some(encoding) = codepage_to_encoding(codepage) else {
+            return self.text_to_ansi(text);
+        };
+
+        if text.len() > self.max_size {
+            return Err(ClipboardError::DataSizeExceeded {
+                actual: text.len(),
+                max: self.max_size,
+            });
+        }
+
+        let text = self.text_normalization.outgoing.apply(text);
+        let mut encoder = encoding.new_encoder();
+        let mut result = Vec::with_capacity(text.len() + 1);
+        let mut remaining = &*text;
+        loop {
+            let capacity = encoder
+                .max_buffer_length_from_utf8_without_replacement(remaining.len())
+                .unwrap_or(remaining.len() * 2);
+            result.reserve(capacity);
+            let (status, read) = encoder.encode_from_utf8_to_vec_without_replacement(remaining, &mut result, true);
+            remaining = &remaining[read..];
+            match status {
+                encoding_rs::EncoderResult::InputEmpty => break,
+                encoding_rs::EncoderResult::OutputFull => {}
+                encoding_rs::EncoderResult::Unmappable(_) => result.push(b'?'),
+            }
+        }
+
+        // Add null terminator
+        result.push(0);
+
+        Ok(result)
+    }
+
+    /// Convert CF_TEXT to UTF-8 using the ANSI codepage of a locale
+    ///
+    /// The locale comes from the CF_LOCALE format published alongside CF_TEXT
+    /// (see [`parse_locale`](Self::parse_locale)). Falls back to Windows-1252
+    /// for codepages without a known decoder.
+    pub fn ansi_to_text_with_locale(&self, data: &[u8], lcid: u32) -> ClipboardResult<String> {
+        let codepage = lcid_to_ansi_codepage(lcid);
+        let Some(encoding) = codepage_to_encoding(codepage) else {
+            return self.ansi_to_text(data);
+        };
+
+        if data.len() > self.max_size {
+            return Err(ClipboardError::DataSizeExceeded {
+                actual: data.len(),
+                max: self.max_size,
+            });
+        }
+
+        // CF_TEXT is null-terminated; anything after the first null is garbage
+        let end = data.iter().position(|&b| b == 0).unwrap_or(data.len());
+        let (text, had_errors) = encoding.decode_without_bom_handling(&data[..end]);
+        if had_errors {
+            tracing::debug!("CF_TEXT contained bytes invalid in codepage {}", codepage);
+        }
+
+        Ok(self.text_normalization.incoming.apply_owned(text.into_owned()))
+    }
+
     /// Convert UTF-8 text to OEM (CP437) for CF_OEMTEXT
     ///
     /// Characters not representable in CP437 are replaced with '?'.
@@ -359,6 +967,7 @@ impl FormatConverter {
             });
         }
 
+        let text = self.text_normalization.outgoing.apply(text);
         let mut result = Vec::with_capacity(text.len() + 1);
 
         for c in text.chars() {
@@ -388,13 +997,22 @@ impl FormatConverter {
         };
 
         let result: String = data.iter().map(|&b| cp437_to_char(b)).collect();
-        Ok(result)
+        Ok(self.text_normalization.incoming.apply_owned(result))
     }
 
-    /// Convert plain HTML to Windows CF_HTML format
+    /// Convert HTML to Windows CF_HTML format
     ///
-    /// The CF_HTML format includes headers with byte offsets.
+    /// The CF_HTML format includes headers with byte offsets. A bare fragment
+    /// is wrapped in a minimal document, while a full document keeps its own
+    /// markup as context; see [`CfHtml::from_html`].
     pub fn html_to_cf_html(&self, html: &str) -> ClipboardResult<Vec<u8>> {
+        self.html_to_cf_html_with_source(html, None)
+    }
+
+    /// Convert HTML to CF_HTML with an optional `SourceURL` header
+    ///
+    /// Office resolves relative links and images against the source URL.
+    pub fn html_to_cf_html_with_source(&self, html: &str, source_url: Option<&str>) -> ClipboardResult<Vec<u8>> {
         if html.len() > self.max_size {
             return Err(ClipboardError::DataSizeExceeded {
                 actual: html.len(),
@@ -402,60 +1020,20 @@ impl FormatConverter {
             });
         }
 
-        // CF_HTML format:
-        // Version:0.9
-        // StartHTML:XXXXXXXX
-        // EndHTML:XXXXXXXX
-        // StartFragment:XXXXXXXX
-        // EndFragment:XXXXXXXX
-        // <html><body><!--StartFragment-->CONTENT<!--EndFragment--></body></html>
-
-        let header_template = "Version:0.9\r\n\
-                               StartHTML:XXXXXXXX\r\n\
-                               EndHTML:XXXXXXXX\r\n\
-                               StartFragment:XXXXXXXX\r\n\
-                               EndFragment:XXXXXXXX\r\n";
-
-        let prefix = "<html><body><!--StartFragment-->";
-        let suffix = "<!--EndFragment--></body></html>";
-
-        let header_len = header_template.len();
-        let start_html = header_len;
-        let start_fragment = header_len + prefix.len();
-        let end_fragment = start_fragment + html.len();
-        let end_html = end_fragment + suffix.len();
-
-        let header = format!(
-            "Version:0.9\r\n\
-             StartHTML:{:08}\r\n\
-             EndHTML:{:08}\r\n\
-             StartFragment:{:08}\r\n\
-             EndFragment:{:08}\r\n",
-            start_html, end_html, start_fragment, end_fragment
-        );
-
-        let mut result = header;
-        result.push_str(prefix);
-        result.push_str(html);
-        result.push_str(suffix);
-
-        Ok(result.into_bytes())
+        let mut cf_html = CfHtml::from_html(html);
+        if let Some(url) = source_url {
+            cf_html = cf_html.with_source_url(url);
+        }
+        Ok(cf_html.to_bytes())
     }
 
     /// Extract HTML content from CF_HTML format
+    ///
+    /// Returns the full context document when it carries more than the bare
+    /// `<html><body>` wrapper (stylesheets, `<head>` metadata, table markup
+    /// around a copied cell), and just the fragment otherwise.
     pub fn cf_html_to_html(&self, data: &[u8]) -> ClipboardResult<String> {
-        let text = std::str::from_utf8(data).map_err(|_| ClipboardError::InvalidUtf8)?;
-
-        // Parse StartFragment and EndFragment from header
-        let start_fragment = Self::parse_header_value(text, "StartFragment:")?;
-        let end_fragment = Self::parse_header_value(text, "EndFragment:")?;
-
-        if start_fragment >= end_fragment || end_fragment > data.len() {
-            return Err(ClipboardError::FormatConversion("invalid CF_HTML offsets".to_string()));
-        }
-
-        let fragment = &text[start_fragment..end_fragment];
-        Ok(fragment.to_string())
+        Ok(CfHtml::parse(data)?.html().to_string())
     }
 
     /// Parse a numeric header value from CF_HTML
@@ -463,7 +1041,7 @@ impl FormatConverter {
         text.lines()
             .find(|line| line.starts_with(key))
             .and_then(|line| line[key.len()..].trim().parse().ok())
-            .ok_or_else(|| ClipboardError::FormatConversion(format!("missing {} header", key)))
+            .ok_or_else(|| ClipboardError::InvalidCfHtml(format!("missing {} header", key)))
     }
 
     // =========================================================================
@@ -697,15 +1275,261 @@ impl FormatConverter {
         Ok(result)
     }
 
+    /// Convert RTF to an HTML fragment
+    ///
+    /// Keeps inline formatting, paragraphs and links; see [`crate::rtf`].
+    pub fn rtf_to_html(&self, data: &[u8]) -> ClipboardResult<String> {
+        if data.len() > self.max_size {
+            return Err(ClipboardError::DataSizeExceeded {
+                actual: data.len(),
+                max: self.max_size,
+            });
+        }
+
+        crate::rtf::rtf_to_html(data)
+    }
+
+    /// Convert HTML to RTF (best effort)
+    pub fn html_to_rtf(&self, html: &str) -> ClipboardResult<Vec<u8>> {
+        if html.len() > self.max_size {
+            return Err(ClipboardError::DataSizeExceeded {
+                actual: html.len(),
+                max: self.max_size,
+            });
+        }
+
+        Ok(crate::rtf::html_to_rtf(html))
+    }
+
+    /// Convert CF_HTML received from the peer to Markdown
+    ///
+    /// Best effort; see [`crate::markdown`].
+    #[cfg(feature = "markdown")]
+    pub fn cf_html_to_markdown(&self, data: &[u8]) -> ClipboardResult<String> {
+        if data.len() > self.max_size {
+            return Err(ClipboardError::DataSizeExceeded {
+                actual: data.len(),
+                max: self.max_size,
+            });
+        }
+
+        let cf_html = CfHtml::parse(data)?;
+        Ok(crate::markdown::html_to_markdown(cf_html.fragment()))
+    }
+
+    /// Render Markdown to CF_HTML for the peer
+    #[cfg(feature = "markdown")]
+    pub fn markdown_to_cf_html(&self, markdown: &str) -> ClipboardResult<Vec<u8>> {
+        if markdown.len() > self.max_size {
+            return Err(ClipboardError::DataSizeExceeded {
+                actual: markdown.len(),
+                max: self.max_size,
+            });
+        }
+
+        self.html_to_cf_html(&crate::markdown::markdown_to_html(markdown))
+    }
+
+    /// Parse a Mozilla URL object (`text/x-moz-url` or `_NETSCAPE_URL`)
+    ///
+    /// Both carry the URL on the first line and an optional title on the
+    /// second. `text/x-moz-url` is UTF-16LE while `_NETSCAPE_URL` is UTF-8;
+    /// the encoding is detected from the data. Returns `(url, title)`.
+    pub fn parse_moz_url(&self, data: &[u8]) -> ClipboardResult<(String, Option<String>)> {
+        if data.len() > self.max_size {
+            return Err(ClipboardError::DataSizeExceeded {
+                actual: data.len(),
+                max: self.max_size,
+            });
+        }
+
+        let text = if data.len() >= 2 && data.len() % 2 == 0 && data.iter().skip(1).step_by(2).any(|&b| b == 0) {
+            let data = data.strip_prefix(&[0xFF, 0xFE]).unwrap_or(data);
+            self.unicode_to_text(data)?
+        } else {
+            std::str::from_utf8(data)
+                .map_err(|_| ClipboardError::InvalidUtf8)?
+                .to_string()
+        };
+        let text = text.trim_end_matches('\0');
+
+        let mut lines = text.lines().map(str::trim);
+        let url = match lines.next() {
+            Some(url) if !url.is_empty() => url.to_string(),
+            _ => return Err(ClipboardError::FormatConversion("empty URL object".to_string())),
+        };
+        let title = lines.next().filter(|t| !t.is_empty()).map(str::to_string);
+
+        Ok((url, title))
+    }
+
+    /// Build a `text/x-moz-url` URL object (UTF-16LE, `url\ntitle`)
+    ///
+    /// Firefox expects a title line; the URL is repeated when none is given.
+    pub fn build_moz_url(&self, url: &str, title: Option<&str>) -> ClipboardResult<Vec<u8>> {
+        let text = format!("{}\n{}", url, title.unwrap_or(url));
+        if text.len() > self.max_size {
+            return Err(ClipboardError::DataSizeExceeded {
+                actual: text.len(),
+                max: self.max_size,
+            });
+        }
+
+        Ok(text.encode_utf16().flat_map(|c| c.to_le_bytes()).collect())
+    }
+
+    /// Convert a Mozilla URL object to UniformResourceLocatorW
+    ///
+    /// The Windows format has no title, so only the URL is kept.
+    pub fn moz_url_to_url_w(&self, data: &[u8]) -> ClipboardResult<Vec<u8>> {
+        let (url, _) = self.parse_moz_url(data)?;
+        self.text_to_unicode(&url)
+    }
+
+    /// Convert UniformResourceLocatorW to a `text/x-moz-url` URL object
+    pub fn url_w_to_moz_url(&self, data: &[u8]) -> ClipboardResult<Vec<u8>> {
+        let text = self.unicode_to_text(data)?;
+        let url = text.trim_end_matches('\0').trim();
+        if url.is_empty() {
+            return Err(ClipboardError::FormatConversion("empty URL object".to_string()));
+        }
+        self.build_moz_url(url, None)
+    }
+
+    /// Convert a Mozilla URL object to the ANSI UniformResourceLocator
+    ///
+    /// Non-ASCII characters are percent-encoded as UTF-8, so the URL
+    /// survives any code page.
+    pub fn moz_url_to_url(&self, data: &[u8]) -> ClipboardResult<Vec<u8>> {
+        let (url, _) = self.parse_moz_url(data)?;
+        let mut ascii = String::with_capacity(url.len());
+        for c in url.chars() {
+            if c.is_ascii() {
+                ascii.push(c);
+            } else {
+                for byte in c.encode_utf8(&mut [0; 4]).bytes() {
+                    ascii.push_str(&format!("%{:02X}", byte));
+                }
+            }
+        }
+        self.encode_ansi(&ascii)
+    }
+
+    /// Convert the ANSI UniformResourceLocator to a `text/x-moz-url` URL object
+    pub fn url_to_moz_url(&self, data: &[u8]) -> ClipboardResult<Vec<u8>> {
+        let text = self.decode_ansi(data)?;
+        let url = text.trim_end_matches('\0').trim();
+        if url.is_empty() {
+            return Err(ClipboardError::FormatConversion("empty URL object".to_string()));
+        }
+        self.build_moz_url(url, None)
+    }
+
+    /// Convert CSV to the Windows "Csv" format (ANSI, CRLF line endings)
+    pub fn csv_to_rdp_csv(&self, csv: &str) -> ClipboardResult<Vec<u8>> {
+        if csv.len() > self.max_size {
+            return Err(ClipboardError::DataSizeExceeded {
+                actual: csv.len(),
+                max: self.max_size,
+            });
+        }
+
+        let csv = crate::spreadsheet::write_csv(&crate::spreadsheet::parse_csv(csv));
+        self.encode_ansi(&csv)
+    }
+
+    /// Convert the Windows "Csv" format to CSV
+    ///
+    /// CRLF record separators are kept whatever the text normalization.
+    pub fn rdp_csv_to_csv(&self, data: &[u8]) -> ClipboardResult<String> {
+        self.decode_ansi(data)
+    }
+
+    /// Convert CSV to Excel's "XML Spreadsheet" format
+    ///
+    /// The workbook is UTF-8 and null-terminated like other Windows text formats.
+    pub fn csv_to_xml_spreadsheet(&self, csv: &str) -> ClipboardResult<Vec<u8>> {
+        if csv.len() > self.max_size {
+            return Err(ClipboardError::DataSizeExceeded {
+                actual: csv.len(),
+                max: self.max_size,
+            });
+        }
+
+        let mut data = crate::spreadsheet::csv_to_xml_spreadsheet(csv).into_bytes();
+        data.push(0);
+        Ok(data)
+    }
+
+    /// Convert Excel's "XML Spreadsheet" format to CSV
+    pub fn xml_spreadsheet_to_csv(&self, data: &[u8]) -> ClipboardResult<String> {
+        if data.len() > self.max_size {
+            return Err(ClipboardError::DataSizeExceeded {
+                actual: data.len(),
+                max: self.max_size,
+            });
+        }
+
+        let data = data.strip_prefix(b"\xEF\xBB\xBF").unwrap_or(data);
+        let end = data.iter().position(|&b| b == 0).unwrap_or(data.len());
+        let xml = std::str::from_utf8(&data[..end]).map_err(|_| ClipboardError::InvalidUtf8)?;
+        crate::spreadsheet::xml_spreadsheet_to_csv(xml)
+    }
+
     /// Convert URI list to HDROP format (file paths)
     ///
     /// The HDROP format is a DROPFILES structure followed by null-terminated paths.
+    /// The list is read as RFC 2483 describes: blank lines and `#` comments are
+    /// ignored, and a URI that is not a `file:` URI fails the whole conversion
+    /// with [`ClipboardError::NonFileUri`], as HDROP cannot carry it.
+    ///
+    /// A URI host naming this machine ([`local_hostname`](Self::local_hostname))
+    /// is dropped, and other hosts become UNC paths.
+    /// HDROP paths are UTF-16, so a path whose decoded bytes are not valid UTF-8
+    /// is handled according to [`non_utf8_paths`](Self::non_utf8_paths).
+    /// Local paths inside a directory mapped by [`path_mapper`](Self::path_mapper)
+    /// become the matching Windows path.
     pub fn uri_list_to_hdrop(&self, uri_list: &str) -> ClipboardResult<Vec<u8>> {
-        let paths: Vec<&str> = uri_list
-            .lines()
-            .filter(|line| !line.starts_with('#'))
-            .filter_map(|line| line.strip_prefix("file://"))
-            .collect();
+        let to_hdrop = |host: Option<&str>, path: &str| match host {
+            None => self
+                .path_mapper
+                .to_windows(path)
+                .unwrap_or_else(|| file_uri_path_to_hdrop(None, path)),
+            Some(_) => file_uri_path_to_hdrop(host, path),
+        };
+
+        let mut paths = Vec::new();
+        for uri in uri_list_entries(uri_list) {
+            if !has_file_scheme(uri) {
+                return Err(ClipboardError::NonFileUri(uri.to_string()));
+            }
+            let Some((host, encoded)) = split_file_uri(uri) else {
+                tracing::debug!("Skipping malformed file URI: {}", uri);
+                continue;
+            };
+            let host = host.filter(|host| {
+                self.local_hostname
+                    .as_deref()
+                    .map_or(true, |local| !is_local_host(host, local))
+            });
+            match String::from_utf8(percent_decode_bytes(encoded)) {
+                Ok(path) => paths.push(to_hdrop(host, &path)),
+                Err(e) => match self.non_utf8_paths {
+                    NonUtf8PathPolicy::Skip => {
+                        tracing::debug!("Skipping file URI with non-UTF-8 path: {}", uri);
+                    }
+                    NonUtf8PathPolicy::Replace => {
+                        let path = String::from_utf8_lossy(e.as_bytes());
+                        paths.push(to_hdrop(host, &path));
+                    }
+                    NonUtf8PathPolicy::Error => {
+                        return Err(ClipboardError::FormatConversion(format!(
+                            "file URI path is not valid UTF-8: {uri}"
+                        )));
+                    }
+                },
+            }
+        }
 
         if paths.is_empty() {
             return Err(ClipboardError::FormatConversion("no valid file URIs".to_string()));
@@ -731,9 +1555,7 @@ impl FormatConverter {
 
         // File paths as UTF-16LE, null-terminated
         for path in paths {
-            // URL decode the path
-            let decoded = percent_decode(path);
-            for c in decoded.encode_utf16() {
+            for c in path.encode_utf16() {
                 result.extend_from_slice(&c.to_le_bytes());
             }
             // Null terminator
@@ -747,7 +1569,20 @@ impl FormatConverter {
     }
 
     /// Convert HDROP format to URI list
+    ///
+    /// Paths on a drive or share mapped by [`path_mapper`](Self::path_mapper)
+    /// become URIs for the matching local path.
     pub fn hdrop_to_uri_list(&self, data: &[u8]) -> ClipboardResult<String> {
+        let to_uri = |path: &[u8]| {
+            let local = std::str::from_utf8(path)
+                .ok()
+                .and_then(|path| self.path_mapper.to_local(path));
+            match local {
+                Some(local) => hdrop_path_to_file_uri(local.as_bytes()),
+                None => hdrop_path_to_file_uri(path),
+            }
+        };
+
         if data.len() < 20 {
             return Err(ClipboardError::FormatConversion("HDROP too small".to_string()));
         }
@@ -782,7 +1617,7 @@ impl FormatConverter {
                 }
 
                 if let Ok(path) = String::from_utf16(&path_chars) {
-                    paths.push(format!("file://{}", percent_encode(&path)));
+                    paths.push(to_uri(path.as_bytes()));
                 }
             }
         } else {
@@ -796,58 +1631,393 @@ impl FormatConverter {
                 if end == 0 {
                     break;
                 }
-                if let Ok(path) = std::str::from_utf8(&file_data[pos..pos + end]) {
-                    paths.push(format!("file://{}", percent_encode(path)));
-                }
+                // Legacy-encoded bytes are escaped as-is rather than dropped
+                paths.push(to_uri(&file_data[pos..pos + end]));
                 pos += end + 1;
             }
         }
 
-        Ok(paths.join("\r\n"))
+        Ok(paths.join("\r\n"))
+    }
+
+    // =========================================================================
+    // Cut/Copy Semantics
+    // =========================================================================
+
+    /// Convert `x-special/gnome-copied-files` to a "Preferred DropEffect" DWORD
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use lamco_clipboard_core::formats::{FormatConverter, DROPEFFECT_MOVE};
+    ///
+    /// let converter = FormatConverter::new();
+    /// let effect = converter
+    ///     .gnome_copied_files_to_drop_effect(b"cut\nfile:///home/user/report.pdf")
+    ///     .unwrap();
+    /// assert_eq!(effect, DROPEFFECT_MOVE.to_le_bytes());
+    /// ```
+    pub fn gnome_copied_files_to_drop_effect(&self, data: &[u8]) -> ClipboardResult<Vec<u8>> {
+        let (operation, _) = self.parse_gnome_copied_files(data)?;
+        Ok(self.encode_drop_effect(operation.drop_effect()))
+    }
+
+    /// Split `x-special/gnome-copied-files` into its operation and URI list
+    ///
+    /// The URIs are returned as a `text/uri-list` with CRLF line endings.
+    pub fn parse_gnome_copied_files(&self, data: &[u8]) -> ClipboardResult<(FileOperation, String)> {
+        if data.len() > self.max_size {
+            return Err(ClipboardError::DataSizeExceeded {
+                actual: data.len(),
+                max: self.max_size,
+            });
+        }
+        let text = std::str::from_utf8(data).map_err(|_| ClipboardError::InvalidUtf8)?;
+
+        let mut lines = text.lines().map(str::trim).filter(|line| !line.is_empty());
+        let verb = lines.next().unwrap_or("");
+        let operation = FileOperation::from_verb(verb)
+            .ok_or_else(|| ClipboardError::FormatConversion(format!("unknown gnome-copied-files verb: {verb:?}")))?;
+
+        Ok((operation, lines.collect::<Vec<_>>().join("\r\n")))
+    }
+
+    /// Build `x-special/gnome-copied-files` from a URI list and an operation
+    ///
+    /// Use [`drop_effect_operation`](Self::drop_effect_operation) to get the
+    /// operation from the peer's "Preferred DropEffect".
+    pub fn uri_list_to_gnome_copied_files(&self, uri_list: &str, operation: FileOperation) -> ClipboardResult<Vec<u8>> {
+        if uri_list.len() > self.max_size {
+            return Err(ClipboardError::DataSizeExceeded {
+                actual: uri_list.len(),
+                max: self.max_size,
+            });
+        }
+
+        let mut result = operation.verb().to_string();
+        for uri in uri_list_entries(uri_list) {
+            result.push('\n');
+            result.push_str(uri);
+        }
+        Ok(result.into_bytes())
+    }
+
+    /// Encode `DROPEFFECT_*` flags as "Preferred DropEffect" data
+    pub fn encode_drop_effect(&self, effect: u32) -> Vec<u8> {
+        effect.to_le_bytes().to_vec()
+    }
+
+    /// Decode "Preferred DropEffect" data to its `DROPEFFECT_*` flags
+    ///
+    /// The format is a single little-endian DWORD. Some applications hand
+    /// over a larger global memory block, so trailing bytes are ignored.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use lamco_clipboard_core::formats::{FormatConverter, DROPEFFECT_COPY, DROPEFFECT_LINK};
+    ///
+    /// let converter = FormatConverter::new();
+    /// let effect = converter.decode_drop_effect(&[5, 0, 0, 0, 0, 0, 0, 0]).unwrap();
+    /// assert_eq!(effect, DROPEFFECT_COPY | DROPEFFECT_LINK);
+    /// ```
+    pub fn decode_drop_effect(&self, data: &[u8]) -> ClipboardResult<u32> {
+        let bytes: [u8; 4] = data
+            .get(..4)
+            .and_then(|b| b.try_into().ok())
+            .ok_or_else(|| ClipboardError::FormatConversion("Preferred DropEffect too small".to_string()))?;
+        Ok(u32::from_le_bytes(bytes))
+    }
+
+    /// Operation requested by a "Preferred DropEffect" DWORD
+    pub fn drop_effect_operation(&self, data: &[u8]) -> ClipboardResult<FileOperation> {
+        Ok(FileOperation::from_drop_effect(self.decode_drop_effect(data)?))
+    }
+
+    /// Convert `application/x-kde-cutselection` to a "Preferred DropEffect" DWORD
+    pub fn kde_cut_selection_to_drop_effect(&self, data: &[u8]) -> ClipboardResult<Vec<u8>> {
+        let operation = match String::from_utf8_lossy(data).trim() {
+            "1" => FileOperation::Cut,
+            "0" | "" => FileOperation::Copy,
+            other => {
+                return Err(ClipboardError::FormatConversion(format!(
+                    "invalid x-kde-cutselection value: {other:?}"
+                )))
+            }
+        };
+        Ok(self.encode_drop_effect(operation.drop_effect()))
+    }
+
+    /// Convert a "Preferred DropEffect" DWORD to `application/x-kde-cutselection`
+    pub fn drop_effect_to_kde_cut_selection(&self, data: &[u8]) -> ClipboardResult<Vec<u8>> {
+        Ok(match self.drop_effect_operation(data)? {
+            FileOperation::Cut => b"1".to_vec(),
+            FileOperation::Copy => b"0".to_vec(),
+        })
+    }
+}
+
+// =============================================================================
+// CF_HTML Helpers
+// =============================================================================
+
+// CF_HTML format:
+// Version:0.9
+// StartHTML:XXXXXXXX
+// EndHTML:XXXXXXXX
+// StartFragment:XXXXXXXX
+// EndFragment:XXXXXXXX
+// <html><body><!--StartFragment-->CONTENT<!--EndFragment--></body></html>
+
+/// Markup between the CF_HTML header and the fragment
+pub(crate) const CF_HTML_PREFIX: &str = "<html><body><!--StartFragment-->";
+
+/// Markup after the fragment
+pub(crate) const CF_HTML_SUFFIX: &str = "<!--EndFragment--></body></html>";
+
+/// Comment marking the start of the fragment in the context document
+const CF_HTML_START_MARKER: &str = "<!--StartFragment-->";
+
+/// Comment marking the end of the fragment in the context document
+const CF_HTML_END_MARKER: &str = "<!--EndFragment-->";
+
+/// Length of the CF_HTML header with offsets zero-padded to 8 digits
+const CF_HTML_HEADER_LEN: usize = "Version:0.9\r\n\
+                                   StartHTML:XXXXXXXX\r\n\
+                                   EndHTML:XXXXXXXX\r\n\
+                                   StartFragment:XXXXXXXX\r\n\
+                                   EndFragment:XXXXXXXX\r\n"
+    .len();
+
+/// CF_HTML header for a fragment of `fragment_len` bytes
+///
+/// Offsets are padded to 8 digits, or to 10 once the document no longer fits
+/// in 8, so the header length never depends on the values themselves.
+pub(crate) fn cf_html_header(fragment_len: usize) -> String {
+    cf_html_header_for(CF_HTML_PREFIX.len(), fragment_len, CF_HTML_SUFFIX.len(), None)
+}
+
+/// CF_HTML header for a context document split around its fragment
+fn cf_html_header_for(before_len: usize, fragment_len: usize, after_len: usize, source_url: Option<&str>) -> String {
+    let source_line = source_url.map_or(String::new(), |url| format!("SourceURL:{url}\r\n"));
+    let header_len = CF_HTML_HEADER_LEN + source_line.len();
+    let width = if header_len + before_len + fragment_len + after_len > 99_999_999 {
+        10
+    } else {
+        8
+    };
+
+    let start_html = header_len + 4 * (width - 8);
+    let start_fragment = start_html + before_len;
+    let end_fragment = start_fragment + fragment_len;
+    let end_html = end_fragment + after_len;
+
+    format!(
+        "Version:0.9\r\n\
+         StartHTML:{start_html:0width$}\r\n\
+         EndHTML:{end_html:0width$}\r\n\
+         StartFragment:{start_fragment:0width$}\r\n\
+         EndFragment:{end_fragment:0width$}\r\n\
+         {source_line}"
+    )
+}
+
+/// Optional CF_HTML offset; absent or `-1` means "not provided"
+fn parse_optional_offset(text: &str, key: &str) -> Option<usize> {
+    text.lines()
+        .find(|line| line.starts_with(key))
+        .and_then(|line| line[key.len()..].trim().parse().ok())
+}
+
+/// Whether markup around a fragment is nothing but the given wrapper tags
+fn is_bare_wrapper(markup: &str, wrapper: &str) -> bool {
+    let compact: String = markup
+        .replace(CF_HTML_START_MARKER, "")
+        .replace(CF_HTML_END_MARKER, "")
+        .chars()
+        .filter(|c| !c.is_whitespace())
+        .collect();
+    compact.is_empty() || compact.eq_ignore_ascii_case(wrapper)
+}
+
+/// A CF_HTML payload: the context document, its fragment and source URL
+///
+/// CF_HTML carries more than the selected markup. The context document
+/// around the fragment holds the stylesheets, `<head>` metadata and
+/// enclosing elements (a `<table>` around copied cells) that Office needs
+/// to paste with full fidelity, and the optional `SourceURL` header lets it
+/// resolve relative links and images.
+///
+/// # Example
+///
+/// ```
+/// use lamco_clipboard_core::CfHtml;
+///
+/// let html = "<html><head><style>td{color:red}</style></head><body><table><tr>\
+///             <!--StartFragment--><td>1</td><!--EndFragment--></tr></table></body></html>";
+/// let data = CfHtml::from_html(html).with_source_url("https://example.com/").to_bytes();
+///
+/// let parsed = CfHtml::parse(&data).unwrap();
+/// assert_eq!(parsed.fragment(), "<td>1</td>");
+/// assert_eq!(parsed.document(), html);
+/// assert_eq!(parsed.source_url(), Some("https://example.com/"));
+/// ```
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CfHtml {
+    document: String,
+    fragment: Range<usize>,
+    source_url: Option<String>,
+}
+
+impl CfHtml {
+    /// Build from local HTML, keeping any document markup as context
+    ///
+    /// - Existing `<!--StartFragment-->`/`<!--EndFragment-->` markers are
+    ///   honored, so a document that came from CF_HTML goes back unchanged
+    /// - Otherwise a document with a `<body>` has its body content marked
+    ///   as the fragment
+    /// - A bare fragment is wrapped in `<html><body>`
+    pub fn from_html(html: &str) -> Self {
+        if let Some(fragment) = Self::find_markers(html) {
+            return Self {
+                document: html.to_string(),
+                fragment,
+                source_url: None,
+            };
+        }
+
+        let (before, fragment, after) = match Self::find_body(html) {
+            Some(body) => (
+                format!("{}{}", &html[..body.start], CF_HTML_START_MARKER),
+                &html[body.clone()],
+                format!("{}{}", CF_HTML_END_MARKER, &html[body.end..]),
+            ),
+            None => (CF_HTML_PREFIX.to_string(), html, CF_HTML_SUFFIX.to_string()),
+        };
+
+        Self {
+            fragment: before.len()..before.len() + fragment.len(),
+            document: before + fragment + &after,
+            source_url: None,
+        }
     }
-}
 
-// =============================================================================
-// URL Encoding Helpers
-// =============================================================================
+    /// Set the `SourceURL` header
+    ///
+    /// Anything after a line break is dropped, as the header is one line.
+    pub fn with_source_url(mut self, url: impl Into<String>) -> Self {
+        let url = url.into();
+        let url = url.lines().next().unwrap_or("").trim();
+        self.source_url = (!url.is_empty()).then(|| url.to_string());
+        self
+    }
+
+    /// Parse CF_HTML data from the peer
+    ///
+    /// `StartHTML`/`EndHTML` may be missing or `-1`, in which case the
+    /// document is just the fragment.
+    pub fn parse(data: &[u8]) -> ClipboardResult<Self> {
+        let text = std::str::from_utf8(data).map_err(|_| ClipboardError::InvalidUtf8)?;
 
-/// Percent-decode a URL path
-fn percent_decode(input: &str) -> String {
-    let mut result = String::new();
-    let mut chars = input.chars().peekable();
+        let start_fragment = FormatConverter::parse_header_value(text, "StartFragment:")?;
+        let end_fragment = FormatConverter::parse_header_value(text, "EndFragment:")?;
 
-    while let Some(c) = chars.next() {
-        if c == '%' {
-            let hex: String = chars.by_ref().take(2).collect();
-            if let Ok(byte) = u8::from_str_radix(&hex, 16) {
-                result.push(byte as char);
-            } else {
-                result.push('%');
-                result.push_str(&hex);
-            }
-        } else {
-            result.push(c);
+        if start_fragment >= end_fragment || end_fragment > data.len() {
+            return Err(ClipboardError::InvalidCfHtml("offsets outside the data".to_string()));
         }
+
+        let start_html = parse_optional_offset(text, "StartHTML:");
+        let end_html = parse_optional_offset(text, "EndHTML:");
+        let (start, end) = match (start_html, end_html) {
+            (Some(start), Some(end)) if start <= start_fragment && end_fragment <= end && end <= data.len() => {
+                (start, end)
+            }
+            _ => (start_fragment, end_fragment),
+        };
+
+        let invalid = || ClipboardError::InvalidCfHtml("offset inside a character".to_string());
+        let document = text.get(start..end).ok_or_else(invalid)?;
+        text.get(start_fragment..end_fragment).ok_or_else(invalid)?;
+
+        let header = text.get(..start).unwrap_or("");
+        let source_url = header
+            .lines()
+            .find_map(|line| line.strip_prefix("SourceURL:"))
+            .map(str::trim)
+            .filter(|url| !url.is_empty())
+            .map(str::to_string);
+
+        Ok(Self {
+            document: document.to_string(),
+            fragment: start_fragment - start..end_fragment - start,
+            source_url,
+        })
     }
 
-    result
-}
+    /// The full context document, fragment markers included
+    pub fn document(&self) -> &str {
+        &self.document
+    }
 
-/// Percent-encode special characters in a path
-fn percent_encode(input: &str) -> String {
-    let mut result = String::new();
+    /// The selected markup
+    pub fn fragment(&self) -> &str {
+        &self.document[self.fragment.clone()]
+    }
+
+    /// The `SourceURL` header, if present
+    pub fn source_url(&self) -> Option<&str> {
+        self.source_url.as_deref()
+    }
+
+    /// Whether the document carries markup beyond a bare `<html><body>` wrapper
+    pub fn has_context(&self) -> bool {
+        !(is_bare_wrapper(&self.document[..self.fragment.start], "<html><body>")
+            && is_bare_wrapper(&self.document[self.fragment.end..], "</body></html>"))
+    }
 
-    for c in input.chars() {
-        match c {
-            ' ' => result.push_str("%20"),
-            '#' => result.push_str("%23"),
-            '%' => result.push_str("%25"),
-            '?' => result.push_str("%3F"),
-            _ => result.push(c),
+    /// HTML to hand to local applications: the document if it has context,
+    /// otherwise just the fragment
+    pub fn html(&self) -> &str {
+        if self.has_context() {
+            &self.document
+        } else {
+            self.fragment()
         }
     }
 
-    result
+    /// Encode with a header matching the document
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut result = cf_html_header_for(
+            self.fragment.start,
+            self.fragment.len(),
+            self.document.len() - self.fragment.end,
+            self.source_url.as_deref(),
+        );
+        result.push_str(&self.document);
+        result.into_bytes()
+    }
+
+    /// Fragment between existing start and end markers
+    fn find_markers(html: &str) -> Option<Range<usize>> {
+        let start = html.find(CF_HTML_START_MARKER)? + CF_HTML_START_MARKER.len();
+        let end = start + html[start..].find(CF_HTML_END_MARKER)?;
+        Some(start..end)
+    }
+
+    /// Content between the `<body>` open tag and the last `</body>`
+    fn find_body(html: &str) -> Option<Range<usize>> {
+        // ASCII lowercasing keeps byte offsets unchanged
+        let lower = html.to_ascii_lowercase();
+        let open = lower.match_indices("<body").map(|(i, _)| i).find(|&i| {
+            matches!(
+                lower.as_bytes().get(i + 5),
+                Some(b'>' | b'/' | b' ' | b'\t' | b'\r' | b'\n')
+            )
+        })?;
+        let start = open + lower[open..].find('>')? + 1;
+        let end = lower.rfind("</body").filter(|&end| end >= start)?;
+        Some(start..end)
+    }
 }
 
 // =============================================================================
@@ -856,6 +2026,8 @@ fn percent_encode(input: &str) -> String {
 
 /// Windows file descriptor flags
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "serde", serde(transparent))]
 pub struct FileDescriptorFlags(u32);
 
 impl FileDescriptorFlags {
@@ -886,6 +2058,7 @@ impl FileDescriptorFlags {
 /// Represents a single file in a clipboard file transfer operation.
 /// Parsed from the 88-byte FILEDESCRIPTORW Windows structure.
 #[derive(Debug, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct FileDescriptor {
     /// File descriptor flags indicating which fields are valid
     pub flags: FileDescriptorFlags,
@@ -1069,35 +2242,54 @@ impl FileDescriptor {
             .ok_or_else(|| ClipboardError::FormatConversion("Invalid filename".to_string()))?;
 
         // Sanitize filename for Windows compatibility
-        let filename = crate::sanitize::sanitize_filename_for_windows(raw_filename);
+        let name = crate::sanitize::sanitize_filename_for_windows(raw_filename);
+
+        // Directories have no content; metadata.len() is filesystem-specific for them
+        let descriptor = FileDescriptor {
+            flags: FileDescriptorFlags::from_raw(FileDescriptorFlags::FILESIZE),
+            // FILE_ATTRIBUTE_DIRECTORY or FILE_ATTRIBUTE_NORMAL
+            attributes: if metadata.is_dir() { 0x10 } else { 0x80 },
+            creation_time: None,
+            access_time: None,
+            write_time: None,
+            size: Some(if metadata.is_dir() { 0 } else { metadata.len() }),
+            name,
+        };
+
+        Ok(descriptor.to_bytes())
+    }
 
+    /// Encode as a 592-byte FILEDESCRIPTORW structure
+    ///
+    /// The inverse of [`parse`](Self::parse). `flags` are written as they
+    /// are; times and size are written when present. Names longer than 259
+    /// UTF-16 code units are cut to leave room for the terminator.
+    pub fn to_bytes(&self) -> Vec<u8> {
         let mut data = vec![0u8; 592];
 
-        // Set flags: we provide file size
-        let flags = FileDescriptorFlags::FILESIZE;
-        data[0..4].copy_from_slice(&flags.to_le_bytes());
+        data[0..4].copy_from_slice(&self.flags.0.to_le_bytes());
+        data[36..40].copy_from_slice(&self.attributes.to_le_bytes());
 
-        // File attributes (offset 36) - normal file
-        let attributes: u32 = if metadata.is_dir() { 0x10 } else { 0x80 }; // FILE_ATTRIBUTE_DIRECTORY or FILE_ATTRIBUTE_NORMAL
-        data[36..40].copy_from_slice(&attributes.to_le_bytes());
+        let times = [(40, self.creation_time), (48, self.access_time), (56, self.write_time)];
+        for (offset, time) in times {
+            if let Some(time) = time {
+                data[offset..offset + 8].copy_from_slice(&time.to_le_bytes());
+            }
+        }
 
-        // File size (offset 64-71: nFileSizeHigh, nFileSizeLow)
-        let size = metadata.len();
-        let size_high = (size >> 32) as u32;
-        let size_low = size as u32;
-        data[64..68].copy_from_slice(&size_high.to_le_bytes());
-        data[68..72].copy_from_slice(&size_low.to_le_bytes());
+        // nFileSizeHigh, then nFileSizeLow
+        if let Some(size) = self.size {
+            data[64..68].copy_from_slice(&((size >> 32) as u32).to_le_bytes());
+            data[68..72].copy_from_slice(&(size as u32).to_le_bytes());
+        }
 
         // Filename (offset 72, 520 bytes = 260 UTF-16 characters)
-        let filename_utf16: Vec<u16> = filename.encode_utf16().collect();
-        let filename_len = filename_utf16.len().min(259); // Leave room for null terminator
-        for (i, &c) in filename_utf16.iter().take(filename_len).enumerate() {
+        for (i, c) in self.name.encode_utf16().take(259).enumerate() {
             let offset = 72 + i * 2;
             data[offset..offset + 2].copy_from_slice(&c.to_le_bytes());
         }
-        // Null terminator already present (data was initialized to 0)
 
-        Ok(data)
+        data
     }
 
     /// Build FileGroupDescriptorW data from a list of file paths
@@ -1139,6 +2331,66 @@ pub fn build_file_group_descriptor_w(paths: &[std::path::PathBuf]) -> ClipboardR
 // Codepage Conversion Helpers (Synthesized Format Support)
 // =============================================================================
 
+/// Windows ANSI codepage used for CF_TEXT under a locale (LCID)
+///
+/// Mirrors the `LOCALE_IDEFAULTANSICODEPAGE` values of Windows for the
+/// primary languages that do not use Windows-1252.
+pub fn lcid_to_ansi_codepage(lcid: u32) -> u16 {
+    let langid = lcid & 0xFFFF;
+    let primary = langid & 0x3FF;
+
+    match primary {
+        // Chinese: Traditional (Taiwan, Hong Kong, Macao) vs Simplified
+        0x04 => match langid {
+            0x0404 | 0x0C04 | 0x1404 => 950,
+            _ => 936,
+        },
+        0x11 => 932, // Japanese
+        0x12 => 949, // Korean
+        0x1E => 874, // Thai
+        // Serbian/Bosnian Cyrillic share the Croatian primary language
+        0x1A => match langid {
+            0x0C1A | 0x1C1A | 0x201A | 0x301A => 1251,
+            _ => 1250,
+        },
+        // Central European
+        0x05 | 0x0E | 0x15 | 0x18 | 0x1B | 0x1C | 0x24 => 1250,
+        // Cyrillic
+        0x02 | 0x19 | 0x22 | 0x23 | 0x2F | 0x3F | 0x40 | 0x44 | 0x50 => 1251,
+        0x08 => 1253,               // Greek
+        0x1F | 0x2C | 0x43 => 1254, // Turkish, Azeri, Uzbek
+        0x0D => 1255,               // Hebrew
+        0x01 | 0x20 | 0x29 => 1256, // Arabic, Urdu, Farsi
+        0x25..=0x27 => 1257,        // Estonian, Latvian, Lithuanian
+        0x2A => 1258,               // Vietnamese
+        _ => 1252,
+    }
+}
+
+/// Map a Windows ANSI codepage to its encoding_rs decoder
+///
+/// Returns `None` for Windows-1252, which is handled by the built-in table
+/// so the synthesized CF_TEXT output stays identical to [`FormatConverter::text_to_ansi`].
+pub(crate) fn codepage_to_encoding(codepage: u16) -> Option<&'static encoding_rs::Encoding> {
+    let encoding = match codepage {
+        874 => encoding_rs::WINDOWS_874,
+        932 => encoding_rs::SHIFT_JIS,
+        936 => encoding_rs::GBK,
+        949 => encoding_rs::EUC_KR,
+        950 => encoding_rs::BIG5,
+        1250 => encoding_rs::WINDOWS_1250,
+        1251 => encoding_rs::WINDOWS_1251,
+        1253 => encoding_rs::WINDOWS_1253,
+        1254 => encoding_rs::WINDOWS_1254,
+        1255 => encoding_rs::WINDOWS_1255,
+        1256 => encoding_rs::WINDOWS_1256,
+        1257 => encoding_rs::WINDOWS_1257,
+        1258 => encoding_rs::WINDOWS_1258,
+        _ => return None,
+    };
+    Some(encoding)
+}
+
 /// Convert a Unicode character to Windows-1252 (Western European)
 ///
 /// Returns '?' for characters not representable in Windows-1252.
@@ -1348,11 +2600,46 @@ mod tests {
     #[test]
     fn test_format_to_mime() {
         assert_eq!(rdp_format_to_mime(CF_UNICODETEXT), Some("text/plain;charset=utf-8"));
-        assert_eq!(rdp_format_to_mime(CF_HTML), Some("text/html"));
-        assert_eq!(rdp_format_to_mime(CF_PNG), Some("image/png"));
-        assert_eq!(rdp_format_to_mime(CF_FILEGROUPDESCRIPTORW), Some("text/uri-list"));
-        assert_eq!(rdp_format_to_mime(49430), Some("text/uri-list"));
         assert_eq!(rdp_format_to_mime(0xFFFF), None);
+
+        // Registered formats are only known by name
+        assert_eq!(rdp_format_to_mime(CF_HTML), None);
+        assert_eq!(rdp_format_to_mime(CF_FILEGROUPDESCRIPTORW), None);
+        assert_eq!(ClipboardFormat::html().mime_type(), Some("text/html"));
+        assert_eq!(ClipboardFormat::png().mime_type(), Some("image/png"));
+        let files = ClipboardFormat::with_name(0xC0F2, "FileGroupDescriptorW");
+        assert_eq!(files.mime_type(), Some("text/uri-list"));
+        assert_eq!(
+            ClipboardFormat::new(CF_UNICODETEXT).mime_type(),
+            Some("text/plain;charset=utf-8")
+        );
+    }
+
+    #[test]
+    fn test_metafile_format_to_mime() {
+        let expected = if cfg!(feature = "metafile") {
+            Some("image/png")
+        } else {
+            None
+        };
+        assert_eq!(rdp_format_to_mime(CF_ENHMETAFILE), expected);
+        assert_eq!(rdp_format_to_mime(CF_METAFILEPICT), expected);
+    }
+
+    #[cfg(feature = "markdown")]
+    #[test]
+    fn test_markdown_cf_html_round_trip() {
+        let formats = mime_to_rdp_formats(&["text/markdown", "text/html", "text/plain"]);
+        assert_eq!(formats.iter().filter(|f| f.id == CF_HTML).count(), 1);
+        assert_eq!(formats.iter().filter(|f| f.id == CF_UNICODETEXT).count(), 1);
+
+        let converter = FormatConverter::new();
+        let cf_html = converter.markdown_to_cf_html("Hello *world*").unwrap();
+        assert!(String::from_utf8_lossy(&cf_html).contains("<em>world</em>"));
+        assert_eq!(converter.cf_html_to_markdown(&cf_html).unwrap(), "Hello *world*");
+
+        let small = FormatConverter::with_max_size(4);
+        assert!(small.markdown_to_cf_html("Hello").is_err());
     }
 
     #[test]
@@ -1389,6 +2676,96 @@ mod tests {
         assert_eq!(recovered, html);
     }
 
+    #[test]
+    fn test_cf_html_keeps_context_document() {
+        let converter = FormatConverter::new();
+
+        // Shaped like an Excel copy: styles in <head>, cells inside a table
+        let document = "<html xmlns:o=\"urn:schemas-microsoft-com:office:office\">\r\n\
+                        <head><style>td { mso-number-format: General; }</style></head>\r\n\
+                        <body><table><tr><!--StartFragment--><td>42</td><!--EndFragment--></tr></table></body>\r\n\
+                        </html>";
+        let source = "SourceURL:file:///C:/Book1.xlsx\r\n";
+        let start_html =
+            "Version:1.0\r\nStartHTML:\r\nEndHTML:\r\nStartFragment:\r\nEndFragment:\r\n".len() + 4 * 10 + source.len();
+        let start_fragment = start_html + document.find("<td>").unwrap();
+        let end_fragment = start_html + document.find("<!--EndFragment-->").unwrap();
+        let end_html = start_html + document.len();
+        let data = format!(
+            "Version:1.0\r\nStartHTML:{start_html:010}\r\nEndHTML:{end_html:010}\r\n\
+             StartFragment:{start_fragment:010}\r\nEndFragment:{end_fragment:010}\r\n{source}{document}"
+        );
+
+        let parsed = CfHtml::parse(data.as_bytes()).unwrap();
+        assert_eq!(parsed.fragment(), "<td>42</td>");
+        assert_eq!(parsed.source_url(), Some("file:///C:/Book1.xlsx"));
+        assert!(parsed.has_context());
+        assert_eq!(converter.cf_html_to_html(data.as_bytes()).unwrap(), document);
+
+        // Sending the document back reuses its markers instead of re-wrapping it
+        let resent = converter
+            .html_to_cf_html_with_source(document, Some("file:///C:/Book1.xlsx"))
+            .unwrap();
+        assert_eq!(CfHtml::parse(&resent).unwrap(), parsed);
+    }
+
+    #[test]
+    fn test_cf_html_from_full_document() {
+        let document =
+            "<!DOCTYPE html><html><head><meta charset=\"utf-8\"></head><BODY class=\"x\"><p>Hi</p></BODY></html>";
+        let cf_html = CfHtml::from_html(document);
+        assert_eq!(cf_html.fragment(), "<p>Hi</p>");
+        assert!(cf_html.document().starts_with("<!DOCTYPE html><html><head>"));
+
+        let parsed = CfHtml::parse(&cf_html.to_bytes()).unwrap();
+        assert_eq!(parsed, cf_html);
+        assert_eq!(parsed.source_url(), None);
+
+        // Bare fragments and Chrome-style whitespace wrappers have no context
+        assert!(!CfHtml::from_html("<b>x</b>").has_context());
+        let chrome = CfHtml::from_html(
+            "<html>\r\n<body>\r\n<!--StartFragment--><b>x</b><!--EndFragment-->\r\n</body>\r\n</html>",
+        );
+        assert_eq!(chrome.html(), "<b>x</b>");
+    }
+
+    #[test]
+    fn test_cf_html_source_url_header() {
+        let converter = FormatConverter::new();
+        let data = converter
+            .html_to_cf_html_with_source(
+                "<a href=\"img.png\">x</a>",
+                Some("https://example.com/page\r\nEndHTML:0"),
+            )
+            .unwrap();
+        let text = std::str::from_utf8(&data).unwrap();
+
+        assert!(text.contains("SourceURL:https://example.com/page\r\n<html>"));
+        let offset = |key| FormatConverter::parse_header_value(text, key).unwrap();
+        assert_eq!(
+            &text[offset("StartFragment:")..offset("EndFragment:")],
+            "<a href=\"img.png\">x</a>"
+        );
+        assert_eq!(offset("EndHTML:"), text.len());
+
+        // Offsets inside a multi-byte character are rejected rather than panicking
+        let bad = "Version:0.9\r\nStartFragment:47\r\nEndFragment:48\r\n\u{e9}";
+        assert!(CfHtml::parse(bad.as_bytes()).is_err());
+    }
+
+    #[test]
+    fn test_cf_html_header_offsets_past_8_digits() {
+        for fragment_len in [0, 12, 99_999_000, 200 * 1024 * 1024] {
+            let header = cf_html_header(fragment_len);
+            let offset = |key| FormatConverter::parse_header_value(&header, key).unwrap();
+
+            assert_eq!(offset("StartHTML:"), header.len());
+            assert_eq!(offset("StartFragment:"), header.len() + CF_HTML_PREFIX.len());
+            assert_eq!(offset("EndFragment:") - offset("StartFragment:"), fragment_len);
+            assert_eq!(offset("EndHTML:"), offset("EndFragment:") + CF_HTML_SUFFIX.len());
+        }
+    }
+
     #[test]
     fn test_clipboard_format_builders() {
         let text = ClipboardFormat::unicode_text();
@@ -1412,6 +2789,54 @@ mod tests {
         assert_eq!(hdrop[16..20], 1u32.to_le_bytes()); // fWide = TRUE
     }
 
+    #[test]
+    fn test_gnome_copied_files_cut_round_trip() {
+        let converter = FormatConverter::new();
+        let nautilus = b"cut\nfile:///home/user/a.txt\nfile:///home/user/b.txt";
+
+        // Local cut -> RDP: the file list plus a move drop effect
+        let effect = converter.gnome_copied_files_to_drop_effect(nautilus).unwrap();
+        assert_eq!(effect, DROPEFFECT_MOVE.to_le_bytes());
+        let hdrop = converter
+            .convert("x-special/gnome-copied-files", "CF_HDROP", nautilus)
+            .unwrap();
+        let uri_list = converter.hdrop_to_uri_list(&hdrop).unwrap();
+        assert_eq!(uri_list, "file:///home/user/a.txt\r\nfile:///home/user/b.txt");
+
+        // RDP -> local: regenerated with the same verb
+        let operation = converter.drop_effect_operation(&effect).unwrap();
+        assert_eq!(
+            converter.uri_list_to_gnome_copied_files(&uri_list, operation).unwrap(),
+            nautilus
+        );
+
+        // Explorer's copy effect includes DROPEFFECT_LINK
+        let explorer_copy = (DROPEFFECT_COPY | DROPEFFECT_LINK).to_le_bytes();
+        assert_eq!(
+            converter.drop_effect_operation(&explorer_copy).unwrap(),
+            FileOperation::Copy
+        );
+        assert!(converter.drop_effect_operation(&[2, 0]).is_err());
+        assert!(converter.gnome_copied_files_to_drop_effect(b"move\nfile:///x").is_err());
+    }
+
+    #[test]
+    fn test_kde_cut_selection() {
+        let converter = FormatConverter::new();
+        let cut = converter.kde_cut_selection_to_drop_effect(b"1").unwrap();
+        assert_eq!(cut, DROPEFFECT_MOVE.to_le_bytes());
+        assert_eq!(converter.drop_effect_to_kde_cut_selection(&cut).unwrap(), b"1");
+
+        let copy = converter.kde_cut_selection_to_drop_effect(b"0").unwrap();
+        assert_eq!(converter.drop_effect_to_kde_cut_selection(&copy).unwrap(), b"0");
+        assert!(converter.kde_cut_selection_to_drop_effect(b"yes").is_err());
+
+        let formats = mime_to_rdp_formats(&["text/uri-list", "application/x-kde-cutselection"]);
+        let names: Vec<_> = formats.iter().filter_map(|f| f.name.as_deref()).collect();
+        assert_eq!(names, ["FileGroupDescriptorW", "FileContents", "Preferred DropEffect"]);
+        assert_eq!(formats[2].mime_type(), Some("x-special/gnome-copied-files"));
+    }
+
     #[test]
     fn test_hdrop_roundtrip() {
         let converter = FormatConverter::new();
@@ -1423,6 +2848,126 @@ mod tests {
         assert_eq!(recovered, original);
     }
 
+    #[test]
+    fn test_hdrop_roundtrip_non_ascii_and_windows_forms() {
+        let converter = FormatConverter::new();
+        let original = "file:///home/user/J%C3%BCrgen%20%E6%97%A5%E6%9C%AC.txt\r\n\
+                        file:///C:/Users/J%C3%BCrgen/r%C3%A9sum%C3%A9.docx\r\n\
+                        file://fileserver/share/%23notes.txt";
+
+        let hdrop = converter.uri_list_to_hdrop(original).unwrap();
+        let paths: Vec<u16> = hdrop[20..]
+            .chunks_exact(2)
+            .map(|c| u16::from_le_bytes([c[0], c[1]]))
+            .collect();
+        let paths = String::from_utf16(&paths).unwrap();
+        assert!(paths.contains("/home/user/Jürgen 日本.txt\0"));
+        assert!(paths.contains("C:\\Users\\Jürgen\\résumé.docx\0"));
+        assert!(paths.contains("\\\\fileserver\\share\\#notes.txt\0"));
+
+        assert_eq!(converter.hdrop_to_uri_list(&hdrop).unwrap(), original);
+    }
+
+    #[test]
+    fn test_uri_list_rfc2483() {
+        let converter = FormatConverter::new().with_local_hostname("workstation");
+        let paths = |hdrop: Vec<u8>| {
+            let units: Vec<u16> = hdrop[20..]
+                .chunks_exact(2)
+                .map(|c| u16::from_le_bytes([c[0], c[1]]))
+                .collect();
+            String::from_utf16(&units).unwrap()
+        };
+
+        // GNOME names the local machine; other hosts are shares
+        let uri_list = "# copied from Files\r\n\
+                        file://workstation/home/user/a.txt\r\n\
+                        \r\n\
+                        file://WORKSTATION.example.com/home/user/b.txt\r\n\
+                        file://fileserver/share/c.txt\r\n";
+        assert_eq!(
+            paths(converter.uri_list_to_hdrop(uri_list).unwrap()),
+            "/home/user/a.txt\0/home/user/b.txt\0\\\\fileserver\\share\\c.txt\0\0"
+        );
+
+        let err = converter
+            .uri_list_to_hdrop("file:///home/user/a.txt\r\nhttps://example.com/a.txt")
+            .unwrap_err();
+        assert!(matches!(err, ClipboardError::NonFileUri(uri) if uri == "https://example.com/a.txt"));
+        assert!(matches!(
+            converter.uri_list_to_hdrop("# nothing\r\n\r\n"),
+            Err(ClipboardError::FormatConversion(_))
+        ));
+    }
+
+    #[test]
+    fn test_hdrop_path_mapping() {
+        let converter = FormatConverter::new().with_path_mapper(
+            PathMapper::new()
+                .with_mapping("Z:", "/mnt/shared")
+                .with_mapping(r"\\tsclient\C", "/media/client"),
+        );
+
+        let hdrop = converter
+            .uri_list_to_hdrop("file:///mnt/shared/Q3%20report.xlsx\r\nfile:///home/user/local.txt")
+            .unwrap();
+        let paths: Vec<u16> = hdrop[20..]
+            .chunks_exact(2)
+            .map(|c| u16::from_le_bytes([c[0], c[1]]))
+            .collect();
+        assert_eq!(
+            String::from_utf16(&paths).unwrap(),
+            "Z:\\Q3 report.xlsx\0/home/user/local.txt\0\0"
+        );
+        assert_eq!(
+            converter.hdrop_to_uri_list(&hdrop).unwrap(),
+            "file:///mnt/shared/Q3%20report.xlsx\r\nfile:///home/user/local.txt"
+        );
+
+        // Redirected client drives come back as local paths
+        let mut dropped = hdrop[..20].to_vec();
+        for c in "\\\\TSCLIENT\\c\\Users\\me.txt\0C:\\other.txt\0\0".encode_utf16() {
+            dropped.extend_from_slice(&c.to_le_bytes());
+        }
+        assert_eq!(
+            converter.hdrop_to_uri_list(&dropped).unwrap(),
+            "file:///media/client/Users/me.txt\r\nfile:///C:/other.txt"
+        );
+    }
+
+    #[test]
+    fn test_hdrop_non_utf8_paths() {
+        let uri_list = "file:///tmp/caf%C3%A9.txt\r\nfile:///tmp/caf%E9.txt";
+        let paths = |hdrop: Vec<u8>| {
+            let uri_list = FormatConverter::new().hdrop_to_uri_list(&hdrop).unwrap();
+            uri_list.lines().map(str::to_string).collect::<Vec<_>>()
+        };
+
+        let replaced = FormatConverter::new().uri_list_to_hdrop(uri_list).unwrap();
+        assert_eq!(
+            paths(replaced),
+            ["file:///tmp/caf%C3%A9.txt", "file:///tmp/caf%EF%BF%BD.txt"]
+        );
+
+        let skipping = FormatConverter::new().with_non_utf8_path_policy(NonUtf8PathPolicy::Skip);
+        assert_eq!(
+            paths(skipping.uri_list_to_hdrop(uri_list).unwrap()),
+            ["file:///tmp/caf%C3%A9.txt"]
+        );
+        assert!(skipping.uri_list_to_hdrop("file:///tmp/caf%E9.txt").is_err());
+
+        let strict = FormatConverter::new().with_non_utf8_path_policy(NonUtf8PathPolicy::Error);
+        assert!(strict.uri_list_to_hdrop(uri_list).is_err());
+
+        // ANSI HDROP with a Latin-1 name keeps its bytes
+        let mut ansi = vec![20, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0];
+        ansi.extend_from_slice(b"/tmp/caf\xe9.txt\0\0");
+        assert_eq!(
+            FormatConverter::new().hdrop_to_uri_list(&ansi).unwrap(),
+            "file:///tmp/caf%E9.txt"
+        );
+    }
+
     #[test]
     fn test_text_to_ansi() {
         let converter = FormatConverter::new();
@@ -1448,6 +2993,58 @@ mod tests {
         assert_eq!(recovered, text);
     }
 
+    #[test]
+    fn test_parse_locale() {
+        let converter = FormatConverter::new();
+        assert_eq!(converter.parse_locale(&0x0419u32.to_le_bytes()).unwrap(), 0x0419);
+        assert!(converter.parse_locale(&[0x19, 0x04]).is_err());
+    }
+
+    #[test]
+    fn test_lcid_to_ansi_codepage() {
+        assert_eq!(lcid_to_ansi_codepage(0x0409), 1252); // en-US
+        assert_eq!(lcid_to_ansi_codepage(0x0419), 1251); // ru-RU
+        assert_eq!(lcid_to_ansi_codepage(0x0411), 932); // ja-JP
+        assert_eq!(lcid_to_ansi_codepage(0x0804), 936); // zh-CN
+        assert_eq!(lcid_to_ansi_codepage(0x0404), 950); // zh-TW
+        assert_eq!(lcid_to_ansi_codepage(0x041A), 1250); // hr-HR
+        assert_eq!(lcid_to_ansi_codepage(0x0C1A), 1251); // sr-Cyrl-CS
+    }
+
+    #[test]
+    fn test_ansi_with_locale_cyrillic() {
+        let converter = FormatConverter::new();
+        // "Привет" in Windows-1251
+        let data = [0xCF, 0xF0, 0xE8, 0xE2, 0xE5, 0xF2, 0x00];
+        assert_eq!(converter.ansi_to_text_with_locale(&data, 0x0419).unwrap(), "Привет");
+        assert_eq!(converter.text_to_ansi_with_locale("Привет", 0x0419).unwrap(), data);
+    }
+
+    #[test]
+    fn test_ansi_with_locale_shift_jis() {
+        let converter = FormatConverter::new();
+        let ansi = converter.text_to_ansi_with_locale("日本語", 0x0411).unwrap();
+        assert_eq!(ansi, [0x93, 0xFA, 0x96, 0x7B, 0x8C, 0xEA, 0x00]);
+        assert_eq!(converter.ansi_to_text_with_locale(&ansi, 0x0411).unwrap(), "日本語");
+    }
+
+    #[test]
+    fn test_ansi_with_locale_unmappable() {
+        let converter = FormatConverter::new();
+        let ansi = converter.text_to_ansi_with_locale("Ж€", 0x0408).unwrap();
+        assert_eq!(ansi, [b'?', 0x80, 0x00]);
+    }
+
+    #[test]
+    fn test_ansi_with_western_locale_matches_default() {
+        let converter = FormatConverter::new();
+        let text = "Price: \u{20AC}100";
+        assert_eq!(
+            converter.text_to_ansi_with_locale(text, 0x0409).unwrap(),
+            converter.text_to_ansi(text).unwrap()
+        );
+    }
+
     #[test]
     fn test_text_to_oem() {
         let converter = FormatConverter::new();
@@ -1463,6 +3060,51 @@ mod tests {
         assert_eq!(result, "Hi");
     }
 
+    #[test]
+    fn test_incoming_text_normalized_by_default() {
+        let converter = FormatConverter::new();
+
+        let mut data: Vec<u8> = "\u{feff}line1\r\nline2\r\n"
+            .encode_utf16()
+            .flat_map(|c| c.to_le_bytes())
+            .collect();
+        data.extend_from_slice(&[0; 8]);
+        assert_eq!(converter.unicode_to_text(&data).unwrap(), "line1\nline2\n");
+        assert_eq!(converter.ansi_to_text(b"a\r\nb\0\0").unwrap(), "a\nb");
+        assert_eq!(converter.oem_to_text(b"a\r\nb\0").unwrap(), "a\nb");
+        assert_eq!(converter.ansi_to_text_with_locale(b"a\r\nb\0", 0x0419).unwrap(), "a\nb");
+
+        // Outgoing text is sent as is
+        assert_eq!(converter.text_to_ansi("a\nb").unwrap(), b"a\nb\0");
+
+        // Csv keeps its record separators
+        assert_eq!(converter.rdp_csv_to_csv(b"a,b\r\n\0").unwrap(), "a,b\r\n");
+    }
+
+    #[test]
+    fn test_text_normalization_policies() {
+        let full = FormatConverter::new().with_text_normalization(TextNormalization::FULL);
+        assert_eq!(full.text_to_ansi("\u{feff}a\nb\r\nc\0").unwrap(), b"a\r\nb\r\nc\0");
+        assert_eq!(
+            full.text_to_unicode("a\nb").unwrap(),
+            full.text_to_unicode("a\r\nb").unwrap()
+        );
+
+        let none = FormatConverter::new().with_text_normalization(TextNormalization::NONE);
+        let data: Vec<u8> = "a\r\nb\0\0".encode_utf16().flat_map(|c| c.to_le_bytes()).collect();
+        assert_eq!(none.unicode_to_text(&data).unwrap(), "a\r\nb\0");
+
+        // Unchanged text is borrowed
+        assert!(matches!(
+            TextNormalization::FULL.outgoing.apply("a\r\nb"),
+            Cow::Borrowed(_)
+        ));
+        assert!(matches!(
+            TextNormalization::FULL.incoming.apply("ab\0"),
+            Cow::Borrowed("ab")
+        ));
+    }
+
     #[test]
     fn test_synthesized_text_formats_announced() {
         // Verify that announcing text also announces synthesized formats
@@ -1577,8 +3219,178 @@ mod tests {
             .any(|f| f.name.as_ref().is_some_and(|n| n == "Rich Text Format")));
     }
 
+    #[test]
+    fn test_webp_and_avif_formats() {
+        let formats = mime_to_rdp_formats(&["image/webp", "image/avif"]);
+        assert!(formats.contains(&ClipboardFormat::with_name(CF_WEBP, "image/webp")));
+        assert!(formats.contains(&ClipboardFormat::with_name(CF_AVIF, "image/avif")));
+        assert_eq!(
+            formats.iter().any(|f| f.id == CF_DIB),
+            cfg!(any(feature = "webp", feature = "avif"))
+        );
+        assert_eq!(formats[0].mime_type(), Some("image/webp"));
+        assert!(formats.iter().any(|f| f.mime_type() == Some("image/avif")));
+    }
+
+    #[test]
+    fn test_tiff_formats() {
+        let formats = mime_to_rdp_formats(&["image/tiff"]);
+        assert_eq!(formats[0], ClipboardFormat::new(CF_TIFF));
+        assert_eq!(formats.iter().any(|f| f.id == CF_DIB), cfg!(feature = "image"));
+
+        let expected = if cfg!(feature = "image") {
+            "image/png"
+        } else {
+            "image/tiff"
+        };
+        assert_eq!(rdp_format_to_mime(CF_TIFF), Some(expected));
+    }
+
+    #[test]
+    fn test_icon_formats() {
+        let formats = mime_to_rdp_formats(&["image/x-icon", "image/vnd.microsoft.icon"]);
+        assert_eq!(formats[0], ClipboardFormat::with_name(CF_ICO, "image/x-icon"));
+        assert_eq!(formats.iter().filter(|f| f.id == CF_ICO).count(), 1);
+        assert_eq!(formats.iter().any(|f| f.id == CF_PNG), cfg!(feature = "image"));
+
+        assert_eq!(formats[0].mime_type(), Some("image/x-icon"));
+        assert_eq!(rdp_format_to_mime(CF_DSPBITMAP).is_some(), cfg!(feature = "image"));
+    }
+
+    #[test]
+    fn test_svg_formats() {
+        let formats = mime_to_rdp_formats(&["image/svg+xml"]);
+        assert_eq!(formats[0], ClipboardFormat::with_name(CF_SVG, "image/svg+xml"));
+        assert_eq!(formats.iter().any(|f| f.id == CF_PNG), cfg!(feature = "svg"));
+        assert_eq!(formats[0].mime_type(), Some("image/svg+xml"));
+    }
+
     #[test]
     fn test_rtf_format_to_mime() {
-        assert_eq!(rdp_format_to_mime(CF_RTF), Some("text/rtf"));
+        let rtf = ClipboardFormat::with_name(CF_RTF, "Rich Text Format");
+        assert_eq!(rtf.mime_type(), Some("text/rtf"));
+    }
+
+    #[test]
+    fn test_moz_url_formats_announced() {
+        let formats = mime_to_rdp_formats(&["text/x-moz-url", "_NETSCAPE_URL"]);
+        assert_eq!(formats.iter().filter(|f| f.id == CF_URLW).count(), 1);
+        assert!(formats
+            .iter()
+            .any(|f| f.name.as_deref() == Some("UniformResourceLocator")));
+        assert!(formats.iter().all(|f| f.mime_type() == Some("text/x-moz-url")));
+    }
+
+    #[test]
+    fn test_parse_moz_url_utf16() {
+        let converter = FormatConverter::new();
+        let data = converter
+            .build_moz_url("https://example.com/", Some("Example"))
+            .unwrap();
+        let (url, title) = converter.parse_moz_url(&data).unwrap();
+        assert_eq!(url, "https://example.com/");
+        assert_eq!(title.as_deref(), Some("Example"));
+    }
+
+    #[test]
+    fn test_parse_netscape_url_utf8() {
+        let converter = FormatConverter::new();
+        let (url, title) = converter.parse_moz_url(b"https://example.com/\r\n").unwrap();
+        assert_eq!(url, "https://example.com/");
+        assert_eq!(title, None);
+        assert!(converter.parse_moz_url(b"").is_err());
+    }
+
+    #[test]
+    fn test_moz_url_roundtrip_via_url_w() {
+        let converter = FormatConverter::new();
+        let moz = converter
+            .build_moz_url("https://example.com/a b", Some("Title"))
+            .unwrap();
+        let url_w = converter.moz_url_to_url_w(&moz).unwrap();
+        assert_eq!(converter.unicode_to_text(&url_w).unwrap(), "https://example.com/a b");
+
+        let back = converter.url_w_to_moz_url(&url_w).unwrap();
+        let (url, title) = converter.parse_moz_url(&back).unwrap();
+        assert_eq!(url, "https://example.com/a b");
+        assert_eq!(title.as_deref(), Some("https://example.com/a b"));
+    }
+
+    #[test]
+    fn test_moz_url_roundtrip_via_ansi_url() {
+        let converter = FormatConverter::new();
+        let moz = converter.build_moz_url("https://example.com/café", None).unwrap();
+        let url = converter
+            .convert("text/x-moz-url", "UniformResourceLocator", &moz)
+            .unwrap();
+        assert_eq!(url, b"https://example.com/caf%C3%A9\0");
+
+        let back = converter
+            .convert("UniformResourceLocator", "text/x-moz-url", &url)
+            .unwrap();
+        let (url, _) = converter.parse_moz_url(&back).unwrap();
+        assert_eq!(url, "https://example.com/caf%C3%A9");
+        assert!(converter.url_to_moz_url(b"\0").is_err());
+    }
+
+    #[test]
+    fn test_web_custom_data_format() {
+        let formats = mime_to_rdp_formats(&["chromium/x-web-custom-data"]);
+        assert_eq!(
+            formats,
+            vec![ClipboardFormat::with_name(
+                CF_WEB_CUSTOM_DATA,
+                "Chromium Web Custom MIME Data Format"
+            )]
+        );
+        assert_eq!(formats[0].mime_type(), Some("chromium/x-web-custom-data"));
+    }
+
+    #[test]
+    fn test_spreadsheet_formats() {
+        let formats = mime_to_rdp_formats(&["text/csv", "text/plain"]);
+        assert_eq!(
+            formats[0],
+            ClipboardFormat::with_name(CF_XML_SPREADSHEET, "XML Spreadsheet")
+        );
+        assert_eq!(formats[1], ClipboardFormat::with_name(CF_CSV, "Csv"));
+        assert_eq!(formats[1].mime_type(), Some("text/csv"));
+
+        let converter = FormatConverter::new();
+        let csv = converter
+            .csv_to_rdp_csv("name,city\nJos\u{e9},\"Paris, FR\"\n")
+            .unwrap();
+        assert_eq!(csv, b"name,city\r\nJos\xE9,\"Paris, FR\"\r\n\0");
+        assert_eq!(
+            converter.rdp_csv_to_csv(&csv).unwrap(),
+            "name,city\r\nJos\u{e9},\"Paris, FR\"\r\n"
+        );
+
+        let xml = converter.csv_to_xml_spreadsheet("a,1\r\n").unwrap();
+        assert_eq!(xml.last(), Some(&0));
+        assert_eq!(converter.xml_spreadsheet_to_csv(&xml).unwrap(), "a,1\r\n");
+        assert!(matches!(
+            converter.xml_spreadsheet_to_csv(&[0xFF, 0xFE]),
+            Err(ClipboardError::InvalidUtf8)
+        ));
+    }
+
+    #[cfg(feature = "serde")]
+    #[test]
+    fn test_serde_file_descriptor() {
+        let descriptor = FileDescriptor {
+            flags: FileDescriptorFlags::from_raw(FileDescriptorFlags::FILESIZE),
+            attributes: 0x80,
+            creation_time: None,
+            access_time: None,
+            write_time: Some(133_000_000_000_000_000),
+            size: Some(42),
+            name: "notes.txt".to_string(),
+        };
+        let json = serde_json::to_string(&descriptor).unwrap();
+        assert!(json.starts_with(r#"{"flags":64,"attributes":128,"#));
+
+        let parsed: FileDescriptor = serde_json::from_str(&json).unwrap();
+        assert_eq!(parsed.to_bytes(), descriptor.to_bytes());
     }
 }
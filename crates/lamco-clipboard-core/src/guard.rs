@@ -0,0 +1,431 @@
+//! Drop guards for transfer resources.
+//!
+//! Clipboard transfers hold resources that must be released when they end:
+//! clipboard data locks on the peer (`clipDataId`), temporary files for
+//! received content, and reservations against a memory budget. Transfers run
+//! inside futures that can be cancelled at any await point, so cleanup code
+//! after an `.await` is not guaranteed to run. These guards release their
+//! resource in [`Drop`] instead, which also runs when a future is dropped.
+//!
+//! [`ClipDataLocks`] and [`MemoryBudget`] count what is still held, and
+//! `debug_assert_released` lets tests catch guards that were leaked with
+//! [`std::mem::forget`] or kept alive by mistake.
+
+use crate::{ClipboardError, ClipboardResult};
+use std::collections::HashSet;
+use std::fmt;
+use std::io::ErrorKind;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex, MutexGuard};
+
+// =============================================================================
+// Clipboard data locks
+// =============================================================================
+
+type ReleaseFn = dyn Fn(u32) + Send + Sync;
+
+struct LocksInner {
+    held: Mutex<HashSet<u32>>,
+    on_release: Box<ReleaseFn>,
+}
+
+/// Set of clipboard data locks held on the peer.
+///
+/// Cloning is cheap and shares the set. The release callback typically
+/// queues an Unlock Clipboard Data PDU; it runs on the thread that drops the
+/// guard and must not block.
+///
+/// # Example
+///
+/// ```
+/// use lamco_clipboard_core::ClipDataLocks;
+/// use std::sync::mpsc;
+///
+/// let (unlock_tx, unlock_rx) = mpsc::channel();
+/// let locks = ClipDataLocks::new(move |id| {
+///     let _ = unlock_tx.send(id);
+/// });
+///
+/// {
+///     let _lock = locks.acquire(7).unwrap();
+///     assert!(locks.is_held(7));
+/// }
+///
+/// assert_eq!(unlock_rx.try_recv(), Ok(7));
+/// locks.debug_assert_released();
+/// ```
+#[derive(Clone)]
+pub struct ClipDataLocks {
+    inner: Arc<LocksInner>,
+}
+
+impl ClipDataLocks {
+    /// Create an empty lock set that calls `on_release` when a lock is dropped
+    pub fn new(on_release: impl Fn(u32) + Send + Sync + 'static) -> Self {
+        Self {
+            inner: Arc::new(LocksInner {
+                held: Mutex::new(HashSet::new()),
+                on_release: Box::new(on_release),
+            }),
+        }
+    }
+
+    /// Record a lock on `clip_data_id`, returning the guard that releases it.
+    ///
+    /// Fails if the ID is already locked, since the peer only tracks one
+    /// lock per ID.
+    pub fn acquire(&self, clip_data_id: u32) -> ClipboardResult<ClipDataLock> {
+        if !self.held().insert(clip_data_id) {
+            return Err(ClipboardError::InvalidState(format!(
+                "clipDataId {} is already locked",
+                clip_data_id
+            )));
+        }
+
+        Ok(ClipDataLock {
+            clip_data_id,
+            locks: self.clone(),
+        })
+    }
+
+    /// Whether a lock on `clip_data_id` is currently held
+    pub fn is_held(&self, clip_data_id: u32) -> bool {
+        self.held().contains(&clip_data_id)
+    }
+
+    /// Number of locks currently held
+    pub fn len(&self) -> usize {
+        self.held().len()
+    }
+
+    /// Whether no locks are held
+    pub fn is_empty(&self) -> bool {
+        self.held().is_empty()
+    }
+
+    /// Assert (in debug builds) that every lock guard has been dropped
+    #[track_caller]
+    pub fn debug_assert_released(&self) {
+        if cfg!(debug_assertions) {
+            let held = self.held();
+            assert!(held.is_empty(), "leaked clipboard data locks: {:?}", held);
+        }
+    }
+
+    fn held(&self) -> MutexGuard<'_, HashSet<u32>> {
+        // The set stays consistent even if a holder panicked
+        self.inner.held.lock().unwrap_or_else(|e| e.into_inner())
+    }
+}
+
+impl fmt::Debug for ClipDataLocks {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("ClipDataLocks").field("held", &*self.held()).finish()
+    }
+}
+
+/// A held clipboard data lock, released on drop
+#[derive(Debug)]
+#[must_use = "the lock is released as soon as the guard is dropped"]
+pub struct ClipDataLock {
+    clip_data_id: u32,
+    locks: ClipDataLocks,
+}
+
+impl ClipDataLock {
+    /// The locked `clipDataId`
+    pub fn clip_data_id(&self) -> u32 {
+        self.clip_data_id
+    }
+}
+
+impl Drop for ClipDataLock {
+    fn drop(&mut self) {
+        if self.locks.held().remove(&self.clip_data_id) {
+            (self.locks.inner.on_release)(self.clip_data_id);
+        }
+    }
+}
+
+// =============================================================================
+// Temporary files
+// =============================================================================
+
+/// Temporary file that is deleted on drop unless persisted.
+///
+/// The guard does not create the file; it takes ownership of a path the
+/// caller is about to write, so a cancelled transfer leaves nothing behind.
+#[derive(Debug)]
+#[must_use = "the file is deleted as soon as the guard is dropped"]
+pub struct TempFileGuard {
+    path: Option<PathBuf>,
+}
+
+impl TempFileGuard {
+    /// Take ownership of a temporary file path
+    pub fn new(path: impl Into<PathBuf>) -> Self {
+        Self {
+            path: Some(path.into()),
+        }
+    }
+
+    /// Path of the guarded file
+    pub fn path(&self) -> &Path {
+        self.path.as_deref().unwrap_or_else(|| Path::new(""))
+    }
+
+    /// Keep the file, returning its path
+    pub fn persist(mut self) -> PathBuf {
+        self.path.take().unwrap_or_default()
+    }
+}
+
+impl Drop for TempFileGuard {
+    fn drop(&mut self) {
+        let Some(path) = self.path.take() else {
+            return;
+        };
+        match std::fs::remove_file(&path) {
+            Ok(()) => tracing::trace!("Removed temporary file {:?}", path),
+            Err(e) if e.kind() == ErrorKind::NotFound => {}
+            Err(e) => tracing::warn!("Failed to remove temporary file {:?}: {}", path, e),
+        }
+    }
+}
+
+// =============================================================================
+// Memory budget
+// =============================================================================
+
+#[derive(Debug)]
+struct BudgetInner {
+    limit: usize,
+    used: AtomicUsize,
+}
+
+/// Shared byte budget for buffered clipboard data.
+///
+/// Transfers reserve their expected size before buffering and get a
+/// [`BudgetReservation`] that returns the bytes on drop. Cloning is cheap
+/// and shares the budget.
+///
+/// # Example
+///
+/// ```
+/// use lamco_clipboard_core::MemoryBudget;
+///
+/// let budget = MemoryBudget::new(1024);
+/// let reservation = budget.try_reserve(1000).unwrap();
+/// assert!(budget.try_reserve(100).is_err());
+///
+/// drop(reservation);
+/// assert_eq!(budget.available(), 1024);
+/// ```
+#[derive(Debug, Clone)]
+pub struct MemoryBudget {
+    inner: Arc<BudgetInner>,
+}
+
+impl MemoryBudget {
+    /// Create a budget of `limit` bytes
+    pub fn new(limit: usize) -> Self {
+        Self {
+            inner: Arc::new(BudgetInner {
+                limit,
+                used: AtomicUsize::new(0),
+            }),
+        }
+    }
+
+    /// Reserve `bytes`, failing if the budget would be exceeded
+    pub fn try_reserve(&self, bytes: usize) -> ClipboardResult<BudgetReservation> {
+        let limit = self.inner.limit;
+        self.inner
+            .used
+            .fetch_update(Ordering::AcqRel, Ordering::Acquire, |used| {
+                used.checked_add(bytes).filter(|&total| total <= limit)
+            })
+            .map_err(|used| ClipboardError::DataSizeExceeded {
+                actual: used.saturating_add(bytes),
+                max: limit,
+            })?;
+
+        Ok(BudgetReservation {
+            bytes,
+            budget: self.clone(),
+        })
+    }
+
+    /// Total budget in bytes
+    pub fn limit(&self) -> usize {
+        self.inner.limit
+    }
+
+    /// Bytes currently reserved
+    pub fn used(&self) -> usize {
+        self.inner.used.load(Ordering::Acquire)
+    }
+
+    /// Bytes still available
+    pub fn available(&self) -> usize {
+        self.inner.limit.saturating_sub(self.used())
+    }
+
+    /// Assert (in debug builds) that every reservation has been dropped
+    #[track_caller]
+    pub fn debug_assert_released(&self) {
+        debug_assert_eq!(self.used(), 0, "leaked memory budget reservations");
+    }
+
+    fn release(&self, bytes: usize) {
+        let previous = self.inner.used.fetch_sub(bytes, Ordering::AcqRel);
+        debug_assert!(previous >= bytes, "memory budget released more than reserved");
+    }
+}
+
+/// Bytes reserved from a [`MemoryBudget`], returned on drop
+#[derive(Debug)]
+#[must_use = "the reservation is released as soon as the guard is dropped"]
+pub struct BudgetReservation {
+    bytes: usize,
+    budget: MemoryBudget,
+}
+
+impl BudgetReservation {
+    /// Number of bytes reserved
+    pub fn bytes(&self) -> usize {
+        self.bytes
+    }
+
+    /// Shrink the reservation, returning the difference to the budget.
+    ///
+    /// Useful once the actual size is known to be below the estimate.
+    pub fn shrink_to(&mut self, bytes: usize) {
+        if bytes < self.bytes {
+            self.budget.release(self.bytes - bytes);
+            self.bytes = bytes;
+        }
+    }
+}
+
+impl Drop for BudgetReservation {
+    fn drop(&mut self) {
+        self.budget.release(self.bytes);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::noop_waker;
+    use std::future::Future;
+    use std::pin::pin;
+    use std::sync::atomic::AtomicU32;
+    use std::task::Context;
+
+    /// Poll a future once, then drop it as a cancelled task would be
+    fn poll_once_and_cancel<F: Future>(future: F) {
+        let waker = noop_waker();
+        let mut future = pin!(future);
+        assert!(future.as_mut().poll(&mut Context::from_waker(&waker)).is_pending());
+    }
+
+    #[test]
+    fn test_lock_released_on_drop() {
+        let released = Arc::new(AtomicU32::new(0));
+        let counter = Arc::clone(&released);
+        let locks = ClipDataLocks::new(move |id| {
+            counter.store(id, Ordering::SeqCst);
+        });
+
+        let lock = locks.acquire(42).unwrap();
+        assert_eq!(lock.clip_data_id(), 42);
+        assert!(locks.acquire(42).is_err());
+        assert_eq!(locks.len(), 1);
+
+        drop(lock);
+        assert_eq!(released.load(Ordering::SeqCst), 42);
+        assert!(locks.is_empty());
+        locks.debug_assert_released();
+    }
+
+    #[test]
+    fn test_guards_released_when_future_cancelled() {
+        let locks = ClipDataLocks::new(|_| {});
+        let budget = MemoryBudget::new(100);
+
+        let lock = locks.acquire(1).unwrap();
+        let reservation = budget.try_reserve(60).unwrap();
+        poll_once_and_cancel(async move {
+            let _lock = lock;
+            let _reservation = reservation;
+            std::future::pending::<()>().await;
+        });
+
+        locks.debug_assert_released();
+        budget.debug_assert_released();
+    }
+
+    #[test]
+    #[cfg(debug_assertions)]
+    #[should_panic(expected = "leaked clipboard data locks")]
+    fn test_leaked_lock_detected() {
+        let locks = ClipDataLocks::new(|_| {});
+        std::mem::forget(locks.acquire(3).unwrap());
+        locks.debug_assert_released();
+    }
+
+    #[test]
+    #[cfg(debug_assertions)]
+    #[should_panic(expected = "leaked memory budget reservations")]
+    fn test_leaked_reservation_detected() {
+        let budget = MemoryBudget::new(10);
+        std::mem::forget(budget.try_reserve(5).unwrap());
+        budget.debug_assert_released();
+    }
+
+    #[test]
+    fn test_budget_limits() {
+        let budget = MemoryBudget::new(100);
+        let mut first = budget.try_reserve(80).unwrap();
+
+        match budget.try_reserve(30) {
+            Err(ClipboardError::DataSizeExceeded { actual, max }) => {
+                assert_eq!(actual, 110);
+                assert_eq!(max, 100);
+            }
+            other => panic!("expected DataSizeExceeded, got {:?}", other),
+        }
+
+        first.shrink_to(50);
+        assert_eq!(budget.used(), 50);
+        let second = budget.try_reserve(30).unwrap();
+        assert_eq!(budget.available(), 20);
+
+        drop(first);
+        drop(second);
+        budget.debug_assert_released();
+    }
+
+    #[test]
+    fn test_temp_file_removed_unless_persisted() {
+        let dir = std::env::temp_dir();
+        let removed = dir.join(format!("lamco-guard-removed-{}", std::process::id()));
+        let kept = dir.join(format!("lamco-guard-kept-{}", std::process::id()));
+        std::fs::write(&removed, b"data").unwrap();
+        std::fs::write(&kept, b"data").unwrap();
+
+        drop(TempFileGuard::new(&removed));
+        assert!(!removed.exists());
+
+        let guard = TempFileGuard::new(&kept);
+        assert_eq!(guard.path(), kept.as_path());
+        let path = guard.persist();
+        assert!(path.exists());
+        std::fs::remove_file(path).unwrap();
+
+        // Never-created files are fine
+        drop(TempFileGuard::new(dir.join("lamco-guard-never-created")));
+    }
+}
@@ -16,11 +16,16 @@
 //! # Supported Conversions
 //!
 //! - PNG ↔ DIB (CF_DIB format 8, 40-byte header)
-//! - PNG ↔ DIBV5 (CF_DIBV5 format 17, 124-byte header with alpha)
+//! - PNG ↔ DIBV5 (CF_DIBV5 format 17, 124-byte header with alpha and ICC profile)
 //! - JPEG ↔ DIB
 //! - JPEG ↔ DIBV5
 //! - BMP ↔ DIB
 //! - GIF → PNG (read-only, converts to PNG for output)
+//! - TIFF ↔ PNG (CF_TIFF format 6), TIFF → DIB and DIBV5
+//! - ICO/CUR → PNG, DIB and DIBV5 (largest frame)
+//! - CF_DSPBITMAP → PNG (carried as a packed DIB)
+//! - WebP → DIB, DIBV5 and PNG (`webp` feature)
+//! - AVIF → DIB, DIBV5 and PNG (`avif` feature, links the system dav1d library)
 //!
 //! # DIB vs DIBV5
 //!
@@ -29,12 +34,293 @@
 //!
 //! Use DIBV5 for images with transparency. Modern Windows applications like
 //! Paint.NET and screenshot tools use DIBV5 to preserve alpha channels.
+//!
+//! An ICC profile embedded in a DIBV5 (`PROFILE_EMBEDDED`) is carried over to
+//! the PNG `iCCP` chunk and back; otherwise images are tagged as sRGB.
+//!
+//! # Size Limits
+//!
+//! A screenshot of a large monitor becomes a DIB of well over 100 MB. Images
+//! larger than [`ImageLimits`] are downscaled while converting to DIB, DIBV5
+//! or PNG. The plain conversion functions use [`ImageLimits::default()`];
+//! the `_with_limits` variants and
+//! [`FormatConverter::image_limits`](crate::formats::FormatConverter::image_limits)
+//! take custom limits.
+//!
+//! Before any pixel buffer is allocated, the dimensions in the image header
+//! are checked against [`ImageLimits::max_decoded_bytes`], so a small PNG
+//! claiming to be 100000x100000 fails with [`ClipboardError::ImageTooLarge`]
+//! instead of exhausting memory.
+//!
+//! # Encoder Settings
+//!
+//! JPEG quality, JPEG chroma subsampling and PNG compression effort are set
+//! with [`ImageEncodeOptions`], passed to the `_with_options` variants or
+//! [`FormatConverter::image_encode`](crate::formats::FormatConverter::image_encode).
 
 use bytes::{BufMut, BytesMut};
-use image::{DynamicImage, ImageFormat};
+use image::codecs::png::{CompressionType, FilterType as PngFilter, PngDecoder, PngEncoder};
+use image::imageops::FilterType;
+use image::{DynamicImage, ImageDecoder, ImageEncoder, ImageFormat};
+use std::io::Write;
 
 use crate::{ClipboardError, ClipboardResult};
 
+// =============================================================================
+// Size Limits
+// =============================================================================
+
+/// Default for [`ImageLimits::max_decoded_bytes`], enough for an 8K RGBA image
+pub const DEFAULT_MAX_DECODED_BYTES: u64 = 256 * 1024 * 1024;
+
+/// How images larger than [`ImageLimits`] are handled
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "serde", serde(rename_all = "snake_case"))]
+pub enum ResizeStrategy {
+    /// Downscale with a Lanczos filter (sharpest, slowest)
+    #[default]
+    Lanczos,
+
+    /// Downscale with a bilinear filter
+    Bilinear,
+
+    /// Fail with [`ClipboardError::ImageTooLarge`]
+    Reject,
+}
+
+/// Largest image produced by a conversion to DIB, DIBV5 or PNG
+///
+/// Images exceeding any limit are scaled down uniformly until they fit all
+/// of them, or rejected, depending on `resize_strategy`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "serde", serde(default))]
+pub struct ImageLimits {
+    /// Maximum width in pixels (default: 8192)
+    pub max_width: u32,
+
+    /// Maximum height in pixels (default: 8192)
+    pub max_height: u32,
+
+    /// Maximum number of pixels (default: twice 4K UHD, about 64 MB as a DIB)
+    pub max_pixels: u64,
+
+    /// What to do with larger images
+    pub resize_strategy: ResizeStrategy,
+
+    /// Largest decoded pixel buffer in bytes (default: 256 MiB)
+    ///
+    /// Checked against the image header before decoding. Larger images are
+    /// always rejected, whatever the resize strategy.
+    pub max_decoded_bytes: u64,
+}
+
+impl ImageLimits {
+    /// No limits: images are always converted at full size
+    pub const UNLIMITED: Self = Self {
+        max_width: u32::MAX,
+        max_height: u32::MAX,
+        max_pixels: u64::MAX,
+        resize_strategy: ResizeStrategy::Lanczos,
+        max_decoded_bytes: u64::MAX,
+    };
+
+    /// Size that fits all limits, or `None` if the image already fits
+    pub fn fit(&self, width: u32, height: u32) -> Option<(u32, u32)> {
+        let pixels = u64::from(width) * u64::from(height);
+        if width <= self.max_width && height <= self.max_height && pixels <= self.max_pixels {
+            return None;
+        }
+
+        let scale = (f64::from(self.max_width) / f64::from(width))
+            .min(f64::from(self.max_height) / f64::from(height))
+            .min((self.max_pixels as f64 / pixels as f64).sqrt());
+        let scaled = |side: u32| ((f64::from(side) * scale).floor() as u32).max(1);
+        Some((scaled(width), scaled(height)))
+    }
+
+    /// Downscale an image to fit the limits according to the resize strategy
+    pub fn apply(&self, image: DynamicImage) -> ClipboardResult<DynamicImage> {
+        let (width, height) = (image.width(), image.height());
+        let Some((new_width, new_height)) = self.fit(width, height) else {
+            return Ok(image);
+        };
+
+        let filter = match self.resize_strategy {
+            ResizeStrategy::Lanczos => FilterType::Lanczos3,
+            ResizeStrategy::Bilinear => FilterType::Triangle,
+            ResizeStrategy::Reject => return Err(ClipboardError::ImageTooLarge { width, height }),
+        };
+        tracing::debug!("Downscaling {}x{} image to {}x{}", width, height, new_width, new_height);
+        Ok(image.resize_exact(new_width, new_height, filter))
+    }
+}
+
+impl Default for ImageLimits {
+    fn default() -> Self {
+        Self {
+            max_width: 8192,
+            max_height: 8192,
+            max_pixels: 2 * 3840 * 2160,
+            resize_strategy: ResizeStrategy::default(),
+            max_decoded_bytes: DEFAULT_MAX_DECODED_BYTES,
+        }
+    }
+}
+
+// =============================================================================
+// Encoder Settings
+// =============================================================================
+
+/// Chroma subsampling of JPEG output
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "serde", serde(rename_all = "snake_case"))]
+pub enum ChromaSubsampling {
+    /// 4:4:4, full color resolution
+    #[default]
+    Yuv444,
+
+    /// 4:2:2, half horizontal color resolution
+    Yuv422,
+
+    /// 4:2:0, half color resolution in both directions (smallest)
+    Yuv420,
+}
+
+/// Compression effort for PNG output
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "serde", serde(rename_all = "snake_case"))]
+pub enum PngCompression {
+    /// Fastest encoding, larger files
+    #[default]
+    Fast,
+
+    /// Balance of speed and size
+    Default,
+
+    /// Smallest files, slowest encoding
+    Best,
+}
+
+/// How the alpha channel of 32-bit DIBs from the peer is read
+///
+/// Windows applications disagree: some put straight alpha on the clipboard,
+/// others premultiply the color channels, and many GDI programs leave the
+/// alpha byte as garbage. Reading premultiplied data as straight shows dark
+/// halos around soft edges; trusting garbage alpha loses the image.
+///
+/// A bitmap whose alpha is zero everywhere is always taken as opaque, since
+/// that is what GDI writes into the unused byte of 32-bit pixels.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "serde", serde(rename_all = "snake_case"))]
+pub enum DibAlpha {
+    /// Color channels do not depend on alpha
+    #[default]
+    Straight,
+
+    /// Color channels are already multiplied by alpha
+    Premultiplied,
+
+    /// Ignore transparency: blend onto `background` (RGB) and output an
+    /// opaque image
+    Composite {
+        /// Color shown through transparent pixels
+        background: [u8; 3],
+    },
+}
+
+impl DibAlpha {
+    /// Reinterpret the alpha of a decoded DIB as straight alpha
+    pub(crate) fn apply(&self, image: DynamicImage) -> DynamicImage {
+        let DynamicImage::ImageRgba8(mut rgba) = image else {
+            return image;
+        };
+
+        if rgba.pixels().all(|p| p[3] == 0) {
+            rgba.pixels_mut().for_each(|p| p[3] = 255);
+            return DynamicImage::ImageRgba8(rgba);
+        }
+
+        match *self {
+            DibAlpha::Straight => {}
+            DibAlpha::Premultiplied => {
+                for p in rgba.pixels_mut().filter(|p| p[3] != 0 && p[3] != 255) {
+                    let a = u16::from(p[3]);
+                    for c in 0..3 {
+                        p[c] = ((u16::from(p[c]) * 255 + a / 2) / a).min(255) as u8;
+                    }
+                }
+            }
+            DibAlpha::Composite { background } => {
+                for p in rgba.pixels_mut() {
+                    let a = u16::from(p[3]);
+                    for c in 0..3 {
+                        p[c] = ((u16::from(p[c]) * a + u16::from(background[c]) * (255 - a) + 127) / 255) as u8;
+                    }
+                    p[3] = 255;
+                }
+            }
+        }
+        DynamicImage::ImageRgba8(rgba)
+    }
+}
+
+/// Encoder settings for JPEG and PNG output
+///
+/// The defaults favor fidelity and speed; deployments short on bandwidth can
+/// lower the JPEG quality, subsample chroma and compress PNG harder.
+/// `dib_alpha` sets how the alpha of DIB sources is read before encoding.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "serde", serde(default))]
+pub struct ImageEncodeOptions {
+    /// JPEG quality from 1 to 100 (default: 75)
+    pub jpeg_quality: u8,
+
+    /// JPEG chroma subsampling (default: 4:4:4)
+    pub chroma_subsampling: ChromaSubsampling,
+
+    /// PNG compression effort (default: fast)
+    pub png_compression: PngCompression,
+
+    /// Alpha interpretation of 32-bit DIB and DIBV5 sources (default: straight)
+    pub dib_alpha: DibAlpha,
+}
+
+impl Default for ImageEncodeOptions {
+    fn default() -> Self {
+        Self {
+            jpeg_quality: 75,
+            chroma_subsampling: ChromaSubsampling::default(),
+            png_compression: PngCompression::default(),
+            dib_alpha: DibAlpha::default(),
+        }
+    }
+}
+
+impl ImageEncodeOptions {
+    fn png_encoder<W: Write>(&self, w: W) -> PngEncoder<W> {
+        let compression = match self.png_compression {
+            PngCompression::Fast => CompressionType::Fast,
+            PngCompression::Default => CompressionType::Default,
+            PngCompression::Best => CompressionType::Best,
+        };
+        PngEncoder::new_with_quality(w, compression, PngFilter::Adaptive)
+    }
+
+    fn sampling_factor(&self) -> jpeg_encoder::SamplingFactor {
+        match self.chroma_subsampling {
+            ChromaSubsampling::Yuv444 => jpeg_encoder::SamplingFactor::R_4_4_4,
+            ChromaSubsampling::Yuv422 => jpeg_encoder::SamplingFactor::R_4_2_2,
+            ChromaSubsampling::Yuv420 => jpeg_encoder::SamplingFactor::R_4_2_0,
+        }
+    }
+}
+
 /// Convert PNG image data to DIB (Device Independent Bitmap) format.
 ///
 /// DIB is the standard Windows bitmap format used in clipboard operations.
@@ -49,28 +335,24 @@ use crate::{ClipboardError, ClipboardResult};
 /// let dib_data = png_to_dib(&png_data)?;
 /// ```
 pub fn png_to_dib(png_data: &[u8]) -> ClipboardResult<Vec<u8>> {
-    let image = image::load_from_memory_with_format(png_data, ImageFormat::Png)
-        .map_err(|e| ClipboardError::ImageDecode(e.to_string()))?;
+    png_to_dib_with_limits(png_data, &ImageLimits::default())
+}
 
-    create_dib_from_image(&image)
+/// Convert PNG image data to DIB format, downscaling to `limits`.
+pub fn png_to_dib_with_limits(png_data: &[u8], limits: &ImageLimits) -> ClipboardResult<Vec<u8>> {
+    to_dib(png_data, ImageFormat::Png, limits)
 }
 
 /// Convert JPEG image data to DIB format.
 pub fn jpeg_to_dib(jpeg_data: &[u8]) -> ClipboardResult<Vec<u8>> {
-    let image = image::load_from_memory_with_format(jpeg_data, ImageFormat::Jpeg)
-        .map_err(|e| ClipboardError::ImageDecode(e.to_string()))?;
-
-    create_dib_from_image(&image)
+    to_dib(jpeg_data, ImageFormat::Jpeg, &ImageLimits::default())
 }
 
 /// Convert GIF image data to DIB format.
 ///
 /// Note: GIF animations are not supported; only the first frame is converted.
 pub fn gif_to_dib(gif_data: &[u8]) -> ClipboardResult<Vec<u8>> {
-    let image = image::load_from_memory_with_format(gif_data, ImageFormat::Gif)
-        .map_err(|e| ClipboardError::ImageDecode(e.to_string()))?;
-
-    create_dib_from_image(&image)
+    to_dib(gif_data, ImageFormat::Gif, &ImageLimits::default())
 }
 
 /// Convert BMP file data to DIB format.
@@ -96,30 +378,62 @@ pub fn bmp_to_dib(bmp_data: &[u8]) -> ClipboardResult<Vec<u8>> {
 /// This is the most common conversion for clipboard images going from
 /// Windows to Linux, as PNG is widely supported and lossless.
 pub fn dib_to_png(dib_data: &[u8]) -> ClipboardResult<Vec<u8>> {
-    let image = parse_dib_to_image(dib_data)?;
+    dib_to_png_with_limits(dib_data, &ImageLimits::default())
+}
 
-    let mut png_data = Vec::new();
-    image
-        .write_to(&mut std::io::Cursor::new(&mut png_data), ImageFormat::Png)
-        .map_err(|e| ClipboardError::ImageEncode(e.to_string()))?;
+/// Convert DIB data to PNG format, downscaling to `limits`.
+pub fn dib_to_png_with_limits(dib_data: &[u8], limits: &ImageLimits) -> ClipboardResult<Vec<u8>> {
+    dib_to_png_with_options(dib_data, limits, &ImageEncodeOptions::default())
+}
 
+/// Convert DIB data to PNG format, downscaling to `limits` and compressing
+/// as set in `options`.
+pub fn dib_to_png_with_options(
+    dib_data: &[u8],
+    limits: &ImageLimits,
+    options: &ImageEncodeOptions,
+) -> ClipboardResult<Vec<u8>> {
+    let mut png_data = Vec::new();
+    write_dib_as_png(dib_data, &mut png_data, limits, options)?;
     Ok(png_data)
 }
 
+/// Encode DIB data as PNG into `writer`, downscaling to `limits`.
+///
+/// Output is written as the encoder produces it rather than collected first;
+/// see also [`FormatConverter::dib_to_png_stream`](crate::formats::FormatConverter::dib_to_png_stream).
+pub fn write_dib_as_png<W: Write>(
+    dib_data: &[u8],
+    writer: W,
+    limits: &ImageLimits,
+    options: &ImageEncodeOptions,
+) -> ClipboardResult<()> {
+    let image = options
+        .dib_alpha
+        .apply(parse_dib_to_image_with_limits(dib_data, limits)?);
+    write_png(&limits.apply(image)?, writer, options)
+}
+
 /// Convert DIB data to JPEG format.
 ///
 /// JPEG is lossy but produces smaller files. Use for photographs.
 pub fn dib_to_jpeg(dib_data: &[u8]) -> ClipboardResult<Vec<u8>> {
-    let image = parse_dib_to_image(dib_data)?;
+    dib_to_jpeg_with_options(dib_data, &ImageEncodeOptions::default())
+}
 
+/// Convert DIB data to JPEG format with the quality and chroma subsampling
+/// set in `options`.
+pub fn dib_to_jpeg_with_options(dib_data: &[u8], options: &ImageEncodeOptions) -> ClipboardResult<Vec<u8>> {
     let mut jpeg_data = Vec::new();
-    image
-        .write_to(&mut std::io::Cursor::new(&mut jpeg_data), ImageFormat::Jpeg)
-        .map_err(|e| ClipboardError::ImageEncode(e.to_string()))?;
-
+    write_dib_as_jpeg(dib_data, &mut jpeg_data, options)?;
     Ok(jpeg_data)
 }
 
+/// Encode DIB data as JPEG into `writer`, writing output as it is produced.
+pub fn write_dib_as_jpeg<W: Write>(dib_data: &[u8], writer: W, options: &ImageEncodeOptions) -> ClipboardResult<()> {
+    write_jpeg(&options.dib_alpha.apply(parse_dib_to_image(dib_data)?), writer, options)
+}
+
 /// Convert DIB data to BMP file format.
 ///
 /// This adds the 14-byte BMP file header to the DIB data.
@@ -152,9 +466,10 @@ pub fn dib_to_bmp(dib_data: &[u8]) -> ClipboardResult<Vec<u8>> {
 ///
 /// Automatically detects the input format based on magic bytes.
 pub fn any_to_dib(data: &[u8]) -> ClipboardResult<Vec<u8>> {
-    let image = image::load_from_memory(data).map_err(|e| ClipboardError::ImageDecode(e.to_string()))?;
+    let limits = ImageLimits::default();
+    let image = decode_guessed(data, &limits)?;
 
-    create_dib_from_image(&image)
+    create_dib_from_image(&image, &limits)
 }
 
 // =============================================================================
@@ -164,8 +479,9 @@ pub fn any_to_dib(data: &[u8]) -> ClipboardResult<Vec<u8>> {
 /// Convert PNG image data to DIBV5 format.
 ///
 /// DIBV5 is the extended Windows bitmap format that supports alpha channels
-/// and color space information. This creates an sRGB DIBV5 with a 124-byte
-/// BITMAPV5HEADER.
+/// and color space information. This creates a DIBV5 with a 124-byte
+/// BITMAPV5HEADER, embedding the PNG's ICC profile if it has one and
+/// tagging it as sRGB otherwise.
 ///
 /// # Example
 ///
@@ -176,49 +492,119 @@ pub fn any_to_dib(data: &[u8]) -> ClipboardResult<Vec<u8>> {
 /// let dibv5_data = png_to_dibv5(&png_data)?;
 /// ```
 pub fn png_to_dibv5(png_data: &[u8]) -> ClipboardResult<Vec<u8>> {
-    let image = image::load_from_memory_with_format(png_data, ImageFormat::Png)
-        .map_err(|e| ClipboardError::ImageDecode(e.to_string()))?;
+    png_to_dibv5_with_limits(png_data, &ImageLimits::default())
+}
+
+/// Convert PNG image data to DIBV5 format, downscaling to `limits`.
+pub fn png_to_dibv5_with_limits(png_data: &[u8], limits: &ImageLimits) -> ClipboardResult<Vec<u8>> {
+    let mut decoder =
+        PngDecoder::new(std::io::Cursor::new(png_data)).map_err(|e| ClipboardError::ImageDecode(e.to_string()))?;
+    let icc_profile = decoder.icc_profile().unwrap_or_default();
+    let image = decode_limited(decoder, limits)?;
 
-    create_dibv5_from_image(&image)
+    create_dibv5_with_profile(&image, icc_profile.as_deref(), limits)
 }
 
 /// Convert JPEG image data to DIBV5 format.
 ///
 /// Note: JPEG doesn't support transparency, so the alpha channel will be 255.
 pub fn jpeg_to_dibv5(jpeg_data: &[u8]) -> ClipboardResult<Vec<u8>> {
-    let image = image::load_from_memory_with_format(jpeg_data, ImageFormat::Jpeg)
-        .map_err(|e| ClipboardError::ImageDecode(e.to_string()))?;
-
-    create_dibv5_from_image(&image)
+    to_dibv5(jpeg_data, ImageFormat::Jpeg, &ImageLimits::default())
 }
 
 /// Convert DIBV5 data to PNG format.
 ///
 /// This is the most common conversion for clipboard images going from
-/// Windows to Linux. PNG preserves the alpha channel from DIBV5.
+/// Windows to Linux. PNG preserves the alpha channel and any embedded ICC
+/// profile from DIBV5.
 pub fn dibv5_to_png(dibv5_data: &[u8]) -> ClipboardResult<Vec<u8>> {
-    let image = parse_dibv5_to_image(dibv5_data)?;
+    dibv5_to_png_with_limits(dibv5_data, &ImageLimits::default())
+}
 
-    let mut png_data = Vec::new();
-    image
-        .write_to(&mut std::io::Cursor::new(&mut png_data), ImageFormat::Png)
-        .map_err(|e| ClipboardError::ImageEncode(e.to_string()))?;
+/// Convert DIBV5 data to PNG format, downscaling to `limits`.
+pub fn dibv5_to_png_with_limits(dibv5_data: &[u8], limits: &ImageLimits) -> ClipboardResult<Vec<u8>> {
+    dibv5_to_png_with_options(dibv5_data, limits, &ImageEncodeOptions::default())
+}
 
+/// Convert DIBV5 data to PNG format, downscaling to `limits` and compressing
+/// as set in `options`.
+pub fn dibv5_to_png_with_options(
+    dibv5_data: &[u8],
+    limits: &ImageLimits,
+    options: &ImageEncodeOptions,
+) -> ClipboardResult<Vec<u8>> {
+    let mut png_data = Vec::new();
+    write_dibv5_as_png(dibv5_data, &mut png_data, limits, options)?;
     Ok(png_data)
 }
 
+/// Encode DIBV5 data as PNG into `writer`, downscaling to `limits` and
+/// keeping its ICC profile.
+///
+/// Output is written as the encoder produces it rather than collected first.
+pub fn write_dibv5_as_png<W: Write>(
+    dibv5_data: &[u8],
+    writer: W,
+    limits: &ImageLimits,
+    options: &ImageEncodeOptions,
+) -> ClipboardResult<()> {
+    let image = options
+        .dib_alpha
+        .apply(parse_dibv5_to_image_with_limits(dibv5_data, limits)?);
+    let rgba = limits.apply(image)?.to_rgba8();
+
+    let mut encoder = options.png_encoder(writer);
+    if let Some(profile) = dibv5_icc_profile(dibv5_data) {
+        if let Err(e) = encoder.set_icc_profile(profile) {
+            tracing::debug!("Dropping DIBV5 ICC profile: {}", e);
+        }
+    }
+    encoder
+        .write_image(
+            rgba.as_raw(),
+            rgba.width(),
+            rgba.height(),
+            image::ExtendedColorType::Rgba8,
+        )
+        .map_err(|e| ClipboardError::ImageEncode(e.to_string()))
+}
+
+/// Extract the ICC profile embedded in DIBV5 data, if any.
+///
+/// Only `PROFILE_EMBEDDED` color spaces carry profile bytes; linked profiles
+/// name a file on the Windows machine and cannot be used remotely.
+pub fn dibv5_icc_profile(dibv5_data: &[u8]) -> Option<Vec<u8>> {
+    if dibv5_data.len() < DIBV5_HEADER_SIZE || read_u32(dibv5_data, 0) as usize != DIBV5_HEADER_SIZE {
+        return None;
+    }
+    if read_u32(dibv5_data, 56) != LCS_PROFILE_EMBEDDED {
+        return None;
+    }
+
+    // bV5ProfileData is relative to the start of the header
+    let offset = read_u32(dibv5_data, 112) as usize;
+    let size = read_u32(dibv5_data, 116) as usize;
+    let end = offset.checked_add(size)?;
+    if size == 0 || offset < DIBV5_HEADER_SIZE {
+        return None;
+    }
+    dibv5_data.get(offset..end).map(<[u8]>::to_vec)
+}
+
 /// Convert DIBV5 data to JPEG format.
 ///
 /// Note: JPEG is lossy and doesn't support transparency.
 /// Use `dibv5_to_png` to preserve alpha.
 pub fn dibv5_to_jpeg(dibv5_data: &[u8]) -> ClipboardResult<Vec<u8>> {
-    let image = parse_dibv5_to_image(dibv5_data)?;
+    dibv5_to_jpeg_with_options(dibv5_data, &ImageEncodeOptions::default())
+}
 
+/// Convert DIBV5 data to JPEG format with the quality and chroma subsampling
+/// set in `options`.
+pub fn dibv5_to_jpeg_with_options(dibv5_data: &[u8], options: &ImageEncodeOptions) -> ClipboardResult<Vec<u8>> {
     let mut jpeg_data = Vec::new();
-    image
-        .write_to(&mut std::io::Cursor::new(&mut jpeg_data), ImageFormat::Jpeg)
-        .map_err(|e| ClipboardError::ImageEncode(e.to_string()))?;
-
+    let image = options.dib_alpha.apply(parse_dibv5_to_image(dibv5_data)?);
+    write_jpeg(&image, &mut jpeg_data, options)?;
     Ok(jpeg_data)
 }
 
@@ -227,9 +613,300 @@ pub fn dibv5_to_jpeg(dibv5_data: &[u8]) -> ClipboardResult<Vec<u8>> {
 /// Automatically detects the input format based on magic bytes.
 /// Use DIBV5 when transparency preservation is important.
 pub fn any_to_dibv5(data: &[u8]) -> ClipboardResult<Vec<u8>> {
-    let image = image::load_from_memory(data).map_err(|e| ClipboardError::ImageDecode(e.to_string()))?;
+    let limits = ImageLimits::default();
+    let image = decode_guessed(data, &limits)?;
+
+    create_dibv5_from_image(&image, &limits)
+}
+
+// =============================================================================
+// TIFF (CF_TIFF format 6)
+// =============================================================================
+
+/// Convert TIFF image data to PNG.
+///
+/// Only the first page of a multi-page TIFF is converted.
+pub fn tiff_to_png(tiff_data: &[u8]) -> ClipboardResult<Vec<u8>> {
+    to_png(
+        tiff_data,
+        ImageFormat::Tiff,
+        &ImageLimits::default(),
+        &ImageEncodeOptions::default(),
+    )
+}
+
+/// Convert TIFF image data to DIB format.
+pub fn tiff_to_dib(tiff_data: &[u8]) -> ClipboardResult<Vec<u8>> {
+    to_dib(tiff_data, ImageFormat::Tiff, &ImageLimits::default())
+}
+
+/// Convert TIFF image data to DIBV5 format, keeping the alpha channel.
+pub fn tiff_to_dibv5(tiff_data: &[u8]) -> ClipboardResult<Vec<u8>> {
+    to_dibv5(tiff_data, ImageFormat::Tiff, &ImageLimits::default())
+}
+
+/// Convert PNG image data to an uncompressed RGBA TIFF.
+pub fn png_to_tiff(png_data: &[u8]) -> ClipboardResult<Vec<u8>> {
+    let image = DynamicImage::ImageRgba8(decode(png_data, ImageFormat::Png, &ImageLimits::default())?.to_rgba8());
+
+    let mut tiff_data = Vec::new();
+    image
+        .write_to(&mut std::io::Cursor::new(&mut tiff_data), ImageFormat::Tiff)
+        .map_err(|e| ClipboardError::ImageEncode(e.to_string()))?;
+
+    Ok(tiff_data)
+}
+
+/// Check the TIFF byte-order mark and version, returning the data unchanged.
+///
+/// Accepts classic TIFF and BigTIFF in either byte order.
+pub fn validate_tiff(tiff_data: &[u8]) -> ClipboardResult<Vec<u8>> {
+    match tiff_data.get(..4) {
+        Some(b"II*\0" | b"MM\0*" | b"II+\0" | b"MM\0+") => Ok(tiff_data.to_vec()),
+        _ => Err(ClipboardError::ImageDecode("Invalid TIFF header".to_string())),
+    }
+}
+
+// =============================================================================
+// Icons and cursors (.ico / .cur)
+// =============================================================================
+
+/// Convert the largest frame of an ICO or CUR file to PNG.
+///
+/// Frames are compared by pixel count, then color depth. Both PNG frames and
+/// BMP frames with an AND mask are supported.
+pub fn ico_to_png(ico_data: &[u8]) -> ClipboardResult<Vec<u8>> {
+    ico_to_png_with_options(ico_data, &ImageLimits::default(), &ImageEncodeOptions::default())
+}
+
+/// Convert the largest frame of an ICO or CUR file to PNG, downscaling to
+/// `limits` and compressing as set in `options`.
+pub fn ico_to_png_with_options(
+    ico_data: &[u8],
+    limits: &ImageLimits,
+    options: &ImageEncodeOptions,
+) -> ClipboardResult<Vec<u8>> {
+    to_png(&largest_icon_frame(ico_data)?, ImageFormat::Ico, limits, options)
+}
+
+/// Convert the largest frame of an ICO or CUR file to DIB format.
+pub fn ico_to_dib(ico_data: &[u8]) -> ClipboardResult<Vec<u8>> {
+    ico_to_dib_with_limits(ico_data, &ImageLimits::default())
+}
+
+/// Convert the largest frame of an ICO or CUR file to DIB format, downscaling to `limits`.
+pub fn ico_to_dib_with_limits(ico_data: &[u8], limits: &ImageLimits) -> ClipboardResult<Vec<u8>> {
+    to_dib(&largest_icon_frame(ico_data)?, ImageFormat::Ico, limits)
+}
+
+/// Convert the largest frame of an ICO or CUR file to DIBV5 format, keeping transparency.
+pub fn ico_to_dibv5(ico_data: &[u8]) -> ClipboardResult<Vec<u8>> {
+    ico_to_dibv5_with_limits(ico_data, &ImageLimits::default())
+}
+
+/// Convert the largest frame of an ICO or CUR file to DIBV5 format, downscaling to `limits`.
+pub fn ico_to_dibv5_with_limits(ico_data: &[u8], limits: &ImageLimits) -> ClipboardResult<Vec<u8>> {
+    to_dibv5(&largest_icon_frame(ico_data)?, ImageFormat::Ico, limits)
+}
+
+/// ICONDIR type of an icon file
+const ICO_TYPE_ICON: u16 = 1;
+
+/// ICONDIR type of a cursor file
+const ICO_TYPE_CURSOR: u16 = 2;
+
+/// Size of the ICONDIR header
+const ICONDIR_SIZE: usize = 6;
+
+/// Size of each ICONDIRENTRY
+const ICONDIRENTRY_SIZE: usize = 16;
+
+/// Rebuild an icon file holding only its largest frame
+///
+/// The image decoder picks the deepest frame rather than the largest, and
+/// reads the hotspot fields of cursors as planes and bit depth; a one-entry
+/// icon directory sidesteps both.
+fn largest_icon_frame(data: &[u8]) -> ClipboardResult<Vec<u8>> {
+    let invalid = |msg: &str| ClipboardError::ImageDecode(msg.to_string());
+    if data.len() < ICONDIR_SIZE || data[0..2] != [0, 0] {
+        return Err(invalid("Not an ICO or CUR file"));
+    }
+    let kind = u16::from_le_bytes([data[2], data[3]]);
+    if kind != ICO_TYPE_ICON && kind != ICO_TYPE_CURSOR {
+        return Err(invalid("Not an ICO or CUR file"));
+    }
+    let count = usize::from(u16::from_le_bytes([data[4], data[5]]));
+    let entries = data
+        .get(ICONDIR_SIZE..ICONDIR_SIZE + count * ICONDIRENTRY_SIZE)
+        .ok_or_else(|| invalid("ICO directory truncated"))?;
+
+    // A size byte of 0 means 256; cursors keep the hotspot where icons keep the bit depth
+    let side = |b: u8| if b == 0 { 256 } else { u32::from(b) };
+    let depth = |entry: &[u8]| match kind {
+        ICO_TYPE_ICON => u16::from_le_bytes([entry[6], entry[7]]),
+        _ => 0,
+    };
+    let entry = entries
+        .chunks_exact(ICONDIRENTRY_SIZE)
+        .max_by_key(|e| (side(e[0]) * side(e[1]), depth(e)))
+        .ok_or_else(|| invalid("ICO file has no frames"))?;
+
+    let size = read_u32(entry, 8) as usize;
+    let offset = read_u32(entry, 12) as usize;
+    let frame = offset
+        .checked_add(size)
+        .and_then(|end| data.get(offset..end))
+        .ok_or_else(|| invalid("ICO frame truncated"))?;
+
+    let mut ico = Vec::with_capacity(ICONDIR_SIZE + ICONDIRENTRY_SIZE + frame.len());
+    ico.extend_from_slice(&[0, 0]);
+    ico.extend_from_slice(&ICO_TYPE_ICON.to_le_bytes());
+    ico.extend_from_slice(&1u16.to_le_bytes());
+    ico.extend_from_slice(&entry[0..4]);
+    ico.extend_from_slice(&1u16.to_le_bytes()); // planes
+    ico.extend_from_slice(&depth(entry).to_le_bytes());
+    ico.extend_from_slice(&(frame.len() as u32).to_le_bytes());
+    ico.extend_from_slice(&((ICONDIR_SIZE + ICONDIRENTRY_SIZE) as u32).to_le_bytes());
+    ico.extend_from_slice(frame);
+    Ok(ico)
+}
+
+// =============================================================================
+// WebP and AVIF (features `webp` and `avif`)
+// =============================================================================
+
+/// Convert WebP image data to DIB format.
+///
+/// Animated WebP is not supported; only the first frame is converted.
+#[cfg(feature = "webp")]
+pub fn webp_to_dib(webp_data: &[u8]) -> ClipboardResult<Vec<u8>> {
+    to_dib(webp_data, ImageFormat::WebP, &ImageLimits::default())
+}
+
+/// Convert WebP image data to DIBV5 format, keeping the alpha channel.
+#[cfg(feature = "webp")]
+pub fn webp_to_dibv5(webp_data: &[u8]) -> ClipboardResult<Vec<u8>> {
+    to_dibv5(webp_data, ImageFormat::WebP, &ImageLimits::default())
+}
+
+/// Convert WebP image data to PNG, for targets that can't read WebP.
+#[cfg(feature = "webp")]
+pub fn webp_to_png(webp_data: &[u8]) -> ClipboardResult<Vec<u8>> {
+    to_png(
+        webp_data,
+        ImageFormat::WebP,
+        &ImageLimits::default(),
+        &ImageEncodeOptions::default(),
+    )
+}
+
+/// Convert AVIF image data to DIB format.
+#[cfg(feature = "avif")]
+pub fn avif_to_dib(avif_data: &[u8]) -> ClipboardResult<Vec<u8>> {
+    to_dib(avif_data, ImageFormat::Avif, &ImageLimits::default())
+}
+
+/// Convert AVIF image data to DIBV5 format, keeping the alpha channel.
+#[cfg(feature = "avif")]
+pub fn avif_to_dibv5(avif_data: &[u8]) -> ClipboardResult<Vec<u8>> {
+    to_dibv5(avif_data, ImageFormat::Avif, &ImageLimits::default())
+}
+
+/// Convert AVIF image data to PNG, for targets that can't read AVIF.
+#[cfg(feature = "avif")]
+pub fn avif_to_png(avif_data: &[u8]) -> ClipboardResult<Vec<u8>> {
+    to_png(
+        avif_data,
+        ImageFormat::Avif,
+        &ImageLimits::default(),
+        &ImageEncodeOptions::default(),
+    )
+}
+
+fn decode(data: &[u8], format: ImageFormat, limits: &ImageLimits) -> ClipboardResult<DynamicImage> {
+    let decoder = image::ImageReader::with_format(std::io::Cursor::new(data), format)
+        .into_decoder()
+        .map_err(|e| ClipboardError::ImageDecode(e.to_string()))?;
+    decode_limited(decoder, limits)
+}
+
+/// Decode an image whose format is detected from its magic bytes
+fn decode_guessed(data: &[u8], limits: &ImageLimits) -> ClipboardResult<DynamicImage> {
+    let decoder = image::ImageReader::new(std::io::Cursor::new(data))
+        .with_guessed_format()
+        .map_err(|e| ClipboardError::ImageDecode(e.to_string()))?
+        .into_decoder()
+        .map_err(|e| ClipboardError::ImageDecode(e.to_string()))?;
+    decode_limited(decoder, limits)
+}
+
+/// Decode pixels only after checking the header against `max_decoded_bytes`
+fn decode_limited(mut decoder: impl ImageDecoder, limits: &ImageLimits) -> ClipboardResult<DynamicImage> {
+    let (width, height) = decoder.dimensions();
+    if decoder.total_bytes() > limits.max_decoded_bytes {
+        return Err(ClipboardError::ImageTooLarge { width, height });
+    }
+
+    // Also bounds the decoder's own intermediate buffers
+    let mut decoder_limits = image::Limits::default();
+    decoder_limits.max_alloc = Some(limits.max_decoded_bytes);
+    decoder
+        .set_limits(decoder_limits)
+        .map_err(|_| ClipboardError::ImageTooLarge { width, height })?;
+
+    DynamicImage::from_decoder(decoder).map_err(|e| match e {
+        image::ImageError::Limits(_) => ClipboardError::ImageTooLarge { width, height },
+        e => ClipboardError::ImageDecode(e.to_string()),
+    })
+}
 
-    create_dibv5_from_image(&image)
+/// Decode an image in a known format to DIB, downscaling to `limits`
+pub(crate) fn to_dib(data: &[u8], format: ImageFormat, limits: &ImageLimits) -> ClipboardResult<Vec<u8>> {
+    create_dib_from_image(&decode(data, format, limits)?, limits)
+}
+
+/// Decode an image in a known format to DIBV5, downscaling to `limits`
+pub(crate) fn to_dibv5(data: &[u8], format: ImageFormat, limits: &ImageLimits) -> ClipboardResult<Vec<u8>> {
+    create_dibv5_from_image(&decode(data, format, limits)?, limits)
+}
+
+/// Decode an image in a known format to PNG, downscaling to `limits`
+pub(crate) fn to_png(
+    data: &[u8],
+    format: ImageFormat,
+    limits: &ImageLimits,
+    options: &ImageEncodeOptions,
+) -> ClipboardResult<Vec<u8>> {
+    encode_png(&limits.apply(decode(data, format, limits)?)?, options)
+}
+
+fn encode_png(image: &DynamicImage, options: &ImageEncodeOptions) -> ClipboardResult<Vec<u8>> {
+    let mut png_data = Vec::new();
+    write_png(image, &mut png_data, options)?;
+    Ok(png_data)
+}
+
+fn write_png<W: Write>(image: &DynamicImage, writer: W, options: &ImageEncodeOptions) -> ClipboardResult<()> {
+    image
+        .write_with_encoder(options.png_encoder(writer))
+        .map_err(|e| ClipboardError::ImageEncode(e.to_string()))
+}
+
+fn write_jpeg<W: Write>(image: &DynamicImage, writer: W, options: &ImageEncodeOptions) -> ClipboardResult<()> {
+    let rgb = image.to_rgb8();
+    let (Ok(width), Ok(height)) = (u16::try_from(rgb.width()), u16::try_from(rgb.height())) else {
+        return Err(ClipboardError::ImageEncode(format!(
+            "{}x{} exceeds the JPEG size limit",
+            rgb.width(),
+            rgb.height()
+        )));
+    };
+
+    let mut encoder = jpeg_encoder::Encoder::new(writer, options.jpeg_quality.clamp(1, 100));
+    encoder.set_sampling_factor(options.sampling_factor());
+    encoder
+        .encode(rgb.as_raw(), width, height, jpeg_encoder::ColorType::Rgb)
+        .map_err(|e| ClipboardError::ImageEncode(e.to_string()))
 }
 
 /// Check if image data has any transparent pixels.
@@ -237,7 +914,7 @@ pub fn any_to_dibv5(data: &[u8]) -> ClipboardResult<Vec<u8>> {
 /// Returns `true` if any pixel has alpha < 255.
 /// Use this to decide whether to use DIB or DIBV5 format.
 pub fn has_transparency(image_data: &[u8]) -> bool {
-    if let Ok(img) = image::load_from_memory(image_data) {
+    if let Ok(img) = decode_guessed(image_data, &ImageLimits::default()) {
         let rgba = img.to_rgba8();
         rgba.pixels().any(|p| p[3] != 255)
     } else {
@@ -259,13 +936,40 @@ pub fn dib_dimensions(dib_data: &[u8]) -> ClipboardResult<(u32, u32)> {
     Ok((width, height))
 }
 
+/// Compute a 64-bit difference hash (dHash) of an image in any supported
+/// format, DIB and DIBV5 included.
+///
+/// Re-encoding an image between PNG, DIB and JPEG changes its bytes but
+/// barely its dHash, so two hashes a few bits apart (see
+/// [`u64::count_ones`] of their XOR) most likely show the same picture.
+/// Returns `None` if the data does not decode as an image.
+pub fn perceptual_hash(image_data: &[u8]) -> Option<u64> {
+    let image = match image_data.get(..4).map(|header| read_u32(header, 0)) {
+        Some(40 | 108 | 124) => parse_dibv5_to_image(image_data).ok()?,
+        _ => decode_guessed(image_data, &ImageLimits::default()).ok()?,
+    };
+
+    // One bit per horizontally adjacent pair of a 9x8 grayscale thumbnail
+    let gray = image.thumbnail_exact(9, 8).to_luma8();
+    let mut hash = 0u64;
+    for y in 0..8 {
+        for x in 0..8 {
+            hash = (hash << 1) | u64::from(gray.get_pixel(x, y)[0] < gray.get_pixel(x + 1, y)[0]);
+        }
+    }
+    Some(hash)
+}
+
 // =============================================================================
 // Internal Functions
 // =============================================================================
 
-/// Create DIB data from a DynamicImage.
-fn create_dib_from_image(image: &DynamicImage) -> ClipboardResult<Vec<u8>> {
-    let rgba = image.to_rgba8();
+/// Create DIB data from a DynamicImage, downscaled to `limits`.
+fn create_dib_from_image(image: &DynamicImage, limits: &ImageLimits) -> ClipboardResult<Vec<u8>> {
+    let rgba = match limits.fit(image.width(), image.height()) {
+        Some(_) => limits.apply(image.clone())?.to_rgba8(),
+        None => image.to_rgba8(),
+    };
     let (width, height) = (rgba.width(), rgba.height());
 
     let mut dib = BytesMut::new();
@@ -287,18 +991,99 @@ fn create_dib_from_image(image: &DynamicImage) -> ClipboardResult<Vec<u8>> {
     dib.put_u32_le(0); // biClrImportant
 
     // Pixel data (convert RGBA to BGRA - Windows byte order)
-    for pixel in rgba.pixels() {
-        dib.put_u8(pixel[2]); // Blue
-        dib.put_u8(pixel[1]); // Green
-        dib.put_u8(pixel[0]); // Red
-        dib.put_u8(pixel[3]); // Alpha
+    let mut dib = dib.to_vec();
+    append_bgra(&mut dib, &rgba);
+
+    Ok(dib)
+}
+
+/// Pixel buffers below this size are converted on the calling thread
+#[cfg(feature = "parallel")]
+const PARALLEL_MIN_BYTES: usize = 1 << 20;
+
+/// Fill `out` one row at a time, spreading rows across threads with the
+/// `parallel` feature
+fn fill_rows<F>(out: &mut [u8], row_len: usize, fill: F)
+where
+    F: Fn(usize, &mut [u8]) + Send + Sync,
+{
+    if row_len == 0 {
+        return;
     }
 
-    Ok(dib.to_vec())
+    #[cfg(feature = "parallel")]
+    if out.len() >= PARALLEL_MIN_BYTES {
+        use rayon::prelude::*;
+        out.par_chunks_exact_mut(row_len)
+            .enumerate()
+            .for_each(|(y, row)| fill(y, row));
+        return;
+    }
+
+    out.chunks_exact_mut(row_len)
+        .enumerate()
+        .for_each(|(y, row)| fill(y, row));
+}
+
+/// Swap the first and third byte of every 4-byte pixel (RGBA ↔ BGRA)
+///
+/// Works on whole `u32`s so the compiler can vectorize the loop.
+fn swap_red_blue(row: &mut [u8]) {
+    for pixel in row.chunks_exact_mut(4) {
+        let v = u32::from_le_bytes([pixel[0], pixel[1], pixel[2], pixel[3]]);
+        let swapped = (v & 0xFF00_FF00) | ((v >> 16) & 0xFF) | ((v & 0xFF) << 16);
+        pixel.copy_from_slice(&swapped.to_le_bytes());
+    }
+}
+
+/// Append the pixels of a top-down RGBA image to `dib` as BGRA
+fn append_bgra(dib: &mut Vec<u8>, rgba: &image::RgbaImage) {
+    let row_len = rgba.width() as usize * 4;
+    let start = dib.len();
+    dib.resize(start + rgba.as_raw().len(), 0);
+
+    let src = rgba.as_raw();
+    fill_rows(&mut dib[start..], row_len, |y, row| {
+        row.copy_from_slice(&src[y * row_len..][..row_len]);
+        swap_red_blue(row);
+    });
+}
+
+/// Read a little-endian u32 at a known-valid offset
+fn read_u32(data: &[u8], offset: usize) -> u32 {
+    u32::from_le_bytes([data[offset], data[offset + 1], data[offset + 2], data[offset + 3]])
+}
+
+/// Reject a bitmap whose decoded RGBA pixels would exceed `max_decoded_bytes`
+///
+/// Runs on the header alone, before any size is computed or allocated.
+fn check_decoded_size(width: u32, height: u32, max_decoded_bytes: u64) -> ClipboardResult<()> {
+    let decoded = u64::from(width).saturating_mul(u64::from(height)).saturating_mul(4);
+    if decoded > max_decoded_bytes {
+        return Err(ClipboardError::ImageTooLarge { width, height });
+    }
+    Ok(())
+}
+
+/// Row size (rows are padded to 4 bytes) and total size of DIB pixel data
+fn dib_data_size(width: u32, height: u32, bit_count: u16) -> ClipboardResult<(usize, usize)> {
+    let overflow = || ClipboardError::ImageDecode(format!("DIB size {}x{} overflows", width, height));
+    let row_bits = (width as usize)
+        .checked_mul(usize::from(bit_count))
+        .ok_or_else(overflow)?;
+    let row_size = row_bits.div_ceil(32) * 4;
+    let total = row_size.checked_mul(height as usize).ok_or_else(overflow)?;
+    Ok((row_size, total))
 }
 
 /// Parse DIB data into a DynamicImage.
-fn parse_dib_to_image(dib_data: &[u8]) -> ClipboardResult<DynamicImage> {
+pub(crate) fn parse_dib_to_image(dib_data: &[u8]) -> ClipboardResult<DynamicImage> {
+    parse_dib_to_image_with_limits(dib_data, &ImageLimits::default())
+}
+
+/// Parse DIB data into a DynamicImage, rejecting it before decoding if its
+/// pixels would exceed `limits.max_decoded_bytes`.
+fn parse_dib_to_image_with_limits(dib_data: &[u8], limits: &ImageLimits) -> ClipboardResult<DynamicImage> {
     if dib_data.len() < 40 {
         return Err(ClipboardError::ImageDecode("DIB too small".to_string()));
     }
@@ -309,36 +1094,214 @@ fn parse_dib_to_image(dib_data: &[u8]) -> ClipboardResult<DynamicImage> {
         return Err(ClipboardError::ImageDecode("Invalid DIB header size".to_string()));
     }
 
+    // V4/V5 headers carry their color masks inside the header
+    if bi_size as usize >= DIBV4_HEADER_SIZE {
+        return parse_full_dibv5(dib_data, limits);
+    }
+
     let width = i32::from_le_bytes([dib_data[4], dib_data[5], dib_data[6], dib_data[7]]).unsigned_abs();
     let height_raw = i32::from_le_bytes([dib_data[8], dib_data[9], dib_data[10], dib_data[11]]);
     let height = height_raw.unsigned_abs();
     let top_down = height_raw < 0;
     let bit_count = u16::from_le_bytes([dib_data[14], dib_data[15]]);
+    check_decoded_size(width, height, limits.max_decoded_bytes)?;
+
+    let compression = read_u32(dib_data, 16);
 
     let header_size = bi_size as usize;
-    if header_size >= dib_data.len() {
+    let mask_count = match compression {
+        BI_BITFIELDS => 3,
+        BI_ALPHABITFIELDS => 4,
+        _ => 0,
+    };
+    // With a plain BITMAPINFOHEADER the masks follow the header; the 52- and
+    // 56-byte V2/V3 headers carry them inside, at the same offset
+    let masks_size = if header_size == 40 { mask_count * 4 } else { 0 };
+    if mask_count > 0 {
+        if !matches!(header_size, 40 | 52 | 56) {
+            return Err(ClipboardError::ImageDecode(format!(
+                "Invalid header size {} for bitfields DIB",
+                header_size
+            )));
+        }
+        // A V3 header carries an alpha mask even with BI_BITFIELDS
+        let masks_read = if header_size == 56 { 4 } else { mask_count };
+        if dib_data.len() < 40 + masks_read * 4 {
+            return Err(ClipboardError::ImageDecode("DIB color masks truncated".to_string()));
+        }
+    }
+    if header_size + masks_size >= dib_data.len() {
         return Err(ClipboardError::ImageDecode("DIB header larger than data".to_string()));
     }
-    let pixel_data = &dib_data[header_size..];
+    let pixel_data = &dib_data[header_size + masks_size..];
 
     // Convert based on bit depth
     let image = match bit_count {
+        16 | 32 if mask_count > 0 => {
+            let alpha_mask = if mask_count == 4 || header_size == 56 {
+                read_u32(dib_data, 52)
+            } else {
+                0
+            };
+            let masks = [
+                read_u32(dib_data, 40),
+                read_u32(dib_data, 44),
+                read_u32(dib_data, 48),
+                alpha_mask,
+            ];
+            convert_bitfields_dib(pixel_data, bit_count, width, height, top_down, masks)?
+        }
         32 => convert_32bit_dib(pixel_data, width, height, top_down)?,
+        16 => convert_bitfields_dib(pixel_data, bit_count, width, height, top_down, RGB555_MASKS)?,
         24 => convert_24bit_dib(pixel_data, width, height, top_down)?,
-        _ => {
-            return Err(ClipboardError::ImageDecode(format!(
-                "Unsupported DIB bit depth: {}",
-                bit_count
-            )))
+        1 | 4 | 8 if is_indexed(bit_count, compression) => {
+            convert_indexed_dib(dib_data, header_size, bit_count, compression, width, height, top_down)?
         }
+        _ => return Err(ClipboardError::UnsupportedBitDepth(bit_count)),
     };
 
     Ok(image)
 }
 
-/// Convert 32-bit BGRA DIB to RGBA image.
-fn convert_32bit_dib(pixel_data: &[u8], width: u32, height: u32, top_down: bool) -> ClipboardResult<DynamicImage> {
-    let expected_size = (width as usize) * (height as usize) * 4;
+/// Whether a DIB stores palette indices, uncompressed or run-length encoded
+fn is_indexed(bit_count: u16, compression: u32) -> bool {
+    matches!(
+        (bit_count, compression),
+        (1 | 4 | 8, BI_RGB) | (8, BI_RLE8) | (4, BI_RLE4)
+    )
+}
+
+/// Convert a palette DIB whose color table starts at `table_offset`.
+fn convert_indexed_dib(
+    data: &[u8],
+    table_offset: usize,
+    bit_count: u16,
+    compression: u32,
+    width: u32,
+    height: u32,
+    top_down: bool,
+) -> ClipboardResult<DynamicImage> {
+    let palette = read_color_table(data, table_offset, bit_count)?;
+    let pixel_data = &data[table_offset + palette.len() * 4..];
+
+    if compression == BI_RGB {
+        convert_palette_dib(pixel_data, &palette, bit_count, width, height, top_down)
+    } else {
+        // RLE bitmaps are always bottom-up
+        let indices = decode_rle(pixel_data, bit_count, width, height)?;
+        convert_palette_dib(&indices, &palette, 8, width, height, false)
+    }
+}
+
+/// Expand BI_RLE8 or BI_RLE4 data to an uncompressed bottom-up 8-bit DIB.
+///
+/// Encoded runs repeat one byte (RLE8) or alternate the two nibbles of a
+/// byte (RLE4); absolute runs copy literal pixels padded to a 16-bit
+/// boundary. Pixels skipped by delta escapes or never written keep index 0,
+/// and pixels past the end of a row are dropped.
+///
+/// The output size comes from the header alone; callers must have checked
+/// it with `check_decoded_size` against the caller's limits.
+fn decode_rle(data: &[u8], bit_count: u16, width: u32, height: u32) -> ClipboardResult<Vec<u8>> {
+    let truncated = || ClipboardError::ImageDecode("RLE data truncated".to_string());
+    let row_size = (width as usize).div_ceil(4) * 4;
+    let size = row_size
+        .checked_mul(height as usize)
+        .ok_or_else(|| ClipboardError::ImageDecode("RLE bitmap too large".to_string()))?;
+    let mut out = vec![0u8; size];
+
+    let (mut x, mut y) = (0usize, 0usize);
+    let mut i = 0;
+    let mut put = |x: &mut usize, y: usize, index: u8| {
+        if *x < width as usize && y < height as usize {
+            out[y * row_size + *x] = index;
+        }
+        *x += 1;
+    };
+
+    while y < height as usize {
+        let (&count, &value) = data.get(i).zip(data.get(i + 1)).ok_or_else(truncated)?;
+        i += 2;
+
+        match (count, value) {
+            // Encoded run
+            (1.., _) => {
+                for n in 0..count {
+                    let index = match bit_count {
+                        4 if n % 2 == 0 => value >> 4,
+                        4 => value & 0x0F,
+                        _ => value,
+                    };
+                    put(&mut x, y, index);
+                }
+            }
+            // End of line
+            (0, 0) => {
+                x = 0;
+                y += 1;
+            }
+            // End of bitmap
+            (0, 1) => break,
+            // Delta: move right and up
+            (0, 2) => {
+                let (&dx, &dy) = data.get(i).zip(data.get(i + 1)).ok_or_else(truncated)?;
+                i += 2;
+                x += usize::from(dx);
+                y += usize::from(dy);
+            }
+            // Absolute run of `value` pixels
+            (0, _) => {
+                let pixels = usize::from(value);
+                let bytes = if bit_count == 4 { pixels.div_ceil(2) } else { pixels };
+                let literal = data.get(i..i + bytes).ok_or_else(truncated)?;
+                i += bytes + bytes % 2;
+
+                for n in 0..pixels {
+                    let index = match bit_count {
+                        4 if n % 2 == 0 => literal[n / 2] >> 4,
+                        4 => literal[n / 2] & 0x0F,
+                        _ => literal[n],
+                    };
+                    put(&mut x, y, index);
+                }
+            }
+        }
+    }
+
+    Ok(out)
+}
+
+/// Read the RGBQUAD color table following the header of a palette DIB.
+///
+/// A `biClrUsed` of zero means the full table for the bit depth.
+fn read_color_table(data: &[u8], offset: usize, bit_count: u16) -> ClipboardResult<Vec<[u8; 3]>> {
+    let max_entries = 1usize << bit_count;
+    let entries = match read_u32(data, 32) as usize {
+        0 => max_entries,
+        used => used.min(max_entries),
+    };
+
+    let table = data
+        .get(offset..offset + entries * 4)
+        .ok_or_else(|| ClipboardError::ImageDecode("DIB color table truncated".to_string()))?;
+    Ok(table.chunks_exact(4).map(|quad| [quad[2], quad[1], quad[0]]).collect())
+}
+
+/// Convert a 1-, 4- or 8-bit palette DIB to RGB image.
+///
+/// Pixels are packed most significant bits first and rows are padded to
+/// 4-byte boundaries. Indices past the end of the color table are black.
+fn convert_palette_dib(
+    pixel_data: &[u8],
+    palette: &[[u8; 3]],
+    bit_count: u16,
+    width: u32,
+    height: u32,
+    top_down: bool,
+) -> ClipboardResult<DynamicImage> {
+    let bits = usize::from(bit_count);
+    let (row_size, expected_size) = dib_data_size(width, height, bit_count)?;
+
     if pixel_data.len() < expected_size {
         return Err(ClipboardError::ImageDecode(format!(
             "Insufficient pixel data: {} < {}",
@@ -347,23 +1310,44 @@ fn convert_32bit_dib(pixel_data: &[u8], width: u32, height: u32, top_down: bool)
         )));
     }
 
-    let mut rgba_data = Vec::with_capacity(expected_size);
+    let pixels_per_byte = 8 / bits;
+    let index_mask = u8::MAX >> (8 - bits);
+    let mut rgb_data = Vec::with_capacity((width as usize) * (height as usize) * 3);
 
     for y in 0..height {
         let row_y = if top_down { y } else { height - 1 - y };
-        let row_offset = (row_y as usize) * (width as usize) * 4;
-
-        for x in 0..width {
-            let pixel_offset = row_offset + (x as usize) * 4;
-            if pixel_offset + 3 < pixel_data.len() {
-                rgba_data.push(pixel_data[pixel_offset + 2]); // Red
-                rgba_data.push(pixel_data[pixel_offset + 1]); // Green
-                rgba_data.push(pixel_data[pixel_offset]); // Blue
-                rgba_data.push(pixel_data[pixel_offset + 3]); // Alpha
-            }
+        let row = &pixel_data[(row_y as usize) * row_size..][..row_size];
+
+        for x in 0..width as usize {
+            let shift = 8 - bits * (x % pixels_per_byte + 1);
+            let index = (row[x / pixels_per_byte] >> shift) & index_mask;
+            rgb_data.extend_from_slice(&palette.get(usize::from(index)).copied().unwrap_or([0, 0, 0]));
         }
     }
 
+    image::RgbImage::from_raw(width, height, rgb_data)
+        .map(DynamicImage::ImageRgb8)
+        .ok_or_else(|| ClipboardError::ImageDecode("Failed to create image from DIB".to_string()))
+}
+
+/// Convert 32-bit BGRA DIB to RGBA image.
+fn convert_32bit_dib(pixel_data: &[u8], width: u32, height: u32, top_down: bool) -> ClipboardResult<DynamicImage> {
+    let (row_len, expected_size) = dib_data_size(width, height, 32)?;
+    if pixel_data.len() < expected_size {
+        return Err(ClipboardError::ImageDecode(format!(
+            "Insufficient pixel data: {} < {}",
+            pixel_data.len(),
+            expected_size
+        )));
+    }
+
+    let mut rgba_data = vec![0; expected_size];
+    fill_rows(&mut rgba_data, row_len, |y, row| {
+        let row_y = if top_down { y } else { height as usize - 1 - y };
+        row.copy_from_slice(&pixel_data[row_y * row_len..][..row_len]);
+        swap_red_blue(row);
+    });
+
     image::RgbaImage::from_raw(width, height, rgba_data)
         .map(DynamicImage::ImageRgba8)
         .ok_or_else(|| ClipboardError::ImageDecode("Failed to create image from DIB".to_string()))
@@ -372,8 +1356,7 @@ fn convert_32bit_dib(pixel_data: &[u8], width: u32, height: u32, top_down: bool)
 /// Convert 24-bit BGR DIB to RGB image.
 fn convert_24bit_dib(pixel_data: &[u8], width: u32, height: u32, top_down: bool) -> ClipboardResult<DynamicImage> {
     // 24-bit DIB rows are aligned to 4-byte boundaries
-    let row_size = (width * 3).div_ceil(4) * 4;
-    let expected_size = (row_size as usize) * (height as usize);
+    let (row_size, expected_size) = dib_data_size(width, height, 24)?;
 
     if pixel_data.len() < expected_size {
         return Err(ClipboardError::ImageDecode(format!(
@@ -383,21 +1366,15 @@ fn convert_24bit_dib(pixel_data: &[u8], width: u32, height: u32, top_down: bool)
         )));
     }
 
-    let mut rgb_data = Vec::with_capacity((width as usize) * (height as usize) * 3);
-
-    for y in 0..height {
-        let row_y = if top_down { y } else { height - 1 - y };
-        let row_offset = (row_y as usize) * (row_size as usize);
-
-        for x in 0..width {
-            let pixel_offset = row_offset + (x as usize) * 3;
-            if pixel_offset + 2 < pixel_data.len() {
-                rgb_data.push(pixel_data[pixel_offset + 2]); // Red
-                rgb_data.push(pixel_data[pixel_offset + 1]); // Green
-                rgb_data.push(pixel_data[pixel_offset]); // Blue
-            }
+    let row_len = (width as usize) * 3;
+    let mut rgb_data = vec![0; row_len * (height as usize)];
+    fill_rows(&mut rgb_data, row_len, |y, row| {
+        let row_y = if top_down { y } else { height as usize - 1 - y };
+        let src = &pixel_data[row_y * row_size..][..row_len];
+        for (dst, bgr) in row.chunks_exact_mut(3).zip(src.chunks_exact(3)) {
+            dst.copy_from_slice(&[bgr[2], bgr[1], bgr[0]]);
         }
-    }
+    });
 
     image::RgbImage::from_raw(width, height, rgb_data)
         .map(DynamicImage::ImageRgb8)
@@ -411,9 +1388,36 @@ fn convert_24bit_dib(pixel_data: &[u8], width: u32, height: u32, top_down: bool)
 /// BITMAPV5HEADER size in bytes.
 const DIBV5_HEADER_SIZE: usize = 124;
 
+/// BITMAPV4HEADER size in bytes (same layout as V5 up to the gamma fields).
+const DIBV4_HEADER_SIZE: usize = 108;
+
+/// BI_RGB compression: uncompressed pixels, palette indices below 16 bits.
+const BI_RGB: u32 = 0;
+
+/// BI_RLE8 compression: run-length encoded 8-bit palette indices.
+const BI_RLE8: u32 = 1;
+
+/// BI_RLE4 compression: run-length encoded 4-bit palette indices.
+const BI_RLE4: u32 = 2;
+
+/// BI_BITFIELDS compression: pixels are decoded with explicit color masks.
+const BI_BITFIELDS: u32 = 3;
+
+/// BI_ALPHABITFIELDS compression: BI_BITFIELDS with an alpha mask (Windows CE).
+const BI_ALPHABITFIELDS: u32 = 6;
+
+/// Masks of 16-bit BI_RGB pixels (5-5-5, top bit unused).
+const RGB555_MASKS: [u32; 4] = [0x7C00, 0x03E0, 0x001F, 0];
+
+/// Masks of 32-bit BI_RGB pixels in DIBV5 (BGRA).
+const BGRA_MASKS: [u32; 4] = [0x00FF_0000, 0x0000_FF00, 0x0000_00FF, 0xFF00_0000];
+
 /// LCS_sRGB color space type ("sRGB" in little-endian ASCII).
 const LCS_SRGB: u32 = 0x7352_4742;
 
+/// PROFILE_EMBEDDED color space type ("MBED"): ICC profile follows the bitmap.
+const LCS_PROFILE_EMBEDDED: u32 = 0x4D42_4544;
+
 /// LCS_GM_IMAGES rendering intent (perceptual).
 const LCS_GM_IMAGES: u32 = 2;
 
@@ -423,13 +1427,27 @@ const LCS_GM_IMAGES: u32 = 2;
 /// - BI_BITFIELDS compression (masks for BGRA)
 /// - sRGB color space
 /// - Full alpha channel support
-fn create_dibv5_from_image(image: &DynamicImage) -> ClipboardResult<Vec<u8>> {
-    let rgba = image.to_rgba8();
+fn create_dibv5_from_image(image: &DynamicImage, limits: &ImageLimits) -> ClipboardResult<Vec<u8>> {
+    create_dibv5_with_profile(image, None, limits)
+}
+
+/// Create DIBV5 data, embedding an ICC profile after the pixel data if given.
+fn create_dibv5_with_profile(
+    image: &DynamicImage,
+    icc_profile: Option<&[u8]>,
+    limits: &ImageLimits,
+) -> ClipboardResult<Vec<u8>> {
+    let rgba = match limits.fit(image.width(), image.height()) {
+        Some(_) => limits.apply(image.clone())?.to_rgba8(),
+        None => image.to_rgba8(),
+    };
     let (width, height) = (rgba.width(), rgba.height());
 
     // Pre-calculate sizes
     let image_size = width.saturating_mul(height).saturating_mul(4);
-    let total_size = DIBV5_HEADER_SIZE + (image_size as usize);
+    let profile = icc_profile.filter(|p| !p.is_empty());
+    let profile_size = profile.map_or(0, <[u8]>::len);
+    let total_size = DIBV5_HEADER_SIZE + (image_size as usize) + profile_size;
 
     let mut dib = BytesMut::with_capacity(total_size);
 
@@ -479,8 +1497,13 @@ fn create_dibv5_from_image(image: &DynamicImage) -> ClipboardResult<Vec<u8>> {
     // Offsets 52-55: Alpha channel mask (byte 3 in BGRA)
     dib.put_u32_le(0xFF00_0000); // bV5AlphaMask
 
-    // Offsets 56-59: Color space type (sRGB)
-    dib.put_u32_le(LCS_SRGB); // bV5CSType
+    // Offsets 56-59: Color space type (embedded profile or sRGB)
+    let cs_type = if profile.is_some() {
+        LCS_PROFILE_EMBEDDED
+    } else {
+        LCS_SRGB
+    };
+    dib.put_u32_le(cs_type); // bV5CSType
 
     // Offsets 60-95: CIEXYZTRIPLE endpoints (36 bytes, zeros for sRGB)
     for _ in 0..9 {
@@ -499,11 +1522,16 @@ fn create_dibv5_from_image(image: &DynamicImage) -> ClipboardResult<Vec<u8>> {
     // Offsets 108-111: Rendering intent
     dib.put_u32_le(LCS_GM_IMAGES); // bV5Intent
 
-    // Offsets 112-115: ICC profile data offset (0 = none)
-    dib.put_u32_le(0); // bV5ProfileData
+    // Offsets 112-115: ICC profile data offset from header start (0 = none)
+    let profile_offset = if profile.is_some() {
+        DIBV5_HEADER_SIZE as u32 + image_size
+    } else {
+        0
+    };
+    dib.put_u32_le(profile_offset); // bV5ProfileData
 
     // Offsets 116-119: ICC profile size (0 = none)
-    dib.put_u32_le(0); // bV5ProfileSize
+    dib.put_u32_le(profile_size as u32); // bV5ProfileSize
 
     // Offsets 120-123: Reserved
     dib.put_u32_le(0); // bV5Reserved
@@ -511,21 +1539,28 @@ fn create_dibv5_from_image(image: &DynamicImage) -> ClipboardResult<Vec<u8>> {
     debug_assert_eq!(dib.len(), DIBV5_HEADER_SIZE);
 
     // Pixel data: convert RGBA to BGRA (Windows byte order)
-    for pixel in rgba.pixels() {
-        dib.put_u8(pixel[2]); // Blue
-        dib.put_u8(pixel[1]); // Green
-        dib.put_u8(pixel[0]); // Red
-        dib.put_u8(pixel[3]); // Alpha
+    let mut dib = dib.to_vec();
+    append_bgra(&mut dib, &rgba);
+
+    if let Some(profile) = profile {
+        dib.extend_from_slice(profile);
     }
 
-    Ok(dib.to_vec())
+    Ok(dib)
 }
 
 /// Parse DIBV5 data into a DynamicImage.
 ///
-/// Handles both standard 124-byte DIBV5 headers and the "short DIBV5" bug
-/// where some applications use a 40-byte header with format ID 17.
+/// Handles standard 124-byte DIBV5 headers, 108-byte V4 headers, and the
+/// "short DIBV5" bug where some applications use a 40-byte header with
+/// format ID 17.
 fn parse_dibv5_to_image(dibv5_data: &[u8]) -> ClipboardResult<DynamicImage> {
+    parse_dibv5_to_image_with_limits(dibv5_data, &ImageLimits::default())
+}
+
+/// Parse DIBV5 data into a DynamicImage, checking its size against `limits`
+/// before decoding.
+fn parse_dibv5_to_image_with_limits(dibv5_data: &[u8], limits: &ImageLimits) -> ClipboardResult<DynamicImage> {
     if dibv5_data.len() < 4 {
         return Err(ClipboardError::ImageDecode("DIBV5 too small".to_string()));
     }
@@ -537,22 +1572,23 @@ fn parse_dibv5_to_image(dibv5_data: &[u8]) -> ClipboardResult<DynamicImage> {
         40 => {
             // "Short DIBV5" - some apps use CF_DIBV5 format ID but DIB header
             // Fall back to regular DIB parser
-            parse_dib_to_image(dibv5_data)
+            parse_dib_to_image_with_limits(dibv5_data, limits)
         }
-        124 => {
-            // Standard DIBV5 with full 124-byte header
-            parse_full_dibv5(dibv5_data)
+        108 | 124 => {
+            // Standard DIBV5 with full 124-byte header, or its V4 predecessor
+            parse_full_dibv5(dibv5_data, limits)
         }
         _ => Err(ClipboardError::ImageDecode(format!(
-            "Invalid DIBV5 header size: {} (expected 40 or 124)",
+            "Invalid DIBV5 header size: {} (expected 40, 108 or 124)",
             header_size
         ))),
     }
 }
 
-/// Parse standard 124-byte DIBV5 data.
-fn parse_full_dibv5(data: &[u8]) -> ClipboardResult<DynamicImage> {
-    if data.len() < DIBV5_HEADER_SIZE {
+/// Parse DIB data with a V4 (108-byte) or V5 (124-byte) header.
+fn parse_full_dibv5(data: &[u8], limits: &ImageLimits) -> ClipboardResult<DynamicImage> {
+    let header_size = read_u32(data, 0) as usize;
+    if header_size < DIBV4_HEADER_SIZE || data.len() < header_size {
         return Err(ClipboardError::ImageDecode(
             "DIBV5 data too small for header".to_string(),
         ));
@@ -563,103 +1599,97 @@ fn parse_full_dibv5(data: &[u8]) -> ClipboardResult<DynamicImage> {
     let height_raw = i32::from_le_bytes([data[8], data[9], data[10], data[11]]);
     let height = height_raw.unsigned_abs();
     let top_down = height_raw < 0;
+    check_decoded_size(width, height, limits.max_decoded_bytes)?;
 
     // Parse bit depth and compression
     let bit_count = u16::from_le_bytes([data[14], data[15]]);
     let compression = u32::from_le_bytes([data[16], data[17], data[18], data[19]]);
 
-    // Parse color masks for BI_BITFIELDS (compression == 3)
-    let (red_mask, green_mask, blue_mask, alpha_mask) = if compression == 3 {
-        (
-            u32::from_le_bytes([data[40], data[41], data[42], data[43]]),
-            u32::from_le_bytes([data[44], data[45], data[46], data[47]]),
-            u32::from_le_bytes([data[48], data[49], data[50], data[51]]),
-            u32::from_le_bytes([data[52], data[53], data[54], data[55]]),
-        )
-    } else {
-        // Default BGRA masks for BI_RGB
-        (0x00FF_0000, 0x0000_FF00, 0x0000_00FF, 0xFF00_0000)
+    // Color masks for BI_BITFIELDS, otherwise the defaults for the bit depth
+    let masks = match (compression, bit_count) {
+        (BI_BITFIELDS | BI_ALPHABITFIELDS, _) => [
+            read_u32(data, 40),
+            read_u32(data, 44),
+            read_u32(data, 48),
+            read_u32(data, 52),
+        ],
+        (_, 16) => RGB555_MASKS,
+        _ => BGRA_MASKS,
     };
 
-    // Pixel data starts after 124-byte header
-    let pixel_data = &data[DIBV5_HEADER_SIZE..];
+    // Pixel data starts right after the header; an embedded ICC profile follows it
+    let pixel_data = &data[header_size..];
 
     match bit_count {
-        32 => convert_32bit_dibv5(
-            pixel_data, width, height, top_down, red_mask, green_mask, blue_mask, alpha_mask,
-        ),
+        16 | 32 => convert_bitfields_dib(pixel_data, bit_count, width, height, top_down, masks),
         24 => convert_24bit_dib(pixel_data, width, height, top_down),
-        _ => Err(ClipboardError::ImageDecode(format!(
-            "Unsupported DIBV5 bit depth: {}",
-            bit_count
-        ))),
+        1 | 4 | 8 if is_indexed(bit_count, compression) => {
+            convert_indexed_dib(data, header_size, bit_count, compression, width, height, top_down)
+        }
+        _ => Err(ClipboardError::UnsupportedBitDepth(bit_count)),
     }
 }
 
-/// Convert 32-bit DIBV5 pixel data using color masks.
-#[allow(clippy::too_many_arguments)]
-fn convert_32bit_dibv5(
+/// Convert 16- or 32-bit pixel data using color masks.
+///
+/// Masks may have any width and position, e.g. 5-5-5 and 5-6-5 for 16-bit
+/// or 10-10-10-2 for 32-bit; each channel is scaled to 8 bits. A zero alpha
+/// mask means the image is opaque.
+fn convert_bitfields_dib(
     pixel_data: &[u8],
+    bit_count: u16,
     width: u32,
     height: u32,
     top_down: bool,
-    red_mask: u32,
-    green_mask: u32,
-    blue_mask: u32,
-    alpha_mask: u32,
+    masks: [u32; 4],
 ) -> ClipboardResult<DynamicImage> {
-    let expected_size = (width as usize) * (height as usize) * 4;
+    let bytes_per_pixel = usize::from(bit_count / 8);
+    let (row_size, expected_size) = dib_data_size(width, height, bit_count)?;
     if pixel_data.len() < expected_size {
         return Err(ClipboardError::ImageDecode(format!(
-            "Insufficient DIBV5 pixel data: {} < {}",
+            "Insufficient pixel data: {} < {}",
             pixel_data.len(),
             expected_size
         )));
     }
 
-    // Calculate shifts for each color channel
-    let red_shift = red_mask.trailing_zeros();
-    let green_shift = green_mask.trailing_zeros();
-    let blue_shift = blue_mask.trailing_zeros();
-    let alpha_shift = alpha_mask.trailing_zeros();
+    // (mask, shift, largest channel value) for red, green, blue and alpha
+    let channels = masks.map(|mask| {
+        let shift = mask.trailing_zeros() % 32;
+        (mask, shift, u64::from(mask >> shift))
+    });
+    let scale = |pixel: u32, (mask, shift, max): (u32, u32, u64)| {
+        let value = u64::from((pixel & mask) >> shift);
+        ((value * 255 + max / 2) / max) as u8
+    };
 
-    let mut rgba_data = Vec::with_capacity(expected_size);
+    let mut rgba_data = Vec::with_capacity((width as usize) * (height as usize) * 4);
 
     for y in 0..height {
         let row_y = if top_down { y } else { height - 1 - y };
-        let row_offset = (row_y as usize) * (width as usize) * 4;
-
-        for x in 0..width {
-            let pixel_offset = row_offset + (x as usize) * 4;
-            if pixel_offset + 3 < pixel_data.len() {
-                let pixel = u32::from_le_bytes([
-                    pixel_data[pixel_offset],
-                    pixel_data[pixel_offset + 1],
-                    pixel_data[pixel_offset + 2],
-                    pixel_data[pixel_offset + 3],
-                ]);
-
-                // Extract channels using masks and shifts
-                let red = ((pixel & red_mask) >> red_shift) as u8;
-                let green = ((pixel & green_mask) >> green_shift) as u8;
-                let blue = ((pixel & blue_mask) >> blue_shift) as u8;
-                let alpha = if alpha_mask != 0 {
-                    ((pixel & alpha_mask) >> alpha_shift) as u8
-                } else {
-                    255 // No alpha channel, assume opaque
-                };
-
-                rgba_data.push(red);
-                rgba_data.push(green);
-                rgba_data.push(blue);
-                rgba_data.push(alpha);
+        let row = &pixel_data[(row_y as usize) * row_size..][..row_size];
+
+        for pixel in row.chunks_exact(bytes_per_pixel).take(width as usize) {
+            let pixel = match *pixel {
+                [lo, hi] => u32::from(u16::from_le_bytes([lo, hi])),
+                [b0, b1, b2, b3] => u32::from_le_bytes([b0, b1, b2, b3]),
+                _ => 0,
+            };
+
+            for (i, channel) in channels.iter().enumerate() {
+                rgba_data.push(match channel.2 {
+                    // No alpha channel, assume opaque
+                    0 if i == 3 => 255,
+                    0 => 0,
+                    _ => scale(pixel, *channel),
+                });
             }
         }
     }
 
     image::RgbaImage::from_raw(width, height, rgba_data)
         .map(DynamicImage::ImageRgba8)
-        .ok_or_else(|| ClipboardError::ImageDecode("Failed to create image from DIBV5".to_string()))
+        .ok_or_else(|| ClipboardError::ImageDecode("Failed to create image from DIB".to_string()))
 }
 
 #[cfg(test)]
@@ -672,7 +1702,7 @@ mod tests {
         let image = DynamicImage::ImageRgba8(image::RgbaImage::from_pixel(10, 10, image::Rgba([255, 0, 0, 255])));
 
         // Convert to DIB
-        let dib = create_dib_from_image(&image).unwrap();
+        let dib = create_dib_from_image(&image, &ImageLimits::default()).unwrap();
 
         // Verify DIB header
         assert!(dib.len() >= 40);
@@ -688,7 +1718,7 @@ mod tests {
     fn test_dib_dimensions() {
         let image = DynamicImage::ImageRgba8(image::RgbaImage::from_pixel(100, 50, image::Rgba([0, 0, 0, 255])));
 
-        let dib = create_dib_from_image(&image).unwrap();
+        let dib = create_dib_from_image(&image, &ImageLimits::default()).unwrap();
         let (width, height) = dib_dimensions(&dib).unwrap();
 
         assert_eq!(width, 100);
@@ -719,7 +1749,7 @@ mod tests {
     fn test_bmp_roundtrip() {
         let image = DynamicImage::ImageRgba8(image::RgbaImage::from_pixel(8, 8, image::Rgba([50, 100, 150, 255])));
 
-        let dib = create_dib_from_image(&image).unwrap();
+        let dib = create_dib_from_image(&image, &ImageLimits::default()).unwrap();
 
         // DIB → BMP → DIB
         let bmp = dib_to_bmp(&dib).unwrap();
@@ -765,7 +1795,7 @@ mod tests {
         let image = DynamicImage::ImageRgba8(image::RgbaImage::from_pixel(10, 10, image::Rgba([255, 0, 0, 128])));
 
         // Convert to DIBV5
-        let dibv5 = create_dibv5_from_image(&image).unwrap();
+        let dibv5 = create_dibv5_from_image(&image, &ImageLimits::default()).unwrap();
 
         // Verify DIBV5 header
         assert!(dibv5.len() >= 124);
@@ -786,7 +1816,7 @@ mod tests {
     fn test_dibv5_header_structure() {
         let image = DynamicImage::ImageRgba8(image::RgbaImage::from_pixel(4, 4, image::Rgba([100, 150, 200, 128])));
 
-        let dibv5 = create_dibv5_from_image(&image).unwrap();
+        let dibv5 = create_dibv5_from_image(&image, &ImageLimits::default()).unwrap();
 
         // Verify header fields
         assert_eq!(u32::from_le_bytes([dibv5[0], dibv5[1], dibv5[2], dibv5[3]]), 124); // Size
@@ -845,6 +1875,39 @@ mod tests {
         assert_eq!(pixel[3], 100);
     }
 
+    #[test]
+    fn test_tiff_roundtrip() {
+        let img = image::RgbaImage::from_pixel(4, 3, image::Rgba([200, 100, 50, 255]));
+        let mut png_data = Vec::new();
+        DynamicImage::ImageRgba8(img)
+            .write_to(&mut std::io::Cursor::new(&mut png_data), ImageFormat::Png)
+            .unwrap();
+
+        let tiff_data = png_to_tiff(&png_data).unwrap();
+        assert_eq!(validate_tiff(&tiff_data).unwrap(), tiff_data);
+        assert_eq!(dib_dimensions(&tiff_to_dib(&tiff_data).unwrap()).unwrap(), (4, 3));
+
+        let decoded = image::load_from_memory_with_format(&tiff_to_png(&tiff_data).unwrap(), ImageFormat::Png).unwrap();
+        assert_eq!(decoded.to_rgba8().get_pixel(3, 2), &image::Rgba([200, 100, 50, 255]));
+
+        assert!(validate_tiff(b"GIF89a").is_err());
+    }
+
+    #[cfg(feature = "webp")]
+    #[test]
+    fn test_webp_to_dibv5_and_png() {
+        let img = image::RgbaImage::from_pixel(3, 2, image::Rgba([10, 20, 30, 128]));
+        let mut webp_data = Vec::new();
+        DynamicImage::ImageRgba8(img)
+            .write_to(&mut std::io::Cursor::new(&mut webp_data), ImageFormat::WebP)
+            .unwrap();
+
+        let decoded = parse_dibv5_to_image(&webp_to_dibv5(&webp_data).unwrap()).unwrap();
+        assert_eq!(decoded.to_rgba8().get_pixel(2, 1), &image::Rgba([10, 20, 30, 128]));
+        assert_eq!(dib_dimensions(&webp_to_dib(&webp_data).unwrap()).unwrap(), (3, 2));
+        assert!(webp_to_png(&webp_data).unwrap().starts_with(b"\x89PNG"));
+    }
+
     #[test]
     fn test_has_transparency() {
         // Image with transparency
@@ -875,7 +1938,7 @@ mod tests {
         let image = DynamicImage::ImageRgba8(image::RgbaImage::from_pixel(3, 3, image::Rgba([255, 128, 64, 255])));
 
         // Create DIB (40-byte header)
-        let dib = create_dib_from_image(&image).unwrap();
+        let dib = create_dib_from_image(&image, &ImageLimits::default()).unwrap();
         assert_eq!(u32::from_le_bytes([dib[0], dib[1], dib[2], dib[3]]), 40);
 
         // Parse as DIBV5 should fall back to DIB parser
@@ -907,7 +1970,7 @@ mod tests {
         let image = DynamicImage::ImageRgba8(img);
 
         // Round-trip through DIBV5
-        let dibv5 = create_dibv5_from_image(&image).unwrap();
+        let dibv5 = create_dibv5_from_image(&image, &ImageLimits::default()).unwrap();
         let parsed = parse_dibv5_to_image(&dibv5).unwrap();
         let rgba = parsed.to_rgba8();
 
@@ -917,4 +1980,536 @@ mod tests {
         assert_eq!(rgba.get_pixel(0, 1), &image::Rgba([0, 0, 255, 64]));
         assert_eq!(rgba.get_pixel(1, 1), &image::Rgba([128, 128, 128, 0]));
     }
+
+    #[test]
+    fn test_dibv5_icc_profile_roundtrip() {
+        let mut img = image::RgbaImage::new(2, 1);
+        img.put_pixel(0, 0, image::Rgba([10, 20, 30, 40]));
+        img.put_pixel(1, 0, image::Rgba([200, 100, 50, 255]));
+        let image = DynamicImage::ImageRgba8(img);
+        let profile = vec![0xAB; 64];
+
+        let dibv5 = create_dibv5_with_profile(&image, Some(&profile), &ImageLimits::default()).unwrap();
+        assert_eq!(read_u32(&dibv5, 56), LCS_PROFILE_EMBEDDED);
+        assert_eq!(read_u32(&dibv5, 112), (DIBV5_HEADER_SIZE + 8) as u32);
+        assert_eq!(dibv5_icc_profile(&dibv5), Some(profile.clone()));
+
+        // DIBV5 → PNG keeps the profile, PNG → DIBV5 restores it
+        let png = dibv5_to_png(&dibv5).unwrap();
+        let back = png_to_dibv5(&png).unwrap();
+        assert_eq!(dibv5_icc_profile(&back), Some(profile));
+        assert_eq!(parse_dibv5_to_image(&back).unwrap().to_rgba8(), image.to_rgba8());
+    }
+
+    #[test]
+    fn test_dibv5_without_profile_is_srgb() {
+        let image = DynamicImage::ImageRgba8(image::RgbaImage::new(1, 1));
+        let dibv5 = create_dibv5_from_image(&image, &ImageLimits::default()).unwrap();
+
+        assert_eq!(read_u32(&dibv5, 56), LCS_SRGB);
+        assert!(dibv5_icc_profile(&dibv5).is_none());
+
+        // Out-of-bounds profile offsets are ignored
+        let mut bogus = create_dibv5_with_profile(&image, Some(&[1, 2, 3]), &ImageLimits::default()).unwrap();
+        bogus[112..116].copy_from_slice(&u32::MAX.to_le_bytes());
+        assert!(dibv5_icc_profile(&bogus).is_none());
+    }
+
+    #[test]
+    fn test_v4_header_and_bitfields_dib() {
+        let mut img = image::RgbaImage::new(1, 1);
+        img.put_pixel(0, 0, image::Rgba([1, 2, 3, 128]));
+        let dibv5 = create_dibv5_from_image(&DynamicImage::ImageRgba8(img), &ImageLimits::default()).unwrap();
+
+        // Truncate to a BITMAPV4HEADER, keeping the masks
+        let mut v4 = dibv5[..DIBV4_HEADER_SIZE].to_vec();
+        v4[0..4].copy_from_slice(&(DIBV4_HEADER_SIZE as u32).to_le_bytes());
+        v4.extend_from_slice(&dibv5[DIBV5_HEADER_SIZE..]);
+        let parsed = parse_dibv5_to_image(&v4).unwrap().to_rgba8();
+        assert_eq!(parsed.get_pixel(0, 0), &image::Rgba([1, 2, 3, 128]));
+
+        // BITMAPINFOHEADER followed by BI_BITFIELDS masks
+        let mut dib = dibv5[..40].to_vec();
+        dib[0..4].copy_from_slice(&40u32.to_le_bytes());
+        dib.extend_from_slice(&dibv5[40..52]);
+        dib.extend_from_slice(&dibv5[DIBV5_HEADER_SIZE..]);
+        let parsed = parse_dib_to_image(&dib).unwrap().to_rgba8();
+        assert_eq!(parsed.get_pixel(0, 0), &image::Rgba([1, 2, 3, 255]));
+    }
+
+    #[test]
+    fn test_image_limits() {
+        let limits = ImageLimits {
+            max_width: 100,
+            max_height: 100,
+            max_pixels: 2_000,
+            resize_strategy: ResizeStrategy::Lanczos,
+            max_decoded_bytes: DEFAULT_MAX_DECODED_BYTES,
+        };
+        assert_eq!(limits.fit(40, 40), None);
+        assert_eq!(limits.fit(400, 50), Some((100, 12)));
+        assert_eq!(limits.fit(80, 80), Some((44, 44)));
+        assert_eq!(ImageLimits::UNLIMITED.fit(u32::MAX, u32::MAX), None);
+
+        let image = DynamicImage::ImageRgba8(image::RgbaImage::from_pixel(400, 50, image::Rgba([9, 8, 7, 255])));
+        let dib = create_dib_from_image(&image, &limits).unwrap();
+        assert_eq!(dib_dimensions(&dib).unwrap(), (100, 12));
+
+        let png = dib_to_png_with_limits(
+            &create_dib_from_image(&image, &ImageLimits::UNLIMITED).unwrap(),
+            &limits,
+        );
+        let decoded = image::load_from_memory(&png.unwrap()).unwrap();
+        assert_eq!((decoded.width(), decoded.height()), (100, 12));
+        assert_eq!(decoded.to_rgba8().get_pixel(50, 6).0, [9, 8, 7, 255]);
+
+        let reject = ImageLimits {
+            resize_strategy: ResizeStrategy::Reject,
+            ..limits
+        };
+        assert!(matches!(
+            create_dib_from_image(&image, &reject),
+            Err(ClipboardError::ImageTooLarge { width: 400, height: 50 })
+        ));
+    }
+
+    #[test]
+    fn test_encode_options() {
+        let image = DynamicImage::ImageRgba8(image::RgbaImage::from_fn(64, 64, |x, y| {
+            image::Rgba([(x * 4) as u8, (y * 4) as u8, ((x * y) % 256) as u8, 255])
+        }));
+        let dib = create_dib_from_image(&image, &ImageLimits::UNLIMITED).unwrap();
+
+        // Sampling factors of the first (luma) component in the SOF0 segment
+        let luma_sampling = |jpeg: &[u8]| {
+            let sof = jpeg.windows(2).position(|w| w == [0xFF, 0xC0]).unwrap();
+            jpeg[sof + 11]
+        };
+
+        let default = dib_to_jpeg(&dib).unwrap();
+        assert_eq!(luma_sampling(&default), 0x11);
+
+        let small = ImageEncodeOptions {
+            jpeg_quality: 20,
+            chroma_subsampling: ChromaSubsampling::Yuv420,
+            png_compression: PngCompression::Best,
+            dib_alpha: DibAlpha::Straight,
+        };
+        let jpeg = dib_to_jpeg_with_options(&dib, &small).unwrap();
+        assert_eq!(luma_sampling(&jpeg), 0x22);
+        assert!(jpeg.len() < default.len());
+        let decoded = image::load_from_memory_with_format(&jpeg, ImageFormat::Jpeg).unwrap();
+        assert_eq!((decoded.width(), decoded.height()), (64, 64));
+
+        let png = dib_to_png_with_options(&dib, &ImageLimits::default(), &small).unwrap();
+        assert!(png.len() <= dib_to_png(&dib).unwrap().len());
+        let decoded = image::load_from_memory_with_format(&png, ImageFormat::Png).unwrap();
+        assert_eq!(decoded.to_rgba8(), image.to_rgba8());
+    }
+
+    #[test]
+    fn test_dib_alpha() {
+        // Half-transparent pure red, premultiplied: (128, 0, 0, 128); a fully
+        // transparent pixel; an opaque blue pixel
+        let pixels = [
+            image::Rgba([128, 0, 0, 128]),
+            image::Rgba([0, 0, 0, 0]),
+            image::Rgba([0, 0, 255, 255]),
+        ];
+        let mut image = image::RgbaImage::new(3, 1);
+        for (x, p) in pixels.iter().enumerate() {
+            image.put_pixel(x as u32, 0, *p);
+        }
+        let dibv5 = create_dibv5_from_image(&DynamicImage::ImageRgba8(image), &ImageLimits::default()).unwrap();
+
+        let decode = |dib_alpha| {
+            let options = ImageEncodeOptions {
+                dib_alpha,
+                ..ImageEncodeOptions::default()
+            };
+            let png = dibv5_to_png_with_options(&dibv5, &ImageLimits::default(), &options).unwrap();
+            image::load_from_memory(&png).unwrap().to_rgba8()
+        };
+
+        let straight = decode(DibAlpha::Straight);
+        assert_eq!(straight.get_pixel(0, 0), &image::Rgba([128, 0, 0, 128]));
+
+        let premultiplied = decode(DibAlpha::Premultiplied);
+        assert_eq!(premultiplied.get_pixel(0, 0), &image::Rgba([255, 0, 0, 128]));
+        assert_eq!(premultiplied.get_pixel(2, 0), &image::Rgba([0, 0, 255, 255]));
+
+        let composite = decode(DibAlpha::Composite {
+            background: [255, 255, 255],
+        });
+        assert_eq!(composite.get_pixel(0, 0), &image::Rgba([191, 127, 127, 255]));
+        assert_eq!(composite.get_pixel(1, 0), &image::Rgba([255, 255, 255, 255]));
+        assert_eq!(composite.get_pixel(2, 0), &image::Rgba([0, 0, 255, 255]));
+
+        // GDI leaves the alpha byte zero: opaque, not invisible
+        let dib = crate::testing::fixtures::DibBuilder::new(2, 2)
+            .bgra()
+            .fill([0x10, 0x20, 0x30, 0])
+            .build();
+        let png = dib_to_png(&dib).unwrap();
+        let decoded = image::load_from_memory(&png).unwrap().to_rgba8();
+        assert!(decoded.pixels().all(|p| p[3] == 255));
+    }
+
+    /// Icon file with the given frames as (width, height, depth or hotspot, data)
+    fn icon_file(kind: u16, frames: &[(u8, u8, u16, Vec<u8>)]) -> Vec<u8> {
+        let mut ico = vec![0, 0];
+        ico.extend_from_slice(&kind.to_le_bytes());
+        ico.extend_from_slice(&(frames.len() as u16).to_le_bytes());
+        let mut offset = ICONDIR_SIZE + frames.len() * ICONDIRENTRY_SIZE;
+        for (width, height, depth, data) in frames {
+            ico.extend_from_slice(&[*width, *height, 0, 0]);
+            ico.extend_from_slice(&300u16.to_le_bytes()); // planes, or a cursor hotspot
+            ico.extend_from_slice(&depth.to_le_bytes());
+            ico.extend_from_slice(&(data.len() as u32).to_le_bytes());
+            ico.extend_from_slice(&(offset as u32).to_le_bytes());
+            offset += data.len();
+        }
+        for (_, _, _, data) in frames {
+            ico.extend_from_slice(data);
+        }
+        ico
+    }
+
+    fn solid_png(size: u32, color: [u8; 4]) -> Vec<u8> {
+        let image = DynamicImage::ImageRgba8(image::RgbaImage::from_pixel(size, size, image::Rgba(color)));
+        encode_png(&image, &ImageEncodeOptions::default()).unwrap()
+    }
+
+    #[test]
+    fn test_icon_largest_frame() {
+        let frames = [
+            (16, 16, 32, solid_png(16, [255, 0, 0, 255])),
+            (48, 48, 8, solid_png(48, [0, 0, 255, 128])),
+            (32, 32, 32, solid_png(32, [0, 255, 0, 255])),
+        ];
+
+        for kind in [ICO_TYPE_ICON, ICO_TYPE_CURSOR] {
+            let png = ico_to_png(&icon_file(kind, &frames)).unwrap();
+            let image = image::load_from_memory(&png).unwrap().to_rgba8();
+            assert_eq!(image.dimensions(), (48, 48));
+            assert_eq!(image.get_pixel(0, 0).0, [0, 0, 255, 128]);
+        }
+
+        let dib = ico_to_dib(&icon_file(ICO_TYPE_ICON, &frames)).unwrap();
+        assert_eq!(dib_dimensions(&dib).unwrap(), (48, 48));
+
+        assert!(ico_to_png(b"\x89PNG").is_err());
+        assert!(ico_to_png(&icon_file(ICO_TYPE_ICON, &[])).is_err());
+        let mut truncated = icon_file(ICO_TYPE_ICON, &frames);
+        truncated.truncate(truncated.len() - frames[2].3.len() - 10);
+        assert!(ico_to_png(&truncated).is_err());
+    }
+
+    #[test]
+    fn test_icon_bmp_frame() {
+        // 2x2 32-bit frame; the header height covers the XOR pixels and the AND mask
+        let mut frame = dib_header(2, 4, 32, BI_RGB, 0);
+        for bgra in [[1, 2, 3, 255], [4, 5, 6, 255], [7, 8, 9, 255], [10, 11, 12, 255]] {
+            frame.extend_from_slice(&bgra);
+        }
+        frame.extend_from_slice(&[0; 8]);
+
+        let ico = icon_file(ICO_TYPE_ICON, &[(2, 2, 32, frame)]);
+        let dibv5 = ico_to_dibv5(&ico).unwrap();
+        let image = parse_dibv5_to_image(&dibv5).unwrap().to_rgba8();
+        assert_eq!(image.dimensions(), (2, 2));
+        // Bottom-up: the first row in the file is the bottom row
+        assert_eq!(image.get_pixel(0, 1).0, [3, 2, 1, 255]);
+    }
+
+    #[test]
+    fn test_large_image_rows() {
+        // Big enough to take the parallel path with the `parallel` feature
+        let (width, height) = (1024u32, 300u32);
+        let pixel = |x: u32, y: u32| image::Rgba([x as u8, y as u8, (x ^ y) as u8, (x + y) as u8]);
+        let image = DynamicImage::ImageRgba8(image::RgbaImage::from_fn(width, height, pixel));
+
+        let dib = create_dib_from_image(&image, &ImageLimits::UNLIMITED).unwrap();
+        assert_eq!(&dib[68..72], &[7, 0, 7, 7]);
+        let parsed = parse_dib_to_image(&dib).unwrap().to_rgba8();
+        assert_eq!(parsed, image.to_rgba8());
+
+        // Bottom-up 24-bit rows with padding
+        let width = 1001u32;
+        let row_size = (width as usize * 3).div_ceil(4) * 4;
+        let mut dib = dib_header(width as i32, height as i32, 24, BI_RGB, 0);
+        for y in (0..height).rev() {
+            for x in 0..width {
+                let [r, g, b, _] = pixel(x, y).0;
+                dib.extend_from_slice(&[b, g, r]);
+            }
+            dib.resize(dib.len() + row_size - width as usize * 3, 0);
+        }
+        let parsed = parse_dib_to_image(&dib).unwrap().to_rgb8();
+        assert_eq!(parsed.get_pixel(1000, 299).0, [1000u32 as u8, 43, (1000 ^ 299) as u8]);
+        assert_eq!(parsed.get_pixel(3, 0).0, [3, 0, 3]);
+    }
+
+    /// PNG with only a header, claiming the given dimensions
+    fn png_header(width: u32, height: u32) -> Vec<u8> {
+        fn crc32(data: &[u8]) -> u32 {
+            let mut crc = u32::MAX;
+            for &byte in data {
+                crc ^= u32::from(byte);
+                for _ in 0..8 {
+                    crc = (crc >> 1) ^ (0xEDB8_8320 & (crc & 1).wrapping_neg());
+                }
+            }
+            !crc
+        }
+        let chunk = |png: &mut Vec<u8>, kind: &[u8], data: &[u8]| {
+            png.extend_from_slice(&(data.len() as u32).to_be_bytes());
+            let start = png.len();
+            png.extend_from_slice(kind);
+            png.extend_from_slice(data);
+            let crc = crc32(&png[start..]);
+            png.extend_from_slice(&crc.to_be_bytes());
+        };
+
+        let mut ihdr = Vec::new();
+        ihdr.extend_from_slice(&width.to_be_bytes());
+        ihdr.extend_from_slice(&height.to_be_bytes());
+        ihdr.extend_from_slice(&[8, 6, 0, 0, 0]);
+
+        let mut png = b"\x89PNG\r\n\x1a\n".to_vec();
+        chunk(&mut png, b"IHDR", &ihdr);
+        chunk(&mut png, b"IDAT", &[]);
+        chunk(&mut png, b"IEND", &[]);
+        png
+    }
+
+    #[test]
+    fn test_decompression_bomb_rejected() {
+        let bomb = png_header(100_000, 100_000);
+        for result in [
+            png_to_dib(&bomb),
+            png_to_dibv5(&bomb),
+            any_to_dib(&bomb),
+            any_to_dibv5(&bomb),
+        ] {
+            assert!(matches!(
+                result,
+                Err(ClipboardError::ImageTooLarge {
+                    width: 100_000,
+                    height: 100_000
+                })
+            ));
+        }
+
+        let png = dib_to_png(&crate::testing::fixtures::DibBuilder::new(64, 64).build()).unwrap();
+        let limits = ImageLimits {
+            max_decoded_bytes: 1024,
+            resize_strategy: ResizeStrategy::Lanczos,
+            ..ImageLimits::default()
+        };
+        assert!(matches!(
+            png_to_dib_with_limits(&png, &limits),
+            Err(ClipboardError::ImageTooLarge { width: 64, height: 64 })
+        ));
+        assert!(png_to_dib_with_limits(&png, &ImageLimits::default()).is_ok());
+
+        let mut rle = dib_header(60_000, 60_000, 8, BI_RLE8, 0);
+        rle.extend_from_slice(&[0; 1024]);
+        rle.extend_from_slice(&[0, 1]);
+        assert!(matches!(
+            parse_dib_to_image(&rle),
+            Err(ClipboardError::ImageTooLarge { .. })
+        ));
+
+        // DIBs are checked against the caller's limits before decoding
+        let small = ImageLimits {
+            max_decoded_bytes: 1024,
+            ..ImageLimits::default()
+        };
+        let dib = crate::testing::fixtures::DibBuilder::new(64, 64).build();
+        assert!(matches!(
+            dib_to_png_with_limits(&dib, &small),
+            Err(ClipboardError::ImageTooLarge { width: 64, height: 64 })
+        ));
+        let mut rle = dib_header(64, 64, 8, BI_RLE8, 0);
+        rle.extend_from_slice(&[0; 1024]);
+        rle.extend_from_slice(&[0, 1]);
+        assert!(matches!(
+            dib_to_png_with_limits(&rle, &small),
+            Err(ClipboardError::ImageTooLarge { width: 64, height: 64 })
+        ));
+        assert!(dib_to_png_with_limits(&rle, &ImageLimits::default()).is_ok());
+        let dibv5 = png_to_dibv5(&dib_to_png(&dib).unwrap()).unwrap();
+        assert!(matches!(
+            dibv5_to_png_with_limits(&dibv5, &small),
+            Err(ClipboardError::ImageTooLarge { width: 64, height: 64 })
+        ));
+    }
+
+    /// BITMAPINFOHEADER for a bottom-up DIB
+    fn dib_header(width: i32, height: i32, bit_count: u16, compression: u32, colors_used: u32) -> Vec<u8> {
+        let mut header = Vec::with_capacity(40);
+        header.extend_from_slice(&40u32.to_le_bytes());
+        header.extend_from_slice(&width.to_le_bytes());
+        header.extend_from_slice(&height.to_le_bytes());
+        header.extend_from_slice(&1u16.to_le_bytes());
+        header.extend_from_slice(&bit_count.to_le_bytes());
+        header.extend_from_slice(&compression.to_le_bytes());
+        header.extend_from_slice(&[0; 12]);
+        header.extend_from_slice(&colors_used.to_le_bytes());
+        header.extend_from_slice(&0u32.to_le_bytes());
+        header
+    }
+
+    #[test]
+    fn test_bitfields_dibs() {
+        // 16-bit 5-6-5 with masks after the header, 3 pixels padded to 8 bytes
+        let mut dib = dib_header(3, 1, 16, BI_BITFIELDS, 0);
+        for mask in [0xF800u32, 0x07E0, 0x001F] {
+            dib.extend_from_slice(&mask.to_le_bytes());
+        }
+        for pixel in [0xF800u16, 0x07E0, 0x0010, 0] {
+            dib.extend_from_slice(&pixel.to_le_bytes());
+        }
+        let image = parse_dib_to_image(&dib).unwrap().to_rgba8();
+        assert_eq!(image.get_pixel(0, 0).0, [255, 0, 0, 255]);
+        assert_eq!(image.get_pixel(1, 0).0, [0, 255, 0, 255]);
+        assert_eq!(image.get_pixel(2, 0).0, [0, 0, 132, 255]);
+
+        // 16-bit BI_RGB defaults to 5-5-5
+        let mut dib = dib_header(2, 1, 16, BI_RGB, 0);
+        for pixel in [0x7C00u16, 0x03FF] {
+            dib.extend_from_slice(&pixel.to_le_bytes());
+        }
+        let image = parse_dib_to_image(&dib).unwrap().to_rgba8();
+        assert_eq!(image.get_pixel(0, 0).0, [255, 0, 0, 255]);
+        assert_eq!(image.get_pixel(1, 0).0, [0, 255, 255, 255]);
+
+        // 32-bit 10-10-10-2 with an alpha mask (BI_ALPHABITFIELDS)
+        let mut dib = dib_header(1, 1, 32, BI_ALPHABITFIELDS, 0);
+        for mask in [0x3FF0_0000u32, 0x000F_FC00, 0x0000_03FF, 0xC000_0000] {
+            dib.extend_from_slice(&mask.to_le_bytes());
+        }
+        dib.extend_from_slice(&(0x3FF0_0000u32 | 0x4000_0000).to_le_bytes());
+        let image = parse_dib_to_image(&dib).unwrap().to_rgba8();
+        assert_eq!(image.get_pixel(0, 0).0, [255, 0, 0, 85]);
+    }
+
+    #[test]
+    fn test_oversized_dib_dimensions() {
+        // i32::MIN width and height, as BITMAPINFOHEADER and BITMAPV5HEADER
+        for header_size in [40u32, 124] {
+            let mut dib = dib_header(i32::MIN, i32::MIN, 32, BI_BITFIELDS, 0);
+            dib[..4].copy_from_slice(&header_size.to_le_bytes());
+            dib.resize(header_size as usize + 16, 0xFF);
+            assert!(matches!(
+                parse_dib_to_image(&dib),
+                Err(ClipboardError::ImageTooLarge { .. })
+            ));
+            assert!(dib_to_png(&dib).is_err());
+        }
+
+        // Sizes that do not fit in memory fail instead of wrapping around
+        let (max, data) = (u32::MAX, [0u8; 64]);
+        assert!(convert_bitfields_dib(&data, 32, max, max, false, BGRA_MASKS).is_err());
+        assert!(convert_32bit_dib(&data, max, max, false).is_err());
+        assert!(convert_24bit_dib(&data, max, max, false).is_err());
+        assert!(convert_palette_dib(&data, &[], 8, max, max, false).is_err());
+    }
+
+    #[test]
+    fn test_truncated_bitfields_header() {
+        // 44-byte header: not a V2/V3 size, and too short for its masks
+        let mut dib = dib_header(1, 1, 16, BI_BITFIELDS, 0);
+        dib[..4].copy_from_slice(&44u32.to_le_bytes());
+        dib.extend_from_slice(&[0; 5]);
+        assert!(matches!(parse_dib_to_image(&dib), Err(ClipboardError::ImageDecode(_))));
+        assert!(dib_to_png(&dib).is_err());
+
+        // V2 header with BI_ALPHABITFIELDS ending before the alpha mask
+        let mut dib = dib_header(1, 1, 32, BI_ALPHABITFIELDS, 0);
+        dib[..4].copy_from_slice(&52u32.to_le_bytes());
+        dib.extend_from_slice(&[0; 13]);
+        assert!(matches!(parse_dib_to_image(&dib), Err(ClipboardError::ImageDecode(_))));
+
+        // V3 header cut inside its masks
+        let mut dib = dib_header(1, 1, 32, BI_BITFIELDS, 0);
+        dib[..4].copy_from_slice(&56u32.to_le_bytes());
+        dib.extend_from_slice(&[0; 10]);
+        assert!(matches!(parse_dib_to_image(&dib), Err(ClipboardError::ImageDecode(_))));
+    }
+
+    #[test]
+    fn test_rle_dibs() {
+        const PALETTE: [u8; 12] = [0, 0, 0, 0, 0, 0, 255, 0, 255, 0, 0, 0]; // black, red, blue
+        let (red, blue) = ([255, 0, 0], [0, 0, 255]);
+
+        // RLE8, 5x2: bottom row run + absolute, delta up, then one pixel
+        let mut dib = dib_header(5, 2, 8, BI_RLE8, 3);
+        dib.extend_from_slice(&PALETTE);
+        dib.extend_from_slice(&[2, 1, 0, 3, 2, 1, 2, 0, 0, 0]); // red red | blue red blue (+pad)
+        dib.extend_from_slice(&[0, 2, 4, 0, 1, 2, 0, 1]); // delta right 4, blue, end
+        let image = parse_dib_to_image(&dib).unwrap().to_rgb8();
+        let bottom: Vec<_> = (0..5).map(|x| image.get_pixel(x, 1).0).collect();
+        assert_eq!(bottom, [red, red, blue, red, blue]);
+        assert_eq!(image.get_pixel(3, 0).0, [0, 0, 0]);
+        assert_eq!(image.get_pixel(4, 0).0, blue);
+
+        // RLE4, 6x1: alternating run of 3, absolute run of 3 in two bytes
+        let mut dib = dib_header(6, 1, 4, BI_RLE4, 3);
+        dib.extend_from_slice(&PALETTE);
+        dib.extend_from_slice(&[3, 0x12, 0, 3, 0x21, 0x20, 0, 1]);
+        let image = parse_dib_to_image(&dib).unwrap().to_rgb8();
+        let row: Vec<_> = (0..6).map(|x| image.get_pixel(x, 0).0).collect();
+        assert_eq!(row, [red, blue, red, blue, red, blue]);
+
+        // Runs past the row end are clipped, missing end-of-bitmap is an error
+        let mut dib = dib_header(2, 1, 8, BI_RLE8, 3);
+        dib.extend_from_slice(&PALETTE);
+        dib.extend_from_slice(&[9, 1, 0, 1]);
+        assert!(parse_dib_to_image(&dib).is_ok());
+        assert!(parse_dib_to_image(&dib[..dib.len() - 2]).is_err());
+    }
+
+    #[test]
+    fn test_palette_dibs() {
+        const RED: [u8; 4] = [0, 0, 255, 0];
+        const BLUE: [u8; 4] = [255, 0, 0, 0];
+
+        // 8-bit, 3x2, two-entry table, rows padded to 4 bytes
+        let mut dib = dib_header(3, 2, 8, BI_RGB, 2);
+        dib.extend_from_slice(&[RED, BLUE].concat());
+        dib.extend_from_slice(&[1, 1, 1, 0]); // bottom row
+        dib.extend_from_slice(&[0, 1, 9, 0]); // top row, index 9 is past the table
+        let image = parse_dib_to_image(&dib).unwrap().to_rgb8();
+        assert_eq!(image.dimensions(), (3, 2));
+        assert_eq!(image.get_pixel(0, 0).0, [255, 0, 0]);
+        assert_eq!(image.get_pixel(1, 0).0, [0, 0, 255]);
+        assert_eq!(image.get_pixel(2, 0).0, [0, 0, 0]);
+        assert_eq!(image.get_pixel(0, 1).0, [0, 0, 255]);
+
+        // 4-bit, full 16-entry table implied by biClrUsed = 0
+        let mut dib = dib_header(3, 1, 4, BI_RGB, 0);
+        let mut table = vec![0u8; 64];
+        table[4 * 15..4 * 16].copy_from_slice(&RED);
+        dib.extend_from_slice(&table);
+        dib.extend_from_slice(&[0xF0, 0xF0, 0, 0]);
+        let image = parse_dib_to_image(&dib).unwrap().to_rgb8();
+        assert_eq!(image.get_pixel(0, 0).0, [255, 0, 0]);
+        assert_eq!(image.get_pixel(1, 0).0, [0, 0, 0]);
+        assert_eq!(image.get_pixel(2, 0).0, [255, 0, 0]);
+
+        // 1-bit, 10 pixels wide, top-down
+        let mut dib = dib_header(10, -1, 1, BI_RGB, 2);
+        dib.extend_from_slice(&[BLUE, RED].concat());
+        dib.extend_from_slice(&[0b1000_0000, 0b0100_0000, 0, 0]);
+        let image = parse_dib_to_image(&dib).unwrap().to_rgb8();
+        assert_eq!(image.get_pixel(0, 0).0, [255, 0, 0]);
+        assert_eq!(image.get_pixel(1, 0).0, [0, 0, 255]);
+        assert_eq!(image.get_pixel(9, 0).0, [255, 0, 0]);
+
+        // Truncated color table
+        let dib = dib_header(1, 1, 8, BI_RGB, 0);
+        assert!(parse_dib_to_image(&[dib, vec![0; 8]].concat()).is_err());
+    }
 }
@@ -0,0 +1,329 @@
+//! Compatibility profiles for CLIPRDR peers with known quirks.
+//!
+//! Most peers follow MS-RDPECLIP closely enough that no special handling is
+//! needed. xrdp's clipboard channel server (chansrv) does not:
+//!
+//! - Format names in the fixed 32-byte short-name field are padded with
+//!   spaces instead of NULs, and names longer than the field are cut off
+//!   without a terminator (`"FileGroupDescrip"`).
+//! - Clipboard data locking is not implemented. Lock and Unlock Clipboard
+//!   Data PDUs are ignored, and File Contents Requests never refer to a lock.
+//!
+//! A [`CompatProfile`] selects the workarounds for a session: it cleans up
+//! format names before they reach the [`FormatRegistry`](crate::FormatRegistry)
+//! and tells the session whether locking can be relied on.
+//! [`decode_format_list`] decodes Format List bodies in every name encoding,
+//! which is what captured sessions are replayed through in the tests.
+
+use crate::formats::ClipboardFormat;
+use crate::registry::expand_truncated_name;
+use crate::{ClipboardError, ClipboardResult};
+
+/// Size of one entry in a short-name Format List (ID + 32-byte name)
+const SHORT_FORMAT_ENTRY_LEN: usize = 36;
+
+/// Workarounds applied for a particular kind of peer
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "serde", serde(rename_all = "snake_case"))]
+pub enum CompatProfile {
+    /// Peer follows the specification; no workarounds
+    #[default]
+    Standard,
+    /// xrdp chansrv: padded or truncated format names, no data locking
+    Xrdp,
+}
+
+impl CompatProfile {
+    /// Whether Lock/Unlock Clipboard Data PDUs should be used.
+    ///
+    /// `peer_can_lock` is whether the peer's General Capability Set carries
+    /// `CB_CAN_LOCK_CLIPDATA`. Under [`Xrdp`](Self::Xrdp) locks are never
+    /// used, so file transfers must not wait for a lock to be taken.
+    pub fn uses_clip_data_locks(self, peer_can_lock: bool) -> bool {
+        match self {
+            Self::Standard => peer_can_lock,
+            Self::Xrdp => false,
+        }
+    }
+
+    /// Clean up a format name received from the peer.
+    ///
+    /// Returns `None` for names that are empty after clean-up, which mark
+    /// standard formats.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use lamco_clipboard_core::interop::CompatProfile;
+    ///
+    /// let profile = CompatProfile::Xrdp;
+    /// assert_eq!(profile.normalize_format_name("HTML Format     ").as_deref(), Some("HTML Format"));
+    /// assert_eq!(
+    ///     profile.normalize_format_name("FileGroupDescrip").as_deref(),
+    ///     Some("FileGroupDescriptorW")
+    /// );
+    /// assert_eq!(profile.normalize_format_name("   "), None);
+    /// ```
+    pub fn normalize_format_name(self, name: &str) -> Option<String> {
+        let name = match self {
+            Self::Standard => name,
+            Self::Xrdp => name.trim_end_matches(|c: char| c == '\0' || c.is_whitespace()),
+        };
+
+        if name.is_empty() {
+            return None;
+        }
+
+        if self == Self::Xrdp {
+            if let Some(full) = expand_truncated_name(name) {
+                tracing::debug!("Expanded truncated format name {:?} to {:?}", name, full);
+                return Some(full.to_string());
+            }
+        }
+
+        Some(name.to_string())
+    }
+
+    /// Clean up every name in a Format List received from the peer
+    pub fn normalize_formats(self, formats: &[ClipboardFormat]) -> Vec<ClipboardFormat> {
+        formats
+            .iter()
+            .map(|format| ClipboardFormat {
+                id: format.id,
+                name: format.name.as_deref().and_then(|name| self.normalize_format_name(name)),
+            })
+            .collect()
+    }
+}
+
+/// How format names are encoded in a Format List PDU
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "serde", serde(rename_all = "snake_case"))]
+pub enum FormatNameEncoding {
+    /// Variable-length, NUL-terminated UTF-16LE names (`CB_USE_LONG_FORMAT_NAMES`)
+    Long,
+    /// Fixed 32-byte UTF-16LE names
+    ShortUnicode,
+    /// Fixed 32-byte ASCII names (`CB_ASCII_NAMES` message flag)
+    ShortAscii,
+}
+
+/// Decode the body of a Format List PDU.
+///
+/// Names are returned as sent, up to their first NUL, so padding is kept for
+/// [`CompatProfile::normalize_formats`] to deal with.
+///
+/// # Example
+///
+/// ```
+/// use lamco_clipboard_core::interop::{decode_format_list, FormatNameEncoding};
+///
+/// // CF_UNICODETEXT without a name
+/// let formats = decode_format_list(&[13, 0, 0, 0, 0, 0], FormatNameEncoding::Long).unwrap();
+/// assert_eq!(formats[0].id, 13);
+/// assert_eq!(formats[0].name, None);
+/// ```
+pub fn decode_format_list(body: &[u8], encoding: FormatNameEncoding) -> ClipboardResult<Vec<ClipboardFormat>> {
+    let mut formats = Vec::new();
+
+    match encoding {
+        FormatNameEncoding::Long => {
+            let mut rest = body;
+            while !rest.is_empty() {
+                if rest.len() < 4 {
+                    return Err(ClipboardError::FormatConversion(
+                        "Format List entry shorter than its format ID".to_string(),
+                    ));
+                }
+                let id = u32::from_le_bytes([rest[0], rest[1], rest[2], rest[3]]);
+                rest = &rest[4..];
+
+                let mut units = Vec::new();
+                loop {
+                    if rest.len() < 2 {
+                        return Err(ClipboardError::FormatConversion(format!(
+                            "Unterminated name for format {}",
+                            id
+                        )));
+                    }
+                    let unit = u16::from_le_bytes([rest[0], rest[1]]);
+                    rest = &rest[2..];
+                    if unit == 0 {
+                        break;
+                    }
+                    units.push(unit);
+                }
+                formats.push(format_with_name(id, String::from_utf16_lossy(&units)));
+            }
+        }
+        FormatNameEncoding::ShortUnicode | FormatNameEncoding::ShortAscii => {
+            if body.len() % SHORT_FORMAT_ENTRY_LEN != 0 {
+                return Err(ClipboardError::FormatConversion(format!(
+                    "Short Format List length {} is not a multiple of {}",
+                    body.len(),
+                    SHORT_FORMAT_ENTRY_LEN
+                )));
+            }
+            for entry in body.chunks_exact(SHORT_FORMAT_ENTRY_LEN) {
+                let id = u32::from_le_bytes([entry[0], entry[1], entry[2], entry[3]]);
+                let field = &entry[4..];
+                let name = if encoding == FormatNameEncoding::ShortAscii {
+                    let len = field.iter().position(|&b| b == 0).unwrap_or(field.len());
+                    String::from_utf8_lossy(&field[..len]).into_owned()
+                } else {
+                    let units: Vec<u16> = field
+                        .chunks_exact(2)
+                        .map(|pair| u16::from_le_bytes([pair[0], pair[1]]))
+                        .take_while(|&unit| unit != 0)
+                        .collect();
+                    String::from_utf16_lossy(&units)
+                };
+                formats.push(format_with_name(id, name));
+            }
+        }
+    }
+
+    Ok(formats)
+}
+
+fn format_with_name(id: u32, name: String) -> ClipboardFormat {
+    if name.is_empty() {
+        ClipboardFormat::new(id)
+    } else {
+        ClipboardFormat::with_name(id, name)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::formats::{CF_TEXT, CF_UNICODETEXT};
+    use crate::FormatRegistry;
+
+    /// Format List bodies in the layout xrdp chansrv sends them
+    mod xrdp_session {
+        use super::*;
+
+        fn short_entry(id: u32, name: &[u8], ascii: bool, pad: u8) -> Vec<u8> {
+            let mut entry = id.to_le_bytes().to_vec();
+            let mut field = Vec::new();
+            for &b in name {
+                field.push(b);
+                if !ascii {
+                    field.push(0);
+                }
+            }
+            field.resize(32, pad);
+            if !ascii && pad != 0 {
+                // UTF-16 padding is one space per unit
+                for pair in field[name.len() * 2..].chunks_exact_mut(2) {
+                    pair[1] = 0;
+                }
+            }
+            entry.extend_from_slice(&field[..32]);
+            entry
+        }
+
+        /// Text copy: CF_UNICODETEXT, CF_TEXT and a space-padded "HTML Format"
+        pub(super) fn text_copy_ascii() -> Vec<u8> {
+            let mut body = short_entry(CF_UNICODETEXT, b"", true, 0);
+            body.extend(short_entry(CF_TEXT, b"", true, 0));
+            body.extend(short_entry(0xC004, b"HTML Format", true, b' '));
+            body
+        }
+
+        /// File copy from a Windows client: the descriptor name is cut at 16 units
+        pub(super) fn file_copy_unicode() -> Vec<u8> {
+            let mut body = short_entry(0xC07A, b"FileGroupDescriptorW", false, 0);
+            body.extend(short_entry(0xC07B, b"FileContents", false, b' '));
+            body.extend(short_entry(0xC07C, b"UniformResourceLocatorW", false, 0));
+            body
+        }
+
+        /// Long-name list with a trailing space on the registered name
+        pub(super) fn image_copy_long() -> Vec<u8> {
+            let mut body = Vec::new();
+            for (id, name) in [(8u32, ""), (0xC010, "PNG ")] {
+                body.extend(id.to_le_bytes());
+                for unit in name.encode_utf16().chain([0]) {
+                    body.extend(unit.to_le_bytes());
+                }
+            }
+            body
+        }
+    }
+
+    fn replay(body: &[u8], encoding: FormatNameEncoding, profile: CompatProfile) -> FormatRegistry {
+        let formats = decode_format_list(body, encoding).unwrap();
+        let mut registry = FormatRegistry::new();
+        registry.update_remote(&profile.normalize_formats(&formats));
+        registry
+    }
+
+    #[test]
+    fn test_replay_padded_ascii_names() {
+        let body = xrdp_session::text_copy_ascii();
+
+        let registry = replay(&body, FormatNameEncoding::ShortAscii, CompatProfile::Xrdp);
+        assert_eq!(registry.remote_format_to_mime(0xC004), Some("text/html"));
+        assert_eq!(registry.remote_id_for_mime("text/html"), Some(0xC004));
+        assert_eq!(registry.remote_name(CF_UNICODETEXT), None);
+
+        // Without the profile the padded name resolves to nothing
+        let registry = replay(&body, FormatNameEncoding::ShortAscii, CompatProfile::Standard);
+        assert_eq!(registry.remote_format_to_mime(0xC004), None);
+    }
+
+    #[test]
+    fn test_replay_truncated_unicode_names() {
+        let body = xrdp_session::file_copy_unicode();
+        let formats = decode_format_list(&body, FormatNameEncoding::ShortUnicode).unwrap();
+        assert_eq!(formats[0].name.as_deref(), Some("FileGroupDescrip"));
+
+        let registry = replay(&body, FormatNameEncoding::ShortUnicode, CompatProfile::Xrdp);
+        assert_eq!(registry.remote_format_to_mime(0xC07A), Some("text/uri-list"));
+        assert_eq!(registry.remote_name(0xC07B), Some("FileContents"));
+
+        // "UniformResourceL" could be either URL format, so it is left alone
+        assert_eq!(registry.remote_name(0xC07C), Some("UniformResourceL"));
+        assert_eq!(registry.remote_format_to_mime(0xC07C), None);
+    }
+
+    #[test]
+    fn test_replay_long_names() {
+        let body = xrdp_session::image_copy_long();
+
+        let registry = replay(&body, FormatNameEncoding::Long, CompatProfile::Xrdp);
+        assert_eq!(registry.remote_format_to_mime(0xC010), Some("image/png"));
+        assert!(registry.remote_has(8));
+    }
+
+    #[test]
+    fn test_decode_rejects_malformed_lists() {
+        assert!(decode_format_list(&[13, 0, 0], FormatNameEncoding::Long).is_err());
+        assert!(decode_format_list(&[13, 0, 0, 0, b'A', 0], FormatNameEncoding::Long).is_err());
+        assert!(decode_format_list(&[0; 35], FormatNameEncoding::ShortAscii).is_err());
+        assert!(decode_format_list(&[], FormatNameEncoding::ShortUnicode)
+            .unwrap()
+            .is_empty());
+    }
+
+    #[test]
+    fn test_clip_data_locks() {
+        assert!(CompatProfile::Standard.uses_clip_data_locks(true));
+        assert!(!CompatProfile::Standard.uses_clip_data_locks(false));
+        assert!(!CompatProfile::Xrdp.uses_clip_data_locks(true));
+    }
+
+    #[test]
+    fn test_standard_profile_keeps_names() {
+        let formats = [
+            ClipboardFormat::with_name(0xC001, "HTML Format "),
+            ClipboardFormat::new(1),
+        ];
+        let normalized = CompatProfile::Standard.normalize_formats(&formats);
+        assert_eq!(normalized, formats);
+    }
+}
@@ -9,6 +9,15 @@
 //! - **[`FormatConverter`]** - MIME ↔ Windows clipboard format conversion
 //! - **[`LoopDetector`]** - Prevent clipboard sync loops with content hashing
 //! - **[`TransferEngine`]** - Chunked transfer for large clipboard data
+//! - **[`MirrorSink`]** - Copy synced content to a secondary preview/recording sink
+//! - **[`ReadOnlySink`]** - Allow pastes into the session but serve a placeholder for copies out
+//! - **[`FileWriterSink`]** - Write received files atomically, renaming or skipping names already taken
+//! - **[`PeerProfile`]** - Stop offering image formats the peer never requests
+//! - **[`RequestWindow`]** - Cap requests in flight to or from the peer and queue the rest
+//! - **[`ConversionMetrics`]** - Count failed conversions by cause to see which formats break in production
+//! - **`ConversionCacheConfig`** - Serve repeated requests for the same image conversion from memory (feature `image`)
+//! - **[`FormatNegotiator`]** - Pick the highest-fidelity remote format the local side can use
+//! - **[`ClipboardSession`](protocol::ClipboardSession)** - Sans-io copy/paste state machine for non-async hosts and WASM
 //!
 //! ## Quick Start
 //!
@@ -20,7 +29,7 @@
 //! let formats = mime_to_rdp_formats(&["text/plain", "text/html"]);
 //!
 //! // Check for clipboard loops
-//! let mut detector = LoopDetector::new();
+//! let detector = LoopDetector::new();
 //! if !detector.would_cause_loop(&formats) {
 //!     // Safe to sync
 //! }
@@ -28,7 +37,21 @@
 //!
 //! ## Feature Flags
 //!
-//! - `image` - Enable image format conversion (PNG, JPEG, BMP ↔ DIB)
+//! - `image` - Enable image format conversion (PNG, JPEG, BMP, TIFF ↔ DIB)
+//! - `metafile` - Rasterize CF_ENHMETAFILE and CF_METAFILEPICT to PNG (implies `image`)
+//! - `webp` - Decode `image/webp` to DIB, DIBV5 and PNG (implies `image`)
+//! - `avif` - Decode `image/avif` to DIB, DIBV5 and PNG via the system dav1d library (implies `image`)
+//! - `svg` - Rasterize `image/svg+xml` to PNG, DIBV5 and DIB (implies `image`)
+//! - `parallel` - Convert the rows of large bitmaps between DIB and RGBA on the rayon thread pool (implies `image`)
+//! - `flac` - Carry `audio/flac` as CF_WAVE and send CF_WAVE out as FLAC
+//! - `markdown` - Carry `text/markdown` over RDP as CF_HTML
+//! - `tokio` - Enable [`PollingChangeDriver`] for backends without change notification,
+//!   [`BlockingSink`] for non-async integrators and `AsyncRead` sources for [`stream`]
+//! - `serde` - `Serialize`/`Deserialize` for formats, file descriptors, session events and configuration
+//! - `fuse` - Mount a remote file list read-only through FUSE, fetching file contents on demand
+//!   ([`remote_fs::RemoteFs`])
+//! - `testing` - Fixture builders for Format Lists, FileGroupDescriptorW, CF_HTML and DIBs, an in-memory
+//!   sink and a `block_on` for driving it
 //!
 //! ## Architecture
 //!
@@ -39,23 +62,112 @@
 #![cfg_attr(docsrs, feature(doc_cfg))]
 #![deny(missing_docs)]
 
+#[cfg(feature = "tokio")]
+mod blocking;
+#[cfg(feature = "image")]
+mod cache;
+mod clock;
+mod coalesce;
+mod dedup;
 mod error;
+mod file_source;
+mod file_writer;
+mod flow;
+mod guard;
+mod metrics;
+mod mirror;
+mod polling;
+mod pool;
+mod readonly;
 mod sink;
+mod staging;
 mod transfer;
 
+pub mod audio;
+pub mod config;
+pub mod converter;
+pub mod file_transfer;
 pub mod formats;
+pub mod interop;
 pub mod loop_detector;
+pub mod negotiate;
+pub mod peer;
+pub mod protocol;
+pub mod registry;
+pub mod remote_fs;
+pub mod rtf;
 pub mod sanitize;
+pub mod spreadsheet;
+pub mod stream;
+pub mod web_custom_data;
+
+#[cfg(feature = "compression")]
+pub mod compression;
 
 #[cfg(feature = "image")]
 pub mod image;
 
-pub use error::{ClipboardError, ClipboardResult};
+#[cfg(feature = "metafile")]
+pub mod metafile;
+
+#[cfg(feature = "markdown")]
+pub mod markdown;
+
+#[cfg(feature = "svg")]
+pub mod svg;
+
+#[cfg(any(test, feature = "testing"))]
+pub mod testing;
+
+#[cfg(feature = "tokio")]
+pub use blocking::BlockingSink;
+#[cfg(feature = "image")]
+pub use cache::{CacheMetrics, ConversionCacheConfig, DEFAULT_CACHE_BYTES, DEFAULT_CACHE_ENTRIES};
+pub use clock::{Clock, ManualClock, SystemClock};
+pub use coalesce::{ChangeCoalescer, CoalesceConfig, DEFAULT_MAX_COALESCE_DELAY_MS, DEFAULT_QUIET_PERIOD_MS};
+pub use config::{ClipboardConfig, ConfigError};
+pub use converter::Converter;
+pub use dedup::DedupSink;
+pub use error::{ClipboardError, ClipboardResult, TransferError, TransferErrorKind};
+pub use file_source::AnnouncedFile;
+pub use file_writer::{CollisionPolicy, FileWriterSink};
+pub use flow::{
+    FlowControlConfig, RateLimiter, RequestWindow, TransferPriority, WindowMetrics, DEFAULT_BURST_BYTES,
+    DEFAULT_INTERACTIVE_MAX_BYTES, DEFAULT_MAX_CONCURRENT_SERVICING, DEFAULT_MAX_OUTSTANDING_REQUESTS,
+    DEFAULT_MAX_QUEUED_REQUESTS, DEFAULT_RESERVED_INTERACTIVE_SLOTS,
+};
 pub use formats::{
-    build_file_group_descriptor_w, ClipboardFormat, FileDescriptor, FileDescriptorFlags, FormatConverter,
+    build_file_group_descriptor_w, CfHtml, ClipboardFormat, FileDescriptor, FileDescriptorFlags, FileOperation,
+    FormatConverter, LineEndings, NonUtf8PathPolicy, TextNormalization, TextPolicy,
+};
+pub use guard::{BudgetReservation, ClipDataLock, ClipDataLocks, MemoryBudget, TempFileGuard};
+#[cfg(feature = "image")]
+pub use image::{ChromaSubsampling, DibAlpha, ImageEncodeOptions, ImageLimits, PngCompression, ResizeStrategy};
+pub use interop::CompatProfile;
+pub use loop_detector::{
+    ClipboardSource, Generation, HashAlgorithm, LoopDetectionConfig, LoopDetector, LoopDetectorState, LoopEvent,
+    LoopKind, LoopStats, RecordedOperation,
+};
+pub use metrics::{
+    ConversionFailure, ConversionMetrics, RttHistogram, TransferFailure, TransferFailures, TransferMetrics,
+    TransferRecorder, RTT_BUCKETS_MS,
 };
-pub use loop_detector::{ClipboardSource, LoopDetectionConfig, LoopDetector};
+pub use mirror::MirrorSink;
+#[cfg(feature = "tokio")]
+pub use mirror::DEFAULT_MIRROR_TIMEOUT_MS;
+pub use negotiate::{FormatNegotiator, Negotiation};
+pub use peer::PeerProfile;
+#[cfg(feature = "tokio")]
+pub use polling::PollingChangeDriver;
+pub use polling::{PollingConfig, PollingMode, DEFAULT_MAX_POLL_INTERVAL_MS, DEFAULT_MIN_POLL_INTERVAL_MS};
+pub use pool::{BufferPool, PoolMetrics, PooledBuffer, DEFAULT_BUFFERS_PER_CLASS, DEFAULT_SIZE_CLASSES};
+pub use readonly::{ReadOnlySink, DEFAULT_EXPORT_PLACEHOLDER};
+pub use registry::FormatRegistry;
+pub use sanitize::PathMapper;
 pub use sink::{ClipboardChange, ClipboardChangeReceiver, ClipboardChangeReceiverInner, ClipboardSink, FileInfo};
+pub use staging::{
+    write_sparse, FsyncPolicy, StagingArea, StagingConfig, DEFAULT_SPARSE_THRESHOLD_BYTES, DEFAULT_STAGING_QUOTA_BYTES,
+};
 pub use transfer::{
     TransferConfig, TransferEngine, TransferProgress, TransferState, DEFAULT_CHUNK_SIZE, DEFAULT_MAX_SIZE,
     DEFAULT_TIMEOUT_MS,
@@ -3,13 +3,22 @@
 //! Prevents clipboard sync loops by tracking format and content hashes.
 
 use sha2::{Digest, Sha256};
-use std::collections::VecDeque;
-use std::time::{Duration, Instant};
-
+use std::collections::{BTreeMap, HashMap, VecDeque};
+use std::fmt;
+use std::str::FromStr;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex, MutexGuard, RwLock, RwLockReadGuard, RwLockWriteGuard};
+use std::time::Duration;
+use xxhash_rust::xxh3::{xxh3_128, Xxh3};
+
+use crate::clock::{Clock, SystemClock};
+use crate::formats::{CF_DIB, CF_DIBV5, CF_DSPBITMAP, CF_ENHMETAFILE, CF_HDROP, CF_METAFILEPICT, CF_TIFF};
 use crate::ClipboardFormat;
 
 /// Configuration for loop detection
 #[derive(Debug, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "serde", serde(default))]
 pub struct LoopDetectionConfig {
     /// Time window for detecting loops (default: 500ms)
     pub window_ms: u64,
@@ -20,12 +29,89 @@ pub struct LoopDetectionConfig {
     /// Enable content hashing for deduplication
     pub enable_content_hashing: bool,
 
+    /// Hash function for formats and content (default: XXH3)
+    pub hash_algorithm: HashAlgorithm,
+
     /// Optional rate limit in milliseconds (default: None)
     ///
     /// When set, sync operations are throttled to at most one per `rate_limit_ms`.
     /// This provides belt-and-suspenders protection against rapid clipboard updates
     /// even when loop detection passes.
     pub rate_limit_ms: Option<u64>,
+
+    /// Also match image content by perceptual hash (default: false)
+    ///
+    /// The same screenshot re-encoded between PNG and DIB has different
+    /// bytes, so its echo escapes the exact content hash. Requires the
+    /// `image` feature; without it this setting has no effect.
+    pub perceptual_image_hashing: bool,
+
+    /// Differing bits up to which two image hashes count as the same
+    /// picture (default: 4 of 64)
+    pub perceptual_hash_distance: u32,
+
+    /// Consecutive format lists alternating between the sides, each side
+    /// repeating its own list, that trip the circuit breaker (default: 0,
+    /// disabled)
+    ///
+    /// Catches ping-pong that single-hop matching misses because each round
+    /// arrives outside `window_ms` or slightly changes the content.
+    pub oscillation_hops: u32,
+
+    /// Time span the alternating hops must fall within (default: 10 s)
+    pub oscillation_window_ms: u64,
+
+    /// How long sync stays paused once the circuit breaker trips (default: 30 s)
+    pub cooldown_ms: u64,
+
+    /// Window for text (default: `window_ms`)
+    ///
+    /// Keep it short so legitimate rapid copies are not suppressed.
+    pub text_window_ms: Option<u64>,
+
+    /// Window for images (default: 2 s)
+    ///
+    /// Converting a large image takes long enough that its echo often
+    /// arrives after `window_ms`.
+    pub image_window_ms: Option<u64>,
+
+    /// Window for file lists (default: `window_ms`)
+    pub files_window_ms: Option<u64>,
+
+    /// Operations to track for text (default: `max_history`)
+    pub text_max_history: Option<usize>,
+
+    /// Operations to track for images (default: `max_history`)
+    pub image_max_history: Option<usize>,
+
+    /// Operations to track for file lists (default: `max_history`)
+    pub files_max_history: Option<usize>,
+
+    /// How long content stays in the long-horizon filter (default: 0,
+    /// disabled)
+    ///
+    /// Behind the precise history, a counting Bloom filter whose counters
+    /// decay over this span also suppresses content repeated minutes later,
+    /// such as a clipboard manager re-asserting ownership. Memory stays at
+    /// `long_horizon_slots` bytes; false positives grow as it fills.
+    pub long_horizon_ms: u64,
+
+    /// Counters in the long-horizon filter (default: 4096)
+    pub long_horizon_slots: usize,
+
+    /// Derive `window_ms` from reported sync latencies (default: false)
+    ///
+    /// Once enough latencies are reported with
+    /// [`LoopDetector::record_sync_latency`], the window becomes twice their
+    /// 95th percentile, clamped to the bounds below. Classes with their own
+    /// window keep it.
+    pub adaptive_window: bool,
+
+    /// Shortest adaptive window (default: 100 ms)
+    pub adaptive_window_min_ms: u64,
+
+    /// Longest adaptive window (default: 5 s)
+    pub adaptive_window_max_ms: u64,
 }
 
 impl Default for LoopDetectionConfig {
@@ -34,7 +120,24 @@ impl Default for LoopDetectionConfig {
             window_ms: 500,
             max_history: 10,
             enable_content_hashing: true,
+            hash_algorithm: HashAlgorithm::default(),
             rate_limit_ms: None,
+            perceptual_image_hashing: false,
+            perceptual_hash_distance: 4,
+            oscillation_hops: 0,
+            oscillation_window_ms: 10_000,
+            cooldown_ms: 30_000,
+            text_window_ms: None,
+            image_window_ms: Some(2000),
+            files_window_ms: None,
+            text_max_history: None,
+            image_max_history: None,
+            files_max_history: None,
+            long_horizon_ms: 0,
+            long_horizon_slots: 4096,
+            adaptive_window: false,
+            adaptive_window_min_ms: 100,
+            adaptive_window_max_ms: 5000,
         }
     }
 }
@@ -56,36 +159,454 @@ impl LoopDetectionConfig {
             ..Default::default()
         }
     }
+
+    /// Detection window for `class`, falling back to `window_ms`
+    pub fn window(&self, class: ContentClass) -> Duration {
+        let window_ms = match class {
+            ContentClass::Text => self.text_window_ms,
+            ContentClass::Image => self.image_window_ms,
+            ContentClass::Files => self.files_window_ms,
+        };
+        Duration::from_millis(window_ms.unwrap_or(self.window_ms))
+    }
+
+    /// Operations tracked for `class`, falling back to `max_history`
+    pub fn max_history(&self, class: ContentClass) -> usize {
+        let max_history = match class {
+            ContentClass::Text => self.text_max_history,
+            ContentClass::Image => self.image_max_history,
+            ContentClass::Files => self.files_max_history,
+        };
+        max_history.unwrap_or(self.max_history)
+    }
+}
+
+/// Kind of clipboard content, for per-class detection windows
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "serde", serde(rename_all = "snake_case"))]
+pub enum ContentClass {
+    /// Text and anything not classified otherwise
+    #[default]
+    Text,
+    /// Bitmaps, encoded images and metafiles
+    Image,
+    /// File lists
+    Files,
+}
+
+impl ContentClass {
+    /// Every class
+    pub const ALL: [Self; 3] = [Self::Text, Self::Image, Self::Files];
+
+    /// Classify a Windows format list; files win over images, images over text
+    pub fn of_formats(formats: &[ClipboardFormat]) -> Self {
+        let is_files =
+            |format: &ClipboardFormat| format.id == CF_HDROP || format.name.as_deref() == Some("FileGroupDescriptorW");
+        let is_image = |format: &ClipboardFormat| {
+            matches!(
+                format.id,
+                2 | CF_DIB | CF_DIBV5 | CF_TIFF | CF_DSPBITMAP | CF_METAFILEPICT | CF_ENHMETAFILE
+            ) || format
+                .name
+                .as_deref()
+                .is_some_and(|name| matches!(name, "PNG" | "JFIF" | "GIF" | "WebP") || name.starts_with("image/"))
+        };
+
+        if formats.iter().any(is_files) {
+            Self::Files
+        } else if formats.iter().any(is_image) {
+            Self::Image
+        } else {
+            Self::Text
+        }
+    }
+
+    /// Classify a MIME type list; files win over images, images over text
+    pub fn of_mime_types(mime_types: &[String]) -> Self {
+        if mime_types
+            .iter()
+            .any(|mime| matches!(mime.as_str(), "text/uri-list" | "x-special/gnome-copied-files"))
+        {
+            Self::Files
+        } else if mime_types.iter().any(|mime| mime.starts_with("image/")) {
+            Self::Image
+        } else {
+            Self::Text
+        }
+    }
+
+    /// Classify clipboard data by its leading bytes
+    ///
+    /// Recognizes PNG, JPEG, GIF, BMP, WebP and DIB headers; everything
+    /// else counts as text.
+    pub fn of_content(data: &[u8]) -> Self {
+        let dib_header = data
+            .get(..4)
+            .map(|size| u32::from_le_bytes([size[0], size[1], size[2], size[3]]));
+        let is_image = data.starts_with(b"\x89PNG\r\n\x1a\n")
+            || data.starts_with(&[0xFF, 0xD8, 0xFF])
+            || data.starts_with(b"GIF8")
+            || data.starts_with(b"BM")
+            || (data.starts_with(b"RIFF") && data.get(8..12) == Some(b"WEBP"))
+            || (matches!(dib_header, Some(40 | 108 | 124)) && data.len() >= 40);
+        if is_image {
+            Self::Image
+        } else {
+            Self::Text
+        }
+    }
+
+    fn index(self) -> usize {
+        self as usize
+    }
+}
+
+/// Hash function used to compare formats and content
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "serde", serde(rename_all = "snake_case"))]
+pub enum HashAlgorithm {
+    /// 128-bit XXH3, non-cryptographic and many times faster than SHA-256
+    /// on multi-megabyte images
+    #[default]
+    Xxh3,
+
+    /// SHA-256, for deployments where the peer may craft colliding content
+    Sha256,
+}
+
+impl HashAlgorithm {
+    /// Every algorithm, in configuration order
+    pub const ALL: [Self; 2] = [Self::Xxh3, Self::Sha256];
+
+    /// Name used in configuration files
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Self::Xxh3 => "xxh3",
+            Self::Sha256 => "sha256",
+        }
+    }
+
+    fn hasher(self) -> Hasher {
+        match self {
+            Self::Xxh3 => Hasher::Xxh3(Box::new(Xxh3::new())),
+            Self::Sha256 => Hasher::Sha256(Sha256::new()),
+        }
+    }
+}
+
+impl fmt::Display for HashAlgorithm {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+impl FromStr for HashAlgorithm {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Self::ALL
+            .into_iter()
+            .find(|algorithm| algorithm.as_str().eq_ignore_ascii_case(s.trim()))
+            .ok_or_else(|| format!("unknown hash algorithm '{}'", s))
+    }
+}
+
+/// Incremental state of a [`HashAlgorithm`]
+enum Hasher {
+    Xxh3(Box<Xxh3>),
+    Sha256(Sha256),
+}
+
+impl Hasher {
+    fn update(&mut self, bytes: &[u8]) {
+        match self {
+            Self::Xxh3(hasher) => hasher.update(bytes),
+            Self::Sha256(hasher) => hasher.update(bytes),
+        }
+    }
+
+    fn finish(self) -> String {
+        match self {
+            Self::Xxh3(hasher) => format!("{:032x}", hasher.digest128()),
+            Self::Sha256(hasher) => format!("{:x}", hasher.finalize()),
+        }
+    }
 }
 
 /// Source of a clipboard operation
-#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+///
+/// A payload reported by one source is an echo if it was recently recorded
+/// from any other source, so relays with several peers work the same way as
+/// the usual RDP/local pair.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "serde", serde(rename_all = "snake_case"))]
 pub enum ClipboardSource {
     /// Operation from RDP client
     Rdp,
     /// Operation from local clipboard (Portal, X11, etc.)
     Local,
+    /// Operation from an additional source, such as a second RDP session in
+    /// a relay (see [`LoopDetector::register_source`])
+    Other(u32),
 }
 
 impl ClipboardSource {
     /// Get the opposite source
+    ///
+    /// Additional sources are remote peers, opposite the local clipboard.
     pub fn opposite(self) -> Self {
         match self {
-            Self::Rdp => Self::Local,
+            Self::Rdp | Self::Other(_) => Self::Local,
             Self::Local => Self::Rdp,
         }
     }
 }
 
+/// Ownership token stamped on an outbound sync
+///
+/// Generations count up per side from 1. A change event carrying a
+/// generation up to the latest one stamped for its side was caused by one
+/// of our own syncs.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "serde", serde(transparent))]
+pub struct Generation(pub u64);
+
+/// What a detected loop matched on
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "serde", serde(rename_all = "snake_case"))]
+pub enum LoopKind {
+    /// A format or MIME type list
+    Formats,
+    /// Clipboard content
+    Content,
+    /// An image with a matching perceptual hash but different bytes
+    Image,
+    /// A change event carrying one of our own sync generations
+    Generation,
+    /// Content bouncing between the sides over several hops; sync is paused
+    Oscillation,
+    /// Content seen before the window, matched by the long-horizon filter
+    Repeated,
+}
+
+/// A sync suppressed because it echoed a recent operation
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct LoopEvent {
+    /// Whether formats or content matched
+    pub kind: LoopKind,
+
+    /// Side whose sync was suppressed
+    pub source: ClipboardSource,
+
+    /// Hash of the echoed formats or content
+    pub hash: String,
+
+    /// Time since the original operation was recorded; for oscillations,
+    /// the time the alternating hops took, and zero for repeats matched by
+    /// the long-horizon filter
+    pub latency: Duration,
+}
+
+/// Counters describing loop detection
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct LoopStats {
+    /// Checks that found an echo of a recent operation
+    pub loops_prevented: u64,
+
+    /// Operations recorded from the RDP side
+    pub rdp_operations: u64,
+
+    /// Operations recorded from the local side
+    pub local_operations: u64,
+
+    /// Operations recorded from additional sources
+    pub other_operations: u64,
+
+    /// Mean time between an operation and the echo that matched it, if any
+    /// loop was prevented
+    pub average_hit_latency: Option<Duration>,
+
+    /// Times the circuit breaker paused sync after an oscillation
+    pub breaker_trips: u64,
+
+    /// 95th percentile of the reported sync latencies, once enough are
+    /// reported
+    pub sync_latency_p95: Option<Duration>,
+}
+
+type LoopCallback = dyn Fn(&LoopEvent) + Send + Sync;
+
+/// Loop detector history saved across a reconnect
+///
+/// Ages are relative to when the state was saved, so time spent
+/// disconnected does not count toward the detection window: the Format
+/// Lists both sides re-announce right after a reconnect still match the
+/// content from before it.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "serde", serde(default))]
+pub struct LoopDetectorState {
+    /// Algorithm the hashes were computed with
+    pub hash_algorithm: HashAlgorithm,
+
+    /// Recent format and MIME type list operations, oldest first
+    pub formats: Vec<RecordedOperation>,
+
+    /// Recent content operations, oldest first
+    pub content: Vec<RecordedOperation>,
+
+    /// Latest generation stamped for the RDP side (0: none)
+    pub rdp_generation: u64,
+
+    /// Latest generation stamped for the local side (0: none)
+    pub local_generation: u64,
+
+    /// Latest generation stamped for each additional source
+    pub other_generations: BTreeMap<u32, u64>,
+}
+
+/// One operation in a [`LoopDetectorState`]
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct RecordedOperation {
+    /// Hash of the formats or content
+    pub hash: String,
+
+    /// Perceptual hash of image content, if computed
+    pub image_hash: Option<u64>,
+
+    /// Side the operation came from
+    pub source: ClipboardSource,
+
+    /// Age of the operation when the state was saved
+    pub age: Duration,
+
+    /// Content class whose window and history applied
+    #[cfg_attr(feature = "serde", serde(default))]
+    pub class: ContentClass,
+}
+
 /// A recorded clipboard operation for loop detection
 #[derive(Debug, Clone)]
 struct ClipboardOperation {
     /// Hash of the operation (formats or content)
     hash: String,
+    /// Perceptual hash, for image content when enabled
+    image_hash: Option<u64>,
     /// Source of the operation
     source: ClipboardSource,
-    /// When the operation occurred
-    timestamp: Instant,
+    /// When the operation occurred, in clock time
+    timestamp: Duration,
+}
+
+/// Recent operations, one queue per [`ContentClass`]
+type History = [RwLock<VecDeque<ClipboardOperation>>; 3];
+
+/// Sync latencies kept for the adaptive window
+const LATENCY_SAMPLES: usize = 64;
+
+/// Sync latencies needed before the window adapts
+const MIN_LATENCY_SAMPLES: usize = 8;
+
+/// Filter counters each insertion adds, in decay ticks
+const BLOOM_LIFETIME: u8 = 8;
+
+/// Counter positions per key
+const BLOOM_HASHES: u64 = 4;
+
+/// Counting Bloom filter whose counters decay over time
+///
+/// An insertion raises the key's counters by [`BLOOM_LIFETIME`] ticks, and
+/// every tick lowers all counters by one, so a key lingers for about
+/// `horizon` after its last insertion.
+#[derive(Debug)]
+struct DecayingBloom {
+    counters: Vec<u8>,
+    tick: Duration,
+    last_decay: Duration,
+}
+
+impl DecayingBloom {
+    fn new(slots: usize, horizon: Duration, now: Duration) -> Self {
+        Self {
+            counters: vec![0; slots.max(1)],
+            tick: (horizon / u32::from(BLOOM_LIFETIME)).max(Duration::from_nanos(1)),
+            last_decay: now,
+        }
+    }
+
+    fn decay(&mut self, now: Duration) {
+        let ticks = now.saturating_sub(self.last_decay).as_nanos() / self.tick.as_nanos();
+        if ticks == 0 {
+            return;
+        }
+        let ticks = u8::try_from(ticks).unwrap_or(u8::MAX);
+        for counter in &mut self.counters {
+            *counter = counter.saturating_sub(ticks);
+        }
+        self.last_decay = if ticks == u8::MAX {
+            now
+        } else {
+            self.last_decay + self.tick * u32::from(ticks)
+        };
+    }
+
+    /// Counter positions of `key`, by double hashing
+    fn positions(&self, key: &[u8]) -> impl Iterator<Item = usize> {
+        let hash = xxh3_128(key);
+        let (first, second) = (hash as u64, (hash >> 64) as u64 | 1);
+        let len = self.counters.len() as u64;
+        (0..BLOOM_HASHES).map(move |i| (first.wrapping_add(i.wrapping_mul(second)) % len) as usize)
+    }
+
+    fn insert(&mut self, key: &[u8], now: Duration) {
+        self.decay(now);
+        for position in self.positions(key).collect::<Vec<_>>() {
+            let counter = &mut self.counters[position];
+            *counter = counter.saturating_add(BLOOM_LIFETIME).min(2 * BLOOM_LIFETIME);
+        }
+    }
+
+    fn contains(&mut self, key: &[u8], now: Duration) -> bool {
+        self.decay(now);
+        self.positions(key).all(|position| self.counters[position] > 0)
+    }
+}
+
+/// Filter key for content hashed as `hash` from `source`
+fn bloom_key(hash: &str, source: ClipboardSource) -> Vec<u8> {
+    let (tag, id) = match source {
+        ClipboardSource::Rdp => (0, 0),
+        ClipboardSource::Local => (1, 0),
+        ClipboardSource::Other(id) => (2, id),
+    };
+    let mut key = vec![tag];
+    key.extend_from_slice(&id.to_le_bytes());
+    key.extend_from_slice(hash.as_bytes());
+    key
+}
+
+/// Rate limit, generation and operation counters of one source
+#[derive(Debug, Default)]
+struct SourceSlots {
+    /// Last sync time for rate limiting, in clock nanoseconds plus one; zero
+    /// means no sync yet
+    last_sync: AtomicU64,
+
+    /// Latest generation stamped with this source as target, and when
+    /// (clock nanoseconds)
+    generation: AtomicU64,
+    generation_time: AtomicU64,
+
+    /// Operations recorded from this source
+    operations: AtomicU64,
 }
 
 /// Detects and prevents clipboard synchronization loops.
@@ -107,6 +628,20 @@ struct ClipboardOperation {
 /// 3. **Time windowing**: Only detects loops within a configurable time window
 /// 4. **Source tracking**: Distinguishes RDP vs local operations
 /// 5. **Rate limiting**: Optional throttle to prevent rapid sync storms
+/// 6. **Generations**: Optional ownership tokens for backends that can tag
+///    the change events caused by their own writes
+///
+/// Text, images and file lists each get their own window and history (see
+/// [`ContentClass`]): an image echo can arrive seconds after the copy, while
+/// a text window that long would swallow legitimate rapid copies.
+///
+/// # Sharing
+///
+/// All methods take `&self`, and the detector is `Send + Sync`: the CLIPRDR
+/// backend and the sink's change listener can share one through an `Arc`.
+/// Format and content histories sit behind separate read-write locks, so
+/// checks run concurrently and only recording takes a short write lock;
+/// rate-limit timestamps are atomics.
 ///
 /// # Example
 ///
@@ -114,7 +649,7 @@ struct ClipboardOperation {
 /// use lamco_clipboard_core::{LoopDetector, ClipboardFormat};
 /// use lamco_clipboard_core::loop_detector::ClipboardSource;
 ///
-/// let mut detector = LoopDetector::new();
+/// let detector = LoopDetector::new();
 ///
 /// // Record an RDP operation
 /// let formats = vec![ClipboardFormat::unicode_text()];
@@ -125,20 +660,85 @@ struct ClipboardOperation {
 ///     println!("Loop detected, skipping sync");
 /// }
 /// ```
-#[derive(Debug)]
+///
+/// # Monitoring
+///
+/// [`stats()`](Self::stats) counts operations and prevented loops, and
+/// [`on_loop_detected()`](Self::on_loop_detected) reports each suppressed
+/// echo, which helps tell real loops from false positives:
+///
+/// ```rust
+/// use lamco_clipboard_core::{LoopDetector, ClipboardFormat};
+/// use lamco_clipboard_core::loop_detector::ClipboardSource;
+/// use std::sync::mpsc;
+///
+/// let (tx, rx) = mpsc::channel();
+/// let detector = LoopDetector::new().on_loop_detected(move |event| {
+///     let _ = tx.send(event.clone());
+/// });
+///
+/// let formats = vec![ClipboardFormat::unicode_text()];
+/// detector.record_formats(&formats, ClipboardSource::Rdp);
+/// assert!(detector.would_cause_loop(&formats));
+///
+/// assert_eq!(rx.try_recv().unwrap().source, ClipboardSource::Local);
+/// assert_eq!(detector.stats().loops_prevented, 1);
+/// ```
 pub struct LoopDetector {
     /// Configuration
     config: LoopDetectionConfig,
 
     /// Recent format operations
-    format_history: VecDeque<ClipboardOperation>,
+    format_history: History,
 
     /// Recent content hashes
-    content_history: VecDeque<ClipboardOperation>,
+    content_history: History,
+
+    /// Time source for windows, rate limits and latencies
+    clock: Arc<dyn Clock>,
+
+    /// Per-source rate limits, generations and counters; additional
+    /// sources are added on first use
+    rdp: Arc<SourceSlots>,
+    local: Arc<SourceSlots>,
+    others: RwLock<HashMap<u32, Arc<SourceSlots>>>,
 
-    /// Last sync time for rate limiting (per source)
-    last_sync_rdp: Option<Instant>,
-    last_sync_local: Option<Instant>,
+    /// Names of additional sources, indexed by their number
+    source_names: RwLock<Vec<String>>,
+
+    /// Long-horizon content filter, when enabled
+    long_horizon: Mutex<Option<DecayingBloom>>,
+
+    /// Recent sync latencies, oldest first, and their 95th percentile in
+    /// nanoseconds plus one; zero until enough are reported
+    latencies: Mutex<VecDeque<Duration>>,
+    latency_p95: AtomicU64,
+
+    /// Recent format list hops for oscillation detection, oldest first
+    hops: Mutex<VecDeque<(ClipboardSource, String, Duration)>>,
+
+    /// Clock nanoseconds plus one until which sync is paused; zero when the
+    /// circuit breaker is closed
+    paused_until: AtomicU64,
+
+    /// Statistics counters
+    loops_prevented: AtomicU64,
+    breaker_trips: AtomicU64,
+    hit_latency_nanos: AtomicU64,
+
+    /// Called for every detected loop
+    on_loop: Option<Box<LoopCallback>>,
+}
+
+impl fmt::Debug for LoopDetector {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("LoopDetector")
+            .field("config", &self.config)
+            .field("format_history", &self.format_history)
+            .field("content_history", &self.content_history)
+            .field("stats", &self.stats())
+            .finish_non_exhaustive()
+    }
 }
 
 impl Default for LoopDetector {
@@ -157,89 +757,393 @@ impl LoopDetector {
     pub fn with_config(config: LoopDetectionConfig) -> Self {
         Self {
             config,
-            format_history: VecDeque::new(),
-            content_history: VecDeque::new(),
-            last_sync_rdp: None,
-            last_sync_local: None,
+            format_history: History::default(),
+            content_history: History::default(),
+            clock: Arc::new(SystemClock),
+            rdp: Arc::default(),
+            local: Arc::default(),
+            others: RwLock::default(),
+            source_names: RwLock::default(),
+            long_horizon: Mutex::default(),
+            latencies: Mutex::default(),
+            latency_p95: AtomicU64::new(0),
+            hops: Mutex::default(),
+            paused_until: AtomicU64::new(0),
+            loops_prevented: AtomicU64::new(0),
+            breaker_trips: AtomicU64::new(0),
+            hit_latency_nanos: AtomicU64::new(0),
+            on_loop: None,
+        }
+    }
+
+    /// Read time from `clock` instead of the system clock
+    pub fn with_clock(mut self, clock: impl Clock + 'static) -> Self {
+        self.clock = Arc::new(clock);
+        self
+    }
+
+    /// Call `callback` for every detected loop
+    ///
+    /// The callback runs on the thread performing the check and must not
+    /// block; forward events to a channel to consume them as a stream.
+    pub fn on_loop_detected(mut self, callback: impl Fn(&LoopEvent) + Send + Sync + 'static) -> Self {
+        self.on_loop = Some(Box::new(callback));
+        self
+    }
+
+    /// Operation and prevented-loop counts since creation or the last reset
+    pub fn stats(&self) -> LoopStats {
+        let loops_prevented = self.loops_prevented.load(Ordering::Relaxed);
+        let average_hit_latency = (loops_prevented > 0)
+            .then(|| Duration::from_nanos(self.hit_latency_nanos.load(Ordering::Relaxed) / loops_prevented));
+        LoopStats {
+            loops_prevented,
+            rdp_operations: self.rdp.operations.load(Ordering::Relaxed),
+            local_operations: self.local.operations.load(Ordering::Relaxed),
+            other_operations: read(&self.others)
+                .values()
+                .map(|slots| slots.operations.load(Ordering::Relaxed))
+                .sum(),
+            average_hit_latency,
+            breaker_trips: self.breaker_trips.load(Ordering::Relaxed),
+            sync_latency_p95: self.sync_latency_p95(),
+        }
+    }
+
+    /// Report an end-to-end sync latency, e.g. from a Format List
+    /// announcement to the resulting write on the other side
+    ///
+    /// Drives the window when `adaptive_window` is set: a fixed 500 ms is
+    /// too short for the echo to arrive over a high-latency link, and long
+    /// enough on a LAN to swallow legitimate rapid copies.
+    pub fn record_sync_latency(&self, latency: Duration) {
+        let mut latencies = lock(&self.latencies);
+        latencies.push_back(latency);
+        if latencies.len() > LATENCY_SAMPLES {
+            latencies.pop_front();
+        }
+        if latencies.len() < MIN_LATENCY_SAMPLES {
+            return;
+        }
+
+        let mut sorted: Vec<Duration> = latencies.iter().copied().collect();
+        sorted.sort_unstable();
+        let p95 = sorted[(sorted.len() * 95).div_ceil(100) - 1];
+        let nanos = u64::try_from(p95.as_nanos()).unwrap_or(u64::MAX - 1);
+        self.latency_p95.store(nanos + 1, Ordering::Relaxed);
+    }
+
+    /// Detection window currently applied to `class`
+    ///
+    /// The configured window, or the adaptive one if enabled and `class`
+    /// has no window of its own.
+    pub fn window(&self, class: ContentClass) -> Duration {
+        let has_own_window = match class {
+            ContentClass::Text => self.config.text_window_ms.is_some(),
+            ContentClass::Image => self.config.image_window_ms.is_some(),
+            ContentClass::Files => self.config.files_window_ms.is_some(),
+        };
+        let min = Duration::from_millis(self.config.adaptive_window_min_ms);
+        let max = Duration::from_millis(self.config.adaptive_window_max_ms).max(min);
+        match self.sync_latency_p95() {
+            Some(p95) if self.config.adaptive_window && !has_own_window => (p95 * 2).clamp(min, max),
+            _ => self.config.window(class),
+        }
+    }
+
+    /// Zero all statistics counters
+    pub fn reset_stats(&self) {
+        self.loops_prevented.store(0, Ordering::Relaxed);
+        self.breaker_trips.store(0, Ordering::Relaxed);
+        self.hit_latency_nanos.store(0, Ordering::Relaxed);
+        self.rdp.operations.store(0, Ordering::Relaxed);
+        self.local.operations.store(0, Ordering::Relaxed);
+        for slots in read(&self.others).values() {
+            slots.operations.store(0, Ordering::Relaxed);
         }
     }
 
     /// Record a format list operation
-    pub fn record_formats(&mut self, formats: &[ClipboardFormat], source: ClipboardSource) {
-        let hash = Self::hash_formats(formats);
-        self.record_operation(&mut self.format_history.clone(), hash, source);
-        // Need to work around borrow checker
-        let hash = Self::hash_formats(formats);
-        self.format_history.push_back(ClipboardOperation {
-            hash,
-            source,
-            timestamp: Instant::now(),
-        });
-        self.cleanup_history();
+    pub fn record_formats(&self, formats: &[ClipboardFormat], source: ClipboardSource) {
+        let hash = self.hash_formats(formats);
+        self.track_oscillation(&hash, source);
+        let class = ContentClass::of_formats(formats);
+        self.record_operation(&self.format_history, class, hash, None, source);
     }
 
     /// Record a MIME type list operation
-    pub fn record_mime_types(&mut self, mime_types: &[String], source: ClipboardSource) {
-        let hash = Self::hash_mime_types(mime_types);
-        self.format_history.push_back(ClipboardOperation {
-            hash,
-            source,
-            timestamp: Instant::now(),
-        });
-        self.cleanup_history();
+    pub fn record_mime_types(&self, mime_types: &[String], source: ClipboardSource) {
+        let hash = self.hash_mime_types(mime_types);
+        self.track_oscillation(&hash, source);
+        let class = ContentClass::of_mime_types(mime_types);
+        self.record_operation(&self.format_history, class, hash, None, source);
     }
 
     /// Record content data for deduplication
-    pub fn record_content(&mut self, data: &[u8], source: ClipboardSource) {
+    ///
+    /// The class is guessed from the data with [`ContentClass::of_content`].
+    pub fn record_content(&self, data: &[u8], source: ClipboardSource) {
+        self.record_content_as(data, ContentClass::of_content(data), source);
+    }
+
+    /// Record content data of a known class for deduplication
+    pub fn record_content_as(&self, data: &[u8], class: ContentClass, source: ClipboardSource) {
         if !self.config.enable_content_hashing {
             return;
         }
 
-        let hash = Self::hash_content(data);
-        self.content_history.push_back(ClipboardOperation {
-            hash,
-            source,
-            timestamp: Instant::now(),
-        });
-        self.cleanup_history();
+        let hash = self.hash_content(data);
+        let image_hash = self.image_hash(data);
+        self.record_content_hash(class, hash, image_hash, source);
     }
 
     /// Check if syncing these formats would cause a loop
     ///
-    /// Returns true if a recent operation from the opposite source
-    /// had the same format hash.
+    /// Returns true if a recent operation from another source had the same
+    /// format hash.
     pub fn would_cause_loop(&self, formats: &[ClipboardFormat]) -> bool {
-        let hash = Self::hash_formats(formats);
-        self.check_hash_collision(&self.format_history, &hash, ClipboardSource::Local)
+        let hash = self.hash_formats(formats);
+        self.check_hash_collision(
+            &self.format_history,
+            ContentClass::of_formats(formats),
+            LoopKind::Formats,
+            hash,
+            None,
+            ClipboardSource::Local,
+        )
     }
 
     /// Check if syncing these MIME types would cause a loop
     pub fn would_cause_loop_mime(&self, mime_types: &[String]) -> bool {
-        let hash = Self::hash_mime_types(mime_types);
-        self.check_hash_collision(&self.format_history, &hash, ClipboardSource::Rdp)
+        let hash = self.hash_mime_types(mime_types);
+        self.check_hash_collision(
+            &self.format_history,
+            ContentClass::of_mime_types(mime_types),
+            LoopKind::Formats,
+            hash,
+            None,
+            ClipboardSource::Rdp,
+        )
     }
 
     /// Check if this content would cause a loop
     pub fn would_cause_content_loop(&self, data: &[u8], source: ClipboardSource) -> bool {
+        self.would_cause_content_loop_as(data, ContentClass::of_content(data), source)
+    }
+
+    /// Check if content of a known class would cause a loop
+    pub fn would_cause_content_loop_as(&self, data: &[u8], class: ContentClass, source: ClipboardSource) -> bool {
         if !self.config.enable_content_hashing {
             return false;
         }
 
-        let hash = Self::hash_content(data);
-        self.check_hash_collision(&self.content_history, &hash, source)
+        let hash = self.hash_content(data);
+        self.check_content_hash(class, hash, self.image_hash(data), source)
+    }
+
+    /// Record an operation identified by a precomputed hash
+    ///
+    /// For event sources the detector has no hashing for, such as drag and
+    /// drop or a custom virtual channel. Goes through the same path as
+    /// [`record_content_as()`](Self::record_content_as): older entries of
+    /// `class` beyond twice its window are pruned, then the oldest are
+    /// evicted down to its `max_history`. Hashes only match hashes recorded
+    /// the same way, so compute them with [`hash()`](Self::hash) to also
+    /// match recorded content.
+    pub fn record_hash(&self, hash: impl Into<String>, class: ContentClass, source: ClipboardSource) {
+        self.record_content_hash(class, hash.into(), None, source);
+    }
+
+    /// Check if an operation identified by a precomputed hash would cause a
+    /// loop
+    ///
+    /// Returns true if an operation with the same hash and class was recorded
+    /// from another source within the class's window.
+    pub fn would_cause_hash_loop(&self, hash: &str, class: ContentClass, source: ClipboardSource) -> bool {
+        self.check_content_hash(class, hash.to_string(), None, source)
+    }
+
+    /// Source this content was most recently recorded from, if within its
+    /// class's window
+    ///
+    /// A relay uses this to avoid sending a payload back to the peer it came
+    /// from.
+    pub fn last_source_of(&self, data: &[u8]) -> Option<ClipboardSource> {
+        let class = ContentClass::of_content(data);
+        let window = self.window(class);
+        let now = self.clock.now();
+        let hash = self.hash_content(data);
+
+        read(&self.content_history[class.index()])
+            .iter()
+            .rev()
+            .take_while(|op| now.saturating_sub(op.timestamp) <= window)
+            .find(|op| op.hash == hash)
+            .map(|op| op.source)
+    }
+
+    /// Name an additional source, returning its [`ClipboardSource::Other`]
+    ///
+    /// Registering the same name again returns the same source.
+    pub fn register_source(&self, name: &str) -> ClipboardSource {
+        let mut names = write(&self.source_names);
+        let id = match names.iter().position(|known| known == name) {
+            Some(id) => id,
+            None => {
+                names.push(name.to_string());
+                names.len() - 1
+            }
+        };
+        ClipboardSource::Other(u32::try_from(id).unwrap_or(u32::MAX))
+    }
+
+    /// Name of a source, for logs: `rdp`, `local`, or the registered name
+    pub fn source_name(&self, source: ClipboardSource) -> String {
+        match source {
+            ClipboardSource::Rdp => "rdp".to_string(),
+            ClipboardSource::Local => "local".to_string(),
+            ClipboardSource::Other(id) => read(&self.source_names)
+                .get(id as usize)
+                .cloned()
+                .unwrap_or_else(|| format!("source {}", id)),
+        }
     }
 
     /// Compute hash for deduplication of arbitrary data
+    ///
+    /// Always SHA-256; [`hash()`](Self::hash) uses the configured algorithm.
     pub fn compute_hash(data: &[u8]) -> String {
-        Self::hash_content(data)
+        let mut hasher = HashAlgorithm::Sha256.hasher();
+        hasher.update(data);
+        hasher.finish()
+    }
+
+    /// Hash data with the configured [`HashAlgorithm`], as the detector
+    /// does for content
+    pub fn hash(&self, data: &[u8]) -> String {
+        self.hash_content(data)
+    }
+
+    /// Save the recent history, e.g. before an RDP auto-reconnect
+    pub fn save_state(&self) -> LoopDetectorState {
+        let now = self.clock.now();
+        let save = |history: &History| {
+            let mut saved: Vec<_> = ContentClass::ALL
+                .into_iter()
+                .flat_map(|class| {
+                    read(&history[class.index()])
+                        .iter()
+                        .map(|op| RecordedOperation {
+                            hash: op.hash.clone(),
+                            image_hash: op.image_hash,
+                            source: op.source,
+                            age: now.saturating_sub(op.timestamp),
+                            class,
+                        })
+                        .collect::<Vec<_>>()
+                })
+                .collect();
+            saved.sort_by_key(|op| std::cmp::Reverse(op.age));
+            saved
+        };
+
+        LoopDetectorState {
+            hash_algorithm: self.config.hash_algorithm,
+            formats: save(&self.format_history),
+            content: save(&self.content_history),
+            rdp_generation: self.rdp.generation.load(Ordering::Relaxed),
+            local_generation: self.local.generation.load(Ordering::Relaxed),
+            other_generations: read(&self.others)
+                .iter()
+                .map(|(&id, slots)| (id, slots.generation.load(Ordering::Relaxed)))
+                .filter(|&(_, generation)| generation > 0)
+                .collect(),
+        }
+    }
+
+    /// Replace the history with one saved by [`save_state()`](Self::save_state)
+    ///
+    /// History hashed with a different algorithm cannot match and is
+    /// dropped; generations only move forward.
+    pub fn restore_state(&self, state: LoopDetectorState) {
+        let now = self.clock.now();
+        let restore = |history: &History, saved: Vec<RecordedOperation>| {
+            for queue in history {
+                write(queue).clear();
+            }
+            if state.hash_algorithm != self.config.hash_algorithm {
+                return;
+            }
+            for op in saved {
+                let mut queue = write(&history[op.class.index()]);
+                queue.push_back(ClipboardOperation {
+                    hash: op.hash,
+                    image_hash: op.image_hash,
+                    source: op.source,
+                    timestamp: now.saturating_sub(op.age),
+                });
+                while queue.len() > self.config.max_history(op.class) {
+                    queue.pop_front();
+                }
+            }
+        };
+
+        if state.hash_algorithm != self.config.hash_algorithm {
+            tracing::debug!(
+                "Dropping saved loop history hashed with {}, detector uses {}",
+                state.hash_algorithm,
+                self.config.hash_algorithm
+            );
+        }
+        restore(&self.format_history, state.formats);
+        restore(&self.content_history, state.content);
+        self.rdp.generation.fetch_max(state.rdp_generation, Ordering::Relaxed);
+        self.local
+            .generation
+            .fetch_max(state.local_generation, Ordering::Relaxed);
+        for (id, generation) in state.other_generations {
+            self.slots(ClipboardSource::Other(id))
+                .generation
+                .fetch_max(generation, Ordering::Relaxed);
+        }
+    }
+
+    /// Number of format and content operations currently remembered
+    ///
+    /// Bounded by `max_history` per kind and content class once old entries
+    /// are pruned.
+    pub fn history_len(&self) -> usize {
+        self.format_history
+            .iter()
+            .chain(&self.content_history)
+            .map(|history| read(history).len())
+            .sum()
     }
 
     /// Clear all history
-    pub fn clear(&mut self) {
-        self.format_history.clear();
-        self.content_history.clear();
-        self.last_sync_rdp = None;
-        self.last_sync_local = None;
+    pub fn clear(&self) {
+        for history in self.format_history.iter().chain(&self.content_history) {
+            write(history).clear();
+        }
+        self.rdp.last_sync.store(0, Ordering::Relaxed);
+        self.local.last_sync.store(0, Ordering::Relaxed);
+        for slots in read(&self.others).values() {
+            slots.last_sync.store(0, Ordering::Relaxed);
+        }
+        *lock(&self.long_horizon) = None;
+        self.resume();
+    }
+
+    /// Check if the circuit breaker is pausing sync after an oscillation
+    pub fn is_paused(&self) -> bool {
+        let paused_until = self.paused_until.load(Ordering::Relaxed);
+        paused_until != 0 && self.clock.now() < Duration::from_nanos(paused_until - 1)
+    }
+
+    /// Close the circuit breaker before its cool-down ends
+    pub fn resume(&self) {
+        self.paused_until.store(0, Ordering::Relaxed);
+        lock(&self.hops).clear();
     }
 
     /// Check if sync is rate limited for the given source
@@ -254,7 +1158,7 @@ impl LoopDetector {
     /// use lamco_clipboard_core::loop_detector::ClipboardSource;
     ///
     /// let config = LoopDetectionConfig::with_rate_limit(200);
-    /// let mut detector = LoopDetector::with_config(config);
+    /// let detector = LoopDetector::with_config(config);
     ///
     /// // First sync is not rate limited
     /// assert!(!detector.is_rate_limited(ClipboardSource::Rdp));
@@ -270,16 +1174,12 @@ impl LoopDetector {
             return false;
         };
 
-        let last_sync = match source {
-            ClipboardSource::Rdp => self.last_sync_rdp,
-            ClipboardSource::Local => self.last_sync_local,
-        };
-
-        let Some(last) = last_sync else {
+        let last_sync = self.slots(source).last_sync.load(Ordering::Relaxed);
+        if last_sync == 0 {
             return false;
-        };
+        }
 
-        let elapsed = last.elapsed();
+        let elapsed = self.clock.now().saturating_sub(Duration::from_nanos(last_sync - 1));
         elapsed < Duration::from_millis(rate_limit_ms)
     }
 
@@ -287,12 +1187,10 @@ impl LoopDetector {
     ///
     /// Call this after successfully syncing clipboard data to update
     /// the rate limiting timestamp.
-    pub fn record_sync(&mut self, source: ClipboardSource) {
-        let now = Instant::now();
-        match source {
-            ClipboardSource::Rdp => self.last_sync_rdp = Some(now),
-            ClipboardSource::Local => self.last_sync_local = Some(now),
-        }
+    pub fn record_sync(&self, source: ClipboardSource) {
+        self.slots(source)
+            .last_sync
+            .store(self.elapsed_nanos() + 1, Ordering::Relaxed);
     }
 
     /// Combined check: would cause loop OR is rate limited
@@ -300,6 +1198,11 @@ impl LoopDetector {
     /// Convenience method that checks both conditions. Returns true if
     /// the sync should be skipped for any reason.
     pub fn should_skip_sync(&self, formats: &[ClipboardFormat], source: ClipboardSource) -> bool {
+        if self.is_paused() {
+            tracing::debug!("Sync skipped: paused after oscillation");
+            return true;
+        }
+
         if self.is_rate_limited(source) {
             tracing::debug!("Sync skipped: rate limited for {:?}", source);
             return true;
@@ -308,6 +1211,14 @@ impl LoopDetector {
         let would_loop = match source {
             ClipboardSource::Rdp => self.would_cause_loop(formats),
             ClipboardSource::Local => self.would_cause_loop(formats),
+            ClipboardSource::Other(_) => self.check_hash_collision(
+                &self.format_history,
+                ContentClass::of_formats(formats),
+                LoopKind::Formats,
+                self.hash_formats(formats),
+                None,
+                source,
+            ),
         };
 
         if would_loop {
@@ -317,8 +1228,88 @@ impl LoopDetector {
         would_loop
     }
 
+    /// Stamp an outbound sync into `target`'s clipboard
+    ///
+    /// Hand the returned generation to the side being written, and have its
+    /// change event carry it back (see [`ClipboardChange::with_generation`]).
+    /// Generations survive [`clear()`](Self::clear), so late echoes are
+    /// still recognized.
+    ///
+    /// # Example
+    ///
+    /// ```rust
+    /// use lamco_clipboard_core::{ClipboardChange, LoopDetector};
+    /// use lamco_clipboard_core::loop_detector::ClipboardSource;
+    ///
+    /// let detector = LoopDetector::new();
+    ///
+    /// // Paste from Windows into the local clipboard
+    /// let generation = detector.begin_sync(ClipboardSource::Local);
+    ///
+    /// // The local backend reports the change its write caused
+    /// let echo = ClipboardChange::new(vec!["text/plain".into()]).with_generation(generation);
+    /// assert!(detector.should_skip_change(&echo));
+    ///
+    /// // A copy by the user carries no generation
+    /// let copy = ClipboardChange::new(vec!["text/html".into()]);
+    /// assert!(!detector.should_skip_change(&copy));
+    /// ```
+    ///
+    /// [`ClipboardChange::with_generation`]: crate::ClipboardChange::with_generation
+    pub fn begin_sync(&self, target: ClipboardSource) -> Generation {
+        let slots = self.slots(target);
+        slots.generation_time.store(self.elapsed_nanos(), Ordering::Relaxed);
+        Generation(slots.generation.fetch_add(1, Ordering::Relaxed) + 1)
+    }
+
+    /// Latest generation stamped for `target`, if any
+    pub fn generation(&self, target: ClipboardSource) -> Option<Generation> {
+        match self.slots(target).generation.load(Ordering::Relaxed) {
+            0 => None,
+            generation => Some(Generation(generation)),
+        }
+    }
+
+    /// Check whether a change event from `source` was caused by our own sync
+    ///
+    /// Deterministic: no time window or hashing is involved, and the check
+    /// is not counted as a prevented loop.
+    pub fn is_own_generation(&self, source: ClipboardSource, generation: Generation) -> bool {
+        self.generation(source)
+            .is_some_and(|latest| generation.0 > 0 && generation <= latest)
+    }
+
+    /// Check whether a local change event should not be synced to the peer
+    ///
+    /// Events carrying one of our own generations are always skipped;
+    /// others fall back to [`should_skip_sync_mime()`](Self::should_skip_sync_mime).
+    pub fn should_skip_change(&self, change: &crate::ClipboardChange) -> bool {
+        let Some(generation) = change.generation else {
+            return self.should_skip_sync_mime(&change.mime_types, ClipboardSource::Local);
+        };
+        if !self.is_own_generation(ClipboardSource::Local, generation) {
+            return self.should_skip_sync_mime(&change.mime_types, ClipboardSource::Local);
+        }
+
+        tracing::debug!("Sync skipped: change caused by our own sync {:?}", generation);
+        let stamped = Duration::from_nanos(self.local.generation_time.load(Ordering::Relaxed));
+        let latency = self.clock.now().saturating_sub(stamped);
+        self.report_loop(
+            LoopKind::Generation,
+            ClipboardSource::Local,
+            self.hash_mime_types(&change.mime_types),
+            latency,
+        );
+        true
+    }
+
     /// Combined check for MIME types: would cause loop OR is rate limited
     pub fn should_skip_sync_mime(&self, mime_types: &[String], source: ClipboardSource) -> bool {
+        if self.is_paused() {
+            tracing::debug!("Sync skipped: paused after oscillation");
+            return true;
+        }
+
         if self.is_rate_limited(source) {
             tracing::debug!("Sync skipped: rate limited for {:?}", source);
             return true;
@@ -337,103 +1328,277 @@ impl LoopDetector {
     // Private Methods
     // =========================================================================
 
+    fn slots(&self, source: ClipboardSource) -> Arc<SourceSlots> {
+        let id = match source {
+            ClipboardSource::Rdp => return Arc::clone(&self.rdp),
+            ClipboardSource::Local => return Arc::clone(&self.local),
+            ClipboardSource::Other(id) => id,
+        };
+        if let Some(slots) = read(&self.others).get(&id) {
+            return Arc::clone(slots);
+        }
+        Arc::clone(write(&self.others).entry(id).or_default())
+    }
+
+    fn sync_latency_p95(&self) -> Option<Duration> {
+        match self.latency_p95.load(Ordering::Relaxed) {
+            0 => None,
+            nanos => Some(Duration::from_nanos(nanos - 1)),
+        }
+    }
+
+    fn elapsed_nanos(&self) -> u64 {
+        u64::try_from(self.clock.now().as_nanos()).unwrap_or(u64::MAX - 1)
+    }
+
     fn check_hash_collision(
         &self,
-        history: &VecDeque<ClipboardOperation>,
-        hash: &str,
+        history: &History,
+        class: ContentClass,
+        kind: LoopKind,
+        hash: String,
+        image_hash: Option<u64>,
         current_source: ClipboardSource,
     ) -> bool {
-        let window = Duration::from_millis(self.config.window_ms);
-        let now = Instant::now();
+        let window = self.window(class);
+        let now = self.clock.now();
+        let same_image = |other: Option<u64>| match (image_hash, other) {
+            (Some(a), Some(b)) => (a ^ b).count_ones() <= self.config.perceptual_hash_distance,
+            _ => false,
+        };
 
-        for op in history.iter().rev() {
+        let found = read(&history[class.index()])
+            .iter()
+            .rev()
             // Only check recent operations
-            if now.duration_since(op.timestamp) > window {
-                break;
+            .take_while(|op| now.saturating_sub(op.timestamp) <= window)
+            // Only detect loops from another source
+            .filter(|op| op.source != current_source)
+            .find_map(|op| {
+                let kind = if op.hash == hash {
+                    kind
+                } else if same_image(op.image_hash) {
+                    LoopKind::Image
+                } else {
+                    return None;
+                };
+                Some((kind, now.saturating_sub(op.timestamp)))
+            });
+        let Some((kind, latency)) = found else {
+            return false;
+        };
+
+        // The history lock is released, so the callback may record operations
+        self.report_loop(kind, current_source, hash, latency);
+        true
+    }
+
+    fn record_content_hash(&self, class: ContentClass, hash: String, image_hash: Option<u64>, source: ClipboardSource) {
+        if self.config.long_horizon_ms > 0 {
+            let now = self.clock.now();
+            lock(&self.long_horizon)
+                .get_or_insert_with(|| {
+                    let horizon = Duration::from_millis(self.config.long_horizon_ms);
+                    DecayingBloom::new(self.config.long_horizon_slots, horizon, now)
+                })
+                .insert(&bloom_key(&hash, source), now);
+        }
+        self.record_operation(&self.content_history, class, hash, image_hash, source);
+    }
+
+    /// Check the precise history, then the long-horizon filter
+    fn check_content_hash(
+        &self,
+        class: ContentClass,
+        hash: String,
+        image_hash: Option<u64>,
+        source: ClipboardSource,
+    ) -> bool {
+        if self.check_hash_collision(
+            &self.content_history,
+            class,
+            LoopKind::Content,
+            hash.clone(),
+            image_hash,
+            source,
+        ) {
+            return true;
+        }
+
+        let repeated = {
+            let mut filter = lock(&self.long_horizon);
+            let Some(filter) = filter.as_mut() else {
+                return false;
+            };
+            let now = self.clock.now();
+            let others: Vec<u32> = read(&self.others).keys().copied().collect();
+            [ClipboardSource::Rdp, ClipboardSource::Local]
+                .into_iter()
+                .chain(others.into_iter().map(ClipboardSource::Other))
+                .filter(|&other| other != source)
+                .any(|other| filter.contains(&bloom_key(&hash, other), now))
+        };
+        if repeated {
+            tracing::debug!("Content repeated within the long horizon");
+            self.report_loop(LoopKind::Repeated, source, hash, Duration::ZERO);
+        }
+        repeated
+    }
+
+    fn report_loop(&self, kind: LoopKind, source: ClipboardSource, hash: String, latency: Duration) {
+        self.loops_prevented.fetch_add(1, Ordering::Relaxed);
+        let nanos = u64::try_from(latency.as_nanos()).unwrap_or(u64::MAX);
+        self.hit_latency_nanos.fetch_add(nanos, Ordering::Relaxed);
+        self.emit(kind, source, hash, latency);
+    }
+
+    /// Trip the circuit breaker once the last `oscillation_hops` format
+    /// lists alternate between the sides and each side keeps sending the
+    /// same list
+    fn track_oscillation(&self, hash: &str, source: ClipboardSource) {
+        let hops = self.config.oscillation_hops as usize;
+        if hops == 0 {
+            return;
+        }
+        let window = Duration::from_millis(self.config.oscillation_window_ms);
+        let now = self.clock.now();
+
+        let span = {
+            let mut log = lock(&self.hops);
+            log.push_back((source, hash.to_string(), now));
+            while log.len() > hops || log.front().is_some_and(|(_, _, at)| now.saturating_sub(*at) > window) {
+                log.pop_front();
             }
 
-            // Only detect loops from the opposite source
-            if op.source == current_source.opposite() && op.hash == hash {
-                return true;
+            let alternating = log.iter().zip(log.iter().skip(1)).all(|(a, b)| a.0 != b.0);
+            let repeating = log.iter().zip(log.iter().skip(2)).all(|(a, c)| a.1 == c.1);
+            if log.len() < hops || !alternating || !repeating {
+                return;
             }
-        }
+            let span = log.front().map_or(Duration::ZERO, |(_, _, at)| now.saturating_sub(*at));
+            log.clear();
+            span
+        };
 
-        false
+        let cooldown = Duration::from_millis(self.config.cooldown_ms);
+        let until = u64::try_from((now + cooldown).as_nanos()).unwrap_or(u64::MAX - 1);
+        self.paused_until.store(until + 1, Ordering::Relaxed);
+        self.breaker_trips.fetch_add(1, Ordering::Relaxed);
+        tracing::warn!(
+            "Clipboard oscillating between RDP and local over {} hops in {:?}; pausing sync for {:?}",
+            hops,
+            span,
+            cooldown
+        );
+        self.emit(LoopKind::Oscillation, source, hash.to_string(), span);
     }
 
-    fn record_operation(&mut self, history: &mut VecDeque<ClipboardOperation>, hash: String, source: ClipboardSource) {
+    fn emit(&self, kind: LoopKind, source: ClipboardSource, hash: String, latency: Duration) {
+        if let Some(on_loop) = &self.on_loop {
+            on_loop(&LoopEvent {
+                kind,
+                source,
+                hash,
+                latency,
+            });
+        }
+    }
+
+    fn record_operation(
+        &self,
+        history: &History,
+        class: ContentClass,
+        hash: String,
+        image_hash: Option<u64>,
+        source: ClipboardSource,
+    ) {
+        let window = self.window(class) * 2;
+        let now = self.clock.now();
+
+        self.slots(source).operations.fetch_add(1, Ordering::Relaxed);
+
+        let mut history = write(&history[class.index()]);
         history.push_back(ClipboardOperation {
             hash,
+            image_hash,
             source,
-            timestamp: Instant::now(),
+            timestamp: now,
         });
-    }
-
-    fn cleanup_history(&mut self) {
-        let window = Duration::from_millis(self.config.window_ms * 2);
-        let now = Instant::now();
 
         // Remove old entries
-        while let Some(front) = self.format_history.front() {
-            if now.duration_since(front.timestamp) > window {
-                self.format_history.pop_front();
-            } else {
-                break;
-            }
-        }
-
-        while let Some(front) = self.content_history.front() {
-            if now.duration_since(front.timestamp) > window {
-                self.content_history.pop_front();
+        while let Some(front) = history.front() {
+            if now.saturating_sub(front.timestamp) > window {
+                history.pop_front();
             } else {
                 break;
             }
         }
 
         // Enforce max history size
-        while self.format_history.len() > self.config.max_history {
-            self.format_history.pop_front();
+        while history.len() > self.config.max_history(class) {
+            history.pop_front();
         }
+    }
 
-        while self.content_history.len() > self.config.max_history {
-            self.content_history.pop_front();
+    #[cfg(feature = "image")]
+    fn image_hash(&self, data: &[u8]) -> Option<u64> {
+        if !self.config.perceptual_image_hashing {
+            return None;
         }
+        crate::image::perceptual_hash(data)
     }
 
-    fn hash_formats(formats: &[ClipboardFormat]) -> String {
-        let mut hasher = Sha256::new();
+    #[cfg(not(feature = "image"))]
+    fn image_hash(&self, _data: &[u8]) -> Option<u64> {
+        None
+    }
+
+    fn hash_formats(&self, formats: &[ClipboardFormat]) -> String {
+        let mut hasher = self.config.hash_algorithm.hasher();
         for format in formats {
-            hasher.update(format.id.to_le_bytes());
+            hasher.update(&format.id.to_le_bytes());
             if let Some(name) = &format.name {
                 hasher.update(name.as_bytes());
             }
         }
-        format!("{:x}", hasher.finalize())
+        hasher.finish()
     }
 
-    fn hash_mime_types(mime_types: &[String]) -> String {
-        let mut hasher = Sha256::new();
+    fn hash_mime_types(&self, mime_types: &[String]) -> String {
+        let mut hasher = self.config.hash_algorithm.hasher();
         for mime in mime_types {
             hasher.update(mime.as_bytes());
             hasher.update(b"\0");
         }
-        format!("{:x}", hasher.finalize())
+        hasher.finish()
     }
 
-    fn hash_content(data: &[u8]) -> String {
-        let mut hasher = Sha256::new();
+    fn hash_content(&self, data: &[u8]) -> String {
+        let mut hasher = self.config.hash_algorithm.hasher();
         hasher.update(data);
-        format!("{:x}", hasher.finalize())
+        hasher.finish()
     }
 }
 
+fn lock<T>(mutex: &Mutex<T>) -> MutexGuard<'_, T> {
+    mutex.lock().unwrap_or_else(|e| e.into_inner())
+}
+
+fn read<T>(lock: &RwLock<T>) -> RwLockReadGuard<'_, T> {
+    lock.read().unwrap_or_else(|e| e.into_inner())
+}
+
+fn write<T>(lock: &RwLock<T>) -> RwLockWriteGuard<'_, T> {
+    lock.write().unwrap_or_else(|e| e.into_inner())
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
 
     #[test]
     fn test_no_loop_different_formats() {
-        let mut detector = LoopDetector::new();
+        let detector = LoopDetector::new();
 
         let formats1 = vec![ClipboardFormat::unicode_text()];
         let formats2 = vec![ClipboardFormat::html()];
@@ -444,7 +1609,7 @@ mod tests {
 
     #[test]
     fn test_loop_same_formats() {
-        let mut detector = LoopDetector::new();
+        let detector = LoopDetector::new();
 
         let formats = vec![ClipboardFormat::unicode_text()];
 
@@ -454,7 +1619,7 @@ mod tests {
 
     #[test]
     fn test_no_loop_same_source() {
-        let mut detector = LoopDetector::new();
+        let detector = LoopDetector::new();
 
         let formats = vec![ClipboardFormat::unicode_text()];
 
@@ -465,17 +1630,17 @@ mod tests {
         // shouldn't trigger (opposite source check)
         // Actually would_cause_loop always checks against Local source
         // So this should NOT trigger because we recorded from Local, checking Local
-        // Hmm, the check is: op.source == current_source.opposite()
+        // Hmm, the check is: op.source != current_source
         // would_cause_loop uses ClipboardSource::Local as current_source
-        // So it checks if op.source == Local.opposite() == Rdp
-        // We recorded from Local, so op.source == Local != Rdp
+        // So it checks if op.source != Local
+        // We recorded from Local, so op.source == Local
         // So this should NOT detect a loop - correct!
         assert!(!detector.would_cause_loop(&formats));
     }
 
     #[test]
     fn test_content_hash() {
-        let mut detector = LoopDetector::new();
+        let detector = LoopDetector::new();
 
         let data = b"Hello, World!";
         detector.record_content(data, ClipboardSource::Rdp);
@@ -486,7 +1651,7 @@ mod tests {
 
     #[test]
     fn test_clear_history() {
-        let mut detector = LoopDetector::new();
+        let detector = LoopDetector::new();
 
         let formats = vec![ClipboardFormat::unicode_text()];
         detector.record_formats(&formats, ClipboardSource::Rdp);
@@ -496,6 +1661,489 @@ mod tests {
         assert!(!detector.would_cause_loop(&formats));
     }
 
+    #[test]
+    fn test_history_len_is_bounded() {
+        let detector = LoopDetector::new();
+
+        for i in 0..50u32 {
+            detector.record_formats(&[ClipboardFormat::new(0xC000 + i)], ClipboardSource::Local);
+            detector.record_content(&i.to_le_bytes(), ClipboardSource::Rdp);
+        }
+        assert_eq!(detector.history_len(), 2 * LoopDetectionConfig::default().max_history);
+
+        detector.clear();
+        assert_eq!(detector.history_len(), 0);
+    }
+
+    #[test]
+    fn test_max_history_evicts_oldest() {
+        let detector = LoopDetector::with_config(LoopDetectionConfig {
+            max_history: 3,
+            ..Default::default()
+        });
+        let lists: Vec<_> = (0..4u32).map(|i| vec![ClipboardFormat::new(0xC000 + i)]).collect();
+
+        for formats in &lists {
+            detector.record_formats(formats, ClipboardSource::Rdp);
+        }
+        assert_eq!(detector.history_len(), 3);
+        assert!(!detector.would_cause_loop(&lists[0]));
+        assert!(lists[1..].iter().all(|formats| detector.would_cause_loop(formats)));
+
+        // Each record adds exactly one entry
+        detector.clear();
+        detector.record_formats(&lists[0], ClipboardSource::Rdp);
+        assert_eq!(detector.history_len(), 1);
+    }
+
+    #[test]
+    fn test_window_expiry_ordering() {
+        let clock = crate::ManualClock::new();
+        let detector = LoopDetector::new().with_clock(clock.clone());
+
+        detector.record_hash("older", ContentClass::Text, ClipboardSource::Rdp);
+        clock.advance(Duration::from_millis(300));
+        detector.record_hash("newer", ContentClass::Text, ClipboardSource::Rdp);
+        clock.advance(Duration::from_millis(300));
+
+        // Only the older entry left the 500 ms window
+        assert!(!detector.would_cause_hash_loop("older", ContentClass::Text, ClipboardSource::Local));
+        assert!(detector.would_cause_hash_loop("newer", ContentClass::Text, ClipboardSource::Local));
+        assert!(!detector.would_cause_hash_loop("newer", ContentClass::Text, ClipboardSource::Rdp));
+        assert!(!detector.would_cause_hash_loop("newer", ContentClass::Image, ClipboardSource::Local));
+
+        // Pruning at twice the window drops the older entry first
+        clock.advance(Duration::from_millis(500));
+        detector.record_hash("latest", ContentClass::Text, ClipboardSource::Local);
+        assert_eq!(detector.history_len(), 2);
+        clock.advance(Duration::from_millis(300));
+        detector.record_hash("latest", ContentClass::Text, ClipboardSource::Local);
+        assert_eq!(detector.history_len(), 2);
+    }
+
+    #[test]
+    fn test_relay_sources() {
+        let detector = LoopDetector::new();
+        let first = detector.register_source("session-1");
+        let second = detector.register_source("session-2");
+        assert_eq!(detector.register_source("session-1"), first);
+        assert_eq!(detector.source_name(second), "session-2");
+        assert_eq!(detector.source_name(ClipboardSource::Other(9)), "source 9");
+
+        // Copied in the first session, relayed to the second and locally
+        detector.record_content(b"payload", first);
+        assert_eq!(detector.last_source_of(b"payload"), Some(first));
+        assert!(detector.would_cause_content_loop(b"payload", second));
+        assert!(detector.would_cause_content_loop(b"payload", ClipboardSource::Local));
+        assert!(!detector.would_cause_content_loop(b"payload", first));
+
+        detector.record_content(b"payload", ClipboardSource::Local);
+        assert_eq!(detector.last_source_of(b"payload"), Some(ClipboardSource::Local));
+        assert_eq!(detector.last_source_of(b"other"), None);
+
+        detector.begin_sync(second);
+        assert!(detector.is_own_generation(second, Generation(1)));
+        assert!(!detector.is_own_generation(first, Generation(1)));
+
+        let stats = detector.stats();
+        assert_eq!((stats.local_operations, stats.other_operations), (1, 1));
+
+        let restored = LoopDetector::new();
+        restored.restore_state(detector.save_state());
+        assert_eq!(restored.generation(second), Some(Generation(1)));
+    }
+
+    #[test]
+    fn test_long_horizon_filter() {
+        let clock = crate::ManualClock::new();
+        let detector = LoopDetector::with_config(LoopDetectionConfig {
+            long_horizon_ms: 60_000,
+            ..Default::default()
+        })
+        .with_clock(clock.clone());
+
+        detector.record_content(b"re-asserted", ClipboardSource::Rdp);
+        clock.advance(Duration::from_secs(30));
+
+        // Long past the window, but still within the horizon
+        assert!(detector.would_cause_content_loop(b"re-asserted", ClipboardSource::Local));
+        assert!(!detector.would_cause_content_loop(b"re-asserted", ClipboardSource::Rdp));
+        assert!(!detector.would_cause_content_loop(b"fresh", ClipboardSource::Local));
+
+        clock.advance(Duration::from_secs(31));
+        assert!(!detector.would_cause_content_loop(b"re-asserted", ClipboardSource::Local));
+
+        detector.record_content(b"cleared", ClipboardSource::Rdp);
+        detector.clear();
+        assert!(!detector.would_cause_content_loop(b"cleared", ClipboardSource::Local));
+
+        // Disabled by default
+        let detector = LoopDetector::new().with_clock(clock.clone());
+        detector.record_content(b"data", ClipboardSource::Rdp);
+        clock.advance(Duration::from_secs(1));
+        assert!(!detector.would_cause_content_loop(b"data", ClipboardSource::Local));
+    }
+
+    #[test]
+    fn test_adaptive_window() {
+        let detector = LoopDetector::with_config(LoopDetectionConfig {
+            adaptive_window: true,
+            ..Default::default()
+        });
+        assert_eq!(detector.window(ContentClass::Text), Duration::from_millis(500));
+
+        // A high-latency link: p95 of 1..=20 × 100 ms is 1.9 s
+        for i in 1..=20 {
+            detector.record_sync_latency(Duration::from_millis(i * 100));
+        }
+        assert_eq!(detector.stats().sync_latency_p95, Some(Duration::from_millis(1900)));
+        assert_eq!(detector.window(ContentClass::Text), Duration::from_millis(3800));
+        assert_eq!(detector.window(ContentClass::Image), Duration::from_millis(2000));
+
+        // A LAN: clamped to the minimum once the old samples age out
+        for _ in 0..LATENCY_SAMPLES {
+            detector.record_sync_latency(Duration::from_millis(5));
+        }
+        assert_eq!(detector.window(ContentClass::Files), Duration::from_millis(100));
+
+        // Reported latencies are ignored unless enabled
+        let fixed = LoopDetector::new();
+        for _ in 0..MIN_LATENCY_SAMPLES {
+            fixed.record_sync_latency(Duration::from_secs(2));
+        }
+        assert_eq!(fixed.window(ContentClass::Text), Duration::from_millis(500));
+    }
+
+    #[test]
+    fn test_shared_between_threads() {
+        fn assert_send_sync<T: Send + Sync>() {}
+        assert_send_sync::<LoopDetector>();
+
+        let detector = std::sync::Arc::new(LoopDetector::new());
+        let formats = vec![ClipboardFormat::unicode_text()];
+
+        let handles: Vec<_> = (0..4u32)
+            .map(|i| {
+                let detector = detector.clone();
+                std::thread::spawn(move || {
+                    for j in 0..100u32 {
+                        detector.record_content(&(i * 1000 + j).to_le_bytes(), ClipboardSource::Local);
+                        detector.would_cause_content_loop(&j.to_le_bytes(), ClipboardSource::Rdp);
+                    }
+                })
+            })
+            .collect();
+        detector.record_formats(&formats, ClipboardSource::Rdp);
+        for handle in handles {
+            handle.join().unwrap();
+        }
+
+        assert!(detector.would_cause_loop(&formats));
+        assert!(detector.history_len() <= 1 + LoopDetectionConfig::default().max_history);
+    }
+
+    #[test]
+    fn test_stats_and_loop_events() {
+        let events = std::sync::Arc::new(std::sync::Mutex::new(Vec::new()));
+        let sink = events.clone();
+        let detector = LoopDetector::new().on_loop_detected(move |event| sink.lock().unwrap().push(event.clone()));
+        assert_eq!(detector.stats(), LoopStats::default());
+
+        let formats = vec![ClipboardFormat::unicode_text()];
+        detector.record_formats(&formats, ClipboardSource::Rdp);
+        detector.record_content(b"data", ClipboardSource::Local);
+        detector.record_mime_types(&["text/plain".to_string()], ClipboardSource::Local);
+
+        assert!(detector.would_cause_loop(&formats));
+        assert!(detector.would_cause_content_loop(b"data", ClipboardSource::Rdp));
+        assert!(!detector.would_cause_content_loop(b"other", ClipboardSource::Rdp));
+
+        let stats = detector.stats();
+        assert_eq!(stats.loops_prevented, 2);
+        assert_eq!((stats.rdp_operations, stats.local_operations), (1, 2));
+        assert!(stats.average_hit_latency.unwrap() <= Duration::from_millis(500));
+
+        let events = events.lock().unwrap();
+        assert_eq!(events.len(), 2);
+        assert_eq!(
+            (events[0].kind, events[0].source),
+            (LoopKind::Formats, ClipboardSource::Local)
+        );
+        assert_eq!(
+            (events[1].kind, events[1].source),
+            (LoopKind::Content, ClipboardSource::Rdp)
+        );
+        assert_eq!(events[1].hash, detector.hash(b"data"));
+
+        detector.reset_stats();
+        assert_eq!(detector.stats(), LoopStats::default());
+    }
+
+    #[cfg(feature = "image")]
+    #[test]
+    fn test_perceptual_image_hashing() {
+        let to_png = |image: ::image::RgbImage| {
+            let mut png = Vec::new();
+            ::image::DynamicImage::ImageRgb8(image)
+                .write_to(&mut std::io::Cursor::new(&mut png), ::image::ImageFormat::Png)
+                .unwrap();
+            png
+        };
+        let png = to_png(::image::RgbImage::from_fn(64, 48, |x, y| {
+            ::image::Rgb([(x * 4) as u8, (y * 5) as u8, 90])
+        }));
+        let dib = crate::image::png_to_dib(&png).unwrap();
+        let jpeg = crate::image::dib_to_jpeg(&dib).unwrap();
+
+        // Exact hashing misses the re-encoded echo
+        let detector = LoopDetector::new();
+        detector.record_content(&png, ClipboardSource::Rdp);
+        assert!(!detector.would_cause_content_loop(&dib, ClipboardSource::Local));
+
+        let config = LoopDetectionConfig {
+            perceptual_image_hashing: true,
+            ..Default::default()
+        };
+        let events = std::sync::Arc::new(std::sync::Mutex::new(Vec::new()));
+        let sink = events.clone();
+        let detector =
+            LoopDetector::with_config(config).on_loop_detected(move |event| sink.lock().unwrap().push(event.kind));
+        detector.record_content(&png, ClipboardSource::Rdp);
+        assert!(detector.would_cause_content_loop(&dib, ClipboardSource::Local));
+        assert!(detector.would_cause_content_loop(&jpeg, ClipboardSource::Local));
+        assert!(detector.would_cause_content_loop(&png, ClipboardSource::Local));
+        assert_eq!(
+            *events.lock().unwrap(),
+            [LoopKind::Image, LoopKind::Image, LoopKind::Content]
+        );
+
+        // A different picture and non-image data do not match
+        let other = to_png(::image::RgbImage::from_fn(64, 48, |x, _| {
+            ::image::Rgb([255 - (x * 4) as u8, 0, 0])
+        }));
+        let other = crate::image::png_to_dib(&other).unwrap();
+        assert!(!detector.would_cause_content_loop(&other, ClipboardSource::Local));
+        assert!(!detector.would_cause_content_loop(b"text", ClipboardSource::Local));
+    }
+
+    #[test]
+    fn test_generations() {
+        let events = std::sync::Arc::new(std::sync::Mutex::new(Vec::new()));
+        let sink = events.clone();
+        let detector = LoopDetector::new().on_loop_detected(move |event| sink.lock().unwrap().push(event.kind));
+        assert_eq!(detector.generation(ClipboardSource::Local), None);
+        assert!(!detector.is_own_generation(ClipboardSource::Local, Generation(1)));
+
+        let first = detector.begin_sync(ClipboardSource::Local);
+        let second = detector.begin_sync(ClipboardSource::Local);
+        assert_eq!((first, second), (Generation(1), Generation(2)));
+        assert_eq!(detector.begin_sync(ClipboardSource::Rdp), Generation(1));
+
+        // Late echoes of earlier syncs are still ours; future ones are not
+        assert!(detector.is_own_generation(ClipboardSource::Local, first));
+        assert!(!detector.is_own_generation(ClipboardSource::Local, Generation(3)));
+        assert!(!detector.is_own_generation(ClipboardSource::Local, Generation(0)));
+
+        detector.clear();
+        let change = crate::ClipboardChange::new(vec!["text/plain".to_string()]);
+        assert!(detector.should_skip_change(&change.clone().with_generation(first)));
+        assert!(!detector.should_skip_change(&change.clone().with_generation(Generation(3))));
+        assert!(!detector.should_skip_change(&change));
+
+        // Without a generation, the hash check still applies to pastes into the local side
+        detector.record_mime_types(&change.mime_types, ClipboardSource::Local);
+        assert!(detector.should_skip_change(&change));
+
+        assert_eq!(*events.lock().unwrap(), [LoopKind::Generation, LoopKind::Formats]);
+        assert_eq!(detector.stats().loops_prevented, 2);
+    }
+
+    #[test]
+    fn test_hash_algorithms() {
+        let fast = LoopDetector::new();
+        let sha = LoopDetector::with_config(LoopDetectionConfig {
+            hash_algorithm: HashAlgorithm::Sha256,
+            ..Default::default()
+        });
+        assert_eq!("SHA256".parse(), Ok(HashAlgorithm::Sha256));
+        assert_eq!(fast.hash(b"test").len(), 32);
+        assert_eq!(sha.hash(b"test"), LoopDetector::compute_hash(b"test"));
+        assert_ne!(fast.hash(b"test"), fast.hash(b"different"));
+
+        for detector in [fast, sha] {
+            detector.record_content(b"payload", ClipboardSource::Rdp);
+            detector.record_formats(&[ClipboardFormat::html()], ClipboardSource::Rdp);
+            assert!(detector.would_cause_content_loop(b"payload", ClipboardSource::Local));
+            assert!(!detector.would_cause_content_loop(b"payloae", ClipboardSource::Local));
+            assert!(detector.would_cause_loop(&[ClipboardFormat::html()]));
+        }
+    }
+
+    #[test]
+    fn test_window_and_rate_limit_expiry() {
+        let clock = crate::ManualClock::new();
+        let detector = LoopDetector::with_config(LoopDetectionConfig::with_rate_limit(200)).with_clock(clock.clone());
+        let formats = vec![ClipboardFormat::unicode_text()];
+
+        detector.record_formats(&formats, ClipboardSource::Rdp);
+        detector.record_sync(ClipboardSource::Rdp);
+        clock.advance(Duration::from_millis(200));
+        assert!(detector.would_cause_loop(&formats));
+        assert!(!detector.is_rate_limited(ClipboardSource::Rdp));
+
+        clock.advance(Duration::from_millis(301));
+        assert!(!detector.would_cause_loop(&formats));
+
+        // Entries older than twice the window are pruned on the next record
+        clock.advance(Duration::from_millis(500));
+        detector.record_content(b"data", ClipboardSource::Local);
+        assert_eq!(detector.history_len(), 2);
+        detector.record_formats(&formats, ClipboardSource::Local);
+        assert_eq!(detector.history_len(), 2);
+    }
+
+    #[test]
+    fn test_per_class_windows() {
+        let clock = crate::ManualClock::new();
+        let detector = LoopDetector::with_config(LoopDetectionConfig {
+            text_window_ms: Some(100),
+            files_max_history: Some(1),
+            ..Default::default()
+        })
+        .with_clock(clock.clone());
+        let text = vec![ClipboardFormat::unicode_text()];
+        let image = vec![ClipboardFormat::new(CF_DIB)];
+        let png = b"\x89PNG\r\n\x1a\n....".to_vec();
+
+        assert_eq!(ContentClass::of_formats(&text), ContentClass::Text);
+        assert_eq!(ContentClass::of_formats(&image), ContentClass::Image);
+        assert_eq!(
+            ContentClass::of_mime_types(&["text/uri-list".into()]),
+            ContentClass::Files
+        );
+        assert_eq!(ContentClass::of_content(&png), ContentClass::Image);
+
+        detector.record_formats(&text, ClipboardSource::Rdp);
+        detector.record_formats(&image, ClipboardSource::Rdp);
+        detector.record_content(&png, ClipboardSource::Local);
+        clock.advance(Duration::from_millis(1000));
+
+        // Text expires after 100 ms, images only after 2 s
+        assert!(!detector.would_cause_loop(&text));
+        assert!(detector.would_cause_loop(&image));
+        assert!(detector.would_cause_content_loop(&png, ClipboardSource::Rdp));
+        assert!(!detector.would_cause_content_loop_as(&png, ContentClass::Text, ClipboardSource::Rdp));
+
+        // Each class keeps its own history
+        detector.clear();
+        detector.record_mime_types(&["text/uri-list".into()], ClipboardSource::Local);
+        detector.record_mime_types(&["x-special/gnome-copied-files".into()], ClipboardSource::Local);
+        detector.record_mime_types(&["text/plain".into()], ClipboardSource::Local);
+        assert_eq!(detector.history_len(), 2);
+
+        let restored = LoopDetector::with_config(detector.config.clone());
+        restored.restore_state(detector.save_state());
+        assert_eq!(restored.history_len(), 2);
+        assert!(restored.would_cause_loop_mime(&["x-special/gnome-copied-files".into()]));
+    }
+
+    #[test]
+    fn test_oscillation_trips_circuit_breaker() {
+        let clock = crate::ManualClock::new();
+        let events = std::sync::Arc::new(std::sync::Mutex::new(Vec::new()));
+        let sink = events.clone();
+        let detector = LoopDetector::with_config(LoopDetectionConfig {
+            oscillation_hops: 4,
+            ..Default::default()
+        })
+        .with_clock(clock.clone())
+        .on_loop_detected(move |event| sink.lock().unwrap().push((event.kind, event.latency)));
+
+        let remote = vec![ClipboardFormat::unicode_text(), ClipboardFormat::html()];
+        let local = vec!["text/plain".to_string(), "text/html".to_string()];
+
+        // Each hop arrives after the echo window, so single-hop matching misses it
+        for _ in 0..2 {
+            assert!(!detector.should_skip_sync(&remote, ClipboardSource::Rdp));
+            detector.record_formats(&remote, ClipboardSource::Rdp);
+            clock.advance(Duration::from_secs(1));
+            assert!(!detector.should_skip_sync_mime(&local, ClipboardSource::Local));
+            detector.record_mime_types(&local, ClipboardSource::Local);
+            clock.advance(Duration::from_secs(1));
+        }
+
+        assert!(detector.is_paused());
+        assert!(detector.should_skip_sync(&remote, ClipboardSource::Rdp));
+        assert!(detector.should_skip_sync_mime(&local, ClipboardSource::Local));
+        assert_eq!(detector.stats().breaker_trips, 1);
+        assert_eq!(
+            *events.lock().unwrap(),
+            [(LoopKind::Oscillation, Duration::from_secs(3))]
+        );
+
+        clock.advance(Duration::from_secs(30));
+        assert!(!detector.is_paused());
+
+        // Different content each round is ordinary use, not a loop
+        for i in 0..4u32 {
+            detector.record_formats(&[ClipboardFormat::new(i)], ClipboardSource::Rdp);
+            detector.record_mime_types(&local, ClipboardSource::Local);
+        }
+        assert!(!detector.is_paused());
+
+        // Hops spread beyond the oscillation window do not count
+        for _ in 0..2 {
+            detector.record_formats(&remote, ClipboardSource::Rdp);
+            clock.advance(Duration::from_secs(6));
+            detector.record_mime_types(&local, ClipboardSource::Local);
+            clock.advance(Duration::from_secs(6));
+        }
+        assert!(!detector.is_paused());
+    }
+
+    #[test]
+    fn test_state_survives_reconnect() {
+        let formats = vec![ClipboardFormat::unicode_text()];
+        let before = LoopDetector::new();
+        before.record_formats(&formats, ClipboardSource::Rdp);
+        before.record_content(b"data", ClipboardSource::Local);
+        let generation = before.begin_sync(ClipboardSource::Local);
+        let state = before.save_state();
+        assert_eq!((state.formats.len(), state.content.len()), (1, 1));
+
+        // The reconnect takes longer than the window, but that time is not counted
+        let clock = crate::ManualClock::new();
+        clock.advance(Duration::from_secs(5));
+        let after = LoopDetector::new().with_clock(clock.clone());
+        after.restore_state(state.clone());
+        assert!(after.would_cause_loop(&formats));
+        assert!(after.would_cause_content_loop(b"data", ClipboardSource::Rdp));
+        assert!(after.is_own_generation(ClipboardSource::Local, generation));
+
+        clock.advance(Duration::from_secs(1));
+        assert!(!after.would_cause_loop(&formats));
+
+        // Hashes from another algorithm are dropped
+        let sha = LoopDetector::with_config(LoopDetectionConfig {
+            hash_algorithm: HashAlgorithm::Sha256,
+            ..Default::default()
+        });
+        sha.restore_state(state);
+        assert_eq!(sha.history_len(), 0);
+    }
+
+    #[cfg(feature = "serde")]
+    #[test]
+    fn test_state_serde_round_trip() {
+        let detector = LoopDetector::new();
+        detector.record_mime_types(&["text/plain".to_string()], ClipboardSource::Local);
+        let state = detector.save_state();
+
+        let json = serde_json::to_string(&state).unwrap();
+        let restored: LoopDetectorState = serde_json::from_str(&json).unwrap();
+        assert_eq!(restored, state);
+    }
+
     #[test]
     fn test_compute_hash() {
         let hash1 = LoopDetector::compute_hash(b"test");
@@ -520,7 +2168,7 @@ mod tests {
         let config = LoopDetectionConfig::with_rate_limit(200);
         assert_eq!(config.rate_limit_ms, Some(200));
 
-        let mut detector = LoopDetector::with_config(config);
+        let detector = LoopDetector::with_config(config);
 
         // First check - not rate limited
         assert!(!detector.is_rate_limited(ClipboardSource::Rdp));
@@ -538,7 +2186,7 @@ mod tests {
     #[test]
     fn test_rate_limit_clear() {
         let config = LoopDetectionConfig::with_rate_limit(200);
-        let mut detector = LoopDetector::with_config(config);
+        let detector = LoopDetector::with_config(config);
 
         detector.record_sync(ClipboardSource::Rdp);
         assert!(detector.is_rate_limited(ClipboardSource::Rdp));
@@ -550,7 +2198,7 @@ mod tests {
     #[test]
     fn test_should_skip_sync_combined() {
         let config = LoopDetectionConfig::with_rate_limit(200);
-        let mut detector = LoopDetector::with_config(config);
+        let detector = LoopDetector::with_config(config);
 
         let formats = vec![ClipboardFormat::unicode_text()];
 
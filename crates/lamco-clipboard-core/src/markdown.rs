@@ -0,0 +1,514 @@
+//! Markdown ↔ HTML conversion.
+//!
+//! Windows has no Markdown clipboard format, so `text/markdown` travels over
+//! RDP as CF_HTML. Markdown copied on Linux is rendered to HTML before it is
+//! offered to the peer, and HTML pasted from Windows can be turned back into
+//! Markdown for editors that ask for `text/markdown`.
+//!
+//! Rendering uses CommonMark with the GitHub table and strikethrough
+//! extensions. The reverse direction is best effort: headings, emphasis,
+//! links, images, lists, block quotes, code and tables are kept; everything
+//! else is reduced to its text.
+
+use crate::rtf::{attribute, collapse_whitespace, decode_entities, HTML_SKIP_ELEMENTS};
+use pulldown_cmark::{html, Options, Parser};
+
+/// Render Markdown to an HTML fragment
+///
+/// # Example
+///
+/// ```
+/// use lamco_clipboard_core::markdown::markdown_to_html;
+///
+/// let html = markdown_to_html("# Title\n\nSome **bold** text");
+/// assert!(html.contains("<h1>Title</h1>"));
+/// assert!(html.contains("<strong>bold</strong>"));
+/// ```
+pub fn markdown_to_html(markdown: &str) -> String {
+    let parser = Parser::new_ext(markdown, Options::ENABLE_TABLES | Options::ENABLE_STRIKETHROUGH);
+    let mut rendered = String::with_capacity(markdown.len() * 3 / 2);
+    html::push_html(&mut rendered, parser);
+    rendered
+}
+
+/// Convert HTML (a fragment or a full document) to Markdown
+///
+/// # Example
+///
+/// ```
+/// use lamco_clipboard_core::markdown::html_to_markdown;
+///
+/// let md = html_to_markdown("<p>Some <b>bold</b> and <a href=\"https://example.com\">a link</a></p>");
+/// assert_eq!(md, "Some **bold** and [a link](https://example.com)");
+/// ```
+pub fn html_to_markdown(html: &str) -> String {
+    let mut writer = MarkdownWriter::default();
+    let mut skip_depth = 0usize;
+    let mut rest = html;
+
+    while !rest.is_empty() {
+        if let Some(after) = rest.strip_prefix('<') {
+            let Some(end) = after.find('>') else {
+                break;
+            };
+            let tag = &after[..end];
+            rest = &after[end + 1..];
+
+            if tag.starts_with('!') || tag.starts_with('?') {
+                continue;
+            }
+            let closing = tag.starts_with('/');
+            let name = tag
+                .trim_start_matches('/')
+                .split(|c: char| c.is_whitespace() || c == '/')
+                .next()
+                .unwrap_or_default()
+                .to_ascii_lowercase();
+
+            if HTML_SKIP_ELEMENTS.contains(&name.as_str()) {
+                if closing {
+                    skip_depth = skip_depth.saturating_sub(1);
+                } else if !tag.ends_with('/') {
+                    skip_depth += 1;
+                }
+                continue;
+            }
+            if skip_depth == 0 {
+                writer.tag(&name, tag, closing);
+            }
+        } else {
+            let end = rest.find('<').unwrap_or(rest.len());
+            let text = &rest[..end];
+            rest = &rest[end..];
+
+            if skip_depth == 0 {
+                writer.text(&decode_entities(text));
+            }
+        }
+    }
+
+    writer.finish()
+}
+
+/// A GitHub-style table being collected cell by cell
+#[derive(Default)]
+struct Table {
+    rows: Vec<Vec<String>>,
+    cell: Option<String>,
+}
+
+/// Markdown output with block structure tracking
+#[derive(Default)]
+struct MarkdownWriter {
+    out: String,
+
+    /// Line prefixes for open block quotes and list items
+    prefixes: Vec<String>,
+
+    /// List marker written in place of the innermost prefix on the next line
+    marker: Option<String>,
+
+    /// Newlines owed before the next output (1 = line break, 2 = new block)
+    pending: usize,
+
+    /// Nothing has been written on the current line yet
+    line_start: bool,
+
+    /// Newlines written since the last output
+    newlines_written: usize,
+
+    /// Open lists: `None` for bullets, `Some(next)` for numbered lists
+    lists: Vec<Option<u32>>,
+
+    /// Targets of open links (`None` for anchors without `href`)
+    links: Vec<Option<String>>,
+
+    /// Depth of `<pre>` elements
+    pre: usize,
+
+    /// Depth of inline `<code>` elements
+    code: usize,
+
+    /// Depth of nested `<table>` elements; only the outermost is kept as a table
+    table_depth: usize,
+
+    table: Option<Table>,
+}
+
+impl MarkdownWriter {
+    fn tag(&mut self, name: &str, tag: &str, closing: bool) {
+        match (name, closing) {
+            ("h1" | "h2" | "h3" | "h4" | "h5" | "h6", false) => {
+                self.block(2);
+                let level = usize::from(name.as_bytes()[1] - b'0');
+                self.raw(&format!("{} ", "#".repeat(level)));
+            }
+            ("h1" | "h2" | "h3" | "h4" | "h5" | "h6" | "p", _) => self.block(2),
+            ("div" | "section" | "article" | "header" | "footer", _) => self.block(1),
+            ("br", _) => {
+                if self.in_cell() {
+                    self.raw(" ");
+                } else if self.pre > 0 {
+                    self.newline();
+                } else {
+                    self.raw("\\");
+                    self.block(1);
+                }
+            }
+            ("hr", _) => {
+                self.block(2);
+                self.raw("---");
+                self.block(2);
+            }
+            ("b" | "strong", _) => self.raw("**"),
+            ("i" | "em", _) => self.raw("*"),
+            ("s" | "strike" | "del", _) => self.raw("~~"),
+            ("code", false) if self.pre == 0 => {
+                self.code += 1;
+                self.raw("`");
+            }
+            ("code", true) if self.pre == 0 => {
+                self.code = self.code.saturating_sub(1);
+                self.raw("`");
+            }
+            ("pre", false) => {
+                self.block(2);
+                self.raw("```");
+                self.newline();
+                self.pre += 1;
+            }
+            ("pre", true) if self.pre > 0 => {
+                self.pre -= 1;
+                if !self.line_start {
+                    self.newline();
+                }
+                self.raw("```");
+                self.block(2);
+            }
+            ("a", false) => {
+                let href = attribute(tag, "href").map(|href| decode_entities(&href));
+                if href.is_some() {
+                    self.raw("[");
+                }
+                self.links.push(href);
+            }
+            ("a", true) => {
+                if let Some(Some(href)) = self.links.pop() {
+                    self.raw(&format!("]({})", link_destination(&href)));
+                }
+            }
+            ("img", false) => {
+                if let Some(src) = attribute(tag, "src") {
+                    let alt = decode_entities(&attribute(tag, "alt").unwrap_or_default());
+                    let src = link_destination(&decode_entities(&src));
+                    self.raw(&format!("![{}]({src})", escape(&alt, false)));
+                }
+            }
+            ("blockquote", false) => {
+                // The blank line before the quote must not carry its marker
+                self.block(2);
+                self.flush_pending();
+                self.prefixes.push("> ".to_string());
+            }
+            ("blockquote", true) => {
+                self.prefixes.pop();
+                self.block(2);
+            }
+            ("ul", false) => {
+                self.block(if self.lists.is_empty() { 2 } else { 1 });
+                self.lists.push(None);
+            }
+            ("ol", false) => {
+                self.block(if self.lists.is_empty() { 2 } else { 1 });
+                let start = attribute(tag, "start").and_then(|s| s.trim().parse().ok()).unwrap_or(1);
+                self.lists.push(Some(start));
+            }
+            ("ul" | "ol", true) => {
+                self.lists.pop();
+                self.block(if self.lists.is_empty() { 2 } else { 1 });
+            }
+            ("li", false) => {
+                self.block(1);
+                let marker = match self.lists.last_mut() {
+                    Some(Some(next)) => {
+                        let marker = format!("{next}. ");
+                        *next += 1;
+                        marker
+                    }
+                    _ => "- ".to_string(),
+                };
+                self.prefixes.push(" ".repeat(marker.len()));
+                self.marker = Some(marker);
+            }
+            ("li", true) => {
+                self.prefixes.pop();
+                self.marker = None;
+                self.block(1);
+            }
+            ("table", false) => {
+                self.table_depth += 1;
+                if self.table_depth == 1 {
+                    self.block(2);
+                    self.table = Some(Table::default());
+                }
+            }
+            ("table", true) if self.table_depth > 0 => {
+                self.table_depth -= 1;
+                if self.table_depth == 0 {
+                    if let Some(table) = self.table.take() {
+                        self.write_table(table);
+                    }
+                    self.block(2);
+                }
+            }
+            ("tr", false) if self.table_depth == 1 => {
+                if let Some(table) = &mut self.table {
+                    table.rows.push(Vec::new());
+                }
+            }
+            ("td" | "th", false) if self.table_depth == 1 => {
+                if let Some(table) = &mut self.table {
+                    if table.rows.is_empty() {
+                        table.rows.push(Vec::new());
+                    }
+                    table.cell = Some(String::new());
+                }
+            }
+            ("td" | "th", true) if self.table_depth == 1 => {
+                if let Some(table) = &mut self.table {
+                    if let Some(cell) = table.cell.take() {
+                        let cell = cell.trim().to_string();
+                        if let Some(row) = table.rows.last_mut() {
+                            row.push(cell);
+                        }
+                    }
+                }
+            }
+            _ => {}
+        }
+    }
+
+    fn text(&mut self, text: &str) {
+        if self.pre > 0 {
+            // The first newline after <pre> is not part of the content
+            let text = if self.out.ends_with("```\n") && self.line_start {
+                text.strip_prefix('\n').unwrap_or(text)
+            } else {
+                text
+            };
+            for (i, line) in text.split('\n').enumerate() {
+                if i > 0 {
+                    self.newline();
+                }
+                if !line.is_empty() {
+                    self.raw(line);
+                }
+            }
+            return;
+        }
+
+        let collapsed = collapse_whitespace(text);
+        let at_start = self.line_start || self.pending > 0 || self.out.is_empty();
+        let text = if at_start && !self.in_cell() {
+            collapsed.trim_start()
+        } else {
+            &collapsed
+        };
+        if text.is_empty() {
+            return;
+        }
+
+        if self.code > 0 {
+            self.raw(text);
+        } else {
+            let escaped = escape(text, self.in_cell());
+            let escaped = if at_start { escape_line_start(&escaped) } else { escaped };
+            self.raw(&escaped);
+        }
+    }
+
+    fn in_cell(&self) -> bool {
+        self.table.as_ref().is_some_and(|table| table.cell.is_some())
+    }
+
+    /// Request a line break (1) or blank line (2) before the next output
+    fn block(&mut self, newlines: usize) {
+        if let Some(cell) = self.table.as_mut().and_then(|table| table.cell.as_mut()) {
+            if !cell.is_empty() {
+                cell.push(' ');
+            }
+            return;
+        }
+        if !self.out.is_empty() {
+            self.pending = self.pending.max(newlines.saturating_sub(self.newlines_written));
+        }
+    }
+
+    fn newline(&mut self) {
+        if self.line_start {
+            // Blank lines keep block quote markers so the quote continues
+            let prefix: String = self.prefixes.concat();
+            self.out.push_str(prefix.trim_end());
+        } else if self.pre == 0 {
+            let trimmed = self.out.trim_end_matches(' ').len();
+            self.out.truncate(trimmed);
+        }
+        self.out.push('\n');
+        self.line_start = true;
+        self.newlines_written += 1;
+    }
+
+    fn flush_pending(&mut self) {
+        if !self.out.is_empty() {
+            for _ in 0..self.pending {
+                self.newline();
+            }
+        }
+        self.pending = 0;
+    }
+
+    /// Write Markdown as-is
+    fn raw(&mut self, text: &str) {
+        if let Some(cell) = self.table.as_mut().and_then(|table| table.cell.as_mut()) {
+            cell.push_str(text);
+            return;
+        }
+
+        self.flush_pending();
+        if self.line_start || self.out.is_empty() {
+            let mut prefix = self.prefixes.concat();
+            if let Some(marker) = self.marker.take() {
+                let indent = prefix.len() - marker.len();
+                prefix.truncate(indent);
+                prefix.push_str(&marker);
+            }
+            self.out.push_str(&prefix);
+            self.line_start = false;
+        }
+        self.out.push_str(text);
+        self.newlines_written = 0;
+    }
+
+    fn write_table(&mut self, table: Table) {
+        let rows: Vec<Vec<String>> = table.rows.into_iter().filter(|row| !row.is_empty()).collect();
+        let columns = rows.iter().map(Vec::len).max().unwrap_or(0);
+        if columns == 0 {
+            return;
+        }
+
+        for (i, row) in rows.iter().enumerate() {
+            let mut line = String::from("|");
+            for column in 0..columns {
+                line.push(' ');
+                line.push_str(row.get(column).map(String::as_str).unwrap_or_default());
+                line.push_str(" |");
+            }
+            self.raw(&line);
+            self.newline();
+
+            if i == 0 {
+                self.raw(&format!("|{}", " --- |".repeat(columns)));
+                self.newline();
+            }
+        }
+    }
+
+    fn finish(self) -> String {
+        self.out.trim_end().to_string()
+    }
+}
+
+/// Escape characters that Markdown would treat as syntax
+fn escape(text: &str, in_table: bool) -> String {
+    let mut escaped = String::with_capacity(text.len());
+    for c in text.chars() {
+        match c {
+            '\\' | '*' | '_' | '`' | '[' | ']' | '<' | '~' => escaped.push('\\'),
+            '|' if in_table => escaped.push('\\'),
+            _ => {}
+        }
+        escaped.push(c);
+    }
+    escaped
+}
+
+/// Escape text that would start a heading, quote, list item or rule
+fn escape_line_start(text: &str) -> String {
+    if text.starts_with(['#', '>', '-', '+', '=']) {
+        return format!("\\{text}");
+    }
+
+    let digits = text.bytes().take_while(u8::is_ascii_digit).count();
+    if digits > 0 && text[digits..].starts_with(['.', ')']) {
+        return format!("{}\\{}", &text[..digits], &text[digits..]);
+    }
+    text.to_string()
+}
+
+/// Wrap link targets that would break the `(...)` syntax in angle brackets
+fn link_destination(url: &str) -> String {
+    if url.contains([' ', '(', ')']) {
+        format!("<{}>", url.replace('<', "%3C").replace('>', "%3E"))
+    } else {
+        url.to_string()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_markdown_to_html_extensions() {
+        let html = markdown_to_html("| a | b |\n|---|---|\n| 1 | 2 |\n\n~~gone~~");
+        assert!(html.contains("<table>"));
+        assert!(html.contains("<td>2</td>"));
+        assert!(html.contains("<del>gone</del>"));
+    }
+
+    #[test]
+    fn test_headings_and_paragraphs() {
+        let md = html_to_markdown("<h2>Title</h2><p>First   paragraph</p><p>Second<br>line</p>");
+        assert_eq!(md, "## Title\n\nFirst paragraph\n\nSecond\\\nline");
+    }
+
+    #[test]
+    fn test_nested_lists() {
+        let md = html_to_markdown(
+            "<ul><li>one<ul><li>inner</li></ul></li><li>two</li></ul><ol start=\"3\"><li>c</li><li>d</li></ol>",
+        );
+        assert_eq!(md, "- one\n  - inner\n- two\n\n3. c\n4. d");
+    }
+
+    #[test]
+    fn test_blockquote_and_code() {
+        let md = html_to_markdown("<blockquote><p>quoted</p><p>more</p></blockquote><pre><code>fn main() {\n    x\n}\n</code></pre><p>use <code>a*b</code></p>");
+        assert_eq!(
+            md,
+            "> quoted\n>\n> more\n\n```\nfn main() {\n    x\n}\n```\n\nuse `a*b`"
+        );
+    }
+
+    #[test]
+    fn test_table() {
+        let md = html_to_markdown(
+            "<table><tr><th>Name</th><th>Value</th></tr><tr><td>a|b</td><td><b>1</b></td></tr></table>",
+        );
+        assert_eq!(md, "| Name | Value |\n| --- | --- |\n| a\\|b | **1** |");
+    }
+
+    #[test]
+    fn test_escaping_and_skipped_elements() {
+        let md = html_to_markdown("<style>p { color: red }</style><p>1. not *a* list &amp; [x]</p>");
+        assert_eq!(md, "1\\. not \\*a\\* list & \\[x\\]");
+
+        let md = html_to_markdown("<p><img src=\"a b.png\" alt=\"pic\"> <a href=\"/x\">x</a></p>");
+        assert_eq!(md, "![pic](<a b.png>) [x](/x)");
+    }
+
+    #[test]
+    fn test_round_trip() {
+        let source = "# Notes\n\n- **bold** item\n- [link](https://example.com)\n\n> quote";
+        assert_eq!(html_to_markdown(&markdown_to_html(source)), source);
+    }
+}
@@ -0,0 +1,1532 @@
+//! Metafile rasterization for CF_ENHMETAFILE and CF_METAFILEPICT.
+//!
+//! Office applications frequently offer copied charts, shapes and SmartArt
+//! only as metafiles. Linux applications cannot paste those, so this module
+//! plays the GDI drawing records back onto a raster canvas and produces PNG.
+//!
+//! # Feature Flag
+//!
+//! This module requires the `metafile` feature (which implies `image`):
+//!
+//! ```toml
+//! [dependencies]
+//! lamco-clipboard-core = { version = "0.5", features = ["metafile"] }
+//! ```
+//!
+//! # Supported Records
+//!
+//! Playback is best effort and covers what chart and shape exports use:
+//!
+//! - Lines, polylines, polygons, Béziers, rectangles, rounded rectangles, ellipses
+//! - Path brackets (fill, stroke, stroke-and-fill)
+//! - Solid pens and brushes, including stock objects
+//! - Window/viewport mapping, map modes, world transforms and saved DC states
+//! - Device-independent bitmaps (`StretchDIBits`, `BitBlt`, `StretchBlt`)
+//!
+//! Text, clipping regions, hatch patterns and EMF+ comments are not rendered.
+//! The canvas starts white, matching how Windows synthesizes CF_DIB from a
+//! metafile.
+
+use image::{DynamicImage, ImageFormat, RgbaImage};
+use tiny_skia::{
+    Color, ColorU8, FillRule, LineCap, LineJoin, Paint, Path, PathBuilder, PathSegment, Pixmap, PixmapPaint, Rect,
+    Stroke, StrokeDash, Transform,
+};
+
+use crate::{ClipboardError, ClipboardResult};
+
+/// Largest width or height of a rasterized metafile in pixels.
+///
+/// Larger pictures are scaled down uniformly to fit.
+pub const MAX_METAFILE_DIMENSION: u32 = 4096;
+
+/// Resolution used when a metafile only carries physical dimensions.
+const DEFAULT_DPI: f32 = 96.0;
+
+/// EMF header signature (" EMF" in little-endian ASCII).
+const EMF_SIGNATURE: u32 = 0x464D_4520;
+
+// Map modes (shared by EMF and WMF)
+const MM_TEXT: u32 = 1;
+const MM_LOMETRIC: u32 = 2;
+const MM_HIMETRIC: u32 = 3;
+const MM_LOENGLISH: u32 = 4;
+const MM_HIENGLISH: u32 = 5;
+const MM_TWIPS: u32 = 6;
+const MM_ISOTROPIC: u32 = 7;
+const MM_ANISOTROPIC: u32 = 8;
+
+// Raster operations that can be played back without a destination read
+const SRCCOPY: u32 = 0x00CC_0020;
+const PATCOPY: u32 = 0x00F0_0021;
+const BLACKNESS: u32 = 0x0000_0042;
+const WHITENESS: u32 = 0x00FF_0062;
+
+// =============================================================================
+// Public API
+// =============================================================================
+
+/// Rasterize an enhanced metafile (CF_ENHMETAFILE) to PNG.
+///
+/// The output size follows the picture bounds recorded in the EMF header,
+/// scaled down to [`MAX_METAFILE_DIMENSION`] if necessary.
+pub fn emf_to_png(emf_data: &[u8]) -> ClipboardResult<Vec<u8>> {
+    encode_png(&rasterize_emf(emf_data)?)
+}
+
+/// Rasterize CF_METAFILEPICT clipboard data to PNG.
+///
+/// Over RDP the format carries a 12-byte header (mapping mode, x extent,
+/// y extent) followed by a Windows Metafile (WMF) without placeable header.
+pub fn metafilepict_to_png(data: &[u8]) -> ClipboardResult<Vec<u8>> {
+    if data.len() < 12 {
+        return Err(ClipboardError::ImageDecode("CF_METAFILEPICT too small".to_string()));
+    }
+
+    let map_mode = le_u32(data, 0).unwrap_or(MM_ANISOTROPIC);
+    let x_ext = le_i32(data, 4).unwrap_or(0);
+    let y_ext = le_i32(data, 8).unwrap_or(0);
+
+    encode_png(&rasterize_wmf(&data[12..], map_mode, x_ext, y_ext)?)
+}
+
+// =============================================================================
+// EMF playback
+// =============================================================================
+
+mod emr {
+    pub(super) const HEADER: u32 = 1;
+    pub(super) const POLYBEZIER: u32 = 2;
+    pub(super) const POLYGON: u32 = 3;
+    pub(super) const POLYLINE: u32 = 4;
+    pub(super) const POLYBEZIERTO: u32 = 5;
+    pub(super) const POLYLINETO: u32 = 6;
+    pub(super) const POLYPOLYLINE: u32 = 7;
+    pub(super) const POLYPOLYGON: u32 = 8;
+    pub(super) const SETWINDOWEXTEX: u32 = 9;
+    pub(super) const SETWINDOWORGEX: u32 = 10;
+    pub(super) const SETVIEWPORTEXTEX: u32 = 11;
+    pub(super) const SETVIEWPORTORGEX: u32 = 12;
+    pub(super) const EOF: u32 = 14;
+    pub(super) const SETMAPMODE: u32 = 17;
+    pub(super) const SETPOLYFILLMODE: u32 = 19;
+    pub(super) const MOVETOEX: u32 = 27;
+    pub(super) const SAVEDC: u32 = 33;
+    pub(super) const RESTOREDC: u32 = 34;
+    pub(super) const SETWORLDTRANSFORM: u32 = 35;
+    pub(super) const MODIFYWORLDTRANSFORM: u32 = 36;
+    pub(super) const SELECTOBJECT: u32 = 37;
+    pub(super) const CREATEPEN: u32 = 38;
+    pub(super) const CREATEBRUSHINDIRECT: u32 = 39;
+    pub(super) const DELETEOBJECT: u32 = 40;
+    pub(super) const ELLIPSE: u32 = 42;
+    pub(super) const RECTANGLE: u32 = 43;
+    pub(super) const ROUNDRECT: u32 = 44;
+    pub(super) const LINETO: u32 = 54;
+    pub(super) const BEGINPATH: u32 = 59;
+    pub(super) const ENDPATH: u32 = 60;
+    pub(super) const CLOSEFIGURE: u32 = 61;
+    pub(super) const FILLPATH: u32 = 62;
+    pub(super) const STROKEANDFILLPATH: u32 = 63;
+    pub(super) const STROKEPATH: u32 = 64;
+    pub(super) const ABORTPATH: u32 = 68;
+    pub(super) const BITBLT: u32 = 76;
+    pub(super) const STRETCHBLT: u32 = 77;
+    pub(super) const STRETCHDIBITS: u32 = 81;
+    pub(super) const POLYBEZIER16: u32 = 85;
+    pub(super) const POLYGON16: u32 = 86;
+    pub(super) const POLYLINE16: u32 = 87;
+    pub(super) const POLYBEZIERTO16: u32 = 88;
+    pub(super) const POLYLINETO16: u32 = 89;
+    pub(super) const POLYPOLYLINE16: u32 = 90;
+    pub(super) const POLYPOLYGON16: u32 = 91;
+    pub(super) const EXTCREATEPEN: u32 = 95;
+}
+
+fn rasterize_emf(data: &[u8]) -> ClipboardResult<Pixmap> {
+    if le_u32(data, 0) != Some(emr::HEADER) || le_u32(data, 40) != Some(EMF_SIGNATURE) {
+        return Err(ClipboardError::ImageDecode("Not an enhanced metafile".to_string()));
+    }
+    let header_err = || ClipboardError::ImageDecode("EMF header truncated".to_string());
+
+    let bounds = read_rect(data, 8).ok_or_else(header_err)?;
+    let frame = read_rect(data, 24).ok_or_else(header_err)?;
+    let device = (
+        le_i32(data, 72).ok_or_else(header_err)?,
+        le_i32(data, 76).ok_or_else(header_err)?,
+    );
+    let millimeters = (
+        le_i32(data, 80).ok_or_else(header_err)?,
+        le_i32(data, 84).ok_or_else(header_err)?,
+    );
+
+    let px_per_mm = if device.0 > 0 && device.1 > 0 && millimeters.0 > 0 && millimeters.1 > 0 {
+        (
+            device.0 as f32 / millimeters.0 as f32,
+            device.1 as f32 / millimeters.1 as f32,
+        )
+    } else {
+        (DEFAULT_DPI / 25.4, DEFAULT_DPI / 25.4)
+    };
+
+    // Bounds are inclusive device pixels; fall back to the frame (0.01 mm)
+    let (left, top, width, height) = if bounds.2 >= bounds.0 && bounds.3 >= bounds.1 {
+        (
+            bounds.0 as f32,
+            bounds.1 as f32,
+            (bounds.2 - bounds.0 + 1) as f32,
+            (bounds.3 - bounds.1 + 1) as f32,
+        )
+    } else {
+        (
+            frame.0 as f32 * px_per_mm.0 / 100.0,
+            frame.1 as f32 * px_per_mm.1 / 100.0,
+            (frame.2 - frame.0) as f32 * px_per_mm.0 / 100.0,
+            (frame.3 - frame.1) as f32 * px_per_mm.1 / 100.0,
+        )
+    };
+
+    let (pixmap, scale) = create_canvas(width, height)?;
+    let base = Transform::from_scale(scale, scale).pre_translate(-left, -top);
+    let mut canvas = Canvas::new(pixmap, base, px_per_mm, DcState::new(MM_TEXT));
+
+    let mut offset = 0;
+    while offset + 8 <= data.len() {
+        let record_type = le_u32(data, offset).unwrap_or(0);
+        let size = le_u32(data, offset + 4).unwrap_or(0) as usize;
+        if size < 8 || offset + size > data.len() {
+            tracing::debug!("EMF record at {} has invalid size {}", offset, size);
+            break;
+        }
+        if record_type == emr::EOF {
+            break;
+        }
+
+        let record = &data[offset..offset + size];
+        if play_emf_record(&mut canvas, record_type, record).is_none() {
+            tracing::trace!("Skipped truncated EMF record {} at {}", record_type, offset);
+        }
+        offset += size;
+    }
+
+    Ok(canvas.pixmap)
+}
+
+/// Play one EMF record. Returns `None` if the record is truncated.
+fn play_emf_record(canvas: &mut Canvas, record_type: u32, r: &[u8]) -> Option<()> {
+    match record_type {
+        emr::POLYBEZIER | emr::POLYGON | emr::POLYLINE | emr::POLYBEZIERTO | emr::POLYLINETO => {
+            let points = read_points32(r, 28, le_u32(r, 24)? as usize)?;
+            canvas.poly(record_type, &points);
+        }
+        emr::POLYBEZIER16 | emr::POLYGON16 | emr::POLYLINE16 | emr::POLYBEZIERTO16 | emr::POLYLINETO16 => {
+            let points = read_points16(r, 28, le_u32(r, 24)? as usize)?;
+            // The 16-bit records mirror the 32-bit ones, offset by 83
+            canvas.poly(record_type - 83, &points);
+        }
+        emr::POLYPOLYLINE | emr::POLYPOLYGON | emr::POLYPOLYLINE16 | emr::POLYPOLYGON16 => {
+            let polys = le_u32(r, 24)? as usize;
+            let total = le_u32(r, 28)? as usize;
+            let counts_at: usize = 32;
+            let points_at = counts_at.checked_add(polys.checked_mul(4)?)?;
+            let wide = matches!(record_type, emr::POLYPOLYLINE | emr::POLYPOLYGON);
+            let points = if wide {
+                read_points32(r, points_at, total)?
+            } else {
+                read_points16(r, points_at, total)?
+            };
+            let counts = (0..polys)
+                .map(|i| le_u32(r, counts_at + i * 4).map(|c| c as usize))
+                .collect::<Option<Vec<_>>>()?;
+            let closed = matches!(record_type, emr::POLYPOLYGON | emr::POLYPOLYGON16);
+            canvas.poly_poly(&points, &counts, closed);
+        }
+        emr::SETWINDOWEXTEX => canvas.state.window_ext = read_point32(r, 8)?,
+        emr::SETWINDOWORGEX => canvas.state.window_org = read_point32(r, 8)?,
+        emr::SETVIEWPORTEXTEX => canvas.state.viewport_ext = read_point32(r, 8)?,
+        emr::SETVIEWPORTORGEX => canvas.state.viewport_org = read_point32(r, 8)?,
+        emr::SETMAPMODE => canvas.state.map_mode = le_u32(r, 8)?,
+        emr::SETPOLYFILLMODE => canvas.state.fill_rule = poly_fill_rule(le_u32(r, 8)?),
+        emr::MOVETOEX => canvas.move_to(read_point32(r, 8)?),
+        emr::LINETO => canvas.line_to(read_point32(r, 8)?),
+        emr::SAVEDC => canvas.save(),
+        emr::RESTOREDC => canvas.restore(le_i32(r, 8)?),
+        emr::SETWORLDTRANSFORM => canvas.state.world = read_xform(r, 8)?,
+        emr::MODIFYWORLDTRANSFORM => {
+            let xform = read_xform(r, 8)?;
+            let world = canvas.state.world;
+            canvas.state.world = match le_u32(r, 32)? {
+                1 => Transform::identity(),
+                2 => world.pre_concat(xform),
+                3 => world.post_concat(xform),
+                4 => xform,
+                _ => world,
+            };
+        }
+        emr::SELECTOBJECT => canvas.select_object(le_u32(r, 8)?),
+        emr::DELETEOBJECT => canvas.delete_object(le_u32(r, 8)?),
+        emr::CREATEPEN => {
+            let pen = Pen::new(le_u32(r, 12)?, le_i32(r, 16)? as f32, le_u32(r, 24)?);
+            canvas.create_object(le_u32(r, 8)?, GdiObject::Pen(pen));
+        }
+        emr::EXTCREATEPEN => {
+            let style = le_u32(r, 28)?;
+            // Cosmetic pens are always one device pixel wide
+            let width = if style & PS_GEOMETRIC != 0 {
+                le_u32(r, 32)? as f32
+            } else {
+                0.0
+            };
+            let mut pen = Pen::new(style, width, le_u32(r, 40)?);
+            if le_u32(r, 36)? == BS_NULL {
+                pen.color = None;
+            }
+            canvas.create_object(le_u32(r, 8)?, GdiObject::Pen(pen));
+        }
+        emr::CREATEBRUSHINDIRECT => {
+            let brush = Brush::new(le_u32(r, 12)?, le_u32(r, 16)?);
+            canvas.create_object(le_u32(r, 8)?, GdiObject::Brush(brush));
+        }
+        emr::ELLIPSE | emr::RECTANGLE => {
+            let (l, t, rt, b) = read_rect(r, 8)?;
+            let rect = logical_rect(l, t, rt, b);
+            canvas.shape(
+                rect,
+                if record_type == emr::ELLIPSE {
+                    Shape::Ellipse
+                } else {
+                    Shape::Rect
+                },
+            );
+        }
+        emr::ROUNDRECT => {
+            let (l, t, rt, b) = read_rect(r, 8)?;
+            let corner = read_point32(r, 24)?;
+            canvas.shape(
+                logical_rect(l, t, rt, b),
+                Shape::RoundRect(corner.0 / 2.0, corner.1 / 2.0),
+            );
+        }
+        emr::BEGINPATH => canvas.begin_path(),
+        emr::ENDPATH => canvas.end_path(),
+        emr::CLOSEFIGURE => canvas.close_figure(),
+        emr::ABORTPATH => canvas.abort_path(),
+        emr::FILLPATH => canvas.draw_path(true, false),
+        emr::STROKEPATH => canvas.draw_path(false, true),
+        emr::STROKEANDFILLPATH => canvas.draw_path(true, true),
+        emr::BITBLT | emr::STRETCHBLT => {
+            let dest = (
+                le_i32(r, 24)? as f32,
+                le_i32(r, 28)? as f32,
+                le_i32(r, 32)? as f32,
+                le_i32(r, 36)? as f32,
+            );
+            let rop = le_u32(r, 40)?;
+            let (off_bmi, cb_bmi) = (le_u32(r, 84)? as usize, le_u32(r, 88)? as usize);
+            let (off_bits, cb_bits) = (le_u32(r, 92)? as usize, le_u32(r, 96)? as usize);
+            if cb_bmi == 0 {
+                canvas.pattern_blt(dest, rop);
+            } else {
+                let (x_src, y_src) = (le_i32(r, 44)?, le_i32(r, 48)?);
+                let src_size = if record_type == emr::STRETCHBLT {
+                    (le_i32(r, 100)?, le_i32(r, 104)?)
+                } else {
+                    (dest.2 as i32, dest.3 as i32)
+                };
+                let bmi = r.get(off_bmi..off_bmi.checked_add(cb_bmi)?)?;
+                let bits = r.get(off_bits..off_bits.checked_add(cb_bits)?)?;
+                canvas.draw_dib(bmi, bits, (x_src, y_src, src_size.0, src_size.1), false, dest, rop);
+            }
+        }
+        emr::STRETCHDIBITS => {
+            let src = (le_i32(r, 32)?, le_i32(r, 36)?, le_i32(r, 40)?, le_i32(r, 44)?);
+            let (off_bmi, cb_bmi) = (le_u32(r, 48)? as usize, le_u32(r, 52)? as usize);
+            let (off_bits, cb_bits) = (le_u32(r, 56)? as usize, le_u32(r, 60)? as usize);
+            let rop = le_u32(r, 68)?;
+            let dest = (
+                le_i32(r, 24)? as f32,
+                le_i32(r, 28)? as f32,
+                le_i32(r, 72)? as f32,
+                le_i32(r, 76)? as f32,
+            );
+            let bmi = r.get(off_bmi..off_bmi.checked_add(cb_bmi)?)?;
+            let bits = r.get(off_bits..off_bits.checked_add(cb_bits)?)?;
+            canvas.draw_dib(bmi, bits, src, true, dest, rop);
+        }
+        _ => {}
+    }
+    Some(())
+}
+
+// =============================================================================
+// WMF playback
+// =============================================================================
+
+mod meta {
+    pub(super) const EOF: u16 = 0x0000;
+    pub(super) const SAVEDC: u16 = 0x001E;
+    pub(super) const CREATEPALETTE: u16 = 0x00F7;
+    pub(super) const SETMAPMODE: u16 = 0x0103;
+    pub(super) const SETPOLYFILLMODE: u16 = 0x0106;
+    pub(super) const RESTOREDC: u16 = 0x0127;
+    pub(super) const SELECTOBJECT: u16 = 0x012D;
+    pub(super) const DIBCREATEPATTERNBRUSH: u16 = 0x0142;
+    pub(super) const DELETEOBJECT: u16 = 0x01F0;
+    pub(super) const CREATEPATTERNBRUSH: u16 = 0x01F9;
+    pub(super) const SETWINDOWORG: u16 = 0x020B;
+    pub(super) const SETWINDOWEXT: u16 = 0x020C;
+    pub(super) const LINETO: u16 = 0x0213;
+    pub(super) const MOVETO: u16 = 0x0214;
+    pub(super) const CREATEPENINDIRECT: u16 = 0x02FA;
+    pub(super) const CREATEFONTINDIRECT: u16 = 0x02FB;
+    pub(super) const CREATEBRUSHINDIRECT: u16 = 0x02FC;
+    pub(super) const POLYGON: u16 = 0x0324;
+    pub(super) const POLYLINE: u16 = 0x0325;
+    pub(super) const ELLIPSE: u16 = 0x0418;
+    pub(super) const RECTANGLE: u16 = 0x041B;
+    pub(super) const POLYPOLYGON: u16 = 0x0538;
+    pub(super) const ROUNDRECT: u16 = 0x061C;
+    pub(super) const CREATEREGION: u16 = 0x06FF;
+    pub(super) const DIBBITBLT: u16 = 0x0940;
+    pub(super) const DIBSTRETCHBLT: u16 = 0x0B41;
+    pub(super) const STRETCHDIB: u16 = 0x0F43;
+}
+
+/// Size of the WMF META_HEADER in bytes
+const WMF_HEADER_SIZE: usize = 18;
+
+fn rasterize_wmf(data: &[u8], map_mode: u32, x_ext: i32, y_ext: i32) -> ClipboardResult<Pixmap> {
+    let header_words = le_u16(data, 2).unwrap_or(0) as usize;
+    if data.len() < WMF_HEADER_SIZE || header_words * 2 != WMF_HEADER_SIZE {
+        return Err(ClipboardError::ImageDecode("Not a Windows metafile".to_string()));
+    }
+
+    let px_per_mm = DEFAULT_DPI / 25.4;
+    let (width, height) = match map_mode {
+        MM_ISOTROPIC | MM_ANISOTROPIC if x_ext > 0 && y_ext > 0 => {
+            // Suggested size in 0.01 mm
+            (x_ext as f32 * px_per_mm / 100.0, y_ext as f32 * px_per_mm / 100.0)
+        }
+        MM_ISOTROPIC | MM_ANISOTROPIC => {
+            // No suggested size: use the window extent as pixels
+            let ext = wmf_window_ext(data).unwrap_or((0.0, 0.0));
+            (ext.0.abs(), ext.1.abs())
+        }
+        MM_TEXT => (x_ext as f32, y_ext as f32),
+        _ => {
+            let mm = mm_per_unit(map_mode);
+            (x_ext as f32 * mm * px_per_mm, y_ext as f32 * mm * px_per_mm)
+        }
+    };
+
+    let (pixmap, scale) = create_canvas(width, height)?;
+    let mut state = DcState::new(map_mode);
+    // The player maps the picture's window onto the output rectangle
+    state.viewport_ext = (width, height);
+    let mut canvas = Canvas::new(
+        pixmap,
+        Transform::from_scale(scale, scale),
+        (px_per_mm, px_per_mm),
+        state,
+    );
+
+    let mut offset = WMF_HEADER_SIZE;
+    while offset + 6 <= data.len() {
+        let size = (le_u32(data, offset).unwrap_or(0) as usize).saturating_mul(2);
+        let function = le_u16(data, offset + 4).unwrap_or(0);
+        if size < 6 || offset + size > data.len() {
+            tracing::debug!("WMF record at {} has invalid size {}", offset, size);
+            break;
+        }
+        if function == meta::EOF {
+            break;
+        }
+
+        let record = &data[offset..offset + size];
+        if play_wmf_record(&mut canvas, function, record).is_none() {
+            tracing::trace!("Skipped truncated WMF record {:#06x} at {}", function, offset);
+        }
+        offset += size;
+    }
+
+    Ok(canvas.pixmap)
+}
+
+/// Find the window extent set by a WMF, used when the picture has no size.
+fn wmf_window_ext(data: &[u8]) -> Option<(f32, f32)> {
+    let mut offset = WMF_HEADER_SIZE;
+    while offset + 6 <= data.len() {
+        let size = (le_u32(data, offset)? as usize).saturating_mul(2);
+        if size < 6 {
+            return None;
+        }
+        if le_u16(data, offset + 4)? == meta::SETWINDOWEXT {
+            return Some((le_i16(data, offset + 8)? as f32, le_i16(data, offset + 6)? as f32));
+        }
+        offset += size;
+    }
+    None
+}
+
+/// Play one WMF record. Returns `None` if the record is truncated.
+fn play_wmf_record(canvas: &mut Canvas, function: u16, r: &[u8]) -> Option<()> {
+    // Parameters are 16-bit words, points are stored y before x
+    let word = |i: usize| le_i16(r, 6 + i * 2);
+    let yx = |i: usize| -> Option<(f32, f32)> { Some((word(i + 1)? as f32, word(i)? as f32)) };
+
+    match function {
+        meta::SETWINDOWORG => canvas.state.window_org = yx(0)?,
+        meta::SETWINDOWEXT => canvas.state.window_ext = yx(0)?,
+        meta::SETMAPMODE => canvas.state.map_mode = word(0)? as u32,
+        meta::SETPOLYFILLMODE => canvas.state.fill_rule = poly_fill_rule(word(0)? as u32),
+        meta::SAVEDC => canvas.save(),
+        meta::RESTOREDC => canvas.restore(word(0)? as i32),
+        meta::MOVETO => canvas.move_to(yx(0)?),
+        meta::LINETO => canvas.line_to(yx(0)?),
+        meta::POLYGON | meta::POLYLINE => {
+            let count = word(0)?.max(0) as usize;
+            let points = read_points16(r, 8, count)?;
+            canvas.poly(
+                if function == meta::POLYGON {
+                    emr::POLYGON
+                } else {
+                    emr::POLYLINE
+                },
+                &points,
+            );
+        }
+        meta::POLYPOLYGON => {
+            let polys = word(0)?.max(0) as usize;
+            let counts = (0..polys)
+                .map(|i| word(1 + i).map(|c| c.max(0) as usize))
+                .collect::<Option<Vec<_>>>()?;
+            let points = read_points16(r, 8 + polys * 2, counts.iter().sum())?;
+            canvas.poly_poly(&points, &counts, true);
+        }
+        meta::RECTANGLE | meta::ELLIPSE => {
+            // bottom, right, top, left
+            let rect = logical_rect(word(3)? as i32, word(2)? as i32, word(1)? as i32, word(0)? as i32);
+            canvas.shape(
+                rect,
+                if function == meta::ELLIPSE {
+                    Shape::Ellipse
+                } else {
+                    Shape::Rect
+                },
+            );
+        }
+        meta::ROUNDRECT => {
+            // height, width, bottom, right, top, left
+            let rect = logical_rect(word(5)? as i32, word(4)? as i32, word(3)? as i32, word(2)? as i32);
+            canvas.shape(rect, Shape::RoundRect(word(1)? as f32 / 2.0, word(0)? as f32 / 2.0));
+        }
+        meta::CREATEPENINDIRECT => {
+            let pen = Pen::new(le_u16(r, 6)? as u32, word(1)? as f32, le_u32(r, 12)?);
+            canvas.add_wmf_object(GdiObject::Pen(pen));
+        }
+        meta::CREATEBRUSHINDIRECT => {
+            let brush = Brush::new(le_u16(r, 6)? as u32, le_u32(r, 8)?);
+            canvas.add_wmf_object(GdiObject::Brush(brush));
+        }
+        meta::CREATEFONTINDIRECT
+        | meta::CREATEPALETTE
+        | meta::CREATEPATTERNBRUSH
+        | meta::DIBCREATEPATTERNBRUSH
+        | meta::CREATEREGION => {
+            // Not rendered, but they occupy object table slots
+            canvas.add_wmf_object(GdiObject::Other);
+        }
+        meta::SELECTOBJECT => canvas.select_object(le_u16(r, 6)? as u32),
+        meta::DELETEOBJECT => canvas.delete_object(le_u16(r, 6)? as u32),
+        meta::STRETCHDIB => {
+            let rop = le_u32(r, 6)?;
+            // rop, usage, srcH, srcW, ySrc, xSrc, destH, destW, yDst, xDst, DIB
+            let src = (word(6)? as i32, word(5)? as i32, word(4)? as i32, word(3)? as i32);
+            let dest = (word(10)? as f32, word(9)? as f32, word(8)? as f32, word(7)? as f32);
+            canvas.draw_dib(r.get(28..)?, &[], src, true, dest, rop);
+        }
+        meta::DIBSTRETCHBLT | meta::DIBBITBLT => {
+            let rop = le_u32(r, 6)?;
+            let stretch = function == meta::DIBSTRETCHBLT;
+            // A record without bitmap has one extra reserved word
+            let plain_words = (function >> 8) as usize + 3;
+            let has_bitmap = r.len() / 2 != plain_words;
+            let mut i = 2;
+            let mut next = || {
+                let value = word(i);
+                i += 1;
+                value
+            };
+            let src_size = if stretch {
+                let h = next()? as i32;
+                (next()? as i32, h)
+            } else {
+                (0, 0)
+            };
+            let y_src = next()? as i32;
+            let x_src = next()? as i32;
+            if !has_bitmap {
+                next()?;
+            }
+            let h = next()? as f32;
+            let w = next()? as f32;
+            let y = next()? as f32;
+            let x = next()? as f32;
+            let dest = (x, y, w, h);
+            if has_bitmap {
+                let src_size = if stretch { src_size } else { (w as i32, h as i32) };
+                let dib = r.get(6 + i * 2..)?;
+                canvas.draw_dib(dib, &[], (x_src, y_src, src_size.0, src_size.1), false, dest, rop);
+            } else {
+                canvas.pattern_blt(dest, rop);
+            }
+        }
+        _ => {}
+    }
+    Some(())
+}
+
+// =============================================================================
+// GDI state and rendering
+// =============================================================================
+
+const PS_NULL: u32 = 5;
+const PS_STYLE_MASK: u32 = 0x0000_000F;
+const PS_ENDCAP_SQUARE: u32 = 0x0000_0100;
+const PS_ENDCAP_FLAT: u32 = 0x0000_0200;
+const PS_JOIN_BEVEL: u32 = 0x0000_1000;
+const PS_JOIN_MITER: u32 = 0x0000_2000;
+const PS_GEOMETRIC: u32 = 0x0001_0000;
+const BS_NULL: u32 = 1;
+
+#[derive(Debug, Clone)]
+struct Pen {
+    style: u32,
+    /// Width in logical units, 0 for a one-pixel pen
+    width: f32,
+    /// `None` for the null pen
+    color: Option<Color>,
+}
+
+impl Pen {
+    fn new(style: u32, width: f32, colorref: u32) -> Self {
+        let color = (style & PS_STYLE_MASK != PS_NULL).then(|| colorref_to_color(colorref));
+        Self { style, width, color }
+    }
+
+    fn stroke(&self, scale: f32) -> Stroke {
+        let width = self.width.abs() * scale;
+        let mut stroke = Stroke {
+            width,
+            line_cap: match self.style & 0x0F00 {
+                PS_ENDCAP_SQUARE => LineCap::Square,
+                PS_ENDCAP_FLAT => LineCap::Butt,
+                _ => LineCap::Round,
+            },
+            line_join: match self.style & 0xF000 {
+                PS_JOIN_BEVEL => LineJoin::Bevel,
+                PS_JOIN_MITER => LineJoin::Miter,
+                _ => LineJoin::Round,
+            },
+            ..Stroke::default()
+        };
+
+        let unit = width.max(1.0);
+        let dashes: &[f32] = match self.style & PS_STYLE_MASK {
+            1 => &[18.0, 6.0],
+            2 => &[3.0, 3.0],
+            3 => &[9.0, 6.0, 3.0, 6.0],
+            4 => &[9.0, 3.0, 3.0, 3.0, 3.0, 3.0],
+            _ => &[],
+        };
+        if !dashes.is_empty() {
+            stroke.dash = StrokeDash::new(dashes.iter().map(|d| d * unit).collect(), 0.0);
+        }
+        stroke
+    }
+}
+
+#[derive(Debug, Clone)]
+struct Brush {
+    /// `None` for the null brush; hatched brushes are drawn solid
+    color: Option<Color>,
+}
+
+impl Brush {
+    fn new(style: u32, colorref: u32) -> Self {
+        Self {
+            color: (style != BS_NULL).then(|| colorref_to_color(colorref)),
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+enum GdiObject {
+    Pen(Pen),
+    Brush(Brush),
+    /// Objects that are tracked but not rendered (fonts, palettes, regions)
+    Other,
+}
+
+/// Device context state saved and restored by SaveDC/RestoreDC
+#[derive(Debug, Clone)]
+struct DcState {
+    pen: Pen,
+    brush: Brush,
+    fill_rule: FillRule,
+    map_mode: u32,
+    window_org: (f32, f32),
+    window_ext: (f32, f32),
+    viewport_org: (f32, f32),
+    viewport_ext: (f32, f32),
+    world: Transform,
+    /// Current position in logical units
+    position: (f32, f32),
+}
+
+impl DcState {
+    fn new(map_mode: u32) -> Self {
+        Self {
+            pen: Pen::new(0, 0.0, 0x0000_0000),
+            brush: Brush::new(0, 0x00FF_FFFF),
+            fill_rule: FillRule::EvenOdd,
+            map_mode,
+            window_org: (0.0, 0.0),
+            window_ext: (1.0, 1.0),
+            viewport_org: (0.0, 0.0),
+            viewport_ext: (1.0, 1.0),
+            world: Transform::identity(),
+            position: (0.0, 0.0),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+enum Shape {
+    Rect,
+    Ellipse,
+    RoundRect(f32, f32),
+}
+
+/// Path bracket being recorded between BeginPath and EndPath, in device space
+#[derive(Debug, Default)]
+struct PathBracket {
+    builder: PathBuilder,
+    figure_open: bool,
+}
+
+struct Canvas {
+    pixmap: Pixmap,
+    /// Device → canvas pixels
+    base: Transform,
+    /// Reference device pixels per millimeter, for the metric map modes
+    px_per_mm: (f32, f32),
+    state: DcState,
+    saved: Vec<DcState>,
+    objects: std::collections::HashMap<u32, GdiObject>,
+    bracket: Option<PathBracket>,
+    /// Path closed by EndPath, consumed by the next path drawing record
+    path: Option<Path>,
+}
+
+impl Canvas {
+    fn new(pixmap: Pixmap, base: Transform, px_per_mm: (f32, f32), state: DcState) -> Self {
+        Self {
+            pixmap,
+            base,
+            px_per_mm,
+            state,
+            saved: Vec::new(),
+            objects: std::collections::HashMap::new(),
+            bracket: None,
+            path: None,
+        }
+    }
+
+    /// Logical → canvas pixel transform for the current state
+    fn transform(&self) -> Transform {
+        let s = &self.state;
+        let (sx, sy) = match s.map_mode {
+            MM_ISOTROPIC | MM_ANISOTROPIC => {
+                let sx = ratio(s.viewport_ext.0, s.window_ext.0);
+                let sy = ratio(s.viewport_ext.1, s.window_ext.1);
+                if s.map_mode == MM_ISOTROPIC {
+                    let m = sx.abs().min(sy.abs());
+                    (m.copysign(sx), m.copysign(sy))
+                } else {
+                    (sx, sy)
+                }
+            }
+            MM_TEXT => (1.0, 1.0),
+            mode => {
+                let mm = mm_per_unit(mode);
+                (mm * self.px_per_mm.0, -mm * self.px_per_mm.1)
+            }
+        };
+        let page = Transform::from_row(
+            sx,
+            0.0,
+            0.0,
+            sy,
+            s.viewport_org.0 - s.window_org.0 * sx,
+            s.viewport_org.1 - s.window_org.1 * sy,
+        );
+        self.base.pre_concat(page).pre_concat(s.world)
+    }
+
+    // --- Objects ---
+
+    fn create_object(&mut self, index: u32, object: GdiObject) {
+        self.objects.insert(index, object);
+    }
+
+    /// WMF objects take the lowest free slot in the object table
+    fn add_wmf_object(&mut self, object: GdiObject) {
+        let index = (0..).find(|i| !self.objects.contains_key(i)).unwrap_or(0);
+        self.objects.insert(index, object);
+    }
+
+    fn select_object(&mut self, index: u32) {
+        if index & 0x8000_0000 != 0 {
+            self.select_stock_object(index & 0x7FFF_FFFF);
+            return;
+        }
+        match self.objects.get(&index) {
+            Some(GdiObject::Pen(pen)) => self.state.pen = pen.clone(),
+            Some(GdiObject::Brush(brush)) => self.state.brush = brush.clone(),
+            _ => {}
+        }
+    }
+
+    fn select_stock_object(&mut self, stock: u32) {
+        match stock {
+            0..=4 => {
+                let gray = [0xFF, 0xC0, 0x80, 0x40, 0x00][stock as usize];
+                self.state.brush = Brush::new(0, u32::from_le_bytes([gray, gray, gray, 0]));
+            }
+            5 => self.state.brush = Brush::new(BS_NULL, 0),
+            6 => self.state.pen = Pen::new(0, 0.0, 0x00FF_FFFF),
+            7 => self.state.pen = Pen::new(0, 0.0, 0),
+            8 => self.state.pen = Pen::new(PS_NULL, 0.0, 0),
+            _ => {}
+        }
+    }
+
+    fn delete_object(&mut self, index: u32) {
+        self.objects.remove(&index);
+    }
+
+    // --- State stack ---
+
+    fn save(&mut self) {
+        self.saved.push(self.state.clone());
+    }
+
+    /// Restore a saved state; negative values are relative to the top
+    fn restore(&mut self, which: i32) {
+        let len = self.saved.len() as i64;
+        let target = if which < 0 {
+            len + which as i64
+        } else {
+            which as i64 - 1
+        };
+        if (0..len).contains(&target) {
+            self.saved.truncate(target as usize + 1);
+            if let Some(state) = self.saved.pop() {
+                self.state = state;
+            }
+        }
+    }
+
+    // --- Drawing primitives ---
+
+    fn move_to(&mut self, point: (f32, f32)) {
+        self.state.position = point;
+        if let Some(bracket) = self.bracket.as_mut() {
+            bracket.figure_open = false;
+        }
+    }
+
+    fn line_to(&mut self, point: (f32, f32)) {
+        let from = self.state.position;
+        self.state.position = point;
+        let mut pb = PathBuilder::new();
+        pb.move_to(from.0, from.1);
+        pb.line_to(point.0, point.1);
+        self.emit_open(pb);
+    }
+
+    /// Polygon/polyline/Bézier records, keyed by their 32-bit EMF record type
+    fn poly(&mut self, record_type: u32, points: &[(f32, f32)]) {
+        let Some(&first) = points.first() else {
+            return;
+        };
+        let mut pb = PathBuilder::new();
+
+        match record_type {
+            emr::POLYGON | emr::POLYLINE => {
+                pb.move_to(first.0, first.1);
+                for p in &points[1..] {
+                    pb.line_to(p.0, p.1);
+                }
+                if record_type == emr::POLYGON {
+                    pb.close();
+                    if let Some(path) = pb.finish() {
+                        self.emit_closed(path);
+                    }
+                    return;
+                }
+            }
+            emr::POLYBEZIER => {
+                pb.move_to(first.0, first.1);
+                for c in points[1..].chunks_exact(3) {
+                    pb.cubic_to(c[0].0, c[0].1, c[1].0, c[1].1, c[2].0, c[2].1);
+                }
+            }
+            emr::POLYLINETO | emr::POLYBEZIERTO => {
+                let from = self.state.position;
+                pb.move_to(from.0, from.1);
+                if record_type == emr::POLYLINETO {
+                    for p in points {
+                        pb.line_to(p.0, p.1);
+                    }
+                } else {
+                    for c in points.chunks_exact(3) {
+                        pb.cubic_to(c[0].0, c[0].1, c[1].0, c[1].1, c[2].0, c[2].1);
+                    }
+                }
+                self.state.position = pb.last_point().map_or(from, |p| (p.x, p.y));
+            }
+            _ => return,
+        }
+        self.emit_open(pb);
+    }
+
+    fn poly_poly(&mut self, points: &[(f32, f32)], counts: &[usize], closed: bool) {
+        let mut pb = PathBuilder::new();
+        let mut rest = points;
+        for &count in counts {
+            if count > rest.len() {
+                break;
+            }
+            let (poly, tail) = rest.split_at(count);
+            rest = tail;
+            if let Some((first, others)) = poly.split_first() {
+                pb.move_to(first.0, first.1);
+                for p in others {
+                    pb.line_to(p.0, p.1);
+                }
+                if closed {
+                    pb.close();
+                }
+            }
+        }
+        if closed {
+            if let Some(path) = pb.finish() {
+                self.emit_closed(path);
+            }
+        } else {
+            self.emit_open(pb);
+        }
+    }
+
+    fn shape(&mut self, rect: Option<Rect>, shape: Shape) {
+        let Some(rect) = rect else {
+            return;
+        };
+        let path = match shape {
+            Shape::Rect => Some(PathBuilder::from_rect(rect)),
+            Shape::Ellipse => PathBuilder::from_oval(rect),
+            Shape::RoundRect(rx, ry) => rounded_rect(rect, rx.abs(), ry.abs()),
+        };
+        if let Some(path) = path {
+            self.emit_closed(path);
+        }
+    }
+
+    /// Stroke an open figure, or append it to the path bracket
+    fn emit_open(&mut self, pb: PathBuilder) {
+        let Some(path) = pb.finish().and_then(|p| p.transform(self.transform())) else {
+            return;
+        };
+        if let Some(bracket) = self.bracket.as_mut() {
+            // Continue the current figure instead of starting a new one
+            for (i, segment) in path.segments().enumerate() {
+                match segment {
+                    PathSegment::MoveTo(_) if i == 0 && bracket.figure_open => {}
+                    PathSegment::MoveTo(p) => bracket.builder.move_to(p.x, p.y),
+                    PathSegment::LineTo(p) => bracket.builder.line_to(p.x, p.y),
+                    PathSegment::QuadTo(p1, p) => bracket.builder.quad_to(p1.x, p1.y, p.x, p.y),
+                    PathSegment::CubicTo(p1, p2, p) => bracket.builder.cubic_to(p1.x, p1.y, p2.x, p2.y, p.x, p.y),
+                    PathSegment::Close => bracket.builder.close(),
+                }
+            }
+            bracket.figure_open = true;
+            return;
+        }
+        self.stroke(&path);
+    }
+
+    /// Fill and stroke a closed figure, or append it to the path bracket
+    fn emit_closed(&mut self, path: Path) {
+        let Some(path) = path.transform(self.transform()) else {
+            return;
+        };
+        if let Some(bracket) = self.bracket.as_mut() {
+            bracket.builder.push_path(&path);
+            bracket.figure_open = false;
+            return;
+        }
+        self.fill(&path);
+        self.stroke(&path);
+    }
+
+    fn fill(&mut self, path: &Path) {
+        if let Some(color) = self.state.brush.color {
+            let paint = solid_paint(color);
+            self.pixmap
+                .fill_path(path, &paint, self.state.fill_rule, Transform::identity(), None);
+        }
+    }
+
+    fn stroke(&mut self, path: &Path) {
+        let pen = &self.state.pen;
+        if let Some(color) = pen.color {
+            let t = self.transform();
+            let scale = (t.sx * t.sy - t.kx * t.ky).abs().sqrt();
+            let paint = solid_paint(color);
+            self.pixmap
+                .stroke_path(path, &paint, &pen.stroke(scale), Transform::identity(), None);
+        }
+    }
+
+    // --- Path brackets ---
+
+    fn begin_path(&mut self) {
+        self.bracket = Some(PathBracket::default());
+        self.path = None;
+    }
+
+    fn end_path(&mut self) {
+        self.path = self.bracket.take().and_then(|b| b.builder.finish());
+    }
+
+    fn close_figure(&mut self) {
+        if let Some(bracket) = self.bracket.as_mut() {
+            bracket.builder.close();
+            bracket.figure_open = false;
+        }
+    }
+
+    fn abort_path(&mut self) {
+        self.bracket = None;
+        self.path = None;
+    }
+
+    fn draw_path(&mut self, fill: bool, stroke: bool) {
+        if let Some(path) = self.path.take() {
+            if fill {
+                self.fill(&path);
+            }
+            if stroke {
+                self.stroke(&path);
+            }
+        }
+    }
+
+    // --- Bitmaps ---
+
+    /// Fill a destination rectangle for a BitBlt without source bitmap
+    fn pattern_blt(&mut self, dest: (f32, f32, f32, f32), rop: u32) {
+        let color = match rop {
+            PATCOPY => self.state.brush.color,
+            BLACKNESS => Some(Color::BLACK),
+            WHITENESS => Some(Color::WHITE),
+            _ => None,
+        };
+        let (Some(color), Some(rect)) = (color, Rect::from_xywh(dest.0, dest.1, dest.2, dest.3)) else {
+            return;
+        };
+        if let Some(path) = PathBuilder::from_rect(rect).transform(self.transform()) {
+            self.pixmap.fill_path(
+                &path,
+                &solid_paint(color),
+                FillRule::Winding,
+                Transform::identity(),
+                None,
+            );
+        }
+    }
+
+    /// Draw a DIB (header, optional color table and bits) into a logical rectangle.
+    ///
+    /// `bottom_up_src` selects the StretchDIBits convention, where the source
+    /// origin of a bottom-up bitmap is its lower-left corner.
+    fn draw_dib(
+        &mut self,
+        bmi: &[u8],
+        bits: &[u8],
+        src: (i32, i32, i32, i32),
+        bottom_up_src: bool,
+        dest: (f32, f32, f32, f32),
+        rop: u32,
+    ) {
+        if rop != SRCCOPY {
+            tracing::trace!("Skipping bitmap with raster operation {:#010x}", rop);
+            return;
+        }
+        let mut dib = bmi.to_vec();
+        dib.extend_from_slice(bits);
+        let image = match crate::image::parse_dib_to_image(&dib) {
+            Ok(image) => image,
+            Err(e) => {
+                tracing::debug!("Skipping undecodable metafile bitmap: {}", e);
+                return;
+            }
+        };
+
+        let (width, height) = (image.width(), image.height());
+        let bottom_up = le_i32(bmi, 8).unwrap_or(0) > 0;
+        let (x, w) = (src.0.max(0) as u32, src.2.unsigned_abs());
+        let h = src.3.unsigned_abs();
+        let y = if bottom_up_src && bottom_up {
+            (height as i64 - src.1 as i64 - h as i64).max(0) as u32
+        } else {
+            src.1.max(0) as u32
+        };
+        let cropped = if (x, y, w, h) == (0, 0, width, height) || w == 0 || h == 0 {
+            image.to_rgba8()
+        } else {
+            image.crop_imm(x, y, w, h).to_rgba8()
+        };
+
+        let Some(pixmap) = rgba_to_pixmap(&cropped) else {
+            return;
+        };
+        let transform = self
+            .transform()
+            .pre_translate(dest.0, dest.1)
+            .pre_scale(dest.2 / pixmap.width() as f32, dest.3 / pixmap.height() as f32);
+        let paint = PixmapPaint {
+            quality: tiny_skia::FilterQuality::Bilinear,
+            ..PixmapPaint::default()
+        };
+        self.pixmap.draw_pixmap(0, 0, pixmap.as_ref(), &paint, transform, None);
+    }
+}
+
+// =============================================================================
+// Helpers
+// =============================================================================
+
+/// Create the white canvas, returning the uniform scale that fits the limit
+fn create_canvas(width: f32, height: f32) -> ClipboardResult<(Pixmap, f32)> {
+    if !(width.is_finite() && height.is_finite()) || width < 1.0 || height < 1.0 {
+        return Err(ClipboardError::ImageDecode(format!(
+            "Metafile has no usable size: {}x{}",
+            width, height
+        )));
+    }
+
+    let max = MAX_METAFILE_DIMENSION as f32;
+    let scale = (max / width).min(max / height).min(1.0);
+    let w = (width * scale).round().max(1.0) as u32;
+    let h = (height * scale).round().max(1.0) as u32;
+
+    let mut pixmap =
+        Pixmap::new(w, h).ok_or_else(|| ClipboardError::ImageDecode(format!("Cannot allocate {}x{} canvas", w, h)))?;
+    pixmap.fill(Color::WHITE);
+    Ok((pixmap, scale))
+}
+
+fn encode_png(pixmap: &Pixmap) -> ClipboardResult<Vec<u8>> {
+    let data = pixmap
+        .pixels()
+        .iter()
+        .flat_map(|p| {
+            let c = p.demultiply();
+            [c.red(), c.green(), c.blue(), c.alpha()]
+        })
+        .collect();
+    let image = RgbaImage::from_raw(pixmap.width(), pixmap.height(), data)
+        .ok_or_else(|| ClipboardError::ImageEncode("Canvas size mismatch".to_string()))?;
+
+    let mut png_data = Vec::new();
+    DynamicImage::ImageRgba8(image)
+        .write_to(&mut std::io::Cursor::new(&mut png_data), ImageFormat::Png)
+        .map_err(|e| ClipboardError::ImageEncode(e.to_string()))?;
+    Ok(png_data)
+}
+
+fn rgba_to_pixmap(image: &RgbaImage) -> Option<Pixmap> {
+    let mut pixmap = Pixmap::new(image.width(), image.height())?;
+    // 32-bit BI_RGB bitmaps in metafiles usually leave alpha zeroed
+    let opaque = image.pixels().all(|p| p[3] == 0);
+    for (dst, src) in pixmap.pixels_mut().iter_mut().zip(image.pixels()) {
+        let alpha = if opaque { 255 } else { src[3] };
+        *dst = ColorU8::from_rgba(src[0], src[1], src[2], alpha).premultiply();
+    }
+    Some(pixmap)
+}
+
+fn rounded_rect(rect: Rect, rx: f32, ry: f32) -> Option<Path> {
+    let rx = rx.min(rect.width() / 2.0);
+    let ry = ry.min(rect.height() / 2.0);
+    if rx <= 0.0 || ry <= 0.0 {
+        return Some(PathBuilder::from_rect(rect));
+    }
+    // Cubic approximation of a quarter ellipse
+    const K: f32 = 0.552_284_8;
+    let (l, t, r, b) = (rect.left(), rect.top(), rect.right(), rect.bottom());
+    let (kx, ky) = (rx * K, ry * K);
+
+    let mut pb = PathBuilder::new();
+    pb.move_to(l + rx, t);
+    pb.line_to(r - rx, t);
+    pb.cubic_to(r - rx + kx, t, r, t + ry - ky, r, t + ry);
+    pb.line_to(r, b - ry);
+    pb.cubic_to(r, b - ry + ky, r - rx + kx, b, r - rx, b);
+    pb.line_to(l + rx, b);
+    pb.cubic_to(l + rx - kx, b, l, b - ry + ky, l, b - ry);
+    pb.line_to(l, t + ry);
+    pb.cubic_to(l, t + ry - ky, l + rx - kx, t, l + rx, t);
+    pb.close();
+    pb.finish()
+}
+
+/// Normalize a logical rectangle given as left, top, right, bottom
+fn logical_rect(l: i32, t: i32, r: i32, b: i32) -> Option<Rect> {
+    Rect::from_ltrb(l.min(r) as f32, t.min(b) as f32, l.max(r) as f32, t.max(b) as f32)
+}
+
+fn solid_paint(color: Color) -> Paint<'static> {
+    let mut paint = Paint::default();
+    paint.set_color(color);
+    paint.anti_alias = true;
+    paint
+}
+
+/// COLORREF is 0x00BBGGRR
+fn colorref_to_color(colorref: u32) -> Color {
+    let [r, g, b, _] = colorref.to_le_bytes();
+    Color::from_rgba8(r, g, b, 255)
+}
+
+fn poly_fill_rule(mode: u32) -> FillRule {
+    // ALTERNATE = 1, WINDING = 2
+    if mode == 2 {
+        FillRule::Winding
+    } else {
+        FillRule::EvenOdd
+    }
+}
+
+/// Millimeters per logical unit for the fixed map modes
+fn mm_per_unit(map_mode: u32) -> f32 {
+    match map_mode {
+        MM_LOMETRIC => 0.1,
+        MM_HIMETRIC => 0.01,
+        MM_LOENGLISH => 0.254,
+        MM_HIENGLISH => 0.0254,
+        MM_TWIPS => 25.4 / 1440.0,
+        _ => 1.0,
+    }
+}
+
+fn ratio(num: f32, den: f32) -> f32 {
+    if den == 0.0 {
+        1.0
+    } else {
+        num / den
+    }
+}
+
+fn le_u16(data: &[u8], offset: usize) -> Option<u16> {
+    data.get(offset..offset + 2).map(|b| u16::from_le_bytes([b[0], b[1]]))
+}
+
+fn le_i16(data: &[u8], offset: usize) -> Option<i16> {
+    le_u16(data, offset).map(|v| v as i16)
+}
+
+fn le_u32(data: &[u8], offset: usize) -> Option<u32> {
+    data.get(offset..offset + 4)
+        .map(|b| u32::from_le_bytes([b[0], b[1], b[2], b[3]]))
+}
+
+fn le_i32(data: &[u8], offset: usize) -> Option<i32> {
+    le_u32(data, offset).map(|v| v as i32)
+}
+
+fn le_f32(data: &[u8], offset: usize) -> Option<f32> {
+    le_u32(data, offset).map(f32::from_bits)
+}
+
+fn read_rect(data: &[u8], offset: usize) -> Option<(i32, i32, i32, i32)> {
+    Some((
+        le_i32(data, offset)?,
+        le_i32(data, offset + 4)?,
+        le_i32(data, offset + 8)?,
+        le_i32(data, offset + 12)?,
+    ))
+}
+
+fn read_point32(data: &[u8], offset: usize) -> Option<(f32, f32)> {
+    Some((le_i32(data, offset)? as f32, le_i32(data, offset + 4)? as f32))
+}
+
+fn read_points32(data: &[u8], offset: usize, count: usize) -> Option<Vec<(f32, f32)>> {
+    data.get(offset..offset.checked_add(count.checked_mul(8)?)?)?;
+    (0..count).map(|i| read_point32(data, offset + i * 8)).collect()
+}
+
+fn read_points16(data: &[u8], offset: usize, count: usize) -> Option<Vec<(f32, f32)>> {
+    data.get(offset..offset.checked_add(count.checked_mul(4)?)?)?;
+    (0..count)
+        .map(|i| {
+            Some((
+                le_i16(data, offset + i * 4)? as f32,
+                le_i16(data, offset + i * 4 + 2)? as f32,
+            ))
+        })
+        .collect()
+}
+
+/// XFORM: eM11, eM12, eM21, eM22, eDx, eDy
+fn read_xform(data: &[u8], offset: usize) -> Option<Transform> {
+    let f = |i: usize| le_f32(data, offset + i * 4);
+    Some(Transform::from_row(f(0)?, f(1)?, f(2)?, f(3)?, f(4)?, f(5)?))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Minimal EMF writer for tests
+    struct EmfBuilder {
+        records: Vec<u8>,
+        bounds: (i32, i32, i32, i32),
+    }
+
+    impl EmfBuilder {
+        fn new(width: i32, height: i32) -> Self {
+            Self {
+                records: Vec::new(),
+                bounds: (0, 0, width - 1, height - 1),
+            }
+        }
+
+        fn record(mut self, record_type: u32, params: &[u32]) -> Self {
+            self.records.extend_from_slice(&record_type.to_le_bytes());
+            self.records
+                .extend_from_slice(&(8 + params.len() as u32 * 4).to_le_bytes());
+            for p in params {
+                self.records.extend_from_slice(&p.to_le_bytes());
+            }
+            self
+        }
+
+        fn build(self) -> Vec<u8> {
+            let mut header = [0u32; 22];
+            header[0] = emr::HEADER;
+            header[1] = 88;
+            header[2] = self.bounds.0 as u32;
+            header[3] = self.bounds.1 as u32;
+            header[4] = self.bounds.2 as u32;
+            header[5] = self.bounds.3 as u32;
+            header[10] = EMF_SIGNATURE;
+            header[18] = 1920; // device pixels
+            header[19] = 1080;
+            header[20] = 508; // device millimeters
+            header[21] = 286;
+
+            let mut out: Vec<u8> = header.iter().flat_map(|v| v.to_le_bytes()).collect();
+            out.extend_from_slice(&self.records);
+            out.extend_from_slice(&emr::EOF.to_le_bytes());
+            out.extend_from_slice(&20u32.to_le_bytes());
+            out.extend_from_slice(&[0; 12]);
+            out
+        }
+    }
+
+    fn decode(png: &[u8]) -> RgbaImage {
+        image::load_from_memory_with_format(png, ImageFormat::Png)
+            .unwrap()
+            .to_rgba8()
+    }
+
+    #[test]
+    fn test_emf_filled_rectangle() {
+        let emf = EmfBuilder::new(20, 10)
+            .record(emr::CREATEBRUSHINDIRECT, &[1, 0, 0x0000_00FF, 0])
+            .record(emr::SELECTOBJECT, &[1])
+            .record(emr::SELECTOBJECT, &[0x8000_0008]) // NULL_PEN
+            .record(emr::RECTANGLE, &[5, 0, 15, 10])
+            .build();
+
+        let image = decode(&emf_to_png(&emf).unwrap());
+        assert_eq!(image.dimensions(), (20, 10));
+        assert_eq!(image.get_pixel(10, 5), &image::Rgba([255, 0, 0, 255]));
+        assert_eq!(image.get_pixel(1, 5), &image::Rgba([255, 255, 255, 255]));
+    }
+
+    #[test]
+    fn test_emf_anisotropic_mapping() {
+        // Logical 0..1000 maps onto the 10x10 device area
+        let emf = EmfBuilder::new(10, 10)
+            .record(emr::SETMAPMODE, &[MM_ANISOTROPIC])
+            .record(emr::SETWINDOWEXTEX, &[1000, 1000])
+            .record(emr::SETVIEWPORTEXTEX, &[10, 10])
+            .record(emr::SELECTOBJECT, &[0x8000_0004]) // BLACK_BRUSH
+            .record(emr::POLYGON16, &[0, 0, 0, 0, 4, 0, 500 << 16, 500 | (500 << 16), 500])
+            .build();
+
+        let image = decode(&emf_to_png(&emf).unwrap());
+        assert_eq!(image.get_pixel(2, 2), &image::Rgba([0, 0, 0, 255]));
+        assert_eq!(image.get_pixel(8, 8), &image::Rgba([255, 255, 255, 255]));
+    }
+
+    #[test]
+    fn test_emf_path_bracket() {
+        let green_brush = [1, 0, 0x0000_FF00, 0];
+        let emf = EmfBuilder::new(10, 10)
+            .record(emr::CREATEBRUSHINDIRECT, &green_brush)
+            .record(emr::SELECTOBJECT, &[1])
+            .record(emr::BEGINPATH, &[])
+            .record(emr::MOVETOEX, &[0, 0])
+            .record(emr::LINETO, &[10, 0])
+            .record(emr::LINETO, &[10, 10])
+            .record(emr::LINETO, &[0, 10])
+            .record(emr::CLOSEFIGURE, &[])
+            .record(emr::ENDPATH, &[])
+            .record(emr::FILLPATH, &[0, 0, 0, 0])
+            .build();
+
+        let image = decode(&emf_to_png(&emf).unwrap());
+        assert_eq!(image.get_pixel(5, 5), &image::Rgba([0, 255, 0, 255]));
+    }
+
+    #[test]
+    fn test_emf_stretchdibits() {
+        // 1x1 blue 24-bit DIB stretched over the whole canvas
+        let mut bmi = [0u8; 40];
+        bmi[0] = 40;
+        bmi[4] = 1;
+        bmi[8] = 1;
+        bmi[12] = 1;
+        bmi[14] = 24;
+        let bits = [0xFF, 0, 0, 0];
+
+        let mut params = vec![0u32; 18];
+        params[4] = 0; // xDest
+        params[5] = 0; // yDest
+        params[8] = 1; // cxSrc
+        params[9] = 1; // cySrc
+        params[10] = 80; // offBmiSrc
+        params[11] = 40;
+        params[12] = 120; // offBitsSrc
+        params[13] = 4;
+        params[15] = SRCCOPY;
+        params[16] = 4; // cxDest
+        params[17] = 4; // cyDest
+        let mut words: Vec<u32> = params;
+        words.extend(bmi.chunks(4).map(|c| u32::from_le_bytes([c[0], c[1], c[2], c[3]])));
+        words.push(u32::from_le_bytes(bits));
+
+        let emf = EmfBuilder::new(4, 4).record(emr::STRETCHDIBITS, &words).build();
+        let image = decode(&emf_to_png(&emf).unwrap());
+        assert_eq!(image.get_pixel(2, 2), &image::Rgba([0, 0, 255, 255]));
+    }
+
+    #[test]
+    fn test_emf_rejects_garbage() {
+        assert!(emf_to_png(b"not a metafile").is_err());
+        assert!(emf_to_png(&[]).is_err());
+    }
+
+    #[test]
+    fn test_emf_scaled_to_limit() {
+        let mut emf = EmfBuilder::new(2, 1);
+        emf.bounds = (0, 0, 3 * MAX_METAFILE_DIMENSION as i32 - 1, 9);
+        let image = decode(&emf_to_png(&emf.build()).unwrap());
+        assert_eq!(image.width(), MAX_METAFILE_DIMENSION);
+    }
+
+    /// Build CF_METAFILEPICT data from WMF records (words after the size field)
+    fn metafilepict(x_ext: i32, y_ext: i32, records: &[&[u16]]) -> Vec<u8> {
+        let mut data = Vec::new();
+        data.extend_from_slice(&MM_ANISOTROPIC.to_le_bytes());
+        data.extend_from_slice(&x_ext.to_le_bytes());
+        data.extend_from_slice(&y_ext.to_le_bytes());
+        // META_HEADER: type, header size, version, size, objects, max record, members
+        for w in [1u16, 9, 0x0300, 0, 0, 4, 0, 0, 0] {
+            data.extend_from_slice(&w.to_le_bytes());
+        }
+        for record in records.iter().chain([&[meta::EOF][..]].iter()) {
+            data.extend_from_slice(&(2 + record.len() as u32).to_le_bytes());
+            for w in *record {
+                data.extend_from_slice(&w.to_le_bytes());
+            }
+        }
+        data
+    }
+
+    #[test]
+    fn test_metafilepict_ellipse() {
+        // 0x0000FF00 brush, NULL_PEN equivalent via PS_NULL pen
+        let data = metafilepict(
+            0,
+            0,
+            &[
+                &[meta::SETWINDOWORG, 0, 0],
+                &[meta::SETWINDOWEXT, 20, 40],
+                &[meta::CREATEBRUSHINDIRECT, 0, 0xFF00, 0, 0],
+                &[meta::CREATEPENINDIRECT, PS_NULL as u16, 0, 0, 0, 0],
+                &[meta::SELECTOBJECT, 0],
+                &[meta::SELECTOBJECT, 1],
+                &[meta::ELLIPSE, 20, 40, 0, 0],
+            ],
+        );
+
+        let image = decode(&metafilepict_to_png(&data).unwrap());
+        assert_eq!(image.dimensions(), (40, 20));
+        assert_eq!(image.get_pixel(20, 10), &image::Rgba([0, 255, 0, 255]));
+        assert_eq!(image.get_pixel(0, 0), &image::Rgba([255, 255, 255, 255]));
+    }
+
+    #[test]
+    fn test_metafilepict_himetric_size() {
+        // 2540 x 1270 hundredths of a millimeter = 96 x 48 pixels
+        let data = metafilepict(
+            2540,
+            1270,
+            &[&[meta::SETWINDOWEXT, 10, 10], &[meta::RECTANGLE, 10, 10, 0, 0]],
+        );
+        let image = decode(&metafilepict_to_png(&data).unwrap());
+        assert_eq!(image.dimensions(), (96, 48));
+    }
+
+    #[test]
+    fn test_metafilepict_object_slots() {
+        // A font occupies slot 0, so the brush lands in slot 1
+        let data = metafilepict(
+            0,
+            0,
+            &[
+                &[meta::SETWINDOWEXT, 10, 10],
+                &[meta::CREATEFONTINDIRECT, 0],
+                &[meta::CREATEBRUSHINDIRECT, 0, 0x00FF, 0, 0],
+                &[meta::SELECTOBJECT, 1],
+                &[meta::RECTANGLE, 10, 10, 0, 0],
+            ],
+        );
+        let image = decode(&metafilepict_to_png(&data).unwrap());
+        assert_eq!(image.get_pixel(5, 5), &image::Rgba([255, 0, 0, 255]));
+    }
+
+    #[test]
+    fn test_metafilepict_invalid() {
+        assert!(metafilepict_to_png(&[0; 8]).is_err());
+        assert!(metafilepict_to_png(&[0; 40]).is_err());
+    }
+}
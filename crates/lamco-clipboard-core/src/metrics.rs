@@ -0,0 +1,584 @@
+//! Counters for failed format conversions.
+//!
+//! Every failure of [`FormatConverter::convert`] is sorted into a
+//! [`ConversionFailure`] category and counted on the converter, so a
+//! deployment can report which conversions break in the field (and which
+//! format pairs peers ask for that have no converter at all).
+//!
+//! Callers using the typed conversion methods directly can count their
+//! failures the same way with [`FormatConverter::record_failure`].
+//!
+//! File transfers report into a [`TransferRecorder`] shared by every
+//! [`FileTransfer`](crate::file_transfer::FileTransfer) and
+//! [`FileServer`](crate::file_transfer::FileServer) it is handed to: bytes
+//! in and out, pastes in progress, chunk round trips and failures by
+//! [`TransferFailure`] cause. [`TransferRecorder::metrics`] takes a
+//! [`TransferMetrics`] snapshot at any time, and a callback can receive one
+//! whenever a transfer ends.
+
+use crate::formats::FormatConverter;
+use crate::{ClipboardError, TransferErrorKind};
+use std::fmt;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Arc;
+use std::time::Duration;
+
+/// Why a conversion failed
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "serde", serde(rename_all = "snake_case"))]
+pub enum ConversionFailure {
+    /// DIB with a bit depth the image decoder does not handle
+    UnsupportedBitDepth,
+    /// CF_HTML with missing headers or offsets that do not fit the data
+    InvalidCfHtml,
+    /// Payload larger than the converter's size limit
+    OversizedPayload,
+    /// Text that is not valid in the expected encoding
+    CharsetError,
+    /// No converter for the requested format pair
+    UnsupportedConversion,
+    /// Any other failure
+    Other,
+}
+
+impl ConversionFailure {
+    /// All categories, in counter order
+    pub const ALL: [Self; 6] = [
+        Self::UnsupportedBitDepth,
+        Self::InvalidCfHtml,
+        Self::OversizedPayload,
+        Self::CharsetError,
+        Self::UnsupportedConversion,
+        Self::Other,
+    ];
+
+    /// Category of an error returned by a conversion
+    pub fn classify(error: &ClipboardError) -> Self {
+        match error {
+            ClipboardError::UnsupportedBitDepth(_) => Self::UnsupportedBitDepth,
+            ClipboardError::InvalidCfHtml(_) => Self::InvalidCfHtml,
+            ClipboardError::DataSizeExceeded { .. } | ClipboardError::ImageTooLarge { .. } => Self::OversizedPayload,
+            ClipboardError::InvalidUtf8 | ClipboardError::InvalidUtf16 => Self::CharsetError,
+            ClipboardError::UnsupportedFormat(_) => Self::UnsupportedConversion,
+            _ => Self::Other,
+        }
+    }
+
+    /// Name suitable as a metric label
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Self::UnsupportedBitDepth => "unsupported_bit_depth",
+            Self::InvalidCfHtml => "invalid_cf_html",
+            Self::OversizedPayload => "oversized_payload",
+            Self::CharsetError => "charset_error",
+            Self::UnsupportedConversion => "unsupported_conversion",
+            Self::Other => "other",
+        }
+    }
+
+    fn index(self) -> usize {
+        self as usize
+    }
+}
+
+impl fmt::Display for ConversionFailure {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+/// Failed conversions per [`ConversionFailure`] category
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct ConversionMetrics {
+    /// DIBs with an unhandled bit depth
+    pub unsupported_bit_depth: u64,
+
+    /// Malformed CF_HTML payloads
+    pub invalid_cf_html: u64,
+
+    /// Payloads over the size limit
+    pub oversized_payload: u64,
+
+    /// Text in the wrong encoding
+    pub charset_error: u64,
+
+    /// Format pairs without a converter
+    pub unsupported_conversion: u64,
+
+    /// Failures in no other category
+    pub other: u64,
+}
+
+impl ConversionMetrics {
+    /// Count for one category
+    pub fn get(&self, failure: ConversionFailure) -> u64 {
+        match failure {
+            ConversionFailure::UnsupportedBitDepth => self.unsupported_bit_depth,
+            ConversionFailure::InvalidCfHtml => self.invalid_cf_html,
+            ConversionFailure::OversizedPayload => self.oversized_payload,
+            ConversionFailure::CharsetError => self.charset_error,
+            ConversionFailure::UnsupportedConversion => self.unsupported_conversion,
+            ConversionFailure::Other => self.other,
+        }
+    }
+
+    /// Failures across all categories
+    pub fn total(&self) -> u64 {
+        ConversionFailure::ALL.iter().map(|&failure| self.get(failure)).sum()
+    }
+}
+
+/// Live counters kept by a [`FormatConverter`]
+#[derive(Debug, Default)]
+pub(crate) struct FailureCounters {
+    counts: [AtomicU64; ConversionFailure::ALL.len()],
+}
+
+impl FailureCounters {
+    fn count(&self, failure: ConversionFailure) -> u64 {
+        self.counts[failure.index()].load(Ordering::Relaxed)
+    }
+}
+
+impl FormatConverter {
+    /// Count a failed conversion and return its category
+    pub fn record_failure(&self, error: &ClipboardError) -> ConversionFailure {
+        let failure = ConversionFailure::classify(error);
+        self.failures.counts[failure.index()].fetch_add(1, Ordering::Relaxed);
+        failure
+    }
+
+    /// Failed conversions since creation or the last reset
+    pub fn conversion_metrics(&self) -> ConversionMetrics {
+        let counters = &self.failures;
+        ConversionMetrics {
+            unsupported_bit_depth: counters.count(ConversionFailure::UnsupportedBitDepth),
+            invalid_cf_html: counters.count(ConversionFailure::InvalidCfHtml),
+            oversized_payload: counters.count(ConversionFailure::OversizedPayload),
+            charset_error: counters.count(ConversionFailure::CharsetError),
+            unsupported_conversion: counters.count(ConversionFailure::UnsupportedConversion),
+            other: counters.count(ConversionFailure::Other),
+        }
+    }
+
+    /// Zero all failure counters
+    pub fn reset_conversion_metrics(&self) {
+        for count in &self.failures.counts {
+            count.store(0, Ordering::Relaxed);
+        }
+    }
+}
+
+/// Upper bounds of the chunk round-trip buckets, in milliseconds
+///
+/// Round trips past the last bound are counted in one more bucket.
+pub const RTT_BUCKETS_MS: [u64; 13] = [1, 2, 5, 10, 25, 50, 100, 250, 500, 1000, 2500, 5000, 10_000];
+
+/// Why a file transfer failed
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "serde", serde(rename_all = "snake_case"))]
+pub enum TransferFailure {
+    /// The peer answered with a failed File Contents Response
+    PeerError,
+    /// A chunk went unanswered while the peer answered others
+    ChunkTimeout,
+    /// The peer stopped answering altogether
+    PeerUnresponsive,
+    /// The transfer ran past its overall deadline
+    TransferTimeout,
+    /// A file did not match the peer's checksum
+    ChecksumMismatch,
+    /// Files over a size, count or extension limit
+    PolicyViolation,
+    /// A file changed size while being transferred
+    FileChanged,
+    /// Any other failure, such as the sink failing to write a file
+    Other,
+}
+
+impl TransferFailure {
+    /// All causes, in counter order
+    pub const ALL: [Self; 8] = [
+        Self::PeerError,
+        Self::ChunkTimeout,
+        Self::PeerUnresponsive,
+        Self::TransferTimeout,
+        Self::ChecksumMismatch,
+        Self::PolicyViolation,
+        Self::FileChanged,
+        Self::Other,
+    ];
+
+    /// Cause of an error that failed a transfer
+    pub fn classify(error: &ClipboardError) -> Self {
+        match error {
+            ClipboardError::FileTransfer(error) => match error.kind {
+                TransferErrorKind::PeerError => Self::PeerError,
+                TransferErrorKind::ChunkTimeout => Self::ChunkTimeout,
+                TransferErrorKind::PeerUnresponsive => Self::PeerUnresponsive,
+                TransferErrorKind::TransferTimeout => Self::TransferTimeout,
+                TransferErrorKind::ChecksumMismatch => Self::ChecksumMismatch,
+                TransferErrorKind::FileTooLarge
+                | TransferErrorKind::TotalSizeExceeded
+                | TransferErrorKind::TooManyFiles
+                | TransferErrorKind::ExtensionDenied => Self::PolicyViolation,
+            },
+            ClipboardError::DataSizeExceeded { .. } => Self::PolicyViolation,
+            ClipboardError::FileChanged { .. } => Self::FileChanged,
+            _ => Self::Other,
+        }
+    }
+
+    /// Name suitable as a metric label
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Self::PeerError => "peer_error",
+            Self::ChunkTimeout => "chunk_timeout",
+            Self::PeerUnresponsive => "peer_unresponsive",
+            Self::TransferTimeout => "transfer_timeout",
+            Self::ChecksumMismatch => "checksum_mismatch",
+            Self::PolicyViolation => "policy_violation",
+            Self::FileChanged => "file_changed",
+            Self::Other => "other",
+        }
+    }
+
+    fn index(self) -> usize {
+        self as usize
+    }
+}
+
+impl fmt::Display for TransferFailure {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+/// Distribution of chunk round trips over [`RTT_BUCKETS_MS`]
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct RttHistogram {
+    /// Round trips per bucket: `counts[i]` took at most `RTT_BUCKETS_MS[i]`
+    /// and more than the bound before it; the last entry counts those past
+    /// every bound
+    pub counts: Vec<u64>,
+
+    /// Sum of all round trips, in microseconds
+    pub sum_micros: u64,
+}
+
+impl RttHistogram {
+    /// Number of round trips recorded
+    pub fn count(&self) -> u64 {
+        self.counts.iter().sum()
+    }
+
+    /// Mean round trip
+    pub fn mean(&self) -> Option<Duration> {
+        let count = self.count();
+        (count > 0).then(|| Duration::from_micros(self.sum_micros / count))
+    }
+}
+
+/// Failed transfers per [`TransferFailure`] cause
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct TransferFailures {
+    /// Failed File Contents Responses
+    pub peer_error: u64,
+
+    /// Chunks unanswered through every retry
+    pub chunk_timeout: u64,
+
+    /// Peers that went silent
+    pub peer_unresponsive: u64,
+
+    /// Transfers past their deadline
+    pub transfer_timeout: u64,
+
+    /// Files that failed checksum verification
+    pub checksum_mismatch: u64,
+
+    /// Pastes refused by a size, count or extension limit
+    pub policy_violation: u64,
+
+    /// Files that changed size mid-transfer
+    pub file_changed: u64,
+
+    /// Failures in no other category
+    pub other: u64,
+}
+
+impl TransferFailures {
+    /// Count for one cause
+    pub fn get(&self, failure: TransferFailure) -> u64 {
+        match failure {
+            TransferFailure::PeerError => self.peer_error,
+            TransferFailure::ChunkTimeout => self.chunk_timeout,
+            TransferFailure::PeerUnresponsive => self.peer_unresponsive,
+            TransferFailure::TransferTimeout => self.transfer_timeout,
+            TransferFailure::ChecksumMismatch => self.checksum_mismatch,
+            TransferFailure::PolicyViolation => self.policy_violation,
+            TransferFailure::FileChanged => self.file_changed,
+            TransferFailure::Other => self.other,
+        }
+    }
+
+    /// Failures across all causes
+    pub fn total(&self) -> u64 {
+        TransferFailure::ALL.iter().map(|&failure| self.get(failure)).sum()
+    }
+}
+
+/// Snapshot of the counters in a [`TransferRecorder`]
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct TransferMetrics {
+    /// File Contents Response payload bytes received
+    pub bytes_received: u64,
+
+    /// File Contents Response payload bytes served
+    pub bytes_sent: u64,
+
+    /// Pastes started and not yet finished
+    pub active_transfers: u64,
+
+    /// Pastes that received every file
+    pub completed_transfers: u64,
+
+    /// Pastes cancelled before completing
+    pub cancelled_transfers: u64,
+
+    /// Requests sent again after a timeout
+    pub retried_requests: u64,
+
+    /// Round trips of requests answered on the first attempt
+    pub chunk_rtt: RttHistogram,
+
+    /// Failed pastes by cause
+    pub failures: TransferFailures,
+}
+
+type MetricsCallback = dyn Fn(&TransferMetrics) + Send + Sync;
+
+#[derive(Default)]
+struct TransferCounters {
+    bytes_received: AtomicU64,
+    bytes_sent: AtomicU64,
+    active: AtomicU64,
+    completed: AtomicU64,
+    cancelled: AtomicU64,
+    retried: AtomicU64,
+    rtt: [AtomicU64; RTT_BUCKETS_MS.len() + 1],
+    rtt_sum_micros: AtomicU64,
+    failures: [AtomicU64; TransferFailure::ALL.len()],
+    on_finished: Option<Box<MetricsCallback>>,
+}
+
+/// Shared file transfer counters
+///
+/// Cloning is cheap and shares the counters, so one recorder can be handed
+/// to every transfer and server of a session, or of a whole host.
+#[derive(Clone, Default)]
+pub struct TransferRecorder {
+    counters: Arc<TransferCounters>,
+}
+
+impl fmt::Debug for TransferRecorder {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("TransferRecorder")
+            .field("metrics", &self.metrics())
+            .finish_non_exhaustive()
+    }
+}
+
+impl TransferRecorder {
+    /// Create a recorder with all counters at zero
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Create a recorder that calls `callback` with a snapshot whenever a
+    /// transfer completes, fails or is cancelled
+    ///
+    /// The callback runs on the thread driving the transfer and must not
+    /// block; forward the snapshot to a channel to export it elsewhere.
+    pub fn with_callback(callback: impl Fn(&TransferMetrics) + Send + Sync + 'static) -> Self {
+        Self {
+            counters: Arc::new(TransferCounters {
+                on_finished: Some(Box::new(callback)),
+                ..TransferCounters::default()
+            }),
+        }
+    }
+
+    /// Counters since creation or the last reset
+    pub fn metrics(&self) -> TransferMetrics {
+        let counters = &*self.counters;
+        let load = |counter: &AtomicU64| counter.load(Ordering::Relaxed);
+        TransferMetrics {
+            bytes_received: load(&counters.bytes_received),
+            bytes_sent: load(&counters.bytes_sent),
+            active_transfers: load(&counters.active),
+            completed_transfers: load(&counters.completed),
+            cancelled_transfers: load(&counters.cancelled),
+            retried_requests: load(&counters.retried),
+            chunk_rtt: RttHistogram {
+                counts: counters.rtt.iter().map(load).collect(),
+                sum_micros: load(&counters.rtt_sum_micros),
+            },
+            failures: TransferFailures {
+                peer_error: load(&counters.failures[TransferFailure::PeerError.index()]),
+                chunk_timeout: load(&counters.failures[TransferFailure::ChunkTimeout.index()]),
+                peer_unresponsive: load(&counters.failures[TransferFailure::PeerUnresponsive.index()]),
+                transfer_timeout: load(&counters.failures[TransferFailure::TransferTimeout.index()]),
+                checksum_mismatch: load(&counters.failures[TransferFailure::ChecksumMismatch.index()]),
+                policy_violation: load(&counters.failures[TransferFailure::PolicyViolation.index()]),
+                file_changed: load(&counters.failures[TransferFailure::FileChanged.index()]),
+                other: load(&counters.failures[TransferFailure::Other.index()]),
+            },
+        }
+    }
+
+    /// Zero all counters except the transfers in progress
+    pub fn reset(&self) {
+        let counters = &*self.counters;
+        let totals = [
+            &counters.bytes_received,
+            &counters.bytes_sent,
+            &counters.completed,
+            &counters.cancelled,
+            &counters.retried,
+            &counters.rtt_sum_micros,
+        ];
+        for count in totals.into_iter().chain(&counters.rtt).chain(&counters.failures) {
+            count.store(0, Ordering::Relaxed);
+        }
+    }
+
+    pub(crate) fn record_received(&self, bytes: usize) {
+        self.counters.bytes_received.fetch_add(bytes as u64, Ordering::Relaxed);
+    }
+
+    pub(crate) fn record_sent(&self, bytes: usize) {
+        self.counters.bytes_sent.fetch_add(bytes as u64, Ordering::Relaxed);
+    }
+
+    pub(crate) fn record_retry(&self) {
+        self.counters.retried.fetch_add(1, Ordering::Relaxed);
+    }
+
+    pub(crate) fn record_rtt(&self, rtt: Duration) {
+        let millis = rtt.as_millis();
+        let bucket = RTT_BUCKETS_MS
+            .iter()
+            .position(|&bound| millis <= u128::from(bound))
+            .unwrap_or(RTT_BUCKETS_MS.len());
+        self.counters.rtt[bucket].fetch_add(1, Ordering::Relaxed);
+        let micros = u64::try_from(rtt.as_micros()).unwrap_or(u64::MAX);
+        self.counters.rtt_sum_micros.fetch_add(micros, Ordering::Relaxed);
+    }
+
+    pub(crate) fn transfer_started(&self) {
+        self.counters.active.fetch_add(1, Ordering::Relaxed);
+    }
+
+    /// Count a transfer that ended: completed on `None`, cancelled on
+    /// [`ClipboardError::TransferCancelled`], failed otherwise
+    pub(crate) fn transfer_finished(&self, error: Option<&ClipboardError>) {
+        let counters = &*self.counters;
+        counters.active.fetch_sub(1, Ordering::Relaxed);
+        match error {
+            None => counters.completed.fetch_add(1, Ordering::Relaxed),
+            Some(ClipboardError::TransferCancelled) => counters.cancelled.fetch_add(1, Ordering::Relaxed),
+            Some(error) => counters.failures[TransferFailure::classify(error).index()].fetch_add(1, Ordering::Relaxed),
+        };
+        if let Some(callback) = &counters.on_finished {
+            callback(&self.metrics());
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_convert_counts_failures() {
+        let converter = FormatConverter::with_max_size(16);
+
+        assert!(converter.convert("text/plain", "CF_UNICODETEXT", &[0xFF]).is_err());
+        assert!(converter.convert("text/plain", "CF_UNICODETEXT", &[b'a'; 32]).is_err());
+        assert!(converter
+            .convert("HTML Format", "text/html", b"Version:0.9\r\n")
+            .is_err());
+        assert!(converter.convert("application/x-unknown", "CF_TEXT", b"x").is_err());
+        assert!(converter.convert("text/plain", "CF_UNICODETEXT", b"ok").is_ok());
+
+        let metrics = converter.conversion_metrics();
+        assert_eq!(metrics.charset_error, 1);
+        assert_eq!(metrics.oversized_payload, 1);
+        assert_eq!(metrics.invalid_cf_html, 1);
+        assert_eq!(metrics.unsupported_conversion, 1);
+        assert_eq!(metrics.total(), 4);
+
+        converter.reset_conversion_metrics();
+        assert_eq!(converter.conversion_metrics(), ConversionMetrics::default());
+    }
+
+    #[test]
+    fn test_record_failure() {
+        let converter = FormatConverter::new();
+        let failure = converter.record_failure(&ClipboardError::UnsupportedBitDepth(4));
+        assert_eq!(failure, ConversionFailure::UnsupportedBitDepth);
+        assert_eq!(failure.to_string(), "unsupported_bit_depth");
+        assert_eq!(converter.conversion_metrics().get(failure), 1);
+
+        converter.record_failure(&ClipboardError::LoopDetected);
+        assert_eq!(converter.conversion_metrics().other, 1);
+    }
+
+    #[test]
+    fn test_transfer_failure_classify() {
+        let error = |kind| {
+            ClipboardError::FileTransfer(crate::TransferError {
+                kind,
+                index: 0,
+                path: "a.exe".to_string(),
+                offset: 0,
+            })
+        };
+        assert_eq!(
+            TransferFailure::classify(&error(TransferErrorKind::ChunkTimeout)),
+            TransferFailure::ChunkTimeout
+        );
+        assert_eq!(
+            TransferFailure::classify(&error(TransferErrorKind::ExtensionDenied)),
+            TransferFailure::PolicyViolation
+        );
+        assert_eq!(
+            TransferFailure::classify(&ClipboardError::FileNotFound("a".to_string())),
+            TransferFailure::Other
+        );
+
+        let recorder = TransferRecorder::new();
+        recorder.record_rtt(Duration::from_millis(1));
+        recorder.record_rtt(Duration::from_secs(60));
+        let histogram = recorder.metrics().chunk_rtt;
+        assert_eq!(histogram.counts.len(), RTT_BUCKETS_MS.len() + 1);
+        assert_eq!((histogram.counts[0], histogram.counts[RTT_BUCKETS_MS.len()]), (1, 1));
+    }
+
+    #[cfg(feature = "image")]
+    #[test]
+    fn test_dib_bit_depth_failure() {
+        let converter = FormatConverter::new();
+        let mut dib = crate::testing::fixtures::DibBuilder::new(2, 2).build();
+        dib[14] = 2; // biBitCount
+        assert!(converter.convert("CF_DIB", "image/png", &dib).is_err());
+        assert_eq!(converter.conversion_metrics().unsupported_bit_depth, 1);
+    }
+}
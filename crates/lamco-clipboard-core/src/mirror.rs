@@ -0,0 +1,278 @@
+//! Tee decorator that mirrors synced clipboard content to a secondary sink.
+//!
+//! [`MirrorSink`] wraps the primary [`ClipboardSink`] and forwards a copy of
+//! every piece of synced content to a secondary sink such as a preview
+//! window or a recording sink. The secondary sink never takes part in
+//! clipboard ownership: it does not receive format announcements, is not
+//! read from, and its change notifications are ignored.
+
+use crate::sink::{ClipboardChangeReceiver, ClipboardSink, FileInfo};
+use crate::ClipboardResult;
+use std::future::Future;
+#[cfg(feature = "tokio")]
+use std::time::Duration;
+
+/// Default longest wait for the mirror sink to take a copy, in milliseconds
+#[cfg(feature = "tokio")]
+pub const DEFAULT_MIRROR_TIMEOUT_MS: u64 = 250;
+
+/// Clipboard sink decorator that copies synced content to a secondary sink.
+///
+/// Content is mirrored in both directions by default:
+///
+/// - **Remote → local**: data passed to [`write_clipboard`](ClipboardSink::write_clipboard)
+/// - **Local → remote**: data returned by [`read_clipboard`](ClipboardSink::read_clipboard)
+///
+/// Errors from the mirror are logged and never affect the primary operation.
+/// File transfers are not mirrored.
+///
+/// The copy is made after the primary operation completes. With the `tokio`
+/// feature and a Tokio runtime driving the sink, a mirror that does not take
+/// the copy within [`with_mirror_timeout`](Self::with_mirror_timeout) is
+/// abandoned, so a stalled preview never holds up the clipboard.
+///
+/// # Example
+///
+/// ```rust,ignore
+/// use lamco_clipboard_core::MirrorSink;
+///
+/// let sink = MirrorSink::new(portal_sink, preview_sink).with_mirror_reads(false);
+/// ```
+#[derive(Debug)]
+pub struct MirrorSink<P, M> {
+    primary: P,
+    mirror: M,
+    mirror_reads: bool,
+    mirror_writes: bool,
+    #[cfg(feature = "tokio")]
+    mirror_timeout: Duration,
+}
+
+impl<P: ClipboardSink, M: ClipboardSink> MirrorSink<P, M> {
+    /// Attach a mirror to a primary sink
+    pub fn new(primary: P, mirror: M) -> Self {
+        Self {
+            primary,
+            mirror,
+            mirror_reads: true,
+            mirror_writes: true,
+            #[cfg(feature = "tokio")]
+            mirror_timeout: Duration::from_millis(DEFAULT_MIRROR_TIMEOUT_MS),
+        }
+    }
+
+    /// Set whether local content sent to the remote side is mirrored
+    pub fn with_mirror_reads(mut self, enabled: bool) -> Self {
+        self.mirror_reads = enabled;
+        self
+    }
+
+    /// Set whether remote content written to the local clipboard is mirrored
+    pub fn with_mirror_writes(mut self, enabled: bool) -> Self {
+        self.mirror_writes = enabled;
+        self
+    }
+
+    /// Set the longest wait for the mirror to take a copy
+    #[cfg(feature = "tokio")]
+    pub fn with_mirror_timeout(mut self, timeout: Duration) -> Self {
+        self.mirror_timeout = timeout;
+        self
+    }
+
+    /// Get a reference to the primary sink
+    pub fn primary(&self) -> &P {
+        &self.primary
+    }
+
+    /// Get a reference to the mirror sink
+    pub fn mirror(&self) -> &M {
+        &self.mirror
+    }
+
+    /// Split into the primary and mirror sinks
+    pub fn into_parts(self) -> (P, M) {
+        (self.primary, self.mirror)
+    }
+
+    async fn copy_to_mirror(&self, mime_type: &str, data: Vec<u8>) {
+        let write = self.mirror.write_clipboard(mime_type, data);
+
+        // The timer needs a runtime; other executors wait for the mirror
+        #[cfg(feature = "tokio")]
+        let result = if tokio::runtime::Handle::try_current().is_ok() {
+            match tokio::time::timeout(self.mirror_timeout, write).await {
+                Ok(result) => result,
+                Err(_) => {
+                    tracing::debug!("Mirror sink timed out on {}", mime_type);
+                    return;
+                }
+            }
+        } else {
+            write.await
+        };
+        #[cfg(not(feature = "tokio"))]
+        let result = write.await;
+
+        if let Err(e) = result {
+            tracing::debug!("Mirror sink rejected {}: {}", mime_type, e);
+        }
+    }
+}
+
+impl<P: ClipboardSink, M: ClipboardSink> ClipboardSink for MirrorSink<P, M> {
+    fn announce_formats(&self, mime_types: Vec<String>) -> impl Future<Output = ClipboardResult<()>> + Send {
+        self.primary.announce_formats(mime_types)
+    }
+
+    async fn read_clipboard(&self, mime_type: &str) -> ClipboardResult<Vec<u8>> {
+        let data = self.primary.read_clipboard(mime_type).await?;
+        if self.mirror_reads {
+            self.copy_to_mirror(mime_type, data.clone()).await;
+        }
+        Ok(data)
+    }
+
+    async fn write_clipboard(&self, mime_type: &str, data: Vec<u8>) -> ClipboardResult<()> {
+        let copy = self.mirror_writes.then(|| data.clone());
+        self.primary.write_clipboard(mime_type, data).await?;
+        if let Some(copy) = copy {
+            self.copy_to_mirror(mime_type, copy).await;
+        }
+        Ok(())
+    }
+
+    fn subscribe_changes(&self) -> impl Future<Output = ClipboardResult<ClipboardChangeReceiver>> + Send {
+        self.primary.subscribe_changes()
+    }
+
+    fn get_file_list(&self) -> impl Future<Output = ClipboardResult<Vec<FileInfo>>> + Send {
+        self.primary.get_file_list()
+    }
+
+    fn read_file_chunk(
+        &self,
+        index: u32,
+        offset: u64,
+        size: u32,
+    ) -> impl Future<Output = ClipboardResult<Vec<u8>>> + Send {
+        self.primary.read_file_chunk(index, offset, size)
+    }
+
+    fn write_file(&self, path: &str, data: Vec<u8>) -> impl Future<Output = ClipboardResult<()>> + Send {
+        self.primary.write_file(path, data)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::block_on;
+    use crate::ClipboardError;
+    use std::sync::Mutex;
+
+    #[derive(Default)]
+    struct RecordingSink {
+        writes: Mutex<Vec<(String, Vec<u8>)>>,
+        fail: bool,
+        #[cfg(feature = "tokio")]
+        stall: bool,
+    }
+
+    impl ClipboardSink for RecordingSink {
+        async fn announce_formats(&self, _mime_types: Vec<String>) -> ClipboardResult<()> {
+            Ok(())
+        }
+
+        async fn read_clipboard(&self, _mime_type: &str) -> ClipboardResult<Vec<u8>> {
+            Ok(b"local".to_vec())
+        }
+
+        async fn write_clipboard(&self, mime_type: &str, data: Vec<u8>) -> ClipboardResult<()> {
+            if self.fail {
+                return Err(ClipboardError::Backend("mirror offline".to_string()));
+            }
+            #[cfg(feature = "tokio")]
+            if self.stall {
+                std::future::pending::<()>().await;
+            }
+            self.writes.lock().unwrap().push((mime_type.to_string(), data));
+            Ok(())
+        }
+
+        async fn subscribe_changes(&self) -> ClipboardResult<ClipboardChangeReceiver> {
+            Err(ClipboardError::Backend("unsupported".to_string()))
+        }
+
+        async fn get_file_list(&self) -> ClipboardResult<Vec<FileInfo>> {
+            Ok(Vec::new())
+        }
+
+        async fn read_file_chunk(&self, _index: u32, _offset: u64, _size: u32) -> ClipboardResult<Vec<u8>> {
+            Ok(Vec::new())
+        }
+
+        async fn write_file(&self, _path: &str, _data: Vec<u8>) -> ClipboardResult<()> {
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn test_mirrors_both_directions() {
+        let sink = MirrorSink::new(RecordingSink::default(), RecordingSink::default());
+
+        block_on(sink.write_clipboard("text/plain", b"remote".to_vec())).unwrap();
+        assert_eq!(block_on(sink.read_clipboard("text/html")).unwrap(), b"local");
+
+        let mirrored = sink.mirror().writes.lock().unwrap().clone();
+        assert_eq!(
+            mirrored,
+            vec![
+                ("text/plain".to_string(), b"remote".to_vec()),
+                ("text/html".to_string(), b"local".to_vec()),
+            ]
+        );
+        assert_eq!(sink.primary().writes.lock().unwrap().len(), 1);
+    }
+
+    #[test]
+    fn test_mirror_directions_configurable() {
+        let sink = MirrorSink::new(RecordingSink::default(), RecordingSink::default()).with_mirror_reads(false);
+
+        block_on(sink.read_clipboard("text/plain")).unwrap();
+        assert!(sink.mirror().writes.lock().unwrap().is_empty());
+    }
+
+    #[test]
+    fn test_mirror_errors_ignored() {
+        let mirror = RecordingSink {
+            fail: true,
+            ..Default::default()
+        };
+        let sink = MirrorSink::new(RecordingSink::default(), mirror);
+
+        assert!(block_on(sink.write_clipboard("text/plain", b"data".to_vec())).is_ok());
+        assert!(block_on(sink.read_clipboard("text/plain")).is_ok());
+    }
+
+    #[cfg(feature = "tokio")]
+    #[test]
+    fn test_slow_mirror_does_not_stall_primary() {
+        let runtime = tokio::runtime::Builder::new_current_thread()
+            .enable_time()
+            .build()
+            .unwrap();
+        let mirror = RecordingSink {
+            stall: true,
+            ..Default::default()
+        };
+        let sink = MirrorSink::new(RecordingSink::default(), mirror).with_mirror_timeout(Duration::from_millis(10));
+
+        runtime.block_on(async {
+            sink.write_clipboard("text/plain", b"remote".to_vec()).await.unwrap();
+            assert_eq!(sink.read_clipboard("text/plain").await.unwrap(), b"local");
+        });
+        assert_eq!(sink.primary().writes.lock().unwrap().len(), 1);
+        assert!(sink.mirror().writes.lock().unwrap().is_empty());
+    }
+}
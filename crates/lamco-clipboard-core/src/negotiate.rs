@@ -0,0 +1,289 @@
+//! Choosing which remote format to request.
+//!
+//! A peer usually announces the same content in several formats (Word: HTML,
+//! RTF, plain text, a metafile; a browser image: PNG, DIBV5, DIB, HTML). The
+//! local application asks for one of its own MIME types, and the format to
+//! request from the peer should be the one that loses the least on the way.
+//!
+//! [`FormatNegotiator`] ranks remote formats by fidelity and picks the best one
+//! that can produce a MIME type the local side accepts, either directly or
+//! through a [`FormatConverter`] conversion.
+//!
+//! Formats in the ranking use the same names as conversion keys: registered
+//! names (`HTML Format`, `PNG`) and `CF_*` constant names for standard formats
+//! (see [`crate::converter`]).
+
+use crate::converter::standard_format_name;
+use crate::formats::{rdp_format_to_mime, ClipboardFormat, FormatConverter};
+use crate::registry::{is_registered_format_id, registered_name_to_mime};
+
+/// Default fidelity ranking, best first
+pub const DEFAULT_FIDELITY_RANKING: &[&str] = &[
+    // Files are the content whenever they are offered
+    "FileGroupDescriptorW",
+    "CF_HDROP",
+    // Structured text
+    "XML Spreadsheet",
+    "HTML Format",
+    "Rich Text Format",
+    "Csv",
+    // Images: lossless first, then lossy, then vector fallbacks
+    "PNG",
+    "CF_DIBV5",
+    "CF_DIB",
+    "CF_TIFF",
+    "JFIF",
+    "image/webp",
+    "WebP",
+    "image/avif",
+    "GIF",
+    "image/x-icon",
+    "image/svg+xml",
+    "CF_ENHMETAFILE",
+    "CF_METAFILEPICT",
+    "CF_DSPBITMAP",
+    // Links and plain text
+    "UniformResourceLocatorW",
+    "UniformResourceLocator",
+    "CF_UNICODETEXT",
+    "CF_TEXT",
+    "CF_OEMTEXT",
+];
+
+/// The remote format to request and the local MIME type to produce from it
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Negotiation {
+    /// Remote format ID to put in the Format Data Request
+    pub format_id: u32,
+
+    /// Name of the remote format (registered name or `CF_*` name)
+    pub format: String,
+
+    /// Local MIME type the data is converted to
+    pub mime_type: String,
+
+    /// Position of the format in the ranking, `None` if it is not ranked
+    pub rank: Option<usize>,
+}
+
+/// Selects the best common representation of remote and local formats
+///
+/// # Example
+///
+/// ```
+/// use lamco_clipboard_core::formats::{ClipboardFormat, CF_DIB, CF_UNICODETEXT};
+/// use lamco_clipboard_core::{FormatConverter, FormatNegotiator};
+///
+/// let remote = [
+///     ClipboardFormat::new(CF_UNICODETEXT),
+///     ClipboardFormat::with_name(0xC0F2, "Rich Text Format"),
+///     ClipboardFormat::with_name(0xC0A3, "HTML Format"),
+/// ];
+///
+/// let negotiator = FormatNegotiator::new();
+/// let converter = FormatConverter::new();
+///
+/// let best = negotiator.negotiate(&remote, &["text/plain", "text/html"], &converter).unwrap();
+/// assert_eq!(best.format_id, 0xC0A3);
+/// assert_eq!(best.mime_type, "text/html");
+///
+/// // A plain-text-only target still gets the text format
+/// let best = negotiator.negotiate(&remote, &["text/plain"], &converter).unwrap();
+/// assert_eq!(best.format_id, CF_UNICODETEXT);
+/// ```
+#[derive(Debug, Clone)]
+pub struct FormatNegotiator {
+    ranking: Vec<String>,
+}
+
+impl Default for FormatNegotiator {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl FormatNegotiator {
+    /// Create a negotiator with [`DEFAULT_FIDELITY_RANKING`]
+    pub fn new() -> Self {
+        Self::with_ranking(DEFAULT_FIDELITY_RANKING.iter().copied())
+    }
+
+    /// Create a negotiator with a custom ranking, best first
+    ///
+    /// Formats not in the ranking are still used, after every ranked one.
+    pub fn with_ranking<I, S>(ranking: I) -> Self
+    where
+        I: IntoIterator<Item = S>,
+        S: Into<String>,
+    {
+        Self {
+            ranking: ranking.into_iter().map(Into::into).collect(),
+        }
+    }
+
+    /// The ranking in use, best first
+    pub fn ranking(&self) -> &[String] {
+        &self.ranking
+    }
+
+    /// Move a format to the top of the ranking
+    pub fn prefer(&mut self, format: &str) {
+        self.ranking.retain(|f| f != format);
+        self.ranking.insert(0, format.to_string());
+    }
+
+    /// Best remote format for any of the accepted MIME types
+    ///
+    /// When one remote format can produce several accepted MIME types, the
+    /// one listed first in `accepted` wins.
+    pub fn negotiate(
+        &self,
+        remote: &[ClipboardFormat],
+        accepted: &[&str],
+        converter: &FormatConverter,
+    ) -> Option<Negotiation> {
+        self.candidates(remote, accepted, converter).into_iter().next()
+    }
+
+    /// Every usable (remote format, MIME type) pair, best first
+    pub fn candidates(
+        &self,
+        remote: &[ClipboardFormat],
+        accepted: &[&str],
+        converter: &FormatConverter,
+    ) -> Vec<Negotiation> {
+        let mut candidates = Vec::new();
+        for format in remote {
+            let Some(name) = format_name(format) else {
+                continue;
+            };
+            let rank = self.ranking.iter().position(|f| *f == name);
+            let native = native_mime(format, &name);
+
+            let mime = accepted.iter().find(|mime| {
+                native.is_some_and(|native| mime_essence(native) == mime_essence(mime))
+                    || converter.has_converter(&name, mime)
+            });
+            if let Some(mime) = mime {
+                candidates.push(Negotiation {
+                    format_id: format.id,
+                    format: name,
+                    mime_type: mime.to_string(),
+                    rank,
+                });
+            }
+        }
+
+        // Stable sort keeps the peer's announcement order among unranked formats
+        candidates.sort_by_key(|c| c.rank.unwrap_or(usize::MAX));
+        candidates
+    }
+}
+
+/// Conversion key of a remote format
+fn format_name(format: &ClipboardFormat) -> Option<String> {
+    match &format.name {
+        Some(name) => Some(name.clone()),
+        None if !is_registered_format_id(format.id) => standard_format_name(format.id).map(str::to_string),
+        None => None,
+    }
+}
+
+/// MIME type the format maps to without conversion
+fn native_mime(format: &ClipboardFormat, name: &str) -> Option<&'static str> {
+    match format.name {
+        Some(_) => registered_name_to_mime(name),
+        None => rdp_format_to_mime(format.id),
+    }
+}
+
+/// MIME type without parameters (`text/plain;charset=utf-8` -> `text/plain`)
+fn mime_essence(mime: &str) -> &str {
+    mime.split(';').next().unwrap_or(mime).trim()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::formats::{CF_DIB, CF_DIBV5, CF_TEXT, CF_UNICODETEXT};
+
+    fn browser_image() -> Vec<ClipboardFormat> {
+        vec![
+            ClipboardFormat::new(CF_DIB),
+            ClipboardFormat::with_name(0xC1F0, "JFIF"),
+            ClipboardFormat::with_name(0xC0A3, "HTML Format"),
+            ClipboardFormat::with_name(0xC1F1, "PNG"),
+            ClipboardFormat::new(CF_DIBV5),
+        ]
+    }
+
+    #[test]
+    fn test_image_fidelity() {
+        let negotiator = FormatNegotiator::new();
+        let converter = FormatConverter::new();
+
+        let best = negotiator
+            .negotiate(&browser_image(), &["image/png"], &converter)
+            .unwrap();
+        assert_eq!(best.format, "PNG");
+        assert_eq!(best.format_id, 0xC1F1);
+
+        let order: Vec<String> = negotiator
+            .candidates(&browser_image(), &["image/png", "image/jpeg"], &converter)
+            .into_iter()
+            .map(|c| c.format)
+            .collect();
+        assert_eq!(order[0], "PNG");
+        assert_eq!(order[1..3], ["CF_DIBV5", "CF_DIB"]);
+        assert_eq!(order.last().map(String::as_str), Some("JFIF"));
+    }
+
+    #[test]
+    fn test_text_fidelity_and_mime_parameters() {
+        let remote = [
+            ClipboardFormat::new(CF_TEXT),
+            ClipboardFormat::new(CF_UNICODETEXT),
+            ClipboardFormat::with_name(0xC0F2, "Rich Text Format"),
+        ];
+        let negotiator = FormatNegotiator::new();
+        let converter = FormatConverter::new();
+
+        let best = negotiator
+            .negotiate(&remote, &["text/rtf", "text/plain;charset=utf-8"], &converter)
+            .unwrap();
+        assert_eq!(best.format, "Rich Text Format");
+
+        let best = negotiator
+            .negotiate(&remote, &["text/plain;charset=utf-8"], &converter)
+            .unwrap();
+        assert_eq!(best.format_id, CF_UNICODETEXT);
+        assert!(best.rank > negotiator.ranking().iter().position(|f| f == "Rich Text Format"));
+
+        assert!(negotiator.negotiate(&remote, &["image/png"], &converter).is_none());
+    }
+
+    #[test]
+    fn test_custom_ranking_and_registered_converters() {
+        let mut converter = FormatConverter::new();
+        converter.register_converter("AcmeCAD Snippet", "text/plain", |_: &FormatConverter, d: &[u8]| {
+            Ok(d.to_vec())
+        });
+        let remote = [
+            ClipboardFormat::new(CF_UNICODETEXT),
+            ClipboardFormat::with_name(0xC300, "AcmeCAD Snippet"),
+        ];
+
+        // Unranked formats come after ranked ones
+        let mut negotiator = FormatNegotiator::new();
+        let best = negotiator.negotiate(&remote, &["text/plain"], &converter).unwrap();
+        assert_eq!(best.format_id, CF_UNICODETEXT);
+
+        negotiator.prefer("AcmeCAD Snippet");
+        let best = negotiator.negotiate(&remote, &["text/plain"], &converter).unwrap();
+        assert_eq!(best.format, "AcmeCAD Snippet");
+        assert_eq!(best.rank, Some(0));
+
+        let negotiator = FormatNegotiator::with_ranking(["CF_UNICODETEXT"]);
+        assert_eq!(negotiator.ranking(), ["CF_UNICODETEXT"]);
+    }
+}
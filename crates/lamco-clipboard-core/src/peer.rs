@@ -0,0 +1,244 @@
+//! Per-session profile of how the peer uses announced formats.
+//!
+//! Every local image copy is announced in several formats (PNG, DIBV5, DIB,
+//! ...), and eager integrators convert each of them up front. Most peers only
+//! ever request one of those formats, so the rest is wasted work in
+//! screenshot-heavy sessions.
+//!
+//! [`PeerProfile`] watches which image formats the peer actually requests.
+//! Once enough pastes have been observed, image formats that were offered
+//! every time but never requested are dropped from later announcements and
+//! need not be prepared.
+
+use crate::formats::ClipboardFormat;
+use std::collections::HashMap;
+
+/// Default number of image pastes observed before unused formats are pruned
+pub const DEFAULT_MIN_IMAGE_OBSERVATIONS: u32 = 3;
+
+/// Image format usage observed during one session.
+///
+/// Format IDs are the local IDs from this side's Format List, which stay
+/// stable for the session when announced through a
+/// [`FormatRegistry`](crate::FormatRegistry).
+///
+/// # Example
+///
+/// ```
+/// use lamco_clipboard_core::formats::{mime_to_rdp_formats, CF_DIB, CF_PNG};
+/// use lamco_clipboard_core::PeerProfile;
+///
+/// let mut profile = PeerProfile::new();
+///
+/// // The peer pastes three screenshots, always as CF_DIB
+/// for _ in 0..3 {
+///     profile.record_announcement(&mime_to_rdp_formats(&["image/png"]));
+///     profile.record_request(CF_DIB);
+/// }
+///
+/// let formats = profile.filter_formats(mime_to_rdp_formats(&["image/png"]));
+/// assert!(formats.iter().any(|f| f.id == CF_DIB));
+/// assert!(!formats.iter().any(|f| f.id == CF_PNG));
+/// ```
+#[derive(Debug, Clone)]
+pub struct PeerProfile {
+    /// Announcements with at least one image request needed before pruning
+    min_observations: u32,
+
+    /// Image formats in the current announcement
+    current_offer: Vec<u32>,
+
+    /// Whether the peer requested an image format from the current announcement
+    current_answered: bool,
+
+    /// Image formats requested in the current announcement
+    current_requested: Vec<u32>,
+
+    /// Local ID -> number of answered announcements that offered it
+    offered: HashMap<u32, u32>,
+
+    /// Local ID -> number of answered announcements in which it was requested
+    requested: HashMap<u32, u32>,
+}
+
+impl Default for PeerProfile {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl PeerProfile {
+    /// Create an empty profile for a new session
+    pub fn new() -> Self {
+        Self::with_min_observations(DEFAULT_MIN_IMAGE_OBSERVATIONS)
+    }
+
+    /// Create a profile that prunes after `min_observations` image pastes
+    pub fn with_min_observations(min_observations: u32) -> Self {
+        Self {
+            min_observations: min_observations.max(1),
+            current_offer: Vec::new(),
+            current_answered: false,
+            current_requested: Vec::new(),
+            offered: HashMap::new(),
+            requested: HashMap::new(),
+        }
+    }
+
+    /// Record a Format List announced to the peer.
+    ///
+    /// Starts a new observation; requests recorded afterwards are attributed
+    /// to this announcement.
+    pub fn record_announcement(&mut self, formats: &[ClipboardFormat]) {
+        self.current_offer = formats.iter().filter(|f| is_image_format(f)).map(|f| f.id).collect();
+        self.current_answered = false;
+        self.current_requested.clear();
+    }
+
+    /// Record a Format Data Request received from the peer
+    pub fn record_request(&mut self, format_id: u32) {
+        if !self.current_offer.contains(&format_id) || self.current_requested.contains(&format_id) {
+            return;
+        }
+
+        if !self.current_answered {
+            self.current_answered = true;
+            for &id in &self.current_offer {
+                *self.offered.entry(id).or_insert(0) += 1;
+            }
+        }
+
+        self.current_requested.push(format_id);
+        *self.requested.entry(format_id).or_insert(0) += 1;
+    }
+
+    /// Number of announcements in which the peer requested an image format
+    pub fn image_observations(&self) -> u32 {
+        self.offered.values().copied().max().unwrap_or(0)
+    }
+
+    /// Whether the peer has shown it never uses this image format
+    pub fn is_unused(&self, format_id: u32) -> bool {
+        self.offered.get(&format_id).copied().unwrap_or(0) >= self.min_observations
+            && self.requested.get(&format_id).copied().unwrap_or(0) == 0
+    }
+
+    /// Whether data for this format is worth converting ahead of a request
+    pub fn should_prepare(&self, format_id: u32) -> bool {
+        !self.is_unused(format_id)
+    }
+
+    /// Image formats the peer has requested, most requested first
+    pub fn preferred_image_formats(&self) -> Vec<u32> {
+        let mut formats: Vec<(u32, u32)> = self
+            .requested
+            .iter()
+            .filter(|(_, &count)| count > 0)
+            .map(|(&id, &count)| (id, count))
+            .collect();
+        formats.sort_by(|a, b| b.1.cmp(&a.1).then(a.0.cmp(&b.0)));
+        formats.into_iter().map(|(id, _)| id).collect()
+    }
+
+    /// Drop image formats the peer never uses from a Format List.
+    ///
+    /// Non-image formats are kept. If every image format would be dropped,
+    /// the list is returned unchanged so the peer can still paste the image.
+    pub fn filter_formats(&self, formats: Vec<ClipboardFormat>) -> Vec<ClipboardFormat> {
+        let keeps_image = formats.iter().any(|f| is_image_format(f) && !self.is_unused(f.id));
+        if !keeps_image {
+            return formats;
+        }
+
+        formats.into_iter().filter(|f| !self.is_unused(f.id)).collect()
+    }
+
+    /// Forget everything observed so far
+    pub fn reset(&mut self) {
+        *self = Self::with_min_observations(self.min_observations);
+    }
+}
+
+fn is_image_format(format: &ClipboardFormat) -> bool {
+    format.mime_type().is_some_and(|mime| mime.starts_with("image/"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::formats::{mime_to_rdp_formats, CF_DIB, CF_DIBV5, CF_PNG, CF_UNICODETEXT};
+
+    fn paste_as(profile: &mut PeerProfile, format_id: u32) {
+        profile.record_announcement(&mime_to_rdp_formats(&["image/png", "text/plain"]));
+        profile.record_request(format_id);
+    }
+
+    #[test]
+    fn test_prunes_after_min_observations() {
+        let mut profile = PeerProfile::new();
+        paste_as(&mut profile, CF_DIB);
+        paste_as(&mut profile, CF_DIB);
+        assert!(!profile.is_unused(CF_PNG));
+
+        paste_as(&mut profile, CF_DIB);
+        assert_eq!(profile.image_observations(), 3);
+        assert!(profile.is_unused(CF_PNG));
+        assert!(profile.is_unused(CF_DIBV5));
+        assert!(profile.should_prepare(CF_DIB));
+
+        let ids: Vec<u32> = profile
+            .filter_formats(mime_to_rdp_formats(&["image/png", "text/plain"]))
+            .iter()
+            .map(|f| f.id)
+            .collect();
+        assert!(ids.contains(&CF_DIB));
+        assert!(ids.contains(&CF_UNICODETEXT));
+        assert!(!ids.contains(&CF_PNG));
+    }
+
+    #[test]
+    fn test_unanswered_announcements_ignored() {
+        let mut profile = PeerProfile::with_min_observations(1);
+
+        // Copies that are never pasted tell us nothing
+        for _ in 0..5 {
+            profile.record_announcement(&mime_to_rdp_formats(&["image/png"]));
+        }
+        assert_eq!(profile.image_observations(), 0);
+        assert!(!profile.is_unused(CF_PNG));
+
+        // Text requests do not count as image observations
+        profile.record_announcement(&mime_to_rdp_formats(&["image/png", "text/plain"]));
+        profile.record_request(CF_UNICODETEXT);
+        assert_eq!(profile.image_observations(), 0);
+    }
+
+    #[test]
+    fn test_never_drops_every_image_format() {
+        let mut profile = PeerProfile::with_min_observations(1);
+        paste_as(&mut profile, CF_PNG);
+
+        // An offer whose only image format was never requested stays intact
+        let formats = profile.filter_formats(vec![ClipboardFormat::new(CF_DIB)]);
+        assert_eq!(formats.len(), 1);
+    }
+
+    #[test]
+    fn test_preferred_order_and_reset() {
+        let mut profile = PeerProfile::new();
+        paste_as(&mut profile, CF_PNG);
+        paste_as(&mut profile, CF_DIB);
+        paste_as(&mut profile, CF_DIB);
+        assert_eq!(profile.preferred_image_formats(), vec![CF_DIB, CF_PNG]);
+
+        // Repeated requests within one announcement count once
+        profile.record_announcement(&mime_to_rdp_formats(&["image/png"]));
+        profile.record_request(CF_PNG);
+        profile.record_request(CF_PNG);
+        assert_eq!(profile.preferred_image_formats(), vec![CF_DIB, CF_PNG]);
+
+        profile.reset();
+        assert!(profile.preferred_image_formats().is_empty());
+        assert_eq!(profile.image_observations(), 0);
+    }
+}
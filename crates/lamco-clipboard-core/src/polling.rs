@@ -0,0 +1,522 @@
+//! Polling fallback for clipboard backends without change notification.
+//!
+//! Some backends (X11 without XFIXES, minimal headless sinks) cannot report
+//! when the clipboard changes. [`PollingChangeDriver`] wraps such a sink and
+//! synthesizes [`ClipboardChange`](crate::ClipboardChange) events by periodically reading a set of
+//! probe formats and comparing content hashes.
+//!
+//! The poll interval adapts to activity: it resets to the minimum after a
+//! change and backs off towards the maximum while the clipboard is idle.
+//!
+//! [`PollingConfig`] is available without the `tokio` feature so it can be
+//! part of [`ClipboardConfig`](crate::config::ClipboardConfig); the driver
+//! itself needs a Tokio runtime.
+
+#[cfg(feature = "tokio")]
+use crate::sink::{ClipboardChange, ClipboardChangeReceiver, ClipboardChangeReceiverInner, ClipboardSink, FileInfo};
+#[cfg(feature = "tokio")]
+use crate::ClipboardResult;
+#[cfg(feature = "tokio")]
+use sha2::{Digest, Sha256};
+use std::fmt;
+#[cfg(feature = "tokio")]
+use std::future::Future;
+use std::str::FromStr;
+#[cfg(feature = "tokio")]
+use std::sync::Arc;
+#[cfg(feature = "tokio")]
+use std::time::Duration;
+#[cfg(feature = "tokio")]
+use tokio::sync::mpsc;
+
+/// Default minimum poll interval in milliseconds
+pub const DEFAULT_MIN_POLL_INTERVAL_MS: u64 = 250;
+
+/// Default maximum poll interval in milliseconds
+pub const DEFAULT_MAX_POLL_INTERVAL_MS: u64 = 2000;
+
+/// When [`PollingChangeDriver`] polls instead of using the sink's own change events
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "serde", serde(rename_all = "snake_case"))]
+pub enum PollingMode {
+    /// Never poll; change subscriptions go straight to the sink
+    Disabled,
+
+    /// Poll only if the sink fails to subscribe to change events
+    Fallback,
+
+    /// Always poll, ignoring the sink's change events
+    #[default]
+    Always,
+}
+
+impl PollingMode {
+    /// Every mode, in configuration order
+    pub const ALL: [Self; 3] = [Self::Disabled, Self::Fallback, Self::Always];
+
+    /// Name used in configuration files
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Self::Disabled => "disabled",
+            Self::Fallback => "fallback",
+            Self::Always => "always",
+        }
+    }
+}
+
+impl fmt::Display for PollingMode {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+impl FromStr for PollingMode {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Self::ALL
+            .into_iter()
+            .find(|mode| mode.as_str().eq_ignore_ascii_case(s.trim()))
+            .ok_or_else(|| format!("unknown polling mode '{}'", s))
+    }
+}
+
+/// Configuration for [`PollingChangeDriver`]
+#[derive(Debug, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "serde", serde(default))]
+pub struct PollingConfig {
+    /// Whether change subscriptions are served by polling
+    pub mode: PollingMode,
+
+    /// Interval used right after a change is observed (milliseconds)
+    pub min_interval_ms: u64,
+
+    /// Upper bound for the interval while the clipboard is idle (milliseconds)
+    pub max_interval_ms: u64,
+
+    /// Multiplier applied to the interval after each idle poll
+    pub backoff_factor: f64,
+
+    /// MIME types read on every poll to detect changes
+    pub probe_mime_types: Vec<String>,
+}
+
+impl Default for PollingConfig {
+    fn default() -> Self {
+        Self {
+            mode: PollingMode::default(),
+            min_interval_ms: DEFAULT_MIN_POLL_INTERVAL_MS,
+            max_interval_ms: DEFAULT_MAX_POLL_INTERVAL_MS,
+            backoff_factor: 1.5,
+            probe_mime_types: vec![
+                "text/plain;charset=utf-8".to_string(),
+                "text/html".to_string(),
+                "image/png".to_string(),
+                "text/uri-list".to_string(),
+            ],
+        }
+    }
+}
+
+impl PollingConfig {
+    /// Set when polling is used
+    pub fn with_mode(mut self, mode: PollingMode) -> Self {
+        self.mode = mode;
+        self
+    }
+
+    /// Set the minimum and maximum poll intervals
+    pub fn with_intervals(mut self, min_ms: u64, max_ms: u64) -> Self {
+        self.min_interval_ms = min_ms.max(1);
+        self.max_interval_ms = max_ms.max(self.min_interval_ms);
+        self
+    }
+
+    /// Set the MIME types read on every poll
+    pub fn with_probe_mime_types(mut self, mime_types: Vec<String>) -> Self {
+        self.probe_mime_types = mime_types;
+        self
+    }
+}
+
+#[cfg(feature = "tokio")]
+/// Clipboard sink wrapper that synthesizes change events by polling.
+///
+/// All [`ClipboardSink`] operations are forwarded to the wrapped sink except
+/// [`subscribe_changes`](ClipboardSink::subscribe_changes), which, as
+/// [`PollingConfig::mode`] allows, spawns a polling task on the current Tokio
+/// runtime. The task stops once the returned receiver is dropped.
+///
+/// # Example
+///
+/// ```rust,ignore
+/// use lamco_clipboard_core::{ClipboardSink, PollingChangeDriver, PollingConfig};
+///
+/// let driver = PollingChangeDriver::with_config(x11_sink, PollingConfig::default().with_intervals(100, 1000));
+/// let mut changes = driver.subscribe_changes().await?;
+/// ```
+#[derive(Debug)]
+pub struct PollingChangeDriver<S> {
+    sink: Arc<S>,
+    config: PollingConfig,
+}
+
+#[cfg(feature = "tokio")]
+impl<S: ClipboardSink + 'static> PollingChangeDriver<S> {
+    /// Wrap a sink with the default polling configuration
+    pub fn new(sink: S) -> Self {
+        Self::with_config(sink, PollingConfig::default())
+    }
+
+    /// Wrap a sink with a custom polling configuration
+    pub fn with_config(sink: S, config: PollingConfig) -> Self {
+        Self {
+            sink: Arc::new(sink),
+            config,
+        }
+    }
+
+    /// Get a reference to the wrapped sink
+    pub fn inner(&self) -> &S {
+        &self.sink
+    }
+
+    /// Get the polling configuration
+    pub fn config(&self) -> &PollingConfig {
+        &self.config
+    }
+}
+
+#[cfg(feature = "tokio")]
+impl<S: ClipboardSink + 'static> ClipboardSink for PollingChangeDriver<S> {
+    fn announce_formats(&self, mime_types: Vec<String>) -> impl Future<Output = ClipboardResult<()>> + Send {
+        self.sink.announce_formats(mime_types)
+    }
+
+    fn read_clipboard(&self, mime_type: &str) -> impl Future<Output = ClipboardResult<Vec<u8>>> + Send {
+        self.sink.read_clipboard(mime_type)
+    }
+
+    fn write_clipboard(&self, mime_type: &str, data: Vec<u8>) -> impl Future<Output = ClipboardResult<()>> + Send {
+        self.sink.write_clipboard(mime_type, data)
+    }
+
+    fn subscribe_changes(&self) -> impl Future<Output = ClipboardResult<ClipboardChangeReceiver>> + Send {
+        let sink = Arc::clone(&self.sink);
+        let config = self.config.clone();
+
+        async move {
+            match config.mode {
+                PollingMode::Disabled => return sink.subscribe_changes().await,
+                PollingMode::Fallback => match sink.subscribe_changes().await {
+                    Ok(receiver) => return Ok(receiver),
+                    Err(e) => tracing::info!("Sink has no change events ({}), polling instead", e),
+                },
+                PollingMode::Always => {}
+            }
+
+            let (tx, rx) = mpsc::unbounded_channel();
+            tokio::spawn(poll_loop(sink, config, tx));
+            Ok(ClipboardChangeReceiver::new(Box::new(PollingReceiver { rx })))
+        }
+    }
+
+    fn get_file_list(&self) -> impl Future<Output = ClipboardResult<Vec<FileInfo>>> + Send {
+        self.sink.get_file_list()
+    }
+
+    fn read_file_chunk(
+        &self,
+        index: u32,
+        offset: u64,
+        size: u32,
+    ) -> impl Future<Output = ClipboardResult<Vec<u8>>> + Send {
+        self.sink.read_file_chunk(index, offset, size)
+    }
+
+    fn write_file(&self, path: &str, data: Vec<u8>) -> impl Future<Output = ClipboardResult<()>> + Send {
+        self.sink.write_file(path, data)
+    }
+}
+
+#[cfg(feature = "tokio")]
+/// Receiver side of the polling task
+struct PollingReceiver {
+    rx: mpsc::UnboundedReceiver<ClipboardChange>,
+}
+
+#[cfg(feature = "tokio")]
+impl ClipboardChangeReceiverInner for PollingReceiver {
+    fn recv_blocking(&mut self) -> Option<ClipboardChange> {
+        self.rx.blocking_recv()
+    }
+
+    fn try_recv(&mut self) -> Option<ClipboardChange> {
+        self.rx.try_recv().ok()
+    }
+}
+
+#[cfg(feature = "tokio")]
+/// Result of reading all probe formats once
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct Snapshot {
+    mime_types: Vec<String>,
+    hash: Option<String>,
+}
+
+#[cfg(feature = "tokio")]
+/// Change detection and interval adaptation, independent of the runtime
+#[derive(Debug)]
+struct PollState {
+    last_hash: Option<String>,
+    initialized: bool,
+    interval_ms: u64,
+    min_interval_ms: u64,
+    max_interval_ms: u64,
+    backoff_factor: f64,
+}
+
+#[cfg(feature = "tokio")]
+impl PollState {
+    fn new(config: &PollingConfig) -> Self {
+        let min_interval_ms = config.min_interval_ms.max(1);
+        Self {
+            last_hash: None,
+            initialized: false,
+            interval_ms: min_interval_ms,
+            min_interval_ms,
+            max_interval_ms: config.max_interval_ms.max(min_interval_ms),
+            backoff_factor: config.backoff_factor.max(1.0),
+        }
+    }
+
+    fn interval(&self) -> Duration {
+        Duration::from_millis(self.interval_ms)
+    }
+
+    /// Record a snapshot, returning a change event if content changed.
+    ///
+    /// The first snapshot only establishes a baseline. Transitions to an
+    /// empty clipboard are tracked but not reported.
+    fn observe(&mut self, snapshot: Snapshot) -> Option<ClipboardChange> {
+        let changed = self.initialized && snapshot.hash != self.last_hash;
+        self.initialized = true;
+        self.last_hash.clone_from(&snapshot.hash);
+
+        if changed {
+            self.interval_ms = self.min_interval_ms;
+        } else {
+            let next = (self.interval_ms as f64 * self.backoff_factor).ceil() as u64;
+            self.interval_ms = next.clamp(self.min_interval_ms, self.max_interval_ms);
+        }
+
+        match snapshot.hash {
+            Some(hash) if changed => Some(ClipboardChange::new(snapshot.mime_types).with_hash(hash)),
+            _ => None,
+        }
+    }
+}
+
+#[cfg(feature = "tokio")]
+/// Read every probe format and hash whatever is available
+async fn take_snapshot<S: ClipboardSink>(sink: &S, probe_mime_types: &[String]) -> Snapshot {
+    let mut hasher = Sha256::new();
+    let mut mime_types = Vec::new();
+
+    for mime_type in probe_mime_types {
+        match sink.read_clipboard(mime_type).await {
+            Ok(data) if !data.is_empty() => {
+                hasher.update(mime_type.as_bytes());
+                hasher.update((data.len() as u64).to_le_bytes());
+                hasher.update(&data);
+                mime_types.push(mime_type.clone());
+            }
+            Ok(_) => {}
+            Err(e) => {
+                tracing::trace!("Poll read of {} failed: {}", mime_type, e);
+            }
+        }
+    }
+
+    let hash = if mime_types.is_empty() {
+        None
+    } else {
+        Some(format!("{:x}", hasher.finalize()))
+    };
+
+    Snapshot { mime_types, hash }
+}
+
+#[cfg(feature = "tokio")]
+async fn poll_loop<S: ClipboardSink>(sink: Arc<S>, config: PollingConfig, tx: mpsc::UnboundedSender<ClipboardChange>) {
+    let mut state = PollState::new(&config);
+
+    loop {
+        let snapshot = take_snapshot(sink.as_ref(), &config.probe_mime_types).await;
+
+        if let Some(change) = state.observe(snapshot) {
+            tracing::debug!("Polling detected clipboard change: {:?}", change.mime_types);
+            if tx.send(change).is_err() {
+                break;
+            }
+        }
+
+        if tx.is_closed() {
+            break;
+        }
+
+        tokio::time::sleep(state.interval()).await;
+    }
+
+    tracing::debug!("Clipboard polling stopped");
+}
+
+#[cfg(all(test, feature = "tokio"))]
+mod tests {
+    use super::*;
+
+    fn snapshot(hash: Option<&str>) -> Snapshot {
+        Snapshot {
+            mime_types: hash.iter().map(|_| "text/plain".to_string()).collect(),
+            hash: hash.map(String::from),
+        }
+    }
+
+    fn config() -> PollingConfig {
+        PollingConfig {
+            min_interval_ms: 100,
+            max_interval_ms: 400,
+            backoff_factor: 2.0,
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn test_first_snapshot_is_baseline() {
+        let mut state = PollState::new(&config());
+        assert!(state.observe(snapshot(Some("a"))).is_none());
+        assert!(state.observe(snapshot(Some("a"))).is_none());
+    }
+
+    #[test]
+    fn test_change_detected() {
+        let mut state = PollState::new(&config());
+        state.observe(snapshot(Some("a")));
+
+        let change = state.observe(snapshot(Some("b"))).unwrap();
+        assert_eq!(change.mime_types, vec!["text/plain".to_string()]);
+        assert_eq!(change.content_hash.as_deref(), Some("b"));
+    }
+
+    #[test]
+    fn test_cleared_clipboard_not_reported() {
+        let mut state = PollState::new(&config());
+        state.observe(snapshot(Some("a")));
+        assert!(state.observe(snapshot(None)).is_none());
+
+        // Content reappearing after a clear is a change
+        assert!(state.observe(snapshot(Some("a"))).is_some());
+    }
+
+    #[test]
+    fn test_adaptive_interval() {
+        let mut state = PollState::new(&config());
+        assert_eq!(state.interval(), Duration::from_millis(100));
+
+        state.observe(snapshot(Some("a")));
+        assert_eq!(state.interval(), Duration::from_millis(200));
+        state.observe(snapshot(Some("a")));
+        assert_eq!(state.interval(), Duration::from_millis(400));
+        state.observe(snapshot(Some("a")));
+        assert_eq!(state.interval(), Duration::from_millis(400));
+
+        // A change resets to the minimum
+        state.observe(snapshot(Some("b")));
+        assert_eq!(state.interval(), Duration::from_millis(100));
+    }
+
+    struct OneChange(Option<ClipboardChange>);
+
+    impl ClipboardChangeReceiverInner for OneChange {
+        fn recv_blocking(&mut self) -> Option<ClipboardChange> {
+            self.0.take()
+        }
+
+        fn try_recv(&mut self) -> Option<ClipboardChange> {
+            self.0.take()
+        }
+    }
+
+    /// Sink whose change events are either available or unsupported
+    struct EventSink {
+        has_events: bool,
+    }
+
+    impl ClipboardSink for EventSink {
+        async fn announce_formats(&self, _mime_types: Vec<String>) -> ClipboardResult<()> {
+            Ok(())
+        }
+
+        async fn read_clipboard(&self, _mime_type: &str) -> ClipboardResult<Vec<u8>> {
+            Ok(b"x".to_vec())
+        }
+
+        async fn write_clipboard(&self, _mime_type: &str, _data: Vec<u8>) -> ClipboardResult<()> {
+            Ok(())
+        }
+
+        async fn subscribe_changes(&self) -> ClipboardResult<ClipboardChangeReceiver> {
+            if self.has_events {
+                let change = ClipboardChange::new(vec!["text/plain".to_string()]).with_hash("native");
+                Ok(ClipboardChangeReceiver::new(Box::new(OneChange(Some(change)))))
+            } else {
+                Err(crate::ClipboardError::Backend(
+                    "change events not supported".to_string(),
+                ))
+            }
+        }
+
+        async fn get_file_list(&self) -> ClipboardResult<Vec<FileInfo>> {
+            Ok(Vec::new())
+        }
+
+        async fn read_file_chunk(&self, _index: u32, _offset: u64, _size: u32) -> ClipboardResult<Vec<u8>> {
+            Ok(Vec::new())
+        }
+
+        async fn write_file(&self, _path: &str, _data: Vec<u8>) -> ClipboardResult<()> {
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn test_modes() {
+        let runtime = tokio::runtime::Builder::new_current_thread()
+            .enable_time()
+            .build()
+            .unwrap();
+        let subscribe = |has_events, mode| {
+            let driver = PollingChangeDriver::with_config(EventSink { has_events }, config().with_mode(mode));
+            runtime.block_on(driver.subscribe_changes())
+        };
+        let native = |receiver: ClipboardChangeReceiver| {
+            let mut receiver = receiver;
+            receiver.try_recv().and_then(|change| change.content_hash) == Some("native".to_string())
+        };
+
+        assert!(subscribe(false, PollingMode::Disabled).is_err());
+        assert!(native(subscribe(true, PollingMode::Disabled).unwrap()));
+        assert!(native(subscribe(true, PollingMode::Fallback).unwrap()));
+        assert!(!native(subscribe(false, PollingMode::Fallback).unwrap()));
+        assert!(!native(subscribe(true, PollingMode::Always).unwrap()));
+    }
+
+    #[test]
+    fn test_config_intervals_clamped() {
+        let config = PollingConfig::default().with_intervals(0, 0);
+        assert_eq!(config.min_interval_ms, 1);
+        assert_eq!(config.max_interval_ms, 1);
+    }
+}
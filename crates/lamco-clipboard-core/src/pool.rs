@@ -0,0 +1,377 @@
+//! Reusable byte buffers for PDU encoding and chunk staging.
+//!
+//! A folder paste with thousands of small files turns into thousands of
+//! File Contents Requests, and each response used to allocate a fresh
+//! `Vec` for its PDU and another for every staged chunk. [`BufferPool`]
+//! keeps released buffers in a few size classes and hands them out again,
+//! so steady-state transfers stop hitting the allocator.
+//!
+//! Buffers come back to the pool when their [`PooledBuffer`] is dropped.
+//! Each class keeps a bounded number of idle buffers and requests larger
+//! than the biggest class are served unpooled, so the pool never holds more
+//! than `size classes × buffers per class` worth of memory.
+
+use std::fmt;
+use std::ops::{Deref, DerefMut};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+
+/// Default buffer capacities: a PDU header, a small file, a chunk, a large chunk
+pub const DEFAULT_SIZE_CLASSES: &[usize] = &[256, 4 * 1024, 64 * 1024, 1024 * 1024];
+
+/// Default number of idle buffers kept per size class
+pub const DEFAULT_BUFFERS_PER_CLASS: usize = 32;
+
+/// Counters describing a [`BufferPool`]
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct PoolMetrics {
+    /// Buffers handed out from the idle list
+    pub hits: u64,
+
+    /// Buffers that had to be allocated
+    pub misses: u64,
+
+    /// Buffers put back on an idle list
+    pub returned: u64,
+
+    /// Buffers freed because their class was full or they fit no class
+    pub discarded: u64,
+
+    /// Buffers currently idle in the pool
+    pub idle: usize,
+}
+
+struct SizeClass {
+    capacity: usize,
+    free: Mutex<Vec<Vec<u8>>>,
+}
+
+struct PoolInner {
+    classes: Vec<SizeClass>,
+    buffers_per_class: usize,
+    hits: AtomicU64,
+    misses: AtomicU64,
+    returned: AtomicU64,
+    discarded: AtomicU64,
+}
+
+impl PoolInner {
+    fn release(&self, mut buf: Vec<u8>) {
+        // Largest class the buffer can still serve; grown buffers move up
+        let class = self.classes.iter().rev().find(|class| buf.capacity() >= class.capacity);
+        if let Some(class) = class.filter(|class| buf.capacity() <= class.capacity * 2) {
+            let mut free = class.free.lock().unwrap_or_else(|e| e.into_inner());
+            if free.len() < self.buffers_per_class {
+                buf.clear();
+                free.push(buf);
+                self.returned.fetch_add(1, Ordering::Relaxed);
+                return;
+            }
+        }
+        self.discarded.fetch_add(1, Ordering::Relaxed);
+    }
+}
+
+/// Pool of byte buffers in fixed size classes.
+///
+/// Cloning is cheap and shares the pool, so one pool can serve every
+/// transfer of a session.
+///
+/// # Example
+///
+/// ```
+/// use lamco_clipboard_core::BufferPool;
+///
+/// let pool = BufferPool::new();
+/// {
+///     let mut pdu = pool.get(1200);
+///     pdu.extend_from_slice(&[0u8; 1200]);
+/// } // returned to the 4 KiB class
+///
+/// let reused = pool.get(3000);
+/// assert!(reused.is_empty());
+/// assert_eq!(pool.metrics().hits, 1);
+/// ```
+#[derive(Clone)]
+pub struct BufferPool {
+    inner: Arc<PoolInner>,
+}
+
+impl Default for BufferPool {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl BufferPool {
+    /// Create a pool with [`DEFAULT_SIZE_CLASSES`]
+    pub fn new() -> Self {
+        Self::with_size_classes(DEFAULT_SIZE_CLASSES, DEFAULT_BUFFERS_PER_CLASS)
+    }
+
+    /// Create a pool with custom class capacities (in bytes, any order)
+    pub fn with_size_classes(capacities: &[usize], buffers_per_class: usize) -> Self {
+        let mut capacities: Vec<usize> = capacities.iter().copied().filter(|&c| c > 0).collect();
+        capacities.sort_unstable();
+        capacities.dedup();
+
+        Self {
+            inner: Arc::new(PoolInner {
+                classes: capacities
+                    .into_iter()
+                    .map(|capacity| SizeClass {
+                        capacity,
+                        free: Mutex::new(Vec::new()),
+                    })
+                    .collect(),
+                buffers_per_class,
+                hits: AtomicU64::new(0),
+                misses: AtomicU64::new(0),
+                returned: AtomicU64::new(0),
+                discarded: AtomicU64::new(0),
+            }),
+        }
+    }
+
+    /// Get an empty buffer with at least `min_capacity` bytes of capacity
+    pub fn get(&self, min_capacity: usize) -> PooledBuffer {
+        let Some(class) = self.inner.classes.iter().find(|class| class.capacity >= min_capacity) else {
+            self.inner.misses.fetch_add(1, Ordering::Relaxed);
+            return PooledBuffer {
+                buf: Vec::with_capacity(min_capacity),
+                pool: None,
+            };
+        };
+
+        let reused = class.free.lock().unwrap_or_else(|e| e.into_inner()).pop();
+        let buf = match reused {
+            Some(buf) => {
+                self.inner.hits.fetch_add(1, Ordering::Relaxed);
+                buf
+            }
+            None => {
+                self.inner.misses.fetch_add(1, Ordering::Relaxed);
+                Vec::with_capacity(class.capacity)
+            }
+        };
+
+        PooledBuffer {
+            buf,
+            pool: Some(Arc::clone(&self.inner)),
+        }
+    }
+
+    /// Get a buffer holding a copy of `data`
+    pub fn copy_from(&self, data: &[u8]) -> PooledBuffer {
+        let mut buf = self.get(data.len());
+        buf.extend_from_slice(data);
+        buf
+    }
+
+    /// Take ownership of an existing buffer so it is recycled when dropped
+    pub fn adopt(&self, buf: Vec<u8>) -> PooledBuffer {
+        PooledBuffer {
+            buf,
+            pool: Some(Arc::clone(&self.inner)),
+        }
+    }
+
+    /// Current counters
+    pub fn metrics(&self) -> PoolMetrics {
+        PoolMetrics {
+            hits: self.inner.hits.load(Ordering::Relaxed),
+            misses: self.inner.misses.load(Ordering::Relaxed),
+            returned: self.inner.returned.load(Ordering::Relaxed),
+            discarded: self.inner.discarded.load(Ordering::Relaxed),
+            idle: self
+                .inner
+                .classes
+                .iter()
+                .map(|class| class.free.lock().unwrap_or_else(|e| e.into_inner()).len())
+                .sum(),
+        }
+    }
+
+    /// Free every idle buffer
+    pub fn clear(&self) {
+        for class in &self.inner.classes {
+            class.free.lock().unwrap_or_else(|e| e.into_inner()).clear();
+        }
+    }
+}
+
+impl fmt::Debug for BufferPool {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let capacities: Vec<usize> = self.inner.classes.iter().map(|class| class.capacity).collect();
+        f.debug_struct("BufferPool")
+            .field("size_classes", &capacities)
+            .field("buffers_per_class", &self.inner.buffers_per_class)
+            .field("metrics", &self.metrics())
+            .finish()
+    }
+}
+
+/// A buffer from a [`BufferPool`], returned to it on drop.
+///
+/// Dereferences to `Vec<u8>`, so it can be filled and read like one.
+pub struct PooledBuffer {
+    buf: Vec<u8>,
+    pool: Option<Arc<PoolInner>>,
+}
+
+impl PooledBuffer {
+    /// Detach the buffer from the pool and take the `Vec`
+    pub fn into_vec(mut self) -> Vec<u8> {
+        self.pool = None;
+        std::mem::take(&mut self.buf)
+    }
+}
+
+impl Deref for PooledBuffer {
+    type Target = Vec<u8>;
+
+    fn deref(&self) -> &Vec<u8> {
+        &self.buf
+    }
+}
+
+impl DerefMut for PooledBuffer {
+    fn deref_mut(&mut self) -> &mut Vec<u8> {
+        &mut self.buf
+    }
+}
+
+impl AsRef<[u8]> for PooledBuffer {
+    fn as_ref(&self) -> &[u8] {
+        &self.buf
+    }
+}
+
+impl fmt::Debug for PooledBuffer {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("PooledBuffer")
+            .field("len", &self.buf.len())
+            .field("capacity", &self.buf.capacity())
+            .finish()
+    }
+}
+
+impl Drop for PooledBuffer {
+    fn drop(&mut self) {
+        if let Some(pool) = self.pool.take() {
+            pool.release(std::mem::take(&mut self.buf));
+        }
+    }
+}
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use super::*;
+    use std::alloc::{GlobalAlloc, Layout, System};
+    use std::cell::Cell;
+
+    thread_local! {
+        static ALLOCATIONS: Cell<usize> = const { Cell::new(0) };
+    }
+
+    /// System allocator that counts allocations made by the current thread
+    struct CountingAllocator;
+
+    #[allow(unsafe_code)]
+    // SAFETY: every call is forwarded unchanged to the system allocator
+    unsafe impl GlobalAlloc for CountingAllocator {
+        unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+            let _ = ALLOCATIONS.try_with(|count| count.set(count.get() + 1));
+            // SAFETY: the caller upholds `GlobalAlloc::alloc`'s contract
+            unsafe { System.alloc(layout) }
+        }
+
+        unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+            // SAFETY: `ptr` was allocated by `System` with this layout
+            unsafe { System.dealloc(ptr, layout) }
+        }
+
+        unsafe fn realloc(&self, ptr: *mut u8, layout: Layout, new_size: usize) -> *mut u8 {
+            let _ = ALLOCATIONS.try_with(|count| count.set(count.get() + 1));
+            // SAFETY: the caller upholds `GlobalAlloc::realloc`'s contract
+            unsafe { System.realloc(ptr, layout, new_size) }
+        }
+    }
+
+    #[global_allocator]
+    static ALLOCATOR: CountingAllocator = CountingAllocator;
+
+    /// Number of allocations `f` makes on this thread
+    pub(crate) fn count_allocations<T>(f: impl FnOnce() -> T) -> (usize, T) {
+        let before = ALLOCATIONS.with(Cell::get);
+        let result = f();
+        (ALLOCATIONS.with(Cell::get) - before, result)
+    }
+
+    #[test]
+    fn test_size_classes() {
+        let pool = BufferPool::with_size_classes(&[1024, 64], 4);
+        assert!(pool.get(10).capacity() >= 64);
+        assert!(pool.get(65).capacity() >= 1024);
+
+        // Larger than every class: served, but never kept
+        let big = pool.get(4096);
+        assert!(big.capacity() >= 4096);
+        drop(big);
+        assert_eq!(pool.metrics().idle, 2);
+        assert_eq!(pool.metrics().misses, 3);
+    }
+
+    #[test]
+    fn test_steady_state_does_not_allocate() {
+        let pool = BufferPool::new();
+        let payload = [7u8; 1500];
+
+        // Warm up, then every further round trip reuses the same buffer
+        drop(pool.copy_from(&payload));
+        let (allocations, ()) = count_allocations(|| {
+            for _ in 0..1000 {
+                let buf = pool.copy_from(&payload);
+                assert_eq!(buf.len(), payload.len());
+            }
+        });
+        assert_eq!(allocations, 0);
+        assert_eq!(pool.metrics().hits, 1000);
+
+        let (allocations, ()) = count_allocations(|| {
+            for _ in 0..1000 {
+                drop(payload.to_vec());
+            }
+        });
+        assert_eq!(allocations, 1000);
+    }
+
+    #[test]
+    fn test_idle_buffers_bounded() {
+        let pool = BufferPool::with_size_classes(&[64], 2);
+        let buffers: Vec<_> = (0..5).map(|_| pool.get(64)).collect();
+        drop(buffers);
+
+        let metrics = pool.metrics();
+        assert_eq!((metrics.idle, metrics.returned, metrics.discarded), (2, 2, 3));
+
+        pool.clear();
+        assert_eq!(pool.metrics().idle, 0);
+    }
+
+    #[test]
+    fn test_adopt_and_detach() {
+        let pool = BufferPool::with_size_classes(&[64], 2);
+
+        // A grown buffer moves to the class it can serve
+        let mut adopted = pool.adopt(Vec::with_capacity(100));
+        adopted.push(1);
+        drop(adopted);
+        assert_eq!(pool.metrics().idle, 1);
+
+        let vec = pool.get(10).into_vec();
+        assert!(vec.capacity() >= 64);
+        assert_eq!(pool.metrics().idle, 0);
+        assert_eq!(pool.metrics().returned, 1);
+    }
+}
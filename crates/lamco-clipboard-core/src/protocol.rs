@@ -0,0 +1,1093 @@
+//! Sans-io clipboard session logic.
+//!
+//! [`ClipboardSession`] is the state machine that sits between a CLIPRDR
+//! channel and a local clipboard: which formats to announce, which format to
+//! request from the peer for a local paste, how to answer the peer's Format
+//! Data Requests and which changes are echoes of our own writes. It performs
+//! no I/O and never awaits. Each [`Input`] goes through
+//! [`ClipboardSession::handle`] and comes back as [`Output`]s for the host to
+//! carry out, so the same logic runs under Tokio, in a blocking host or in
+//! WASM, and tests can drive it with plain values.
+//!
+//! [`drive`] is the thin async layer for hosts with a [`ClipboardSink`]: it
+//! runs the local actions against the sink, feeds their results back into the
+//! session and returns what is left for the channel.
+//!
+//! With the `compression` feature, [`ClipboardSession::with_compression`]
+//! exchanges large text and HTML zstd-compressed with peers that support it;
+//! see [`compression`](crate::compression).
+//!
+//! File transfers are not covered here; File Contents Request/Response
+//! exchanges for a FileGroupDescriptorW are handled by
+//! [`FileTransfer`](crate::file_transfer::FileTransfer).
+//!
+//! # Example
+//!
+//! ```
+//! use lamco_clipboard_core::formats::CF_UNICODETEXT;
+//! use lamco_clipboard_core::protocol::{ClipboardSession, Input, LocalAction, Output, PeerMessage};
+//!
+//! let mut session = ClipboardSession::new();
+//! session.handle(Input::LocalChanged {
+//!     mime_types: vec!["text/plain".to_string()],
+//! });
+//!
+//! // The peer pastes: read the local clipboard, then answer with the result
+//! let outputs = session.handle(Input::FormatDataRequest { format_id: CF_UNICODETEXT });
+//! assert_eq!(
+//!     outputs,
+//!     vec![Output::Local(LocalAction::Read { mime_type: "text/plain".to_string() })]
+//! );
+//!
+//! let outputs = session.handle(Input::LocalData {
+//!     mime_type: "text/plain".to_string(),
+//!     data: b"Hi".to_vec(),
+//! });
+//! assert_eq!(
+//!     outputs,
+//!     vec![Output::Peer(PeerMessage::FormatDataResponse(vec![b'H', 0, b'i', 0, 0, 0]))]
+//! );
+//! ```
+
+#[cfg(feature = "compression")]
+use crate::compression::{self, CompressionConfig};
+use crate::converter::standard_format_name;
+use crate::formats::{ClipboardFormat, FormatConverter, CF_LOCALE, CF_UNICODETEXT};
+use crate::loop_detector::{ClipboardSource, LoopDetectionConfig, LoopDetector};
+use crate::negotiate::{FormatNegotiator, Negotiation};
+use crate::registry::{registered_name_to_mime, FormatRegistry};
+use crate::sink::ClipboardSink;
+use std::collections::VecDeque;
+
+/// Events fed into a [`ClipboardSession`]
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "serde", serde(rename_all = "snake_case"))]
+pub enum Input {
+    /// The local clipboard changed
+    LocalChanged {
+        /// MIME types now offered locally
+        mime_types: Vec<String>,
+    },
+
+    /// The peer sent a Format List
+    RemoteFormatList(Vec<ClipboardFormat>),
+
+    /// A local application pastes one of the announced MIME types
+    LocalPaste {
+        /// MIME type the application asked for
+        mime_type: String,
+    },
+
+    /// The peer answered our oldest Format Data Request
+    FormatDataResponse {
+        /// Response payload
+        data: Vec<u8>,
+        /// Whether the peer reported failure
+        is_error: bool,
+    },
+
+    /// The peer sent a Format Data Request
+    FormatDataRequest {
+        /// Format ID from our Format List
+        format_id: u32,
+    },
+
+    /// Result of a [`LocalAction::Read`]
+    LocalData {
+        /// MIME type that was read
+        mime_type: String,
+        /// Local clipboard content
+        data: Vec<u8>,
+    },
+
+    /// A [`LocalAction::Read`] failed
+    LocalReadFailed {
+        /// MIME type that could not be read
+        mime_type: String,
+    },
+}
+
+/// PDUs to send on the CLIPRDR channel
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "serde", serde(rename_all = "snake_case"))]
+pub enum PeerMessage {
+    /// Format List announcing the local clipboard
+    FormatList(Vec<ClipboardFormat>),
+
+    /// Format Data Request for a remote format
+    FormatDataRequest {
+        /// Remote format ID
+        format_id: u32,
+    },
+
+    /// Successful Format Data Response
+    FormatDataResponse(Vec<u8>),
+
+    /// Failed Format Data Response
+    FormatDataError,
+}
+
+/// Operations on the local clipboard
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "serde", serde(rename_all = "snake_case"))]
+pub enum LocalAction {
+    /// Offer the peer's content locally under these MIME types
+    Announce(Vec<String>),
+
+    /// Read the local clipboard and feed the result back as
+    /// [`Input::LocalData`] or [`Input::LocalReadFailed`]
+    Read {
+        /// MIME type to read
+        mime_type: String,
+    },
+
+    /// Hand pasted data to the local application
+    Write {
+        /// MIME type of the data
+        mime_type: String,
+        /// Converted data
+        data: Vec<u8>,
+    },
+
+    /// A local paste cannot be served
+    PasteFailed {
+        /// MIME type the application asked for
+        mime_type: String,
+    },
+}
+
+/// Work produced by a [`ClipboardSession`]
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "serde", serde(rename_all = "snake_case"))]
+pub enum Output {
+    /// Send a PDU to the peer
+    Peer(PeerMessage),
+
+    /// Act on the local clipboard
+    Local(LocalAction),
+}
+
+/// One of our Format Data Requests awaiting its response
+#[derive(Debug, Clone)]
+enum Requested {
+    /// Data for a local paste
+    Paste {
+        choice: Negotiation,
+        /// Requested through the compressed wrapper format
+        #[cfg(feature = "compression")]
+        compressed: bool,
+    },
+    /// CF_LOCALE, to decode the CF_TEXT requested next
+    Locale,
+    /// Opt-in to compressed File Contents Responses
+    #[cfg(feature = "compression")]
+    FileCompression,
+}
+
+/// A peer request waiting for local data
+#[derive(Debug, Clone)]
+struct Serving {
+    /// Format name the peer asked for
+    format: String,
+    /// Local MIME type being read for it
+    mime_type: String,
+    /// Requested through the compressed wrapper format
+    #[cfg(feature = "compression")]
+    compressed: bool,
+}
+
+/// Per-connection clipboard state, without I/O
+///
+/// CLIPRDR answers requests in order, so outstanding requests in each
+/// direction are matched to responses first in, first out.
+#[derive(Debug)]
+pub struct ClipboardSession {
+    converter: FormatConverter,
+    negotiator: FormatNegotiator,
+    registry: FormatRegistry,
+    detector: LoopDetector,
+
+    /// MIME types on the local clipboard, if this side owns it
+    local_mime_types: Vec<String>,
+
+    /// Formats in the peer's last Format List
+    remote_formats: Vec<ClipboardFormat>,
+
+    /// Locale (LCID) the peer published with its CF_TEXT, once fetched
+    remote_locale: Option<u32>,
+
+    /// Our Format Data Requests awaiting a response
+    requested: VecDeque<Requested>,
+
+    /// Peer Format Data Requests awaiting local data
+    serving: VecDeque<Serving>,
+
+    /// Exchange CF_UNICODETEXT only
+    text_only: bool,
+
+    #[cfg(feature = "compression")]
+    compression: Option<CompressionConfig>,
+
+    /// Whether the peer opted in to compressed File Contents Responses for
+    /// the current local copy
+    #[cfg(feature = "compression")]
+    files_compressed: bool,
+}
+
+impl Default for ClipboardSession {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl ClipboardSession {
+    /// Create a session with default conversion, ranking and loop detection
+    pub fn new() -> Self {
+        Self {
+            converter: FormatConverter::new(),
+            negotiator: FormatNegotiator::new(),
+            registry: FormatRegistry::new(),
+            detector: LoopDetector::new(),
+            local_mime_types: Vec::new(),
+            remote_formats: Vec::new(),
+            remote_locale: None,
+            requested: VecDeque::new(),
+            serving: VecDeque::new(),
+            text_only: false,
+            #[cfg(feature = "compression")]
+            compression: None,
+            #[cfg(feature = "compression")]
+            files_compressed: false,
+        }
+    }
+
+    /// Create a session that exchanges plain text only
+    ///
+    /// Local copies announce CF_UNICODETEXT alone, the peer's other formats
+    /// are ignored and requests for them refused, so no image or file
+    /// format is ever converted. For thin clients and kiosks.
+    pub fn text_only() -> Self {
+        Self {
+            text_only: true,
+            ..Self::new()
+        }
+    }
+
+    /// Whether only CF_UNICODETEXT is exchanged
+    pub fn is_text_only(&self) -> bool {
+        self.text_only
+    }
+
+    /// Use a custom converter
+    pub fn with_converter(mut self, converter: FormatConverter) -> Self {
+        self.converter = converter;
+        self
+    }
+
+    /// Use a custom format ranking
+    pub fn with_negotiator(mut self, negotiator: FormatNegotiator) -> Self {
+        self.negotiator = negotiator;
+        self
+    }
+
+    /// Use custom loop detection settings
+    pub fn with_loop_detection(mut self, config: LoopDetectionConfig) -> Self {
+        self.detector = LoopDetector::with_config(config);
+        self
+    }
+
+    /// Exchange compressible formats zstd-compressed with peers that
+    /// support it
+    ///
+    /// Local copies announce a wrapper format next to each compressible
+    /// format and answer requests for it compressed per `config`. Local
+    /// pastes request the wrapper whenever the peer announces one.
+    #[cfg(feature = "compression")]
+    pub fn with_compression(mut self, config: CompressionConfig) -> Self {
+        self.compression = Some(config);
+        self
+    }
+
+    /// Whether the peer asked for compressed File Contents Responses for
+    /// the current local copy
+    ///
+    /// Becomes true when the peer requests
+    /// [`FILE_CONTENTS_FORMAT_NAME`](compression::FILE_CONTENTS_FORMAT_NAME);
+    /// call [`FileServer::accept_compression`](crate::file_transfer::FileServer::accept_compression)
+    /// then.
+    #[cfg(feature = "compression")]
+    pub fn peer_accepts_compressed_files(&self) -> bool {
+        self.files_compressed
+    }
+
+    /// Opt in to compressed File Contents Responses for the peer's current
+    /// copy
+    ///
+    /// Returns the Format Data Request to send before the first File
+    /// Contents Request, or `None` if the peer did not announce
+    /// [`FILE_CONTENTS_FORMAT_NAME`](compression::FILE_CONTENTS_FORMAT_NAME)
+    /// or compression is off; receive the files with
+    /// [`FileTransfer::with_compression`](crate::file_transfer::FileTransfer::with_compression)
+    /// only when a request was returned.
+    #[cfg(feature = "compression")]
+    pub fn request_file_compression(&mut self) -> Option<Output> {
+        self.compression.as_ref()?;
+        let format_id = self.registry.remote_id(compression::FILE_CONTENTS_FORMAT_NAME)?;
+        self.requested.push_back(Requested::FileCompression);
+        Some(Output::Peer(PeerMessage::FormatDataRequest { format_id }))
+    }
+
+    /// The converter used for every conversion
+    pub fn converter(&self) -> &FormatConverter {
+        &self.converter
+    }
+
+    /// Format ID mapping for this connection
+    pub fn registry(&self) -> &FormatRegistry {
+        &self.registry
+    }
+
+    /// Formats in the peer's last Format List
+    pub fn remote_formats(&self) -> &[ClipboardFormat] {
+        &self.remote_formats
+    }
+
+    /// MIME types on the local clipboard, empty while the peer owns it
+    pub fn local_mime_types(&self) -> &[String] {
+        &self.local_mime_types
+    }
+
+    /// Requests sent to the peer and not yet answered
+    pub fn pending_requests(&self) -> usize {
+        self.requested.len()
+    }
+
+    /// Process one input
+    pub fn handle(&mut self, input: Input) -> Vec<Output> {
+        match input {
+            Input::LocalChanged { mime_types } => self.on_local_changed(mime_types),
+            Input::RemoteFormatList(formats) => self.on_remote_format_list(formats),
+            Input::LocalPaste { mime_type } => self.on_local_paste(mime_type),
+            Input::FormatDataResponse { data, is_error } => self.on_format_data_response(data, is_error),
+            Input::FormatDataRequest { format_id } => self.on_format_data_request(format_id),
+            Input::LocalData { mime_type, data } => self.on_local_data(mime_type, data),
+            Input::LocalReadFailed { mime_type } => {
+                tracing::debug!("Local read of {} failed", mime_type);
+                self.serving.pop_front();
+                vec![Output::Peer(PeerMessage::FormatDataError)]
+            }
+        }
+    }
+
+    /// Drop everything but CF_UNICODETEXT in a text-only session
+    fn text_formats(&self, mut formats: Vec<ClipboardFormat>) -> Vec<ClipboardFormat> {
+        if self.text_only {
+            formats.retain(|format| format.id == CF_UNICODETEXT);
+        }
+        formats
+    }
+
+    fn on_local_changed(&mut self, mime_types: Vec<String>) -> Vec<Output> {
+        // Writing pasted data raises a change event of its own
+        if self.detector.would_cause_loop_mime(&mime_types) {
+            tracing::debug!("Ignoring local change caused by a paste: {:?}", mime_types);
+            return Vec::new();
+        }
+
+        let mimes: Vec<&str> = mime_types.iter().map(String::as_str).collect();
+        let formats = self.registry.announce(&mimes);
+        #[cfg(feature = "compression")]
+        let formats = self.add_wrapper_formats(formats);
+        let formats = self.text_formats(formats);
+
+        // A new local owner supersedes whatever the peer announced before
+        self.remote_formats.clear();
+        self.remote_locale = None;
+        self.registry.clear_remote();
+        self.local_mime_types = mime_types;
+        #[cfg(feature = "compression")]
+        {
+            self.files_compressed = false;
+        }
+
+        // Some peers announce a received Format List straight back
+        self.detector.record_formats(&formats, ClipboardSource::Rdp);
+        vec![Output::Peer(PeerMessage::FormatList(formats))]
+    }
+
+    fn on_remote_format_list(&mut self, formats: Vec<ClipboardFormat>) -> Vec<Output> {
+        self.registry.update_remote(&formats);
+        let echo = self.detector.would_cause_loop(&formats);
+        // Filtered only now, as the full list tells a new copy from an echo
+        self.remote_formats = self.text_formats(formats);
+        self.remote_locale = None;
+
+        if echo {
+            tracing::debug!("Ignoring Format List echoing our own announcement");
+            return Vec::new();
+        }
+
+        let mut mime_types: Vec<String> = Vec::new();
+        for format in &self.remote_formats {
+            if let Some(mime) = self.registry.remote_format_to_mime(format.id) {
+                if !mime_types.iter().any(|m| m == mime) {
+                    mime_types.push(mime.to_string());
+                }
+            }
+        }
+
+        self.local_mime_types.clear();
+        vec![Output::Local(LocalAction::Announce(mime_types))]
+    }
+
+    fn on_local_paste(&mut self, mime_type: String) -> Vec<Output> {
+        let Some(choice) = self
+            .negotiator
+            .negotiate(&self.remote_formats, &[mime_type.as_str()], &self.converter)
+        else {
+            tracing::debug!("No remote format can produce {}", mime_type);
+            return vec![Output::Local(LocalAction::PasteFailed { mime_type })];
+        };
+
+        // CF_TEXT is in the peer's ANSI codepage, named by CF_LOCALE
+        let mut outputs = Vec::new();
+        if choice.format == "CF_TEXT"
+            && self.remote_locale.is_none()
+            && self.remote_formats.iter().any(|format| format.id == CF_LOCALE)
+        {
+            self.requested.push_back(Requested::Locale);
+            outputs.push(Output::Peer(PeerMessage::FormatDataRequest { format_id: CF_LOCALE }));
+        }
+
+        let wrapper = self.remote_wrapper(&choice.format);
+        let format_id = wrapper.unwrap_or(choice.format_id);
+        self.requested.push_back(Requested::Paste {
+            choice,
+            #[cfg(feature = "compression")]
+            compressed: wrapper.is_some(),
+        });
+        outputs.push(Output::Peer(PeerMessage::FormatDataRequest { format_id }));
+        outputs
+    }
+
+    fn on_format_data_response(&mut self, data: Vec<u8>, is_error: bool) -> Vec<Output> {
+        let Some(requested) = self.requested.pop_front() else {
+            tracing::warn!("Format Data Response without a pending request");
+            return Vec::new();
+        };
+        let choice = match requested {
+            Requested::Paste { choice, .. } if is_error => {
+                tracing::debug!("Peer failed to provide {}", choice.format);
+                let mime_type = choice.mime_type;
+                return vec![Output::Local(LocalAction::PasteFailed { mime_type })];
+            }
+            #[cfg(feature = "compression")]
+            Requested::Paste {
+                choice,
+                compressed: true,
+            } => match compression::decompress(&data, self.converter.max_size) {
+                Ok(payload) => return self.write_pasted(choice, payload),
+                Err(e) => {
+                    tracing::warn!("Compressed {} from the peer is unusable: {}", choice.format, e);
+                    let mime_type = choice.mime_type;
+                    return vec![Output::Local(LocalAction::PasteFailed { mime_type })];
+                }
+            },
+            Requested::Paste { choice, .. } => choice,
+            Requested::Locale => {
+                if is_error {
+                    tracing::debug!("Peer failed to provide CF_LOCALE");
+                } else {
+                    match self.converter.parse_locale(&data) {
+                        Ok(lcid) => self.remote_locale = Some(lcid),
+                        Err(e) => tracing::debug!("Ignoring CF_LOCALE from the peer: {}", e),
+                    }
+                }
+                return Vec::new();
+            }
+            #[cfg(feature = "compression")]
+            Requested::FileCompression => {
+                if is_error {
+                    tracing::debug!("Peer refused compressed file contents");
+                }
+                return Vec::new();
+            }
+        };
+        self.write_pasted(choice, data)
+    }
+
+    /// Convert pasted data to the local MIME type and write it
+    fn write_pasted(&mut self, choice: Negotiation, data: Vec<u8>) -> Vec<Output> {
+        let mime_type = choice.mime_type;
+
+        let locale = self
+            .remote_locale
+            .filter(|_| choice.format == "CF_TEXT" && mime_essence(&mime_type) == "text/plain");
+        let data = if let Some(lcid) = locale {
+            match self.converter.ansi_to_text_with_locale(&data, lcid) {
+                Ok(text) => text.into_bytes(),
+                Err(e) => {
+                    tracing::warn!("Converting {} to {} failed: {}", choice.format, mime_type, e);
+                    return vec![Output::Local(LocalAction::PasteFailed { mime_type })];
+                }
+            }
+        } else if self.converter.has_converter(&choice.format, &mime_type) {
+            match self.converter.convert(&choice.format, &mime_type, &data) {
+                Ok(data) => data,
+                Err(e) => {
+                    tracing::warn!("Converting {} to {} failed: {}", choice.format, mime_type, e);
+                    return vec![Output::Local(LocalAction::PasteFailed { mime_type })];
+                }
+            }
+        } else {
+            data
+        };
+
+        // The write is echoed back as a local change; see `on_local_changed`
+        self.detector
+            .record_mime_types(std::slice::from_ref(&mime_type), ClipboardSource::Local);
+        vec![Output::Local(LocalAction::Write { mime_type, data })]
+    }
+
+    fn on_format_data_request(&mut self, format_id: u32) -> Vec<Output> {
+        if self.text_only && format_id != CF_UNICODETEXT {
+            tracing::debug!("Refusing format {} in a text-only session", format_id);
+            return vec![Output::Peer(PeerMessage::FormatDataError)];
+        }
+        let format = match self.registry.local_name(format_id) {
+            Some(name) => Some(name.to_string()),
+            None => standard_format_name(format_id).map(str::to_string),
+        };
+        let Some(format) = format else {
+            tracing::debug!("Peer requested unknown format {}", format_id);
+            return vec![Output::Peer(PeerMessage::FormatDataError)];
+        };
+
+        #[cfg(feature = "compression")]
+        let (format, compressed) = match compression::unwrapped_name(&format) {
+            Some(_) if self.compression.is_none() => (format, false),
+            Some(_) if format == compression::FILE_CONTENTS_FORMAT_NAME => {
+                tracing::debug!("Peer accepts compressed file contents");
+                self.files_compressed = true;
+                return vec![Output::Peer(PeerMessage::FormatDataResponse(Vec::new()))];
+            }
+            Some(inner) => (inner.to_string(), true),
+            None => (format, false),
+        };
+
+        let source = self
+            .local_mime_types
+            .iter()
+            .find(|mime| self.converter.has_converter(mime_essence(mime), &format))
+            .or_else(|| {
+                let native = registered_name_to_mime(&format)?;
+                self.local_mime_types
+                    .iter()
+                    .find(|mime| mime_essence(mime) == mime_essence(native))
+            });
+        let Some(mime_type) = source.cloned() else {
+            tracing::debug!("No local MIME type can produce {}", format);
+            return vec![Output::Peer(PeerMessage::FormatDataError)];
+        };
+
+        self.serving.push_back(Serving {
+            format,
+            mime_type: mime_type.clone(),
+            #[cfg(feature = "compression")]
+            compressed,
+        });
+        vec![Output::Local(LocalAction::Read { mime_type })]
+    }
+
+    fn on_local_data(&mut self, mime_type: String, data: Vec<u8>) -> Vec<Output> {
+        let Some(serving) = self.serving.pop_front() else {
+            tracing::warn!("Local data for {} without a pending request", mime_type);
+            return Vec::new();
+        };
+        if serving.mime_type != mime_type {
+            tracing::warn!("Expected local data for {}, got {}", serving.mime_type, mime_type);
+            return vec![Output::Peer(PeerMessage::FormatDataError)];
+        }
+
+        let essence = mime_essence(&mime_type);
+        let data = if self.converter.has_converter(essence, &serving.format) {
+            match self.converter.convert(essence, &serving.format, &data) {
+                Ok(data) => data,
+                Err(e) => {
+                    tracing::warn!("Converting {} to {} failed: {}", mime_type, serving.format, e);
+                    return vec![Output::Peer(PeerMessage::FormatDataError)];
+                }
+            }
+        } else {
+            data
+        };
+
+        #[cfg(feature = "compression")]
+        let data = match self.compression.as_ref().filter(|_| serving.compressed) {
+            Some(config) => match compression::compress(&data, config) {
+                Ok(frame) => frame,
+                Err(e) => {
+                    tracing::warn!("Compressing {} failed: {}", serving.format, e);
+                    return vec![Output::Peer(PeerMessage::FormatDataError)];
+                }
+            },
+            None => data,
+        };
+        vec![Output::Peer(PeerMessage::FormatDataResponse(data))]
+    }
+
+    /// Peer's wrapper format to request instead of `format`
+    #[cfg(feature = "compression")]
+    fn remote_wrapper(&self, format: &str) -> Option<u32> {
+        self.compression.as_ref()?;
+        self.registry.remote_id(&compression::wrapped_name(format))
+    }
+
+    #[cfg(not(feature = "compression"))]
+    fn remote_wrapper(&self, _format: &str) -> Option<u32> {
+        None
+    }
+
+    /// Announce a wrapper format next to every compressible format
+    #[cfg(feature = "compression")]
+    fn add_wrapper_formats(&mut self, mut formats: Vec<ClipboardFormat>) -> Vec<ClipboardFormat> {
+        if self.compression.is_none() {
+            return formats;
+        }
+        let wrapped: Vec<String> = formats
+            .iter()
+            .filter_map(|format| match &format.name {
+                Some(name) => Some(name.as_str()),
+                None => standard_format_name(format.id),
+            })
+            .filter(|name| compression::is_compressible(name))
+            .map(compression::wrapped_name)
+            .collect();
+        for name in wrapped {
+            if let Some(id) = self.registry.register_local(&name) {
+                formats.push(ClipboardFormat::with_name(id, name));
+            }
+        }
+        formats
+    }
+}
+
+/// MIME type without parameters (`text/plain;charset=utf-8` -> `text/plain`)
+fn mime_essence(mime: &str) -> &str {
+    mime.split(';').next().unwrap_or(mime).trim()
+}
+
+/// Process an input, carrying out local actions against a sink
+///
+/// Reads are fed back into the session until nothing local is left. Returns
+/// the PDUs to send and any [`LocalAction::PasteFailed`] for the host to
+/// report to the pasting application.
+pub async fn drive<S: ClipboardSink>(session: &mut ClipboardSession, sink: &S, input: Input) -> Vec<Output> {
+    let mut pending: VecDeque<Output> = session.handle(input).into();
+    let mut remaining = Vec::new();
+
+    while let Some(output) = pending.pop_front() {
+        match output {
+            Output::Local(LocalAction::Announce(mime_types)) => {
+                if let Err(e) = sink.announce_formats(mime_types).await {
+                    tracing::warn!("Announcing remote formats failed: {}", e);
+                }
+            }
+            Output::Local(LocalAction::Read { mime_type }) => {
+                let input = match sink.read_clipboard(&mime_type).await {
+                    Ok(data) => Input::LocalData { mime_type, data },
+                    Err(e) => {
+                        tracing::debug!("Reading {} failed: {}", mime_type, e);
+                        Input::LocalReadFailed { mime_type }
+                    }
+                };
+                pending.extend(session.handle(input));
+            }
+            Output::Local(LocalAction::Write { mime_type, data }) => {
+                if let Err(e) = sink.write_clipboard(&mime_type, data).await {
+                    tracing::warn!("Writing {} failed: {}", mime_type, e);
+                    remaining.push(Output::Local(LocalAction::PasteFailed { mime_type }));
+                }
+            }
+            other => remaining.push(other),
+        }
+    }
+
+    remaining
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::formats::{CF_DIB, CF_HTML, CF_TEXT};
+    use crate::testing::block_on;
+    use crate::testing::sink::MemorySink;
+
+    fn utf16(text: &str) -> Vec<u8> {
+        text.encode_utf16().chain([0]).flat_map(u16::to_le_bytes).collect()
+    }
+
+    #[test]
+    fn test_local_copy_served_to_peer() {
+        let mut session = ClipboardSession::new();
+        let outputs = session.handle(Input::LocalChanged {
+            mime_types: vec!["text/html".to_string(), "text/plain".to_string()],
+        });
+        let [Output::Peer(PeerMessage::FormatList(formats))] = &outputs[..] else {
+            panic!("expected a Format List, got {:?}", outputs);
+        };
+        assert!(formats.iter().any(|f| f.id == CF_HTML));
+
+        // Unknown formats are refused without touching the local clipboard
+        assert_eq!(
+            session.handle(Input::FormatDataRequest { format_id: 0xC0FF }),
+            vec![Output::Peer(PeerMessage::FormatDataError)]
+        );
+
+        session.handle(Input::FormatDataRequest { format_id: CF_HTML });
+        let outputs = session.handle(Input::LocalData {
+            mime_type: "text/html".to_string(),
+            data: b"<b>x</b>".to_vec(),
+        });
+        let [Output::Peer(PeerMessage::FormatDataResponse(cf_html))] = &outputs[..] else {
+            panic!("expected a Format Data Response, got {:?}", outputs);
+        };
+        assert_eq!(session.converter().cf_html_to_html(cf_html).unwrap(), "<b>x</b>");
+    }
+
+    #[test]
+    fn test_text_only() {
+        let mut session = ClipboardSession::text_only();
+        let outputs = session.handle(Input::LocalChanged {
+            mime_types: vec![
+                "text/html".to_string(),
+                "text/plain".to_string(),
+                "image/png".to_string(),
+            ],
+        });
+        assert_eq!(
+            outputs,
+            vec![Output::Peer(PeerMessage::FormatList(vec![
+                ClipboardFormat::unicode_text()
+            ]))]
+        );
+        assert_eq!(
+            session.handle(Input::FormatDataRequest { format_id: CF_HTML }),
+            vec![Output::Peer(PeerMessage::FormatDataError)]
+        );
+
+        let outputs = session.handle(Input::RemoteFormatList(vec![
+            ClipboardFormat::new(CF_DIB),
+            ClipboardFormat::unicode_text(),
+        ]));
+        assert_eq!(
+            outputs,
+            vec![Output::Local(LocalAction::Announce(vec![
+                "text/plain;charset=utf-8".to_string()
+            ]))]
+        );
+        assert!(matches!(
+            &session.handle(Input::LocalPaste {
+                mime_type: "image/png".to_string()
+            })[..],
+            [Output::Local(LocalAction::PasteFailed { .. })]
+        ));
+    }
+
+    #[test]
+    fn test_remote_copy_pasted_locally() {
+        let mut session = ClipboardSession::new();
+        let outputs = session.handle(Input::RemoteFormatList(vec![ClipboardFormat::unicode_text()]));
+        assert_eq!(
+            outputs,
+            vec![Output::Local(LocalAction::Announce(vec![
+                "text/plain;charset=utf-8".to_string()
+            ]))]
+        );
+
+        let outputs = session.handle(Input::LocalPaste {
+            mime_type: "text/plain".to_string(),
+        });
+        assert_eq!(
+            outputs,
+            vec![Output::Peer(PeerMessage::FormatDataRequest {
+                format_id: CF_UNICODETEXT
+            })]
+        );
+
+        let outputs = session.handle(Input::FormatDataResponse {
+            data: utf16("Hi"),
+            is_error: false,
+        });
+        assert_eq!(
+            outputs,
+            vec![Output::Local(LocalAction::Write {
+                mime_type: "text/plain".to_string(),
+                data: b"Hi".to_vec()
+            })]
+        );
+        assert_eq!(session.pending_requests(), 0);
+
+        // Taking ownership of the local clipboard is not a new local copy
+        assert!(session
+            .handle(Input::LocalChanged {
+                mime_types: vec!["text/plain".to_string()]
+            })
+            .is_empty());
+
+        // Nothing offered can become an image
+        assert_eq!(
+            session.handle(Input::LocalPaste {
+                mime_type: "image/png".to_string()
+            }),
+            vec![Output::Local(LocalAction::PasteFailed {
+                mime_type: "image/png".to_string()
+            })]
+        );
+    }
+
+    #[test]
+    fn test_cf_text_decoded_with_peer_locale() {
+        let mut session = ClipboardSession::new();
+        session.handle(Input::RemoteFormatList(vec![
+            ClipboardFormat::new(CF_TEXT),
+            ClipboardFormat::new(CF_LOCALE),
+        ]));
+
+        // The locale is fetched ahead of the text, once per Format List
+        let paste = |session: &mut ClipboardSession| {
+            session.handle(Input::LocalPaste {
+                mime_type: "text/plain".to_string(),
+            })
+        };
+        assert_eq!(
+            paste(&mut session),
+            vec![
+                Output::Peer(PeerMessage::FormatDataRequest { format_id: CF_LOCALE }),
+                Output::Peer(PeerMessage::FormatDataRequest { format_id: CF_TEXT }),
+            ]
+        );
+        assert!(session
+            .handle(Input::FormatDataResponse {
+                data: 0x0419u32.to_le_bytes().to_vec(),
+                is_error: false,
+            })
+            .is_empty());
+
+        // "Привет" in Windows-1251
+        let cp1251 = vec![0xCF, 0xF0, 0xE8, 0xE2, 0xE5, 0xF2, 0x00];
+        let outputs = session.handle(Input::FormatDataResponse {
+            data: cp1251.clone(),
+            is_error: false,
+        });
+        assert_eq!(
+            outputs,
+            vec![Output::Local(LocalAction::Write {
+                mime_type: "text/plain".to_string(),
+                data: "Привет".as_bytes().to_vec()
+            })]
+        );
+
+        assert_eq!(
+            paste(&mut session),
+            vec![Output::Peer(PeerMessage::FormatDataRequest { format_id: CF_TEXT })]
+        );
+        session.handle(Input::FormatDataResponse {
+            data: cp1251,
+            is_error: false,
+        });
+
+        // Without CF_LOCALE, CF_TEXT is Windows-1252
+        session.handle(Input::RemoteFormatList(vec![ClipboardFormat::new(CF_TEXT)]));
+        assert_eq!(
+            paste(&mut session),
+            vec![Output::Peer(PeerMessage::FormatDataRequest { format_id: CF_TEXT })]
+        );
+        let outputs = session.handle(Input::FormatDataResponse {
+            data: vec![0xE9, 0x00],
+            is_error: false,
+        });
+        assert_eq!(
+            outputs,
+            vec![Output::Local(LocalAction::Write {
+                mime_type: "text/plain".to_string(),
+                data: "é".as_bytes().to_vec()
+            })]
+        );
+    }
+
+    #[test]
+    fn test_echoed_format_list_ignored() {
+        let mut session = ClipboardSession::new();
+        let outputs = session.handle(Input::LocalChanged {
+            mime_types: vec!["text/plain".to_string()],
+        });
+        let [Output::Peer(PeerMessage::FormatList(formats))] = &outputs[..] else {
+            panic!("expected a Format List, got {:?}", outputs);
+        };
+
+        assert!(session.handle(Input::RemoteFormatList(formats.clone())).is_empty());
+        assert!(session
+            .handle(Input::FormatDataResponse {
+                data: Vec::new(),
+                is_error: false
+            })
+            .is_empty());
+    }
+
+    #[test]
+    fn test_drive_runs_local_actions() {
+        let sink = MemorySink::default();
+        let mut session = ClipboardSession::new();
+
+        block_on(sink.write_clipboard("text/plain", b"Hi".to_vec())).unwrap();
+        block_on(drive(
+            &mut session,
+            &sink,
+            Input::LocalChanged {
+                mime_types: vec!["text/plain".to_string()],
+            },
+        ));
+
+        // The read is fed back without the host seeing it
+        let outputs = block_on(drive(
+            &mut session,
+            &sink,
+            Input::FormatDataRequest {
+                format_id: CF_UNICODETEXT,
+            },
+        ));
+        assert_eq!(
+            outputs,
+            vec![Output::Peer(PeerMessage::FormatDataResponse(utf16("Hi")))]
+        );
+
+        sink.clear();
+        let outputs = block_on(drive(
+            &mut session,
+            &sink,
+            Input::FormatDataRequest {
+                format_id: CF_UNICODETEXT,
+            },
+        ));
+        assert_eq!(outputs, vec![Output::Peer(PeerMessage::FormatDataError)]);
+    }
+
+    #[cfg(feature = "compression")]
+    #[test]
+    fn test_compression() {
+        let mut copier = ClipboardSession::new().with_compression(CompressionConfig::default());
+        let mut paster = ClipboardSession::new().with_compression(CompressionConfig::default());
+        let outputs = copier.handle(Input::LocalChanged {
+            mime_types: vec!["text/html".to_string(), "text/uri-list".to_string()],
+        });
+        let [Output::Peer(PeerMessage::FormatList(formats))] = &outputs[..] else {
+            panic!("expected a Format List, got {:?}", outputs);
+        };
+        let wrapper = |name: &str| {
+            formats
+                .iter()
+                .find(|f| f.name.as_deref() == Some(name))
+                .map(|f| f.id)
+                .unwrap()
+        };
+        let html_wrapper = wrapper("LamcoZstd:HTML Format");
+        let files_wrapper = wrapper(compression::FILE_CONTENTS_FORMAT_NAME);
+        paster.handle(Input::RemoteFormatList(formats.clone()));
+
+        // A peer without compression asks for the plain format
+        let mut plain = ClipboardSession::new();
+        plain.handle(Input::RemoteFormatList(formats.clone()));
+        let request = Output::Peer(PeerMessage::FormatDataRequest { format_id: CF_HTML });
+        let paste = Input::LocalPaste {
+            mime_type: "text/html".to_string(),
+        };
+        assert_eq!(plain.handle(paste.clone()), [request]);
+
+        // Large HTML crosses compressed and arrives as if sent plain
+        let request = Output::Peer(PeerMessage::FormatDataRequest {
+            format_id: html_wrapper,
+        });
+        assert_eq!(paster.handle(paste), [request]);
+        let outputs = copier.handle(Input::FormatDataRequest {
+            format_id: html_wrapper,
+        });
+        assert_eq!(
+            outputs,
+            [Output::Local(LocalAction::Read {
+                mime_type: "text/html".to_string()
+            })]
+        );
+        let html = "<p>Quarterly figures</p>".repeat(2000).into_bytes();
+        let outputs = copier.handle(Input::LocalData {
+            mime_type: "text/html".to_string(),
+            data: html.clone(),
+        });
+        let [Output::Peer(PeerMessage::FormatDataResponse(frame))] = &outputs[..] else {
+            panic!("expected a response, got {:?}", outputs);
+        };
+        assert!(frame.len() < html.len() / 10);
+
+        let converter = FormatConverter::new();
+        let cf_html = converter.convert("text/html", "HTML Format", &html).unwrap();
+        let expected = converter.convert("HTML Format", "text/html", &cf_html).unwrap();
+        let outputs = paster.handle(Input::FormatDataResponse {
+            data: frame.clone(),
+            is_error: false,
+        });
+        assert_eq!(
+            outputs,
+            [Output::Local(LocalAction::Write {
+                mime_type: "text/html".to_string(),
+                data: expected
+            })]
+        );
+
+        // File contents opt-in
+        assert!(!copier.peer_accepts_compressed_files());
+        let Some(Output::Peer(PeerMessage::FormatDataRequest { format_id })) = paster.request_file_compression() else {
+            panic!("expected an opt-in request");
+        };
+        assert_eq!(format_id, files_wrapper);
+        assert_eq!(
+            copier.handle(Input::FormatDataRequest { format_id }),
+            [Output::Peer(PeerMessage::FormatDataResponse(Vec::new()))]
+        );
+        assert!(copier.peer_accepts_compressed_files());
+        assert!(paster
+            .handle(Input::FormatDataResponse {
+                data: Vec::new(),
+                is_error: false
+            })
+            .is_empty());
+        assert_eq!(paster.pending_requests(), 0);
+        assert!(plain.request_file_compression().is_none());
+
+        // A new copy needs a new opt-in
+        copier.handle(Input::LocalChanged {
+            mime_types: vec!["text/uri-list".to_string()],
+        });
+        assert!(!copier.peer_accepts_compressed_files());
+    }
+
+    #[cfg(feature = "serde")]
+    #[test]
+    fn test_serde_outputs() {
+        let outputs = vec![
+            Output::Peer(PeerMessage::FormatList(vec![ClipboardFormat::new(CF_UNICODETEXT)])),
+            Output::Local(LocalAction::Read {
+                mime_type: "text/plain".to_string(),
+            }),
+        ];
+        let json = serde_json::to_string(&outputs).unwrap();
+        assert_eq!(
+            json,
+            r#"[{"peer":{"format_list":[{"id":13,"name":null}]}},{"local":{"read":{"mime_type":"text/plain"}}}]"#
+        );
+        assert_eq!(serde_json::from_str::<Vec<Output>>(&json).unwrap(), outputs);
+    }
+}
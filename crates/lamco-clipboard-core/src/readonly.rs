@@ -0,0 +1,246 @@
+//! Read-only clipboard policy with placeholder substitution.
+//!
+//! [`ReadOnlySink`] wraps a [`ClipboardSink`] so content can be pasted into
+//! the session but not copied out of it. Rather than dropping local copies,
+//! which leaves the remote user with a paste that silently does nothing,
+//! every local change is announced to the peer as plain text and served as a
+//! placeholder message explaining why the real content is unavailable.
+
+use crate::sink::{ClipboardChange, ClipboardChangeReceiver, ClipboardChangeReceiverInner, ClipboardSink, FileInfo};
+use crate::{ClipboardError, ClipboardResult};
+use std::future::Future;
+use std::sync::Arc;
+
+/// Placeholder served to the peer in place of local clipboard content
+pub const DEFAULT_EXPORT_PLACEHOLDER: &str = "[clipboard export disabled by policy]";
+
+/// MIME type local changes are announced as
+const PLACEHOLDER_MIME: &str = "text/plain;charset=utf-8";
+
+/// Clipboard sink decorator that blocks copying content out of the session.
+///
+/// - **Remote → local**: formats, data and files are passed through unchanged
+/// - **Local → remote**: changes are announced as plain text only, and every
+///   text read returns the placeholder. Other formats are refused and the
+///   file list is always empty.
+///
+/// # Example
+///
+/// ```rust,ignore
+/// use lamco_clipboard_core::ReadOnlySink;
+///
+/// let sink = ReadOnlySink::new(portal_sink).with_placeholder("[copy blocked: confidential session]");
+/// ```
+#[derive(Debug)]
+pub struct ReadOnlySink<S> {
+    inner: S,
+    placeholder: Arc<str>,
+}
+
+impl<S: ClipboardSink> ReadOnlySink<S> {
+    /// Wrap a sink with the default placeholder
+    pub fn new(inner: S) -> Self {
+        Self {
+            inner,
+            placeholder: Arc::from(DEFAULT_EXPORT_PLACEHOLDER),
+        }
+    }
+
+    /// Set the text served to the peer instead of local content
+    pub fn with_placeholder(mut self, placeholder: impl Into<String>) -> Self {
+        self.placeholder = Arc::from(placeholder.into());
+        self
+    }
+
+    /// Get the placeholder text
+    pub fn placeholder(&self) -> &str {
+        &self.placeholder
+    }
+
+    /// Get a reference to the wrapped sink
+    pub fn inner(&self) -> &S {
+        &self.inner
+    }
+
+    /// Unwrap the inner sink
+    pub fn into_inner(self) -> S {
+        self.inner
+    }
+}
+
+/// Whether a placeholder can be served for a requested MIME type
+fn is_plain_text(mime_type: &str) -> bool {
+    let essence = mime_type.split(';').next().unwrap_or("").trim();
+    essence.eq_ignore_ascii_case("text/plain") || matches!(essence, "UTF8_STRING" | "STRING" | "TEXT")
+}
+
+impl<S: ClipboardSink> ClipboardSink for ReadOnlySink<S> {
+    fn announce_formats(&self, mime_types: Vec<String>) -> impl Future<Output = ClipboardResult<()>> + Send {
+        self.inner.announce_formats(mime_types)
+    }
+
+    async fn read_clipboard(&self, mime_type: &str) -> ClipboardResult<Vec<u8>> {
+        if is_plain_text(mime_type) {
+            tracing::debug!("Clipboard export disabled, serving placeholder for {}", mime_type);
+            Ok(self.placeholder.as_bytes().to_vec())
+        } else {
+            Err(ClipboardError::UnsupportedFormat(format!(
+                "{} (clipboard export disabled)",
+                mime_type
+            )))
+        }
+    }
+
+    fn write_clipboard(&self, mime_type: &str, data: Vec<u8>) -> impl Future<Output = ClipboardResult<()>> + Send {
+        self.inner.write_clipboard(mime_type, data)
+    }
+
+    async fn subscribe_changes(&self) -> ClipboardResult<ClipboardChangeReceiver> {
+        let inner = self.inner.subscribe_changes().await?;
+        Ok(ClipboardChangeReceiver::new(Box::new(PlaceholderChanges { inner })))
+    }
+
+    async fn get_file_list(&self) -> ClipboardResult<Vec<FileInfo>> {
+        Ok(Vec::new())
+    }
+
+    async fn read_file_chunk(&self, index: u32, _offset: u64, _size: u32) -> ClipboardResult<Vec<u8>> {
+        Err(ClipboardError::InvalidState(format!(
+            "file {} not available: clipboard export disabled",
+            index
+        )))
+    }
+
+    fn write_file(&self, path: &str, data: Vec<u8>) -> impl Future<Output = ClipboardResult<()>> + Send {
+        self.inner.write_file(path, data)
+    }
+}
+
+/// Change receiver that reduces every local change to a plain text offer
+struct PlaceholderChanges {
+    inner: ClipboardChangeReceiver,
+}
+
+impl PlaceholderChanges {
+    fn rewrite(mut change: ClipboardChange) -> ClipboardChange {
+        if !change.mime_types.is_empty() {
+            change.mime_types = vec![PLACEHOLDER_MIME.to_string()];
+        }
+        change
+    }
+}
+
+impl ClipboardChangeReceiverInner for PlaceholderChanges {
+    fn recv_blocking(&mut self) -> Option<ClipboardChange> {
+        self.inner.recv_blocking().map(Self::rewrite)
+    }
+
+    fn try_recv(&mut self) -> Option<ClipboardChange> {
+        self.inner.try_recv().map(Self::rewrite)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::block_on;
+    use std::collections::VecDeque;
+    use std::sync::Mutex;
+
+    struct QueuedChanges(VecDeque<ClipboardChange>);
+
+    impl ClipboardChangeReceiverInner for QueuedChanges {
+        fn recv_blocking(&mut self) -> Option<ClipboardChange> {
+            self.0.pop_front()
+        }
+
+        fn try_recv(&mut self) -> Option<ClipboardChange> {
+            self.0.pop_front()
+        }
+    }
+
+    #[derive(Default)]
+    struct RecordingSink {
+        writes: Mutex<Vec<(String, Vec<u8>)>>,
+    }
+
+    impl ClipboardSink for RecordingSink {
+        async fn announce_formats(&self, _mime_types: Vec<String>) -> ClipboardResult<()> {
+            Ok(())
+        }
+
+        async fn read_clipboard(&self, _mime_type: &str) -> ClipboardResult<Vec<u8>> {
+            Ok(b"secret".to_vec())
+        }
+
+        async fn write_clipboard(&self, mime_type: &str, data: Vec<u8>) -> ClipboardResult<()> {
+            self.writes.lock().unwrap().push((mime_type.to_string(), data));
+            Ok(())
+        }
+
+        async fn subscribe_changes(&self) -> ClipboardResult<ClipboardChangeReceiver> {
+            let changes = VecDeque::from([
+                ClipboardChange::new(vec!["image/png".to_string(), "text/html".to_string()]).with_hash("abc"),
+                ClipboardChange::new(Vec::new()),
+            ]);
+            Ok(ClipboardChangeReceiver::new(Box::new(QueuedChanges(changes))))
+        }
+
+        async fn get_file_list(&self) -> ClipboardResult<Vec<FileInfo>> {
+            Ok(vec![FileInfo::file("secret.txt", 6)])
+        }
+
+        async fn read_file_chunk(&self, _index: u32, _offset: u64, _size: u32) -> ClipboardResult<Vec<u8>> {
+            Ok(b"secret".to_vec())
+        }
+
+        async fn write_file(&self, _path: &str, _data: Vec<u8>) -> ClipboardResult<()> {
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn test_reads_return_placeholder() {
+        let sink = ReadOnlySink::new(RecordingSink::default());
+
+        for mime in ["text/plain", "text/plain;charset=utf-8", "UTF8_STRING"] {
+            assert_eq!(
+                block_on(sink.read_clipboard(mime)).unwrap(),
+                DEFAULT_EXPORT_PLACEHOLDER.as_bytes()
+            );
+        }
+        assert!(matches!(
+            block_on(sink.read_clipboard("image/png")),
+            Err(ClipboardError::UnsupportedFormat(_))
+        ));
+        assert!(block_on(sink.get_file_list()).unwrap().is_empty());
+        assert!(block_on(sink.read_file_chunk(0, 0, 6)).is_err());
+    }
+
+    #[test]
+    fn test_writes_pass_through() {
+        let sink = ReadOnlySink::new(RecordingSink::default());
+
+        block_on(sink.write_clipboard("text/plain", b"pasted".to_vec())).unwrap();
+        assert_eq!(
+            *sink.inner().writes.lock().unwrap(),
+            vec![("text/plain".to_string(), b"pasted".to_vec())]
+        );
+    }
+
+    #[test]
+    fn test_changes_announced_as_text() {
+        let sink = ReadOnlySink::new(RecordingSink::default()).with_placeholder("blocked");
+        assert_eq!(sink.placeholder(), "blocked");
+
+        let mut changes = block_on(sink.subscribe_changes()).unwrap();
+        let change = changes.try_recv().unwrap();
+        assert_eq!(change.mime_types, vec![PLACEHOLDER_MIME]);
+        assert_eq!(change.content_hash.as_deref(), Some("abc"));
+
+        // A cleared clipboard stays empty
+        assert!(changes.try_recv().unwrap().mime_types.is_empty());
+        assert!(changes.try_recv().is_none());
+        assert_eq!(block_on(sink.read_clipboard(PLACEHOLDER_MIME)).unwrap(), b"blocked");
+    }
+}
@@ -0,0 +1,449 @@
+//! Per-session registry for registered clipboard format IDs.
+//!
+//! Windows assigns IDs to registered formats ("HTML Format", "PNG",
+//! "FileGroupDescriptorW", ...) at runtime, so the same name can carry a
+//! different ID on every peer and in every session. Only the standard formats
+//! below `0xC000` have fixed IDs.
+//!
+//! [`FormatRegistry`] tracks both directions of a CLIPRDR session:
+//!
+//! - **Remote**: the ID ↔ name pairs received in the peer's Format List, used
+//!   to resolve incoming Format Data Requests and to pick the ID to request.
+//! - **Local**: the IDs this side assigns to registered formats when it
+//!   announces its own Format List.
+//!
+//! Both sides are resolved to MIME types by name, never by numeric ID.
+
+use crate::formats::{
+    mime_to_rdp_formats, rdp_format_to_mime, ClipboardFormat, CF_AVIF, CF_CSV, CF_FILECONTENTS,
+    CF_FILEGROUPDESCRIPTORW, CF_FILE_CHECKSUMS, CF_GIF, CF_HTML, CF_ICO, CF_JPEG, CF_PNG, CF_PREFERRED_DROP_EFFECT,
+    CF_RTF, CF_SVG, CF_URL, CF_URLW, CF_WEBP, CF_WEB_CUSTOM_DATA, CF_XML_SPREADSHEET,
+};
+use std::collections::HashMap;
+
+/// First ID in the registered (non-standard) clipboard format range
+pub const REGISTERED_FORMAT_BASE: u32 = 0xC000;
+
+/// Last ID in the registered clipboard format range
+pub const REGISTERED_FORMAT_MAX: u32 = 0xFFFF;
+
+/// Well-known registered format names and their MIME types
+const REGISTERED_FORMATS: &[(&str, Option<&str>)] = &[
+    ("HTML Format", Some("text/html")),
+    ("Rich Text Format", Some("text/rtf")),
+    ("PNG", Some("image/png")),
+    ("JFIF", Some("image/jpeg")),
+    ("GIF", Some("image/gif")),
+    ("image/webp", Some("image/webp")),
+    ("WebP", Some("image/webp")),
+    ("image/avif", Some("image/avif")),
+    ("image/svg+xml", Some("image/svg+xml")),
+    ("image/x-icon", Some("image/x-icon")),
+    ("UniformResourceLocatorW", Some("text/x-moz-url")),
+    ("UniformResourceLocator", Some("text/x-moz-url")),
+    (
+        "Chromium Web Custom MIME Data Format",
+        Some("chromium/x-web-custom-data"),
+    ),
+    ("XML Spreadsheet", Some("text/csv")),
+    ("Csv", Some("text/csv")),
+    ("FileGroupDescriptorW", Some("text/uri-list")),
+    ("Preferred DropEffect", Some("x-special/gnome-copied-files")),
+    // FileContents is a data retrieval mechanism, not a format
+    ("FileContents", None),
+    // Verification data for a file copy, only meaningful to this library
+    ("LamcoFileChecksums", None),
+];
+
+/// Returns true if the ID is in the registered format range
+pub fn is_registered_format_id(id: u32) -> bool {
+    (REGISTERED_FORMAT_BASE..=REGISTERED_FORMAT_MAX).contains(&id)
+}
+
+/// Map a registered format name to its preferred MIME type
+///
+/// # Example
+///
+/// ```
+/// use lamco_clipboard_core::registry::registered_name_to_mime;
+///
+/// assert_eq!(registered_name_to_mime("HTML Format"), Some("text/html"));
+/// assert_eq!(registered_name_to_mime("FileContents"), None);
+/// ```
+pub fn registered_name_to_mime(name: &str) -> Option<&'static str> {
+    REGISTERED_FORMATS
+        .iter()
+        .find(|(n, _)| *n == name)
+        .and_then(|(_, mime)| *mime)
+}
+
+/// Full well-known name for a name cut short by a fixed-size name field.
+///
+/// Short format names hold at most 16 UTF-16 units or 32 ASCII bytes, so
+/// longer names arrive truncated. Only names long enough to have been cut
+/// that are a prefix of exactly one well-known name are expanded.
+pub(crate) fn expand_truncated_name(name: &str) -> Option<&'static str> {
+    if name.len() < 15 {
+        return None;
+    }
+    let mut matches = REGISTERED_FORMATS
+        .iter()
+        .map(|(n, _)| *n)
+        .filter(|n| n.len() > name.len() && n.starts_with(name));
+    match (matches.next(), matches.next()) {
+        (Some(full), None) => Some(full),
+        _ => None,
+    }
+}
+
+/// Per-session mapping between registered format names, IDs and MIME types
+#[derive(Debug, Clone)]
+pub struct FormatRegistry {
+    /// Remote format IDs in the order they were announced
+    remote_ids: Vec<u32>,
+
+    /// Remote ID -> name, from the peer's last Format List
+    remote_by_id: HashMap<u32, String>,
+
+    /// Remote name -> ID
+    remote_by_name: HashMap<String, u32>,
+
+    /// Local name -> ID, assigned when announcing
+    local_by_name: HashMap<String, u32>,
+
+    /// Local ID -> name
+    local_by_id: HashMap<u32, String>,
+
+    /// Next candidate ID for newly registered local formats
+    next_local_id: u32,
+}
+
+impl Default for FormatRegistry {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl FormatRegistry {
+    /// Create a registry for a new session.
+    ///
+    /// Well-known local formats keep the IDs of the `CF_*` constants in
+    /// [`formats`](crate::formats) so announcements stay stable; they are only
+    /// meaningful for formats this side announces.
+    pub fn new() -> Self {
+        let mut registry = Self {
+            remote_ids: Vec::new(),
+            remote_by_id: HashMap::new(),
+            remote_by_name: HashMap::new(),
+            local_by_name: HashMap::new(),
+            local_by_id: HashMap::new(),
+            next_local_id: 0xD100,
+        };
+
+        for (name, id) in [
+            ("HTML Format", CF_HTML),
+            ("Rich Text Format", CF_RTF),
+            ("PNG", CF_PNG),
+            ("JFIF", CF_JPEG),
+            ("GIF", CF_GIF),
+            ("image/webp", CF_WEBP),
+            ("image/avif", CF_AVIF),
+            ("image/svg+xml", CF_SVG),
+            ("image/x-icon", CF_ICO),
+            ("UniformResourceLocatorW", CF_URLW),
+            ("UniformResourceLocator", CF_URL),
+            ("Chromium Web Custom MIME Data Format", CF_WEB_CUSTOM_DATA),
+            ("XML Spreadsheet", CF_XML_SPREADSHEET),
+            ("Csv", CF_CSV),
+            ("FileGroupDescriptorW", CF_FILEGROUPDESCRIPTORW),
+            ("Preferred DropEffect", CF_PREFERRED_DROP_EFFECT),
+            ("FileContents", CF_FILECONTENTS),
+            ("LamcoFileChecksums", CF_FILE_CHECKSUMS),
+        ] {
+            registry.local_by_name.insert(name.to_string(), id);
+            registry.local_by_id.insert(id, name.to_string());
+        }
+
+        registry
+    }
+
+    // =========================================================================
+    // Remote formats
+    // =========================================================================
+
+    /// Record the peer's Format List, replacing the previous one.
+    ///
+    /// Formats without a name are standard formats and need no mapping.
+    pub fn update_remote(&mut self, formats: &[ClipboardFormat]) {
+        self.clear_remote();
+
+        for format in formats {
+            self.remote_ids.push(format.id);
+            if let Some(name) = format.name.as_deref().filter(|n| !n.is_empty()) {
+                if !is_registered_format_id(format.id) {
+                    tracing::debug!("Peer named non-registered format {} as {:?}", format.id, name);
+                }
+                self.remote_by_id.insert(format.id, name.to_string());
+                self.remote_by_name.insert(name.to_string(), format.id);
+            }
+        }
+    }
+
+    /// Forget the peer's Format List
+    pub fn clear_remote(&mut self) {
+        self.remote_ids.clear();
+        self.remote_by_id.clear();
+        self.remote_by_name.clear();
+    }
+
+    /// Whether the peer's Format List contains an ID
+    pub fn remote_has(&self, id: u32) -> bool {
+        self.remote_ids.contains(&id)
+    }
+
+    /// Name the peer assigned to a format ID
+    pub fn remote_name(&self, id: u32) -> Option<&str> {
+        self.remote_by_id.get(&id).map(String::as_str)
+    }
+
+    /// ID the peer assigned to a registered format name
+    pub fn remote_id(&self, name: &str) -> Option<u32> {
+        self.remote_by_name.get(name).copied()
+    }
+
+    /// Resolve a format ID from the peer's Format List to a MIME type
+    pub fn remote_format_to_mime(&self, id: u32) -> Option<&'static str> {
+        match self.remote_name(id) {
+            Some(name) => registered_name_to_mime(name),
+            None => rdp_format_to_mime(id),
+        }
+    }
+
+    /// Find the peer format ID to request for a MIME type.
+    ///
+    /// Only formats in the peer's Format List are considered. Registered
+    /// formats are preferred over standard ones (e.g. the peer's "PNG" over
+    /// CF_DIB for `image/png`), including alternative names for the same
+    /// MIME type such as "WebP".
+    pub fn remote_id_for_mime(&self, mime_type: &str) -> Option<u32> {
+        let candidates = mime_to_rdp_formats(&[mime_type]);
+
+        candidates
+            .iter()
+            .find_map(|format| format.name.as_deref().and_then(|name| self.remote_id(name)))
+            .or_else(|| {
+                self.remote_ids.iter().copied().find(|&id| {
+                    self.remote_name(id)
+                        .is_some_and(|name| registered_name_to_mime(name) == Some(mime_type))
+                })
+            })
+            .or_else(|| {
+                candidates
+                    .iter()
+                    .filter(|format| format.name.is_none())
+                    .map(|format| format.id)
+                    .find(|id| !is_registered_format_id(*id) && self.remote_has(*id))
+            })
+    }
+
+    // =========================================================================
+    // Local formats
+    // =========================================================================
+
+    /// Get or assign the local ID for a registered format name
+    ///
+    /// Returns `None` once every registered format ID is in use; reusing one
+    /// would make the peer's requests for it ambiguous.
+    pub fn register_local(&mut self, name: &str) -> Option<u32> {
+        if let Some(&id) = self.local_by_name.get(name) {
+            return Some(id);
+        }
+
+        let Some(id) = (self.next_local_id..=REGISTERED_FORMAT_MAX).find(|id| !self.local_by_id.contains_key(id))
+        else {
+            tracing::warn!("No registered format IDs left for '{}'", name);
+            return None;
+        };
+        self.next_local_id = id.saturating_add(1);
+
+        self.local_by_name.insert(name.to_string(), id);
+        self.local_by_id.insert(id, name.to_string());
+        Some(id)
+    }
+
+    /// Local ID assigned to a registered format name
+    pub fn local_id(&self, name: &str) -> Option<u32> {
+        self.local_by_name.get(name).copied()
+    }
+
+    /// Registered format name for a local ID
+    pub fn local_name(&self, id: u32) -> Option<&str> {
+        self.local_by_id.get(&id).map(String::as_str)
+    }
+
+    /// Resolve a format ID requested by the peer (from our Format List) to a MIME type
+    pub fn local_format_to_mime(&self, id: u32) -> Option<&'static str> {
+        match self.local_name(id) {
+            Some(name) => registered_name_to_mime(name),
+            None => rdp_format_to_mime(id),
+        }
+    }
+
+    /// Build the Format List to announce for local MIME types.
+    ///
+    /// Like [`mime_to_rdp_formats`], but every named format carries the ID
+    /// assigned by this registry instead of a placeholder. Named formats left
+    /// without an ID are not announced.
+    pub fn announce(&mut self, mime_types: &[&str]) -> Vec<ClipboardFormat> {
+        mime_to_rdp_formats(mime_types)
+            .into_iter()
+            .filter_map(|format| match format.name {
+                Some(name) => Some(ClipboardFormat::with_name(self.register_local(&name)?, name)),
+                None => Some(format),
+            })
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::formats::{CF_DIB, CF_UNICODETEXT};
+
+    #[test]
+    fn test_remote_ids_resolved_by_name() {
+        let mut registry = FormatRegistry::new();
+        registry.update_remote(&[
+            ClipboardFormat::new(CF_UNICODETEXT),
+            ClipboardFormat::with_name(0xC0A3, "HTML Format"),
+            ClipboardFormat::with_name(0xC1F0, "PNG"),
+        ]);
+
+        assert_eq!(registry.remote_format_to_mime(0xC0A3), Some("text/html"));
+        assert_eq!(registry.remote_format_to_mime(0xC1F0), Some("image/png"));
+        assert_eq!(
+            registry.remote_format_to_mime(CF_UNICODETEXT),
+            Some("text/plain;charset=utf-8")
+        );
+
+        // Hardcoded pseudo-IDs mean nothing for the peer
+        assert_eq!(registry.remote_format_to_mime(CF_HTML), None);
+    }
+
+    #[test]
+    fn test_remote_list_replaced() {
+        let mut registry = FormatRegistry::new();
+        registry.update_remote(&[ClipboardFormat::with_name(0xC0A3, "HTML Format")]);
+        registry.update_remote(&[ClipboardFormat::with_name(0xC0B0, "HTML Format")]);
+
+        assert_eq!(registry.remote_id("HTML Format"), Some(0xC0B0));
+        assert_eq!(registry.remote_name(0xC0A3), None);
+
+        registry.clear_remote();
+        assert_eq!(registry.remote_id("HTML Format"), None);
+    }
+
+    #[test]
+    fn test_remote_id_for_mime() {
+        let mut registry = FormatRegistry::new();
+        registry.update_remote(&[ClipboardFormat::new(CF_DIB), ClipboardFormat::with_name(0xC1F0, "PNG")]);
+
+        assert_eq!(registry.remote_id_for_mime("image/png"), Some(0xC1F0));
+        assert_eq!(registry.remote_id_for_mime("image/bmp"), Some(CF_DIB));
+        assert_eq!(registry.remote_id_for_mime("text/html"), None);
+        assert_eq!(registry.remote_id_for_mime("text/plain"), None);
+
+        registry.update_remote(&[ClipboardFormat::new(CF_UNICODETEXT)]);
+        assert_eq!(registry.remote_id_for_mime("text/plain"), Some(CF_UNICODETEXT));
+
+        // Excel offers both; the workbook keeps cell structure
+        registry.update_remote(&[
+            ClipboardFormat::with_name(0xC2A0, "Csv"),
+            ClipboardFormat::with_name(0xC2A1, "XML Spreadsheet"),
+        ]);
+        assert_eq!(registry.remote_id_for_mime("text/csv"), Some(0xC2A1));
+
+        // Browsers register WebP under either name
+        registry.update_remote(&[ClipboardFormat::with_name(0xC2B0, "WebP")]);
+        assert_eq!(registry.remote_id_for_mime("image/webp"), Some(0xC2B0));
+    }
+
+    #[test]
+    fn test_preferred_drop_effect_both_directions() {
+        // Explorer cut: the file list plus its drop effect under session IDs
+        let mut registry = FormatRegistry::new();
+        registry.update_remote(&[
+            ClipboardFormat::with_name(0xC0A0, "FileGroupDescriptorW"),
+            ClipboardFormat::with_name(0xC0A1, "FileContents"),
+            ClipboardFormat::with_name(0xC0A2, "Preferred DropEffect"),
+        ]);
+        assert_eq!(
+            registry.remote_format_to_mime(0xC0A2),
+            Some("x-special/gnome-copied-files")
+        );
+        assert_eq!(
+            registry.remote_id_for_mime("application/x-kde-cutselection"),
+            Some(0xC0A2)
+        );
+        assert_eq!(registry.remote_id_for_mime("text/uri-list"), Some(0xC0A0));
+
+        // Nautilus cut: announced with our fixed local ID
+        let announced = registry.announce(&["x-special/gnome-copied-files"]);
+        assert!(announced.contains(&ClipboardFormat::with_name(
+            CF_PREFERRED_DROP_EFFECT,
+            "Preferred DropEffect"
+        )));
+        assert_eq!(
+            registry.local_format_to_mime(CF_PREFERRED_DROP_EFFECT),
+            Some("x-special/gnome-copied-files")
+        );
+    }
+
+    #[test]
+    fn test_announce_assigns_local_ids() {
+        let mut registry = FormatRegistry::new();
+        let formats = registry.announce(&["text/html", "text/uri-list"]);
+
+        assert!(formats.contains(&ClipboardFormat::with_name(CF_HTML, "HTML Format")));
+        assert!(formats.contains(&ClipboardFormat::with_name(
+            CF_FILEGROUPDESCRIPTORW,
+            "FileGroupDescriptorW"
+        )));
+        assert!(formats.iter().all(|f| f.id != 0));
+
+        assert_eq!(registry.local_format_to_mime(CF_HTML), Some("text/html"));
+        assert_eq!(registry.local_format_to_mime(CF_FILECONTENTS), None);
+    }
+
+    #[test]
+    fn test_register_local_allocates_unique_ids() {
+        let mut registry = FormatRegistry::new();
+        let a = registry.register_local("Custom A");
+        let b = registry.register_local("Custom B");
+
+        assert_ne!(a, b);
+        assert_eq!(registry.register_local("Custom A"), a);
+        assert_eq!(registry.local_name(b.unwrap()), Some("Custom B"));
+        assert!(is_registered_format_id(a.unwrap()));
+    }
+
+    #[test]
+    fn test_register_local_exhausted() {
+        let mut registry = FormatRegistry::new();
+        let mut last = None;
+        for n in 0.. {
+            match registry.register_local(&format!("Custom {}", n)) {
+                Some(id) => last = Some((id, n)),
+                None => break,
+            }
+        }
+        let (id, n) = last.unwrap();
+        assert_eq!(id, REGISTERED_FORMAT_MAX);
+
+        // Existing names keep their IDs, new ones get none
+        assert_eq!(registry.register_local(&format!("Custom {}", n)), Some(id));
+        assert_eq!(registry.register_local("One Too Many"), None);
+        assert_eq!(registry.local_name(id), Some(format!("Custom {}", n).as_str()));
+        assert_eq!(registry.local_id("One Too Many"), None);
+    }
+}
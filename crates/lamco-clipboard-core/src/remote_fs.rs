@@ -0,0 +1,604 @@
+//! Remote clipboard files as a lazily read file tree.
+//!
+//! Windows pastes files with delayed rendering: Explorer asks for the
+//! contents of a file only when it copies that file. [`RemoteFileTree`] does
+//! the same for Linux file managers. It lays a FileGroupDescriptorW list out
+//! as a directory tree with inode numbers and turns each read into a File
+//! Contents Request for just the bytes asked for, so nothing is fetched
+//! before a file is opened and a file manager pasting a single file out of a
+//! large copy only transfers that file.
+//!
+//! With the `fuse` feature, [`RemoteFs`] mounts the tree read-only through
+//! FUSE. Its reads block on a [`FileContentsFetcher`], which the host
+//! implements by sending the request over CLIPRDR and waiting for the File
+//! Contents Response. Keep the copy's clipboard data locked while the mount
+//! exists so the requests stay valid after the peer's clipboard changes.
+//!
+//! # Example
+//!
+//! ```
+//! use lamco_clipboard_core::file_transfer::FileContentsKind;
+//! use lamco_clipboard_core::remote_fs::{RemoteFileTree, ROOT_INODE};
+//! use lamco_clipboard_core::FileDescriptor;
+//!
+//! # fn descriptor(name: &str, size: u64) -> FileDescriptor {
+//! #     FileDescriptor {
+//! #         flags: lamco_clipboard_core::FileDescriptorFlags::from_raw(0x40),
+//! #         attributes: 0x80,
+//! #         creation_time: None,
+//! #         access_time: None,
+//! #         write_time: None,
+//! #         size: Some(size),
+//! #         name: name.to_string(),
+//! #     }
+//! # }
+//! # let descriptors = vec![descriptor("photos\\beach.jpg", 3_000_000)];
+//! // descriptors = FileDescriptor::parse_list(&file_group_descriptor)?
+//! let mut tree = RemoteFileTree::new(&descriptors).with_clip_data_id(7);
+//!
+//! let photos = tree.lookup(ROOT_INODE, "photos").unwrap().inode;
+//! let beach = tree.lookup(photos, "beach.jpg").unwrap().inode;
+//!
+//! // A 128 KiB read of the file becomes one range request
+//! let request = tree.read_request(beach, 131_072, 131_072)?.unwrap();
+//! assert_eq!(request.kind, FileContentsKind::Range { position: 131_072, size: 131_072 });
+//! assert_eq!(request.clip_data_id, Some(7));
+//! # Ok::<(), lamco_clipboard_core::ClipboardError>(())
+//! ```
+
+use crate::file_transfer::{local_path, FileContentsKind, FileContentsRequest, FILE_ATTRIBUTE_DIRECTORY};
+use crate::formats::FileDescriptor;
+use crate::{ClipboardError, ClipboardResult};
+use std::time::{Duration, SystemTime};
+
+/// Inode of the root directory
+pub const ROOT_INODE: u64 = 1;
+
+/// FILETIME of the Unix epoch, in 100 ns intervals since 1601-01-01
+const UNIX_EPOCH_FILETIME: u64 = 116_444_736_000_000_000;
+
+/// Whether a tree entry is a file or a directory
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RemoteNodeKind {
+    /// A directory, listed or implied by the paths below it
+    Directory,
+    /// A file read through File Contents Requests
+    File,
+}
+
+/// A file or directory of the remote file list
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RemoteNode {
+    /// Inode number, [`ROOT_INODE`] for the root
+    pub inode: u64,
+
+    /// Inode of the containing directory; the root is its own parent
+    pub parent: u64,
+
+    /// Sanitized name within the parent
+    pub name: String,
+
+    /// File or directory
+    pub kind: RemoteNodeKind,
+
+    /// Index in the FileGroupDescriptorW; `None` for the root and for
+    /// directories only implied by the paths of their contents
+    pub index: Option<u32>,
+
+    /// Size in bytes; `None` for files whose descriptor carries none until
+    /// it is set from a size request
+    pub size: Option<u64>,
+
+    /// Last write time as a Windows FILETIME
+    pub write_time: Option<u64>,
+}
+
+impl RemoteNode {
+    /// Last write time, if the descriptor carries one
+    pub fn modified(&self) -> Option<SystemTime> {
+        let since_epoch = self.write_time?.checked_sub(UNIX_EPOCH_FILETIME)?;
+        Some(SystemTime::UNIX_EPOCH + Duration::from_nanos(since_epoch.saturating_mul(100)))
+    }
+}
+
+/// Directory tree of a remote file list, reading files on demand.
+///
+/// Paths use the same sanitized components as
+/// [`FileTransfer`](crate::file_transfer::FileTransfer), so a lazily read
+/// file has the name an eager paste would have written.
+#[derive(Debug, Clone)]
+pub struct RemoteFileTree {
+    /// Nodes by inode - 1
+    nodes: Vec<RemoteNode>,
+    clip_data_id: Option<u32>,
+    next_stream_id: u32,
+}
+
+impl RemoteFileTree {
+    /// Build the tree for the files of a FileGroupDescriptorW
+    ///
+    /// Directories missing from the list are created for the paths below
+    /// them. When a name appears twice, the first entry wins.
+    pub fn new(descriptors: &[FileDescriptor]) -> Self {
+        let mut tree = Self {
+            nodes: vec![RemoteNode {
+                inode: ROOT_INODE,
+                parent: ROOT_INODE,
+                name: String::new(),
+                kind: RemoteNodeKind::Directory,
+                index: None,
+                size: Some(0),
+                write_time: None,
+            }],
+            clip_data_id: None,
+            next_stream_id: 1,
+        };
+
+        for (index, descriptor) in descriptors.iter().enumerate() {
+            let path = local_path(&descriptor.name);
+            let mut folders: Vec<_> = path.split('/').collect();
+            let Some(name) = folders.pop().filter(|name| !name.is_empty()) else {
+                continue;
+            };
+
+            let mut parent = ROOT_INODE;
+            for folder in folders {
+                parent = tree.directory(parent, folder);
+            }
+
+            let is_directory = descriptor.attributes & FILE_ATTRIBUTE_DIRECTORY != 0;
+            if is_directory {
+                let inode = tree.directory(parent, name);
+                let node = tree.node_mut(inode);
+                if node.kind == RemoteNodeKind::Directory && node.index.is_none() {
+                    node.index = Some(index as u32);
+                    node.write_time = descriptor.write_time;
+                }
+                continue;
+            }
+            if tree.lookup(parent, name).is_some() {
+                tracing::debug!("{} listed twice in the file list, keeping the first", path);
+                continue;
+            }
+            tree.push(RemoteNode {
+                inode: 0,
+                parent,
+                name: name.to_string(),
+                kind: RemoteNodeKind::File,
+                index: Some(index as u32),
+                size: descriptor.size,
+                write_time: descriptor.write_time,
+            });
+        }
+        tree
+    }
+
+    /// Refer requests to a clipboard data lock held on the peer
+    pub fn with_clip_data_id(mut self, clip_data_id: u32) -> Self {
+        self.clip_data_id = Some(clip_data_id);
+        self
+    }
+
+    /// Clipboard data lock the requests refer to
+    pub fn clip_data_id(&self) -> Option<u32> {
+        self.clip_data_id
+    }
+
+    /// Node with `inode`
+    pub fn node(&self, inode: u64) -> Option<&RemoteNode> {
+        let slot = usize::try_from(inode.checked_sub(1)?).ok()?;
+        self.nodes.get(slot)
+    }
+
+    /// Entry `name` in the directory `parent`
+    pub fn lookup(&self, parent: u64, name: &str) -> Option<&RemoteNode> {
+        self.nodes
+            .iter()
+            .find(|node| node.parent == parent && node.inode != ROOT_INODE && node.name == name)
+    }
+
+    /// Entries of the directory `inode`, in file list order
+    pub fn children(&self, inode: u64) -> impl Iterator<Item = &RemoteNode> + '_ {
+        self.nodes
+            .iter()
+            .filter(move |node| node.parent == inode && node.inode != ROOT_INODE)
+    }
+
+    /// Number of files and directories, not counting the root
+    pub fn len(&self) -> usize {
+        self.nodes.len() - 1
+    }
+
+    /// Whether the file list was empty
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Size request for a file whose descriptor carries no size
+    ///
+    /// Returns `None` once the size is known. Answer with
+    /// [`set_size()`](Self::set_size).
+    pub fn size_request(&mut self, inode: u64) -> ClipboardResult<Option<FileContentsRequest>> {
+        let node = self.file(inode)?;
+        if node.size.is_some() {
+            return Ok(None);
+        }
+        let index = node.index.unwrap_or(0);
+        Ok(Some(self.request(index, FileContentsKind::Size)))
+    }
+
+    /// Record the size reported for a file
+    pub fn set_size(&mut self, inode: u64, size: u64) -> ClipboardResult<()> {
+        self.file(inode)?;
+        self.node_mut(inode).size = Some(size);
+        Ok(())
+    }
+
+    /// Range request for `size` bytes of a file from `offset`
+    ///
+    /// The range is clamped to the file size; reads at or past the end
+    /// return `None`. Fails if the size is not known yet, see
+    /// [`size_request()`](Self::size_request).
+    pub fn read_request(&mut self, inode: u64, offset: u64, size: u32) -> ClipboardResult<Option<FileContentsRequest>> {
+        let node = self.file(inode)?;
+        let Some(file_size) = node.size else {
+            return Err(ClipboardError::InvalidState(format!(
+                "size of {} is not known yet",
+                node.name
+            )));
+        };
+        if offset >= file_size || size == 0 {
+            return Ok(None);
+        }
+        let len = (file_size - offset).min(u64::from(size)) as u32;
+        let index = node.index.unwrap_or(0);
+        let kind = FileContentsKind::Range {
+            position: offset,
+            size: len,
+        };
+        Ok(Some(self.request(index, kind)))
+    }
+
+    fn file(&self, inode: u64) -> ClipboardResult<&RemoteNode> {
+        let node = self
+            .node(inode)
+            .ok_or_else(|| ClipboardError::FileNotFound(format!("inode {}", inode)))?;
+        if node.kind != RemoteNodeKind::File {
+            return Err(ClipboardError::InvalidState(format!("{} is a directory", node.name)));
+        }
+        Ok(node)
+    }
+
+    fn request(&mut self, file_index: u32, kind: FileContentsKind) -> FileContentsRequest {
+        let request = FileContentsRequest {
+            stream_id: self.next_stream_id,
+            file_index,
+            kind,
+            clip_data_id: self.clip_data_id,
+        };
+        self.next_stream_id = self.next_stream_id.wrapping_add(1).max(1);
+        request
+    }
+
+    /// Inode of the directory `name` in `parent`, created if missing
+    fn directory(&mut self, parent: u64, name: &str) -> u64 {
+        if let Some(node) = self.lookup(parent, name) {
+            return node.inode;
+        }
+        self.push(RemoteNode {
+            inode: 0,
+            parent,
+            name: name.to_string(),
+            kind: RemoteNodeKind::Directory,
+            index: None,
+            size: Some(0),
+            write_time: None,
+        })
+    }
+
+    fn push(&mut self, mut node: RemoteNode) -> u64 {
+        node.inode = self.nodes.len() as u64 + 1;
+        let inode = node.inode;
+        self.nodes.push(node);
+        inode
+    }
+
+    fn node_mut(&mut self, inode: u64) -> &mut RemoteNode {
+        &mut self.nodes[(inode - 1) as usize]
+    }
+}
+
+/// Sends File Contents Requests for [`RemoteFs`] and waits for the answers
+///
+/// Called from the FUSE session thread; block until the peer's File
+/// Contents Response arrives and return its data, or an error for a failed
+/// response or a timeout.
+#[cfg(feature = "fuse")]
+pub trait FileContentsFetcher: Send + 'static {
+    /// Send `request` to the peer and return the response data
+    fn fetch(&mut self, request: FileContentsRequest) -> ClipboardResult<Vec<u8>>;
+}
+
+#[cfg(feature = "fuse")]
+impl<F> FileContentsFetcher for F
+where
+    F: FnMut(FileContentsRequest) -> ClipboardResult<Vec<u8>> + Send + 'static,
+{
+    fn fetch(&mut self, request: FileContentsRequest) -> ClipboardResult<Vec<u8>> {
+        self(request)
+    }
+}
+
+/// How long the kernel may cache entries and attributes; the file list
+/// does not change while mounted
+#[cfg(feature = "fuse")]
+const ATTR_TTL: Duration = Duration::from_secs(60);
+
+/// Read-only FUSE filesystem over a [`RemoteFileTree`]
+///
+/// ```no_run
+/// use lamco_clipboard_core::remote_fs::{RemoteFileTree, RemoteFs};
+///
+/// # let descriptors = Vec::new();
+/// # fn send_and_wait(_: lamco_clipboard_core::file_transfer::FileContentsRequest)
+/// #     -> lamco_clipboard_core::ClipboardResult<Vec<u8>> { Ok(Vec::new()) }
+/// let tree = RemoteFileTree::new(&descriptors).with_clip_data_id(7);
+/// let session = RemoteFs::new(tree, send_and_wait).mount("/run/user/1000/clipboard")?;
+/// // The files are readable until `session` is dropped, which unmounts
+/// # Ok::<(), lamco_clipboard_core::ClipboardError>(())
+/// ```
+#[cfg(feature = "fuse")]
+#[derive(Debug)]
+pub struct RemoteFs<F> {
+    tree: RemoteFileTree,
+    fetcher: F,
+}
+
+#[cfg(feature = "fuse")]
+impl<F: FileContentsFetcher> RemoteFs<F> {
+    /// Serve `tree`, fetching file contents through `fetcher`
+    pub fn new(tree: RemoteFileTree, fetcher: F) -> Self {
+        Self { tree, fetcher }
+    }
+
+    /// Mount read-only at `mountpoint` on a background thread
+    ///
+    /// Dropping the returned session unmounts.
+    pub fn mount(self, mountpoint: impl AsRef<std::path::Path>) -> ClipboardResult<fuser::BackgroundSession> {
+        let options = [
+            fuser::MountOption::RO,
+            fuser::MountOption::NoExec,
+            fuser::MountOption::FSName("lamco-clipboard".to_string()),
+        ];
+        Ok(fuser::spawn_mount2(self, mountpoint, &options)?)
+    }
+
+    /// Attributes of `inode`, asking the peer for the size if needed
+    fn attr(&mut self, inode: u64, request: &fuser::Request<'_>) -> ClipboardResult<fuser::FileAttr> {
+        if let Some(size_request) = self.tree.size_request(inode)? {
+            let data = self.fetcher.fetch(size_request)?;
+            let size = data
+                .get(..8)
+                .and_then(|bytes| bytes.try_into().ok())
+                .map(u64::from_le_bytes)
+                .ok_or_else(|| ClipboardError::InvalidState(format!("size response of {} bytes", data.len())))?;
+            self.tree.set_size(inode, size)?;
+        }
+
+        let node = self
+            .tree
+            .node(inode)
+            .ok_or_else(|| ClipboardError::FileNotFound(format!("inode {}", inode)))?;
+        let size = node.size.unwrap_or(0);
+        let modified = node.modified().unwrap_or(SystemTime::UNIX_EPOCH);
+        let (kind, perm, nlink) = match node.kind {
+            RemoteNodeKind::Directory => (fuser::FileType::Directory, 0o555, 2),
+            RemoteNodeKind::File => (fuser::FileType::RegularFile, 0o444, 1),
+        };
+        Ok(fuser::FileAttr {
+            ino: inode,
+            size,
+            blocks: size.div_ceil(512),
+            atime: modified,
+            mtime: modified,
+            ctime: modified,
+            crtime: modified,
+            kind,
+            perm,
+            nlink,
+            uid: request.uid(),
+            gid: request.gid(),
+            rdev: 0,
+            blksize: 512,
+            flags: 0,
+        })
+    }
+}
+
+/// errno for a failed FUSE operation
+#[cfg(feature = "fuse")]
+fn errno(error: &ClipboardError) -> libc::c_int {
+    match error {
+        ClipboardError::FileNotFound(_) => libc::ENOENT,
+        ClipboardError::TransferCancelled => libc::EINTR,
+        ClipboardError::TransferTimeout(_) => libc::ETIMEDOUT,
+        ClipboardError::PermissionDenied(_) => libc::EACCES,
+        ClipboardError::Io(e) => e.raw_os_error().unwrap_or(libc::EIO),
+        _ => libc::EIO,
+    }
+}
+
+#[cfg(feature = "fuse")]
+impl<F: FileContentsFetcher> fuser::Filesystem for RemoteFs<F> {
+    fn lookup(&mut self, req: &fuser::Request<'_>, parent: u64, name: &std::ffi::OsStr, reply: fuser::ReplyEntry) {
+        let Some(inode) = name
+            .to_str()
+            .and_then(|name| self.tree.lookup(parent, name))
+            .map(|node| node.inode)
+        else {
+            reply.error(libc::ENOENT);
+            return;
+        };
+        match self.attr(inode, req) {
+            Ok(attr) => reply.entry(&ATTR_TTL, &attr, 0),
+            Err(e) => reply.error(errno(&e)),
+        }
+    }
+
+    fn getattr(&mut self, req: &fuser::Request<'_>, ino: u64, _fh: Option<u64>, reply: fuser::ReplyAttr) {
+        match self.attr(ino, req) {
+            Ok(attr) => reply.attr(&ATTR_TTL, &attr),
+            Err(e) => reply.error(errno(&e)),
+        }
+    }
+
+    fn read(
+        &mut self,
+        req: &fuser::Request<'_>,
+        ino: u64,
+        _fh: u64,
+        offset: i64,
+        size: u32,
+        _flags: i32,
+        _lock_owner: Option<u64>,
+        reply: fuser::ReplyData,
+    ) {
+        let Ok(offset) = u64::try_from(offset) else {
+            reply.error(libc::EINVAL);
+            return;
+        };
+        let result = self
+            .attr(ino, req)
+            .and_then(|_| match self.tree.read_request(ino, offset, size)? {
+                Some(request) => self.fetcher.fetch(request),
+                None => Ok(Vec::new()),
+            });
+        match result {
+            Ok(data) => reply.data(&data),
+            Err(e) => {
+                tracing::warn!("Reading remote file {} at {} failed: {}", ino, offset, e);
+                reply.error(errno(&e));
+            }
+        }
+    }
+
+    fn readdir(
+        &mut self,
+        _req: &fuser::Request<'_>,
+        ino: u64,
+        _fh: u64,
+        offset: i64,
+        mut reply: fuser::ReplyDirectory,
+    ) {
+        let Some(node) = self.tree.node(ino) else {
+            reply.error(libc::ENOENT);
+            return;
+        };
+        if node.kind != RemoteNodeKind::Directory {
+            reply.error(libc::ENOTDIR);
+            return;
+        }
+
+        let entries = [
+            (ino, fuser::FileType::Directory, "."),
+            (node.parent, fuser::FileType::Directory, ".."),
+        ]
+        .into_iter()
+        .chain(self.tree.children(ino).map(|child| {
+            let kind = match child.kind {
+                RemoteNodeKind::Directory => fuser::FileType::Directory,
+                RemoteNodeKind::File => fuser::FileType::RegularFile,
+            };
+            (child.inode, kind, child.name.as_str())
+        }));
+        for (position, (inode, kind, name)) in entries.enumerate().skip(usize::try_from(offset).unwrap_or(0)) {
+            // The offset passed back is that of the next entry
+            if reply.add(inode, position as i64 + 1, kind, name) {
+                break;
+            }
+        }
+        reply.ok();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::formats::FileDescriptorFlags;
+
+    fn descriptor(name: &str, size: Option<u64>, attributes: u32) -> FileDescriptor {
+        FileDescriptor {
+            flags: FileDescriptorFlags::from_raw(0x40),
+            attributes,
+            creation_time: None,
+            access_time: None,
+            write_time: Some(UNIX_EPOCH_FILETIME + 10_000_000),
+            size,
+            name: name.to_string(),
+        }
+    }
+
+    #[test]
+    fn test_tree_layout() {
+        let tree = RemoteFileTree::new(&[
+            descriptor("project", None, 0x10),
+            descriptor("project\\src\\main.rs", Some(100), 0x80),
+            descriptor("project\\README.md", Some(20), 0x80),
+            descriptor("..\\notes.txt", Some(5), 0x80),
+            descriptor("notes.txt", Some(9), 0x80),
+        ]);
+        assert_eq!(tree.len(), 5);
+
+        let project = tree.lookup(ROOT_INODE, "project").unwrap();
+        assert_eq!((project.kind, project.index), (RemoteNodeKind::Directory, Some(0)));
+        let src = tree.lookup(project.inode, "src").unwrap();
+        assert_eq!((src.kind, src.index), (RemoteNodeKind::Directory, None));
+        let main = tree.lookup(src.inode, "main.rs").unwrap();
+        assert_eq!((main.index, main.size), (Some(1), Some(100)));
+        assert_eq!(main.modified(), Some(SystemTime::UNIX_EPOCH + Duration::from_secs(1)));
+
+        let names: Vec<_> = tree.children(project.inode).map(|node| node.name.as_str()).collect();
+        assert_eq!(names, ["src", "README.md"]);
+
+        // `..` cannot escape the root; the later duplicate is dropped
+        let notes = tree.lookup(ROOT_INODE, "notes.txt").unwrap();
+        assert_eq!((notes.index, notes.size), (Some(3), Some(5)));
+        assert_eq!(tree.node(notes.inode), Some(notes));
+        assert!(tree.node(0).is_none());
+    }
+
+    #[test]
+    fn test_reads_become_requests() {
+        let mut tree = RemoteFileTree::new(&[descriptor("a.bin", Some(1000), 0x80), descriptor("b.bin", None, 0x80)])
+            .with_clip_data_id(4);
+        let a = tree.lookup(ROOT_INODE, "a.bin").unwrap().inode;
+        let b = tree.lookup(ROOT_INODE, "b.bin").unwrap().inode;
+
+        let request = tree.read_request(a, 900, 4096).unwrap().unwrap();
+        assert_eq!(
+            request.kind,
+            FileContentsKind::Range {
+                position: 900,
+                size: 100
+            }
+        );
+        assert_eq!((request.file_index, request.clip_data_id), (0, Some(4)));
+        assert!(tree.read_request(a, 1000, 4096).unwrap().is_none());
+        assert_eq!(tree.size_request(a).unwrap(), None);
+
+        // Files without a size are asked for it first
+        assert!(tree.read_request(b, 0, 10).is_err());
+        let size = tree.size_request(b).unwrap().unwrap();
+        assert_eq!((size.kind, size.file_index), (FileContentsKind::Size, 1));
+        assert_ne!(size.stream_id, request.stream_id);
+        tree.set_size(b, 3).unwrap();
+        let request = tree.read_request(b, 0, 10).unwrap().unwrap();
+        assert_eq!(request.kind, FileContentsKind::Range { position: 0, size: 3 });
+
+        assert!(matches!(
+            tree.read_request(99, 0, 1),
+            Err(ClipboardError::FileNotFound(_))
+        ));
+        assert!(tree.read_request(ROOT_INODE, 0, 1).is_err());
+    }
+}
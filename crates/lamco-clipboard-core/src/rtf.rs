@@ -0,0 +1,818 @@
+//! Rich Text Format ↔ HTML conversion.
+//!
+//! WordPad, Outlook and Word put RTF on the clipboard, while most Linux
+//! editors only accept `text/html`. This module renders the character and
+//! paragraph formatting that both formats share:
+//!
+//! - Bold, italic, underline and strikethrough
+//! - Font size and foreground color
+//! - Paragraphs, line breaks and tabs
+//! - Hyperlinks (`HYPERLINK` fields ↔ `<a href>`)
+//!
+//! Everything else (tables, images, styles, fonts) is dropped and only the
+//! text is kept. HTML → RTF is best effort in the same way.
+//!
+//! # Example
+//!
+//! ```rust
+//! use lamco_clipboard_core::rtf::{html_to_rtf, rtf_to_html};
+//!
+//! let html = rtf_to_html(br"{\rtf1\ansi Hello {\b world}\par}").unwrap();
+//! assert_eq!(html, "<p>Hello <b>world</b></p>");
+//!
+//! let rtf = html_to_rtf("<p>Hello <b>world</b></p>");
+//! assert!(rtf.starts_with(br"{\rtf1"));
+//! ```
+
+use crate::formats::codepage_to_encoding;
+use crate::sanitize::is_safe_link;
+use crate::{ClipboardError, ClipboardResult};
+use std::fmt::Write;
+
+/// RTF destinations whose content is not document text
+const SKIP_DESTINATIONS: &[&str] = &[
+    "fonttbl",
+    "stylesheet",
+    "info",
+    "pict",
+    "header",
+    "footer",
+    "footnote",
+    "annotation",
+    "datafield",
+    "docvar",
+    "xe",
+    "tc",
+    "rxe",
+    "object",
+    "listtable",
+    "listoverridetable",
+];
+
+// =============================================================================
+// RTF → HTML
+// =============================================================================
+
+/// Character formatting of a text run
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+struct CharFormat {
+    bold: bool,
+    italic: bool,
+    underline: bool,
+    strike: bool,
+    /// Index into the color table (0 = automatic)
+    color: usize,
+    /// Font size in half-points (0 = default)
+    font_size: u32,
+}
+
+/// Where text in the current group goes
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Destination {
+    Text,
+    ColorTable,
+    FieldInstruction,
+    Skip,
+}
+
+/// State saved and restored by RTF groups
+#[derive(Debug, Clone)]
+struct GroupState {
+    format: CharFormat,
+    destination: Destination,
+    /// Number of fallback characters after `\uN`
+    unicode_skip: usize,
+}
+
+impl Default for GroupState {
+    fn default() -> Self {
+        Self {
+            format: CharFormat::default(),
+            destination: Destination::Text,
+            unicode_skip: 1,
+        }
+    }
+}
+
+/// Accumulates HTML output for the RTF parser
+#[derive(Default)]
+struct HtmlWriter {
+    html: String,
+    paragraph: String,
+    /// Formatting of the open run, if any
+    run: Option<CharFormat>,
+    colors: Vec<Option<(u8, u8, u8)>>,
+    /// Color being assembled from `\red \green \blue`
+    pending_color: (u8, u8, u8),
+    field_instruction: String,
+    /// Target of the hyperlink currently being rendered
+    link: Option<String>,
+    /// High surrogate from `\u`, waiting for the low one that follows
+    high_surrogate: Option<u16>,
+    /// Document codepage for 8-bit text
+    encoding: Option<&'static encoding_rs::Encoding>,
+    /// Undecoded 8-bit text, kept together so double-byte characters decode
+    pending_bytes: Vec<u8>,
+    pending_format: CharFormat,
+}
+
+impl HtmlWriter {
+    fn push_byte(&mut self, byte: u8, format: &CharFormat) {
+        self.high_surrogate = None;
+        if !self.pending_bytes.is_empty() && self.pending_format != *format {
+            self.flush_bytes();
+        }
+        self.pending_format = format.clone();
+        self.pending_bytes.push(byte);
+    }
+
+    fn flush_bytes(&mut self) {
+        if self.pending_bytes.is_empty() {
+            return;
+        }
+        let bytes = std::mem::take(&mut self.pending_bytes);
+        let format = std::mem::take(&mut self.pending_format);
+        let encoding = self.encoding.unwrap_or(encoding_rs::WINDOWS_1252);
+        let (text, _) = encoding.decode_without_bom_handling(&bytes);
+        for c in text.chars() {
+            self.push_char(c, &format);
+        }
+    }
+
+    fn push_char(&mut self, c: char, format: &CharFormat) {
+        self.high_surrogate = None;
+        self.flush_bytes();
+        if self.run.as_ref() != Some(format) {
+            self.close_run();
+            self.open_run(format);
+        }
+        match c {
+            '&' => self.paragraph.push_str("&amp;"),
+            '<' => self.paragraph.push_str("&lt;"),
+            '>' => self.paragraph.push_str("&gt;"),
+            '"' => self.paragraph.push_str("&quot;"),
+            '\t' => self.paragraph.push_str("&emsp;"),
+            '\u{a0}' => self.paragraph.push_str("&nbsp;"),
+            c => self.paragraph.push(c),
+        }
+    }
+
+    fn open_run(&mut self, format: &CharFormat) {
+        let mut style = String::new();
+        if let Some(Some((r, g, b))) = self.colors.get(format.color) {
+            let _ = write!(style, "color:#{:02x}{:02x}{:02x};", r, g, b);
+        }
+        if format.font_size > 0 {
+            let _ = write!(style, "font-size:{}pt;", format.font_size as f32 / 2.0);
+        }
+        if !style.is_empty() {
+            let _ = write!(self.paragraph, "<span style=\"{}\">", style.trim_end_matches(';'));
+        }
+        for (enabled, tag) in Self::tags(format) {
+            if enabled {
+                let _ = write!(self.paragraph, "<{}>", tag);
+            }
+        }
+        self.run = Some(format.clone());
+    }
+
+    fn close_run(&mut self) {
+        let Some(format) = self.run.take() else {
+            return;
+        };
+        for (enabled, tag) in Self::tags(&format).into_iter().rev() {
+            if enabled {
+                let _ = write!(self.paragraph, "</{}>", tag);
+            }
+        }
+        if self.colors.get(format.color).is_some_and(Option::is_some) || format.font_size > 0 {
+            self.paragraph.push_str("</span>");
+        }
+    }
+
+    fn tags(format: &CharFormat) -> [(bool, &'static str); 4] {
+        [
+            (format.bold, "b"),
+            (format.italic, "i"),
+            (format.underline, "u"),
+            (format.strike, "s"),
+        ]
+    }
+
+    fn line_break(&mut self) {
+        self.flush_bytes();
+        self.close_run();
+        self.paragraph.push_str("<br>");
+    }
+
+    fn end_paragraph(&mut self) {
+        self.flush_bytes();
+        self.close_run();
+        let _ = write!(self.html, "<p>{}</p>", self.paragraph);
+        self.paragraph.clear();
+    }
+
+    fn start_link(&mut self) {
+        let instruction = std::mem::take(&mut self.field_instruction);
+        let mut words = instruction.split_whitespace();
+        if words.next() != Some("HYPERLINK") {
+            return;
+        }
+        // Switches such as \l (anchor) precede or follow the quoted target
+        let Some(target) = words.find(|w| !w.starts_with('\\')) else {
+            return;
+        };
+        let target = target.trim_matches('"').to_string();
+        if !is_safe_link(&target) {
+            tracing::debug!("Dropping RTF hyperlink to {:?}", target);
+            return;
+        }
+
+        self.flush_bytes();
+        self.close_run();
+        let _ = write!(self.paragraph, "<a href=\"{}\">", escape_attribute(&target));
+        self.link = Some(target);
+    }
+
+    fn end_link(&mut self) {
+        self.flush_bytes();
+        if self.link.take().is_some() {
+            self.close_run();
+            self.paragraph.push_str("</a>");
+        }
+    }
+
+    fn finish(mut self) -> String {
+        self.flush_bytes();
+        self.end_link();
+        self.close_run();
+        if !self.paragraph.is_empty() {
+            self.end_paragraph();
+        }
+        self.html
+    }
+}
+
+/// Convert an RTF document to an HTML fragment
+///
+/// Each RTF paragraph becomes a `<p>` element. Text in `\'xx` escapes is
+/// decoded using the document's `\ansicpg` codepage.
+pub fn rtf_to_html(data: &[u8]) -> ClipboardResult<String> {
+    if !data.starts_with(b"{\\rtf") {
+        return Err(ClipboardError::FormatConversion(
+            "Invalid RTF: must start with {\\rtf".to_string(),
+        ));
+    }
+
+    let mut writer = HtmlWriter::default();
+    let mut stack: Vec<GroupState> = Vec::new();
+    let mut state = GroupState::default();
+    // Fallback characters still to skip after \uN
+    let mut skip_fallback = 0usize;
+    // Group depth of the field instruction, rendered when its group closes
+    let mut field_depth: Option<usize> = None;
+    // Group depth of the field holding the current hyperlink
+    let mut link_depth: Option<usize> = None;
+    // Set by \* : the next control word starts an ignorable destination
+    let mut ignorable = false;
+    let mut i = 0;
+
+    while i < data.len() {
+        let byte = data[i];
+        i += 1;
+
+        match byte {
+            b'{' => {
+                stack.push(state.clone());
+                skip_fallback = 0;
+            }
+            b'}' => {
+                if link_depth == Some(stack.len()) {
+                    writer.end_link();
+                    link_depth = None;
+                }
+                if field_depth == Some(stack.len()) {
+                    // The instruction is complete; the result group follows
+                    field_depth = None;
+                    writer.start_link();
+                    if writer.link.is_some() {
+                        link_depth = Some(stack.len().saturating_sub(1));
+                    }
+                }
+                state = stack.pop().unwrap_or_default();
+                skip_fallback = 0;
+            }
+            b'\\' => {
+                let start = i;
+                while i < data.len() && data[i].is_ascii_alphabetic() {
+                    i += 1;
+                }
+                let word = std::str::from_utf8(&data[start..i]).unwrap_or_default();
+
+                if word.is_empty() {
+                    let Some(&symbol) = data.get(i) else {
+                        break;
+                    };
+                    i += 1;
+                    let c = match symbol {
+                        b'\'' => {
+                            let hex = data.get(i..i + 2).and_then(|h| std::str::from_utf8(h).ok());
+                            i += 2;
+                            let Some(byte) = hex.and_then(|h| u8::from_str_radix(h, 16).ok()) else {
+                                continue;
+                            };
+                            if skip_fallback > 0 {
+                                skip_fallback -= 1;
+                            } else {
+                                emit_byte(&mut writer, &state, byte);
+                            }
+                            None
+                        }
+                        b'*' => {
+                            ignorable = true;
+                            None
+                        }
+                        b'~' => Some('\u{a0}'),
+                        b'_' => Some('-'),
+                        b'\\' | b'{' | b'}' => Some(symbol as char),
+                        b'\n' | b'\r' => {
+                            if state.destination == Destination::Text {
+                                writer.end_paragraph();
+                            }
+                            None
+                        }
+                        _ => None,
+                    };
+                    if let Some(c) = c {
+                        emit_char(&mut writer, &state, c);
+                    }
+                    continue;
+                }
+
+                let param_start = i;
+                if i < data.len() && data[i] == b'-' {
+                    i += 1;
+                }
+                while i < data.len() && data[i].is_ascii_digit() {
+                    i += 1;
+                }
+                let param: Option<i32> = std::str::from_utf8(&data[param_start..i])
+                    .ok()
+                    .and_then(|p| p.parse().ok());
+                // A single space delimits the control word
+                if data.get(i) == Some(&b' ') {
+                    i += 1;
+                }
+
+                if std::mem::take(&mut ignorable) && !matches!(word, "fldinst") {
+                    state.destination = Destination::Skip;
+                    continue;
+                }
+                if state.destination == Destination::Skip {
+                    continue;
+                }
+                if SKIP_DESTINATIONS.contains(&word) {
+                    state.destination = Destination::Skip;
+                    continue;
+                }
+
+                let on = param != Some(0);
+                match word {
+                    "ansicpg" => {
+                        writer.encoding = param.and_then(|p| u16::try_from(p).ok()).and_then(codepage_to_encoding);
+                    }
+                    "colortbl" => {
+                        state.destination = Destination::ColorTable;
+                        writer.colors.clear();
+                    }
+                    "red" => writer.pending_color.0 = param.unwrap_or(0) as u8,
+                    "green" => writer.pending_color.1 = param.unwrap_or(0) as u8,
+                    "blue" => writer.pending_color.2 = param.unwrap_or(0) as u8,
+                    "fldinst" => {
+                        state.destination = Destination::FieldInstruction;
+                        writer.field_instruction.clear();
+                        field_depth = Some(stack.len());
+                    }
+                    "par" if state.destination == Destination::Text => writer.end_paragraph(),
+                    "line" if state.destination == Destination::Text => writer.line_break(),
+                    "tab" => emit_char(&mut writer, &state, '\t'),
+                    "emdash" => emit_char(&mut writer, &state, '\u{2014}'),
+                    "endash" => emit_char(&mut writer, &state, '\u{2013}'),
+                    "bullet" => emit_char(&mut writer, &state, '\u{2022}'),
+                    "lquote" => emit_char(&mut writer, &state, '\u{2018}'),
+                    "rquote" => emit_char(&mut writer, &state, '\u{2019}'),
+                    "ldblquote" => emit_char(&mut writer, &state, '\u{201C}'),
+                    "rdblquote" => emit_char(&mut writer, &state, '\u{201D}'),
+                    "u" => {
+                        if let Some(code) = param {
+                            // Negative values encode code units above 0x7FFF;
+                            // characters beyond the BMP come as surrogate pairs
+                            let unit = code as i16 as u16;
+                            match (writer.high_surrogate.take(), unit) {
+                                (_, 0xD800..=0xDBFF) => writer.high_surrogate = Some(unit),
+                                (Some(high), 0xDC00..=0xDFFF) => {
+                                    if let Some(Ok(c)) = char::decode_utf16([high, unit]).next() {
+                                        emit_char(&mut writer, &state, c);
+                                    }
+                                }
+                                // A lone surrogate is dropped
+                                _ => {
+                                    if let Some(c) = char::from_u32(u32::from(unit)) {
+                                        emit_char(&mut writer, &state, c);
+                                    }
+                                }
+                            }
+                            skip_fallback = state.unicode_skip;
+                        }
+                    }
+                    "uc" => state.unicode_skip = param.unwrap_or(1).max(0) as usize,
+                    "plain" => state.format = CharFormat::default(),
+                    "b" => state.format.bold = on,
+                    "i" => state.format.italic = on,
+                    "ul" => state.format.underline = on,
+                    "ulnone" => state.format.underline = false,
+                    "strike" => state.format.strike = on,
+                    "cf" => state.format.color = param.unwrap_or(0).max(0) as usize,
+                    "fs" => state.format.font_size = param.unwrap_or(0).max(0) as u32,
+                    _ => {}
+                }
+            }
+            b'\r' | b'\n' => {}
+            _ => {
+                if skip_fallback > 0 {
+                    skip_fallback -= 1;
+                    continue;
+                }
+                match state.destination {
+                    // Raw 8-bit bytes are in the document codepage
+                    Destination::Text => emit_byte(&mut writer, &state, byte),
+                    Destination::ColorTable if byte == b';' => {
+                        let (r, g, b) = std::mem::take(&mut writer.pending_color);
+                        // The first (empty) entry is the automatic color
+                        let entry = (!writer.colors.is_empty()).then_some((r, g, b));
+                        writer.colors.push(entry);
+                    }
+                    Destination::FieldInstruction => writer.field_instruction.push(byte as char),
+                    Destination::ColorTable | Destination::Skip => {}
+                }
+            }
+        }
+    }
+
+    Ok(writer.finish())
+}
+
+/// Write a character in the current group, if the group renders text
+fn emit_char(writer: &mut HtmlWriter, state: &GroupState, c: char) {
+    match state.destination {
+        Destination::Text => writer.push_char(c, &state.format),
+        Destination::FieldInstruction => writer.field_instruction.push(c),
+        Destination::ColorTable | Destination::Skip => {}
+    }
+}
+
+/// Write a byte of codepage text in the current group
+fn emit_byte(writer: &mut HtmlWriter, state: &GroupState, byte: u8) {
+    match state.destination {
+        Destination::Text => writer.push_byte(byte, &state.format),
+        Destination::FieldInstruction => writer.field_instruction.push(byte as char),
+        Destination::ColorTable | Destination::Skip => {}
+    }
+}
+
+fn escape_attribute(value: &str) -> String {
+    value
+        .replace('&', "&amp;")
+        .replace('"', "&quot;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+}
+
+// =============================================================================
+// HTML → RTF
+// =============================================================================
+
+/// Elements whose content is never rendered
+pub(crate) const HTML_SKIP_ELEMENTS: &[&str] = &["head", "script", "style", "title", "template"];
+
+/// Elements that start a new paragraph
+const HTML_BLOCK_ELEMENTS: &[&str] = &[
+    "p",
+    "div",
+    "li",
+    "tr",
+    "h1",
+    "h2",
+    "h3",
+    "h4",
+    "h5",
+    "h6",
+    "blockquote",
+    "pre",
+    "ul",
+    "ol",
+    "table",
+];
+
+/// Convert HTML (a fragment or a full document) to RTF
+///
+/// Best effort: inline formatting, paragraphs, line breaks and links are
+/// kept; everything else is reduced to its text.
+pub fn html_to_rtf(html: &str) -> Vec<u8> {
+    let mut rtf = String::with_capacity(html.len() + 100);
+    rtf.push_str("{\\rtf1\\ansi\\deff0\n{\\fonttbl{\\f0\\fswiss\\fcharset0 Arial;}}\n");
+
+    let mut skip_depth = 0usize;
+    // Suppress paragraph breaks until some text has been written
+    let mut at_paragraph_start = true;
+    let mut rest = html;
+
+    while !rest.is_empty() {
+        if let Some(after) = rest.strip_prefix('<') {
+            let Some(end) = after.find('>') else {
+                break;
+            };
+            let tag = &after[..end];
+            rest = &after[end + 1..];
+
+            if tag.starts_with('!') || tag.starts_with('?') {
+                continue;
+            }
+            let closing = tag.starts_with('/');
+            let name = tag
+                .trim_start_matches('/')
+                .split(|c: char| c.is_whitespace() || c == '/')
+                .next()
+                .unwrap_or_default()
+                .to_ascii_lowercase();
+
+            if HTML_SKIP_ELEMENTS.contains(&name.as_str()) {
+                if closing {
+                    skip_depth = skip_depth.saturating_sub(1);
+                } else if !tag.ends_with('/') {
+                    skip_depth += 1;
+                }
+                continue;
+            }
+            if skip_depth > 0 {
+                continue;
+            }
+
+            let control = match (name.as_str(), closing) {
+                ("b" | "strong", false) => "{\\b ",
+                ("i" | "em", false) => "{\\i ",
+                ("u" | "ins", false) => "{\\ul ",
+                ("s" | "strike" | "del", false) => "{\\strike ",
+                ("b" | "strong" | "i" | "em" | "u" | "ins" | "s" | "strike" | "del", true) => "}",
+                ("a", false) => {
+                    match attribute(tag, "href").map(|href| decode_entities(&href)) {
+                        Some(href) if is_safe_link(&href) => {
+                            let _ = write!(
+                                rtf,
+                                "{{\\field{{\\*\\fldinst HYPERLINK \"{}\"}}{{\\fldrslt ",
+                                rtf_escape(&href)
+                            );
+                        }
+                        // Keep the closing braces balanced
+                        _ => rtf.push_str("{{"),
+                    }
+                    continue;
+                }
+                ("a", true) => "}}",
+                ("br", _) => "\\line\n",
+                (name, _) if HTML_BLOCK_ELEMENTS.contains(&name) => {
+                    if !at_paragraph_start {
+                        rtf.push_str("\\par\n");
+                        at_paragraph_start = true;
+                    }
+                    continue;
+                }
+                _ => continue,
+            };
+            rtf.push_str(control);
+            if control == "\\line\n" {
+                at_paragraph_start = false;
+            }
+        } else {
+            let end = rest.find('<').unwrap_or(rest.len());
+            let text = &rest[..end];
+            rest = &rest[end..];
+
+            if skip_depth > 0 {
+                continue;
+            }
+            let text = decode_entities(&collapse_whitespace(text));
+            let text = if at_paragraph_start { text.trim_start() } else { &text };
+            if !text.is_empty() {
+                rtf.push_str(&rtf_escape(text));
+                at_paragraph_start = false;
+            }
+        }
+    }
+
+    rtf.push('}');
+    rtf.into_bytes()
+}
+
+/// Collapse whitespace runs to a single space, the way a browser renders text
+pub(crate) fn collapse_whitespace(text: &str) -> String {
+    let mut result = String::with_capacity(text.len());
+    let mut in_space = false;
+    for c in text.chars() {
+        if c.is_whitespace() {
+            if !in_space {
+                result.push(' ');
+            }
+            in_space = true;
+        } else {
+            result.push(c);
+            in_space = false;
+        }
+    }
+    result
+}
+
+/// Value of an attribute in a start tag
+pub(crate) fn attribute(tag: &str, name: &str) -> Option<String> {
+    let lower = tag.to_ascii_lowercase();
+    let mut search = 0;
+    while let Some(pos) = lower[search..].find(name) {
+        let start = search + pos;
+        search = start + name.len();
+        let preceded = lower[..start].ends_with(|c: char| c.is_whitespace());
+        let after = lower[search..].trim_start();
+        if !preceded || !after.starts_with('=') {
+            continue;
+        }
+        let value = tag[tag.len() - after.len() + 1..].trim_start();
+        return Some(match value.chars().next() {
+            Some(quote @ ('"' | '\'')) => value[1..].split(quote).next().unwrap_or_default().to_string(),
+            _ => value
+                .split(|c: char| c.is_whitespace() || c == '>')
+                .next()
+                .unwrap_or_default()
+                .to_string(),
+        });
+    }
+    None
+}
+
+/// Decode the HTML character references that matter for text
+pub(crate) fn decode_entities(text: &str) -> String {
+    if !text.contains('&') {
+        return text.to_string();
+    }
+
+    let mut result = String::with_capacity(text.len());
+    let mut rest = text;
+    while let Some(pos) = rest.find('&') {
+        result.push_str(&rest[..pos]);
+        rest = &rest[pos..];
+
+        let decoded = rest.find(';').filter(|&end| end <= 10).and_then(|end| {
+            let entity = &rest[1..end];
+            let c = match entity {
+                "amp" => Some('&'),
+                "lt" => Some('<'),
+                "gt" => Some('>'),
+                "quot" => Some('"'),
+                "apos" => Some('\''),
+                "nbsp" => Some('\u{a0}'),
+                _ => entity
+                    .strip_prefix("#x")
+                    .or_else(|| entity.strip_prefix("#X"))
+                    .and_then(|hex| u32::from_str_radix(hex, 16).ok())
+                    .or_else(|| entity.strip_prefix('#').and_then(|dec| dec.parse().ok()))
+                    .and_then(char::from_u32),
+            };
+            c.map(|c| (c, end))
+        });
+
+        match decoded {
+            Some((c, end)) => {
+                result.push(c);
+                rest = &rest[end + 1..];
+            }
+            None => {
+                result.push('&');
+                rest = &rest[1..];
+            }
+        }
+    }
+    result.push_str(rest);
+    result
+}
+
+/// Escape text for an RTF document
+fn rtf_escape(text: &str) -> String {
+    let mut escaped = String::with_capacity(text.len());
+    for c in text.chars() {
+        match c {
+            '\\' => escaped.push_str("\\\\"),
+            '{' => escaped.push_str("\\{"),
+            '}' => escaped.push_str("\\}"),
+            '\t' => escaped.push_str("\\tab "),
+            '\u{a0}' => escaped.push_str("\\~"),
+            c if c.is_ascii() => escaped.push(c),
+            c => {
+                // \uN takes a signed 16-bit value; astral characters need a surrogate pair
+                let mut units = [0u16; 2];
+                for unit in c.encode_utf16(&mut units) {
+                    let _ = write!(escaped, "\\u{}?", *unit as i16);
+                }
+            }
+        }
+    }
+    escaped
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_rtf_to_html_formatting() {
+        let rtf = br"{\rtf1\ansi\deff0{\fonttbl{\f0 Arial;}}
+{\colortbl ;\red255\green0\blue0;}
+\pard Plain {\b bold} {\i\ul both}\par
+{\cf1\fs28 red}\line next\par}";
+        let html = rtf_to_html(rtf).unwrap();
+        assert_eq!(
+            html,
+            "<p>Plain <b>bold</b> <i><u>both</u></i></p>\
+             <p><span style=\"color:#ff0000;font-size:14pt\">red</span><br>next</p>"
+        );
+    }
+
+    #[test]
+    fn test_rtf_to_html_escapes_and_unicode() {
+        let rtf = br"{\rtf1\ansi\ansicpg1252 a<b & \{c\} caf\'e9 \u8364? \uc2\u20320 xx}";
+        assert_eq!(rtf_to_html(rtf).unwrap(), "<p>a&lt;b &amp; {c} café € 你</p>");
+    }
+
+    #[test]
+    fn test_rtf_to_html_codepage() {
+        let rtf = br"{\rtf1\ansi\ansicpg1251 \'cf\'f0\'e8\'e2\'e5\'f2}";
+        assert_eq!(rtf_to_html(rtf).unwrap(), "<p>Привет</p>");
+    }
+
+    #[test]
+    fn test_rtf_to_html_double_byte_codepage() {
+        // "日本" in Shift-JIS
+        let rtf = br"{\rtf1\ansi\ansicpg932 \'93\'fa\'96\'7b}";
+        assert_eq!(rtf_to_html(rtf).unwrap(), "<p>日本</p>");
+    }
+
+    #[test]
+    fn test_rtf_to_html_hyperlink() {
+        let rtf =
+            br#"{\rtf1 See {\field{\*\fldinst{HYPERLINK "https://example.com/?a=1&b=2"}}{\fldrslt{\ul link}}} now}"#;
+        assert_eq!(
+            rtf_to_html(rtf).unwrap(),
+            "<p>See <a href=\"https://example.com/?a=1&amp;b=2\"><u>link</u></a> now</p>"
+        );
+
+        // Only web, mail and file links are kept; the text stays either way
+        let rtf = br#"{\rtf1 {\field{\*\fldinst{HYPERLINK "JavaScript:alert(1)"}}{\fldrslt click}}}"#;
+        assert_eq!(rtf_to_html(rtf).unwrap(), "<p>click</p>");
+        let rtf = html_to_rtf("<a href=\"java&#x09;script:alert(1)\">x</a> <a href=\"mailto:a@b.org\">m</a>");
+        let rtf = String::from_utf8(rtf).unwrap();
+        assert!(!rtf.contains("script"));
+        assert!(rtf.contains("HYPERLINK \"mailto:a@b.org\""));
+    }
+
+    #[test]
+    fn test_rtf_to_html_surrogate_pairs() {
+        // U+1F600 as \u-10179 \u-8704, with fallback characters
+        let rtf = br"{\rtf1 a\u-10179?\u-8704?b \u-10179?c\u-8704?d}";
+        assert_eq!(rtf_to_html(rtf).unwrap(), "<p>a\u{1F600}b cd</p>");
+    }
+
+    #[test]
+    fn test_rtf_to_html_skips_ignorable_destinations() {
+        let rtf = br"{\rtf1{\*\generator Riched20;}{\info{\title T}}Text}";
+        assert_eq!(rtf_to_html(rtf).unwrap(), "<p>Text</p>");
+        assert!(rtf_to_html(b"not rtf").is_err());
+    }
+
+    #[test]
+    fn test_html_to_rtf() {
+        let rtf = html_to_rtf(
+            "<html><head><style>p{}</style></head><body>\
+             <p>Hello <b>bold</b> &amp; <i>it</i></p><p>a<br>b <a href=\"https://x.org\">x</a> €</p>\
+             </body></html>",
+        );
+        let rtf = String::from_utf8(rtf).unwrap();
+        assert!(rtf.ends_with(
+            "Hello {\\b bold} & {\\i it}\\par\n\
+             a\\line\nb {\\field{\\*\\fldinst HYPERLINK \"https://x.org\"}{\\fldrslt x}} \\u8364?\\par\n}"
+        ));
+    }
+
+    #[test]
+    fn test_html_rtf_roundtrip() {
+        let html = "<p>One <b>two</b> <i>three</i></p><p>four</p>";
+        let rtf = html_to_rtf(html);
+        assert_eq!(rtf_to_html(&rtf).unwrap(), html);
+    }
+}
@@ -5,8 +5,12 @@
 //!
 //! - Filename character restrictions
 //! - Reserved filenames
+//! - Unicode normalization and path length limits ([`FilenamePolicy`]),
+//!   with a reversible name map ([`FilenameMap`])
 //! - Text encoding and line endings
 //! - File URI parsing
+//! - Path mapping between Windows and local namespaces ([`PathMapper`])
+//! - Terminal-safe paste preparation
 //!
 //! # Example
 //!
@@ -22,7 +26,9 @@
 //! let paths = parse_file_uris(uris);
 //! ```
 
-use std::path::PathBuf;
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use unicode_normalization::UnicodeNormalization;
 
 // =============================================================================
 // Windows Filename Sanitization
@@ -34,22 +40,29 @@ const WINDOWS_INVALID_CHARS: &[char] = &['\\', '/', ':', '*', '?', '"', '<', '>'
 /// Reserved filenames in Windows (case-insensitive).
 /// These cannot be used as filenames, even with extensions.
 const WINDOWS_RESERVED_NAMES: &[&str] = &[
-    "CON", "PRN", "AUX", "NUL", "COM1", "COM2", "COM3", "COM4", "COM5", "COM6", "COM7", "COM8", "COM9", "LPT1", "LPT2",
-    "LPT3", "LPT4", "LPT5", "LPT6", "LPT7", "LPT8", "LPT9",
+    "CON", "PRN", "AUX", "NUL", "CONIN$", "CONOUT$", "COM0", "COM1", "COM2", "COM3", "COM4", "COM5", "COM6", "COM7",
+    "COM8", "COM9", "COM¹", "COM²", "COM³", "LPT0", "LPT1", "LPT2", "LPT3", "LPT4", "LPT5", "LPT6", "LPT7", "LPT8",
+    "LPT9", "LPT¹", "LPT²", "LPT³",
 ];
 
-/// Maximum filename length for Windows (without path).
+/// Maximum filename length for Windows (without path), in UTF-16 code units.
 const WINDOWS_MAX_FILENAME_LEN: usize = 255;
 
+/// Classic Windows path limit (`MAX_PATH`), including the terminating NUL.
+pub const WINDOWS_MAX_PATH: usize = 260;
+
 /// Sanitize a filename for use on Windows.
 ///
 /// This function:
 /// - Replaces invalid characters (`\ / : * ? " < > |`) with underscores
-/// - Handles reserved names (CON, PRN, AUX, NUL, COM1-9, LPT1-9) by prefixing with `_`
+/// - Handles reserved names (CON, PRN, AUX, NUL, COM0-9, LPT0-9) by prefixing with `_`
 /// - Removes trailing dots and spaces (Windows silently strips these)
-/// - Truncates to 255 characters if necessary
+/// - Truncates to 255 UTF-16 code units if necessary
 /// - Handles empty filenames
 ///
+/// Unicode normalization is left alone; use [`FilenamePolicy`] for that and
+/// for the other options.
+///
 /// # Arguments
 ///
 /// * `filename` - The original filename (just the name, not a full path)
@@ -69,61 +82,45 @@ const WINDOWS_MAX_FILENAME_LEN: usize = 255;
 /// assert_eq!(sanitize_filename_for_windows("file.txt."), "file.txt");
 /// ```
 pub fn sanitize_filename_for_windows(filename: &str) -> String {
-    if filename.is_empty() {
-        return "_unnamed_".to_string();
-    }
-
-    // Replace invalid characters with underscores
-    let mut sanitized: String = filename
-        .chars()
-        .map(|c| {
-            if WINDOWS_INVALID_CHARS.contains(&c) || c.is_control() {
-                '_'
-            } else {
-                c
-            }
-        })
-        .collect();
-
-    // Remove trailing dots and spaces (Windows strips these silently)
-    while sanitized.ends_with('.') || sanitized.ends_with(' ') {
-        sanitized.pop();
-    }
+    FilenamePolicy::windows()
+        .with_unicode_form(UnicodeForm::Preserve)
+        .sanitize(filename)
+}
 
-    // Handle empty result after stripping
-    if sanitized.is_empty() {
-        return "_unnamed_".to_string();
+/// Prefix an absolute Windows path with `\\?\` if it exceeds `MAX_PATH`
+///
+/// Paths in this form bypass the 260-character limit on Windows 10 and
+/// later, for applications that are not long-path aware. Slashes are turned
+/// into backslashes because the prefix also disables path normalization.
+/// Short, already prefixed and relative paths are returned unchanged.
+///
+/// # Example
+///
+/// ```rust
+/// use lamco_clipboard_core::sanitize::windows_long_path;
+///
+/// assert_eq!(windows_long_path(r"C:\short.txt"), r"C:\short.txt");
+///
+/// let long = format!(r"C:\{}.txt", "x".repeat(300));
+/// assert!(windows_long_path(&long).starts_with(r"\\?\C:\"));
+///
+/// let unc = format!(r"\\server\share\{}", "x".repeat(300));
+/// assert!(windows_long_path(&unc).starts_with(r"\\?\UNC\server\share\"));
+/// ```
+pub fn windows_long_path(path: &str) -> String {
+    if path.encode_utf16().count() < WINDOWS_MAX_PATH || path.starts_with(r"\\?\") {
+        return path.to_string();
     }
 
-    // Check for reserved names (case-insensitive, with or without extension)
-    let name_upper = sanitized.to_uppercase();
-    let base_name = name_upper.split('.').next().unwrap_or("");
-
-    if WINDOWS_RESERVED_NAMES.contains(&base_name) {
-        sanitized = format!("_{}", sanitized);
+    let path = path.replace('/', "\\");
+    if let Some(unc) = path.strip_prefix(r"\\") {
+        return format!(r"\\?\UNC\{unc}");
     }
 
-    // Truncate if too long (preserve extension if possible)
-    if sanitized.len() > WINDOWS_MAX_FILENAME_LEN {
-        if let Some(dot_pos) = sanitized.rfind('.') {
-            let ext = &sanitized[dot_pos..];
-            let ext_len = ext.len();
-            if ext_len < WINDOWS_MAX_FILENAME_LEN - 1 {
-                let base_max = WINDOWS_MAX_FILENAME_LEN - ext_len;
-                let base = &sanitized[..dot_pos];
-                // Truncate base, keeping extension
-                let truncated_base: String = base.chars().take(base_max).collect();
-                sanitized = format!("{}{}", truncated_base, ext);
-            } else {
-                // Extension itself is too long, just truncate
-                sanitized = sanitized.chars().take(WINDOWS_MAX_FILENAME_LEN).collect();
-            }
-        } else {
-            sanitized = sanitized.chars().take(WINDOWS_MAX_FILENAME_LEN).collect();
-        }
+    match path.as_bytes() {
+        [drive, b':', b'\\', ..] if drive.is_ascii_alphabetic() => format!(r"\\?\{path}"),
+        _ => path,
     }
-
-    sanitized
 }
 
 // =============================================================================
@@ -134,7 +131,7 @@ pub fn sanitize_filename_for_windows(filename: &str) -> String {
 /// Only forward slash and null byte are truly invalid.
 const LINUX_INVALID_CHARS: &[char] = &['/', '\0'];
 
-/// Maximum filename length for most Linux filesystems.
+/// Maximum filename length for most Linux filesystems, in bytes.
 const LINUX_MAX_FILENAME_LEN: usize = 255;
 
 /// Sanitize a filename for use on Linux.
@@ -144,7 +141,7 @@ const LINUX_MAX_FILENAME_LEN: usize = 255;
 /// - Null bytes
 /// - Backslashes (convert to underscores for safety with shell commands)
 /// - Leading dashes (can be confused with command options)
-/// - Truncation to 255 characters
+/// - Truncation to 255 bytes
 ///
 /// # Arguments
 ///
@@ -164,51 +161,434 @@ const LINUX_MAX_FILENAME_LEN: usize = 255;
 /// assert_eq!(sanitize_filename_for_linux("-dangerous"), "_-dangerous");
 /// ```
 pub fn sanitize_filename_for_linux(filename: &str) -> String {
-    if filename.is_empty() {
-        return "_unnamed_".to_string();
+    FilenamePolicy::linux()
+        .with_unicode_form(UnicodeForm::Preserve)
+        .sanitize(filename)
+}
+
+// =============================================================================
+// Filename Policy
+// =============================================================================
+
+/// Name used when nothing of the original filename survives
+const UNNAMED: &str = "_unnamed_";
+
+/// Shortest name [`FilenamePolicy::sanitize_in`] will truncate to before
+/// leaving the path to long-path handling instead
+const MIN_TRUNCATED_NAME_LEN: usize = 16;
+
+/// Filesystem a [`FilenamePolicy`] produces names for
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "serde", serde(rename_all = "snake_case"))]
+pub enum FilenameTarget {
+    /// Reserved characters and device names, no trailing dots or spaces,
+    /// lengths in UTF-16 code units, case-insensitive
+    Windows,
+    /// Only `/` and NUL are invalid (plus `\` and leading `-` for shell
+    /// safety), lengths in bytes, case-sensitive
+    Linux,
+}
+
+/// Unicode normalization applied to filenames
+///
+/// macOS clients send decomposed names (`e` + U+0301) while Windows and most
+/// Linux software use composed ones (`é`), so the same name can otherwise
+/// appear twice or fail to match an existing file.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "serde", serde(rename_all = "snake_case"))]
+pub enum UnicodeForm {
+    /// Keep code points as received
+    Preserve,
+    /// Canonical composition (NFC)
+    #[default]
+    Nfc,
+    /// Canonical decomposition (NFD)
+    Nfd,
+}
+
+/// What replaces characters the target filesystem cannot store
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "serde", serde(rename_all = "snake_case"))]
+pub enum ReplacementStrategy {
+    /// Substitute a fixed character
+    Char(char),
+    /// Drop the character
+    Remove,
+    /// Substitute a look-alike so the name reads the same: fullwidth forms
+    /// for punctuation (`:` becomes `：`) and control pictures for control
+    /// characters
+    Lookalike,
+}
+
+impl Default for ReplacementStrategy {
+    fn default() -> Self {
+        Self::Char('_')
     }
+}
 
-    // Replace invalid characters
-    let mut sanitized: String = filename
-        .chars()
-        .map(|c| {
-            if LINUX_INVALID_CHARS.contains(&c) || c == '\\' {
-                '_'
-            } else {
-                c
+/// Rules for turning a received filename into one the target can store
+///
+/// # Example
+///
+/// ```rust
+/// use lamco_clipboard_core::sanitize::{FilenamePolicy, ReplacementStrategy};
+///
+/// let policy = FilenamePolicy::windows().with_replacement(ReplacementStrategy::Lookalike);
+/// assert_eq!(policy.sanitize("12:30 notes?.txt"), "12：30 notes？.txt");
+/// assert_eq!(policy.sanitize("nul.tar.gz"), "_nul.tar.gz");
+///
+/// // Decomposed names from macOS are composed
+/// assert_eq!(policy.sanitize("Cafe\u{301}.txt"), "Caf\u{e9}.txt");
+/// ```
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct FilenamePolicy {
+    target: FilenameTarget,
+    unicode_form: UnicodeForm,
+    replacement: ReplacementStrategy,
+    max_path_len: Option<usize>,
+}
+
+impl FilenamePolicy {
+    /// Policy for Windows filesystems, limited to `MAX_PATH`
+    pub fn windows() -> Self {
+        Self {
+            target: FilenameTarget::Windows,
+            unicode_form: UnicodeForm::default(),
+            replacement: ReplacementStrategy::default(),
+            max_path_len: Some(WINDOWS_MAX_PATH),
+        }
+    }
+
+    /// Policy for Linux filesystems
+    pub fn linux() -> Self {
+        Self {
+            target: FilenameTarget::Linux,
+            unicode_form: UnicodeForm::default(),
+            replacement: ReplacementStrategy::default(),
+            max_path_len: None,
+        }
+    }
+
+    /// Set the Unicode normalization form
+    pub fn with_unicode_form(mut self, form: UnicodeForm) -> Self {
+        self.unicode_form = form;
+        self
+    }
+
+    /// Set what replaces invalid characters
+    pub fn with_replacement(mut self, replacement: ReplacementStrategy) -> Self {
+        self.replacement = replacement;
+        self
+    }
+
+    /// Set the full path limit, including the terminating NUL
+    ///
+    /// `None` means the destination handles long paths (Windows with long
+    /// paths enabled, or paths passed through [`windows_long_path`]), so
+    /// only the per-name limit applies.
+    pub fn with_max_path_len(mut self, max_path_len: Option<usize>) -> Self {
+        self.max_path_len = max_path_len;
+        self
+    }
+
+    /// Target filesystem
+    pub fn target(&self) -> FilenameTarget {
+        self.target
+    }
+
+    /// Unicode normalization form
+    pub fn unicode_form(&self) -> UnicodeForm {
+        self.unicode_form
+    }
+
+    /// Replacement for invalid characters
+    pub fn replacement(&self) -> ReplacementStrategy {
+        self.replacement
+    }
+
+    /// Full path limit, if any
+    pub fn max_path_len(&self) -> Option<usize> {
+        self.max_path_len
+    }
+
+    /// Sanitize a filename (just the name, not a full path)
+    pub fn sanitize(&self, filename: &str) -> String {
+        self.sanitize_with_limit(filename, self.max_name_len())
+    }
+
+    /// Sanitize a filename to be created in `dir`
+    ///
+    /// Like [`sanitize`](Self::sanitize), but also shortens the name so the
+    /// joined path stays within the path limit. If `dir` leaves too little
+    /// room the name is kept at full length and the caller has to use a long
+    /// path, for example via [`windows_long_path`].
+    ///
+    /// ```rust
+    /// use lamco_clipboard_core::sanitize::FilenamePolicy;
+    ///
+    /// let dir = format!(r"C:\Users\me\{}", "d".repeat(200));
+    /// let name = FilenamePolicy::windows().sanitize_in(&dir, &format!("{}.txt", "n".repeat(100)));
+    /// assert_eq!(dir.len() + 1 + name.len(), 259);
+    /// assert!(name.ends_with(".txt"));
+    /// ```
+    pub fn sanitize_in(&self, dir: &str, filename: &str) -> String {
+        let Some(max_path_len) = self.max_path_len else {
+            return self.sanitize(filename);
+        };
+
+        let separator = usize::from(!dir.is_empty() && !dir.ends_with(['/', '\\']));
+        let used = self.measure(dir) + separator + 1;
+        match max_path_len.checked_sub(used) {
+            Some(room) if room >= MIN_TRUNCATED_NAME_LEN => {
+                self.sanitize_with_limit(filename, room.min(self.max_name_len()))
             }
-        })
-        .collect();
+            _ => self.sanitize(filename),
+        }
+    }
+
+    fn sanitize_with_limit(&self, filename: &str, limit: usize) -> String {
+        let normalized = match self.unicode_form {
+            UnicodeForm::Preserve => filename.to_string(),
+            UnicodeForm::Nfc => filename.nfc().collect(),
+            UnicodeForm::Nfd => filename.nfd().collect(),
+        };
+
+        let mut sanitized = String::with_capacity(normalized.len());
+        for c in normalized.chars() {
+            if !self.is_invalid(c) {
+                sanitized.push(c);
+                continue;
+            }
+            match self.replacement {
+                ReplacementStrategy::Char(replacement) => sanitized.push(replacement),
+                ReplacementStrategy::Remove => {}
+                ReplacementStrategy::Lookalike => sanitized.push(lookalike(c).unwrap_or('_')),
+            }
+        }
+
+        match self.target {
+            FilenameTarget::Windows => {
+                // Windows strips these silently, so the file would not be found again
+                trim_trailing_dots_and_spaces(&mut sanitized);
+                if sanitized.is_empty() {
+                    return UNNAMED.to_string();
+                }
+
+                let upper = sanitized.to_uppercase();
+                let base = upper.split('.').next().unwrap_or_default().trim_end();
+                if WINDOWS_RESERVED_NAMES.contains(&base) {
+                    sanitized.insert(0, '_');
+                }
+
+                let mut truncated = self.truncate(&sanitized, limit);
+                trim_trailing_dots_and_spaces(&mut truncated);
+                truncated
+            }
+            FilenameTarget::Linux => {
+                // Can be confused with command options
+                if sanitized.starts_with('-') {
+                    sanitized.insert(0, '_');
+                }
+                if sanitized.is_empty() || sanitized == "." || sanitized == ".." {
+                    return UNNAMED.to_string();
+                }
+
+                self.truncate(&sanitized, limit)
+            }
+        }
+    }
 
-    // Handle leading dash (can be confused with command options)
-    if sanitized.starts_with('-') {
-        sanitized = format!("_{}", sanitized);
+    /// Key under which the target considers two names the same
+    fn collision_key(&self, name: &str) -> String {
+        match self.target {
+            FilenameTarget::Windows => name.to_lowercase(),
+            FilenameTarget::Linux => name.to_string(),
+        }
     }
 
-    // Handle empty result
-    if sanitized.is_empty() || sanitized == "." || sanitized == ".." {
-        return "_unnamed_".to_string();
+    fn is_invalid(&self, c: char) -> bool {
+        match self.target {
+            FilenameTarget::Windows => WINDOWS_INVALID_CHARS.contains(&c) || c.is_control(),
+            FilenameTarget::Linux => LINUX_INVALID_CHARS.contains(&c) || c == '\\',
+        }
     }
 
-    // Truncate if too long
-    if sanitized.len() > LINUX_MAX_FILENAME_LEN {
-        if let Some(dot_pos) = sanitized.rfind('.') {
-            let ext = &sanitized[dot_pos..];
-            let ext_len = ext.len();
-            if ext_len < LINUX_MAX_FILENAME_LEN - 1 {
-                let base_max = LINUX_MAX_FILENAME_LEN - ext_len;
-                let base = &sanitized[..dot_pos];
-                let truncated_base: String = base.chars().take(base_max).collect();
-                sanitized = format!("{}{}", truncated_base, ext);
-            } else {
-                sanitized = sanitized.chars().take(LINUX_MAX_FILENAME_LEN).collect();
+    fn max_name_len(&self) -> usize {
+        match self.target {
+            FilenameTarget::Windows => WINDOWS_MAX_FILENAME_LEN,
+            FilenameTarget::Linux => LINUX_MAX_FILENAME_LEN,
+        }
+    }
+
+    /// Length in the target's units
+    fn measure(&self, s: &str) -> usize {
+        s.chars().map(|c| self.char_len(c)).sum()
+    }
+
+    fn char_len(&self, c: char) -> usize {
+        match self.target {
+            FilenameTarget::Windows => c.len_utf16(),
+            FilenameTarget::Linux => c.len_utf8(),
+        }
+    }
+
+    /// Shorten to `limit`, keeping the extension if it fits
+    fn truncate(&self, name: &str, limit: usize) -> String {
+        if self.measure(name) <= limit {
+            return name.to_string();
+        }
+
+        let (base, ext) = match name.rfind('.') {
+            Some(pos) if pos > 0 && self.measure(&name[pos..]) < limit => name.split_at(pos),
+            _ => (name, ""),
+        };
+
+        let mut room = limit - self.measure(ext);
+        let mut end = 0;
+        for (i, c) in base.char_indices() {
+            let len = self.char_len(c);
+            if len > room {
+                break;
             }
-        } else {
-            sanitized = sanitized.chars().take(LINUX_MAX_FILENAME_LEN).collect();
+            room -= len;
+            end = i + c.len_utf8();
         }
+
+        format!("{}{}", &base[..end], ext)
     }
 
-    sanitized
+    /// `name` with ` (n)` before the extension, still within the name limit
+    fn numbered(&self, name: &str, n: usize) -> String {
+        let suffix = format!(" ({n})");
+        let shortened = self.truncate(name, self.max_name_len() - self.measure(&suffix));
+        match shortened.rfind('.') {
+            Some(pos) if pos > 0 => format!("{}{}{}", &shortened[..pos], suffix, &shortened[pos..]),
+            _ => format!("{shortened}{suffix}"),
+        }
+    }
+}
+
+impl Default for FilenamePolicy {
+    fn default() -> Self {
+        Self::windows()
+    }
+}
+
+fn trim_trailing_dots_and_spaces(name: &mut String) {
+    let len = name.trim_end_matches(['.', ' ']).len();
+    name.truncate(len);
+}
+
+/// Visually similar character the target can store
+fn lookalike(c: char) -> Option<char> {
+    match c {
+        // Control Pictures block: U+2400 SYMBOL FOR NULL onwards
+        '\0'..='\x1f' => char::from_u32(0x2400 + c as u32),
+        '\x7f' => Some('\u{2421}'),
+        // Halfwidth and Fullwidth Forms mirror printable ASCII from U+FF01
+        '!'..='~' => char::from_u32(c as u32 - 0x21 + 0xFF01),
+        _ => None,
+    }
+}
+
+/// Names sanitized for one transfer, so they can be mapped back
+///
+/// Each name handed out is unique on the target, so two originals that
+/// sanitize to the same name (`a:b` and `a?b`, or `Notes` and `notes` on
+/// Windows) get numbered instead of overwriting each other.
+/// [`desanitize`](Self::desanitize) returns the original for a name the
+/// map produced, so a file sent back keeps its original name.
+///
+/// # Example
+///
+/// ```rust
+/// use lamco_clipboard_core::sanitize::{FilenameMap, FilenamePolicy};
+///
+/// let mut names = FilenameMap::new(FilenamePolicy::windows());
+/// assert_eq!(names.sanitize("a:b.txt"), "a_b.txt");
+/// assert_eq!(names.sanitize("a?b.txt"), "a_b (2).txt");
+///
+/// assert_eq!(names.desanitize("a_b (2).txt"), "a?b.txt");
+/// assert_eq!(names.desanitize("other.txt"), "other.txt");
+/// ```
+#[derive(Debug, Clone)]
+pub struct FilenameMap {
+    policy: FilenamePolicy,
+
+    /// Collision key of each sanitized name, to the sanitized and original names
+    entries: HashMap<String, (String, String)>,
+}
+
+impl FilenameMap {
+    /// Create an empty map using `policy`
+    pub fn new(policy: FilenamePolicy) -> Self {
+        Self {
+            policy,
+            entries: HashMap::new(),
+        }
+    }
+
+    /// Policy names are sanitized with
+    pub fn policy(&self) -> &FilenamePolicy {
+        &self.policy
+    }
+
+    /// Sanitize `filename`, unique among the names this map handed out
+    ///
+    /// Sanitizing the same original again returns the same name.
+    pub fn sanitize(&mut self, filename: &str) -> String {
+        let sanitized = self.policy.sanitize(filename);
+
+        let mut candidate = sanitized.clone();
+        let mut n = 2;
+        loop {
+            match self.entries.get(&self.policy.collision_key(&candidate)) {
+                None => break,
+                Some((existing, original)) if original == filename => return existing.clone(),
+                Some(_) => {
+                    candidate = self.policy.numbered(&sanitized, n);
+                    n += 1;
+                }
+            }
+        }
+
+        self.entries.insert(
+            self.policy.collision_key(&candidate),
+            (candidate.clone(), filename.to_string()),
+        );
+        candidate
+    }
+
+    /// Original name for a name this map produced
+    pub fn original(&self, sanitized: &str) -> Option<&str> {
+        self.entries
+            .get(&self.policy.collision_key(sanitized))
+            .map(|(_, original)| original.as_str())
+    }
+
+    /// Original name for `sanitized`, or `sanitized` itself if the map did not produce it
+    pub fn desanitize<'a>(&'a self, sanitized: &'a str) -> &'a str {
+        self.original(sanitized).unwrap_or(sanitized)
+    }
+
+    /// Number of names handed out
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    /// Whether no name was handed out yet
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    /// Forget all names, for the next transfer
+    pub fn clear(&mut self) {
+        self.entries.clear();
+    }
 }
 
 // =============================================================================
@@ -278,7 +658,9 @@ pub fn parse_file_uris(data: &[u8]) -> Vec<PathBuf> {
 
 /// Parse a single file:// URI to a PathBuf.
 ///
-/// Handles URL-encoded characters (e.g., `%20` for space).
+/// Handles percent-encoded bytes (e.g., `%20` for space, `%C3%A9` for `é`)
+/// and the `file://localhost/` and `file:/path` forms. On Unix the decoded
+/// bytes become the path as-is, so names that are not valid UTF-8 survive.
 ///
 /// # Arguments
 ///
@@ -286,75 +668,365 @@ pub fn parse_file_uris(data: &[u8]) -> Vec<PathBuf> {
 ///
 /// # Returns
 ///
-/// The decoded path, or None if the URI is invalid.
+/// The decoded path, or None if the URI is invalid or names another host.
 pub fn parse_file_uri(uri: &str) -> Option<PathBuf> {
-    let path_str = uri.strip_prefix("file://")?;
+    let (host, path) = split_file_uri(uri)?;
+    if host.is_some() {
+        return None;
+    }
+
+    Some(path_from_bytes(percent_decode_bytes(path)))
+}
 
-    // URL decode the path
-    let decoded = percent_decode(path_str);
+/// Build a file:// URI for a local path.
+///
+/// The inverse of [`parse_file_uri`]. Every byte outside the RFC 3986 path
+/// characters is percent-encoded, including each byte of a multi-byte UTF-8
+/// character, so the URI names the same file even when the filename is not
+/// valid UTF-8.
+///
+/// # Example
+///
+/// ```rust
+/// use lamco_clipboard_core::sanitize::path_to_file_uri;
+/// use std::path::Path;
+///
+/// assert_eq!(path_to_file_uri(Path::new("/tmp/my file.txt")), "file:///tmp/my%20file.txt");
+/// assert_eq!(path_to_file_uri(Path::new("/tmp/café")), "file:///tmp/caf%C3%A9");
+/// ```
+pub fn path_to_file_uri(path: &Path) -> String {
+    #[cfg(unix)]
+    let bytes = std::os::unix::ffi::OsStrExt::as_bytes(path.as_os_str()).to_vec();
+    #[cfg(not(unix))]
+    let bytes = path.to_string_lossy().into_owned().into_bytes();
+
+    format!("file://{}", percent_encode_bytes(&bytes))
+}
+
+#[cfg(unix)]
+fn path_from_bytes(bytes: Vec<u8>) -> PathBuf {
+    use std::os::unix::ffi::OsStringExt;
+    PathBuf::from(std::ffi::OsString::from_vec(bytes))
+}
+
+#[cfg(not(unix))]
+fn path_from_bytes(bytes: Vec<u8>) -> PathBuf {
+    PathBuf::from(String::from_utf8_lossy(&bytes).into_owned())
+}
+
+/// URIs in a `text/uri-list` (RFC 2483): trimmed lines, without blank lines
+/// and `#` comments.
+pub(crate) fn uri_list_entries(uri_list: &str) -> impl Iterator<Item = &str> {
+    uri_list
+        .lines()
+        .map(str::trim)
+        .filter(|line| !line.is_empty() && !line.starts_with('#'))
+}
 
-    Some(PathBuf::from(decoded))
+/// Whether a URI uses the `file:` scheme, well-formed or not.
+pub(crate) fn has_file_scheme(uri: &str) -> bool {
+    uri.get(..5).is_some_and(|scheme| scheme.eq_ignore_ascii_case("file:"))
 }
 
-/// Simple percent-decoding for file URIs.
+/// URI schemes a hyperlink in pasted rich text may use
+const LINK_SCHEMES: &[&str] = &["http", "https", "mailto", "file"];
+
+/// Whether a hyperlink target may be carried into pasted rich text.
+///
+/// Only [`LINK_SCHEMES`] are allowed, so `javascript:` and `data:` links
+/// never reach the paste target. Relative links and anchors have no scheme
+/// and are kept. Whitespace and control characters are ignored in the
+/// scheme, as browsers do.
+pub(crate) fn is_safe_link(target: &str) -> bool {
+    let Some((scheme, _)) = target.split_once(':') else {
+        return true;
+    };
+    if scheme.contains(['/', '?', '#']) {
+        return true;
+    }
+    let scheme: String = scheme
+        .chars()
+        .filter(|c| !c.is_whitespace() && !c.is_control())
+        .collect();
+    LINK_SCHEMES.iter().any(|allowed| scheme.eq_ignore_ascii_case(allowed))
+}
+
+/// Whether the host of a file URI names this machine.
+///
+/// GNOME writes the machine's own hostname into file URIs. An unqualified
+/// name matches the first label of a qualified one, so `myhost` and
+/// `myhost.example.com` are the same machine.
+pub(crate) fn is_local_host(host: &str, local: &str) -> bool {
+    if host.eq_ignore_ascii_case(local) {
+        return true;
+    }
+    let label = |name: &str| name.split('.').next().unwrap_or(name).to_ascii_lowercase();
+    (!host.contains('.') || !local.contains('.')) && label(host) == label(local)
+}
+
+/// Hostname of this machine, if the kernel reports one.
+pub(crate) fn system_hostname() -> Option<String> {
+    ["/proc/sys/kernel/hostname", "/etc/hostname"]
+        .iter()
+        .filter_map(|path| std::fs::read_to_string(path).ok())
+        .map(|name| name.trim().to_string())
+        .find(|name| !name.is_empty())
+}
+
+/// Split a `file:` URI into its host and still-encoded path.
+///
+/// The host is `None` for local files (`file:///p`, `file://localhost/p`,
+/// `file:/p`). A query or fragment is dropped.
+pub(crate) fn split_file_uri(uri: &str) -> Option<(Option<&str>, &str)> {
+    if !has_file_scheme(uri) {
+        return None;
+    }
+    let rest = &uri[5..];
+    let rest = &rest[..rest.find(['?', '#']).unwrap_or(rest.len())];
+
+    let (host, path) = match rest.strip_prefix("//") {
+        Some(authority) => {
+            let slash = authority.find('/').unwrap_or(authority.len());
+            authority.split_at(slash)
+        }
+        None if rest.starts_with('/') => ("", rest),
+        None => return None,
+    };
+    if host.is_empty() && path.is_empty() {
+        return None;
+    }
+
+    let host = (!host.is_empty() && !host.eq_ignore_ascii_case("localhost")).then_some(host);
+    Some((host, path))
+}
+
+/// Windows-side path for a decoded file URI path.
 ///
-/// Decodes URL-encoded characters like `%20` (space), `%2F` (slash), etc.
-fn percent_decode(input: &str) -> String {
+/// `/C:/dir/f` becomes `C:\dir\f` (the legacy `/C|/` form too) and a
+/// remote host becomes a UNC path; other paths are used unchanged.
+pub(crate) fn file_uri_path_to_hdrop(host: Option<&str>, path: &str) -> String {
+    if let Some(host) = host {
+        return format!(r"\\{}{}", host, path.replace('/', "\\"));
+    }
+
+    let bytes = path.as_bytes();
+    let is_drive = bytes.len() >= 3
+        && bytes[0] == b'/'
+        && bytes[1].is_ascii_alphabetic()
+        && matches!(bytes[2], b':' | b'|')
+        && matches!(bytes.get(3), None | Some(b'/'));
+    if is_drive {
+        let rest = path[3..].replace('/', "\\");
+        let rest = if rest.is_empty() { "\\".to_string() } else { rest };
+        return format!("{}:{}", &path[1..2], rest);
+    }
+
+    path.to_string()
+}
+
+/// File URI for a path taken from HDROP.
+///
+/// The inverse of [`file_uri_path_to_hdrop`]: drive paths (`C:\dir\f`) and UNC
+/// paths (`\\server\share\f`) get their separators turned into slashes; other
+/// paths are encoded unchanged.
+pub(crate) fn hdrop_path_to_file_uri(path: &[u8]) -> String {
+    let is_drive = path.len() >= 2
+        && path[0].is_ascii_alphabetic()
+        && path[1] == b':'
+        && matches!(path.get(2), None | Some(b'\\' | b'/'));
+    if is_drive {
+        let slashes: Vec<u8> = path.iter().map(|&b| if b == b'\\' { b'/' } else { b }).collect();
+        return format!("file:///{}", percent_encode_bytes(&slashes));
+    }
+
+    if let Some(unc) = path.strip_prefix(br"\\") {
+        let slashes: Vec<u8> = unc.iter().map(|&b| if b == b'\\' { b'/' } else { b }).collect();
+        let host_len = slashes.iter().position(|&b| b == b'/').unwrap_or(slashes.len());
+        let (host, rest) = slashes.split_at(host_len);
+        return format!("file://{}{}", percent_encode_bytes(host), percent_encode_bytes(rest));
+    }
+
+    format!("file://{}", percent_encode_bytes(path))
+}
+
+/// Percent-decoding for file URIs (RFC 3986).
+///
+/// Decodes `%XX` escapes like `%20` (space) or `%C3%A9` (`é`) to raw bytes;
+/// malformed escapes are kept literally.
+pub(crate) fn percent_decode_bytes(input: &str) -> Vec<u8> {
+    let bytes = input.as_bytes();
+    let mut result = Vec::with_capacity(bytes.len());
+    let mut i = 0;
+
+    while i < bytes.len() {
+        if bytes[i] == b'%' && i + 2 < bytes.len() {
+            let (hi, lo) = (bytes[i + 1], bytes[i + 2]);
+            if hi.is_ascii_hexdigit() && lo.is_ascii_hexdigit() {
+                result.push(hex_value(hi) << 4 | hex_value(lo));
+                i += 3;
+                continue;
+            }
+        }
+        result.push(bytes[i]);
+        i += 1;
+    }
+
+    result
+}
+
+fn hex_value(digit: u8) -> u8 {
+    match digit {
+        b'0'..=b'9' => digit - b'0',
+        b'a'..=b'f' => digit - b'a' + 10,
+        _ => digit - b'A' + 10,
+    }
+}
+
+/// Percent-encode a path for a file URI (RFC 3986).
+///
+/// Keeps unreserved characters, sub-delimiters, `:`, `@` and `/`; every other
+/// byte, including each byte of non-ASCII characters, becomes `%XX`.
+pub(crate) fn percent_encode_bytes(input: &[u8]) -> String {
+    const HEX: &[u8; 16] = b"0123456789ABCDEF";
+
     let mut result = String::with_capacity(input.len());
-    let mut chars = input.chars().peekable();
-
-    while let Some(c) = chars.next() {
-        if c == '%' {
-            // Try to read two hex digits
-            let hex: String = chars.by_ref().take(2).collect();
-            if hex.len() == 2 {
-                if let Ok(byte) = u8::from_str_radix(&hex, 16) {
-                    // For multi-byte UTF-8, we need to collect bytes
-                    // Simple case: ASCII character
-                    if byte < 128 {
-                        result.push(byte as char);
-                        continue;
-                    }
-                    // For non-ASCII, decode as UTF-8 byte sequence
-                    let mut bytes = vec![byte];
-                    while chars.peek() == Some(&'%') {
-                        chars.next(); // consume '%'
-                        let hex2: String = chars.by_ref().take(2).collect();
-                        if hex2.len() == 2 {
-                            if let Ok(b) = u8::from_str_radix(&hex2, 16) {
-                                bytes.push(b);
-                                // Check if we have a complete UTF-8 sequence
-                                if let Ok(s) = std::str::from_utf8(&bytes) {
-                                    result.push_str(s);
-                                    bytes.clear();
-                                    break;
-                                }
-                            } else {
-                                // Invalid hex, put back what we consumed
-                                result.push('%');
-                                result.push_str(&hex2);
-                                break;
-                            }
-                        }
-                    }
-                    if !bytes.is_empty() {
-                        // Incomplete UTF-8 sequence, use replacement char
-                        result.push('\u{FFFD}');
-                    }
-                    continue;
-                }
+    for &b in input {
+        match b {
+            b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' | b'-' | b'.' | b'_' | b'~' => result.push(b as char),
+            b'!' | b'$' | b'&' | b'\'' | b'(' | b')' | b'*' | b'+' | b',' | b';' | b'=' => result.push(b as char),
+            b':' | b'@' | b'/' => result.push(b as char),
+            _ => {
+                result.push('%');
+                result.push(HEX[(b >> 4) as usize] as char);
+                result.push(HEX[(b & 0xF) as usize] as char);
             }
-            // Invalid percent encoding, keep literal
-            result.push('%');
-            result.push_str(&hex);
-        } else {
-            result.push(c);
         }
     }
 
     result
 }
 
+// =============================================================================
+// Path Mapping
+// =============================================================================
+
+/// Translates paths between Windows and local namespaces for shared storage
+///
+/// When both ends see the same files, for example through a redirected drive
+/// or a share mounted on both sides, a dropped path is only useful if it
+/// names the file in the receiver's namespace. Each mapping pairs a Windows
+/// prefix (a drive such as `Z:` or a UNC share such as `\\server\share`)
+/// with a local directory. Prefixes match whole path components, Windows
+/// prefixes case-insensitively, and the longest matching prefix wins.
+///
+/// Paths outside every mapping are left to the default conversion, which
+/// keeps them as they are.
+///
+/// # Example
+///
+/// ```rust
+/// use lamco_clipboard_core::sanitize::PathMapper;
+///
+/// let mapper = PathMapper::new()
+///     .with_mapping(r"Z:\", "/mnt/projects")
+///     .with_mapping(r"\\tsclient\home", "/home/alice");
+///
+/// assert_eq!(mapper.to_windows("/mnt/projects/a/b.txt").as_deref(), Some(r"Z:\a\b.txt"));
+/// assert_eq!(mapper.to_local(r"\\TSCLIENT\Home\notes.md").as_deref(), Some("/home/alice/notes.md"));
+/// assert_eq!(mapper.to_local(r"C:\Windows"), None);
+/// ```
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(
+    feature = "serde",
+    serde(from = "Vec<(String, String)>", into = "Vec<(String, String)>")
+)]
+pub struct PathMapper {
+    /// Windows prefix (backslashes, no trailing separator) and local prefix
+    /// (no trailing slash, empty for `/`)
+    mappings: Vec<(String, String)>,
+}
+
+impl PathMapper {
+    /// Create a mapper without mappings
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Map a Windows drive or UNC share to a local directory
+    pub fn with_mapping(mut self, windows: impl Into<String>, local: impl Into<String>) -> Self {
+        let windows = windows.into().replace('/', "\\");
+        let windows = windows.trim_end_matches('\\').to_string();
+        let local = local.into().trim_end_matches('/').to_string();
+        self.mappings.push((windows, local));
+        self
+    }
+
+    /// Whether no mapping is configured
+    pub fn is_empty(&self) -> bool {
+        self.mappings.is_empty()
+    }
+
+    /// Windows path for a local path, if it is inside a mapped directory
+    pub fn to_windows(&self, local: &str) -> Option<String> {
+        let (windows, rest) = self
+            .mappings
+            .iter()
+            .filter_map(|(windows, prefix)| {
+                let rest = local.strip_prefix(prefix.as_str())?;
+                (rest.is_empty() || rest.starts_with('/')).then_some((windows, prefix.len(), rest))
+            })
+            .max_by_key(|(_, len, _)| *len)
+            .map(|(windows, _, rest)| (windows, rest))?;
+
+        let rest = rest.replace('/', "\\");
+        // A bare drive letter means the current directory on that drive
+        if rest.is_empty() && windows.ends_with(':') {
+            return Some(format!("{windows}\\"));
+        }
+        Some(format!("{windows}{rest}"))
+    }
+
+    /// Local path for a Windows path, if it is on a mapped drive or share
+    pub fn to_local(&self, windows: &str) -> Option<String> {
+        let windows = windows.replace('/', "\\");
+        let (local, rest) = self
+            .mappings
+            .iter()
+            .filter_map(|(prefix, local)| {
+                let head = windows.get(..prefix.len())?;
+                let rest = &windows[prefix.len()..];
+                let matches = head.eq_ignore_ascii_case(prefix) && (rest.is_empty() || rest.starts_with('\\'));
+                matches.then_some((local, prefix.len(), rest))
+            })
+            .max_by_key(|(_, len, _)| *len)
+            .map(|(local, _, rest)| (local, rest))?;
+
+        let rest = rest.trim_end_matches('\\').replace('\\', "/");
+        if local.is_empty() && rest.is_empty() {
+            return Some("/".to_string());
+        }
+        Some(format!("{local}{rest}"))
+    }
+}
+
+/// Mappings as `(windows, local)` pairs, normalized as by [`PathMapper::with_mapping`]
+#[cfg(feature = "serde")]
+impl From<Vec<(String, String)>> for PathMapper {
+    fn from(mappings: Vec<(String, String)>) -> Self {
+        mappings.into_iter().fold(Self::new(), |mapper, (windows, local)| {
+            mapper.with_mapping(windows, local)
+        })
+    }
+}
+
+#[cfg(feature = "serde")]
+impl From<PathMapper> for Vec<(String, String)> {
+    fn from(mapper: PathMapper) -> Self {
+        mapper.mappings
+    }
+}
+
 // =============================================================================
 // Text Sanitization
 // =============================================================================
@@ -454,6 +1126,119 @@ pub fn sanitize_text_for_linux(text: &str) -> String {
     result
 }
 
+// =============================================================================
+// Terminal Paste Policy
+// =============================================================================
+
+/// Bracketed paste start marker (xterm `ESC [ 200 ~`)
+pub const BRACKETED_PASTE_START: &str = "\x1b[200~";
+
+/// Bracketed paste end marker (xterm `ESC [ 201 ~`)
+pub const BRACKETED_PASTE_END: &str = "\x1b[201~";
+
+/// How text is prepared when the paste target is a terminal emulator
+///
+/// A shell executes every newline it receives, so a multi-line paste runs
+/// commands the user never confirmed. Hosts that know the focused window is
+/// a terminal apply this policy to pasted text in either direction.
+#[derive(Debug, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "serde", serde(default))]
+pub struct TerminalPasteConfig {
+    /// Wrap the text in bracketed paste markers (default: true)
+    ///
+    /// Shells with bracketed paste enabled then insert the text without
+    /// executing it. Any end marker inside the text is removed so it cannot
+    /// terminate the bracket early.
+    pub bracketed_paste: bool,
+
+    /// Remove trailing newlines so the last line is not executed (default: true)
+    pub strip_trailing_newlines: bool,
+
+    /// Expand tabs to spaces at this tab width (default: None)
+    ///
+    /// Tabs pasted into a shell trigger completion instead of inserting
+    /// whitespace.
+    pub expand_tabs: Option<usize>,
+}
+
+impl Default for TerminalPasteConfig {
+    fn default() -> Self {
+        Self {
+            bracketed_paste: true,
+            strip_trailing_newlines: true,
+            expand_tabs: None,
+        }
+    }
+}
+
+/// Prepare text for pasting into a terminal emulator.
+///
+/// Line endings are left as they are; combine with
+/// [`sanitize_text_for_linux`] or [`sanitize_text_for_windows`] as needed.
+///
+/// # Example
+///
+/// ```rust
+/// use lamco_clipboard_core::sanitize::{prepare_for_terminal, TerminalPasteConfig};
+///
+/// let config = TerminalPasteConfig {
+///     bracketed_paste: false,
+///     expand_tabs: Some(4),
+///     ..Default::default()
+/// };
+/// assert_eq!(prepare_for_terminal("ls\t-l\n\n", &config), "ls  -l");
+/// ```
+pub fn prepare_for_terminal(text: &str, config: &TerminalPasteConfig) -> String {
+    let mut result = match config.expand_tabs {
+        Some(width) => expand_tabs(text, width),
+        None => text.to_string(),
+    };
+
+    if config.strip_trailing_newlines {
+        let trimmed = result.trim_end_matches(['\r', '\n']).len();
+        result.truncate(trimmed);
+    }
+
+    if config.bracketed_paste {
+        // Remove end markers that would let the content escape the bracket,
+        // including ones that only form after an inner marker is removed
+        while result.contains(BRACKETED_PASTE_END) {
+            result = result.replace(BRACKETED_PASTE_END, "");
+        }
+        result = format!("{}{}{}", BRACKETED_PASTE_START, result, BRACKETED_PASTE_END);
+    }
+
+    result
+}
+
+/// Expand tabs to spaces, aligning to multiples of `width` per line
+fn expand_tabs(text: &str, width: usize) -> String {
+    let width = width.max(1);
+    let mut result = String::with_capacity(text.len());
+    let mut column = 0;
+
+    for c in text.chars() {
+        match c {
+            '\t' => {
+                let spaces = width - column % width;
+                result.extend(std::iter::repeat(' ').take(spaces));
+                column += spaces;
+            }
+            '\n' | '\r' => {
+                result.push(c);
+                column = 0;
+            }
+            _ => {
+                result.push(c);
+                column += 1;
+            }
+        }
+    }
+
+    result
+}
+
 // =============================================================================
 // Tests
 // =============================================================================
@@ -517,6 +1302,177 @@ mod tests {
         assert_eq!(sanitize_filename_for_linux("--help"), "_--help");
     }
 
+    #[test]
+    fn test_filename_policy_normalization() {
+        let decomposed = "Re\u{301}sume\u{301}.pdf";
+        let composed = "R\u{e9}sum\u{e9}.pdf";
+
+        assert_eq!(FilenamePolicy::windows().sanitize(decomposed), composed);
+        assert_eq!(FilenamePolicy::linux().sanitize(decomposed), composed);
+        assert_eq!(
+            FilenamePolicy::linux()
+                .with_unicode_form(UnicodeForm::Nfd)
+                .sanitize(composed),
+            decomposed
+        );
+        assert_eq!(sanitize_filename_for_windows(decomposed), decomposed);
+    }
+
+    #[test]
+    fn test_filename_policy_replacement() {
+        let name = "a<b>|c\t.txt";
+        let windows = FilenamePolicy::windows();
+
+        assert_eq!(windows.sanitize(name), "a_b__c_.txt");
+        assert_eq!(
+            windows
+                .clone()
+                .with_replacement(ReplacementStrategy::Remove)
+                .sanitize(name),
+            "abc.txt"
+        );
+        assert_eq!(
+            windows
+                .clone()
+                .with_replacement(ReplacementStrategy::Char('-'))
+                .sanitize(name),
+            "a-b--c-.txt"
+        );
+        assert_eq!(
+            windows.with_replacement(ReplacementStrategy::Lookalike).sanitize(name),
+            "a\u{ff1c}b\u{ff1e}\u{ff5c}c\u{2409}.txt"
+        );
+        assert_eq!(
+            FilenamePolicy::linux()
+                .with_replacement(ReplacementStrategy::Lookalike)
+                .sanitize("a/b"),
+            "a\u{ff0f}b"
+        );
+
+        // Nothing left to name the file
+        assert_eq!(
+            FilenamePolicy::linux()
+                .with_replacement(ReplacementStrategy::Remove)
+                .sanitize("//"),
+            "_unnamed_"
+        );
+    }
+
+    #[test]
+    fn test_filename_policy_reserved_names() {
+        let policy = FilenamePolicy::windows();
+        assert_eq!(policy.sanitize("COM0"), "_COM0");
+        assert_eq!(policy.sanitize("lpt\u{b9}.txt"), "_lpt\u{b9}.txt");
+        assert_eq!(policy.sanitize("CONIN$"), "_CONIN$");
+        assert_eq!(policy.sanitize("con .txt"), "_con .txt");
+        assert_eq!(policy.sanitize("nul."), "_nul");
+        assert_eq!(policy.sanitize("console.txt"), "console.txt");
+        assert_eq!(policy.sanitize("COM10"), "COM10");
+    }
+
+    #[test]
+    fn test_filename_policy_length_units() {
+        // 200 two-byte characters: fine for Windows, too long in bytes for Linux
+        let name = format!("{}.txt", "\u{e9}".repeat(200));
+        assert_eq!(FilenamePolicy::windows().sanitize(&name), name);
+
+        let linux = FilenamePolicy::linux().sanitize(&name);
+        assert_eq!(linux.len(), 254);
+        assert!(linux.ends_with(".txt"));
+
+        // Truncation never leaves a trailing space before Windows strips it
+        let spaced = format!("{} b", "a".repeat(254));
+        assert_eq!(FilenamePolicy::windows().sanitize(&spaced), "a".repeat(254));
+    }
+
+    #[test]
+    fn test_filename_policy_path_limit() {
+        let policy = FilenamePolicy::windows();
+        let name = format!("{}.docx", "n".repeat(120));
+
+        assert_eq!(policy.sanitize_in(r"C:\Users\me", &name), name);
+
+        let dir = format!(r"C:\{}\", "d".repeat(180));
+        let fitted = policy.sanitize_in(&dir, &name);
+        assert_eq!(dir.len() + fitted.len(), WINDOWS_MAX_PATH - 1);
+        assert!(fitted.ends_with(".docx"));
+
+        // No room left: keep the name and leave it to long-path handling
+        let deep = format!(r"C:\{}", "d".repeat(250));
+        assert_eq!(policy.sanitize_in(&deep, &name), name);
+        assert_eq!(policy.with_max_path_len(None).sanitize_in(&dir, &name), name);
+
+        assert_eq!(
+            windows_long_path(&format!("{deep}/{name}")),
+            format!(r"\\?\{deep}\{name}")
+        );
+        assert_eq!(windows_long_path(&format!("rel\\{deep}")), format!("rel\\{deep}"));
+        assert_eq!(windows_long_path(r"\\?\C:\x"), r"\\?\C:\x");
+    }
+
+    #[test]
+    fn test_filename_map_round_trip() {
+        let mut names = FilenameMap::new(FilenamePolicy::windows());
+
+        assert_eq!(names.sanitize("Notes.txt"), "Notes.txt");
+        assert_eq!(names.sanitize("notes.txt"), "notes (2).txt");
+        assert_eq!(names.sanitize("report?"), "report_");
+        assert_eq!(names.sanitize("report*"), "report_ (2)");
+        assert_eq!(names.sanitize("report?"), "report_");
+        assert_eq!(names.len(), 4);
+
+        assert_eq!(names.desanitize("NOTES (2).TXT"), "notes.txt");
+        assert_eq!(names.desanitize("report_ (2)"), "report*");
+        assert_eq!(names.original("unknown"), None);
+
+        // Numbering stays within the name limit
+        let long = format!("{}?.txt", "x".repeat(260));
+        let first = names.sanitize(&long);
+        let second = names.sanitize(&long.replace('?', "*"));
+        assert_eq!(first.len(), 255);
+        assert_eq!(second.len(), 255);
+        assert!(second.ends_with(" (2).txt"));
+
+        names.clear();
+        assert!(names.is_empty());
+
+        // Linux names are case-sensitive
+        let mut names = FilenameMap::new(FilenamePolicy::linux());
+        assert_eq!(names.sanitize("Notes.txt"), "Notes.txt");
+        assert_eq!(names.sanitize("notes.txt"), "notes.txt");
+    }
+
+    #[test]
+    fn test_path_mapper() {
+        let mapper = PathMapper::new()
+            .with_mapping("Z:/", "/mnt/z/")
+            .with_mapping(r"\\nas\data", "/srv")
+            .with_mapping(r"\\nas\data\archive", "/archive");
+
+        // Whole components only
+        assert_eq!(mapper.to_windows("/mnt/zz/file"), None);
+        assert_eq!(mapper.to_windows("/mnt/z").as_deref(), Some("Z:\\"));
+        assert_eq!(mapper.to_local(r"Z:\").as_deref(), Some("/mnt/z"));
+        assert_eq!(mapper.to_local(r"\\nas\database\x"), None);
+
+        // Longest prefix wins in both directions
+        assert_eq!(
+            mapper.to_local(r"\\nas\data\archive\2019").as_deref(),
+            Some("/archive/2019")
+        );
+        assert_eq!(mapper.to_local(r"\\nas\data\current").as_deref(), Some("/srv/current"));
+        assert_eq!(mapper.to_windows("/srv/a/b").as_deref(), Some(r"\\nas\data\a\b"));
+        assert_eq!(mapper.to_windows("/mnt/z/a").as_deref(), Some(r"Z:\a"));
+
+        // Root directory mapping
+        let root = PathMapper::new().with_mapping("R:", "/");
+        assert_eq!(root.to_windows("/etc/hosts").as_deref(), Some(r"R:\etc\hosts"));
+        assert_eq!(root.to_local("r:/").as_deref(), Some("/"));
+
+        assert!(PathMapper::new().is_empty());
+        assert_eq!(PathMapper::new().to_local(r"C:\x"), None);
+    }
+
     #[test]
     fn test_parse_file_uri() {
         assert_eq!(
@@ -533,10 +1489,69 @@ mod tests {
 
     #[test]
     fn test_percent_decode() {
-        assert_eq!(percent_decode("hello%20world"), "hello world");
-        assert_eq!(percent_decode("file%2Fname"), "file/name");
-        assert_eq!(percent_decode("no-encoding"), "no-encoding");
-        assert_eq!(percent_decode("%"), "%"); // Incomplete
+        assert_eq!(percent_decode_bytes("hello%20world"), b"hello world");
+        assert_eq!(percent_decode_bytes("file%2Fname"), b"file/name");
+        assert_eq!(percent_decode_bytes("no-encoding"), b"no-encoding");
+        assert_eq!(percent_decode_bytes("%"), b"%"); // Incomplete
+        assert_eq!(percent_decode_bytes("%zz%C3%A9"), "%zz\u{e9}".as_bytes());
+    }
+
+    #[test]
+    fn test_file_uri_non_utf8_roundtrip() {
+        // "café menu.txt" as written by a Latin-1 system
+        let uri = "file:///tmp/caf%E9%20menu.txt";
+        let path = parse_file_uri(uri).unwrap();
+
+        #[cfg(unix)]
+        assert_eq!(
+            std::os::unix::ffi::OsStrExt::as_bytes(path.as_os_str()),
+            b"/tmp/caf\xe9 menu.txt"
+        );
+        #[cfg(unix)]
+        assert_eq!(path_to_file_uri(&path), uri);
+
+        assert_eq!(percent_encode_bytes("été #1?".as_bytes()), "%C3%A9t%C3%A9%20%231%3F");
+        assert_eq!(percent_encode_bytes(b"a\xff\xfeb\n"), "a%FF%FEb%0A");
+        assert_eq!(percent_encode_bytes(b"/a-b_c.~!$&'()*+,;=:@"), "/a-b_c.~!$&'()*+,;=:@");
+    }
+
+    #[test]
+    fn test_file_uri_hosts() {
+        assert_eq!(split_file_uri("file:///tmp/x"), Some((None, "/tmp/x")));
+        assert_eq!(split_file_uri("FILE://localhost/tmp/x"), Some((None, "/tmp/x")));
+        assert_eq!(split_file_uri("file:/tmp/x?query#frag"), Some((None, "/tmp/x")));
+        assert_eq!(
+            split_file_uri("file://server/share/x"),
+            Some((Some("server"), "/share/x"))
+        );
+        assert_eq!(split_file_uri("file:tmp/x"), None);
+        assert_eq!(split_file_uri("file://"), None);
+
+        assert_eq!(
+            parse_file_uri("file://localhost/tmp/a%C3%A9"),
+            Some(PathBuf::from("/tmp/aé"))
+        );
+        assert_eq!(parse_file_uri("file://server/share/x"), None);
+        assert_eq!(percent_decode_bytes("%+1%2"), b"%+1%2");
+    }
+
+    #[test]
+    fn test_hdrop_paths() {
+        let cases: &[(&str, &str)] = &[
+            ("file:///C:/Users/J%C3%BCrgen/a%20b.txt", r"C:\Users\Jürgen\a b.txt"),
+            ("file:///D:/", r"D:\"),
+            ("file://server/share/x.txt", r"\\server\share\x.txt"),
+            ("file:///home/user/x.txt", "/home/user/x.txt"),
+        ];
+        for (uri, hdrop) in cases {
+            let (host, path) = split_file_uri(uri).unwrap();
+            let decoded = String::from_utf8(percent_decode_bytes(path)).unwrap();
+            assert_eq!(file_uri_path_to_hdrop(host, &decoded), *hdrop);
+            assert_eq!(hdrop_path_to_file_uri(hdrop.as_bytes()), *uri);
+        }
+
+        assert_eq!(file_uri_path_to_hdrop(None, "/C|/x"), r"C:\x");
+        assert_eq!(file_uri_path_to_hdrop(None, "/CD:/x"), "/CD:/x");
     }
 
     #[test]
@@ -545,4 +1560,53 @@ mod tests {
         assert_eq!(convert_line_endings_to_windows("a\r\nb\nc"), "a\r\nb\r\nc");
         assert_eq!(convert_line_endings_to_unix("a\r\nb\r\nc"), "a\nb\nc");
     }
+
+    #[test]
+    fn test_prepare_for_terminal_default() {
+        let config = TerminalPasteConfig::default();
+        assert_eq!(
+            prepare_for_terminal("echo a\necho b\n", &config),
+            "\x1b[200~echo a\necho b\x1b[201~"
+        );
+    }
+
+    #[test]
+    fn test_prepare_for_terminal_strips_embedded_end_marker() {
+        let config = TerminalPasteConfig::default();
+        let malicious = "safe\x1b[20\x1b[201~1~rm -rf ~\n";
+        assert_eq!(
+            prepare_for_terminal(malicious, &config),
+            "\x1b[200~saferm -rf ~\x1b[201~"
+        );
+    }
+
+    #[test]
+    fn test_prepare_for_terminal_expand_tabs() {
+        let config = TerminalPasteConfig {
+            bracketed_paste: false,
+            strip_trailing_newlines: false,
+            expand_tabs: Some(4),
+        };
+        assert_eq!(
+            prepare_for_terminal("a\tbc\td\r\n\tx\n", &config),
+            "a   bc  d\r\n    x\n"
+        );
+    }
+
+    #[cfg(feature = "serde")]
+    #[test]
+    fn test_serde_policies() {
+        let policy = FilenamePolicy::windows().with_replacement(ReplacementStrategy::Char('-'));
+        let json = serde_json::to_string(&policy).unwrap();
+        assert_eq!(
+            json,
+            r#"{"target":"windows","unicode_form":"nfc","replacement":{"char":"-"},"max_path_len":260}"#
+        );
+        assert_eq!(serde_json::from_str::<FilenamePolicy>(&json).unwrap(), policy);
+
+        // Mappings are normalized on the way in
+        let mapper: PathMapper = serde_json::from_str(r#"[["R:/", "/mnt/r/"]]"#).unwrap();
+        assert_eq!(mapper, PathMapper::new().with_mapping("R:", "/mnt/r"));
+        assert_eq!(serde_json::to_string(&mapper).unwrap(), r#"[["R:","/mnt/r"]]"#);
+    }
 }
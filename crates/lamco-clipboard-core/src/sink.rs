@@ -3,11 +3,13 @@
 //! This trait defines the interface that clipboard backends must implement.
 //! It is protocol-agnostic and uses MIME types for format identification.
 
+use crate::loop_detector::Generation;
 use crate::ClipboardResult;
 use std::future::Future;
 
 /// Information about a file in the clipboard
 #[derive(Debug, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct FileInfo {
     /// File name (without path)
     pub name: String,
@@ -63,6 +65,7 @@ impl FileInfo {
 
 /// A clipboard change notification
 #[derive(Debug, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct ClipboardChange {
     /// MIME types available in the clipboard
     pub mime_types: Vec<String>,
@@ -72,6 +75,10 @@ pub struct ClipboardChange {
 
     /// Content hash for deduplication (optional)
     pub content_hash: Option<String>,
+
+    /// Generation of our own sync that caused this change, if the backend
+    /// can tell (see [`LoopDetector::begin_sync`](crate::LoopDetector::begin_sync))
+    pub generation: Option<Generation>,
 }
 
 impl ClipboardChange {
@@ -81,6 +88,7 @@ impl ClipboardChange {
             mime_types,
             is_primary: false,
             content_hash: None,
+            generation: None,
         }
     }
 
@@ -95,6 +103,12 @@ impl ClipboardChange {
         self.content_hash = Some(hash.into());
         self
     }
+
+    /// Mark the change as caused by our own sync
+    pub fn with_generation(mut self, generation: Generation) -> Self {
+        self.generation = Some(generation);
+        self
+    }
 }
 
 /// Receiver for clipboard change notifications.
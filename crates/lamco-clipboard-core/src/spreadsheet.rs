@@ -0,0 +1,401 @@
+//! Spreadsheet clipboard formats: CSV and Excel's "XML Spreadsheet".
+//!
+//! LibreOffice Calc offers copied cells as `text/csv`; Excel offers them as
+//! the registered "Csv" format and as "XML Spreadsheet" (SpreadsheetML 2003).
+//! Without these, a range copied between the two collapses to tab-separated
+//! plain text and loses quoting, embedded newlines and cell types.
+//!
+//! Both formats are converted through a grid of cell strings. Cells that look
+//! like numbers are written as `Number` cells so Excel keeps them numeric;
+//! everything else is a `String` cell. Formulas and styles are not carried.
+
+use crate::rtf::decode_entities;
+use crate::{ClipboardError, ClipboardResult};
+use std::fmt::Write;
+
+/// Windows registered format name for comma-separated values
+pub const CSV_FORMAT_NAME: &str = "Csv";
+
+/// Windows registered format name for SpreadsheetML 2003
+pub const XML_SPREADSHEET_FORMAT_NAME: &str = "XML Spreadsheet";
+
+/// Linux MIME type for comma-separated values
+pub const CSV_MIME_TYPE: &str = "text/csv";
+
+/// Parse CSV (RFC 4180) into rows of cells
+///
+/// Quoted fields may contain delimiters, doubled quotes and line breaks.
+/// The delimiter is detected from the first line: `,` unless the line only
+/// contains `;` or tabs, as written by Excel in some locales.
+///
+/// # Example
+///
+/// ```
+/// use lamco_clipboard_core::spreadsheet::parse_csv;
+///
+/// let rows = parse_csv("name,note\r\nA,\"says \"\"hi\"\", twice\"\r\n");
+/// assert_eq!(rows, vec![vec!["name", "note"], vec!["A", "says \"hi\", twice"]]);
+/// ```
+pub fn parse_csv(text: &str) -> Vec<Vec<String>> {
+    let delimiter = detect_delimiter(text);
+    let mut rows = Vec::new();
+    let mut row = Vec::new();
+    let mut field = String::new();
+    let mut in_quotes = false;
+    let mut chars = text.chars().peekable();
+
+    while let Some(c) = chars.next() {
+        match c {
+            '"' if in_quotes => {
+                if chars.peek() == Some(&'"') {
+                    chars.next();
+                    field.push('"');
+                } else {
+                    in_quotes = false;
+                }
+            }
+            '"' if field.is_empty() => in_quotes = true,
+            c if in_quotes => field.push(c),
+            c if c == delimiter => row.push(std::mem::take(&mut field)),
+            '\r' | '\n' => {
+                if c == '\r' && chars.peek() == Some(&'\n') {
+                    chars.next();
+                }
+                row.push(std::mem::take(&mut field));
+                rows.push(std::mem::take(&mut row));
+            }
+            c => field.push(c),
+        }
+    }
+
+    if !field.is_empty() || !row.is_empty() {
+        row.push(field);
+        rows.push(row);
+    }
+    rows
+}
+
+/// Write rows of cells as CSV with CRLF line endings
+///
+/// Fields are quoted only when they contain a comma, quote, line break or
+/// surrounding whitespace.
+pub fn write_csv(rows: &[Vec<String>]) -> String {
+    let mut csv = String::new();
+    for row in rows {
+        for (i, cell) in row.iter().enumerate() {
+            if i > 0 {
+                csv.push(',');
+            }
+            let needs_quotes = cell.contains([',', '"', '\r', '\n']) || cell.trim() != cell;
+            if needs_quotes {
+                csv.push('"');
+                csv.push_str(&cell.replace('"', "\"\""));
+                csv.push('"');
+            } else {
+                csv.push_str(cell);
+            }
+        }
+        csv.push_str("\r\n");
+    }
+    csv
+}
+
+/// Build an "XML Spreadsheet" workbook with a single worksheet
+pub fn rows_to_xml_spreadsheet(rows: &[Vec<String>]) -> String {
+    let columns = rows.iter().map(Vec::len).max().unwrap_or(0);
+    let mut xml = String::from(
+        "<?xml version=\"1.0\"?>\r\n\
+         <?mso-application progid=\"Excel.Sheet\"?>\r\n\
+         <Workbook xmlns=\"urn:schemas-microsoft-com:office:spreadsheet\" \
+         xmlns:o=\"urn:schemas-microsoft-com:office:office\" \
+         xmlns:x=\"urn:schemas-microsoft-com:office:excel\" \
+         xmlns:ss=\"urn:schemas-microsoft-com:office:spreadsheet\" \
+         xmlns:html=\"http://www.w3.org/TR/REC-html40\">\r\n\
+         <Worksheet ss:Name=\"Sheet1\">\r\n",
+    );
+    let _ = write!(
+        xml,
+        "<Table ss:ExpandedColumnCount=\"{}\" ss:ExpandedRowCount=\"{}\" x:FullColumns=\"1\" x:FullRows=\"1\">\r\n",
+        columns.max(1),
+        rows.len().max(1)
+    );
+
+    for row in rows {
+        xml.push_str("<Row>");
+        // Empty cells are omitted; the next cell then carries its 1-based column
+        let mut skipped = false;
+        for (column, cell) in row.iter().enumerate() {
+            if cell.is_empty() {
+                skipped = true;
+                continue;
+            }
+            if skipped {
+                let _ = write!(xml, "<Cell ss:Index=\"{}\">", column + 1);
+                skipped = false;
+            } else {
+                xml.push_str("<Cell>");
+            }
+            let ty = if is_number(cell) { "Number" } else { "String" };
+            let _ = write!(xml, "<Data ss:Type=\"{}\">{}</Data></Cell>", ty, escape_xml(cell));
+        }
+        xml.push_str("</Row>\r\n");
+    }
+
+    xml.push_str("</Table>\r\n</Worksheet>\r\n</Workbook>\r\n");
+    xml
+}
+
+/// Read the cells of the first worksheet in an "XML Spreadsheet" workbook
+///
+/// Rich text inside a cell is reduced to its text, and merged cells keep
+/// their value in the first column of the merge.
+pub fn xml_spreadsheet_to_rows(xml: &str) -> ClipboardResult<Vec<Vec<String>>> {
+    let mut rows: Vec<Vec<String>> = Vec::new();
+    let mut found_table = false;
+    let mut in_data = false;
+    let mut data = String::new();
+    let mut column = 0usize;
+    let mut rest = xml;
+
+    while let Some(start) = rest.find('<') {
+        if in_data {
+            data.push_str(&rest[..start]);
+        }
+        let after = &rest[start + 1..];
+        let Some(end) = after.find('>') else {
+            break;
+        };
+        let tag = &after[..end];
+        rest = &after[end + 1..];
+
+        if tag.starts_with('?') || tag.starts_with('!') {
+            continue;
+        }
+        let closing = tag.starts_with('/');
+        let self_closing = tag.ends_with('/');
+        let name = tag
+            .trim_start_matches('/')
+            .split(|c: char| c.is_whitespace() || c == '/')
+            .next()
+            .unwrap_or_default();
+        // Element names may carry a namespace prefix (`ss:Row`)
+        let local = name.rsplit(':').next().unwrap_or_default();
+
+        match (local, closing) {
+            ("Table", false) => found_table = true,
+            ("Worksheet", true) if found_table => break,
+            ("Row", false) if found_table => {
+                if let Some(index) = index_attribute(tag) {
+                    while rows.len() + 1 < index {
+                        rows.push(Vec::new());
+                    }
+                }
+                rows.push(Vec::new());
+                column = 0;
+            }
+            ("Cell", false) if found_table => {
+                if let Some(index) = index_attribute(tag) {
+                    column = column.max(index - 1);
+                }
+                if let Some(row) = rows.last_mut() {
+                    row.resize(row.len().max(column + 1), String::new());
+                }
+                let merged = crate::rtf::attribute(tag, "ss:mergeacross")
+                    .and_then(|n| n.trim().parse::<usize>().ok())
+                    .unwrap_or(0);
+                if self_closing {
+                    column += 1 + merged;
+                } else {
+                    // Remember the span until the cell closes
+                    column += merged;
+                }
+            }
+            ("Cell", true) if found_table => column += 1,
+            ("Data", false) if found_table && !self_closing => {
+                in_data = true;
+                data.clear();
+            }
+            ("Data", true) if in_data => {
+                in_data = false;
+                let text = decode_entities(&data).replace("\r\n", "\n");
+                if let Some(row) = rows.last_mut() {
+                    // The row was sized to end at this cell when it opened
+                    let cell_column = row.len() - 1;
+                    row[cell_column] = text;
+                }
+            }
+            _ => {}
+        }
+    }
+
+    if !found_table {
+        return Err(ClipboardError::FormatConversion(
+            "XML Spreadsheet has no worksheet table".to_string(),
+        ));
+    }
+
+    // Drop trailing empty cells and rows
+    for row in &mut rows {
+        while row.last().is_some_and(String::is_empty) {
+            row.pop();
+        }
+    }
+    while rows.last().is_some_and(Vec::is_empty) {
+        rows.pop();
+    }
+    Ok(rows)
+}
+
+/// Convert CSV to an "XML Spreadsheet" workbook
+///
+/// # Example
+///
+/// ```
+/// use lamco_clipboard_core::spreadsheet::{csv_to_xml_spreadsheet, xml_spreadsheet_to_csv};
+///
+/// let xml = csv_to_xml_spreadsheet("item,qty\r\napple,3\r\n");
+/// assert!(xml.contains("<Data ss:Type=\"Number\">3</Data>"));
+/// assert_eq!(xml_spreadsheet_to_csv(&xml).unwrap(), "item,qty\r\napple,3\r\n");
+/// ```
+pub fn csv_to_xml_spreadsheet(csv: &str) -> String {
+    rows_to_xml_spreadsheet(&parse_csv(csv))
+}
+
+/// Convert an "XML Spreadsheet" workbook to CSV
+pub fn xml_spreadsheet_to_csv(xml: &str) -> ClipboardResult<String> {
+    Ok(write_csv(&xml_spreadsheet_to_rows(xml)?))
+}
+
+/// Pick the delimiter used on the first line
+fn detect_delimiter(text: &str) -> char {
+    let first_line = text.lines().next().unwrap_or_default();
+    if first_line.contains(',') {
+        ','
+    } else if first_line.contains(';') {
+        ';'
+    } else if first_line.contains('\t') {
+        '\t'
+    } else {
+        ','
+    }
+}
+
+/// `ss:Index` of a `Row` or `Cell` start tag (1-based)
+fn index_attribute(tag: &str) -> Option<usize> {
+    crate::rtf::attribute(tag, "ss:index")
+        .and_then(|index| index.trim().parse::<usize>().ok())
+        .filter(|&index| index > 0)
+}
+
+/// Whether Excel should store the cell as a number
+///
+/// Leading zeros and signs are kept as text so codes like `007` or `+44`
+/// survive the round trip.
+fn is_number(cell: &str) -> bool {
+    let digits = cell.strip_prefix('-').unwrap_or(cell);
+    let leading_zero = digits.len() > 1 && digits.starts_with('0') && !digits[1..].starts_with('.');
+    digits.starts_with(|c: char| c.is_ascii_digit())
+        && !leading_zero
+        && digits
+            .chars()
+            .all(|c| c.is_ascii_digit() || matches!(c, '.' | 'e' | 'E' | '-' | '+'))
+        && cell.parse::<f64>().is_ok_and(f64::is_finite)
+}
+
+/// Escape cell text for XML, keeping line breaks as character references
+fn escape_xml(text: &str) -> String {
+    let mut escaped = String::with_capacity(text.len());
+    for c in text.chars() {
+        match c {
+            '&' => escaped.push_str("&amp;"),
+            '<' => escaped.push_str("&lt;"),
+            '>' => escaped.push_str("&gt;"),
+            '"' => escaped.push_str("&quot;"),
+            '\n' => escaped.push_str("&#10;"),
+            '\r' => {}
+            c => escaped.push(c),
+        }
+    }
+    escaped
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_csv_quoting_and_line_breaks() {
+        let rows = parse_csv("a,\"multi\nline\",\"\"\n,x\n");
+        assert_eq!(rows, vec![vec!["a", "multi\nline", ""], vec!["", "x"]]);
+    }
+
+    #[test]
+    fn test_parse_csv_detects_semicolons() {
+        let rows = parse_csv("a;b\r\n1,5;2\r\n");
+        assert_eq!(rows, vec![vec!["a", "b"], vec!["1,5", "2"]]);
+    }
+
+    #[test]
+    fn test_write_csv_quotes_when_needed() {
+        let rows = vec![vec![
+            "plain".to_string(),
+            "a,b".to_string(),
+            " pad".to_string(),
+            "q\"".to_string(),
+        ]];
+        assert_eq!(write_csv(&rows), "plain,\"a,b\",\" pad\",\"q\"\"\"\r\n");
+    }
+
+    #[test]
+    fn test_number_detection() {
+        for number in ["0", "42", "-3.5", "0.25", "1e3"] {
+            assert!(is_number(number), "{number}");
+        }
+        for text in ["007", "+44", "", "1.2.3", "inf", "NaN", "12 apples", " 1"] {
+            assert!(!is_number(text), "{text}");
+        }
+    }
+
+    #[test]
+    fn test_xml_round_trip_with_gaps_and_escaping() {
+        let rows = vec![
+            vec!["a & b".to_string(), String::new(), "line\nbreak".to_string()],
+            vec![],
+            vec![String::new(), "12".to_string()],
+        ];
+        let xml = rows_to_xml_spreadsheet(&rows);
+        assert!(xml.contains("<Cell ss:Index=\"3\"><Data ss:Type=\"String\">line&#10;break</Data>"));
+        assert_eq!(xml_spreadsheet_to_rows(&xml).unwrap(), rows);
+    }
+
+    #[test]
+    fn test_excel_workbook() {
+        // Shape of what Excel puts on the clipboard, trimmed
+        let xml = r#"<?xml version="1.0"?>
+<Workbook xmlns="urn:schemas-microsoft-com:office:spreadsheet" xmlns:ss="urn:schemas-microsoft-com:office:spreadsheet">
+ <Styles><Style ss:ID="Default"/></Styles>
+ <Worksheet ss:Name="Sheet1">
+  <Table ss:ExpandedColumnCount="3" ss:ExpandedRowCount="3">
+   <Row>
+    <Cell ss:MergeAcross="1"><Data ss:Type="String">Title</Data></Cell>
+    <Cell><ss:Data ss:Type="String" xmlns="http://www.w3.org/TR/REC-html40"><B>Bold</B> text</ss:Data></Cell>
+   </Row>
+   <Row ss:Index="3">
+    <Cell ss:StyleID="s1"/>
+    <Cell><Data ss:Type="Number">1.5</Data></Cell>
+   </Row>
+  </Table>
+ </Worksheet>
+ <Worksheet ss:Name="Sheet2"><Table><Row><Cell><Data ss:Type="String">ignored</Data></Cell></Row></Table></Worksheet>
+</Workbook>"#;
+
+        let rows = xml_spreadsheet_to_rows(xml).unwrap();
+        assert_eq!(rows, vec![vec!["Title", "", "Bold text"], vec![], vec!["", "1.5"]]);
+        assert_eq!(xml_spreadsheet_to_csv(xml).unwrap(), "Title,,Bold text\r\n\r\n,1.5\r\n");
+    }
+
+    #[test]
+    fn test_not_a_workbook() {
+        assert!(xml_spreadsheet_to_rows("<html><body>no</body></html>").is_err());
+    }
+}
@@ -0,0 +1,551 @@
+//! Staging directory for received files.
+//!
+//! A file paste delivers each file whole, but writing it straight to its
+//! final destination leaves half a folder behind when the transfer fails,
+//! and nothing stops a peer from filling the disk. A [`StagingArea`] is a
+//! private directory per session where received files are written first:
+//!
+//! - Writes count against a quota and fail with
+//!   [`ClipboardError::DataSizeExceeded`] once it is used up.
+//! - Each file is written under a temporary name and renamed into place, so
+//!   a crash never leaves a truncated file under the real name.
+//! - [`FsyncPolicy`] chooses how much durability is paid for.
+//! - Long runs of zeros are left as holes rather than written, so VM disk
+//!   images and database files stay sparse instead of inflating to full
+//!   size on disk.
+//! - [`commit`](StagingArea::commit) moves everything to the destination;
+//!   dropping the area without committing deletes it, so a failed or
+//!   abandoned session cleans up after itself.
+
+use crate::{ClipboardError, ClipboardResult};
+use std::collections::BTreeMap;
+use std::fmt;
+use std::fs::{self, File};
+use std::io::{ErrorKind, Seek, SeekFrom, Write};
+use std::path::{Component, Path, PathBuf};
+use std::str::FromStr;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Mutex, PoisonError};
+
+/// Default bytes a session may stage: 4 GiB
+pub const DEFAULT_STAGING_QUOTA_BYTES: u64 = 4 * 1024 * 1024 * 1024;
+
+/// Default shortest run of zeros left as a hole: 64 KiB
+pub const DEFAULT_SPARSE_THRESHOLD_BYTES: u64 = 64 * 1024;
+
+/// Granularity of holes; filesystems allocate whole blocks anyway
+const SPARSE_BLOCK_BYTES: usize = 4096;
+
+/// Directory under the system temporary directory used when none is configured
+const DEFAULT_STAGING_DIRECTORY: &str = "lamco-clipboard";
+
+/// When staged files are flushed to disk
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "serde", serde(rename_all = "snake_case"))]
+pub enum FsyncPolicy {
+    /// Leave flushing to the OS
+    #[default]
+    Never,
+
+    /// Flush each file before renaming it into place
+    Files,
+
+    /// Flush each file and the directory holding it, so the rename itself
+    /// survives a power loss
+    Directories,
+}
+
+impl FsyncPolicy {
+    /// Every policy, in configuration order
+    pub const ALL: [Self; 3] = [Self::Never, Self::Files, Self::Directories];
+
+    /// Name used in configuration files
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Self::Never => "never",
+            Self::Files => "files",
+            Self::Directories => "directories",
+        }
+    }
+}
+
+impl fmt::Display for FsyncPolicy {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+impl FromStr for FsyncPolicy {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Self::ALL
+            .into_iter()
+            .find(|policy| policy.as_str().eq_ignore_ascii_case(s.trim()))
+            .ok_or_else(|| format!("unknown fsync policy '{}'", s))
+    }
+}
+
+/// Configuration for [`StagingArea`]
+#[derive(Debug, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "serde", serde(default))]
+pub struct StagingConfig {
+    /// Directory session directories are created in (default: None, a
+    /// `lamco-clipboard` directory under the system temporary directory)
+    pub directory: Option<PathBuf>,
+
+    /// Bytes a session may stage (default: 4 GiB)
+    pub quota_bytes: u64,
+
+    /// When staged files are flushed to disk (default: never)
+    pub fsync: FsyncPolicy,
+
+    /// Shortest run of zeros left as a hole instead of written (default:
+    /// 64 KiB, 0 writes every byte)
+    pub sparse_threshold_bytes: u64,
+}
+
+impl Default for StagingConfig {
+    fn default() -> Self {
+        Self {
+            directory: None,
+            quota_bytes: DEFAULT_STAGING_QUOTA_BYTES,
+            fsync: FsyncPolicy::Never,
+            sparse_threshold_bytes: DEFAULT_SPARSE_THRESHOLD_BYTES,
+        }
+    }
+}
+
+impl StagingConfig {
+    /// Directory session directories are created in
+    pub fn base_directory(&self) -> PathBuf {
+        self.directory
+            .clone()
+            .unwrap_or_else(|| std::env::temp_dir().join(DEFAULT_STAGING_DIRECTORY))
+    }
+}
+
+/// Private directory for the files received in one session.
+///
+/// Paths are relative, with `/` separators, as in
+/// [`FileTransferEvent::FileReceived`](crate::file_transfer::FileTransferEvent::FileReceived).
+/// Safe to share between tasks; writes to different paths may run
+/// concurrently.
+///
+/// # Example
+///
+/// ```
+/// use lamco_clipboard_core::{StagingArea, StagingConfig};
+///
+/// let destination = std::env::temp_dir().join(format!("staging-doc-{}", std::process::id()));
+/// let staging = StagingArea::create(&StagingConfig::default())?;
+/// staging.stage("docs/notes.txt", b"hello")?;
+/// assert_eq!(staging.used(), 5);
+///
+/// let committed = staging.commit(&destination)?;
+/// assert_eq!(committed, vec![destination.join("docs/notes.txt")]);
+/// # std::fs::remove_dir_all(&destination)?;
+/// # Ok::<(), lamco_clipboard_core::ClipboardError>(())
+/// ```
+#[derive(Debug)]
+pub struct StagingArea {
+    root: PathBuf,
+    quota: u64,
+    fsync: FsyncPolicy,
+    sparse_threshold: u64,
+    used: AtomicU64,
+    /// Staged files and their sizes
+    files: Mutex<BTreeMap<String, u64>>,
+    /// Cleared once the files are committed or kept
+    cleanup: bool,
+}
+
+impl StagingArea {
+    /// Create a new session directory under the configured base directory
+    pub fn create(config: &StagingConfig) -> ClipboardResult<Self> {
+        static SESSION: AtomicU64 = AtomicU64::new(0);
+
+        let base = config.base_directory();
+        fs::create_dir_all(&base)?;
+        let root = loop {
+            let session = SESSION.fetch_add(1, Ordering::Relaxed);
+            let root = base.join(format!("session-{}-{}", std::process::id(), session));
+            match fs::create_dir(&root) {
+                Ok(()) => break root,
+                // Left behind by an earlier process with the same PID
+                Err(e) if e.kind() == ErrorKind::AlreadyExists => continue,
+                Err(e) => return Err(e.into()),
+            }
+        };
+        tracing::debug!("Staging received files in {:?}", root);
+
+        Ok(Self {
+            root,
+            quota: config.quota_bytes,
+            fsync: config.fsync,
+            sparse_threshold: config.sparse_threshold_bytes,
+            used: AtomicU64::new(0),
+            files: Mutex::new(BTreeMap::new()),
+            cleanup: true,
+        })
+    }
+
+    /// Session directory
+    pub fn path(&self) -> &Path {
+        &self.root
+    }
+
+    /// Bytes the session may stage
+    pub fn quota(&self) -> u64 {
+        self.quota
+    }
+
+    /// Bytes staged so far
+    pub fn used(&self) -> u64 {
+        self.used.load(Ordering::Relaxed)
+    }
+
+    /// Relative paths of the staged files
+    pub fn files(&self) -> Vec<String> {
+        self.staged().keys().cloned().collect()
+    }
+
+    /// Write a received file into the staging area, returning where it is
+    ///
+    /// Staging a path again replaces the earlier file.
+    pub fn stage(&self, relative: &str, data: &[u8]) -> ClipboardResult<PathBuf> {
+        let target = self.resolve(relative)?;
+        self.reserve(data.len() as u64)?;
+
+        let result = self.write(&target, data);
+        let replaced = match &result {
+            Ok(()) => self.staged().insert(relative.to_string(), data.len() as u64),
+            Err(_) => Some(data.len() as u64),
+        };
+        if let Some(size) = replaced {
+            self.used.fetch_sub(size, Ordering::Relaxed);
+        }
+        result.map(|()| target)
+    }
+
+    /// Delete a staged file, returning its quota
+    pub fn remove(&self, relative: &str) -> ClipboardResult<()> {
+        let target = self.resolve(relative)?;
+        let Some(size) = self.staged().remove(relative) else {
+            return Err(ClipboardError::FileNotFound(relative.to_string()));
+        };
+        self.used.fetch_sub(size, Ordering::Relaxed);
+        fs::remove_file(target)?;
+        Ok(())
+    }
+
+    /// Move every staged file to `destination`, keeping relative paths
+    ///
+    /// Returns the final paths. The session directory is removed afterwards;
+    /// if a move fails, the files not yet moved are deleted with it.
+    pub fn commit(self, destination: &Path) -> ClipboardResult<Vec<PathBuf>> {
+        let files = std::mem::take(&mut *self.staged());
+        let mut committed = Vec::with_capacity(files.len());
+        for relative in files.into_keys() {
+            let from = self.resolve(&relative)?;
+            let to = destination.join(&relative);
+            if let Some(parent) = to.parent() {
+                fs::create_dir_all(parent)?;
+            }
+            if fs::rename(&from, &to).is_err() {
+                // Across filesystems
+                fs::copy(&from, &to)?;
+                fs::remove_file(&from)?;
+            }
+            if self.fsync == FsyncPolicy::Directories {
+                sync_parent(&to)?;
+            }
+            committed.push(to);
+        }
+        Ok(committed)
+    }
+
+    /// Keep the session directory and its files, returning its path
+    pub fn keep(mut self) -> PathBuf {
+        self.cleanup = false;
+        self.root.clone()
+    }
+
+    fn staged(&self) -> std::sync::MutexGuard<'_, BTreeMap<String, u64>> {
+        self.files.lock().unwrap_or_else(PoisonError::into_inner)
+    }
+
+    /// Path of `relative` inside the session directory
+    fn resolve(&self, relative: &str) -> ClipboardResult<PathBuf> {
+        resolve_relative(&self.root, relative, "the staging area")
+    }
+
+    fn reserve(&self, bytes: u64) -> ClipboardResult<()> {
+        self.used
+            .fetch_update(Ordering::Relaxed, Ordering::Relaxed, |used| {
+                used.checked_add(bytes).filter(|total| *total <= self.quota)
+            })
+            .map(|_| ())
+            .map_err(|used| ClipboardError::DataSizeExceeded {
+                actual: usize::try_from(used.saturating_add(bytes)).unwrap_or(usize::MAX),
+                max: usize::try_from(self.quota).unwrap_or(usize::MAX),
+            })
+    }
+
+    fn write(&self, target: &Path, data: &[u8]) -> ClipboardResult<()> {
+        let partial = write_partial(target, data, self.fsync, self.sparse_threshold)?;
+        if let Err(e) = fs::rename(&partial, target) {
+            let _ = fs::remove_file(&partial);
+            return Err(e.into());
+        }
+        if self.fsync == FsyncPolicy::Directories {
+            sync_parent(target)?;
+        }
+        Ok(())
+    }
+}
+
+/// Path of `relative` inside `root`, refusing absolute paths and `..`
+pub(crate) fn resolve_relative(root: &Path, relative: &str, area: &str) -> ClipboardResult<PathBuf> {
+    let path = Path::new(relative);
+    let escapes = path
+        .components()
+        .any(|component| !matches!(component, Component::Normal(_)));
+    if relative.is_empty() || escapes {
+        return Err(ClipboardError::InvalidState(format!(
+            "{} is not a relative path inside {}",
+            relative, area
+        )));
+    }
+    Ok(root.join(path))
+}
+
+/// Write `data` to a `.partial` file next to `target`, creating its
+/// directory, and return the partial file's path for renaming into place
+///
+/// The partial file is removed again if writing fails.
+pub(crate) fn write_partial(
+    target: &Path,
+    data: &[u8],
+    fsync: FsyncPolicy,
+    sparse_threshold: u64,
+) -> ClipboardResult<PathBuf> {
+    if let Some(parent) = target.parent() {
+        fs::create_dir_all(parent)?;
+    }
+    let mut partial = target.as_os_str().to_owned();
+    partial.push(".partial");
+    let partial = PathBuf::from(partial);
+
+    let written = (|| -> ClipboardResult<()> {
+        let mut file = File::create(&partial)?;
+        let holes = write_sparse(&mut file, data, sparse_threshold)?;
+        if holes > 0 {
+            tracing::trace!("Left {} zero bytes of {:?} as holes", holes, target);
+        }
+        if fsync != FsyncPolicy::Never {
+            file.sync_all()?;
+        }
+        Ok(())
+    })();
+    match written {
+        Ok(()) => Ok(partial),
+        Err(e) => {
+            let _ = fs::remove_file(&partial);
+            Err(e)
+        }
+    }
+}
+
+impl Drop for StagingArea {
+    fn drop(&mut self) {
+        if !self.cleanup {
+            return;
+        }
+        match fs::remove_dir_all(&self.root) {
+            Ok(()) => tracing::trace!("Removed staging directory {:?}", self.root),
+            Err(e) if e.kind() == ErrorKind::NotFound => {}
+            Err(e) => tracing::warn!("Failed to remove staging directory {:?}: {}", self.root, e),
+        }
+    }
+}
+
+/// Write `data` to the start of a new file, seeking over runs of zeros of
+/// at least `threshold` bytes instead of writing them
+///
+/// Only whole 4 KiB blocks become holes. The file ends up `data.len()`
+/// bytes long either way; on filesystems without sparse files the holes
+/// read back as zeros all the same. Returns the bytes skipped. A
+/// `threshold` of 0 writes everything.
+///
+/// ```
+/// use lamco_clipboard_core::write_sparse;
+///
+/// let path = std::env::temp_dir().join(format!("sparse-doc-{}", std::process::id()));
+/// let mut data = vec![0u8; 1 << 20];
+/// data[..5].copy_from_slice(b"disk!");
+///
+/// let mut file = std::fs::File::create(&path)?;
+/// assert_eq!(write_sparse(&mut file, &data, 64 * 1024)?, (1 << 20) - 4096);
+/// assert_eq!(std::fs::read(&path)?, data);
+/// # std::fs::remove_file(&path)?;
+/// # Ok::<(), lamco_clipboard_core::ClipboardError>(())
+/// ```
+pub fn write_sparse(file: &mut File, data: &[u8], threshold: u64) -> ClipboardResult<u64> {
+    if threshold == 0 {
+        file.write_all(data)?;
+        return Ok(0);
+    }
+
+    let mut pending = 0;
+    let mut holes = 0;
+    let mut zeros_from = None;
+    let blocks = data.chunks(SPARSE_BLOCK_BYTES).enumerate();
+    for (offset, block) in blocks
+        .map(|(i, block)| (i * SPARSE_BLOCK_BYTES, Some(block)))
+        .chain([(data.len(), None)])
+    {
+        let zero = block.is_some_and(|block| block.len() == SPARSE_BLOCK_BYTES && block.iter().all(|b| *b == 0));
+        if zero {
+            zeros_from.get_or_insert(offset);
+            continue;
+        }
+        if let Some(start) = zeros_from.take() {
+            if (offset - start) as u64 >= threshold {
+                file.write_all(&data[pending..start])?;
+                file.seek(SeekFrom::Start(offset as u64))?;
+                holes += (offset - start) as u64;
+                pending = offset;
+            }
+        }
+    }
+    file.write_all(&data[pending..])?;
+    // A trailing hole is only there once the length says so
+    file.set_len(data.len() as u64)?;
+    Ok(holes)
+}
+
+/// Flush the directory entry of `path`
+pub(crate) fn sync_parent(path: &Path) -> ClipboardResult<()> {
+    if let Some(parent) = path.parent() {
+        // Directories cannot be opened for syncing on every platform
+        if let Ok(directory) = File::open(parent) {
+            directory.sync_all()?;
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn config(name: &str) -> StagingConfig {
+        StagingConfig {
+            directory: Some(std::env::temp_dir().join(format!("lamco-staging-{}-{}", name, std::process::id()))),
+            quota_bytes: 10,
+            fsync: FsyncPolicy::Directories,
+            sparse_threshold_bytes: DEFAULT_SPARSE_THRESHOLD_BYTES,
+        }
+    }
+
+    #[test]
+    fn test_quota_and_cleanup() {
+        let config = config("quota");
+        let staging = StagingArea::create(&config).unwrap();
+        let root = staging.path().to_path_buf();
+
+        let path = staging.stage("a/one.txt", b"12345").unwrap();
+        assert_eq!(fs::read(&path).unwrap(), b"12345");
+        // Replacing a file only counts the new size
+        staging.stage("a/one.txt", b"1234").unwrap();
+        staging.stage("two.txt", b"123456").unwrap();
+        assert_eq!(staging.used(), 10);
+
+        let result = staging.stage("three.txt", b"1");
+        assert!(matches!(
+            result,
+            Err(ClipboardError::DataSizeExceeded { actual: 11, max: 10 })
+        ));
+        assert!(!root.join("three.txt").exists());
+        assert!(!root.join("three.txt.partial").exists());
+
+        staging.remove("two.txt").unwrap();
+        assert_eq!(staging.used(), 4);
+        assert!(staging.remove("two.txt").is_err());
+        assert!(staging.stage("../escape.txt", b"").is_err());
+        assert!(staging.stage("/etc/passwd", b"").is_err());
+        assert_eq!(staging.files(), vec!["a/one.txt"]);
+
+        // Dropped without committing, as when the session fails
+        drop(staging);
+        assert!(!root.exists());
+        fs::remove_dir_all(config.base_directory()).unwrap();
+    }
+
+    #[test]
+    fn test_commit_and_keep() {
+        let config = config("commit");
+        let destination = config.base_directory().join("destination");
+
+        let staging = StagingArea::create(&config).unwrap();
+        let root = staging.path().to_path_buf();
+        staging.stage("docs/a.txt", b"a").unwrap();
+        staging.stage("b.txt", b"b").unwrap();
+        let committed = staging.commit(&destination).unwrap();
+        assert_eq!(
+            committed,
+            vec![destination.join("b.txt"), destination.join("docs/a.txt")]
+        );
+        assert_eq!(fs::read(destination.join("docs/a.txt")).unwrap(), b"a");
+        assert!(!root.exists());
+
+        let staging = StagingArea::create(&config).unwrap();
+        staging.stage("kept.txt", b"k").unwrap();
+        let kept = staging.keep();
+        assert!(kept.join("kept.txt").exists());
+
+        fs::remove_dir_all(config.base_directory()).unwrap();
+    }
+
+    #[test]
+    fn test_sparse_files() {
+        let config = StagingConfig {
+            quota_bytes: 1 << 20,
+            ..config("sparse")
+        };
+        let staging = StagingArea::create(&config).unwrap();
+
+        // Header, a hole, a short zero run that is written, data, then a
+        // trailing hole
+        let mut image = vec![0u8; 512 * 1024];
+        image[..10].copy_from_slice(b"disk image");
+        image[300 * 1024] = 1;
+        image[310 * 1024] = 2;
+        let path = staging.stage("disk.img", &image).unwrap();
+        assert_eq!(fs::read(&path).unwrap(), image);
+
+        let mut file = File::create(config.base_directory().join("probe")).unwrap();
+        let holes = write_sparse(&mut file, &image, DEFAULT_SPARSE_THRESHOLD_BYTES).unwrap();
+        // Blocks 1 to 74 and 78 to 127 are holes; block 76 alone is too short
+        assert_eq!(holes, (74 + 50) * 4096);
+        let mut file = File::create(config.base_directory().join("probe")).unwrap();
+        assert_eq!(write_sparse(&mut file, &image, 0).unwrap(), 0);
+        assert_eq!(fs::read(config.base_directory().join("probe")).unwrap(), image);
+        let mut file = File::create(config.base_directory().join("empty")).unwrap();
+        assert_eq!(write_sparse(&mut file, &[], 4096).unwrap(), 0);
+
+        drop(staging);
+        fs::remove_dir_all(config.base_directory()).unwrap();
+    }
+
+    #[test]
+    fn test_fsync_policy_names() {
+        for policy in FsyncPolicy::ALL {
+            assert_eq!(policy.as_str().parse::<FsyncPolicy>().unwrap(), policy);
+        }
+        assert!("sometimes".parse::<FsyncPolicy>().is_err());
+    }
+}
@@ -0,0 +1,581 @@
+//! Incremental conversion for large payloads.
+//!
+//! The [`FormatConverter`] methods take the whole source and return the whole
+//! result, so a 200 MB paste briefly needs the source, the converted copy and
+//! the chunks cut from it for transfer. The encoders here convert one input
+//! chunk at a time instead, so they work directly on `Bytes` chunks from a
+//! portal or network read:
+//!
+//! - [`UnicodeEncoder`] - UTF-8 text to CF_UNICODETEXT
+//! - [`CfHtmlWrapper`] - HTML fragment to CF_HTML
+//!
+//! With the `tokio` feature, [`FormatConverter::text_to_unicode_stream`] and
+//! [`FormatConverter::cf_html_wrap_stream`] drive them from an `AsyncRead`
+//! and hand out chunks sized for a [`TransferEngine`](crate::TransferEngine).
+//!
+//! With the `image` feature, [`FormatConverter::dib_to_png_stream`],
+//! [`FormatConverter::dibv5_to_png_stream`] and
+//! [`FormatConverter::dib_to_jpeg_stream`] hand out encoder output in the
+//! same chunks while the image is still being encoded, instead of building
+//! the whole PNG or JPEG first.
+//!
+//! # Example
+//!
+//! ```
+//! use lamco_clipboard_core::stream::UnicodeEncoder;
+//! use lamco_clipboard_core::FormatConverter;
+//!
+//! let text = "Grüße";
+//! let (first, second) = text.as_bytes().split_at(3); // splits the ü
+//!
+//! let mut encoder = UnicodeEncoder::new(1024);
+//! let mut utf16 = encoder.push(first).unwrap();
+//! utf16.extend(encoder.push(second).unwrap());
+//! utf16.extend(encoder.finish().unwrap());
+//!
+//! assert_eq!(utf16, FormatConverter::new().text_to_unicode(text).unwrap());
+//! ```
+
+use crate::formats::{cf_html_header, CF_HTML_PREFIX, CF_HTML_SUFFIX};
+#[cfg(any(feature = "tokio", feature = "image"))]
+use crate::{formats::FormatConverter, transfer::TransferEngine};
+use crate::{ClipboardError, ClipboardResult};
+
+/// Incremental UTF-8 to UTF-16LE encoder for CF_UNICODETEXT
+///
+/// Chunks may split multi-byte characters anywhere; the incomplete tail is
+/// held until the next chunk.
+#[derive(Debug)]
+pub struct UnicodeEncoder {
+    utf8: Utf8Splitter,
+}
+
+impl UnicodeEncoder {
+    /// Create an encoder accepting at most `max_size` bytes of text
+    pub fn new(max_size: usize) -> Self {
+        Self {
+            utf8: Utf8Splitter::new(max_size),
+        }
+    }
+
+    /// Encode the next chunk of text
+    pub fn push(&mut self, chunk: &[u8]) -> ClipboardResult<Vec<u8>> {
+        let text = self.utf8.push(chunk)?;
+        Ok(text.encode_utf16().flat_map(|c| c.to_le_bytes()).collect())
+    }
+
+    /// Finish the text, returning the null terminator
+    ///
+    /// Fails if the input ended inside a multi-byte character.
+    pub fn finish(self) -> ClipboardResult<Vec<u8>> {
+        self.utf8.finish()?;
+        Ok(vec![0, 0])
+    }
+}
+
+/// Incremental CF_HTML wrapper for an HTML fragment of known length
+///
+/// The CF_HTML header holds the byte offsets of the fragment, so the length
+/// has to be known before the first chunk goes out; it is usually the size of
+/// the source file or the portal transfer.
+#[derive(Debug)]
+pub struct CfHtmlWrapper {
+    html_len: usize,
+    utf8: Utf8Splitter,
+    header: Option<String>,
+}
+
+impl CfHtmlWrapper {
+    /// Create a wrapper for a fragment of exactly `html_len` bytes
+    pub fn new(html_len: usize) -> Self {
+        Self {
+            html_len,
+            utf8: Utf8Splitter::new(html_len),
+            header: Some(cf_html_header(html_len)),
+        }
+    }
+
+    /// Size of the complete CF_HTML document, for announcing the transfer
+    pub fn total_len(&self) -> usize {
+        cf_html_header(self.html_len).len() + CF_HTML_PREFIX.len() + self.html_len + CF_HTML_SUFFIX.len()
+    }
+
+    /// Wrap the next chunk of the fragment; the first call also returns the header
+    pub fn push(&mut self, chunk: &[u8]) -> ClipboardResult<Vec<u8>> {
+        let html = self.utf8.push(chunk)?;
+
+        let mut out = Vec::with_capacity(html.len());
+        if let Some(header) = self.header.take() {
+            out.extend_from_slice(header.as_bytes());
+            out.extend_from_slice(CF_HTML_PREFIX.as_bytes());
+        }
+        out.extend_from_slice(html.as_bytes());
+        Ok(out)
+    }
+
+    /// Finish the document, returning whatever is left to send
+    ///
+    /// Fails if the fragment was shorter than announced.
+    pub fn finish(mut self) -> ClipboardResult<Vec<u8>> {
+        let mut out = self.push(&[])?;
+        let received = self.utf8.finish()?;
+        if received != self.html_len {
+            return Err(ClipboardError::FormatConversion(format!(
+                "HTML fragment is {} bytes, announced {}",
+                received, self.html_len
+            )));
+        }
+
+        out.extend_from_slice(CF_HTML_SUFFIX.as_bytes());
+        Ok(out)
+    }
+}
+
+/// Splits a UTF-8 byte stream at character boundaries
+#[derive(Debug)]
+struct Utf8Splitter {
+    pending: Vec<u8>,
+    received: usize,
+    max_size: usize,
+}
+
+impl Utf8Splitter {
+    fn new(max_size: usize) -> Self {
+        Self {
+            pending: Vec::new(),
+            received: 0,
+            max_size,
+        }
+    }
+
+    /// Complete characters up to the end of `chunk`
+    fn push(&mut self, chunk: &[u8]) -> ClipboardResult<String> {
+        self.received += chunk.len();
+        if self.received > self.max_size {
+            return Err(ClipboardError::DataSizeExceeded {
+                actual: self.received,
+                max: self.max_size,
+            });
+        }
+
+        let mut bytes = std::mem::take(&mut self.pending);
+        bytes.extend_from_slice(chunk);
+        match String::from_utf8(bytes) {
+            Ok(text) => Ok(text),
+            // Only an incomplete trailing sequence is carried over
+            Err(e) if e.utf8_error().error_len().is_none() => {
+                let valid = e.utf8_error().valid_up_to();
+                let mut bytes = e.into_bytes();
+                self.pending = bytes.split_off(valid);
+                String::from_utf8(bytes).map_err(|_| ClipboardError::InvalidUtf8)
+            }
+            Err(_) => Err(ClipboardError::InvalidUtf8),
+        }
+    }
+
+    /// Total bytes received, failing if a character was left incomplete
+    fn finish(&self) -> ClipboardResult<usize> {
+        if self.pending.is_empty() {
+            Ok(self.received)
+        } else {
+            Err(ClipboardError::InvalidUtf8)
+        }
+    }
+}
+
+/// Cuts converted output into transfer-sized chunks
+#[cfg(any(feature = "tokio", feature = "image"))]
+struct Chunker<F> {
+    chunk_size: usize,
+    max_size: usize,
+    buf: Vec<u8>,
+    sent: u64,
+    send: F,
+    /// Error behind the last failed `io::Write` call, returned in its place
+    #[cfg(feature = "image")]
+    failed: Option<ClipboardError>,
+}
+
+#[cfg(any(feature = "tokio", feature = "image"))]
+impl<F: FnMut(Vec<u8>) -> ClipboardResult<()>> Chunker<F> {
+    fn new(engine: &TransferEngine, send: F) -> Self {
+        // A zero chunk size would never fill a chunk
+        let chunk_size = engine.chunk_size().max(1);
+        Self {
+            chunk_size,
+            max_size: engine.max_size(),
+            buf: Vec::with_capacity(chunk_size),
+            sent: 0,
+            send,
+            #[cfg(feature = "image")]
+            failed: None,
+        }
+    }
+
+    fn push(&mut self, mut data: &[u8]) -> ClipboardResult<()> {
+        let total = self.sent as usize + self.buf.len() + data.len();
+        if total > self.max_size {
+            return Err(ClipboardError::DataSizeExceeded {
+                actual: total,
+                max: self.max_size,
+            });
+        }
+
+        while !data.is_empty() {
+            let take = (self.chunk_size - self.buf.len()).min(data.len());
+            self.buf.extend_from_slice(&data[..take]);
+            data = &data[take..];
+            if self.buf.len() == self.chunk_size {
+                self.flush()?;
+            }
+        }
+        Ok(())
+    }
+
+    fn flush(&mut self) -> ClipboardResult<()> {
+        if self.buf.is_empty() {
+            return Ok(());
+        }
+        let chunk = std::mem::replace(&mut self.buf, Vec::with_capacity(self.chunk_size));
+        self.sent += chunk.len() as u64;
+        (self.send)(chunk)
+    }
+
+    fn finish(mut self) -> ClipboardResult<u64> {
+        self.flush()?;
+        Ok(self.sent)
+    }
+}
+
+/// Lets encoders write straight into transfer chunks
+#[cfg(feature = "image")]
+impl<F: FnMut(Vec<u8>) -> ClipboardResult<()>> std::io::Write for Chunker<F> {
+    fn write(&mut self, data: &[u8]) -> std::io::Result<usize> {
+        self.push(data).map_err(|e| {
+            let io_error = std::io::Error::other(e.to_string());
+            self.failed = Some(e);
+            io_error
+        })?;
+        Ok(data.len())
+    }
+
+    /// Chunks go out once full; the last one is sent by `finish`
+    fn flush(&mut self) -> std::io::Result<()> {
+        Ok(())
+    }
+}
+
+#[cfg(feature = "image")]
+impl FormatConverter {
+    /// Encode a DIB as PNG, handing the output to `send` in transfer chunks
+    ///
+    /// Chunks of the engine's `chunk_size` go out while the encoder is still
+    /// running, so the PNG is never held in full. Output is limited to the
+    /// engine's `max_size`; [`image_limits`](Self::image_limits) and
+    /// [`image_encode`](Self::image_encode) apply as for
+    /// [`convert`](Self::convert), but the conversion cache is bypassed.
+    /// Returns the number of bytes sent.
+    pub fn dib_to_png_stream<F>(&self, dib_data: &[u8], engine: &TransferEngine, send: F) -> ClipboardResult<u64>
+    where
+        F: FnMut(Vec<u8>) -> ClipboardResult<()>,
+    {
+        encode_chunked(engine, send, |chunker| {
+            crate::image::write_dib_as_png(dib_data, chunker, &self.image_limits, &self.image_encode)
+        })
+    }
+
+    /// Encode a DIBV5 as PNG, keeping its ICC profile, in transfer chunks
+    ///
+    /// As [`dib_to_png_stream`](Self::dib_to_png_stream).
+    pub fn dibv5_to_png_stream<F>(&self, dibv5_data: &[u8], engine: &TransferEngine, send: F) -> ClipboardResult<u64>
+    where
+        F: FnMut(Vec<u8>) -> ClipboardResult<()>,
+    {
+        encode_chunked(engine, send, |chunker| {
+            crate::image::write_dibv5_as_png(dibv5_data, chunker, &self.image_limits, &self.image_encode)
+        })
+    }
+
+    /// Encode a DIB as JPEG in transfer chunks
+    ///
+    /// As [`dib_to_png_stream`](Self::dib_to_png_stream).
+    pub fn dib_to_jpeg_stream<F>(&self, dib_data: &[u8], engine: &TransferEngine, send: F) -> ClipboardResult<u64>
+    where
+        F: FnMut(Vec<u8>) -> ClipboardResult<()>,
+    {
+        encode_chunked(engine, send, |chunker| {
+            crate::image::write_dib_as_jpeg(dib_data, chunker, &self.image_encode)
+        })
+    }
+}
+
+/// Run an encoder writing into a [`Chunker`], reporting the chunker's own
+/// error (such as an exceeded size limit) rather than the encoder's I/O error
+#[cfg(feature = "image")]
+fn encode_chunked<F, E>(engine: &TransferEngine, send: F, encode: E) -> ClipboardResult<u64>
+where
+    F: FnMut(Vec<u8>) -> ClipboardResult<()>,
+    E: FnOnce(&mut Chunker<F>) -> ClipboardResult<()>,
+{
+    let mut chunker = Chunker::new(engine, send);
+    if let Err(e) = encode(&mut chunker) {
+        return Err(chunker.failed.take().unwrap_or(e));
+    }
+    chunker.finish()
+}
+
+#[cfg(feature = "tokio")]
+impl FormatConverter {
+    /// Stream UTF-8 text from `reader` as CF_UNICODETEXT chunks
+    ///
+    /// Input is read and output is handed to `send` in chunks of the engine's
+    /// `chunk_size` (the last one may be shorter), so only about two chunks are
+    /// held at once. Input is limited to `max_size` like
+    /// [`text_to_unicode`](Self::text_to_unicode), output to the engine's
+    /// `max_size`. Returns the number of bytes sent.
+    ///
+    /// The text is sent as read; the outgoing
+    /// [`text_normalization`](Self::text_normalization) policy is not applied.
+    pub async fn text_to_unicode_stream<R, F>(
+        &self,
+        reader: R,
+        engine: &TransferEngine,
+        send: F,
+    ) -> ClipboardResult<u64>
+    where
+        R: tokio::io::AsyncRead + Unpin,
+        F: FnMut(Vec<u8>) -> ClipboardResult<()>,
+    {
+        let mut encoder = UnicodeEncoder::new(self.max_size);
+        let mut chunker = Chunker::new(engine, send);
+
+        read_chunks(reader, chunker.chunk_size, |chunk| chunker.push(&encoder.push(chunk)?)).await?;
+        chunker.push(&encoder.finish()?)?;
+        chunker.finish()
+    }
+
+    /// Stream an HTML fragment of `html_len` bytes from `reader` as CF_HTML chunks
+    ///
+    /// Chunking and limits as for
+    /// [`text_to_unicode_stream`](Self::text_to_unicode_stream). Fails if the
+    /// reader yields more or fewer than `html_len` bytes.
+    pub async fn cf_html_wrap_stream<R, F>(
+        &self,
+        reader: R,
+        html_len: usize,
+        engine: &TransferEngine,
+        send: F,
+    ) -> ClipboardResult<u64>
+    where
+        R: tokio::io::AsyncRead + Unpin,
+        F: FnMut(Vec<u8>) -> ClipboardResult<()>,
+    {
+        if html_len > self.max_size {
+            return Err(ClipboardError::DataSizeExceeded {
+                actual: html_len,
+                max: self.max_size,
+            });
+        }
+
+        let mut wrapper = CfHtmlWrapper::new(html_len);
+        let mut chunker = Chunker::new(engine, send);
+
+        read_chunks(reader, chunker.chunk_size, |chunk| chunker.push(&wrapper.push(chunk)?)).await?;
+        chunker.push(&wrapper.finish()?)?;
+        chunker.finish()
+    }
+}
+
+/// Read `reader` to the end in blocks of up to `block_size` bytes
+#[cfg(feature = "tokio")]
+async fn read_chunks<R, F>(mut reader: R, block_size: usize, mut on_chunk: F) -> ClipboardResult<()>
+where
+    R: tokio::io::AsyncRead + Unpin,
+    F: FnMut(&[u8]) -> ClipboardResult<()>,
+{
+    use tokio::io::AsyncReadExt;
+
+    let mut block = vec![0; block_size];
+    loop {
+        let n = reader.read(&mut block).await?;
+        if n == 0 {
+            return Ok(());
+        }
+        on_chunk(&block[..n])?;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    #[cfg(feature = "tokio")]
+    use crate::testing::block_on;
+    use crate::FormatConverter;
+
+    const TEXT: &str = "Grüße, 日本語 and emoji 🎉 across chunk boundaries";
+
+    #[test]
+    fn test_unicode_encoder_matches_whole_buffer() {
+        let expected = FormatConverter::new().text_to_unicode(TEXT).unwrap();
+
+        for chunk_size in 1..=7 {
+            let mut encoder = UnicodeEncoder::new(TEXT.len());
+            let mut out = Vec::new();
+            for chunk in TEXT.as_bytes().chunks(chunk_size) {
+                out.extend(encoder.push(chunk).unwrap());
+            }
+            out.extend(encoder.finish().unwrap());
+            assert_eq!(out, expected, "chunk size {chunk_size}");
+        }
+    }
+
+    #[test]
+    fn test_unicode_encoder_errors() {
+        let mut encoder = UnicodeEncoder::new(16);
+        encoder.push(&[b'a', 0xC3]).unwrap();
+        assert!(matches!(encoder.finish(), Err(ClipboardError::InvalidUtf8)));
+
+        let mut encoder = UnicodeEncoder::new(16);
+        assert!(matches!(encoder.push(&[0xFF, b'a']), Err(ClipboardError::InvalidUtf8)));
+
+        let mut encoder = UnicodeEncoder::new(4);
+        encoder.push(b"abc").unwrap();
+        assert!(matches!(
+            encoder.push(b"de"),
+            Err(ClipboardError::DataSizeExceeded { actual: 5, max: 4 })
+        ));
+    }
+
+    #[test]
+    fn test_cf_html_wrapper_matches_whole_buffer() {
+        let html = "<p>Grüße <b>日本語</b></p>";
+        let expected = FormatConverter::new().html_to_cf_html(html).unwrap();
+
+        let mut wrapper = CfHtmlWrapper::new(html.len());
+        assert_eq!(wrapper.total_len(), expected.len());
+
+        let mut out = Vec::new();
+        for chunk in html.as_bytes().chunks(5) {
+            out.extend(wrapper.push(chunk).unwrap());
+        }
+        out.extend(wrapper.finish().unwrap());
+        assert_eq!(out, expected);
+    }
+
+    #[test]
+    fn test_cf_html_wrapper_length_mismatch() {
+        let mut short = CfHtmlWrapper::new(10);
+        short.push(b"<p>").unwrap();
+        assert!(short.finish().is_err());
+
+        let mut long = CfHtmlWrapper::new(2);
+        assert!(long.push(b"<p>").is_err());
+    }
+
+    #[cfg(feature = "tokio")]
+    #[test]
+    fn test_streams_use_engine_chunks() {
+        use crate::transfer::{TransferConfig, TransferEngine};
+
+        let converter = FormatConverter::new();
+        let engine = TransferEngine::with_config(TransferConfig {
+            chunk_size: 16,
+            ..TransferConfig::default()
+        });
+
+        let mut chunks = Vec::new();
+        let sent = block_on(converter.text_to_unicode_stream(TEXT.as_bytes(), &engine, |chunk| {
+            chunks.push(chunk);
+            Ok(())
+        }))
+        .unwrap();
+        assert!(chunks[..chunks.len() - 1].iter().all(|c| c.len() == 16));
+        assert_eq!(chunks.concat(), converter.text_to_unicode(TEXT).unwrap());
+        assert_eq!(sent as usize, chunks.concat().len());
+
+        let html = "<p>Grüße</p>".repeat(10);
+        let mut out = Vec::new();
+        block_on(
+            converter.cf_html_wrap_stream(html.as_bytes(), html.len(), &engine, |chunk| {
+                out.extend(chunk);
+                Ok(())
+            }),
+        )
+        .unwrap();
+        assert_eq!(out, converter.html_to_cf_html(&html).unwrap());
+    }
+
+    #[cfg(feature = "tokio")]
+    #[test]
+    fn test_stream_respects_engine_max_size() {
+        use crate::transfer::{TransferConfig, TransferEngine};
+
+        let engine = TransferEngine::with_config(TransferConfig {
+            chunk_size: 4,
+            max_size: 8,
+            ..TransferConfig::default()
+        });
+        let result = block_on(FormatConverter::new().text_to_unicode_stream(&b"abcdef"[..], &engine, |_| Ok(())));
+        assert!(matches!(result, Err(ClipboardError::DataSizeExceeded { max: 8, .. })));
+    }
+
+    #[cfg(feature = "image")]
+    #[test]
+    fn test_image_streams_match_whole_buffer() {
+        use crate::testing::fixtures::DibBuilder;
+        use crate::transfer::{TransferConfig, TransferEngine};
+
+        let converter = FormatConverter::new();
+        let engine = TransferEngine::with_config(TransferConfig {
+            chunk_size: 64,
+            ..TransferConfig::default()
+        });
+        let dib = DibBuilder::new(48, 48).build();
+
+        let mut chunks = Vec::new();
+        let sent = converter
+            .dib_to_png_stream(&dib, &engine, |chunk| {
+                chunks.push(chunk);
+                Ok(())
+            })
+            .unwrap();
+        assert!(chunks.len() > 1);
+        assert!(chunks[..chunks.len() - 1].iter().all(|c| c.len() == 64));
+        assert_eq!(chunks.concat(), crate::image::dib_to_png(&dib).unwrap());
+        assert_eq!(sent, chunks.concat().len() as u64);
+
+        let mut jpeg = Vec::new();
+        converter
+            .dib_to_jpeg_stream(&dib, &engine, |chunk| {
+                jpeg.extend(chunk);
+                Ok(())
+            })
+            .unwrap();
+        assert_eq!(jpeg, crate::image::dib_to_jpeg(&dib).unwrap());
+    }
+
+    #[cfg(feature = "image")]
+    #[test]
+    fn test_image_stream_errors() {
+        use crate::testing::fixtures::DibBuilder;
+        use crate::transfer::{TransferConfig, TransferEngine};
+
+        let dib = DibBuilder::new(48, 48).build();
+        let engine = TransferEngine::with_config(TransferConfig {
+            chunk_size: 16,
+            max_size: 32,
+            ..TransferConfig::default()
+        });
+        let result = FormatConverter::new().dib_to_png_stream(&dib, &engine, |_| Ok(()));
+        assert!(matches!(result, Err(ClipboardError::DataSizeExceeded { max: 32, .. })));
+
+        // Errors from `send` come back unchanged
+        let engine = TransferEngine::with_config(TransferConfig {
+            chunk_size: 16,
+            ..TransferConfig::default()
+        });
+        let result =
+            FormatConverter::new().dib_to_png_stream(&dib, &engine, |_| Err(ClipboardError::TransferCancelled));
+        assert!(matches!(result, Err(ClipboardError::TransferCancelled)));
+    }
+}
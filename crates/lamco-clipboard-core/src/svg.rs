@@ -0,0 +1,137 @@
+//! SVG rasterization for peers that can't paste vector art.
+//!
+//! `image/svg+xml` is always announced and passed through unchanged as the
+//! registered format of the same name. Design tools on both sides read it
+//! directly, but most Windows applications only paste bitmaps, so with this
+//! module the SVG is also offered as PNG, CF_DIBV5 and CF_DIB, rendered with
+//! resvg.
+//!
+//! # Feature Flag
+//!
+//! This module requires the `svg` feature (which implies `image`):
+//!
+//! ```toml
+//! [dependencies]
+//! lamco-clipboard-core = { version = "0.5", features = ["svg"] }
+//! ```
+//!
+//! # Limitations
+//!
+//! Text is not rendered unless it has been converted to paths, since no fonts
+//! are loaded. External images are never fetched: only `data:` URLs embedded
+//! in the document are drawn, so clipboard content cannot pull local files
+//! into the bitmap sent to the peer.
+
+use image::{DynamicImage, ImageFormat, RgbaImage};
+use resvg::{tiny_skia, usvg};
+
+use crate::{ClipboardError, ClipboardResult};
+
+/// Largest width or height of a rasterized SVG in pixels.
+///
+/// Larger drawings are scaled down uniformly to fit.
+pub const MAX_SVG_DIMENSION: u32 = 4096;
+
+/// Rasterize an SVG document to PNG.
+///
+/// The output size follows the document's `width` and `height` (or its
+/// `viewBox`), scaled down to [`MAX_SVG_DIMENSION`] if necessary. Areas the
+/// drawing does not cover stay transparent.
+pub fn svg_to_png(svg_data: &[u8]) -> ClipboardResult<Vec<u8>> {
+    encode_png(&rasterize(svg_data)?)
+}
+
+/// Rasterize an SVG document to DIB format.
+pub fn svg_to_dib(svg_data: &[u8]) -> ClipboardResult<Vec<u8>> {
+    crate::image::png_to_dib(&svg_to_png(svg_data)?)
+}
+
+/// Rasterize an SVG document to DIBV5 format, keeping transparency.
+pub fn svg_to_dibv5(svg_data: &[u8]) -> ClipboardResult<Vec<u8>> {
+    crate::image::png_to_dibv5(&svg_to_png(svg_data)?)
+}
+
+fn rasterize(svg_data: &[u8]) -> ClipboardResult<tiny_skia::Pixmap> {
+    let options = usvg::Options {
+        image_href_resolver: usvg::ImageHrefResolver {
+            resolve_data: usvg::ImageHrefResolver::default_data_resolver(),
+            resolve_string: Box::new(|_, _| None),
+        },
+        ..usvg::Options::default()
+    };
+    let tree = usvg::Tree::from_data(svg_data, &options).map_err(|e| ClipboardError::ImageDecode(e.to_string()))?;
+
+    let size = tree.size();
+    let max = MAX_SVG_DIMENSION as f32;
+    let scale = (max / size.width()).min(max / size.height()).min(1.0);
+    let w = (size.width() * scale).round().max(1.0) as u32;
+    let h = (size.height() * scale).round().max(1.0) as u32;
+
+    let mut pixmap = tiny_skia::Pixmap::new(w, h)
+        .ok_or_else(|| ClipboardError::ImageDecode(format!("Cannot allocate {}x{} canvas", w, h)))?;
+    resvg::render(
+        &tree,
+        tiny_skia::Transform::from_scale(scale, scale),
+        &mut pixmap.as_mut(),
+    );
+    Ok(pixmap)
+}
+
+fn encode_png(pixmap: &tiny_skia::Pixmap) -> ClipboardResult<Vec<u8>> {
+    let data = pixmap
+        .pixels()
+        .iter()
+        .flat_map(|p| {
+            let c = p.demultiply();
+            [c.red(), c.green(), c.blue(), c.alpha()]
+        })
+        .collect();
+    let image = RgbaImage::from_raw(pixmap.width(), pixmap.height(), data)
+        .ok_or_else(|| ClipboardError::ImageEncode("Canvas size mismatch".to_string()))?;
+
+    let mut png_data = Vec::new();
+    DynamicImage::ImageRgba8(image)
+        .write_to(&mut std::io::Cursor::new(&mut png_data), ImageFormat::Png)
+        .map_err(|e| ClipboardError::ImageEncode(e.to_string()))?;
+    Ok(png_data)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn decode(png: &[u8]) -> RgbaImage {
+        image::load_from_memory_with_format(png, ImageFormat::Png)
+            .unwrap()
+            .to_rgba8()
+    }
+
+    #[test]
+    fn test_svg_rect_rasterized() {
+        let svg = br##"<svg xmlns="http://www.w3.org/2000/svg" width="20" height="10">
+            <rect x="10" width="10" height="10" fill="#ff0000"/>
+        </svg>"##;
+
+        let image = decode(&svg_to_png(svg).unwrap());
+        assert_eq!(image.dimensions(), (20, 10));
+        assert_eq!(image.get_pixel(15, 5).0, [255, 0, 0, 255]);
+        assert_eq!(image.get_pixel(5, 5).0[3], 0);
+
+        assert_eq!(
+            crate::image::dib_dimensions(&svg_to_dib(svg).unwrap()).unwrap(),
+            (20, 10)
+        );
+    }
+
+    #[test]
+    fn test_svg_scaled_to_limit() {
+        let svg = br#"<svg xmlns="http://www.w3.org/2000/svg" width="20000" height="100"/>"#;
+        let image = decode(&svg_to_png(svg).unwrap());
+        assert_eq!(image.width(), MAX_SVG_DIMENSION);
+    }
+
+    #[test]
+    fn test_svg_rejects_garbage() {
+        assert!(svg_to_png(b"not an svg").is_err());
+    }
+}
@@ -0,0 +1,40 @@
+//! Test support for crates built on this one
+//!
+//! Enabled with the `testing` feature. Nothing here is used by the crate
+//! itself at runtime; it exists so integrators can produce well-formed wire
+//! data in their own tests without copying ours.
+
+use std::future::Future;
+use std::pin::pin;
+use std::sync::Arc;
+use std::task::{Context, Poll, Wake, Waker};
+
+pub mod fixtures;
+pub mod sink;
+
+struct NoopWaker;
+
+impl Wake for NoopWaker {
+    fn wake(self: Arc<Self>) {}
+}
+
+/// Waker that does nothing when woken
+pub fn noop_waker() -> Waker {
+    Waker::from(Arc::new(NoopWaker))
+}
+
+/// Drive a future that never actually suspends, such as one running against
+/// [`MemorySink`](sink::MemorySink)
+///
+/// A future waiting on a timer or I/O is polled in a busy loop forever; run
+/// those on a real runtime instead.
+pub fn block_on<F: Future>(future: F) -> F::Output {
+    let mut future = pin!(future);
+    let waker = noop_waker();
+    let mut cx = Context::from_waker(&waker);
+    loop {
+        if let Poll::Ready(output) = future.as_mut().poll(&mut cx) {
+            return output;
+        }
+    }
+}
@@ -0,0 +1,359 @@
+//! Builders for well-formed clipboard wire data
+//!
+//! Each builder produces bytes the way a Windows peer would send them, so the
+//! output can be fed straight into the decoders and converters of this crate
+//! or into an integrator's own PDU handling.
+//!
+//! # Example
+//!
+//! ```
+//! use lamco_clipboard_core::interop::{decode_format_list, FormatNameEncoding};
+//! use lamco_clipboard_core::testing::fixtures::FormatListBuilder;
+//!
+//! let body = FormatListBuilder::new().format(13).named(0xC004, "HTML Format").body();
+//!
+//! let formats = decode_format_list(&body, FormatNameEncoding::Long).unwrap();
+//! assert_eq!(formats[1].name.as_deref(), Some("HTML Format"));
+//! ```
+
+use crate::formats::{CfHtml, FileDescriptor, FileDescriptorFlags};
+use crate::interop::FormatNameEncoding;
+
+/// CB_FORMAT_LIST message type
+const CB_FORMAT_LIST: u16 = 0x0002;
+
+/// CB_ASCII_NAMES message flag
+const CB_ASCII_NAMES: u16 = 0x0004;
+
+/// Size of the name field in a short Format List entry
+const SHORT_NAME_LEN: usize = 32;
+
+/// Builder for Format List PDUs
+#[derive(Debug, Clone)]
+pub struct FormatListBuilder {
+    encoding: FormatNameEncoding,
+    formats: Vec<(u32, Option<String>)>,
+}
+
+impl Default for FormatListBuilder {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl FormatListBuilder {
+    /// Create an empty list using long format names
+    pub fn new() -> Self {
+        Self {
+            encoding: FormatNameEncoding::Long,
+            formats: Vec::new(),
+        }
+    }
+
+    /// Set how names are encoded
+    pub fn encoding(mut self, encoding: FormatNameEncoding) -> Self {
+        self.encoding = encoding;
+        self
+    }
+
+    /// Add a format without a name
+    pub fn format(mut self, id: u32) -> Self {
+        self.formats.push((id, None));
+        self
+    }
+
+    /// Add a registered format with its name
+    ///
+    /// Short encodings cut the name to what fits in their 32-byte field.
+    pub fn named(mut self, id: u32, name: impl Into<String>) -> Self {
+        self.formats.push((id, Some(name.into())));
+        self
+    }
+
+    /// Add several formats without names
+    pub fn formats(mut self, ids: impl IntoIterator<Item = u32>) -> Self {
+        self.formats.extend(ids.into_iter().map(|id| (id, None)));
+        self
+    }
+
+    /// Encode the PDU body, without the clipboard PDU header
+    pub fn body(&self) -> Vec<u8> {
+        let mut body = Vec::new();
+
+        for (id, name) in &self.formats {
+            body.extend_from_slice(&id.to_le_bytes());
+            let name = name.as_deref().unwrap_or("");
+
+            match self.encoding {
+                FormatNameEncoding::Long => {
+                    for unit in name.encode_utf16().chain(std::iter::once(0)) {
+                        body.extend_from_slice(&unit.to_le_bytes());
+                    }
+                }
+                FormatNameEncoding::ShortUnicode => {
+                    let mut field = [0u8; SHORT_NAME_LEN];
+                    for (i, unit) in name.encode_utf16().take(SHORT_NAME_LEN / 2 - 1).enumerate() {
+                        field[i * 2..i * 2 + 2].copy_from_slice(&unit.to_le_bytes());
+                    }
+                    body.extend_from_slice(&field);
+                }
+                FormatNameEncoding::ShortAscii => {
+                    let mut field = [0u8; SHORT_NAME_LEN];
+                    for (slot, byte) in field.iter_mut().zip(name.bytes().take(SHORT_NAME_LEN - 1)) {
+                        *slot = byte;
+                    }
+                    body.extend_from_slice(&field);
+                }
+            }
+        }
+
+        body
+    }
+
+    /// Encode the full PDU: msgType, msgFlags and dataLen followed by the body
+    pub fn pdu(&self) -> Vec<u8> {
+        let body = self.body();
+        let flags = if self.encoding == FormatNameEncoding::ShortAscii {
+            CB_ASCII_NAMES
+        } else {
+            0
+        };
+
+        let mut pdu = Vec::with_capacity(8 + body.len());
+        pdu.extend_from_slice(&CB_FORMAT_LIST.to_le_bytes());
+        pdu.extend_from_slice(&flags.to_le_bytes());
+        pdu.extend_from_slice(&(body.len() as u32).to_le_bytes());
+        pdu.extend_from_slice(&body);
+        pdu
+    }
+}
+
+/// Builder for FileGroupDescriptorW blobs
+#[derive(Debug, Clone, Default)]
+pub struct FileGroupDescriptorBuilder {
+    descriptors: Vec<FileDescriptor>,
+}
+
+impl FileGroupDescriptorBuilder {
+    /// Create an empty descriptor list
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Add a regular file of `size` bytes
+    ///
+    /// `name` may contain backslashes to place the file inside a directory.
+    pub fn file(self, name: impl Into<String>, size: u64) -> Self {
+        self.descriptor(FileDescriptor {
+            flags: FileDescriptorFlags::from_raw(FileDescriptorFlags::ATTRIBUTES | FileDescriptorFlags::FILESIZE),
+            attributes: 0x80, // FILE_ATTRIBUTE_NORMAL
+            creation_time: None,
+            access_time: None,
+            write_time: None,
+            size: Some(size),
+            name: name.into(),
+        })
+    }
+
+    /// Add a directory entry
+    pub fn directory(self, name: impl Into<String>) -> Self {
+        self.descriptor(FileDescriptor {
+            flags: FileDescriptorFlags::from_raw(FileDescriptorFlags::ATTRIBUTES),
+            attributes: 0x10, // FILE_ATTRIBUTE_DIRECTORY
+            creation_time: None,
+            access_time: None,
+            write_time: None,
+            size: None,
+            name: name.into(),
+        })
+    }
+
+    /// Add a descriptor with full control over its fields
+    pub fn descriptor(mut self, descriptor: FileDescriptor) -> Self {
+        self.descriptors.push(descriptor);
+        self
+    }
+
+    /// Encode as cItems followed by one FILEDESCRIPTORW per entry
+    pub fn build(&self) -> Vec<u8> {
+        let mut data = Vec::with_capacity(4 + self.descriptors.len() * 592);
+        data.extend_from_slice(&(self.descriptors.len() as u32).to_le_bytes());
+        for descriptor in &self.descriptors {
+            data.extend_from_slice(&descriptor.to_bytes());
+        }
+        data
+    }
+}
+
+/// CF_HTML payload for an HTML fragment
+pub fn cf_html(html: &str) -> Vec<u8> {
+    CfHtml::from_html(html).to_bytes()
+}
+
+/// CF_HTML payload for an HTML fragment copied from `url`
+pub fn cf_html_with_source(html: &str, url: &str) -> Vec<u8> {
+    CfHtml::from_html(html).with_source_url(url).to_bytes()
+}
+
+/// Builder for CF_DIB payloads of a given size
+///
+/// Produces a BITMAPINFOHEADER with BI_RGB compression followed by pixel rows
+/// padded to four bytes, filled with a single color.
+#[derive(Debug, Clone)]
+pub struct DibBuilder {
+    width: u32,
+    height: u32,
+    bit_count: u16,
+    top_down: bool,
+    color: [u8; 4],
+}
+
+impl DibBuilder {
+    /// Create a 24-bit bottom-up DIB filled with black
+    pub fn new(width: u32, height: u32) -> Self {
+        Self {
+            width,
+            height,
+            bit_count: 24,
+            top_down: false,
+            color: [0, 0, 0, 0xFF],
+        }
+    }
+
+    /// Use 32 bits per pixel instead of 24
+    pub fn bgra(mut self) -> Self {
+        self.bit_count = 32;
+        self
+    }
+
+    /// Store rows top-down (negative biHeight)
+    pub fn top_down(mut self) -> Self {
+        self.top_down = true;
+        self
+    }
+
+    /// Fill every pixel with this RGBA color
+    ///
+    /// Alpha is only written for 32-bit DIBs.
+    pub fn fill(mut self, rgba: [u8; 4]) -> Self {
+        self.color = rgba;
+        self
+    }
+
+    /// Encode header and pixel data
+    pub fn build(&self) -> Vec<u8> {
+        let bytes_per_pixel = usize::from(self.bit_count / 8);
+        let row_len = (self.width as usize * bytes_per_pixel).div_ceil(4) * 4;
+        let image_size = row_len * self.height as usize;
+        let height = if self.top_down {
+            -(self.height as i32)
+        } else {
+            self.height as i32
+        };
+
+        let mut data = Vec::with_capacity(40 + image_size);
+        data.extend_from_slice(&40u32.to_le_bytes()); // biSize
+        data.extend_from_slice(&(self.width as i32).to_le_bytes());
+        data.extend_from_slice(&height.to_le_bytes());
+        data.extend_from_slice(&1u16.to_le_bytes()); // biPlanes
+        data.extend_from_slice(&self.bit_count.to_le_bytes());
+        data.extend_from_slice(&0u32.to_le_bytes()); // BI_RGB
+        data.extend_from_slice(&(image_size as u32).to_le_bytes());
+        data.extend_from_slice(&2835i32.to_le_bytes()); // 72 DPI
+        data.extend_from_slice(&2835i32.to_le_bytes());
+        data.extend_from_slice(&0u32.to_le_bytes()); // biClrUsed
+        data.extend_from_slice(&0u32.to_le_bytes()); // biClrImportant
+
+        let [r, g, b, a] = self.color;
+        let pixel = [b, g, r, a];
+        let mut row = Vec::with_capacity(row_len);
+        for _ in 0..self.width {
+            row.extend_from_slice(&pixel[..bytes_per_pixel]);
+        }
+        row.resize(row_len, 0);
+        for _ in 0..self.height {
+            data.extend_from_slice(&row);
+        }
+
+        data
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::formats::CF_UNICODETEXT;
+    use crate::interop::decode_format_list;
+
+    #[test]
+    fn test_format_list_round_trip() {
+        let builder = FormatListBuilder::new()
+            .format(CF_UNICODETEXT)
+            .named(0xC0A1, "HTML Format")
+            .named(0xC0A2, "A name that is far too long for a short entry");
+
+        for encoding in [
+            FormatNameEncoding::Long,
+            FormatNameEncoding::ShortUnicode,
+            FormatNameEncoding::ShortAscii,
+        ] {
+            let formats = decode_format_list(&builder.clone().encoding(encoding).body(), encoding).unwrap();
+            assert_eq!(formats.len(), 3);
+            assert_eq!(formats[0].id, CF_UNICODETEXT);
+            assert_eq!(formats[0].name, None);
+            assert_eq!(formats[1].name.as_deref(), Some("HTML Format"));
+        }
+
+        let pdu = FormatListBuilder::new()
+            .encoding(FormatNameEncoding::ShortAscii)
+            .format(1)
+            .pdu();
+        assert_eq!(&pdu[..8], &[0x02, 0x00, 0x04, 0x00, 36, 0, 0, 0]);
+        assert_eq!(pdu.len(), 8 + 36);
+    }
+
+    #[test]
+    fn test_file_group_descriptor_round_trip() {
+        let data = FileGroupDescriptorBuilder::new()
+            .directory("docs")
+            .file("docs\\report.pdf", 5_000_000_000)
+            .build();
+
+        let descriptors = FileDescriptor::parse_list(&data).unwrap();
+        assert_eq!(descriptors.len(), 2);
+        assert_eq!(descriptors[0].name, "docs");
+        assert_eq!(descriptors[0].attributes, 0x10);
+        assert_eq!(descriptors[1].name, "docs\\report.pdf");
+        assert_eq!(descriptors[1].size, Some(5_000_000_000));
+    }
+
+    #[test]
+    fn test_cf_html() {
+        let data = cf_html_with_source("<b>hi</b>", "https://example.com/");
+        let text = String::from_utf8(data).unwrap();
+        assert!(text.starts_with("Version:"));
+        assert!(text.contains("SourceURL:https://example.com/"));
+        assert!(text.contains("<b>hi</b>"));
+    }
+
+    #[test]
+    fn test_dib_layout() {
+        let dib = DibBuilder::new(3, 2).fill([0x10, 0x20, 0x30, 0xFF]).build();
+        // 3 pixels * 3 bytes = 9, padded to 12
+        assert_eq!(dib.len(), 40 + 12 * 2);
+        assert_eq!(&dib[40..43], &[0x30, 0x20, 0x10]);
+        assert_eq!(&dib[49..52], &[0, 0, 0]);
+
+        let dib = DibBuilder::new(2, 2).bgra().top_down().build();
+        assert_eq!(i32::from_le_bytes([dib[8], dib[9], dib[10], dib[11]]), -2);
+        assert_eq!(dib.len(), 40 + 16);
+    }
+
+    #[cfg(feature = "image")]
+    #[test]
+    fn test_dib_decodes() {
+        let dib = DibBuilder::new(7, 5).build();
+        assert_eq!(crate::image::dib_dimensions(&dib).unwrap(), (7, 5));
+        assert!(crate::image::dib_to_png(&dib).is_ok());
+    }
+}
@@ -0,0 +1,116 @@
+//! In-memory [`ClipboardSink`] for driving sessions and transfers in tests
+//!
+//! # Example
+//!
+//! ```
+//! use lamco_clipboard_core::testing::block_on;
+//! use lamco_clipboard_core::testing::sink::MemorySink;
+//! use lamco_clipboard_core::ClipboardSink;
+//!
+//! let sink = MemorySink::new();
+//! block_on(sink.write_clipboard("text/plain", b"Hi".to_vec())).unwrap();
+//! assert_eq!(block_on(sink.read_clipboard("text/plain")).unwrap(), b"Hi");
+//! ```
+
+use crate::sink::{ClipboardChangeReceiver, ClipboardSink, FileInfo};
+use crate::{ClipboardError, ClipboardResult};
+use std::sync::{Mutex, PoisonError};
+
+type Entries = Vec<(String, Vec<u8>)>;
+
+/// Clipboard sink holding everything in memory
+///
+/// Clipboard writes are kept in order and a read returns the latest write of
+/// the MIME type. Files set with [`with_files`](Self::with_files) are served
+/// to the peer; files the peer sends are recorded and returned by
+/// [`written_files`](Self::written_files). Change notification is not
+/// supported.
+#[derive(Debug, Default)]
+pub struct MemorySink {
+    clipboard: Mutex<Entries>,
+    files: Entries,
+    written: Mutex<Entries>,
+}
+
+impl MemorySink {
+    /// Create an empty sink
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Serve these files, as `(name, contents)` pairs
+    pub fn with_files(mut self, files: Entries) -> Self {
+        self.files = files;
+        self
+    }
+
+    /// Clipboard writes so far, oldest first
+    pub fn clipboard(&self) -> Entries {
+        self.clipboard.lock().unwrap_or_else(PoisonError::into_inner).clone()
+    }
+
+    /// Empty the clipboard
+    pub fn clear(&self) {
+        self.clipboard.lock().unwrap_or_else(PoisonError::into_inner).clear();
+    }
+
+    /// Files served to the peer
+    pub fn files(&self) -> &[(String, Vec<u8>)] {
+        &self.files
+    }
+
+    /// Files written through [`write_file`](ClipboardSink::write_file), oldest first
+    pub fn written_files(&self) -> Entries {
+        self.written.lock().unwrap_or_else(PoisonError::into_inner).clone()
+    }
+}
+
+impl ClipboardSink for MemorySink {
+    async fn announce_formats(&self, _mime_types: Vec<String>) -> ClipboardResult<()> {
+        Ok(())
+    }
+
+    async fn read_clipboard(&self, mime_type: &str) -> ClipboardResult<Vec<u8>> {
+        let clipboard = self.clipboard.lock().unwrap_or_else(PoisonError::into_inner);
+        clipboard
+            .iter()
+            .rev()
+            .find(|(mime, _)| mime == mime_type)
+            .map(|(_, data)| data.clone())
+            .ok_or_else(|| ClipboardError::UnsupportedFormat(mime_type.to_string()))
+    }
+
+    async fn write_clipboard(&self, mime_type: &str, data: Vec<u8>) -> ClipboardResult<()> {
+        let mut clipboard = self.clipboard.lock().unwrap_or_else(PoisonError::into_inner);
+        clipboard.push((mime_type.to_string(), data));
+        Ok(())
+    }
+
+    async fn subscribe_changes(&self) -> ClipboardResult<ClipboardChangeReceiver> {
+        Err(ClipboardError::Backend("no change notification".to_string()))
+    }
+
+    async fn get_file_list(&self) -> ClipboardResult<Vec<FileInfo>> {
+        Ok(self
+            .files
+            .iter()
+            .map(|(name, data)| FileInfo::file(name.clone(), data.len() as u64))
+            .collect())
+    }
+
+    async fn read_file_chunk(&self, index: u32, offset: u64, size: u32) -> ClipboardResult<Vec<u8>> {
+        let (_, data) = self
+            .files
+            .get(index as usize)
+            .ok_or_else(|| ClipboardError::FileNotFound(format!("file {}", index)))?;
+        let start = usize::try_from(offset).unwrap_or(usize::MAX).min(data.len());
+        let end = start.saturating_add(size as usize).min(data.len());
+        Ok(data[start..end].to_vec())
+    }
+
+    async fn write_file(&self, path: &str, data: Vec<u8>) -> ClipboardResult<()> {
+        let mut written = self.written.lock().unwrap_or_else(PoisonError::into_inner);
+        written.push((path.to_string(), data));
+        Ok(())
+    }
+}
@@ -4,8 +4,11 @@
 //! with progress tracking and integrity verification.
 
 use sha2::{Digest, Sha256};
+use std::sync::Arc;
 use std::time::{Duration, Instant};
 
+use crate::clock::{Clock, SystemClock};
+use crate::pool::{BufferPool, PooledBuffer};
 use crate::{ClipboardError, ClipboardResult};
 
 /// Default chunk size: 64KB
@@ -19,6 +22,8 @@ pub const DEFAULT_TIMEOUT_MS: u64 = 30_000;
 
 /// State of a transfer operation
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "serde", serde(rename_all = "snake_case"))]
 pub enum TransferState {
     /// Transfer not started
     Pending,
@@ -46,6 +51,7 @@ impl TransferState {
 
 /// Progress information for a transfer
 #[derive(Debug, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct TransferProgress {
     /// Total bytes to transfer
     pub total_bytes: u64,
@@ -57,6 +63,9 @@ pub struct TransferProgress {
     pub state: TransferState,
 
     /// Transfer start time
+    ///
+    /// Local to this process, so it is not serialized.
+    #[cfg_attr(feature = "serde", serde(skip))]
     pub started_at: Option<Instant>,
 
     /// Estimated time remaining in milliseconds
@@ -97,6 +106,8 @@ impl TransferProgress {
 
 /// Configuration for the transfer engine
 #[derive(Debug, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "serde", serde(default))]
 pub struct TransferConfig {
     /// Chunk size in bytes
     pub chunk_size: usize,
@@ -161,13 +172,19 @@ pub struct TransferEngine {
     progress: Option<TransferProgress>,
 
     /// Received chunks (for incoming transfer)
-    received_chunks: Vec<Vec<u8>>,
+    received_chunks: Vec<PooledBuffer>,
+
+    /// Buffers for staged chunks, recycled between transfers
+    pool: BufferPool,
 
     /// Expected hash (for verification)
     expected_hash: Option<String>,
 
-    /// Transfer start time
-    started_at: Option<Instant>,
+    /// Transfer start time, in clock time
+    started_at: Option<Duration>,
+
+    /// Time source for timeouts and ETA
+    clock: Arc<dyn Clock>,
 }
 
 impl Default for TransferEngine {
@@ -188,11 +205,33 @@ impl TransferEngine {
             config,
             progress: None,
             received_chunks: Vec::new(),
+            pool: BufferPool::new(),
             expected_hash: None,
             started_at: None,
+            clock: Arc::new(SystemClock),
         }
     }
 
+    /// Read time from `clock` instead of the system clock
+    ///
+    /// Governs the timeout and ETA; [`TransferProgress::started_at`] stays
+    /// an [`Instant`] for display.
+    pub fn with_clock(mut self, clock: impl Clock + 'static) -> Self {
+        self.clock = Arc::new(clock);
+        self
+    }
+
+    /// Stage chunks in a shared pool, e.g. one pool for every engine of a session
+    pub fn with_buffer_pool(mut self, pool: BufferPool) -> Self {
+        self.pool = pool;
+        self
+    }
+
+    /// Get the pool chunks are staged in
+    pub fn buffer_pool(&self) -> &BufferPool {
+        &self.pool
+    }
+
     /// Get current progress
     pub fn progress(&self) -> Option<&TransferProgress> {
         self.progress.as_ref()
@@ -202,6 +241,27 @@ impl TransferEngine {
     ///
     /// Returns a vector of chunks ready to be sent.
     pub fn prepare_send(&mut self, data: &[u8]) -> ClipboardResult<Vec<Vec<u8>>> {
+        self.begin_send(data)?;
+        let chunks: Vec<Vec<u8>> = data.chunks(self.config.chunk_size).map(|c| c.to_vec()).collect();
+
+        Ok(chunks)
+    }
+
+    /// Prepare data for chunked sending in pooled buffers
+    ///
+    /// Like [`prepare_send`](Self::prepare_send), but each chunk is copied
+    /// into a buffer from the engine's pool and goes back to it once sent.
+    pub fn prepare_send_pooled(&mut self, data: &[u8]) -> ClipboardResult<Vec<PooledBuffer>> {
+        self.begin_send(data)?;
+        let chunks = data
+            .chunks(self.config.chunk_size)
+            .map(|c| self.pool.copy_from(c))
+            .collect();
+
+        Ok(chunks)
+    }
+
+    fn begin_send(&mut self, data: &[u8]) -> ClipboardResult<()> {
         if data.len() > self.config.max_size {
             return Err(ClipboardError::DataSizeExceeded {
                 actual: data.len(),
@@ -209,7 +269,7 @@ impl TransferEngine {
             });
         }
 
-        self.started_at = Some(Instant::now());
+        self.started_at = Some(self.clock.now());
         self.progress = Some(TransferProgress::new(data.len() as u64));
 
         if let Some(ref mut progress) = self.progress {
@@ -217,9 +277,7 @@ impl TransferEngine {
             progress.started_at = Some(Instant::now());
         }
 
-        let chunks: Vec<Vec<u8>> = data.chunks(self.config.chunk_size).map(|c| c.to_vec()).collect();
-
-        Ok(chunks)
+        Ok(())
     }
 
     /// Start receiving a chunked transfer
@@ -233,7 +291,7 @@ impl TransferEngine {
 
         self.received_chunks.clear();
         self.expected_hash = expected_hash;
-        self.started_at = Some(Instant::now());
+        self.started_at = Some(self.clock.now());
         self.progress = Some(TransferProgress::new(total_size));
 
         if let Some(ref mut progress) = self.progress {
@@ -245,10 +303,24 @@ impl TransferEngine {
     }
 
     /// Receive a chunk of data
+    ///
+    /// The buffer is kept in the engine's pool after the transfer ends.
     pub fn receive_chunk(&mut self, chunk: Vec<u8>) -> ClipboardResult<()> {
+        let chunk = self.pool.adopt(chunk);
+        self.stage_chunk(chunk)
+    }
+
+    /// Receive a chunk borrowed from a decoded PDU, copying it into a pooled buffer
+    pub fn receive_chunk_from_slice(&mut self, chunk: &[u8]) -> ClipboardResult<()> {
+        let chunk = self.pool.copy_from(chunk);
+        self.stage_chunk(chunk)
+    }
+
+    fn stage_chunk(&mut self, chunk: PooledBuffer) -> ClipboardResult<()> {
         // Check timeout
+        let now = self.clock.now();
         if let Some(started) = self.started_at {
-            if started.elapsed() > Duration::from_millis(self.config.timeout_ms) {
+            if now.saturating_sub(started) > Duration::from_millis(self.config.timeout_ms) {
                 if let Some(ref mut progress) = self.progress {
                     progress.state = TransferState::Failed;
                 }
@@ -271,8 +343,8 @@ impl TransferEngine {
         progress.transferred_bytes += chunk.len() as u64;
 
         // Calculate ETA
-        if let Some(started) = progress.started_at {
-            let elapsed = started.elapsed().as_secs_f64();
+        if let Some(started) = self.started_at {
+            let elapsed = now.saturating_sub(started).as_secs_f64();
             if elapsed > 0.0 && progress.transferred_bytes > 0 {
                 let rate = progress.transferred_bytes as f64 / elapsed;
                 let remaining = progress.total_bytes - progress.transferred_bytes;
@@ -308,7 +380,7 @@ impl TransferEngine {
         // Assemble data
         let mut data = Vec::with_capacity(progress.total_bytes as usize);
         for chunk in &self.received_chunks {
-            data.extend_from_slice(chunk);
+            data.extend_from_slice(chunk.as_slice());
         }
 
         // Verify integrity if hash was provided
@@ -366,6 +438,7 @@ impl TransferEngine {
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::pool::tests::count_allocations;
 
     #[test]
     fn test_prepare_send() {
@@ -444,6 +517,27 @@ mod tests {
         assert_eq!(progress.state, TransferState::Cancelled);
     }
 
+    #[test]
+    fn test_timeout_and_eta() {
+        let clock = crate::ManualClock::new();
+        let mut engine = TransferEngine::with_config(TransferConfig {
+            timeout_ms: 1000,
+            ..TransferConfig::default()
+        })
+        .with_clock(clock.clone());
+
+        engine.start_receive(300, None).unwrap();
+        clock.advance(Duration::from_millis(100));
+        engine.receive_chunk(vec![0; 100]).unwrap();
+        // 100 bytes in 100 ms leaves 200 ms for the rest
+        assert_eq!(engine.progress().unwrap().eta_ms, Some(200));
+
+        clock.advance(Duration::from_millis(901));
+        let result = engine.receive_chunk(vec![0; 100]);
+        assert!(matches!(result, Err(ClipboardError::TransferTimeout(1000))));
+        assert_eq!(engine.progress().unwrap().state, TransferState::Failed);
+    }
+
     #[test]
     fn test_progress_percentage() {
         let mut progress = TransferProgress::new(100);
@@ -459,7 +553,41 @@ mod tests {
         };
         let mut engine = TransferEngine::with_config(config);
 
-        let result = engine.prepare_send(&vec![0u8; 200]);
+        let result = engine.prepare_send(&[0u8; 200]);
         assert!(matches!(result, Err(ClipboardError::DataSizeExceeded { .. })));
     }
+
+    #[test]
+    fn test_pooled_chunks_recycled() {
+        let mut engine = TransferEngine::with_config(TransferConfig {
+            chunk_size: 1000,
+            verify_integrity: false,
+            ..Default::default()
+        });
+        let chunk = [1u8; 1000];
+
+        let receive = |engine: &mut TransferEngine| {
+            engine.start_receive(3000, None).unwrap();
+            for _ in 0..3 {
+                engine.receive_chunk_from_slice(&chunk).unwrap();
+            }
+            engine.finalize_receive().unwrap()
+        };
+
+        // After the first transfer only the assembled data is allocated
+        receive(&mut engine);
+        let (allocations, ()) = count_allocations(|| {
+            for _ in 0..100 {
+                assert_eq!(receive(&mut engine).len(), 3000);
+            }
+        });
+        assert_eq!(allocations, 100);
+
+        // Sent chunks come from the same pool
+        let sent = engine.prepare_send_pooled(&[2u8; 2500]).unwrap();
+        assert_eq!(sent.iter().map(|c| c.len()).collect::<Vec<_>>(), [1000, 1000, 500]);
+        assert_eq!(engine.buffer_pool().metrics().idle, 0);
+        drop(sent);
+        assert_eq!(engine.buffer_pool().metrics().idle, 3);
+    }
 }
@@ -0,0 +1,259 @@
+//! Chromium web custom data (`clipboard.write` with custom MIME types).
+//!
+//! Chromium stores custom MIME types written by web pages in a single
+//! clipboard entry: the "Chromium Web Custom MIME Data Format" registered
+//! format on Windows and `chromium/x-web-custom-data` on Linux. Both carry
+//! the same base::Pickle of a type → data map:
+//!
+//! ```text
+//! u32 payload size
+//! u32 entry count
+//! entry count × (string16 type, string16 data)
+//! ```
+//!
+//! where a string16 is a u32 length in UTF-16 code units followed by the
+//! UTF-16LE code units, padded to a 4-byte boundary. All integers are
+//! little-endian.
+//!
+//! [`WebCustomData`] unpacks the map so each custom type can be offered as its
+//! own MIME type on the Linux side, and packs it again on the way back.
+//!
+//! # Example
+//!
+//! ```rust
+//! use lamco_clipboard_core::web_custom_data::WebCustomData;
+//!
+//! let mut custom = WebCustomData::new();
+//! custom.insert("application/x-app-state", "{\"id\":1}");
+//!
+//! let pickle = custom.to_pickle();
+//! let parsed = WebCustomData::parse(&pickle).unwrap();
+//! assert_eq!(parsed.get("application/x-app-state"), Some("{\"id\":1}"));
+//! ```
+
+use crate::{ClipboardError, ClipboardResult};
+
+/// Windows registered format name for Chromium web custom data
+pub const WEB_CUSTOM_DATA_FORMAT_NAME: &str = "Chromium Web Custom MIME Data Format";
+
+/// Linux MIME type for Chromium web custom data
+pub const WEB_CUSTOM_DATA_MIME: &str = "chromium/x-web-custom-data";
+
+/// Size of the pickle header (payload size)
+const PICKLE_HEADER_SIZE: usize = 4;
+
+/// Custom MIME types and their data, in insertion order
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct WebCustomData {
+    entries: Vec<(String, String)>,
+}
+
+impl WebCustomData {
+    /// Create an empty map
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Parse a Chromium custom data pickle
+    pub fn parse(data: &[u8]) -> ClipboardResult<Self> {
+        let mut reader = PickleReader::new(data)?;
+
+        let count = reader.read_u32()? as usize;
+        // Each entry needs at least two length fields
+        if count > reader.remaining() / 8 {
+            return Err(ClipboardError::FormatConversion(format!(
+                "web custom data claims {} entries in {} bytes",
+                count,
+                reader.remaining()
+            )));
+        }
+
+        let mut entries = Vec::with_capacity(count);
+        for _ in 0..count {
+            let mime_type = reader.read_string16()?;
+            let data = reader.read_string16()?;
+            entries.push((mime_type, data));
+        }
+
+        Ok(Self { entries })
+    }
+
+    /// Serialize as a Chromium custom data pickle
+    pub fn to_pickle(&self) -> Vec<u8> {
+        let mut payload = Vec::new();
+        payload.extend_from_slice(&(self.entries.len() as u32).to_le_bytes());
+        for (mime_type, data) in &self.entries {
+            write_string16(&mut payload, mime_type);
+            write_string16(&mut payload, data);
+        }
+
+        let mut pickle = Vec::with_capacity(PICKLE_HEADER_SIZE + payload.len());
+        pickle.extend_from_slice(&(payload.len() as u32).to_le_bytes());
+        pickle.extend_from_slice(&payload);
+        pickle
+    }
+
+    /// Set the data for a custom MIME type, replacing any previous value
+    pub fn insert(&mut self, mime_type: impl Into<String>, data: impl Into<String>) {
+        let mime_type = mime_type.into();
+        let data = data.into();
+        match self.entries.iter_mut().find(|(t, _)| *t == mime_type) {
+            Some(entry) => entry.1 = data,
+            None => self.entries.push((mime_type, data)),
+        }
+    }
+
+    /// Get the data for a custom MIME type
+    pub fn get(&self, mime_type: &str) -> Option<&str> {
+        self.entries
+            .iter()
+            .find(|(t, _)| t == mime_type)
+            .map(|(_, data)| data.as_str())
+    }
+
+    /// Custom MIME types to offer individually on the Linux side
+    pub fn mime_types(&self) -> impl Iterator<Item = &str> {
+        self.entries.iter().map(|(t, _)| t.as_str())
+    }
+
+    /// Iterate over `(mime_type, data)` pairs
+    pub fn iter(&self) -> impl Iterator<Item = (&str, &str)> {
+        self.entries.iter().map(|(t, d)| (t.as_str(), d.as_str()))
+    }
+
+    /// Number of custom types
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    /// Returns true if there are no custom types
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+}
+
+/// Append a length-prefixed, 4-byte aligned UTF-16LE string
+fn write_string16(buf: &mut Vec<u8>, s: &str) {
+    let units: Vec<u16> = s.encode_utf16().collect();
+    buf.extend_from_slice(&(units.len() as u32).to_le_bytes());
+    buf.extend(units.iter().flat_map(|u| u.to_le_bytes()));
+    buf.resize(align4(buf.len()), 0);
+}
+
+fn align4(n: usize) -> usize {
+    (n + 3) & !3
+}
+
+/// Cursor over a pickle payload
+struct PickleReader<'a> {
+    payload: &'a [u8],
+    pos: usize,
+}
+
+impl<'a> PickleReader<'a> {
+    fn new(data: &'a [u8]) -> ClipboardResult<Self> {
+        if data.len() < PICKLE_HEADER_SIZE {
+            return Err(ClipboardError::FormatConversion(
+                "web custom data too short".to_string(),
+            ));
+        }
+
+        let size = u32::from_le_bytes([data[0], data[1], data[2], data[3]]) as usize;
+        let payload = data[PICKLE_HEADER_SIZE..]
+            .get(..size)
+            .ok_or_else(|| ClipboardError::FormatConversion("web custom data truncated".to_string()))?;
+
+        Ok(Self { payload, pos: 0 })
+    }
+
+    fn remaining(&self) -> usize {
+        self.payload.len() - self.pos
+    }
+
+    fn take(&mut self, len: usize) -> ClipboardResult<&'a [u8]> {
+        let bytes = self
+            .payload
+            .get(self.pos..self.pos + len)
+            .ok_or_else(|| ClipboardError::FormatConversion("web custom data truncated".to_string()))?;
+        self.pos = align4(self.pos + len).min(self.payload.len());
+        Ok(bytes)
+    }
+
+    fn read_u32(&mut self) -> ClipboardResult<u32> {
+        let bytes = self.take(4)?;
+        Ok(u32::from_le_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]))
+    }
+
+    fn read_string16(&mut self) -> ClipboardResult<String> {
+        let len = self.read_u32()? as usize;
+        let byte_len = len
+            .checked_mul(2)
+            .filter(|&n| n <= self.remaining())
+            .ok_or_else(|| ClipboardError::FormatConversion("web custom data truncated".to_string()))?;
+        let bytes = self.take(byte_len)?;
+
+        let units: Vec<u16> = bytes
+            .chunks_exact(2)
+            .map(|chunk| u16::from_le_bytes([chunk[0], chunk[1]]))
+            .collect();
+        String::from_utf16(&units).map_err(|_| ClipboardError::InvalidUtf16)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_pickle_layout() {
+        let mut custom = WebCustomData::new();
+        custom.insert("a", "xyz");
+
+        let pickle = custom.to_pickle();
+        #[rustfmt::skip]
+        let expected = [
+            24, 0, 0, 0,                // payload size
+            1, 0, 0, 0,                 // entry count
+            1, 0, 0, 0, b'a', 0, 0, 0,  // "a" + padding
+            3, 0, 0, 0, b'x', 0, b'y', 0, b'z', 0, 0, 0, // "xyz" + padding
+        ];
+        assert_eq!(pickle, expected);
+    }
+
+    #[test]
+    fn test_roundtrip() {
+        let mut custom = WebCustomData::new();
+        custom.insert("application/x-one", "1");
+        custom.insert("web text/x-two", "zwei \u{1F600}");
+        custom.insert("application/x-one", "uno");
+
+        let parsed = WebCustomData::parse(&custom.to_pickle()).unwrap();
+        assert_eq!(parsed, custom);
+        assert_eq!(parsed.len(), 2);
+        assert_eq!(parsed.get("application/x-one"), Some("uno"));
+        assert_eq!(
+            parsed.mime_types().collect::<Vec<_>>(),
+            vec!["application/x-one", "web text/x-two"]
+        );
+    }
+
+    #[test]
+    fn test_parse_rejects_truncated() {
+        let mut custom = WebCustomData::new();
+        custom.insert("application/x-one", "data");
+        let pickle = custom.to_pickle();
+
+        assert!(WebCustomData::parse(&pickle[..pickle.len() - 4]).is_err());
+        assert!(WebCustomData::parse(&[0, 0]).is_err());
+
+        // Absurd entry count
+        let bogus = [4, 0, 0, 0, 0xFF, 0xFF, 0xFF, 0x7F];
+        assert!(WebCustomData::parse(&bogus).is_err());
+    }
+
+    #[test]
+    fn test_empty() {
+        let custom = WebCustomData::parse(&WebCustomData::new().to_pickle()).unwrap();
+        assert!(custom.is_empty());
+    }
+}
@@ -4,12 +4,16 @@
 
 use ironrdp_cliprdr::backend::CliprdrBackend;
 use ironrdp_cliprdr::pdu::{
-    ClipboardFormat as RdpClipboardFormat, ClipboardGeneralCapabilityFlags, FileContentsRequest, FileContentsResponse,
-    FormatDataRequest, FormatDataResponse, LockDataId,
+    ClipboardFormat as RdpClipboardFormat, ClipboardFormatName, ClipboardGeneralCapabilityFlags, FileContentsRequest,
+    FileContentsResponse, FormatDataRequest, FormatDataResponse, LockDataId,
 };
 use ironrdp_core::AsAny;
+use lamco_clipboard_core::CompatProfile;
 
+use crate::config::CliprdrConfig;
+use crate::downgrade::CapabilityDowngrade;
 use crate::event::{ClipboardEvent, ClipboardEventSender};
+use crate::role::CliprdrRole;
 
 /// RDP clipboard backend that bridges IronRDP and [`ClipboardSink`].
 ///
@@ -56,11 +60,23 @@ pub struct RdpCliprdrBackend {
     /// Negotiated capabilities
     capabilities: ClipboardGeneralCapabilityFlags,
 
+    /// Requested features the peer did not negotiate
+    downgrades: Vec<CapabilityDowngrade>,
+
     /// Remote formats currently available
     remote_formats: Vec<RdpClipboardFormat>,
 
     /// Whether backend is ready
     is_ready: bool,
+
+    /// Workarounds for the peer implementation
+    compat: CompatProfile,
+
+    /// End of the channel this backend serves
+    role: CliprdrRole,
+
+    /// Capabilities to advertise
+    config: CliprdrConfig,
 }
 
 impl RdpCliprdrBackend {
@@ -75,11 +91,51 @@ impl RdpCliprdrBackend {
             temp_dir,
             event_sender,
             capabilities: ClipboardGeneralCapabilityFlags::empty(),
+            downgrades: Vec::new(),
             remote_formats: Vec::new(),
             is_ready: false,
+            compat: CompatProfile::Standard,
+            role: CliprdrRole::Client,
+            config: CliprdrConfig::default(),
         }
     }
 
+    /// Advertise the capabilities `config` enables
+    pub fn with_config(mut self, config: CliprdrConfig) -> Self {
+        self.config = config;
+        self
+    }
+
+    /// Get the capability configuration
+    pub fn config(&self) -> CliprdrConfig {
+        self.config
+    }
+
+    /// Serve the given end of the channel
+    ///
+    /// A [`CliprdrRole::Server`] backend becomes ready once the client's
+    /// initial Format List arrives.
+    pub fn with_role(mut self, role: CliprdrRole) -> Self {
+        self.role = role;
+        self
+    }
+
+    /// Get the end of the channel this backend serves
+    pub fn role(&self) -> CliprdrRole {
+        self.role
+    }
+
+    /// Apply workarounds for a peer with known quirks, e.g. [`CompatProfile::Xrdp`]
+    pub fn with_compat_profile(mut self, compat: CompatProfile) -> Self {
+        self.compat = compat;
+        self
+    }
+
+    /// Get the compatibility profile in use
+    pub fn compat_profile(&self) -> CompatProfile {
+        self.compat
+    }
+
     /// Get the current remote formats
     pub fn remote_formats(&self) -> &[RdpClipboardFormat] {
         &self.remote_formats
@@ -91,10 +147,19 @@ impl RdpCliprdrBackend {
     }
 
     /// Get the negotiated capabilities
+    ///
+    /// Only ever contains capabilities this side advertised.
     pub fn capabilities(&self) -> ClipboardGeneralCapabilityFlags {
         self.capabilities
     }
 
+    /// Get the features lost in capability negotiation
+    ///
+    /// Empty until capabilities have been negotiated.
+    pub fn downgrades(&self) -> &[CapabilityDowngrade] {
+        &self.downgrades
+    }
+
     /// Create an event sender/receiver pair and backend
     pub fn create_with_channel(temp_dir: String) -> (Self, crate::ClipboardEventReceiver) {
         let sender = ClipboardEventSender::new();
@@ -104,6 +169,20 @@ impl RdpCliprdrBackend {
     }
 }
 
+impl RdpCliprdrBackend {
+    fn normalize_format(&self, format: &RdpClipboardFormat) -> RdpClipboardFormat {
+        let Some(name) = format.name() else {
+            return format.clone();
+        };
+
+        match self.compat.normalize_format_name(name.value()) {
+            Some(normalized) if normalized == name.value() => format.clone(),
+            Some(normalized) => RdpClipboardFormat::new(format.id()).with_name(ClipboardFormatName::new(normalized)),
+            None => RdpClipboardFormat::new(format.id()),
+        }
+    }
+}
+
 impl AsAny for RdpCliprdrBackend {
     fn as_any(&self) -> &dyn std::any::Any {
         self
@@ -120,17 +199,21 @@ impl CliprdrBackend for RdpCliprdrBackend {
     }
 
     fn client_capabilities(&self) -> ClipboardGeneralCapabilityFlags {
-        // Request support for long format names, file streaming, locking, and privacy
-        ClipboardGeneralCapabilityFlags::USE_LONG_FORMAT_NAMES
-            | ClipboardGeneralCapabilityFlags::STREAM_FILECLIP_ENABLED
-            | ClipboardGeneralCapabilityFlags::CAN_LOCK_CLIPDATA
-            // Privacy: don't include source file paths in clipboard data
-            // This prevents leaking the original file location from the remote system
-            | ClipboardGeneralCapabilityFlags::FILECLIP_NO_FILE_PATHS
+        let mut capabilities = self.config.capabilities();
+
+        // Don't offer locking to peers known to mishandle it
+        if !self.compat.uses_clip_data_locks(true) {
+            capabilities.remove(ClipboardGeneralCapabilityFlags::CAN_LOCK_CLIPDATA);
+        }
+
+        capabilities
     }
 
     fn on_ready(&mut self) {
-        tracing::debug!("Clipboard backend ready");
+        if self.is_ready {
+            return;
+        }
+        tracing::debug!("Clipboard backend ready ({})", self.role);
         self.is_ready = true;
         self.event_sender.send(ClipboardEvent::Ready);
     }
@@ -141,20 +224,43 @@ impl CliprdrBackend for RdpCliprdrBackend {
     }
 
     fn on_process_negotiated_capabilities(&mut self, capabilities: ClipboardGeneralCapabilityFlags) {
+        // Whatever the peer lists, only what both sides advertised is used
+        let capabilities = capabilities & self.client_capabilities();
         tracing::debug!("Negotiated capabilities: {:?}", capabilities);
         self.capabilities = capabilities;
         self.event_sender
             .send(ClipboardEvent::NegotiatedCapabilities(capabilities));
+
+        self.downgrades = CapabilityDowngrade::detect(self.client_capabilities(), capabilities);
+        if !self.downgrades.is_empty() {
+            for downgrade in &self.downgrades {
+                tracing::warn!("Clipboard capability downgrade: {}", downgrade);
+            }
+            self.event_sender.send(ClipboardEvent::CapabilitiesDowngraded {
+                downgrades: self.downgrades.clone(),
+            });
+        }
     }
 
     fn on_remote_copy(&mut self, available_formats: &[RdpClipboardFormat]) {
         tracing::debug!("Remote copy: {} formats available", available_formats.len());
 
-        // Store formats for later reference
-        self.remote_formats = available_formats.to_vec();
+        // The client's initial Format List completes the server's handshake
+        if self.role.is_server() {
+            self.on_ready();
+        }
+
+        // Clean up padded or truncated names before anything resolves them
+        let formats: Vec<RdpClipboardFormat> = available_formats
+            .iter()
+            .map(|format| self.normalize_format(format))
+            .collect();
 
         // Queue for async processing
-        self.event_sender.send(ClipboardEvent::remote_copy(available_formats));
+        self.event_sender.send(ClipboardEvent::remote_copy(&formats));
+
+        // Store formats for later reference
+        self.remote_formats = formats;
     }
 
     fn on_format_data_request(&mut self, request: FormatDataRequest) {
@@ -206,6 +312,7 @@ impl CliprdrBackend for RdpCliprdrBackend {
 #[cfg(test)]
 mod tests {
     use super::*;
+    use ironrdp_cliprdr::pdu::ClipboardFormatId;
 
     #[test]
     fn test_backend_creation() {
@@ -229,6 +336,24 @@ mod tests {
         assert!(matches!(events[0], ClipboardEvent::Ready));
     }
 
+    #[test]
+    fn test_server_ready_on_initial_format_list() {
+        let sender = ClipboardEventSender::new();
+        let receiver = sender.subscribe();
+        let mut backend = RdpCliprdrBackend::new("/tmp".to_string(), sender).with_role(CliprdrRole::Server);
+
+        backend.on_remote_copy(&[]);
+        assert!(backend.is_ready());
+        backend.on_ready();
+        backend.on_remote_copy(&[]);
+
+        let events = receiver.drain();
+        assert_eq!(events.len(), 3);
+        assert!(matches!(events[0], ClipboardEvent::Ready));
+        assert!(matches!(events[1], ClipboardEvent::RemoteCopy { .. }));
+        assert!(matches!(events[2], ClipboardEvent::RemoteCopy { .. }));
+    }
+
     #[test]
     fn test_client_capabilities() {
         let (backend, _) = RdpCliprdrBackend::create_with_channel("/tmp".to_string());
@@ -239,4 +364,75 @@ mod tests {
         assert!(caps.contains(ClipboardGeneralCapabilityFlags::CAN_LOCK_CLIPDATA));
         assert!(caps.contains(ClipboardGeneralCapabilityFlags::FILECLIP_NO_FILE_PATHS));
     }
+
+    #[test]
+    fn test_configured_capabilities() {
+        let (backend, receiver) = RdpCliprdrBackend::create_with_channel("/tmp".to_string());
+        let mut backend = backend.with_config(CliprdrConfig::default().with_locking(false).with_huge_files(true));
+
+        let caps = backend.client_capabilities();
+        assert!(!caps.contains(ClipboardGeneralCapabilityFlags::CAN_LOCK_CLIPDATA));
+        assert!(caps.contains(ClipboardGeneralCapabilityFlags::HUGE_FILE_SUPPORT_ENABLED));
+
+        // A peer listing everything only gets what was advertised
+        backend.on_process_negotiated_capabilities(ClipboardGeneralCapabilityFlags::all());
+        assert_eq!(backend.capabilities(), caps);
+        assert!(matches!(
+            &receiver.drain()[..],
+            [ClipboardEvent::NegotiatedCapabilities(negotiated)] if *negotiated == caps
+        ));
+    }
+
+    #[test]
+    fn test_capability_downgrades() {
+        let (mut backend, receiver) = RdpCliprdrBackend::create_with_channel("/tmp".to_string());
+        backend.on_process_negotiated_capabilities(ClipboardGeneralCapabilityFlags::USE_LONG_FORMAT_NAMES);
+
+        let expected = [CapabilityDowngrade::FileClipboard, CapabilityDowngrade::ClipDataLocking];
+        assert_eq!(backend.downgrades(), expected);
+
+        let events = receiver.drain();
+        assert_eq!(events.len(), 2);
+        assert!(matches!(
+            &events[1],
+            ClipboardEvent::CapabilitiesDowngraded { downgrades } if downgrades[..] == expected
+        ));
+
+        // A full set clears earlier downgrades and sends no warning
+        let caps = backend.client_capabilities();
+        backend.on_process_negotiated_capabilities(caps);
+        assert!(backend.downgrades().is_empty());
+        assert_eq!(receiver.drain().len(), 1);
+    }
+
+    #[test]
+    fn test_xrdp_profile() {
+        let (backend, receiver) = RdpCliprdrBackend::create_with_channel("/tmp".to_string());
+        let mut backend = backend.with_compat_profile(CompatProfile::Xrdp);
+
+        let caps = backend.client_capabilities();
+        assert!(!caps.contains(ClipboardGeneralCapabilityFlags::CAN_LOCK_CLIPDATA));
+        assert!(caps.contains(ClipboardGeneralCapabilityFlags::USE_LONG_FORMAT_NAMES));
+
+        backend.on_remote_copy(&[
+            RdpClipboardFormat::new(ClipboardFormatId::new(0xC004))
+                .with_name(ClipboardFormatName::new("HTML Format   ")),
+            RdpClipboardFormat::new(ClipboardFormatId::new(0xC07A))
+                .with_name(ClipboardFormatName::new("FileGroupDescrip")),
+        ]);
+
+        let names: Vec<_> = backend
+            .remote_formats()
+            .iter()
+            .map(|f| f.name().map(|n| n.value().to_string()))
+            .collect();
+        assert_eq!(
+            names,
+            vec![
+                Some("HTML Format".to_string()),
+                Some("FileGroupDescriptorW".to_string())
+            ]
+        );
+        assert!(matches!(receiver.drain()[0], ClipboardEvent::RemoteCopy { .. }));
+    }
 }
@@ -0,0 +1,1151 @@
+//! Bridge between the CLIPRDR channel and a [`ClipboardSink`].
+//!
+//! [`RdpCliprdrBackend`](crate::RdpCliprdrBackend) only queues what the peer
+//! sends. [`ClipboardBridge`] is the other half: it takes those events, runs
+//! them through the sans-io [`ClipboardSession`] (format mapping,
+//! conversion, loop detection), a [`FileServer`] for files copied here and a
+//! [`FileTransfer`] for files pasted from the peer, carries out the local
+//! side against the sink, and sends the resulting PDUs through IronRDP's
+//! [`ClipboardMessageProxy`].
+//!
+//! | Peer sends                  | Bridge does                                              |
+//! |-----------------------------|----------------------------------------------------------|
+//! | Format List                 | Announces the converted MIME types on the sink           |
+//! | Format Data Request         | Reads and converts local data, or builds the file list   |
+//! | Format Data Response        | Writes the pasted data, or starts receiving the files    |
+//! | File Contents Request       | Serves a size or range from the announced files          |
+//! | File Contents Response      | Feeds the transfer, writing finished files to the sink   |
+//! | Lock / Unlock Clipboard Data| Snapshots / releases the announced files                 |
+//!
+//! Format Data Responses carry no request ID, so the bridge remembers what
+//! each of its Format Data Requests was for and matches responses in order.
+//!
+//! Only negotiated capabilities are used: without file copy, local file
+//! lists are not announced; without huge file support, file lists with a
+//! file of 4 GiB or more are refused either way; without locking, pastes
+//! run unlocked. [`downgrades()`](ClipboardBridge::downgrades) lists what
+//! the peer left out, for host UIs to explain.
+//!
+//! Local changes go through a [`ChangeCoalescer`], so a burst of them, as
+//! clipboard managers produce, is announced with a single Format List, and
+//! a change to the content already announced is not announced again.
+//!
+//! A [`DirectionPolicy`] can disable either sync direction at runtime.
+//!
+//! As a [`CliprdrRole::Server`] the bridge holds local Format Lists back
+//! until the client's initial one arrives, then announces the local
+//! clipboard only if the client's was empty.
+
+use ironrdp_cliprdr::backend::{ClipboardMessage, ClipboardMessageProxy};
+use ironrdp_cliprdr::pdu::{
+    ClipboardFormat as RdpClipboardFormat, ClipboardFormatId, ClipboardFormatName, ClipboardGeneralCapabilityFlags,
+    FileContentsFlags, FileContentsRequest as RdpFileContentsRequest, FileContentsResponse, FormatDataResponse,
+};
+use lamco_clipboard_core::file_transfer::{
+    self, FileContentsKind, FileContentsRequest, FileServer, FileTransfer, FileTransferConfig,
+};
+use lamco_clipboard_core::formats::{ClipboardFormat, FileDescriptor};
+use lamco_clipboard_core::protocol::{self, ClipboardSession, Input, LocalAction, Output, PeerMessage};
+use lamco_clipboard_core::sanitize::parse_file_uris;
+use lamco_clipboard_core::{
+    AnnouncedFile, ChangeCoalescer, ClipDataLocks, ClipboardChange, ClipboardError, ClipboardSink,
+};
+use std::collections::VecDeque;
+use std::path::PathBuf;
+use std::sync::{Arc, Mutex, PoisonError};
+use std::time::Duration;
+
+use crate::direction::DirectionPolicy;
+use crate::downgrade::CapabilityDowngrade;
+use crate::error::{ClipboardRdpError, ClipboardRdpResult};
+use crate::event::{ClipboardEvent, ClipboardEventReceiver};
+use crate::role::CliprdrRole;
+
+/// Registered name of the file list format
+const FILE_LIST_FORMAT: &str = "FileGroupDescriptorW";
+
+/// Local MIME types a file copy is offered under
+const FILE_LIST_MIME_TYPES: &[&str] = &["text/uri-list", "x-special/gnome-copied-files"];
+
+/// What one of our Format Data Requests was for
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Requested {
+    /// Issued by the session for a local paste
+    Session,
+    /// The peer's file list, to receive its files
+    FileList,
+}
+
+/// Drives a CLIPRDR connection against a [`ClipboardSink`].
+///
+/// Feed it every [`ClipboardEvent`] the backend queues, and every local
+/// clipboard change and paste. Methods are async only because the sink is;
+/// the bridge itself never waits on the network.
+///
+/// # Example
+///
+/// ```rust,ignore
+/// use lamco_rdp_clipboard::{ClipboardBridge, RdpCliprdrBackend};
+///
+/// let (backend, events) = RdpCliprdrBackend::create_with_channel("/tmp/clipboard".to_string());
+/// // Hand `backend` to IronRDP's cliprdr, then:
+/// let mut bridge = ClipboardBridge::new(FileWriterSink::new(portal_sink, downloads), proxy);
+///
+/// loop {
+///     bridge.process(&events).await?;
+///     if let Some(change) = local_changes.try_recv() {
+///         bridge.local_changed(change).await?;
+///     }
+///     bridge.poll().await?;
+/// }
+/// ```
+#[derive(Debug)]
+pub struct ClipboardBridge<S, P> {
+    sink: S,
+    proxy: P,
+    session: ClipboardSession,
+
+    /// Files of the current local copy
+    files: FileServer,
+
+    /// Limits for files pasted from the peer
+    transfer_config: FileTransferConfig,
+
+    /// Files being pasted from the peer
+    transfer: Option<FileTransfer>,
+
+    /// Our Format Data Requests awaiting a response, oldest first
+    requested: VecDeque<Requested>,
+
+    /// Capabilities both sides support
+    capabilities: ClipboardGeneralCapabilityFlags,
+
+    /// Requested features the peer did not negotiate
+    downgrades: Vec<CapabilityDowngrade>,
+
+    /// Locks held on the peer's clipboard data
+    locks: ClipDataLocks,
+
+    /// Locks released since the last Unlock PDUs were sent
+    released: Arc<Mutex<Vec<u32>>>,
+
+    next_clip_data_id: u32,
+
+    /// End of the channel this side is
+    role: CliprdrRole,
+
+    /// Whether the peer's first Format List has arrived
+    remote_list_seen: bool,
+
+    /// Directions content may flow in
+    direction: DirectionPolicy,
+
+    /// Local changes waiting to be announced
+    coalescer: ChangeCoalescer,
+}
+
+impl<S: ClipboardSink, P: ClipboardMessageProxy> ClipboardBridge<S, P> {
+    /// Bridge `sink` to the channel `proxy` sends on
+    pub fn new(sink: S, proxy: P) -> Self {
+        let released = Arc::new(Mutex::new(Vec::new()));
+        let locks = {
+            let released = Arc::clone(&released);
+            ClipDataLocks::new(move |clip_data_id| {
+                released
+                    .lock()
+                    .unwrap_or_else(PoisonError::into_inner)
+                    .push(clip_data_id);
+            })
+        };
+        Self {
+            sink,
+            proxy,
+            session: ClipboardSession::new(),
+            files: FileServer::new(),
+            transfer_config: FileTransferConfig::default(),
+            transfer: None,
+            requested: VecDeque::new(),
+            capabilities: ClipboardGeneralCapabilityFlags::empty(),
+            downgrades: Vec::new(),
+            locks,
+            released,
+            next_clip_data_id: 0,
+            role: CliprdrRole::Client,
+            remote_list_seen: false,
+            direction: DirectionPolicy::default(),
+            coalescer: ChangeCoalescer::new(),
+        }
+    }
+
+    /// Enforce a direction policy; keep a clone to change it later
+    pub fn with_direction_policy(mut self, direction: DirectionPolicy) -> Self {
+        self.direction = direction;
+        self
+    }
+
+    /// Get the direction policy in force
+    pub fn direction_policy(&self) -> &DirectionPolicy {
+        &self.direction
+    }
+
+    /// Debounce local changes with a configured coalescer
+    ///
+    /// Give it zero delays to announce every change as it happens.
+    pub fn with_coalescer(mut self, coalescer: ChangeCoalescer) -> Self {
+        self.coalescer = coalescer;
+        self
+    }
+
+    /// Act as the given end of the channel
+    pub fn with_role(mut self, role: CliprdrRole) -> Self {
+        self.role = role;
+        self
+    }
+
+    /// Bridge plain text only
+    ///
+    /// Runs a [`ClipboardSession::text_only`] session and never touches
+    /// files. Pair it with backends advertising
+    /// [`CliprdrConfig::without_files`](crate::CliprdrConfig::without_files).
+    pub fn text_only(sink: S, proxy: P) -> Self {
+        Self::new(sink, proxy).with_session(ClipboardSession::text_only())
+    }
+
+    /// Use a configured session, e.g. with a custom converter or loop detection
+    pub fn with_session(mut self, session: ClipboardSession) -> Self {
+        self.session = session;
+        self
+    }
+
+    /// Serve local files with a configured server, e.g. with a policy or metrics
+    pub fn with_file_server(mut self, files: FileServer) -> Self {
+        self.files = files;
+        self
+    }
+
+    /// Set the limits for files pasted from the peer
+    pub fn with_transfer_config(mut self, config: FileTransferConfig) -> Self {
+        self.transfer_config = config;
+        self
+    }
+
+    /// Get the end of the channel this side is
+    pub fn role(&self) -> CliprdrRole {
+        self.role
+    }
+
+    /// Get the session state
+    pub fn session(&self) -> &ClipboardSession {
+        &self.session
+    }
+
+    /// Get the features lost because the peer did not negotiate them
+    ///
+    /// Empty until negotiation, and when the peer supports everything
+    /// this side requested.
+    pub fn downgrades(&self) -> &[CapabilityDowngrade] {
+        &self.downgrades
+    }
+
+    /// Get the file paste in progress, e.g. for its progress
+    pub fn transfer(&self) -> Option<&FileTransfer> {
+        self.transfer.as_ref()
+    }
+
+    /// Get the sink
+    pub fn sink(&self) -> &S {
+        &self.sink
+    }
+
+    /// Handle every event the backend has queued
+    ///
+    /// A failed event does not hold up the ones after it; the first error
+    /// is returned once all were handled.
+    pub async fn process(&mut self, receiver: &ClipboardEventReceiver) -> ClipboardRdpResult<()> {
+        let mut first_error = None;
+        for event in receiver.drain() {
+            if let Err(e) = self.handle_event(event).await {
+                tracing::warn!("Handling clipboard event failed: {}", e);
+                first_error.get_or_insert(e);
+            }
+        }
+        first_error.map_or(Ok(()), Err)
+    }
+
+    /// Handle one event queued by the backend
+    ///
+    /// Errors concern the local side only; the peer has been answered
+    /// before one is returned.
+    pub async fn handle_event(&mut self, event: ClipboardEvent) -> ClipboardRdpResult<()> {
+        let result = match event {
+            ClipboardEvent::Ready => Ok(()),
+            ClipboardEvent::RequestFormatList => {
+                let mime_types = self.session.local_mime_types().to_vec();
+                self.announce(mime_types).await;
+                Ok(())
+            }
+            ClipboardEvent::NegotiatedCapabilities(capabilities) => {
+                self.capabilities = capabilities;
+                // Downgrades, if any, follow in their own event
+                self.downgrades.clear();
+                Ok(())
+            }
+            ClipboardEvent::CapabilitiesDowngraded { downgrades } => {
+                self.downgrades = downgrades;
+                Ok(())
+            }
+            ClipboardEvent::RemoteCopy { formats } => {
+                // The peer owns the clipboard now: a held local change is
+                // stale, and copying the same local content again must be
+                // announced
+                self.coalescer.forget();
+                // A new copy makes the files of the previous one unreachable
+                if let Some(mut transfer) = self.transfer.take() {
+                    transfer.cancel();
+                }
+                // A server offers what was copied before the client connected,
+                // unless the client's clipboard has content of its own
+                let initial = !std::mem::replace(&mut self.remote_list_seen, true);
+                let offer = if self.role.is_server() && initial && formats.is_empty() {
+                    self.session.local_mime_types().to_vec()
+                } else {
+                    Vec::new()
+                };
+                if self.direction.get().allows_remote_to_host() {
+                    let formats = formats.iter().map(from_rdp_format).collect();
+                    self.drive(Input::RemoteFormatList(formats)).await;
+                } else {
+                    tracing::debug!("Remote to host sync disabled, ignoring the peer's Format List");
+                }
+                if !offer.is_empty() {
+                    self.announce(offer).await;
+                }
+                Ok(())
+            }
+            ClipboardEvent::FormatDataRequest { format_id } => {
+                let format_id = format_id.value();
+                if !self.direction.get().allows_host_to_remote() {
+                    tracing::debug!("Host to remote sync disabled, refusing Format Data Request");
+                    self.send(ClipboardMessage::SendFormatData(FormatDataResponse::new_error()));
+                } else if self.session.registry().local_name(format_id) == Some(FILE_LIST_FORMAT) {
+                    self.serve_file_list().await;
+                } else {
+                    self.drive(Input::FormatDataRequest { format_id }).await;
+                }
+                Ok(())
+            }
+            ClipboardEvent::FormatDataResponse { data, is_error } => match self.requested.pop_front() {
+                // Requested before the policy changed: fail the paste
+                Some(Requested::Session) if !self.direction.get().allows_remote_to_host() => {
+                    self.drive(Input::FormatDataResponse {
+                        data: Vec::new(),
+                        is_error: true,
+                    })
+                    .await;
+                    Err(ClipboardRdpError::DirectionBlocked(self.direction.get()))
+                }
+                Some(Requested::Session) => {
+                    self.drive(Input::FormatDataResponse { data, is_error }).await;
+                    Ok(())
+                }
+                Some(Requested::FileList) if !self.direction.get().allows_remote_to_host() => {
+                    Err(ClipboardRdpError::DirectionBlocked(self.direction.get()))
+                }
+                Some(Requested::FileList) => self.receive_files(&data, is_error).await,
+                None => {
+                    tracing::warn!("Format Data Response without a pending request");
+                    Ok(())
+                }
+            },
+            ClipboardEvent::FileContentsRequest {
+                stream_id,
+                index,
+                position,
+                size,
+                is_size_request,
+                clip_data_id,
+            } => {
+                let kind = if is_size_request {
+                    FileContentsKind::Size
+                } else {
+                    FileContentsKind::Range { position, size }
+                };
+                self.serve_file_contents(FileContentsRequest {
+                    stream_id,
+                    file_index: index,
+                    kind,
+                    clip_data_id,
+                });
+                Ok(())
+            }
+            ClipboardEvent::FileContentsResponse {
+                stream_id,
+                data,
+                is_error,
+            } => self.on_file_contents(stream_id, data, is_error).await,
+            ClipboardEvent::Lock { data_id } => {
+                self.files.lock(data_id);
+                Ok(())
+            }
+            ClipboardEvent::Unlock { data_id } => {
+                self.files.unlock(data_id);
+                Ok(())
+            }
+        };
+        self.send_unlocks();
+        result
+    }
+
+    /// Announce a change of the local clipboard to the peer
+    ///
+    /// The change is coalesced with the ones around it: the Format List goes
+    /// out once the clipboard has been quiet for the coalescer's quiet
+    /// period, from here or from [`poll()`](Self::poll), and is skipped if
+    /// its MIME types and content hash match the last one announced. Primary
+    /// selection changes are ignored, as CLIPRDR has no primary selection.
+    ///
+    /// A server only records the change until the client's initial Format
+    /// List has arrived. File lists are left out unless file copy was
+    /// negotiated. Nothing is sent while host to remote sync is disabled.
+    pub async fn local_changed(&mut self, change: ClipboardChange) -> ClipboardRdpResult<()> {
+        if change.is_primary {
+            tracing::debug!("Ignoring primary selection change");
+            return Ok(());
+        }
+        self.coalescer.push(change);
+        self.announce_changes().await;
+        Ok(())
+    }
+
+    /// Request the peer's content for a local paste of `mime_type`
+    ///
+    /// The data is written to the sink once the peer answers. A paste of a
+    /// file list MIME type receives the peer's files instead, each written
+    /// with [`ClipboardSink::write_file`].
+    pub async fn local_paste(&mut self, mime_type: &str) -> ClipboardRdpResult<()> {
+        let direction = self.direction.get();
+        if !direction.allows_remote_to_host() {
+            return Err(ClipboardRdpError::DirectionBlocked(direction));
+        }
+        let file_list = self
+            .session
+            .registry()
+            .remote_id(FILE_LIST_FORMAT)
+            .filter(|_| is_file_list_mime(mime_type));
+        match file_list {
+            Some(_) if !self.files_enabled() => Err(ClipboardRdpError::FileTransfer(
+                "the peer did not negotiate file copy".to_string(),
+            )),
+            Some(format_id) => {
+                self.requested.push_back(Requested::FileList);
+                self.send(ClipboardMessage::SendInitiatePaste(ClipboardFormatId::new(format_id)));
+                Ok(())
+            }
+            None => {
+                self.drive(Input::LocalPaste {
+                    mime_type: mime_type.to_string(),
+                })
+                .await;
+                Ok(())
+            }
+        }
+    }
+
+    /// Announce coalesced local changes, retry timed-out file requests and
+    /// issue held-back ones
+    ///
+    /// Call it when [`next_poll()`](Self::next_poll) comes due.
+    pub async fn poll(&mut self) -> ClipboardRdpResult<()> {
+        self.announce_changes().await;
+        if let Err(e) = self.stop_blocked_transfer() {
+            self.send_unlocks();
+            return Err(e);
+        }
+        let Some(transfer) = self.transfer.as_mut() else {
+            return Ok(());
+        };
+        let result = match transfer.poll() {
+            Ok(events) => file_transfer::drive(transfer, &self.sink, events).await,
+            Err(e) => Err(e),
+        };
+        let result = self.after_transfer_step(result);
+        self.send_unlocks();
+        result
+    }
+
+    /// Time until [`poll()`](Self::poll) has work to do
+    pub fn next_poll(&self) -> Option<Duration> {
+        let transfer = self.transfer.as_ref().and_then(FileTransfer::next_poll);
+        [self.coalescer.next_poll(), transfer].into_iter().flatten().min()
+    }
+
+    /// Announce the local changes whose burst has ended
+    async fn announce_changes(&mut self) {
+        while let Some(change) = self.coalescer.poll() {
+            self.announce(change.mime_types).await;
+        }
+    }
+
+    /// Announce local MIME types to the peer, bypassing the coalescer
+    async fn announce(&mut self, mut mime_types: Vec<String>) {
+        if !self.files_enabled() {
+            mime_types.retain(|mime| !is_file_list_mime(mime));
+        }
+        self.drive(Input::LocalChanged { mime_types }).await;
+    }
+
+    /// Run an input through the session and send what it produced
+    async fn drive(&mut self, input: Input) {
+        for output in protocol::drive(&mut self.session, &self.sink, input).await {
+            match output {
+                Output::Peer(message) => self.send_peer_message(message),
+                Output::Local(LocalAction::PasteFailed { mime_type }) => {
+                    tracing::warn!("Paste of {} from the peer failed", mime_type);
+                }
+                Output::Local(action) => tracing::debug!("Unhandled local action {:?}", action),
+            }
+        }
+    }
+
+    fn send_peer_message(&mut self, message: PeerMessage) {
+        let message = match message {
+            PeerMessage::FormatList(_) if self.role.is_server() && !self.remote_list_seen => {
+                tracing::debug!("Holding back the Format List until the client's arrives");
+                // Not announced, so the same content must not be skipped later
+                self.coalescer.forget();
+                return;
+            }
+            PeerMessage::FormatList(_) if !self.direction.get().allows_host_to_remote() => {
+                tracing::debug!("Host to remote sync disabled, holding back the Format List");
+                self.coalescer.forget();
+                return;
+            }
+            PeerMessage::FormatList(formats) => {
+                // Files in the previous copy are no longer offered
+                self.files.set_files(Vec::new());
+                ClipboardMessage::SendInitiateCopy(formats.iter().map(to_rdp_format).collect())
+            }
+            PeerMessage::FormatDataRequest { format_id } => {
+                self.requested.push_back(Requested::Session);
+                ClipboardMessage::SendInitiatePaste(ClipboardFormatId::new(format_id))
+            }
+            PeerMessage::FormatDataResponse(data) => {
+                ClipboardMessage::SendFormatData(FormatDataResponse::new_data(data))
+            }
+            PeerMessage::FormatDataError => ClipboardMessage::SendFormatData(FormatDataResponse::new_error()),
+        };
+        self.send(message);
+    }
+
+    /// Answer the peer's request for our file list, snapshotting the files
+    async fn serve_file_list(&mut self) {
+        let response = match self.read_local_files().await {
+            Ok((paths, data)) => {
+                tracing::debug!("Serving a file list of {} entries", paths.len());
+                match paths.into_iter().map(AnnouncedFile::snapshot).collect() {
+                    Ok(files) => {
+                        self.files.set_files(files);
+                        FormatDataResponse::new_data(data)
+                    }
+                    Err(e) => {
+                        tracing::warn!("Announced files are unreadable: {}", e);
+                        FormatDataResponse::new_error()
+                    }
+                }
+            }
+            Err(e) => {
+                tracing::warn!("Building the file list failed: {}", e);
+                FormatDataResponse::new_error()
+            }
+        };
+        self.send(ClipboardMessage::SendFormatData(response));
+    }
+
+    /// Paths of the local file copy and their FileGroupDescriptorW
+    async fn read_local_files(&self) -> Result<(Vec<PathBuf>, Vec<u8>), ClipboardError> {
+        let mime_type = self
+            .session
+            .local_mime_types()
+            .iter()
+            .find(|mime| is_file_list_mime(mime))
+            .ok_or_else(|| ClipboardError::UnsupportedFormat(FILE_LIST_FORMAT.to_string()))?;
+        let data = self.sink.read_clipboard(mime_type).await?;
+        let paths = if mime_essence(mime_type) == "x-special/gnome-copied-files" {
+            let (_, uri_list) = self.session.converter().parse_gnome_copied_files(&data)?;
+            parse_file_uris(uri_list.as_bytes())
+        } else {
+            parse_file_uris(&data)
+        };
+        let descriptors = FileDescriptor::build_list(&paths)?;
+        if !self.huge_files_enabled() {
+            let max = u64::from(u32::MAX);
+            if let Some(size) = FileDescriptor::parse_list(&descriptors)?
+                .iter()
+                .filter_map(|descriptor| descriptor.size)
+                .find(|&size| size > max)
+            {
+                return Err(ClipboardError::DataSizeExceeded {
+                    actual: usize::try_from(size).unwrap_or(usize::MAX),
+                    max: max as usize,
+                });
+            }
+        }
+        Ok((paths, descriptors))
+    }
+
+    fn serve_file_contents(&mut self, request: FileContentsRequest) {
+        let stream_id = request.stream_id;
+        if !self.direction.get().allows_host_to_remote() {
+            tracing::debug!(
+                "Host to remote sync disabled, refusing File Contents Request {}",
+                stream_id
+            );
+            self.send(ClipboardMessage::SendFileContentsResponse(
+                FileContentsResponse::new_error(stream_id),
+            ));
+            return;
+        }
+        let response = match (self.files.serve(&request), request.kind) {
+            (Ok(data), FileContentsKind::Size) => match data.try_into() {
+                Ok(size) => FileContentsResponse::new_size_response(stream_id, u64::from_le_bytes(size)),
+                Err(_) => FileContentsResponse::new_error(stream_id),
+            },
+            (Ok(data), FileContentsKind::Range { .. }) => FileContentsResponse::new_data_response(stream_id, data),
+            (Err(e), _) => {
+                tracing::debug!("File Contents Request {} refused: {}", stream_id, e);
+                FileContentsResponse::new_error(stream_id)
+            }
+        };
+        self.send(ClipboardMessage::SendFileContentsResponse(response));
+    }
+
+    /// Start receiving the files of the peer's file list
+    async fn receive_files(&mut self, data: &[u8], is_error: bool) -> ClipboardRdpResult<()> {
+        if is_error {
+            return Err(ClipboardRdpError::FileTransfer(
+                "the peer refused its file list".to_string(),
+            ));
+        }
+        let descriptors = FileDescriptor::parse_list(data)?;
+        if !self.huge_files_enabled() && descriptors.iter().any(|d| d.size > Some(u32::MAX.into())) {
+            return Err(ClipboardRdpError::FileTransfer(
+                "the peer offered a file of 4 GiB or more without huge file support".to_string(),
+            ));
+        }
+        let mut transfer = FileTransfer::new(&descriptors).with_config(self.transfer_config.clone());
+        if self
+            .capabilities
+            .contains(ClipboardGeneralCapabilityFlags::CAN_LOCK_CLIPDATA)
+        {
+            let clip_data_id = self.next_clip_data_id;
+            self.next_clip_data_id = self.next_clip_data_id.wrapping_add(1);
+            transfer = transfer.with_lock(self.locks.acquire(clip_data_id)?);
+            self.send(ClipboardMessage::SendLockClipboard { clip_data_id });
+        }
+        tracing::debug!("Receiving {} files from the peer", descriptors.len());
+
+        let transfer = self.transfer.insert(transfer);
+        let result = match transfer.start() {
+            Ok(events) => file_transfer::drive(transfer, &self.sink, events).await,
+            Err(e) => Err(e),
+        };
+        self.after_transfer_step(result)
+    }
+
+    async fn on_file_contents(&mut self, stream_id: u32, data: Vec<u8>, is_error: bool) -> ClipboardRdpResult<()> {
+        self.stop_blocked_transfer()?;
+        let Some(transfer) = self.transfer.as_mut() else {
+            tracing::debug!("File Contents Response {} without a transfer", stream_id);
+            return Ok(());
+        };
+        let result = match transfer.on_response(stream_id, data, is_error) {
+            Ok(events) => file_transfer::drive(transfer, &self.sink, events).await,
+            Err(e) => Err(e),
+        };
+        self.after_transfer_step(result)
+    }
+
+    /// Cancel a file paste remote to host sync no longer allows
+    fn stop_blocked_transfer(&mut self) -> ClipboardRdpResult<()> {
+        let direction = self.direction.get();
+        match self.transfer.take() {
+            Some(mut transfer) if !direction.allows_remote_to_host() => {
+                transfer.cancel();
+                Err(ClipboardRdpError::DirectionBlocked(direction))
+            }
+            transfer => {
+                self.transfer = transfer;
+                Ok(())
+            }
+        }
+    }
+
+    /// Send the requests a transfer step produced, and drop the transfer
+    /// once it is over
+    fn after_transfer_step(
+        &mut self,
+        result: Result<Vec<FileContentsRequest>, ClipboardError>,
+    ) -> ClipboardRdpResult<()> {
+        let requests = match result {
+            Ok(requests) => requests,
+            Err(ClipboardError::TransferCancelled) => Vec::new(),
+            Err(e) => {
+                self.transfer = None;
+                return Err(e.into());
+            }
+        };
+        for request in &requests {
+            self.send(ClipboardMessage::SendFileContentsRequest(to_rdp_request(request)));
+        }
+        if self
+            .transfer
+            .as_ref()
+            .is_some_and(|transfer| transfer.state().is_finished())
+        {
+            tracing::debug!("File paste finished");
+            self.transfer = None;
+        }
+        Ok(())
+    }
+
+    /// Send Unlock PDUs for locks whose transfers ended
+    fn send_unlocks(&mut self) {
+        let released = std::mem::take(&mut *self.released.lock().unwrap_or_else(PoisonError::into_inner));
+        for clip_data_id in released {
+            self.send(ClipboardMessage::SendUnlockClipboard { clip_data_id });
+        }
+    }
+
+    fn files_enabled(&self) -> bool {
+        !self.session.is_text_only()
+            && self
+                .capabilities
+                .contains(ClipboardGeneralCapabilityFlags::STREAM_FILECLIP_ENABLED)
+    }
+
+    fn huge_files_enabled(&self) -> bool {
+        self.capabilities
+            .contains(ClipboardGeneralCapabilityFlags::HUGE_FILE_SUPPORT_ENABLED)
+    }
+
+    fn send(&self, message: ClipboardMessage) {
+        self.proxy.send_clipboard_message(message);
+    }
+}
+
+fn from_rdp_format(format: &RdpClipboardFormat) -> ClipboardFormat {
+    match format.name() {
+        Some(name) => ClipboardFormat::with_name(format.id().value(), name.value()),
+        None => ClipboardFormat::new(format.id().value()),
+    }
+}
+
+fn to_rdp_format(format: &ClipboardFormat) -> RdpClipboardFormat {
+    let rdp = RdpClipboardFormat::new(ClipboardFormatId::new(format.id));
+    match &format.name {
+        Some(name) => rdp.with_name(ClipboardFormatName::new(name.clone())),
+        None => rdp,
+    }
+}
+
+fn to_rdp_request(request: &FileContentsRequest) -> RdpFileContentsRequest {
+    let (flags, position, requested_size) = match request.kind {
+        // Size requests always ask for the 8 bytes of a 64-bit size
+        FileContentsKind::Size => (FileContentsFlags::SIZE, 0, 8),
+        FileContentsKind::Range { position, size } => (FileContentsFlags::RANGE, position, size),
+    };
+    RdpFileContentsRequest {
+        stream_id: request.stream_id,
+        index: request.file_index,
+        flags,
+        position,
+        requested_size,
+        data_id: request.clip_data_id,
+    }
+}
+
+/// Whether a local MIME type carries a file copy
+fn is_file_list_mime(mime: &str) -> bool {
+    FILE_LIST_MIME_TYPES.contains(&mime_essence(mime))
+}
+
+/// MIME type without parameters (`text/plain;charset=utf-8` -> `text/plain`)
+fn mime_essence(mime: &str) -> &str {
+    mime.split(';').next().unwrap_or(mime).trim()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::direction::ClipboardDirection;
+    use crate::RdpCliprdrBackend;
+    use ironrdp_cliprdr::backend::CliprdrBackend;
+    use lamco_clipboard_core::formats::FileDescriptorFlags;
+    use lamco_clipboard_core::testing::block_on;
+    use lamco_clipboard_core::testing::sink::MemorySink;
+    use lamco_clipboard_core::{CoalesceConfig, ManualClock};
+
+    #[derive(Debug, Clone, Default)]
+    struct RecordingProxy(Arc<Mutex<Vec<ClipboardMessage>>>);
+
+    impl RecordingProxy {
+        fn take(&self) -> Vec<ClipboardMessage> {
+            std::mem::take(&mut *self.0.lock().unwrap())
+        }
+    }
+
+    impl ClipboardMessageProxy for RecordingProxy {
+        fn send_clipboard_message(&self, message: ClipboardMessage) {
+            self.0.lock().unwrap().push(message);
+        }
+    }
+
+    /// Coalescer announcing every change at once
+    fn immediate() -> ChangeCoalescer {
+        ChangeCoalescer::new().with_config(CoalesceConfig::default().with_delays(0, 0))
+    }
+
+    fn change(mime_types: &[&str]) -> ClipboardChange {
+        ClipboardChange::new(mime_types.iter().map(|mime| mime.to_string()).collect())
+    }
+
+    fn utf16(text: &str) -> Vec<u8> {
+        text.encode_utf16().chain([0]).flat_map(u16::to_le_bytes).collect()
+    }
+
+    #[test]
+    fn test_text_both_ways() {
+        let proxy = RecordingProxy::default();
+        let mut bridge = ClipboardBridge::new(MemorySink::default(), proxy.clone()).with_coalescer(immediate());
+
+        // Local copy: announced, then served when the peer pastes
+        bridge
+            .sink()
+            .clipboard
+            .lock()
+            .unwrap()
+            .push(("text/plain".to_string(), b"Hi".to_vec()));
+        block_on(bridge.local_changed(change(&["text/plain"]))).unwrap();
+        let sent = proxy.take();
+        let [ClipboardMessage::SendInitiateCopy(formats)] = &sent[..] else {
+            panic!("expected a Format List, got {:?}", sent);
+        };
+        assert!(formats.iter().any(|format| format.id().value() == 13));
+
+        block_on(bridge.handle_event(ClipboardEvent::FormatDataRequest {
+            format_id: ClipboardFormatId::new(13),
+        }))
+        .unwrap();
+        let sent = proxy.take();
+        let [ClipboardMessage::SendFormatData(response)] = &sent[..] else {
+            panic!("expected a Format Data Response, got {:?}", sent);
+        };
+        assert_eq!(response.data(), utf16("Hi"));
+
+        // Remote copy: pasted locally once the peer answers
+        block_on(
+            bridge.handle_event(ClipboardEvent::remote_copy(&[RdpClipboardFormat::new(
+                ClipboardFormatId::new(13),
+            )])),
+        )
+        .unwrap();
+        block_on(bridge.local_paste("text/plain")).unwrap();
+        assert!(matches!(
+            &proxy.take()[..],
+            [ClipboardMessage::SendInitiatePaste(id)] if id.value() == 13
+        ));
+        block_on(bridge.handle_event(ClipboardEvent::FormatDataResponse {
+            data: utf16("Yo"),
+            is_error: false,
+        }))
+        .unwrap();
+        assert_eq!(
+            bridge.sink().clipboard().last(),
+            Some(&("text/plain".to_string(), b"Yo".to_vec()))
+        );
+    }
+
+    #[test]
+    fn test_changes_coalesced() {
+        let proxy = RecordingProxy::default();
+        let clock = ManualClock::new();
+        let mut bridge = ClipboardBridge::new(MemorySink::default(), proxy.clone())
+            .with_coalescer(ChangeCoalescer::new().with_clock(clock.clone()));
+
+        // A clipboard manager setting formats one at a time
+        for mime_types in [&["text/plain"][..], &["text/plain", "text/html"]] {
+            block_on(bridge.local_changed(change(mime_types).with_hash("a"))).unwrap();
+            clock.advance(Duration::from_millis(20));
+        }
+        block_on(bridge.local_changed(change(&["text/plain", "text/html"]).with_hash("a"))).unwrap();
+        block_on(bridge.poll()).unwrap();
+        assert!(proxy.take().is_empty());
+
+        clock.advance(bridge.next_poll().unwrap());
+        block_on(bridge.poll()).unwrap();
+        let sent = proxy.take();
+        let [ClipboardMessage::SendInitiateCopy(formats)] = &sent[..] else {
+            panic!("expected one Format List, got {:?}", sent);
+        };
+        assert!(formats.iter().any(|format| format.id().value() == 13));
+        assert_eq!(bridge.next_poll(), None);
+
+        // The same content again is not re-announced
+        block_on(bridge.local_changed(change(&["text/html", "text/plain"]).with_hash("a"))).unwrap();
+        clock.advance(Duration::from_secs(1));
+        block_on(bridge.poll()).unwrap();
+        assert!(proxy.take().is_empty());
+
+        // Unless the peer copied something in between
+        block_on(
+            bridge.handle_event(ClipboardEvent::remote_copy(&[RdpClipboardFormat::new(
+                ClipboardFormatId::new(8),
+            )])),
+        )
+        .unwrap();
+        block_on(bridge.local_changed(change(&["text/plain", "text/html"]).with_hash("a"))).unwrap();
+        clock.advance(Duration::from_secs(1));
+        block_on(bridge.poll()).unwrap();
+        assert!(matches!(&proxy.take()[..], [ClipboardMessage::SendInitiateCopy(_)]));
+
+        // A local copy still held when the peer copies is never announced
+        block_on(bridge.local_changed(change(&["text/plain"]).with_hash("b"))).unwrap();
+        block_on(
+            bridge.handle_event(ClipboardEvent::remote_copy(&[RdpClipboardFormat::new(
+                ClipboardFormatId::new(8),
+            )])),
+        )
+        .unwrap();
+        clock.advance(Duration::from_secs(1));
+        block_on(bridge.poll()).unwrap();
+        assert!(proxy.take().is_empty());
+        assert_eq!(bridge.next_poll(), None);
+    }
+
+    #[test]
+    fn test_server_role() {
+        let proxy = RecordingProxy::default();
+        let mut bridge = ClipboardBridge::new(MemorySink::default(), proxy.clone())
+            .with_coalescer(immediate())
+            .with_role(CliprdrRole::Server);
+
+        // Copied before the client connected: held until its list arrives
+        block_on(bridge.local_changed(change(&["text/plain"]))).unwrap();
+        assert!(proxy.take().is_empty());
+
+        // The client's clipboard is empty, so ours is offered
+        block_on(bridge.handle_event(ClipboardEvent::remote_copy(&[]))).unwrap();
+        assert!(matches!(&proxy.take()[..], [ClipboardMessage::SendInitiateCopy(_)]));
+
+        // The client starting out with content keeps it
+        let proxy = RecordingProxy::default();
+        let mut bridge = ClipboardBridge::new(MemorySink::default(), proxy.clone())
+            .with_coalescer(immediate())
+            .with_role(CliprdrRole::Server);
+        block_on(bridge.local_changed(change(&["text/plain"]))).unwrap();
+        block_on(
+            bridge.handle_event(ClipboardEvent::remote_copy(&[RdpClipboardFormat::new(
+                ClipboardFormatId::new(13),
+            )])),
+        )
+        .unwrap();
+        assert!(proxy.take().is_empty());
+
+        // Later changes go straight out, and client pastes are served
+        bridge
+            .sink()
+            .clipboard
+            .lock()
+            .unwrap()
+            .push(("text/plain".to_string(), b"Hi".to_vec()));
+        block_on(bridge.local_changed(change(&["text/plain"]))).unwrap();
+        assert!(matches!(&proxy.take()[..], [ClipboardMessage::SendInitiateCopy(_)]));
+        block_on(bridge.handle_event(ClipboardEvent::FormatDataRequest {
+            format_id: ClipboardFormatId::new(13),
+        }))
+        .unwrap();
+        let sent = proxy.take();
+        let [ClipboardMessage::SendFormatData(response)] = &sent[..] else {
+            panic!("expected a Format Data Response, got {:?}", sent);
+        };
+        assert_eq!(response.data(), utf16("Hi"));
+    }
+
+    #[test]
+    fn test_files_need_negotiation() {
+        let proxy = RecordingProxy::default();
+        let mut bridge = ClipboardBridge::new(MemorySink::default(), proxy.clone()).with_coalescer(immediate());
+        block_on(bridge.handle_event(ClipboardEvent::NegotiatedCapabilities(
+            ClipboardGeneralCapabilityFlags::USE_LONG_FORMAT_NAMES,
+        )))
+        .unwrap();
+
+        // A local file copy announces no file list
+        block_on(bridge.local_changed(change(&["text/uri-list", "text/plain"]))).unwrap();
+        let sent = proxy.take();
+        let [ClipboardMessage::SendInitiateCopy(formats)] = &sent[..] else {
+            panic!("expected a Format List, got {:?}", sent);
+        };
+        assert!(!formats
+            .iter()
+            .any(|format| format.name().map(|name| name.value()) == Some(FILE_LIST_FORMAT)));
+
+        // Nor can the peer's files be pasted
+        block_on(
+            bridge.handle_event(ClipboardEvent::remote_copy(&[RdpClipboardFormat::new(
+                ClipboardFormatId::new(0xC0A0),
+            )
+            .with_name(ClipboardFormatName::new(FILE_LIST_FORMAT))])),
+        )
+        .unwrap();
+        assert!(matches!(
+            block_on(bridge.local_paste("text/uri-list")),
+            Err(ClipboardRdpError::FileTransfer(_))
+        ));
+        assert!(proxy.take().is_empty());
+    }
+
+    #[test]
+    fn test_direction_policy() {
+        let proxy = RecordingProxy::default();
+        let policy = DirectionPolicy::new(ClipboardDirection::RemoteToHost);
+        let mut bridge = ClipboardBridge::new(MemorySink::default(), proxy.clone())
+            .with_coalescer(immediate())
+            .with_direction_policy(policy.clone());
+        bridge
+            .sink()
+            .clipboard
+            .lock()
+            .unwrap()
+            .push(("text/plain".to_string(), b"Hi".to_vec()));
+
+        // Nothing leaves: no Format List, and requests are refused
+        block_on(bridge.local_changed(change(&["text/plain"]))).unwrap();
+        assert!(proxy.take().is_empty());
+        block_on(bridge.handle_event(ClipboardEvent::FormatDataRequest {
+            format_id: ClipboardFormatId::new(13),
+        }))
+        .unwrap();
+        let sent = proxy.take();
+        let [ClipboardMessage::SendFormatData(response)] = &sent[..] else {
+            panic!("expected a Format Data Response, got {:?}", sent);
+        };
+        assert!(response.is_error());
+
+        // Flipped at runtime: the peer's content is ignored, ours goes out
+        policy.set(ClipboardDirection::HostToRemote);
+        block_on(
+            bridge.handle_event(ClipboardEvent::remote_copy(&[RdpClipboardFormat::new(
+                ClipboardFormatId::new(13),
+            )])),
+        )
+        .unwrap();
+        assert!(matches!(
+            block_on(bridge.local_paste("text/plain")),
+            Err(ClipboardRdpError::DirectionBlocked(ClipboardDirection::HostToRemote))
+        ));
+        assert!(proxy.take().is_empty());
+        block_on(bridge.local_changed(change(&["text/plain"]))).unwrap();
+        assert!(matches!(&proxy.take()[..], [ClipboardMessage::SendInitiateCopy(_)]));
+    }
+
+    #[test]
+    fn test_text_only() {
+        let proxy = RecordingProxy::default();
+        let mut bridge = ClipboardBridge::text_only(MemorySink::default(), proxy.clone()).with_coalescer(immediate());
+        block_on(bridge.handle_event(ClipboardEvent::NegotiatedCapabilities(
+            ClipboardGeneralCapabilityFlags::STREAM_FILECLIP_ENABLED,
+        )))
+        .unwrap();
+
+        block_on(bridge.local_changed(change(&["text/uri-list", "image/png", "text/plain"]))).unwrap();
+        let sent = proxy.take();
+        let [ClipboardMessage::SendInitiateCopy(formats)] = &sent[..] else {
+            panic!("expected a Format List, got {:?}", sent);
+        };
+        let ids: Vec<u32> = formats.iter().map(|format| format.id().value()).collect();
+        assert_eq!(ids, [13]);
+    }
+
+    #[test]
+    fn test_capability_downgrades() {
+        let proxy = RecordingProxy::default();
+        let mut bridge = ClipboardBridge::new(MemorySink::default(), proxy.clone());
+        let (mut backend, receiver) = RdpCliprdrBackend::create_with_channel("/tmp".to_string());
+
+        backend.on_process_negotiated_capabilities(ClipboardGeneralCapabilityFlags::USE_LONG_FORMAT_NAMES);
+        block_on(bridge.process(&receiver)).unwrap();
+        assert_eq!(
+            bridge.downgrades(),
+            [CapabilityDowngrade::FileClipboard, CapabilityDowngrade::ClipDataLocking]
+        );
+
+        // Renegotiating with everything clears them
+        let caps = backend.client_capabilities();
+        backend.on_process_negotiated_capabilities(caps);
+        block_on(bridge.process(&receiver)).unwrap();
+        assert!(bridge.downgrades().is_empty());
+    }
+
+    #[test]
+    fn test_files_pasted_from_peer() {
+        let proxy = RecordingProxy::default();
+        let mut bridge = ClipboardBridge::new(MemorySink::default(), proxy.clone()).with_coalescer(immediate());
+        let capabilities = ClipboardGeneralCapabilityFlags::STREAM_FILECLIP_ENABLED
+            | ClipboardGeneralCapabilityFlags::CAN_LOCK_CLIPDATA;
+        block_on(bridge.handle_event(ClipboardEvent::NegotiatedCapabilities(capabilities))).unwrap();
+
+        block_on(
+            bridge.handle_event(ClipboardEvent::remote_copy(&[RdpClipboardFormat::new(
+                ClipboardFormatId::new(0xC0A0),
+            )
+            .with_name(ClipboardFormatName::new(FILE_LIST_FORMAT))])),
+        )
+        .unwrap();
+        block_on(bridge.local_paste("text/uri-list")).unwrap();
+        assert!(matches!(
+            &proxy.take()[..],
+            [ClipboardMessage::SendInitiatePaste(id)] if id.value() == 0xC0A0
+        ));
+
+        let descriptor = FileDescriptor {
+            flags: FileDescriptorFlags::from_raw(FileDescriptorFlags::FILESIZE),
+            attributes: 0,
+            creation_time: None,
+            access_time: None,
+            write_time: None,
+            size: Some(5),
+            name: "notes.txt".to_string(),
+        };
+        let mut list = 1u32.to_le_bytes().to_vec();
+        list.extend(descriptor.to_bytes());
+        block_on(bridge.handle_event(ClipboardEvent::FormatDataResponse {
+            data: list,
+            is_error: false,
+        }))
+        .unwrap();
+        let sent = proxy.take();
+        let [ClipboardMessage::SendLockClipboard { clip_data_id: 0 }, ClipboardMessage::SendFileContentsRequest(request)] =
+            &sent[..]
+        else {
+            panic!("expected a lock and a request, got {:?}", sent);
+        };
+        assert_eq!(request.data_id, Some(0));
+
+        block_on(bridge.handle_event(ClipboardEvent::FileContentsResponse {
+            stream_id: request.stream_id,
+            data: b"hello".to_vec(),
+            is_error: false,
+        }))
+        .unwrap();
+        assert_eq!(
+            bridge.sink().written_files(),
+            vec![("notes.txt".to_string(), b"hello".to_vec())]
+        );
+        assert!(bridge.transfer().is_none());
+        assert!(matches!(
+            &proxy.take()[..],
+            [ClipboardMessage::SendUnlockClipboard { clip_data_id: 0 }]
+        ));
+    }
+}
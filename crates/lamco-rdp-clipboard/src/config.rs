@@ -0,0 +1,110 @@
+//! Capabilities this side advertises on the clipboard channel.
+//!
+//! Both ends send a General Capability Set and only features both list are
+//! used. [`CliprdrConfig`] chooses what this side lists; the backend reports
+//! the intersection with the peer's as
+//! [`NegotiatedCapabilities`](crate::ClipboardEvent::NegotiatedCapabilities),
+//! and [`ClipboardBridge`](crate::ClipboardBridge) only uses what is in it.
+//!
+//! Locking, path stripping and huge files only concern file copy, so they are
+//! advertised only together with [`file_transfer`](CliprdrConfig::file_transfer).
+
+use ironrdp_cliprdr::pdu::ClipboardGeneralCapabilityFlags;
+
+/// Clipboard features to advertise to the peer
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct CliprdrConfig {
+    /// Copy files through the clipboard (`CB_STREAM_FILECLIP_ENABLED`)
+    pub file_transfer: bool,
+
+    /// Keep source paths out of file lists (`CB_FILECLIP_NO_FILE_PATHS`)
+    pub no_file_paths: bool,
+
+    /// Lock clipboard data during file pastes (`CB_CAN_LOCK_CLIPDATA`)
+    pub locking: bool,
+
+    /// Copy files of 4 GiB and more (`CB_HUGE_FILE_SUPPORT_ENABLED`)
+    pub huge_files: bool,
+}
+
+impl Default for CliprdrConfig {
+    fn default() -> Self {
+        Self {
+            file_transfer: true,
+            no_file_paths: true,
+            locking: true,
+            huge_files: false,
+        }
+    }
+}
+
+impl CliprdrConfig {
+    /// Advertise text and image formats only
+    pub fn without_files() -> Self {
+        Self {
+            file_transfer: false,
+            ..Self::default()
+        }
+    }
+
+    /// Set whether files can be copied
+    pub fn with_file_transfer(mut self, enabled: bool) -> Self {
+        self.file_transfer = enabled;
+        self
+    }
+
+    /// Set whether source paths are kept out of file lists
+    pub fn with_no_file_paths(mut self, enabled: bool) -> Self {
+        self.no_file_paths = enabled;
+        self
+    }
+
+    /// Set whether clipboard data is locked during file pastes
+    pub fn with_locking(mut self, enabled: bool) -> Self {
+        self.locking = enabled;
+        self
+    }
+
+    /// Set whether files of 4 GiB and more can be copied
+    pub fn with_huge_files(mut self, enabled: bool) -> Self {
+        self.huge_files = enabled;
+        self
+    }
+
+    /// Capability flags to advertise
+    ///
+    /// Long format names are always requested.
+    pub fn capabilities(&self) -> ClipboardGeneralCapabilityFlags {
+        let mut capabilities = ClipboardGeneralCapabilityFlags::USE_LONG_FORMAT_NAMES;
+        if self.file_transfer {
+            capabilities |= ClipboardGeneralCapabilityFlags::STREAM_FILECLIP_ENABLED;
+            if self.no_file_paths {
+                capabilities |= ClipboardGeneralCapabilityFlags::FILECLIP_NO_FILE_PATHS;
+            }
+            if self.locking {
+                capabilities |= ClipboardGeneralCapabilityFlags::CAN_LOCK_CLIPDATA;
+            }
+            if self.huge_files {
+                capabilities |= ClipboardGeneralCapabilityFlags::HUGE_FILE_SUPPORT_ENABLED;
+            }
+        }
+        capabilities
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_capabilities() {
+        let capabilities = CliprdrConfig::default().capabilities();
+        assert!(capabilities.contains(ClipboardGeneralCapabilityFlags::STREAM_FILECLIP_ENABLED));
+        assert!(capabilities.contains(ClipboardGeneralCapabilityFlags::CAN_LOCK_CLIPDATA));
+        assert!(!capabilities.contains(ClipboardGeneralCapabilityFlags::HUGE_FILE_SUPPORT_ENABLED));
+
+        // File options are dropped along with file copy
+        let capabilities = CliprdrConfig::without_files().with_huge_files(true).capabilities();
+        assert_eq!(capabilities, ClipboardGeneralCapabilityFlags::USE_LONG_FORMAT_NAMES);
+    }
+}
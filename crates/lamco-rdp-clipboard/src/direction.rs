@@ -0,0 +1,130 @@
+//! Which way clipboard content may flow.
+//!
+//! VDI deployments commonly allow pasting into a session but not copying out
+//! of it, or the reverse. A [`DirectionPolicy`] is a shared handle to the
+//! [`ClipboardDirection`] in force; [`ClipboardBridge`](crate::ClipboardBridge)
+//! checks it on every event, so it can be changed at runtime, e.g. from an
+//! admin console while the bridge runs on its own task.
+//!
+//! | Blocked direction | Bridge does                                                         |
+//! |-------------------|---------------------------------------------------------------------|
+//! | Host → remote     | Holds back Format Lists, refuses the peer's data and file requests  |
+//! | Remote → host     | Ignores the peer's Format Lists, refuses and stops local pastes     |
+
+use std::fmt;
+use std::str::FromStr;
+use std::sync::atomic::{AtomicU8, Ordering};
+use std::sync::Arc;
+
+/// Directions clipboard content may flow in
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Hash)]
+pub enum ClipboardDirection {
+    /// Copy and paste both ways
+    #[default]
+    Both,
+
+    /// Local content may be pasted in the remote session only
+    HostToRemote,
+
+    /// Remote content may be pasted locally only
+    RemoteToHost,
+
+    /// Clipboard sync is off
+    None,
+}
+
+impl ClipboardDirection {
+    /// Every direction, in configuration order
+    pub const ALL: [Self; 4] = [Self::Both, Self::HostToRemote, Self::RemoteToHost, Self::None];
+
+    /// Name used in configuration files
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Self::Both => "both",
+            Self::HostToRemote => "host_to_remote",
+            Self::RemoteToHost => "remote_to_host",
+            Self::None => "none",
+        }
+    }
+
+    /// Check whether local content may reach the peer
+    pub fn allows_host_to_remote(self) -> bool {
+        matches!(self, Self::Both | Self::HostToRemote)
+    }
+
+    /// Check whether the peer's content may reach the local clipboard
+    pub fn allows_remote_to_host(self) -> bool {
+        matches!(self, Self::Both | Self::RemoteToHost)
+    }
+}
+
+impl fmt::Display for ClipboardDirection {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+impl FromStr for ClipboardDirection {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Self::ALL
+            .into_iter()
+            .find(|direction| direction.as_str().eq_ignore_ascii_case(s.trim()))
+            .ok_or_else(|| format!("unknown clipboard direction '{}'", s))
+    }
+}
+
+/// Shared, runtime-changeable [`ClipboardDirection`]
+///
+/// Clones share the setting, so keep one to change the direction of a
+/// bridge that was handed another.
+#[derive(Debug, Clone, Default)]
+pub struct DirectionPolicy(Arc<AtomicU8>);
+
+impl DirectionPolicy {
+    /// Create a policy starting out with `direction`
+    pub fn new(direction: ClipboardDirection) -> Self {
+        let policy = Self::default();
+        policy.set(direction);
+        policy
+    }
+
+    /// Get the direction in force
+    pub fn get(&self) -> ClipboardDirection {
+        ClipboardDirection::ALL
+            .get(usize::from(self.0.load(Ordering::Relaxed)))
+            .copied()
+            .unwrap_or_default()
+    }
+
+    /// Change the direction; takes effect with the next event
+    pub fn set(&self, direction: ClipboardDirection) {
+        let index = ClipboardDirection::ALL
+            .iter()
+            .position(|&candidate| candidate == direction)
+            .unwrap_or_default();
+        self.0.store(index as u8, Ordering::Relaxed);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_direction_policy() {
+        let policy = DirectionPolicy::default();
+        assert_eq!(policy.get(), ClipboardDirection::Both);
+
+        let shared = policy.clone();
+        for direction in ClipboardDirection::ALL {
+            shared.set(direction);
+            assert_eq!(policy.get(), direction);
+            assert_eq!(direction.as_str().parse(), Ok(direction));
+        }
+        assert!(!ClipboardDirection::RemoteToHost.allows_host_to_remote());
+        assert!(ClipboardDirection::RemoteToHost.allows_remote_to_host());
+        assert!("sideways".parse::<ClipboardDirection>().is_err());
+    }
+}
@@ -0,0 +1,105 @@
+//! Capability downgrades detected during CLIPRDR negotiation.
+//!
+//! The clipboard channel only uses features both sides advertise in their
+//! General Capability Sets. When the peer leaves out one this side asked
+//! for, clipboard sync still starts but some operations will not work, and
+//! without a warning they fail much later with no obvious cause. A
+//! [`CapabilityDowngrade`] names each missing feature so host UIs can tell
+//! the user up front, e.g. "file copy unavailable with this client".
+
+use ironrdp_cliprdr::pdu::ClipboardGeneralCapabilityFlags;
+use std::fmt;
+
+/// A clipboard feature lost because the peer did not negotiate it
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum CapabilityDowngrade {
+    /// Format names are limited to 32 bytes, so long registered names are truncated
+    LongFormatNames,
+
+    /// Files cannot be copied through the clipboard
+    FileClipboard,
+
+    /// Clipboard data cannot be locked, so a file paste can break if the
+    /// source clipboard changes mid-transfer
+    ClipDataLocking,
+
+    /// Files of 4 GiB and more cannot be copied
+    HugeFiles,
+}
+
+impl CapabilityDowngrade {
+    /// Every downgrade, in the order they are reported
+    pub const ALL: [Self; 4] = [
+        Self::LongFormatNames,
+        Self::FileClipboard,
+        Self::ClipDataLocking,
+        Self::HugeFiles,
+    ];
+
+    /// Capability flag whose absence causes this downgrade
+    pub fn flag(self) -> ClipboardGeneralCapabilityFlags {
+        match self {
+            Self::LongFormatNames => ClipboardGeneralCapabilityFlags::USE_LONG_FORMAT_NAMES,
+            Self::FileClipboard => ClipboardGeneralCapabilityFlags::STREAM_FILECLIP_ENABLED,
+            Self::ClipDataLocking => ClipboardGeneralCapabilityFlags::CAN_LOCK_CLIPDATA,
+            Self::HugeFiles => ClipboardGeneralCapabilityFlags::HUGE_FILE_SUPPORT_ENABLED,
+        }
+    }
+
+    /// Short message suitable for showing to end users
+    pub fn description(self) -> &'static str {
+        match self {
+            Self::LongFormatNames => "some rich clipboard formats are unavailable with this client",
+            Self::FileClipboard => "file copy is unavailable with this client",
+            Self::ClipDataLocking => "file copy may fail if the clipboard changes during a paste",
+            Self::HugeFiles => "files of 4 GiB or more cannot be copied with this client",
+        }
+    }
+
+    /// Downgrades between the capabilities requested and those negotiated
+    pub fn detect(
+        requested: ClipboardGeneralCapabilityFlags,
+        negotiated: ClipboardGeneralCapabilityFlags,
+    ) -> Vec<Self> {
+        Self::ALL
+            .into_iter()
+            .filter(|downgrade| requested.contains(downgrade.flag()) && !negotiated.contains(downgrade.flag()))
+            .collect()
+    }
+}
+
+impl fmt::Display for CapabilityDowngrade {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.description())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_detect_downgrades() {
+        let requested = ClipboardGeneralCapabilityFlags::USE_LONG_FORMAT_NAMES
+            | ClipboardGeneralCapabilityFlags::STREAM_FILECLIP_ENABLED
+            | ClipboardGeneralCapabilityFlags::CAN_LOCK_CLIPDATA;
+
+        assert!(CapabilityDowngrade::detect(requested, requested).is_empty());
+        assert_eq!(
+            CapabilityDowngrade::detect(requested, ClipboardGeneralCapabilityFlags::USE_LONG_FORMAT_NAMES),
+            vec![CapabilityDowngrade::FileClipboard, CapabilityDowngrade::ClipDataLocking]
+        );
+
+        // Features we never asked for are not downgrades
+        let without_locks = requested - ClipboardGeneralCapabilityFlags::CAN_LOCK_CLIPDATA;
+        assert!(CapabilityDowngrade::detect(without_locks, without_locks).is_empty());
+    }
+
+    #[test]
+    fn test_description() {
+        assert_eq!(
+            CapabilityDowngrade::FileClipboard.to_string(),
+            "file copy is unavailable with this client"
+        );
+    }
+}
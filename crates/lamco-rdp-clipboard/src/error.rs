@@ -3,6 +3,8 @@
 use lamco_clipboard_core::ClipboardError;
 use thiserror::Error;
 
+use crate::direction::ClipboardDirection;
+
 /// Errors that can occur during RDP clipboard operations.
 #[derive(Debug, Error)]
 pub enum ClipboardRdpError {
@@ -37,6 +39,10 @@ pub enum ClipboardRdpError {
     /// Timeout waiting for response
     #[error("timeout waiting for clipboard response")]
     Timeout,
+
+    /// The clipboard direction policy forbids the transfer
+    #[error("blocked by clipboard direction policy ({0})")]
+    DirectionBlocked(ClipboardDirection),
 }
 
 /// Result type for RDP clipboard operations.
@@ -9,8 +9,12 @@ use ironrdp_cliprdr::pdu::{
     FileContentsRequest, FileContentsResponse, FormatDataRequest, FormatDataResponse, LockDataId,
 };
 use std::collections::VecDeque;
+#[cfg(feature = "tokio")]
+use std::sync::atomic::{AtomicUsize, Ordering};
 use std::sync::{Arc, RwLock};
 
+use crate::downgrade::CapabilityDowngrade;
+
 /// Events generated by the clipboard backend for async processing.
 #[derive(Debug, Clone)]
 pub enum ClipboardEvent {
@@ -23,6 +27,15 @@ pub enum ClipboardEvent {
     /// Negotiated capabilities received
     NegotiatedCapabilities(ClipboardGeneralCapabilityFlags),
 
+    /// Negotiation left out features this side requested
+    ///
+    /// Sent after [`NegotiatedCapabilities`](Self::NegotiatedCapabilities)
+    /// so host UIs can explain what will not work with this peer.
+    CapabilitiesDowngraded {
+        /// Features the peer did not negotiate
+        downgrades: Vec<CapabilityDowngrade>,
+    },
+
     /// Remote clipboard content changed (remote copy)
     RemoteCopy {
         /// Available formats from remote
@@ -55,6 +68,8 @@ pub enum ClipboardEvent {
         size: u32,
         /// Whether this is a size request (vs data request)
         is_size_request: bool,
+        /// Clipboard data lock the request refers to, if any
+        clip_data_id: Option<u32>,
     },
 
     /// Remote sent file contents response
@@ -111,6 +126,7 @@ impl ClipboardEvent {
             position: request.position,
             size: request.requested_size,
             is_size_request: request.flags.contains(FileContentsFlags::SIZE),
+            clip_data_id: request.data_id,
         }
     }
 
@@ -134,15 +150,77 @@ impl ClipboardEvent {
     pub fn unlock(data_id: LockDataId) -> Self {
         Self::Unlock { data_id: data_id.0 }
     }
+
+    /// Check whether losing this event would break the channel protocol
+    ///
+    /// The peer waits for an answer to every data and file request, responses
+    /// are matched to our requests in order, and locks must be released.
+    /// Format lists and negotiation results replace earlier state, so losing
+    /// the latest one leaves stale formats or capabilities behind.
+    pub fn is_protocol_critical(&self) -> bool {
+        matches!(
+            self,
+            Self::RequestFormatList
+                | Self::NegotiatedCapabilities(_)
+                | Self::CapabilitiesDowngraded { .. }
+                | Self::RemoteCopy { .. }
+                | Self::FormatDataRequest { .. }
+                | Self::FormatDataResponse { .. }
+                | Self::FileContentsRequest { .. }
+                | Self::FileContentsResponse { .. }
+                | Self::Lock { .. }
+                | Self::Unlock { .. }
+        )
+    }
 }
 
 /// Sender side of the clipboard event channel.
 ///
 /// This is a simple queue-based sender that uses `RwLock` for thread-safety.
 /// Events are queued for later processing by an async task.
+///
+/// Senders handed out by a [`CliprdrBridge`](crate::CliprdrBridge) instead
+/// feed its channel. While its queue is full, events are dropped unless they
+/// are [protocol critical](ClipboardEvent::is_protocol_critical).
 #[derive(Debug, Clone)]
 pub struct ClipboardEventSender {
     queue: Arc<RwLock<VecDeque<ClipboardEvent>>>,
+
+    /// Channel to a bridge task, replacing the queue
+    #[cfg(feature = "tokio")]
+    channel: Option<EventChannel>,
+}
+
+/// Sending half of a bridge task's event channel
+///
+/// The channel itself is unbounded so protocol-critical events are never
+/// lost; `queued` counts the other events against `capacity`.
+#[cfg(feature = "tokio")]
+#[derive(Debug, Clone)]
+struct EventChannel {
+    events: tokio::sync::mpsc::UnboundedSender<ClipboardEvent>,
+    queued: Arc<AtomicUsize>,
+    capacity: usize,
+}
+
+/// Receiving half of a bridge task's event channel
+#[cfg(feature = "tokio")]
+#[derive(Debug)]
+pub(crate) struct EventChannelReceiver {
+    events: tokio::sync::mpsc::UnboundedReceiver<ClipboardEvent>,
+    queued: Arc<AtomicUsize>,
+}
+
+#[cfg(feature = "tokio")]
+impl EventChannelReceiver {
+    /// Receive the next event, `None` once every sender is gone
+    pub(crate) async fn recv(&mut self) -> Option<ClipboardEvent> {
+        let event = self.events.recv().await?;
+        if !event.is_protocol_critical() {
+            self.queued.fetch_sub(1, Ordering::Relaxed);
+        }
+        Some(event)
+    }
 }
 
 impl ClipboardEventSender {
@@ -150,11 +228,53 @@ impl ClipboardEventSender {
     pub fn new() -> Self {
         Self {
             queue: Arc::new(RwLock::new(VecDeque::new())),
+            #[cfg(feature = "tokio")]
+            channel: None,
         }
     }
 
+    /// Create a sender feeding a channel that queues at most `capacity`
+    /// droppable events, and its receiver
+    #[cfg(feature = "tokio")]
+    pub(crate) fn channel(capacity: usize) -> (Self, EventChannelReceiver) {
+        let (events, receiver) = tokio::sync::mpsc::unbounded_channel();
+        let queued = Arc::new(AtomicUsize::new(0));
+        let sender = Self {
+            channel: Some(EventChannel {
+                events,
+                queued: Arc::clone(&queued),
+                capacity,
+            }),
+            ..Self::new()
+        };
+        (
+            sender,
+            EventChannelReceiver {
+                events: receiver,
+                queued,
+            },
+        )
+    }
+
     /// Send an event (non-blocking, queues for later processing)
     pub fn send(&self, event: ClipboardEvent) {
+        #[cfg(feature = "tokio")]
+        if let Some(channel) = &self.channel {
+            let critical = event.is_protocol_critical();
+            if !critical && channel.queued.fetch_add(1, Ordering::Relaxed) >= channel.capacity {
+                channel.queued.fetch_sub(1, Ordering::Relaxed);
+                tracing::warn!("Clipboard event queue full, dropping {:?}", event);
+                return;
+            }
+            if channel.events.send(event).is_err() {
+                if !critical {
+                    channel.queued.fetch_sub(1, Ordering::Relaxed);
+                }
+                tracing::debug!("Clipboard bridge stopped, dropping event");
+            }
+            return;
+        }
+
         if let Ok(mut queue) = self.queue.try_write() {
             queue.push_back(event);
         } else {
@@ -163,6 +283,8 @@ impl ClipboardEventSender {
     }
 
     /// Create a receiver that shares the same queue
+    ///
+    /// Events sent to a bridge's channel never reach it.
     pub fn subscribe(&self) -> ClipboardEventReceiver {
         ClipboardEventReceiver {
             queue: Arc::clone(&self.queue),
@@ -238,4 +360,61 @@ mod tests {
         assert!(matches!(event, Some(ClipboardEvent::Ready)));
         assert!(receiver.try_recv().is_none());
     }
+
+    #[cfg(feature = "tokio")]
+    #[tokio::test]
+    async fn test_full_channel_keeps_critical_events() {
+        let (sender, mut receiver) = ClipboardEventSender::channel(1);
+        let paste = || ClipboardEvent::FormatDataRequest {
+            format_id: ClipboardFormatId::new(13),
+        };
+
+        sender.send(ClipboardEvent::Ready);
+        sender.send(ClipboardEvent::Ready);
+        sender.send(paste());
+        sender.send(paste());
+        drop(sender);
+
+        let mut events = Vec::new();
+        while let Some(event) = receiver.recv().await {
+            events.push(event);
+        }
+        assert!(matches!(
+            &events[..],
+            [
+                ClipboardEvent::Ready,
+                ClipboardEvent::FormatDataRequest { .. },
+                ClipboardEvent::FormatDataRequest { .. }
+            ]
+        ));
+        assert_eq!(receiver.queued.load(Ordering::Relaxed), 0);
+    }
+    #[cfg(feature = "tokio")]
+    #[tokio::test]
+    async fn test_full_channel_keeps_format_lists() {
+        let (sender, mut receiver) = ClipboardEventSender::channel(2);
+        for _ in 0..4 {
+            sender.send(ClipboardEvent::Ready);
+        }
+        sender.send(ClipboardEvent::remote_copy(&[RdpClipboardFormat::new(
+            ClipboardFormatId::new(13),
+        )]));
+        sender.send(ClipboardEvent::RequestFormatList);
+        drop(sender);
+
+        let mut events = Vec::new();
+        while let Some(event) = receiver.recv().await {
+            events.push(event);
+        }
+        assert!(matches!(
+            &events[..],
+            [
+                ClipboardEvent::Ready,
+                ClipboardEvent::Ready,
+                ClipboardEvent::RemoteCopy { formats },
+                ClipboardEvent::RequestFormatList
+            ] if formats.len() == 1
+        ));
+        assert_eq!(receiver.queued.load(Ordering::Relaxed), 0);
+    }
 }
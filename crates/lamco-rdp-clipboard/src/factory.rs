@@ -1,9 +1,12 @@
 //! Factory for creating RDP clipboard backends.
 
 use ironrdp_cliprdr::backend::{CliprdrBackend, CliprdrBackendFactory};
+use lamco_clipboard_core::CompatProfile;
 
 use crate::backend::RdpCliprdrBackend;
+use crate::config::CliprdrConfig;
 use crate::event::{ClipboardEventReceiver, ClipboardEventSender};
+use crate::role::CliprdrRole;
 
 /// Factory for creating [`RdpCliprdrBackend`] instances.
 ///
@@ -35,6 +38,15 @@ pub struct RdpCliprdrFactory {
 
     /// Shared event sender
     event_sender: ClipboardEventSender,
+
+    /// Workarounds applied by every backend
+    compat: CompatProfile,
+
+    /// End of the channel every backend serves
+    role: CliprdrRole,
+
+    /// Capabilities every backend advertises
+    config: CliprdrConfig,
 }
 
 impl RdpCliprdrFactory {
@@ -43,6 +55,9 @@ impl RdpCliprdrFactory {
         Self {
             temp_dir: temp_dir.into(),
             event_sender: ClipboardEventSender::new(),
+            compat: CompatProfile::Standard,
+            role: CliprdrRole::Client,
+            config: CliprdrConfig::default(),
         }
     }
 
@@ -51,9 +66,30 @@ impl RdpCliprdrFactory {
         Self {
             temp_dir: temp_dir.into(),
             event_sender,
+            compat: CompatProfile::Standard,
+            role: CliprdrRole::Client,
+            config: CliprdrConfig::default(),
         }
     }
 
+    /// Apply workarounds for a peer with known quirks to every backend created
+    pub fn with_compat_profile(mut self, compat: CompatProfile) -> Self {
+        self.compat = compat;
+        self
+    }
+
+    /// Create backends for the given end of the channel
+    pub fn with_role(mut self, role: CliprdrRole) -> Self {
+        self.role = role;
+        self
+    }
+
+    /// Advertise the capabilities `config` enables from every backend
+    pub fn with_config(mut self, config: CliprdrConfig) -> Self {
+        self.config = config;
+        self
+    }
+
     /// Get a receiver for clipboard events.
     ///
     /// All backends created by this factory will send events to this receiver.
@@ -74,7 +110,10 @@ impl RdpCliprdrFactory {
 
 impl CliprdrBackendFactory for RdpCliprdrFactory {
     fn build_cliprdr_backend(&self) -> Box<dyn CliprdrBackend> {
-        let backend = RdpCliprdrBackend::new(self.temp_dir.clone(), self.event_sender.clone());
+        let backend = RdpCliprdrBackend::new(self.temp_dir.clone(), self.event_sender.clone())
+            .with_compat_profile(self.compat)
+            .with_role(self.role)
+            .with_config(self.config);
         Box::new(backend)
     }
 }
@@ -44,19 +44,43 @@
 //! The [`CliprdrBackend`](ironrdp_cliprdr::backend::CliprdrBackend) trait methods are called
 //! synchronously from the RDP message processing loop. To avoid blocking, this implementation
 //! queues events for asynchronous processing and provides a separate event processing loop.
+//!
+//! [`ClipboardBridge`] is that loop: it handles the queued events against a [`ClipboardSink`],
+//! converting formats, serving and receiving files, and answers the peer through IronRDP's
+//! [`ClipboardMessageProxy`].
+//!
+//! With the `tokio` feature, `CliprdrBridge` runs that loop on its own task fed through
+//! queues, so slow sinks such as the XDG Desktop Portal never hold up the RDP I/O thread.
 
 #![cfg_attr(docsrs, feature(doc_cfg))]
 #![deny(missing_docs)]
 
 mod backend;
+mod bridge;
+mod config;
+mod direction;
+mod downgrade;
 mod error;
 mod event;
 mod factory;
+mod role;
+#[cfg(feature = "tokio")]
+mod task;
 
 pub use backend::RdpCliprdrBackend;
+pub use bridge::ClipboardBridge;
+pub use config::CliprdrConfig;
+pub use direction::{ClipboardDirection, DirectionPolicy};
+pub use downgrade::CapabilityDowngrade;
 pub use error::{ClipboardRdpError, ClipboardRdpResult};
 pub use event::{ClipboardEvent, ClipboardEventReceiver, ClipboardEventSender};
 pub use factory::RdpCliprdrFactory;
+pub use role::CliprdrRole;
+#[cfg(feature = "tokio")]
+pub use task::{
+    CliprdrBridge, CliprdrBridgeConfig, DEFAULT_COMMAND_QUEUE_CAPACITY, DEFAULT_EVENT_QUEUE_CAPACITY,
+    DEFAULT_RESPONSE_TIMEOUT_MS,
+};
 
 // Re-export core types for convenience
 pub use lamco_clipboard_core;
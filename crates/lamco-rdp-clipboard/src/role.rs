@@ -0,0 +1,45 @@
+//! Which end of the CLIPRDR channel this side is.
+//!
+//! The two ends start the channel differently. The server sends Monitor
+//! Ready; the client answers with its capabilities and an initial Format
+//! List, which the server acknowledges. Until that list arrives a server has
+//! nothing to announce to, and by convention the connecting client's
+//! clipboard wins: a server only offers its own content when the client
+//! started out with an empty one.
+
+use std::fmt;
+
+/// End of the clipboard channel this side plays
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Hash)]
+pub enum CliprdrRole {
+    /// Connecting to an RDP server, as with `CliprdrClient`
+    #[default]
+    Client,
+
+    /// Accepting RDP clients, as with `CliprdrServer`
+    Server,
+}
+
+impl CliprdrRole {
+    /// Every role
+    pub const ALL: [Self; 2] = [Self::Client, Self::Server];
+
+    /// Name used in logs and configuration
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Self::Client => "client",
+            Self::Server => "server",
+        }
+    }
+
+    /// Check whether this is the server end
+    pub fn is_server(self) -> bool {
+        self == Self::Server
+    }
+}
+
+impl fmt::Display for CliprdrRole {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
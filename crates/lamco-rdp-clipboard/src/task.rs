@@ -0,0 +1,367 @@
+//! Running a [`ClipboardBridge`] on its own task.
+//!
+//! IronRDP calls [`CliprdrBackend`](ironrdp_cliprdr::backend::CliprdrBackend)
+//! methods synchronously on the RDP I/O thread, while Portal and other D-Bus
+//! sinks answer asynchronously and may take a while. [`CliprdrBridge`] keeps
+//! the two apart: it spawns the bridge on a Tokio task and hands out an
+//! [`ClipboardEventSender`] whose `send` only does a non-blocking push into a
+//! channel. The host's own calls, a local copy or paste, go through a bounded
+//! channel and are answered over a oneshot, each waited on no longer than the
+//! configured timeout.
+//!
+//! When the event queue is full, other backend events are dropped with a
+//! warning rather than stalling the I/O thread. Format lists, negotiation
+//! results, data and file requests, their responses, and lock changes are
+//! [protocol critical](ClipboardEvent::is_protocol_critical) and always
+//! queued: a lost Format List leaves stale formats announced, a lost request
+//! leaves the peer's paste hanging, and a lost response pairs later responses
+//! with the wrong requests. The peer waits for its requests to be answered,
+//! so these stay few.
+
+use ironrdp_cliprdr::backend::ClipboardMessageProxy;
+use lamco_clipboard_core::{ClipboardChange, ClipboardSink};
+use std::time::Duration;
+use tokio::sync::{mpsc, oneshot};
+use tokio::task::JoinHandle;
+
+use crate::backend::RdpCliprdrBackend;
+use crate::bridge::ClipboardBridge;
+use crate::error::{ClipboardRdpError, ClipboardRdpResult};
+use crate::event::{ClipboardEvent, ClipboardEventSender, EventChannelReceiver};
+
+/// Default number of droppable backend events queued for the task
+pub const DEFAULT_EVENT_QUEUE_CAPACITY: usize = 256;
+
+/// Default capacity of the host command queue
+pub const DEFAULT_COMMAND_QUEUE_CAPACITY: usize = 16;
+
+/// Default time to wait for the task to answer a host call, in milliseconds
+pub const DEFAULT_RESPONSE_TIMEOUT_MS: u64 = 5000;
+
+/// Queue sizes and timeouts of a [`CliprdrBridge`]
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CliprdrBridgeConfig {
+    /// Backend events that may wait for the task before new ones are dropped
+    ///
+    /// Protocol-critical events are queued regardless.
+    pub event_queue_capacity: usize,
+
+    /// Host calls that may wait for the task before callers block
+    pub command_queue_capacity: usize,
+
+    /// Time a host call waits for its answer, in milliseconds
+    pub response_timeout_ms: u64,
+}
+
+impl Default for CliprdrBridgeConfig {
+    fn default() -> Self {
+        Self {
+            event_queue_capacity: DEFAULT_EVENT_QUEUE_CAPACITY,
+            command_queue_capacity: DEFAULT_COMMAND_QUEUE_CAPACITY,
+            response_timeout_ms: DEFAULT_RESPONSE_TIMEOUT_MS,
+        }
+    }
+}
+
+impl CliprdrBridgeConfig {
+    /// Set both queue capacities
+    pub fn with_queue_capacities(mut self, events: usize, commands: usize) -> Self {
+        self.event_queue_capacity = events;
+        self.command_queue_capacity = commands;
+        self
+    }
+
+    /// Set how long host calls wait for their answer
+    pub fn with_response_timeout(mut self, timeout_ms: u64) -> Self {
+        self.response_timeout_ms = timeout_ms;
+        self
+    }
+}
+
+/// A host call and where to send its result
+#[derive(Debug)]
+enum Command {
+    LocalChanged {
+        change: ClipboardChange,
+        reply: oneshot::Sender<ClipboardRdpResult<()>>,
+    },
+    LocalPaste {
+        mime_type: String,
+        reply: oneshot::Sender<ClipboardRdpResult<()>>,
+    },
+}
+
+/// Handle to a [`ClipboardBridge`] running on a Tokio task.
+///
+/// The task stops once this handle is dropped.
+///
+/// # Example
+///
+/// ```rust,ignore
+/// use lamco_rdp_clipboard::{ClipboardBridge, CliprdrBridge, CliprdrBridgeConfig};
+///
+/// let bridge = CliprdrBridge::spawn(ClipboardBridge::new(portal_sink, proxy), CliprdrBridgeConfig::default());
+/// // Hand this to IronRDP's cliprdr; its callbacks never wait on the sink
+/// let backend = bridge.backend("/tmp/clipboard".to_string());
+///
+/// while let Some(change) = local_changes.recv().await {
+///     bridge.local_changed(change).await?;
+/// }
+/// ```
+#[derive(Debug)]
+pub struct CliprdrBridge {
+    events: ClipboardEventSender,
+    commands: mpsc::Sender<Command>,
+    response_timeout: Duration,
+    task: JoinHandle<()>,
+}
+
+impl CliprdrBridge {
+    /// Spawn `bridge` on the current Tokio runtime
+    ///
+    /// # Panics
+    ///
+    /// Panics if called outside a Tokio runtime, or if the command queue capacity is 0.
+    pub fn spawn<S, P>(bridge: ClipboardBridge<S, P>, config: CliprdrBridgeConfig) -> Self
+    where
+        S: ClipboardSink + 'static,
+        P: ClipboardMessageProxy + Sync + 'static,
+    {
+        let (events, event_rx) = ClipboardEventSender::channel(config.event_queue_capacity);
+        let (command_tx, command_rx) = mpsc::channel(config.command_queue_capacity);
+        let task = tokio::spawn(run(bridge, event_rx, command_rx));
+        Self {
+            events,
+            commands: command_tx,
+            response_timeout: Duration::from_millis(config.response_timeout_ms),
+            task,
+        }
+    }
+
+    /// Get a sender feeding the task, for [`RdpCliprdrBackend::new`]
+    pub fn event_sender(&self) -> ClipboardEventSender {
+        self.events.clone()
+    }
+
+    /// Create a backend whose events go to the task
+    pub fn backend(&self, temp_dir: String) -> RdpCliprdrBackend {
+        RdpCliprdrBackend::new(temp_dir, self.event_sender())
+    }
+
+    /// Announce a local clipboard change to the peer
+    ///
+    /// The task announces it once the change's burst has ended; see
+    /// [`ClipboardBridge::local_changed`].
+    pub async fn local_changed(&self, change: ClipboardChange) -> ClipboardRdpResult<()> {
+        self.call(|reply| Command::LocalChanged { change, reply }).await
+    }
+
+    /// Paste `mime_type` from the peer's clipboard
+    ///
+    /// See [`ClipboardBridge::local_paste`].
+    pub async fn local_paste(&self, mime_type: &str) -> ClipboardRdpResult<()> {
+        let mime_type = mime_type.to_string();
+        self.call(|reply| Command::LocalPaste { mime_type, reply }).await
+    }
+
+    /// Check whether the task has stopped
+    pub fn is_finished(&self) -> bool {
+        self.task.is_finished()
+    }
+
+    /// Send a command and wait for its result
+    ///
+    /// On timeout the command still runs; only the caller stops waiting.
+    async fn call(
+        &self,
+        command: impl FnOnce(oneshot::Sender<ClipboardRdpResult<()>>) -> Command,
+    ) -> ClipboardRdpResult<()> {
+        let (reply, response) = oneshot::channel();
+        let exchange = async {
+            self.commands
+                .send(command(reply))
+                .await
+                .map_err(|_| ClipboardRdpError::SendError("clipboard bridge task stopped".to_string()))?;
+            response
+                .await
+                .map_err(|_| ClipboardRdpError::RecvError("clipboard bridge task stopped".to_string()))?
+        };
+        tokio::time::timeout(self.response_timeout, exchange)
+            .await
+            .map_err(|_| ClipboardRdpError::Timeout)?
+    }
+}
+
+/// Handle events and commands until the handle is dropped
+async fn run<S, P>(
+    mut bridge: ClipboardBridge<S, P>,
+    mut events: EventChannelReceiver,
+    mut commands: mpsc::Receiver<Command>,
+) where
+    S: ClipboardSink,
+    P: ClipboardMessageProxy,
+{
+    loop {
+        let next_poll = bridge.next_poll();
+        tokio::select! {
+            Some(event) = events.recv() => {
+                if let Err(e) = bridge.handle_event(event).await {
+                    tracing::warn!("Handling clipboard event failed: {}", e);
+                }
+            }
+            command = commands.recv() => match command {
+                Some(Command::LocalChanged { change, reply }) => {
+                    let _ = reply.send(bridge.local_changed(change).await);
+                }
+                Some(Command::LocalPaste { mime_type, reply }) => {
+                    let _ = reply.send(bridge.local_paste(&mime_type).await);
+                }
+                None => break,
+            },
+            () = tokio::time::sleep(next_poll.unwrap_or_default()), if next_poll.is_some() => {
+                if let Err(e) = bridge.poll().await {
+                    tracing::warn!("Polling clipboard bridge failed: {}", e);
+                }
+            }
+        }
+    }
+    tracing::debug!("Clipboard bridge task stopped");
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use ironrdp_cliprdr::backend::ClipboardMessage;
+    use lamco_clipboard_core::sink::{ClipboardChangeReceiver, FileInfo};
+    use lamco_clipboard_core::{ChangeCoalescer, ClipboardError, ClipboardResult, CoalesceConfig};
+    use std::sync::{Arc, Mutex};
+
+    #[derive(Debug, Clone, Default)]
+    struct RecordingProxy(Arc<Mutex<Vec<ClipboardMessage>>>);
+
+    impl RecordingProxy {
+        fn take(&self) -> Vec<ClipboardMessage> {
+            std::mem::take(&mut *self.0.lock().unwrap())
+        }
+    }
+
+    impl ClipboardMessageProxy for RecordingProxy {
+        fn send_clipboard_message(&self, message: ClipboardMessage) {
+            self.0.lock().unwrap().push(message);
+        }
+    }
+
+    /// Sink whose reads take `delay`, like a slow Portal
+    struct SlowSink {
+        delay: Duration,
+    }
+
+    impl ClipboardSink for SlowSink {
+        async fn announce_formats(&self, _mime_types: Vec<String>) -> ClipboardResult<()> {
+            Ok(())
+        }
+
+        async fn read_clipboard(&self, _mime_type: &str) -> ClipboardResult<Vec<u8>> {
+            tokio::time::sleep(self.delay).await;
+            Ok(b"Hi".to_vec())
+        }
+
+        async fn write_clipboard(&self, _mime_type: &str, _data: Vec<u8>) -> ClipboardResult<()> {
+            tokio::time::sleep(self.delay).await;
+            Ok(())
+        }
+
+        async fn subscribe_changes(&self) -> ClipboardResult<ClipboardChangeReceiver> {
+            Err(ClipboardError::InvalidState("no changes".to_string()))
+        }
+
+        async fn get_file_list(&self) -> ClipboardResult<Vec<FileInfo>> {
+            Ok(Vec::new())
+        }
+
+        async fn read_file_chunk(&self, _index: u32, _offset: u64, _size: u32) -> ClipboardResult<Vec<u8>> {
+            Ok(Vec::new())
+        }
+
+        async fn write_file(&self, _path: &str, _data: Vec<u8>) -> ClipboardResult<()> {
+            Ok(())
+        }
+    }
+
+    fn text() -> ClipboardChange {
+        ClipboardChange::new(vec!["text/plain".to_string()])
+    }
+
+    fn spawn(delay: Duration, config: CliprdrBridgeConfig) -> (CliprdrBridge, RecordingProxy) {
+        let proxy = RecordingProxy::default();
+        let bridge = ClipboardBridge::new(SlowSink { delay }, proxy.clone())
+            .with_coalescer(ChangeCoalescer::new().with_config(CoalesceConfig::default().with_delays(0, 0)));
+        (CliprdrBridge::spawn(bridge, config), proxy)
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_events_reach_the_task() {
+        let (bridge, proxy) = spawn(Duration::from_millis(100), CliprdrBridgeConfig::default());
+
+        bridge.local_changed(text()).await.unwrap();
+        assert!(matches!(&proxy.take()[..], [ClipboardMessage::SendInitiateCopy(_)]));
+
+        // The peer pastes; sending returns at once while the sink is read
+        bridge.event_sender().send(ClipboardEvent::FormatDataRequest {
+            format_id: ironrdp_cliprdr::pdu::ClipboardFormatId::new(13),
+        });
+        assert!(proxy.take().is_empty());
+        tokio::time::sleep(Duration::from_millis(200)).await;
+        assert!(matches!(&proxy.take()[..], [ClipboardMessage::SendFormatData(_)]));
+    }
+
+    #[tokio::test]
+    async fn test_burst_flushed_by_task() {
+        let proxy = RecordingProxy::default();
+        let coalescer = ChangeCoalescer::new().with_config(CoalesceConfig::default().with_delays(50, 1000));
+        let bridge = ClipboardBridge::new(SlowSink { delay: Duration::ZERO }, proxy.clone()).with_coalescer(coalescer);
+        let bridge = CliprdrBridge::spawn(bridge, CliprdrBridgeConfig::default());
+
+        for _ in 0..5 {
+            bridge.local_changed(text()).await.unwrap();
+        }
+        assert!(proxy.take().is_empty());
+        // Announced by the task once the clipboard has been quiet
+        tokio::time::sleep(Duration::from_millis(300)).await;
+        assert!(matches!(&proxy.take()[..], [ClipboardMessage::SendInitiateCopy(_)]));
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_timeouts_and_full_queue() {
+        let config = CliprdrBridgeConfig::default()
+            .with_queue_capacities(1, 1)
+            .with_response_timeout(50);
+        let (bridge, proxy) = spawn(Duration::from_secs(10), config);
+        bridge.local_changed(text()).await.unwrap();
+        proxy.take();
+
+        let events = bridge.event_sender();
+        let paste = || ClipboardEvent::FormatDataRequest {
+            format_id: ironrdp_cliprdr::pdu::ClipboardFormatId::new(13),
+        };
+        events.send(paste());
+        tokio::time::sleep(Duration::from_millis(1)).await;
+        // The first is being served; the second Format List request overflows
+        // the queue, but data requests are queued regardless
+        events.send(ClipboardEvent::RequestFormatList);
+        events.send(ClipboardEvent::RequestFormatList);
+        events.send(paste());
+        events.send(paste());
+        assert!(matches!(
+            bridge.local_changed(ClipboardChange::new(Vec::new())).await,
+            Err(ClipboardRdpError::Timeout)
+        ));
+        tokio::time::sleep(Duration::from_secs(30)).await;
+        let sent = proxy.take();
+        let responses = sent
+            .iter()
+            .filter(|message| matches!(message, ClipboardMessage::SendFormatData(_)))
+            .count();
+        assert_eq!(responses, 3);
+    }
+}
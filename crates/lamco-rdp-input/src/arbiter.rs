@@ -0,0 +1,498 @@
+//! Multi-client input arbitration.
+//!
+//! When several RDP clients shadow one session, each connection has its own
+//! [`InputTranslator`](crate::InputTranslator) (key and button state is per
+//! client) and tags what it translates with a [`SourceId`]. The
+//! [`InputArbiter`] merges those streams and decides which source controls
+//! the session at any moment, so collaborating clients do not fight over the
+//! pointer.
+//!
+//! Events from sources without control are dropped. When control moves, the
+//! keys and buttons the previous controller still holds are released first,
+//! so nothing stays stuck down on the server.
+//!
+//! # Example
+//!
+//! ```rust,no_run
+//! use lamco_rdp_input::{ArbitrationPolicy, InputArbiter, InputTranslator, RdpInputEvent};
+//! use std::time::{Duration, Instant};
+//!
+//! # fn example() -> Result<(), Box<dyn std::error::Error>> {
+//! let mut alice = InputTranslator::new(vec![])?;
+//! alice.set_source_id(1);
+//! let mut bob = InputTranslator::new(vec![])?;
+//! bob.set_source_id(2);
+//!
+//! let mut arbiter = InputArbiter::new(ArbitrationPolicy::FirstWins {
+//!     idle_timeout: Duration::from_secs(2),
+//! });
+//!
+//! let event = alice.translate_tagged(RdpInputEvent::MouseMove { x: 10, y: 10 })?;
+//! for tagged in arbiter.submit(event, Instant::now()) {
+//!     // Inject tagged.event
+//! }
+//!
+//! // Bob is ignored until Alice has been idle for two seconds
+//! let event = bob.translate_tagged(RdpInputEvent::MouseMove { x: 500, y: 500 })?;
+//! assert!(arbiter.submit(event, Instant::now()).is_empty());
+//! # Ok(())
+//! # }
+//! ```
+
+use crate::keyboard::KeyModifiers;
+use crate::mouse::MouseButton;
+use crate::translator::{KeyboardEventType, LinuxInputEvent};
+use std::collections::{HashMap, HashSet};
+use std::time::{Duration, Instant};
+use tracing::debug;
+
+/// Identifies the client connection an event came from
+pub type SourceId = u32;
+
+/// Default idle time after which another source may take control (milliseconds)
+pub const DEFAULT_IDLE_TIMEOUT_MS: u64 = 2000;
+
+/// Default longest turn while other sources wait, for round-robin (milliseconds)
+pub const DEFAULT_TURN_SLICE_MS: u64 = 10_000;
+
+/// A translated event and the source it came from
+#[derive(Debug, Clone)]
+pub struct TaggedEvent {
+    /// Client connection that produced the event
+    pub source: SourceId,
+    /// Translated event
+    pub event: LinuxInputEvent,
+}
+
+/// How the arbiter picks the controlling source
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ArbitrationPolicy {
+    /// Only the source given control with
+    /// [`grant_control`](InputArbiter::grant_control) is heard
+    Exclusive,
+
+    /// The first source to send input takes control and keeps it until it
+    /// has been idle for `idle_timeout`
+    FirstWins {
+        /// Idle time after which another source may take over
+        idle_timeout: Duration,
+    },
+
+    /// Like [`FirstWins`](Self::FirstWins), but a controller also yields
+    /// after `slice` while other sources are waiting, and control passes to
+    /// the waiting sources in the order they joined
+    RoundRobin {
+        /// Idle time after which the next waiting source takes over
+        idle_timeout: Duration,
+        /// Longest turn while other sources are waiting
+        slice: Duration,
+    },
+}
+
+impl Default for ArbitrationPolicy {
+    fn default() -> Self {
+        Self::FirstWins {
+            idle_timeout: Duration::from_millis(DEFAULT_IDLE_TIMEOUT_MS),
+        }
+    }
+}
+
+/// Keys and buttons a source has pressed and not released
+#[derive(Debug, Default)]
+struct Held {
+    /// Keycode to the scancode it was pressed with
+    keys: HashMap<u32, u16>,
+    buttons: HashSet<MouseButton>,
+}
+
+/// The source currently in control
+#[derive(Debug, Clone, Copy)]
+struct Controller {
+    source: SourceId,
+    since: Instant,
+    last_input: Instant,
+}
+
+/// Merges input from several sources, letting one control at a time
+#[derive(Debug)]
+pub struct InputArbiter {
+    policy: ArbitrationPolicy,
+
+    /// Sources in the order they joined
+    sources: Vec<SourceId>,
+
+    controller: Option<Controller>,
+
+    /// Sources that sent input without control, for round-robin
+    waiting: HashSet<SourceId>,
+
+    /// Presses forwarded for the controller
+    held: Held,
+
+    /// Events dropped because their source had no control
+    dropped: u64,
+}
+
+impl InputArbiter {
+    /// Create an arbiter with the given policy
+    pub fn new(policy: ArbitrationPolicy) -> Self {
+        Self {
+            policy,
+            sources: Vec::new(),
+            controller: None,
+            waiting: HashSet::new(),
+            held: Held::default(),
+            dropped: 0,
+        }
+    }
+
+    /// Get the arbitration policy
+    pub fn policy(&self) -> ArbitrationPolicy {
+        self.policy
+    }
+
+    /// Change the arbitration policy, keeping the current controller
+    pub fn set_policy(&mut self, policy: ArbitrationPolicy) {
+        self.policy = policy;
+    }
+
+    /// Register a source, fixing its place in the round-robin order
+    ///
+    /// Sources are also registered on their first event.
+    pub fn add_source(&mut self, source: SourceId) {
+        if !self.sources.contains(&source) {
+            self.sources.push(source);
+        }
+    }
+
+    /// Forget a disconnected source, releasing its keys if it had control
+    pub fn remove_source(&mut self, source: SourceId, now: Instant) -> Vec<TaggedEvent> {
+        self.sources.retain(|&s| s != source);
+        self.waiting.remove(&source);
+        if self.controller() == Some(source) {
+            self.hand_over(None, now)
+        } else {
+            Vec::new()
+        }
+    }
+
+    /// Registered sources in join order
+    pub fn sources(&self) -> &[SourceId] {
+        &self.sources
+    }
+
+    /// Source currently in control
+    pub fn controller(&self) -> Option<SourceId> {
+        self.controller.map(|c| c.source)
+    }
+
+    /// Give control to `source`, or to nobody
+    ///
+    /// Works under every policy, for example for a "take control" button.
+    /// Returns releases for what the previous controller still holds.
+    pub fn grant_control(&mut self, source: Option<SourceId>, now: Instant) -> Vec<TaggedEvent> {
+        if let Some(source) = source {
+            self.add_source(source);
+        }
+        if self.controller() == source {
+            return Vec::new();
+        }
+        self.hand_over(source, now)
+    }
+
+    /// Number of events dropped because their source had no control
+    pub fn dropped_events(&self) -> u64 {
+        self.dropped
+    }
+
+    /// Arbitrate one event
+    ///
+    /// Returns the events to inject: nothing if the source has no control,
+    /// otherwise the event itself, preceded by releases for the previous
+    /// controller if control just moved.
+    pub fn submit(&mut self, tagged: TaggedEvent, now: Instant) -> Vec<TaggedEvent> {
+        let source = tagged.source;
+        self.add_source(source);
+
+        let mut events = Vec::new();
+        if self.controller() != Some(source) {
+            match self.next_controller(source, now) {
+                Some(next) if next == source => events = self.hand_over(Some(source), now),
+                Some(next) => {
+                    events = self.hand_over(Some(next), now);
+                    self.reject(source);
+                    return events;
+                }
+                None => {
+                    self.reject(source);
+                    return events;
+                }
+            }
+        }
+
+        if let Some(controller) = &mut self.controller {
+            controller.last_input = now;
+        }
+        self.track(&tagged.event);
+        events.push(tagged);
+        events
+    }
+
+    /// Arbitrate several events from one source
+    pub fn submit_all(
+        &mut self,
+        source: SourceId,
+        events: impl IntoIterator<Item = LinuxInputEvent>,
+        now: Instant,
+    ) -> Vec<TaggedEvent> {
+        events
+            .into_iter()
+            .flat_map(|event| self.submit(TaggedEvent { source, event }, now))
+            .collect()
+    }
+
+    /// Who should control the session now that `source` sent input
+    fn next_controller(&self, source: SourceId, now: Instant) -> Option<SourceId> {
+        let Some(controller) = self.controller else {
+            return match self.policy {
+                ArbitrationPolicy::Exclusive => None,
+                _ => Some(source),
+            };
+        };
+
+        match self.policy {
+            ArbitrationPolicy::Exclusive => None,
+            ArbitrationPolicy::FirstWins { idle_timeout } => {
+                (now.duration_since(controller.last_input) >= idle_timeout).then_some(source)
+            }
+            ArbitrationPolicy::RoundRobin { idle_timeout, slice } => {
+                let idle = now.duration_since(controller.last_input) >= idle_timeout;
+                let turn_over = now.duration_since(controller.since) >= slice;
+                if !idle && !turn_over {
+                    return None;
+                }
+
+                // First waiting source after the controller in join order
+                let start = self
+                    .sources
+                    .iter()
+                    .position(|&s| s == controller.source)
+                    .map_or(0, |i| i + 1);
+                let len = self.sources.len();
+                (0..len)
+                    .map(|offset| self.sources[(start + offset) % len])
+                    .find(|&s| s != controller.source && (s == source || self.waiting.contains(&s)))
+            }
+        }
+    }
+
+    fn reject(&mut self, source: SourceId) {
+        self.dropped += 1;
+        if matches!(self.policy, ArbitrationPolicy::RoundRobin { .. }) {
+            self.waiting.insert(source);
+        }
+    }
+
+    /// Move control, releasing what the previous controller holds
+    fn hand_over(&mut self, source: Option<SourceId>, now: Instant) -> Vec<TaggedEvent> {
+        let previous = self.controller.take();
+        let held = std::mem::take(&mut self.held);
+        let mut events = Vec::new();
+
+        if let Some(previous) = previous {
+            debug!("Input control passes from source {} to {:?}", previous.source, source);
+            events.extend(held.keys.into_iter().map(|(keycode, scancode)| TaggedEvent {
+                source: previous.source,
+                event: LinuxInputEvent::Keyboard {
+                    event_type: KeyboardEventType::KeyUp,
+                    keycode,
+                    scancode,
+                    modifiers: KeyModifiers::default(),
+                    timestamp: now,
+                },
+            }));
+            events.extend(held.buttons.into_iter().map(|button| TaggedEvent {
+                source: previous.source,
+                event: LinuxInputEvent::MouseButton {
+                    button_code: button.to_linux_button(),
+                    button,
+                    pressed: false,
+                    timestamp: now,
+                },
+            }));
+        }
+
+        if let Some(source) = source {
+            self.waiting.remove(&source);
+            self.controller = Some(Controller {
+                source,
+                since: now,
+                last_input: now,
+            });
+        }
+        events
+    }
+
+    /// Record presses and releases forwarded for the controller
+    fn track(&mut self, event: &LinuxInputEvent) {
+        match *event {
+            LinuxInputEvent::Keyboard {
+                event_type: KeyboardEventType::KeyDown,
+                keycode,
+                scancode,
+                ..
+            } => {
+                self.held.keys.insert(keycode, scancode);
+            }
+            LinuxInputEvent::Keyboard {
+                event_type: KeyboardEventType::KeyUp,
+                keycode,
+                ..
+            } => {
+                self.held.keys.remove(&keycode);
+            }
+            LinuxInputEvent::MouseButton { button, pressed, .. } => {
+                if pressed {
+                    self.held.buttons.insert(button);
+                } else {
+                    self.held.buttons.remove(&button);
+                }
+            }
+            _ => {}
+        }
+    }
+}
+
+impl Default for InputArbiter {
+    fn default() -> Self {
+        Self::new(ArbitrationPolicy::default())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn mouse_move(source: SourceId, now: Instant) -> TaggedEvent {
+        TaggedEvent {
+            source,
+            event: LinuxInputEvent::MouseMove {
+                x: 0.0,
+                y: 0.0,
+                timestamp: now,
+            },
+        }
+    }
+
+    fn key(source: SourceId, keycode: u32, pressed: bool, now: Instant) -> TaggedEvent {
+        TaggedEvent {
+            source,
+            event: LinuxInputEvent::Keyboard {
+                event_type: if pressed {
+                    KeyboardEventType::KeyDown
+                } else {
+                    KeyboardEventType::KeyUp
+                },
+                keycode,
+                scancode: 0,
+                modifiers: KeyModifiers::default(),
+                timestamp: now,
+            },
+        }
+    }
+
+    fn sources(events: &[TaggedEvent]) -> Vec<SourceId> {
+        events.iter().map(|e| e.source).collect()
+    }
+
+    #[test]
+    fn test_first_wins() {
+        let mut arbiter = InputArbiter::new(ArbitrationPolicy::FirstWins {
+            idle_timeout: Duration::from_secs(2),
+        });
+        let start = Instant::now();
+
+        assert_eq!(sources(&arbiter.submit(mouse_move(1, start), start)), [1]);
+        assert!(arbiter.submit(mouse_move(2, start), start).is_empty());
+
+        let later = start + Duration::from_secs(1);
+        assert!(!arbiter.submit(key(1, 30, true, later), later).is_empty());
+        assert!(arbiter.submit(mouse_move(2, later), later).is_empty());
+        assert_eq!(arbiter.dropped_events(), 2);
+
+        // Source 1 goes idle holding a key: it is released before source 2 moves
+        let idle = later + Duration::from_secs(2);
+        let events = arbiter.submit(mouse_move(2, idle), idle);
+        assert_eq!(sources(&events), [1, 2]);
+        assert!(matches!(
+            events[0].event,
+            LinuxInputEvent::Keyboard {
+                event_type: KeyboardEventType::KeyUp,
+                keycode: 30,
+                ..
+            }
+        ));
+        assert_eq!(arbiter.controller(), Some(2));
+    }
+
+    #[test]
+    fn test_exclusive() {
+        let mut arbiter = InputArbiter::new(ArbitrationPolicy::Exclusive);
+        let now = Instant::now();
+
+        assert!(arbiter.submit(mouse_move(1, now), now).is_empty());
+        assert!(arbiter.grant_control(Some(2), now).is_empty());
+        assert!(arbiter.submit(mouse_move(1, now), now).is_empty());
+        assert_eq!(sources(&arbiter.submit(key(2, 42, true, now), now)), [2]);
+
+        // Idle time never matters
+        let later = now + Duration::from_secs(3600);
+        assert!(arbiter.submit(mouse_move(1, later), later).is_empty());
+
+        let released = arbiter.grant_control(Some(1), later);
+        assert_eq!(sources(&released), [2]);
+        assert_eq!(sources(&arbiter.submit(mouse_move(1, later), later)), [1]);
+    }
+
+    #[test]
+    fn test_round_robin() {
+        let mut arbiter = InputArbiter::new(ArbitrationPolicy::RoundRobin {
+            idle_timeout: Duration::from_secs(2),
+            slice: Duration::from_secs(5),
+        });
+        for source in [1, 2, 3] {
+            arbiter.add_source(source);
+        }
+        let start = Instant::now();
+
+        arbiter.submit(mouse_move(1, start), start);
+        let t1 = start + Duration::from_secs(1);
+        assert!(arbiter.submit(mouse_move(3, t1), t1).is_empty());
+        assert!(arbiter.submit(mouse_move(2, t1), t1).is_empty());
+
+        // Source 1 stays busy, but its turn ends; 2 is next in join order
+        let t2 = start + Duration::from_secs(4);
+        arbiter.submit(mouse_move(1, t2), t2);
+        let t3 = start + Duration::from_secs(5);
+        assert!(arbiter.submit(mouse_move(3, t3), t3).is_empty());
+        assert_eq!(arbiter.controller(), Some(2));
+
+        // 2 never used its turn; after it idles, 3 is next
+        let t4 = t3 + Duration::from_secs(2);
+        assert_eq!(sources(&arbiter.submit(mouse_move(3, t4), t4)), [3]);
+        assert_eq!(arbiter.controller(), Some(3));
+    }
+
+    #[test]
+    fn test_remove_controller_releases() {
+        let mut arbiter = InputArbiter::default();
+        let now = Instant::now();
+
+        arbiter.submit(key(1, 29, true, now), now);
+        let released = arbiter.remove_source(1, now);
+        assert_eq!(sources(&released), [1]);
+        assert_eq!(arbiter.controller(), None);
+        assert_eq!(sources(&arbiter.submit(mouse_move(2, now), now)), [2]);
+        assert_eq!(arbiter.sources(), [2]);
+    }
+}
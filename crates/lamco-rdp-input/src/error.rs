@@ -94,6 +94,10 @@ pub enum InputError {
     #[error("Invalid mouse event: {0}")]
     InvalidMouseEvent(String),
 
+    /// Invalid key remapping rule
+    #[error("Invalid remap rule: {0}")]
+    InvalidRemapRule(String),
+
     /// Unknown error
     #[error("Unknown error: {0}")]
     Unknown(String),
@@ -134,7 +138,10 @@ pub fn classify_error(error: &InputError) -> ErrorType {
         | InputError::InvalidCoordinate(_, _)
         | InputError::InvalidMonitorConfig(_) => ErrorType::Coordinate,
 
-        InputError::LayoutError(_) | InputError::LayoutNotFound(_) | InputError::XkbError(_) => ErrorType::Layout,
+        InputError::LayoutError(_)
+        | InputError::LayoutNotFound(_)
+        | InputError::XkbError(_)
+        | InputError::InvalidRemapRule(_) => ErrorType::Layout,
 
         InputError::EventQueueFull | InputError::EventSendFailed | InputError::EventReceiveFailed => {
             ErrorType::EventQueue
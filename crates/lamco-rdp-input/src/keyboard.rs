@@ -5,7 +5,8 @@
 
 use crate::error::Result;
 use crate::mapper::ScancodeMapper;
-use std::collections::HashSet;
+use crate::remap::RemapRules;
+use std::collections::{HashMap, HashSet};
 use std::time::Instant;
 use tracing::debug;
 
@@ -28,6 +29,29 @@ pub struct KeyModifiers {
     pub scroll_lock: bool,
 }
 
+/// How keypad keys are translated with respect to NumLock
+///
+/// RDP sends the same scancodes for keypad digits and keypad navigation;
+/// which one the user meant depends on the *client's* NumLock state, while
+/// the Linux keycodes for the keypad are interpreted using the *server's*.
+/// The two drift apart whenever NumLock is toggled while the session is not
+/// focused, so keypad input lands in the wrong mode.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum NumLockPolicy {
+    /// Emit keypad keycodes unchanged and let the server's NumLock decide
+    #[default]
+    Passthrough,
+
+    /// Resolve keypad keys using the client's NumLock state
+    ///
+    /// With client NumLock off, keypad keys become navigation keys (Home,
+    /// Up, ...) regardless of the server state. With client NumLock on they
+    /// stay keypad keys; if the server's NumLock is known to be off (see
+    /// [`KeyboardHandler::set_server_num_lock`]) digits are sent as main-row
+    /// digits instead, which type the same character in either server mode.
+    FollowClient,
+}
+
 /// Keyboard event types
 #[derive(Debug, Clone)]
 pub enum KeyboardEvent {
@@ -87,6 +111,23 @@ pub struct KeyboardHandler {
 
     /// Key repeat rate (milliseconds between repeats)
     repeat_rate_ms: u64,
+
+    /// Keypad NumLock handling
+    num_lock_policy: NumLockPolicy,
+
+    /// Server NumLock state, if known
+    server_num_lock: Option<bool>,
+
+    /// Keycodes substituted for pressed keypad keys, so the release matches
+    /// the press even if NumLock changes while the key is held
+    keypad_substitutions: HashMap<u32, u32>,
+
+    /// User-defined keycode substitutions
+    remap_rules: RemapRules,
+
+    /// Remapped keycodes of pressed keys, so the release matches the press
+    /// even if the rules change while the key is held
+    remapped_keys: HashMap<u32, u32>,
 }
 
 impl KeyboardHandler {
@@ -99,6 +140,11 @@ impl KeyboardHandler {
             last_key_times: std::collections::HashMap::new(),
             repeat_delay_ms: 500,
             repeat_rate_ms: 33,
+            num_lock_policy: NumLockPolicy::default(),
+            server_num_lock: None,
+            keypad_substitutions: HashMap::new(),
+            remap_rules: RemapRules::new(),
+            remapped_keys: HashMap::new(),
         }
     }
 
@@ -106,6 +152,20 @@ impl KeyboardHandler {
     pub fn handle_key_down(&mut self, scancode: u16, extended: bool, e1_prefix: bool) -> Result<KeyboardEvent> {
         // Translate scancode to keycode
         let keycode = self.mapper.translate_scancode(scancode as u32, extended, e1_prefix)?;
+        let keycode = *self
+            .remapped_keys
+            .entry(keycode)
+            .or_insert_with(|| self.remap_rules.apply(keycode));
+        let keycode = match self.keypad_substitutions.get(&keycode) {
+            Some(&substituted) => substituted,
+            None => {
+                let substituted = self.resolve_keypad(keycode);
+                if substituted != keycode {
+                    self.keypad_substitutions.insert(keycode, substituted);
+                }
+                substituted
+            }
+        };
 
         let timestamp = Instant::now();
 
@@ -162,6 +222,11 @@ impl KeyboardHandler {
     pub fn handle_key_up(&mut self, scancode: u16, extended: bool, e1_prefix: bool) -> Result<KeyboardEvent> {
         // Translate scancode to keycode
         let keycode = self.mapper.translate_scancode(scancode as u32, extended, e1_prefix)?;
+        let keycode = self
+            .remapped_keys
+            .remove(&keycode)
+            .unwrap_or_else(|| self.remap_rules.apply(keycode));
+        let keycode = self.keypad_substitutions.remove(&keycode).unwrap_or(keycode);
 
         let timestamp = Instant::now();
 
@@ -228,6 +293,8 @@ impl KeyboardHandler {
             KEY_NUMLOCK => {
                 if pressed {
                     self.modifiers.num_lock = !self.modifiers.num_lock;
+                    // The key is forwarded, so the server toggles too
+                    self.server_num_lock = self.server_num_lock.map(|on| !on);
                 }
             }
             KEY_SCROLLLOCK => {
@@ -239,6 +306,94 @@ impl KeyboardHandler {
         }
     }
 
+    /// Keycode to emit for a keypad key under the current NumLock policy
+    fn resolve_keypad(&self, keycode: u32) -> u32 {
+        #[allow(clippy::wildcard_imports)]
+        use crate::mapper::keycodes::*;
+
+        if self.num_lock_policy == NumLockPolicy::Passthrough {
+            return keycode;
+        }
+
+        if !self.modifiers.num_lock {
+            return match keycode {
+                KEY_KP7 => KEY_HOME,
+                KEY_KP8 => KEY_UP,
+                KEY_KP9 => KEY_PAGEUP,
+                KEY_KP4 => KEY_LEFT,
+                KEY_KP6 => KEY_RIGHT,
+                KEY_KP1 => KEY_END,
+                KEY_KP2 => KEY_DOWN,
+                KEY_KP3 => KEY_PAGEDOWN,
+                KEY_KP0 => KEY_INSERT,
+                KEY_KPDOT => KEY_DELETE,
+                // KP5 has no navigation meaning
+                _ => keycode,
+            };
+        }
+
+        if self.server_num_lock == Some(false) {
+            return match keycode {
+                KEY_KP0 => KEY_0,
+                KEY_KP1 => KEY_1,
+                KEY_KP2 => KEY_2,
+                KEY_KP3 => KEY_3,
+                KEY_KP4 => KEY_4,
+                KEY_KP5 => KEY_5,
+                KEY_KP6 => KEY_6,
+                KEY_KP7 => KEY_7,
+                KEY_KP8 => KEY_8,
+                KEY_KP9 => KEY_9,
+                KEY_KPDOT => KEY_DOT,
+                _ => keycode,
+            };
+        }
+
+        keycode
+    }
+
+    /// Set lock key states from an RDP synchronize event
+    ///
+    /// Clients send the synchronize event on connect and when the session
+    /// regains focus, carrying their actual Caps/Num/Scroll Lock states.
+    pub fn synchronize_locks(&mut self, caps_lock: bool, num_lock: bool, scroll_lock: bool) {
+        self.modifiers.caps_lock = caps_lock;
+        self.modifiers.num_lock = num_lock;
+        self.modifiers.scroll_lock = scroll_lock;
+        debug!(
+            "Lock state synchronized: caps={}, num={}, scroll={}",
+            caps_lock, num_lock, scroll_lock
+        );
+    }
+
+    /// Set the keypad NumLock policy
+    pub fn set_num_lock_policy(&mut self, policy: NumLockPolicy) {
+        self.num_lock_policy = policy;
+    }
+
+    /// Get the keypad NumLock policy
+    pub fn num_lock_policy(&self) -> NumLockPolicy {
+        self.num_lock_policy
+    }
+
+    /// Record the server's NumLock state, or `None` if it is unknown
+    ///
+    /// Only used by [`NumLockPolicy::FollowClient`].
+    pub fn set_server_num_lock(&mut self, state: Option<bool>) {
+        self.server_num_lock = state;
+    }
+
+    /// Set the user-defined key remapping, applied after the layout mapping
+    pub fn set_remap_rules(&mut self, rules: RemapRules) {
+        debug!("Key remapping set: {} keys", rules.len());
+        self.remap_rules = rules;
+    }
+
+    /// Get the user-defined key remapping
+    pub fn remap_rules(&self) -> &RemapRules {
+        &self.remap_rules
+    }
+
     /// Check if a key is currently pressed
     pub fn is_key_pressed(&self, keycode: u32) -> bool {
         self.pressed_keys.contains(&keycode)
@@ -274,6 +429,8 @@ impl KeyboardHandler {
     pub fn reset(&mut self) {
         self.pressed_keys.clear();
         self.last_key_times.clear();
+        self.keypad_substitutions.clear();
+        self.remapped_keys.clear();
         self.modifiers = KeyModifiers::default();
         debug!("Keyboard state reset");
     }
@@ -298,6 +455,7 @@ impl Default for KeyboardHandler {
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::mapper::keycodes::{KEY_7, KEY_CAPSLOCK, KEY_ESC, KEY_HOME, KEY_KP7, KEY_KP8, KEY_LEFTCTRL, KEY_Z};
 
     #[test]
     fn test_keyboard_handler_creation() {
@@ -513,4 +671,115 @@ mod tests {
             _ => panic!("Expected KeyDown event"),
         }
     }
+
+    #[test]
+    fn test_keypad_passthrough() {
+        let mut handler = KeyboardHandler::new();
+
+        // Keypad 7 stays a keypad key regardless of NumLock
+        match handler.handle_key_down(0x47, false, false).unwrap() {
+            KeyboardEvent::KeyDown { keycode, .. } => assert_eq!(keycode, KEY_KP7),
+            _ => panic!("Expected KeyDown"),
+        }
+    }
+
+    #[test]
+    fn test_keypad_follow_client_navigation() {
+        let mut handler = KeyboardHandler::new();
+        handler.set_num_lock_policy(NumLockPolicy::FollowClient);
+        handler.synchronize_locks(false, false, false);
+
+        match handler.handle_key_down(0x47, false, false).unwrap() {
+            KeyboardEvent::KeyDown { keycode, .. } => assert_eq!(keycode, KEY_HOME),
+            _ => panic!("Expected KeyDown"),
+        }
+
+        // NumLock turned on while the key is held: release must match the press
+        handler.synchronize_locks(false, true, false);
+        match handler.handle_key_up(0x47, false, false).unwrap() {
+            KeyboardEvent::KeyUp { keycode, .. } => assert_eq!(keycode, KEY_HOME),
+            _ => panic!("Expected KeyUp"),
+        }
+        assert_eq!(handler.pressed_key_count(), 0);
+    }
+
+    #[test]
+    fn test_keypad_follow_client_digits() {
+        let mut handler = KeyboardHandler::new();
+        handler.set_num_lock_policy(NumLockPolicy::FollowClient);
+        handler.synchronize_locks(false, true, false);
+
+        // Server state unknown: keypad keys pass through
+        match handler.handle_key_down(0x47, false, false).unwrap() {
+            KeyboardEvent::KeyDown { keycode, .. } => assert_eq!(keycode, KEY_KP7),
+            _ => panic!("Expected KeyDown"),
+        }
+        handler.handle_key_up(0x47, false, false).unwrap();
+
+        // Server NumLock off: send main-row digits instead
+        handler.set_server_num_lock(Some(false));
+        match handler.handle_key_down(0x47, false, false).unwrap() {
+            KeyboardEvent::KeyDown { keycode, .. } => assert_eq!(keycode, KEY_7),
+            _ => panic!("Expected KeyDown"),
+        }
+        handler.handle_key_up(0x47, false, false).unwrap();
+
+        // Extended arrow keys are never affected
+        match handler.handle_key_down(0x47, true, false).unwrap() {
+            KeyboardEvent::KeyDown { keycode, .. } => assert_eq!(keycode, KEY_HOME),
+            _ => panic!("Expected KeyDown"),
+        }
+    }
+
+    #[test]
+    fn test_num_lock_key_toggles_known_server_state() {
+        let mut handler = KeyboardHandler::new();
+        handler.set_num_lock_policy(NumLockPolicy::FollowClient);
+        handler.synchronize_locks(false, false, false);
+        handler.set_server_num_lock(Some(false));
+
+        handler.handle_key_down(0x45, false, false).unwrap();
+        handler.handle_key_up(0x45, false, false).unwrap();
+        assert!(handler.modifiers().num_lock);
+
+        // Both sides now on: keypad keys pass through
+        match handler.handle_key_down(0x48, false, false).unwrap() {
+            KeyboardEvent::KeyDown { keycode, .. } => assert_eq!(keycode, KEY_KP8),
+            _ => panic!("Expected KeyDown"),
+        }
+    }
+
+    #[test]
+    fn test_remap_after_layout() {
+        let mut handler = KeyboardHandler::new();
+        handler.set_layout("de");
+        handler.set_remap_rules(RemapRules::new().swap(KEY_CAPSLOCK, KEY_LEFTCTRL));
+
+        // CapsLock now acts as Ctrl and no longer toggles
+        match handler.handle_key_down(0x3A, false, false).unwrap() {
+            KeyboardEvent::KeyDown { keycode, modifiers, .. } => {
+                assert_eq!(keycode, KEY_LEFTCTRL);
+                assert!(modifiers.ctrl);
+                assert!(!modifiers.caps_lock);
+            }
+            _ => panic!("Expected KeyDown"),
+        }
+
+        // Rules changing while the key is held do not strand the press
+        handler.set_remap_rules(RemapRules::new());
+        match handler.handle_key_up(0x3A, false, false).unwrap() {
+            KeyboardEvent::KeyUp { keycode, modifiers, .. } => {
+                assert_eq!(keycode, KEY_LEFTCTRL);
+                assert!(!modifiers.ctrl);
+            }
+            _ => panic!("Expected KeyUp"),
+        }
+
+        // Rules name the key the layout produced: 0x15 is Z on QWERTZ
+        handler.set_remap_rules(RemapRules::new().map(KEY_Z, KEY_ESC));
+        match handler.handle_key_down(0x15, false, false).unwrap() {
+            KeyboardEvent::KeyDown { keycode, .. } => assert_eq!(keycode, KEY_ESC),
+            _ => panic!("Expected KeyDown"),
+        }
+    }
 }
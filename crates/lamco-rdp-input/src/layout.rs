@@ -0,0 +1,119 @@
+//! Keyboard layout detection from the client's keyboard layout ID.
+//!
+//! RDP clients report their active keyboard layout in the `keyboardLayout`
+//! field of Client Core Data (TS_UD_CS_CORE) as a Windows keyboard layout ID:
+//! the low word is the language ID (`0x0409` US English, `0x0407` German) and
+//! the high word selects a variant (`0x00010409` US Dvorak).
+//!
+//! [`layout_for_keyboard_layout_id`] maps those IDs to the layout tables of
+//! the [`ScancodeMapper`](crate::ScancodeMapper), so integrators can call
+//! [`InputTranslator::apply_keyboard_layout_id`](crate::InputTranslator::apply_keyboard_layout_id)
+//! at session start instead of keeping their own lookup table.
+
+/// Callback that picks a layout for a keyboard layout ID before the built-in table
+pub type LayoutResolver = Box<dyn Fn(u32) -> Option<String> + Send + Sync>;
+
+/// Keyboard layout IDs and the mapper layout with the same key positions
+const KEYBOARD_LAYOUTS: &[(u32, &str)] = &[
+    // QWERTY
+    (0x0409, "us"), // English (United States)
+    (0x0809, "us"), // English (United Kingdom)
+    (0x0C09, "us"), // English (Australia)
+    (0x1009, "us"), // English (Canada)
+    (0x1409, "us"), // English (New Zealand)
+    (0x1809, "us"), // English (Ireland)
+    (0x0C0C, "us"), // French (Canada)
+    (0x0410, "us"), // Italian
+    (0x040A, "us"), // Spanish (traditional sort)
+    (0x0C0A, "us"), // Spanish
+    (0x080A, "us"), // Spanish (Latin America)
+    (0x0416, "us"), // Portuguese (Brazil)
+    (0x0816, "us"), // Portuguese
+    (0x0413, "us"), // Dutch
+    (0x0406, "us"), // Danish
+    (0x0414, "us"), // Norwegian
+    (0x041D, "us"), // Swedish
+    (0x040B, "us"), // Finnish
+    (0x0415, "us"), // Polish (programmers)
+    (0x0408, "us"), // Greek
+    (0x041F, "us"), // Turkish Q
+    (0x0419, "us"), // Russian
+    (0x0422, "us"), // Ukrainian
+    (0x0411, "us"), // Japanese
+    (0x0412, "us"), // Korean
+    (0x0404, "us"), // Chinese (Traditional)
+    (0x0804, "us"), // Chinese (Simplified)
+    // QWERTZ
+    (0x0407, "de"), // German
+    (0x0C07, "de"), // German (Austria)
+    (0x0807, "de"), // German (Switzerland)
+    (0x1007, "de"), // German (Luxembourg)
+    (0x1407, "de"), // German (Liechtenstein)
+    (0x100C, "de"), // French (Switzerland)
+    (0x0405, "de"), // Czech
+    (0x041B, "de"), // Slovak
+    (0x040E, "de"), // Hungarian
+    (0x0424, "de"), // Slovenian
+    (0x041A, "de"), // Croatian
+    // AZERTY
+    (0x040C, "fr"), // French
+    (0x080C, "fr"), // French (Belgium)
+    (0x0813, "fr"), // Dutch (Belgium)
+    (0x140C, "fr"), // French (Luxembourg)
+    (0x180C, "fr"), // French (Monaco)
+];
+
+/// Map a Windows keyboard layout ID to a [`ScancodeMapper`](crate::ScancodeMapper) layout
+///
+/// Variants without an entry of their own fall back to their language ID.
+/// Returns `None` for languages not in the table.
+///
+/// # Example
+///
+/// ```
+/// use lamco_rdp_input::layout::layout_for_keyboard_layout_id;
+///
+/// assert_eq!(layout_for_keyboard_layout_id(0x0407), Some("de"));
+/// assert_eq!(layout_for_keyboard_layout_id(0x0001_0409), Some("us"));
+/// assert_eq!(layout_for_keyboard_layout_id(0x0436), None);
+/// ```
+pub fn layout_for_keyboard_layout_id(keyboard_layout: u32) -> Option<&'static str> {
+    let find = |id: u32| {
+        KEYBOARD_LAYOUTS
+            .iter()
+            .find(|(klid, _)| *klid == id)
+            .map(|(_, layout)| *layout)
+    };
+    find(keyboard_layout).or_else(|| find(keyboard_layout & 0xFFFF))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_position_families() {
+        assert_eq!(layout_for_keyboard_layout_id(0x0409), Some("us"));
+        assert_eq!(layout_for_keyboard_layout_id(0x0809), Some("us"));
+        assert_eq!(layout_for_keyboard_layout_id(0x0807), Some("de"));
+        assert_eq!(layout_for_keyboard_layout_id(0x080C), Some("fr"));
+        assert_eq!(layout_for_keyboard_layout_id(0x0C0C), Some("us"));
+    }
+
+    #[test]
+    fn test_variant_falls_back_to_language() {
+        // German (IBM)
+        assert_eq!(layout_for_keyboard_layout_id(0x0001_0407), Some("de"));
+        assert_eq!(layout_for_keyboard_layout_id(0x0001_0000), None);
+    }
+
+    #[test]
+    fn test_table_has_no_duplicates() {
+        for (i, (id, _)) in KEYBOARD_LAYOUTS.iter().enumerate() {
+            assert!(
+                !KEYBOARD_LAYOUTS[i + 1..].iter().any(|(other, _)| other == id),
+                "duplicate {id:#06x}"
+            );
+        }
+    }
+}
@@ -23,6 +23,7 @@
 //!   - Toggle key handling (Caps Lock, Num Lock, Scroll Lock)
 //!   - Key repeat detection with configurable timing
 //!   - Bidirectional scancode ↔ keycode translation
+//!   - User-defined key remapping (swap CapsLock/Ctrl and similar)
 //!
 //! - **Advanced Mouse Support**
 //!   - Absolute and relative movement
@@ -32,6 +33,14 @@
 //!   - Button state tracking
 //!   - Timestamp tracking for event ordering
 //!
+//! - **Multi-Client Input Merge**
+//!   - Events tagged with the client connection they came from
+//!   - Exclusive, first-wins and round-robin control arbitration
+//!   - Held keys and buttons released when control moves
+//!
+//! - **Test Fixtures** (`testing` feature)
+//!   - Scripted key, chord, typing, click, drag and scroll sequences
+//!
 //! - **Multi-Monitor Coordinate Transformation**
 //!   - Complete transformation pipeline (RDP → Virtual Desktop → Monitor → Stream)
 //!   - DPI scaling and monitor scale factor support
@@ -103,8 +112,11 @@
 //! // Create translator
 //! let mut translator = InputTranslator::new(monitors)?;
 //!
-//! // Configure keyboard layout
-//! translator.set_keyboard_layout("us");
+//! // Configure keyboard layout from the client's keyboard layout ID (Client Core Data)
+//! translator.apply_keyboard_layout_id(0x0409);
+//!
+//! // Swap CapsLock and Ctrl, as loaded from the user's config
+//! translator.set_remap_rules("capslock <> leftctrl".parse()?);
 //!
 //! // Configure mouse acceleration
 //! translator.set_mouse_acceleration(true);
@@ -244,20 +256,29 @@
 #![cfg_attr(docsrs, feature(doc_cfg))]
 
 // Core modules
+pub mod arbiter;
 pub mod coordinates;
 pub mod error;
 pub mod keyboard;
+pub mod layout;
 pub mod mapper;
 pub mod mouse;
+pub mod remap;
 pub mod translator;
 
+#[cfg(any(test, feature = "testing"))]
+pub mod testing;
+
 // Re-export main types for convenience
+pub use arbiter::{ArbitrationPolicy, InputArbiter, SourceId, TaggedEvent};
 pub use coordinates::{CoordinateTransformer, MonitorInfo};
 pub use error::{ErrorContext, InputError, RecoveryAction, Result};
-pub use keyboard::{KeyModifiers, KeyboardEvent, KeyboardHandler};
+pub use keyboard::{KeyModifiers, KeyboardEvent, KeyboardHandler, NumLockPolicy};
+pub use layout::{layout_for_keyboard_layout_id, LayoutResolver};
 pub use mapper::{keycodes, ScancodeMapper};
 pub use mouse::{MouseButton, MouseEvent, MouseHandler};
-pub use translator::{InputTranslator, KeyboardEventType, LinuxInputEvent, RdpInputEvent};
+pub use remap::RemapRules;
+pub use translator::{InputTranslator, KeyboardEventType, LinuxInputEvent, RdpInputEvent, DEFAULT_CHORD_TIMEOUT_MS};
 
 // Re-export commonly used types at module level
 /// Convenience re-export of Result type
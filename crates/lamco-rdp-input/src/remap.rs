@@ -0,0 +1,395 @@
+//! User-defined key remapping.
+//!
+//! [`RemapRules`] replaces keycodes after the layout mapping, so a rule names
+//! the key the layout produced, not the client scancode. Rules are applied
+//! once and do not chain, which makes swaps such as CapsLock and Ctrl work:
+//!
+//! ```text
+//! # Swap CapsLock and left Ctrl
+//! capslock <> leftctrl
+//!
+//! # Right Alt acts as Compose
+//! rightalt = compose
+//! ```
+//!
+//! Keys are named after their evdev constant, with or without the `KEY_`
+//! prefix and in any case (`KEY_CAPSLOCK`, `capslock`), or given as a raw
+//! keycode in hex (`0x3a`). `#` starts a comment.
+//!
+//! ```
+//! use lamco_rdp_input::mapper::keycodes::{KEY_CAPSLOCK, KEY_LEFTCTRL};
+//! use lamco_rdp_input::RemapRules;
+//!
+//! let rules: RemapRules = "capslock <> leftctrl".parse().unwrap();
+//! assert_eq!(rules.apply(KEY_CAPSLOCK), KEY_LEFTCTRL);
+//! assert_eq!(rules.apply(KEY_LEFTCTRL), KEY_CAPSLOCK);
+//! ```
+
+use crate::error::{InputError, Result};
+#[allow(clippy::wildcard_imports)]
+use crate::mapper::keycodes::*;
+use std::collections::HashMap;
+use std::str::FromStr;
+
+/// Key names accepted in rules, without the `KEY_` prefix
+const KEY_NAMES: &[(&str, u32)] = &[
+    ("esc", KEY_ESC),
+    ("1", KEY_1),
+    ("2", KEY_2),
+    ("3", KEY_3),
+    ("4", KEY_4),
+    ("5", KEY_5),
+    ("6", KEY_6),
+    ("7", KEY_7),
+    ("8", KEY_8),
+    ("9", KEY_9),
+    ("0", KEY_0),
+    ("minus", KEY_MINUS),
+    ("equal", KEY_EQUAL),
+    ("backspace", KEY_BACKSPACE),
+    ("tab", KEY_TAB),
+    ("q", KEY_Q),
+    ("w", KEY_W),
+    ("e", KEY_E),
+    ("r", KEY_R),
+    ("t", KEY_T),
+    ("y", KEY_Y),
+    ("u", KEY_U),
+    ("i", KEY_I),
+    ("o", KEY_O),
+    ("p", KEY_P),
+    ("leftbrace", KEY_LEFTBRACE),
+    ("rightbrace", KEY_RIGHTBRACE),
+    ("enter", KEY_ENTER),
+    ("leftctrl", KEY_LEFTCTRL),
+    ("a", KEY_A),
+    ("s", KEY_S),
+    ("d", KEY_D),
+    ("f", KEY_F),
+    ("g", KEY_G),
+    ("h", KEY_H),
+    ("j", KEY_J),
+    ("k", KEY_K),
+    ("l", KEY_L),
+    ("semicolon", KEY_SEMICOLON),
+    ("apostrophe", KEY_APOSTROPHE),
+    ("grave", KEY_GRAVE),
+    ("leftshift", KEY_LEFTSHIFT),
+    ("backslash", KEY_BACKSLASH),
+    ("z", KEY_Z),
+    ("x", KEY_X),
+    ("c", KEY_C),
+    ("v", KEY_V),
+    ("b", KEY_B),
+    ("n", KEY_N),
+    ("m", KEY_M),
+    ("comma", KEY_COMMA),
+    ("dot", KEY_DOT),
+    ("slash", KEY_SLASH),
+    ("rightshift", KEY_RIGHTSHIFT),
+    ("kpasterisk", KEY_KPASTERISK),
+    ("leftalt", KEY_LEFTALT),
+    ("space", KEY_SPACE),
+    ("capslock", KEY_CAPSLOCK),
+    ("f1", KEY_F1),
+    ("f2", KEY_F2),
+    ("f3", KEY_F3),
+    ("f4", KEY_F4),
+    ("f5", KEY_F5),
+    ("f6", KEY_F6),
+    ("f7", KEY_F7),
+    ("f8", KEY_F8),
+    ("f9", KEY_F9),
+    ("f10", KEY_F10),
+    ("numlock", KEY_NUMLOCK),
+    ("scrolllock", KEY_SCROLLLOCK),
+    ("kp7", KEY_KP7),
+    ("kp8", KEY_KP8),
+    ("kp9", KEY_KP9),
+    ("kpminus", KEY_KPMINUS),
+    ("kp4", KEY_KP4),
+    ("kp5", KEY_KP5),
+    ("kp6", KEY_KP6),
+    ("kpplus", KEY_KPPLUS),
+    ("kp1", KEY_KP1),
+    ("kp2", KEY_KP2),
+    ("kp3", KEY_KP3),
+    ("kp0", KEY_KP0),
+    ("kpdot", KEY_KPDOT),
+    ("102nd", KEY_102ND),
+    ("f11", KEY_F11),
+    ("f12", KEY_F12),
+    ("ro", KEY_RO),
+    ("katakanahiragana", KEY_KATAKANAHIRAGANA),
+    ("henkan", KEY_HENKAN),
+    ("muhenkan", KEY_MUHENKAN),
+    ("kpenter", KEY_KPENTER),
+    ("rightctrl", KEY_RIGHTCTRL),
+    ("kpslash", KEY_KPSLASH),
+    ("sysrq", KEY_SYSRQ),
+    ("rightalt", KEY_RIGHTALT),
+    ("home", KEY_HOME),
+    ("up", KEY_UP),
+    ("pageup", KEY_PAGEUP),
+    ("left", KEY_LEFT),
+    ("right", KEY_RIGHT),
+    ("end", KEY_END),
+    ("down", KEY_DOWN),
+    ("pagedown", KEY_PAGEDOWN),
+    ("insert", KEY_INSERT),
+    ("delete", KEY_DELETE),
+    ("mute", KEY_MUTE),
+    ("volumedown", KEY_VOLUMEDOWN),
+    ("volumeup", KEY_VOLUMEUP),
+    ("power", KEY_POWER),
+    ("kpequal", KEY_KPEQUAL),
+    ("pause", KEY_PAUSE),
+    ("kpcomma", KEY_KPCOMMA),
+    ("hangeul", KEY_HANGEUL),
+    ("hanja", KEY_HANJA),
+    ("yen", KEY_YEN),
+    ("leftmeta", KEY_LEFTMETA),
+    ("rightmeta", KEY_RIGHTMETA),
+    ("compose", KEY_COMPOSE),
+    ("stop", KEY_STOP),
+    ("again", KEY_AGAIN),
+    ("props", KEY_PROPS),
+    ("undo", KEY_UNDO),
+    ("front", KEY_FRONT),
+    ("copy", KEY_COPY),
+    ("open", KEY_OPEN),
+    ("paste", KEY_PASTE),
+    ("find", KEY_FIND),
+    ("cut", KEY_CUT),
+    ("help", KEY_HELP),
+    ("menu", KEY_MENU),
+    ("calc", KEY_CALC),
+    ("sleep", KEY_SLEEP),
+    ("wakeup", KEY_WAKEUP),
+    ("www", KEY_WWW),
+    ("mail", KEY_MAIL),
+    ("bookmarks", KEY_BOOKMARKS),
+    ("computer", KEY_COMPUTER),
+    ("back", KEY_BACK),
+    ("forward", KEY_FORWARD),
+    ("ejectcd", KEY_EJECTCD),
+    ("nextsong", KEY_NEXTSONG),
+    ("playpause", KEY_PLAYPAUSE),
+    ("previoussong", KEY_PREVIOUSSONG),
+    ("stopcd", KEY_STOPCD),
+    ("refresh", KEY_REFRESH),
+    ("f13", KEY_F13),
+    ("f14", KEY_F14),
+    ("f15", KEY_F15),
+    ("f16", KEY_F16),
+    ("f17", KEY_F17),
+    ("f18", KEY_F18),
+    ("f19", KEY_F19),
+    ("f20", KEY_F20),
+    ("f21", KEY_F21),
+    ("f22", KEY_F22),
+    ("f23", KEY_F23),
+    ("f24", KEY_F24),
+    ("media", KEY_MEDIA),
+    ("search", KEY_SEARCH),
+    ("homepage", KEY_HOMEPAGE),
+    ("break", KEY_BREAK),
+    ("print", KEY_PRINT),
+];
+
+/// Look up a keycode by name or hex value
+///
+/// # Example
+///
+/// ```
+/// use lamco_rdp_input::mapper::keycodes::KEY_CAPSLOCK;
+/// use lamco_rdp_input::remap::keycode_from_name;
+///
+/// assert_eq!(keycode_from_name("KEY_CAPSLOCK"), Some(KEY_CAPSLOCK));
+/// assert_eq!(keycode_from_name("CapsLock"), Some(KEY_CAPSLOCK));
+/// assert_eq!(keycode_from_name("0x3a"), Some(KEY_CAPSLOCK));
+/// assert_eq!(keycode_from_name("hyper"), None);
+/// ```
+pub fn keycode_from_name(name: &str) -> Option<u32> {
+    let name = name.trim();
+    if let Some(hex) = name.strip_prefix("0x").or_else(|| name.strip_prefix("0X")) {
+        return u32::from_str_radix(hex, 16).ok();
+    }
+
+    let name = name.to_ascii_lowercase();
+    let name = name.strip_prefix("key_").unwrap_or(&name);
+    KEY_NAMES
+        .iter()
+        .find(|(candidate, _)| *candidate == name)
+        .map(|(_, keycode)| *keycode)
+}
+
+/// Name of a keycode as accepted by [`keycode_from_name`]
+pub fn keycode_name(keycode: u32) -> Option<&'static str> {
+    KEY_NAMES
+        .iter()
+        .find(|(_, candidate)| *candidate == keycode)
+        .map(|(name, _)| *name)
+}
+
+/// Keycode substitutions applied after layout mapping
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct RemapRules {
+    /// Source keycode to target keycode
+    rules: HashMap<u32, u32>,
+}
+
+impl RemapRules {
+    /// Create an empty rule set
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Parse rules from config text, one rule per line
+    ///
+    /// `from = to` maps one key, `a <> b` swaps two. Fails on unknown key
+    /// names and on keys given more than one target.
+    pub fn parse(text: &str) -> Result<Self> {
+        let mut rules = Self::new();
+
+        for (index, line) in text.lines().enumerate() {
+            let line = line.split('#').next().unwrap_or_default().trim();
+            if line.is_empty() {
+                continue;
+            }
+            let line_no = index + 1;
+
+            let (from, to, swap) = if let Some((from, to)) = line.split_once("<>") {
+                (from, to, true)
+            } else if let Some((from, to)) = line.split_once('=') {
+                (from, to, false)
+            } else {
+                return Err(InputError::InvalidRemapRule(format!(
+                    "line {line_no}: expected `from = to` or `a <> b`, got `{line}`"
+                )));
+            };
+
+            let key = |name: &str| {
+                keycode_from_name(name).ok_or_else(|| {
+                    InputError::InvalidRemapRule(format!("line {line_no}: unknown key `{}`", name.trim()))
+                })
+            };
+            let (from, to) = (key(from)?, key(to)?);
+
+            if swap {
+                rules.try_insert(from, to, line_no)?;
+                rules.try_insert(to, from, line_no)?;
+            } else {
+                rules.try_insert(from, to, line_no)?;
+            }
+        }
+
+        Ok(rules)
+    }
+
+    /// Add a rule, failing if `from` already has a different target
+    fn try_insert(&mut self, from: u32, to: u32, line_no: usize) -> Result<()> {
+        match self.rules.insert(from, to) {
+            Some(previous) if previous != to => Err(InputError::InvalidRemapRule(format!(
+                "line {line_no}: {} is already mapped to {}",
+                keycode_name(from).map_or_else(|| format!("{from:#x}"), str::to_string),
+                keycode_name(previous).map_or_else(|| format!("{previous:#x}"), str::to_string),
+            ))),
+            _ => Ok(()),
+        }
+    }
+
+    /// Map `from` to `to`, replacing any earlier rule for `from`
+    pub fn map(mut self, from: u32, to: u32) -> Self {
+        self.rules.insert(from, to);
+        self
+    }
+
+    /// Swap two keys
+    pub fn swap(self, a: u32, b: u32) -> Self {
+        self.map(a, b).map(b, a)
+    }
+
+    /// Keycode to emit for `keycode`
+    pub fn apply(&self, keycode: u32) -> u32 {
+        self.rules.get(&keycode).copied().unwrap_or(keycode)
+    }
+
+    /// Number of remapped keys
+    pub fn len(&self) -> usize {
+        self.rules.len()
+    }
+
+    /// Whether no key is remapped
+    pub fn is_empty(&self) -> bool {
+        self.rules.is_empty()
+    }
+}
+
+impl FromStr for RemapRules {
+    type Err = InputError;
+
+    fn from_str(s: &str) -> Result<Self> {
+        Self::parse(s)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_map_and_swap() {
+        let rules = RemapRules::parse(
+            "# VDI tweaks\n\
+             KEY_CAPSLOCK <> KEY_LEFTCTRL\n\
+             \n\
+             rightalt = compose  # for accented characters\n",
+        )
+        .unwrap();
+
+        assert_eq!(rules.len(), 3);
+        assert_eq!(rules.apply(KEY_CAPSLOCK), KEY_LEFTCTRL);
+        assert_eq!(rules.apply(KEY_LEFTCTRL), KEY_CAPSLOCK);
+        assert_eq!(rules.apply(KEY_RIGHTALT), KEY_COMPOSE);
+        // Rules do not chain
+        assert_eq!(rules.apply(KEY_COMPOSE), KEY_COMPOSE);
+        assert_eq!(rules.apply(KEY_A), KEY_A);
+    }
+
+    #[test]
+    fn test_parse_errors() {
+        assert!(matches!(
+            RemapRules::parse("capslock leftctrl"),
+            Err(InputError::InvalidRemapRule(_))
+        ));
+        assert!(matches!(
+            RemapRules::parse("capslock = hyper"),
+            Err(InputError::InvalidRemapRule(_))
+        ));
+
+        let err = RemapRules::parse("capslock = leftctrl\ncapslock = esc").unwrap_err();
+        assert!(err.to_string().contains("line 2"));
+
+        // Repeating the same rule is harmless
+        assert!(RemapRules::parse("capslock = esc\nKEY_CAPSLOCK = KEY_ESC").is_ok());
+    }
+
+    #[test]
+    fn test_builder() {
+        let rules = RemapRules::new().swap(KEY_LEFTMETA, KEY_LEFTALT).map(0x3a, KEY_ESC);
+        assert_eq!(rules.apply(KEY_LEFTMETA), KEY_LEFTALT);
+        assert_eq!(rules.apply(KEY_LEFTALT), KEY_LEFTMETA);
+        assert_eq!(rules.apply(KEY_CAPSLOCK), KEY_ESC);
+    }
+
+    #[test]
+    fn test_key_names_unique() {
+        for (i, (name, _)) in KEY_NAMES.iter().enumerate() {
+            assert!(
+                !KEY_NAMES[i + 1..].iter().any(|(other, _)| other == name),
+                "duplicate {name}"
+            );
+        }
+    }
+}
@@ -0,0 +1,6 @@
+//! Test support for crates built on this one
+//!
+//! Enabled with the `testing` feature, so integrators can script RDP input in
+//! their own tests without copying ours.
+
+pub mod fixtures;
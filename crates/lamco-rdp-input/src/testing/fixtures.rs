@@ -0,0 +1,288 @@
+//! Builders for RDP input event sequences
+//!
+//! Scancodes use the same notation as the [`ScancodeMapper`](crate::ScancodeMapper)
+//! tables: plain set-1 codes for standard keys and `0xE0xx` for extended ones.
+//!
+//! # Example
+//!
+//! ```
+//! use lamco_rdp_input::testing::fixtures::{InputSequence, LEFT_CTRL};
+//!
+//! let events = InputSequence::new().chord(&[LEFT_CTRL], 0x2E).type_ascii("hi").build();
+//! assert_eq!(events.len(), 8);
+//! ```
+
+use crate::mouse::MouseButton;
+use crate::translator::RdpInputEvent;
+
+/// Left Shift scancode
+pub const LEFT_SHIFT: u16 = 0x2A;
+/// Left Ctrl scancode
+pub const LEFT_CTRL: u16 = 0x1D;
+/// Left Alt scancode
+pub const LEFT_ALT: u16 = 0x38;
+/// Left Windows key scancode
+pub const LEFT_META: u16 = 0xE05B;
+
+/// One wheel notch
+const WHEEL_DELTA: i32 = 120;
+
+/// Builder for a scripted sequence of [`RdpInputEvent`]s
+#[derive(Debug, Clone, Default)]
+pub struct InputSequence {
+    events: Vec<RdpInputEvent>,
+}
+
+impl InputSequence {
+    /// Create an empty sequence
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Append an arbitrary event
+    pub fn event(mut self, event: RdpInputEvent) -> Self {
+        self.events.push(event);
+        self
+    }
+
+    /// Press a key
+    pub fn key_down(self, scancode: u16) -> Self {
+        self.key(scancode, true)
+    }
+
+    /// Release a key
+    pub fn key_up(self, scancode: u16) -> Self {
+        self.key(scancode, false)
+    }
+
+    /// Press and release a key
+    pub fn tap(self, scancode: u16) -> Self {
+        self.key_down(scancode).key_up(scancode)
+    }
+
+    /// Hold `modifiers` in order, tap `scancode`, then release them in reverse
+    pub fn chord(mut self, modifiers: &[u16], scancode: u16) -> Self {
+        for &modifier in modifiers {
+            self = self.key_down(modifier);
+        }
+        self = self.tap(scancode);
+        for &modifier in modifiers.iter().rev() {
+            self = self.key_up(modifier);
+        }
+        self
+    }
+
+    /// Type `text` on a US layout, using Shift where needed
+    ///
+    /// # Panics
+    ///
+    /// Panics on characters outside printable ASCII, newline and tab.
+    pub fn type_ascii(mut self, text: &str) -> Self {
+        for c in text.chars() {
+            let (scancode, shift) = us_scancode(c).unwrap_or_else(|| panic!("no US scancode for {:?}", c));
+            self = if shift {
+                self.chord(&[LEFT_SHIFT], scancode)
+            } else {
+                self.tap(scancode)
+            };
+        }
+        self
+    }
+
+    /// Move the pointer to an absolute position
+    pub fn move_to(self, x: u32, y: u32) -> Self {
+        self.event(RdpInputEvent::MouseMove { x, y })
+    }
+
+    /// Move the pointer relative to its current position
+    pub fn move_by(self, delta_x: i32, delta_y: i32) -> Self {
+        self.event(RdpInputEvent::MouseMoveRelative { delta_x, delta_y })
+    }
+
+    /// Press a mouse button
+    pub fn button_down(self, button: MouseButton) -> Self {
+        self.event(RdpInputEvent::MouseButton {
+            button: rdp_button(button),
+            pressed: true,
+        })
+    }
+
+    /// Release a mouse button
+    pub fn button_up(self, button: MouseButton) -> Self {
+        self.event(RdpInputEvent::MouseButton {
+            button: rdp_button(button),
+            pressed: false,
+        })
+    }
+
+    /// Press and release a mouse button
+    pub fn click(self, button: MouseButton) -> Self {
+        self.button_down(button).button_up(button)
+    }
+
+    /// Drag with the left button from one absolute position to another
+    pub fn drag(self, from: (u32, u32), to: (u32, u32)) -> Self {
+        self.move_to(from.0, from.1)
+            .button_down(MouseButton::Left)
+            .move_to(to.0, to.1)
+            .button_up(MouseButton::Left)
+    }
+
+    /// Scroll by whole wheel notches; positive `notches_y` scrolls up
+    pub fn scroll(self, notches_x: i32, notches_y: i32) -> Self {
+        self.event(RdpInputEvent::MouseWheel {
+            delta_x: notches_x * WHEEL_DELTA,
+            delta_y: notches_y * WHEEL_DELTA,
+        })
+    }
+
+    /// Number of events so far
+    pub fn len(&self) -> usize {
+        self.events.len()
+    }
+
+    /// Whether the sequence is empty
+    pub fn is_empty(&self) -> bool {
+        self.events.is_empty()
+    }
+
+    /// Finish the sequence
+    pub fn build(self) -> Vec<RdpInputEvent> {
+        self.events
+    }
+
+    fn key(self, scancode: u16, pressed: bool) -> Self {
+        let extended = scancode & 0xFF00 == 0xE000;
+        self.event(RdpInputEvent::KeyboardScancode {
+            scancode: scancode & 0x00FF,
+            extended,
+            e1_prefix: false,
+            pressed,
+        })
+    }
+}
+
+/// RDP pointer flags for a button
+fn rdp_button(button: MouseButton) -> u16 {
+    match button {
+        MouseButton::Left => 0x1000,
+        MouseButton::Right => 0x2000,
+        MouseButton::Middle => 0x4000,
+        MouseButton::Extra1 => 0x0080,
+        MouseButton::Extra2 => 0x0100,
+    }
+}
+
+/// Scancode and Shift state for a character on a US layout
+fn us_scancode(c: char) -> Option<(u16, bool)> {
+    const ROWS: [(&str, &str, u16); 4] = [
+        ("1234567890-=", "!@#$%^&*()_+", 0x02),
+        ("qwertyuiop[]", "QWERTYUIOP{}", 0x10),
+        ("asdfghjkl;'`", "ASDFGHJKL:\"~", 0x1E),
+        ("\\zxcvbnm,./", "|ZXCVBNM<>?", 0x2B),
+    ];
+
+    match c {
+        ' ' => return Some((0x39, false)),
+        '\n' => return Some((0x1C, false)),
+        '\t' => return Some((0x0F, false)),
+        _ => {}
+    }
+
+    ROWS.iter().find_map(|(plain, shifted, first)| {
+        if let Some(i) = plain.chars().position(|p| p == c) {
+            Some((first + i as u16, false))
+        } else {
+            shifted.chars().position(|s| s == c).map(|i| (first + i as u16, true))
+        }
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::mapper::keycodes::*;
+    use crate::{InputTranslator, LinuxInputEvent, MonitorInfo};
+
+    fn translator() -> InputTranslator {
+        InputTranslator::new(vec![MonitorInfo {
+            id: 1,
+            name: "Primary".to_string(),
+            x: 0,
+            y: 0,
+            width: 1920,
+            height: 1080,
+            dpi: 96.0,
+            scale_factor: 1.0,
+            stream_x: 0,
+            stream_y: 0,
+            stream_width: 1920,
+            stream_height: 1080,
+            is_primary: true,
+        }])
+        .unwrap()
+    }
+
+    fn pressed_keycodes(events: Vec<RdpInputEvent>) -> Vec<u32> {
+        let mut translator = translator();
+        let mut keycodes = Vec::new();
+        for event in events {
+            for linux in translator.translate_events(event).unwrap() {
+                if let LinuxInputEvent::Keyboard {
+                    event_type: crate::KeyboardEventType::KeyDown,
+                    keycode,
+                    ..
+                } = linux
+                {
+                    keycodes.push(keycode);
+                }
+            }
+        }
+        keycodes
+    }
+
+    #[test]
+    fn test_type_ascii() {
+        let events = InputSequence::new().type_ascii("a B\\").build();
+        assert_eq!(
+            pressed_keycodes(events),
+            vec![KEY_A, KEY_SPACE, KEY_LEFTSHIFT, KEY_B, KEY_BACKSLASH]
+        );
+    }
+
+    #[test]
+    fn test_chord_extended() {
+        let events = InputSequence::new().chord(&[LEFT_META], 0x20).build();
+        assert_eq!(events.len(), 4);
+        assert_eq!(pressed_keycodes(events), vec![KEY_LEFTMETA, KEY_D]);
+    }
+
+    #[test]
+    fn test_mouse_sequence() {
+        let events = InputSequence::new()
+            .drag((10, 10), (200, 100))
+            .click(MouseButton::Right)
+            .scroll(0, -2)
+            .build();
+        assert_eq!(events.len(), 7);
+        assert!(matches!(
+            events[1],
+            RdpInputEvent::MouseButton {
+                button: 0x1000,
+                pressed: true
+            }
+        ));
+        assert!(matches!(
+            events[6],
+            RdpInputEvent::MouseWheel {
+                delta_x: 0,
+                delta_y: -240
+            }
+        ));
+
+        let mut translator = translator();
+        for event in events {
+            translator.translate_events(event).unwrap();
+        }
+    }
+}
@@ -3,13 +3,19 @@
 //! Top-level coordinator for translating RDP input events to Linux evdev events
 //! with complete keyboard and mouse support.
 
+use crate::arbiter::{SourceId, TaggedEvent};
 use crate::coordinates::{CoordinateTransformer, MonitorInfo};
 use crate::error::{InputError, Result};
-use crate::keyboard::{KeyModifiers, KeyboardEvent, KeyboardHandler};
+use crate::keyboard::{KeyModifiers, KeyboardEvent, KeyboardHandler, NumLockPolicy};
+use crate::layout::{layout_for_keyboard_layout_id, LayoutResolver};
 use crate::mouse::{MouseButton, MouseEvent, MouseHandler};
-use std::time::Instant;
+use crate::remap::RemapRules;
+use std::time::{Duration, Instant};
 use tracing::{debug, warn};
 
+/// Default window for pressing left and right together to emulate middle (milliseconds)
+pub const DEFAULT_CHORD_TIMEOUT_MS: u64 = 50;
+
 /// RDP input event types
 #[derive(Debug, Clone)]
 pub enum RdpInputEvent {
@@ -138,6 +144,24 @@ pub struct InputTranslator {
 
     /// Last EPS calculation time
     last_eps_time: Instant,
+
+    /// Emulate the middle button with a left+right chord
+    middle_button_emulation: bool,
+
+    /// Window for the second button of a chord
+    chord_timeout: Duration,
+
+    /// Left or right press held back while waiting for the other button
+    pending_chord: Option<(MouseButton, Instant)>,
+
+    /// Buttons of an active chord not yet released
+    chord_buttons_held: u8,
+
+    /// Integrator override for keyboard layout IDs
+    layout_resolver: Option<LayoutResolver>,
+
+    /// Client connection this translator serves
+    source_id: SourceId,
 }
 
 impl InputTranslator {
@@ -150,11 +174,117 @@ impl InputTranslator {
             events_processed: 0,
             events_this_second: 0,
             last_eps_time: Instant::now(),
+            middle_button_emulation: false,
+            chord_timeout: Duration::from_millis(DEFAULT_CHORD_TIMEOUT_MS),
+            pending_chord: None,
+            chord_buttons_held: 0,
+            layout_resolver: None,
+            source_id: 0,
         })
     }
 
     /// Translate an RDP input event to Linux format
+    ///
+    /// Always yields exactly one event, so middle-button emulation is not
+    /// applied here; use [`translate_events`](Self::translate_events) when it
+    /// is enabled.
     pub fn translate_event(&mut self, event: RdpInputEvent) -> Result<LinuxInputEvent> {
+        self.record_event();
+        self.dispatch(event)
+    }
+
+    /// Translate an RDP input event and tag it with this translator's source
+    ///
+    /// For merging several clients with an [`InputArbiter`](crate::InputArbiter).
+    pub fn translate_tagged(&mut self, event: RdpInputEvent) -> Result<TaggedEvent> {
+        let event = self.translate_event(event)?;
+        Ok(TaggedEvent {
+            source: self.source_id,
+            event,
+        })
+    }
+
+    /// Translate an RDP input event, applying middle-button emulation
+    ///
+    /// A left or right press is held back for the chord timeout: if the other
+    /// button follows in time, both become a single middle button press,
+    /// otherwise the held press is emitted before the next event. Callers
+    /// must also call [`poll_chord_timeout`](Self::poll_chord_timeout) at
+    /// [`chord_deadline`](Self::chord_deadline) so a lone press is not held
+    /// indefinitely when no further input arrives.
+    pub fn translate_events(&mut self, event: RdpInputEvent) -> Result<Vec<LinuxInputEvent>> {
+        self.record_event();
+
+        let mut events = Vec::new();
+        let chord_button = match event {
+            RdpInputEvent::MouseButton { button, pressed } if self.middle_button_emulation => {
+                match MouseButton::from_rdp_button(button) {
+                    Some(button @ (MouseButton::Left | MouseButton::Right)) => Some((button, pressed)),
+                    _ => None,
+                }
+            }
+            _ => None,
+        };
+
+        let Some((button, pressed)) = chord_button else {
+            events.extend(self.flush_pending_chord()?);
+            events.push(self.dispatch(event)?);
+            return Ok(events);
+        };
+
+        if pressed {
+            match self.pending_chord {
+                Some((pending, since)) if pending != button && since.elapsed() < self.chord_timeout => {
+                    self.pending_chord = None;
+                    self.chord_buttons_held = 2;
+                    debug!("Left+right chord: emulating middle button");
+                    events.push(self.emit_mouse_button(MouseButton::Middle, true)?);
+                }
+                _ if self.chord_buttons_held > 0 => {
+                    // Re-press before the chord is fully released: already covered by middle
+                }
+                _ => {
+                    events.extend(self.flush_pending_chord()?);
+                    self.pending_chord = Some((button, Instant::now()));
+                }
+            }
+        } else if self.chord_buttons_held > 0 {
+            self.chord_buttons_held -= 1;
+            if self.chord_buttons_held == 1 {
+                events.push(self.emit_mouse_button(MouseButton::Middle, false)?);
+            }
+        } else {
+            // A quick click releases before the timeout: emit the held press first
+            events.extend(self.flush_pending_chord()?);
+            events.push(self.emit_mouse_button(button, false)?);
+        }
+
+        Ok(events)
+    }
+
+    /// When the held chord press must be flushed, if one is pending
+    pub fn chord_deadline(&self) -> Option<Instant> {
+        self.pending_chord.map(|(_, since)| since + self.chord_timeout)
+    }
+
+    /// Emit the held chord press if its timeout has passed by `now`
+    pub fn poll_chord_timeout(&mut self, now: Instant) -> Result<Option<LinuxInputEvent>> {
+        match self.chord_deadline() {
+            Some(deadline) if now >= deadline => self.flush_pending_chord(),
+            _ => Ok(None),
+        }
+    }
+
+    /// Emit the held chord press, if any
+    fn flush_pending_chord(&mut self) -> Result<Option<LinuxInputEvent>> {
+        match self.pending_chord.take() {
+            Some((button, _)) => self.emit_mouse_button(button, true).map(Some),
+            None => Ok(None),
+        }
+    }
+
+    /// Update event counters
+    fn record_event(&mut self) {
         self.events_processed += 1;
         self.events_this_second += 1;
 
@@ -164,7 +294,10 @@ impl InputTranslator {
             self.events_this_second = 0;
             self.last_eps_time = Instant::now();
         }
+    }
 
+    /// Translate a single event without chord handling
+    fn dispatch(&mut self, event: RdpInputEvent) -> Result<LinuxInputEvent> {
         match event {
             RdpInputEvent::KeyboardScancode {
                 scancode,
@@ -270,6 +403,11 @@ impl InputTranslator {
             InputError::Unknown(format!("Unknown mouse button: 0x{:04X}", button_flags))
         })?;
 
+        self.emit_mouse_button(button, pressed)
+    }
+
+    /// Update button state and build the Linux button event
+    fn emit_mouse_button(&mut self, button: MouseButton, pressed: bool) -> Result<LinuxInputEvent> {
         let mouse_event = if pressed {
             self.mouse.handle_button_down(button)?
         } else {
@@ -313,6 +451,16 @@ impl InputTranslator {
         }
     }
 
+    /// Set the source ID for tagged events (default 0)
+    pub fn set_source_id(&mut self, source_id: SourceId) {
+        self.source_id = source_id;
+    }
+
+    /// Get the source ID for tagged events
+    pub fn source_id(&self) -> SourceId {
+        self.source_id
+    }
+
     /// Update monitor configuration
     pub fn update_monitors(&mut self, monitors: Vec<MonitorInfo>) -> Result<()> {
         self.coord_transformer.update_monitors(monitors)
@@ -323,11 +471,76 @@ impl InputTranslator {
         self.keyboard.set_layout(layout);
     }
 
+    /// Set the layout from the client's keyboard layout ID
+    ///
+    /// `keyboard_layout` is the `keyboardLayout` field of Client Core Data.
+    /// The resolver set with [`set_layout_resolver`](Self::set_layout_resolver)
+    /// is asked first, then the built-in table. If neither knows the ID the
+    /// current layout is kept and `None` is returned.
+    pub fn apply_keyboard_layout_id(&mut self, keyboard_layout: u32) -> Option<&str> {
+        let layout = self
+            .layout_resolver
+            .as_ref()
+            .and_then(|resolve| resolve(keyboard_layout))
+            .or_else(|| layout_for_keyboard_layout_id(keyboard_layout).map(str::to_string));
+
+        match layout {
+            Some(layout) => {
+                debug!("Keyboard layout ID {:#010x} mapped to {}", keyboard_layout, layout);
+                self.keyboard.set_layout(&layout);
+                Some(self.keyboard.layout())
+            }
+            None => {
+                debug!(
+                    "Unknown keyboard layout ID {:#010x}, keeping {}",
+                    keyboard_layout,
+                    self.keyboard.layout()
+                );
+                None
+            }
+        }
+    }
+
+    /// Override how keyboard layout IDs map to layouts
+    ///
+    /// Returning `None` from the resolver falls back to the built-in table.
+    pub fn set_layout_resolver(&mut self, resolver: impl Fn(u32) -> Option<String> + Send + Sync + 'static) {
+        self.layout_resolver = Some(Box::new(resolver));
+    }
+
     /// Get current keyboard layout
     pub fn keyboard_layout(&self) -> &str {
         self.keyboard.layout()
     }
 
+    /// Set user-defined key remapping, applied after the layout mapping
+    ///
+    /// Keys held while the rules change still release under the keycode
+    /// they were pressed with.
+    pub fn set_remap_rules(&mut self, rules: RemapRules) {
+        self.keyboard.set_remap_rules(rules);
+    }
+
+    /// Get the user-defined key remapping
+    pub fn remap_rules(&self) -> &RemapRules {
+        self.keyboard.remap_rules()
+    }
+
+    /// Apply lock key states from an RDP synchronize event
+    pub fn synchronize_locks(&mut self, caps_lock: bool, num_lock: bool, scroll_lock: bool) {
+        self.keyboard.synchronize_locks(caps_lock, num_lock, scroll_lock);
+    }
+
+    /// Set how keypad keys are resolved against NumLock
+    pub fn set_num_lock_policy(&mut self, policy: NumLockPolicy) {
+        self.keyboard.set_num_lock_policy(policy);
+    }
+
+    /// Record the server's NumLock state, or `None` if it is unknown
+    pub fn set_server_num_lock(&mut self, state: Option<bool>) {
+        self.keyboard.set_server_num_lock(state);
+    }
+
     /// Set mouse acceleration enabled
     pub fn set_mouse_acceleration(&mut self, enabled: bool) {
         self.coord_transformer.set_acceleration_enabled(enabled);
@@ -343,10 +556,22 @@ impl InputTranslator {
         self.mouse.set_high_precision_scroll(enabled);
     }
 
+    /// Enable or disable middle-button emulation (left+right chord)
+    pub fn set_middle_button_emulation(&mut self, enabled: bool) {
+        self.middle_button_emulation = enabled;
+    }
+
+    /// Set the window for completing a left+right chord
+    pub fn set_chord_timeout(&mut self, timeout_ms: u64) {
+        self.chord_timeout = Duration::from_millis(timeout_ms);
+    }
+
     /// Reset input state (release all keys and buttons)
     pub fn reset(&mut self) {
         self.keyboard.reset();
         self.mouse.reset();
+        self.pending_chord = None;
+        self.chord_buttons_held = 0;
         debug!("Input translator reset");
     }
 
@@ -517,6 +742,20 @@ mod tests {
         assert_eq!(translator.keyboard_layout(), "de");
     }
 
+    #[test]
+    fn test_apply_keyboard_layout_id() {
+        let mut translator = InputTranslator::new(vec![create_test_monitor()]).unwrap();
+
+        assert_eq!(translator.apply_keyboard_layout_id(0x040C), Some("fr"));
+        assert_eq!(translator.apply_keyboard_layout_id(0x0436), None);
+        assert_eq!(translator.keyboard_layout(), "fr");
+
+        // The resolver wins where it answers; the built-in table covers the rest
+        translator.set_layout_resolver(|id| (id == 0x0436).then(|| "za".to_string()));
+        assert_eq!(translator.apply_keyboard_layout_id(0x0436), Some("za"));
+        assert_eq!(translator.apply_keyboard_layout_id(0x0407), Some("de"));
+    }
+
     #[test]
     fn test_reset() {
         let mut translator = InputTranslator::new(vec![create_test_monitor()]).unwrap();
@@ -564,4 +803,85 @@ mod tests {
         assert!(x >= 0.0);
         assert!(y >= 0.0);
     }
+
+    fn button_event(button: u16, pressed: bool) -> RdpInputEvent {
+        RdpInputEvent::MouseButton { button, pressed }
+    }
+
+    fn buttons(events: &[LinuxInputEvent]) -> Vec<(MouseButton, bool)> {
+        events
+            .iter()
+            .map(|event| match event {
+                LinuxInputEvent::MouseButton { button, pressed, .. } => (*button, *pressed),
+                _ => panic!("Expected MouseButton event"),
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_middle_button_chord() {
+        let mut translator = InputTranslator::new(vec![create_test_monitor()]).unwrap();
+        translator.set_middle_button_emulation(true);
+        translator.set_chord_timeout(10_000);
+
+        assert!(translator
+            .translate_events(button_event(0x1000, true))
+            .unwrap()
+            .is_empty());
+        assert!(translator.chord_deadline().is_some());
+
+        let events = translator.translate_events(button_event(0x2000, true)).unwrap();
+        assert_eq!(buttons(&events), vec![(MouseButton::Middle, true)]);
+
+        let events = translator.translate_events(button_event(0x2000, false)).unwrap();
+        assert_eq!(buttons(&events), vec![(MouseButton::Middle, false)]);
+        assert!(translator
+            .translate_events(button_event(0x1000, false))
+            .unwrap()
+            .is_empty());
+        assert!(translator.chord_deadline().is_none());
+    }
+
+    #[test]
+    fn test_chord_quick_click_passes_through() {
+        let mut translator = InputTranslator::new(vec![create_test_monitor()]).unwrap();
+        translator.set_middle_button_emulation(true);
+        translator.set_chord_timeout(10_000);
+
+        assert!(translator
+            .translate_events(button_event(0x1000, true))
+            .unwrap()
+            .is_empty());
+        let events = translator.translate_events(button_event(0x1000, false)).unwrap();
+        assert_eq!(
+            buttons(&events),
+            vec![(MouseButton::Left, true), (MouseButton::Left, false)]
+        );
+    }
+
+    #[test]
+    fn test_chord_timeout_flushes_press() {
+        let mut translator = InputTranslator::new(vec![create_test_monitor()]).unwrap();
+        translator.set_middle_button_emulation(true);
+        translator.set_chord_timeout(0);
+
+        translator.translate_events(button_event(0x2000, true)).unwrap();
+        let event = translator.poll_chord_timeout(Instant::now()).unwrap().unwrap();
+        assert_eq!(buttons(&[event]), vec![(MouseButton::Right, true)]);
+
+        // Left arriving after the timeout is an ordinary (held back) press
+        assert!(translator
+            .translate_events(button_event(0x1000, true))
+            .unwrap()
+            .is_empty());
+        assert!(translator.poll_chord_timeout(Instant::now()).unwrap().is_some());
+    }
+
+    #[test]
+    fn test_chord_disabled_passes_through() {
+        let mut translator = InputTranslator::new(vec![create_test_monitor()]).unwrap();
+
+        let events = translator.translate_events(button_event(0x1000, true)).unwrap();
+        assert_eq!(buttons(&events), vec![(MouseButton::Left, true)]);
+    }
 }
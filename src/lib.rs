@@ -13,7 +13,10 @@
 //! - `input` (default) - Include input translation
 //! - `clipboard-core` (default) - Include clipboard core utilities
 //! - `clipboard-rdp` - Include IronRDP clipboard integration
+//! - `clipboard-rdp-tokio` - Run the clipboard bridge on a Tokio task (`CliprdrBridge`)
 //! - `full` - Enable all features
+//! - `serde` - Serialize/Deserialize for clipboard data and configuration types
+//! - `testing` - Fixture builders for the enabled crates, under [`testing`]
 //!
 //! ## Quick Start
 //!
@@ -28,6 +31,14 @@
 //! [dependencies]
 //! lamco-rdp = { version = "0.1", default-features = false, features = ["input"] }
 //! ```
+//!
+//! ## API Stability
+//!
+//! The [`v1`] module is the supported public surface. Items re-exported there
+//! keep their paths and signatures across minor releases; everything reachable
+//! only through the crate re-exports ([`input`], [`clipboard_core`], ...) may
+//! change between minor versions. Breaking changes to `v1` ship as a new `v2`
+//! module alongside it.
 
 #![cfg_attr(docsrs, feature(doc_cfg))]
 
@@ -40,6 +51,21 @@ pub use lamco_clipboard_core as clipboard_core;
 #[cfg(feature = "clipboard-rdp")]
 pub use lamco_rdp_clipboard as clipboard_rdp;
 
+/// Fixture builders for downstream tests
+///
+/// Not part of the [`v1`] stability promise.
+#[cfg(feature = "testing")]
+pub mod testing {
+    /// Wire data and input sequence builders
+    pub mod fixtures {
+        #[cfg(feature = "input")]
+        pub use lamco_rdp_input::testing::fixtures::*;
+
+        #[cfg(feature = "clipboard-core")]
+        pub use lamco_clipboard_core::testing::fixtures::*;
+    }
+}
+
 /// Prelude module for convenient imports
 pub mod prelude {
     #[cfg(feature = "input")]
@@ -48,3 +74,51 @@ pub mod prelude {
     #[cfg(feature = "clipboard-core")]
     pub use lamco_clipboard_core::{ClipboardSink, FormatConverter, LoopDetector};
 }
+
+/// Stable public API, version 1
+///
+/// Downstream products should import from here rather than from the
+/// individual crates. New items may be added in minor releases; existing
+/// items are not removed or changed until the next major release.
+///
+/// ```rust
+/// use lamco_rdp::v1::clipboard::{ClipboardSink, FormatConverter};
+/// ```
+pub mod v1 {
+    /// Input translation: RDP events to Linux evdev events
+    #[cfg(feature = "input")]
+    pub mod input {
+        pub use lamco_rdp_input::{
+            layout_for_keyboard_layout_id, ArbitrationPolicy, CoordinateTransformer, InputArbiter, InputError,
+            InputTranslator, KeyModifiers, KeyboardEventType, LayoutResolver, LinuxInputEvent, MonitorInfo,
+            MouseButton, NumLockPolicy, RdpInputEvent, RemapRules, Result, SourceId, TaggedEvent,
+        };
+    }
+
+    /// Protocol-agnostic clipboard: sink trait, conversion and configuration
+    #[cfg(feature = "clipboard-core")]
+    pub mod clipboard {
+        pub use lamco_clipboard_core::{
+            CfHtml, ChangeCoalescer, ClipboardChange, ClipboardChangeReceiver, ClipboardConfig, ClipboardError,
+            ClipboardFormat, ClipboardResult, ClipboardSink, ClipboardSource, CoalesceConfig, CompatProfile,
+            ConfigError, ConversionFailure, ConversionMetrics, Converter, FileInfo, FileOperation, FlowControlConfig,
+            FormatConverter, FormatNegotiator, FormatRegistry, LoopDetectionConfig, LoopDetector, MirrorSink,
+            Negotiation, NonUtf8PathPolicy, PathMapper, PeerProfile, PollingConfig, PollingMode, ReadOnlySink,
+            RequestWindow, TextNormalization, TransferConfig, TransferEngine, TransferProgress, TransferState,
+            WindowMetrics,
+        };
+    }
+
+    /// IronRDP clipboard integration: backend, factory, bridge and events
+    #[cfg(feature = "clipboard-rdp")]
+    pub mod clipboard_rdp {
+        pub use lamco_rdp_clipboard::{
+            CapabilityDowngrade, ClipboardBridge, ClipboardDirection, ClipboardEvent, ClipboardEventReceiver,
+            ClipboardEventSender, ClipboardRdpError, ClipboardRdpResult, CliprdrConfig, CliprdrRole, DirectionPolicy,
+            RdpCliprdrBackend, RdpCliprdrFactory,
+        };
+
+        #[cfg(feature = "clipboard-rdp-tokio")]
+        pub use lamco_rdp_clipboard::{CliprdrBridge, CliprdrBridgeConfig};
+    }
+}